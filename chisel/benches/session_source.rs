@@ -65,6 +65,11 @@ fn get_empty_session_source() -> SessionSource {
             foundry_config: Config::default(),
             evm_opts: EvmOpts::default(),
             backend: None,
+            fork: None,
+            retain_state: false,
+            debug: false,
+            profile: false,
+            rpc_aliases_file: None,
             traces: false,
         },
     )
@@ -3,7 +3,11 @@
 //! This module contains the core readline loop for the Chisel CLI as well as the
 //! executable's `main` function.
 
-use chisel::prelude::{ChiselCommand, ChiselDispatcher, DispatchResult, SolidityHelper};
+use chisel::prelude::{
+    contains_secret, input_is_complete, parse_timeout, read_editor_history, write_editor_history,
+    ChiselCommand, ChiselDispatcher, ChiselSession, DispatchResult, GlobalHistory, HistoryConfig,
+    RedefinitionPolicy, SolidityHelper, CONTINUATION_PROMPT, DEFAULT_HISTORY_SIZE,
+};
 use clap::Parser;
 use foundry_cli::cmd::{forge::build::BuildArgs, LoadConfig};
 use foundry_common::evm::EvmArgs;
@@ -15,6 +19,7 @@ use foundry_config::{
     Config,
 };
 use rustyline::{error::ReadlineError, Editor};
+use std::path::Path;
 use yansi::Paint;
 
 // Loads project's figment and merges the build cli arguments into it
@@ -40,6 +45,38 @@ pub struct ChiselParser {
     #[clap(flatten)]
     pub evm_opts: EvmArgs,
 
+    /// Disable automatically saving the session after each accepted input
+    #[clap(long)]
+    pub no_autosave: bool,
+
+    /// Reject inputs that define an item of the session again, rather than replacing the
+    /// earlier definition
+    #[clap(long)]
+    pub reject_redefinitions: bool,
+
+    /// Load cached sessions even if some of their snippets fail to parse, skipping the broken
+    /// snippets
+    #[clap(long)]
+    pub lenient: bool,
+
+    /// The wall-clock time limit of each execution of the session, in seconds unless suffixed
+    /// with `ms`. The gas limit of each execution is set with `--gas-limit`.
+    #[clap(long, value_name = "SECONDS")]
+    pub timeout: Option<String>,
+
+    /// The number of entries that the global history, `~/.foundry/chisel_history`, and the
+    /// history of each session keep. `0` disables the histories, leaving their files untouched.
+    #[clap(long, value_name = "ENTRIES", default_value_t = DEFAULT_HISTORY_SIZE)]
+    pub history_size: usize,
+
+    /// Record inputs in the history even if they repeat the previous entry
+    #[clap(long)]
+    pub history_keep_dups: bool,
+
+    /// Leave inputs that start with a space out of the history, i.e. to keep secrets out of it
+    #[clap(long)]
+    pub history_ignore_space: bool,
+
     #[command(subcommand)]
     pub sub: Option<ChiselParserSub>,
 }
@@ -53,8 +90,24 @@ pub enum ChiselParserSub {
     Load { id: String },
     /// View the source of a cached session
     View { id: String },
+    /// Show the differences between two cached sessions
+    Diff {
+        /// The ID of the old session
+        old: String,
+        /// The ID of the new session
+        new: String,
+    },
     /// Clear all cached chisel sessions from the cache directory
     ClearCache,
+    /// Prune cached sessions that have not been modified recently
+    Clean {
+        /// Prune sessions that have not been modified in this many days
+        #[clap(long, default_value = "30")]
+        days: u64,
+        /// Always keep this many of the most recently modified sessions, regardless of age
+        #[clap(long, default_value = "0")]
+        keep: usize,
+    },
 }
 
 #[tokio::main]
@@ -65,21 +118,50 @@ async fn main() -> eyre::Result<()> {
     // Keeps track of whether or not an interrupt was the last input
     let mut interrupt = false;
 
-    // Create a new rustyline Editor
-    let mut rl = Editor::<SolidityHelper>::new()?;
-    rl.set_helper(Some(SolidityHelper));
+    // Create a new rustyline Editor, along with the history that is shared by every session
+    let history_config = HistoryConfig {
+        max_entries: args.history_size,
+        ignore_dups: !args.history_keep_dups,
+        ignore_space: args.history_ignore_space,
+    };
+    let mut rl = create_rustyline_editor(&history_config)?;
+    let global_history = match GlobalHistory::new(history_config) {
+        Ok(history) => Some(history),
+        Err(e) => {
+            eprintln!("{}", Paint::yellow(format!("Warning: Global history disabled: {e}")));
+            None
+        }
+    };
 
     // Load configuration
-    let (config, evm_opts) = args.load_config_and_evm_opts()?;
+    let (config, mut evm_opts) = args.load_config_and_evm_opts()?;
+
+    // The `--fork-url` flag forks through the session, which pins the fork's block
+    let fork_url = evm_opts.fork_url.take();
+    let fork_block_number = evm_opts.fork_block_number.take();
+
+    // Loaded sessions resolve the `[rpc_endpoints]` of the project that chisel was launched in
+    let rpc_endpoints = config.rpc_endpoints.clone();
 
-    // Create a new cli dispatcher
-    let mut dispatcher = ChiselDispatcher::new(&chisel::session_source::SessionSourceConfig {
-        // Enable traces if any level of verbosity was passed
-        traces: config.verbosity > 0,
-        foundry_config: config,
-        evm_opts,
-        backend: None,
-    })?;
+    // Create a new cli dispatcher. Subcommands only work with cached sessions, so they do not
+    // need a fresh session, which would resolve solc and reserve a session ID.
+    let mut dispatcher = match &args.sub {
+        None => ChiselDispatcher::new(&chisel::session_source::SessionSourceConfig {
+            // Enable traces if any level of verbosity was passed
+            traces: config.verbosity > 0,
+            foundry_config: config,
+            evm_opts,
+            backend: None,
+            fork: None,
+            retain_state: false,
+            debug: false,
+            profile: false,
+            rpc_aliases_file: None,
+        })?,
+        Some(_) => ChiselDispatcher::with_session(ChiselSession::open()?),
+    };
+
+    dispatcher.session.lenient = args.lenient;
 
     // Check for chisel subcommands
     match &args.sub {
@@ -97,7 +179,9 @@ async fn main() -> eyre::Result<()> {
         Some(ChiselParserSub::Load { id }) | Some(ChiselParserSub::View { id }) => {
             // For both of these subcommands, we need to attempt to load the session from cache
             match dispatcher.dispatch_command(ChiselCommand::Load, &[id]).await {
-                DispatchResult::CommandSuccess(_) => { /* Continue */ }
+                DispatchResult::CommandSuccess(_) => {
+                    dispatcher.session.inherit_rpc_endpoints(&rpc_endpoints);
+                }
                 DispatchResult::CommandFailed(e) => {
                     eprintln!("{e}");
                     return Ok(())
@@ -116,6 +200,35 @@ async fn main() -> eyre::Result<()> {
                 return Ok(())
             }
         }
+        Some(ChiselParserSub::Diff { old, new }) => {
+            let sessions = dispatcher
+                .session
+                .load(old)
+                .and_then(|old| dispatcher.session.load(new).map(|new| (old, new)));
+            match sessions {
+                Ok((old_session, new_session)) => {
+                    let diff = ChiselSession::diff(&old_session, &new_session);
+                    if diff.is_empty() {
+                        println!("No differences between sessions {old} and {new}.");
+                    }
+                    for line in diff.to_string().lines() {
+                        if line.starts_with("+++") || line.starts_with("---") {
+                            println!("{}", Paint::new(line).bold());
+                        } else if line.starts_with('+') {
+                            println!("{}", Paint::green(line));
+                        } else if line.starts_with('-') {
+                            println!("{}", Paint::red(line));
+                        } else if line.starts_with("@@") {
+                            println!("{}", Paint::cyan(line));
+                        } else {
+                            println!("{line}");
+                        }
+                    }
+                }
+                Err(e) => eprintln!("{e}"),
+            }
+            return Ok(())
+        }
         Some(ChiselParserSub::ClearCache) => {
             match dispatcher.dispatch_command(ChiselCommand::ClearCache, &[]).await {
                 DispatchResult::CommandSuccess(Some(msg)) => println!("{}", Paint::green(msg)),
@@ -124,17 +237,92 @@ async fn main() -> eyre::Result<()> {
             }
             return Ok(())
         }
+        Some(ChiselParserSub::Clean { days, keep }) => {
+            // Saturate rather than overflow, as pruning by an absurd age prunes nothing
+            let older_than = std::time::Duration::from_secs(days.saturating_mul(24 * 60 * 60));
+            match dispatcher.session.prune_sessions(older_than, *keep) {
+                Ok(pruned) if pruned.is_empty() => println!("No sessions to prune."),
+                Ok(pruned) => {
+                    for file_name in pruned {
+                        println!("{}", Paint::green(format!("Pruned {file_name}")));
+                    }
+                }
+                Err(e) => eprintln!("{e}"),
+            }
+            return Ok(())
+        }
         None => { /* No chisel subcommand present; Continue */ }
     }
 
+    if let Some(fork_url) = fork_url {
+        match dispatcher.session.fork(&fork_url, fork_block_number).await {
+            Ok(warnings) => {
+                for warning in warnings {
+                    eprintln!("{}", Paint::yellow(format!("Warning: {warning}")));
+                }
+            }
+            Err(e) => eprintln!("{}", Paint::red(format!("Failed to fork {fork_url}: {e}"))),
+        }
+    }
+
+    // Execution limits supplied on the command line apply to the new or loaded session
+    if let Some(gas_limit) = args.evm_opts.env.gas_limit {
+        if let Err(e) = dispatcher.session.set_gas_limit(gas_limit) {
+            eprintln!("{}", Paint::red(format!("Failed to set the gas limit: {e}")));
+        }
+    }
+    if let Some(timeout) = &args.timeout {
+        if let Err(e) =
+            parse_timeout(timeout).and_then(|timeout| dispatcher.session.set_timeout(timeout))
+        {
+            eprintln!("{}", Paint::red(format!("Failed to set the time limit: {e}")));
+        }
+    }
+
+    dispatcher.session.autosave = !args.no_autosave;
+    if args.reject_redefinitions {
+        dispatcher.session.redefinition_policy = RedefinitionPolicy::Reject;
+    }
+
+    // Load the global history and the session's history. If either can't be read, start
+    // without it.
+    let mut history_file = dispatcher.session.history_file();
+    let mut session_history =
+        load_history(&mut rl, global_history.as_ref(), history_file.as_deref(), &history_config);
+    update_completions(&mut rl, &dispatcher);
+
     // Print welcome header
     println!("Welcome to Chisel! Type `{}` to show available commands.", Paint::green("!help"));
 
+    // Let the user know about sessions that can be recovered from their journal
+    let recoverable = dispatcher
+        .session
+        .recoverable_sessions()
+        .into_iter()
+        .filter(|id| Some(id) != dispatcher.session.id.as_ref())
+        .collect::<Vec<_>>();
+    if !recoverable.is_empty() {
+        println!(
+            "{}",
+            Paint::yellow(format!(
+                "Found unsaved inputs for session(s) {}. Use `!recover <id>` to recover them.",
+                recoverable.join(", ")
+            ))
+        );
+    }
+
+    // Input that spans multiple lines, accumulated until it is complete
+    let mut pending = String::new();
+
     // Begin Rustyline loop
     loop {
         // Get the prompt from the dispatcher
         // Variable based on status of the last entry
-        let prompt = dispatcher.get_prompt();
+        let prompt = if pending.is_empty() {
+            dispatcher.get_prompt()
+        } else {
+            CONTINUATION_PROMPT.to_string()
+        };
 
         // Read the next line
         let next_string = rl.readline(prompt.as_str());
@@ -145,11 +333,38 @@ async fn main() -> eyre::Result<()> {
                 // Clear interrupt flag
                 interrupt = false;
 
-                // Add line to history
-                rl.add_history_entry(&line);
+                // Keep reading until the input is complete. Commands always fit on one line.
+                if !pending.is_empty() {
+                    pending.push('\n');
+                }
+                pending.push_str(&line);
+                if !pending.trim_start().starts_with('!') && !input_is_complete(&pending) {
+                    continue
+                }
+                let input = std::mem::take(&mut pending);
+
+                // Add input to the editor's history, as well as to the session's and the global
+                // history. Inputs that may contain secrets are left out of all of them.
+                if !contains_secret(&input) {
+                    rl.add_history_entry(&input);
+                }
+                if history_config.records(&input, session_history.last().map(String::as_str)) {
+                    session_history.push(input.clone());
+                    let excess = session_history.len().saturating_sub(history_config.max_entries);
+                    session_history.drain(..excess);
+                    save_history(&session_history, history_file.as_deref(), &history_config);
+                }
+                if let Some(global_history) = &global_history {
+                    if let Err(e) = global_history.record(&input) {
+                        eprintln!(
+                            "{}",
+                            Paint::yellow(format!("Warning: Failed to save global history: {e}"))
+                        );
+                    }
+                }
 
                 // Dispatch and match results
-                match dispatcher.dispatch(&line).await {
+                match dispatcher.dispatch(&input).await {
                     DispatchResult::Success(msg) | DispatchResult::CommandSuccess(msg) => if let Some(msg) = msg {
                         println!("{}", Paint::green(msg));
                     },
@@ -160,10 +375,38 @@ async fn main() -> eyre::Result<()> {
                     }
                     DispatchResult::FileIoError(e) => eprintln!("{}", Paint::red(format!("⚒️ Chisel File IO Error - {e}"))),
                     DispatchResult::CommandFailed(msg) | DispatchResult::Failure(Some(msg)) => eprintln!("{}", Paint::red(msg)),
+                    DispatchResult::Quit => break,
                     DispatchResult::Failure(None) => eprintln!("{}\nPlease Report this bug as a github issue if it persists: https://github.com/foundry-rs/foundry/issues/new/choose", Paint::red("⚒️ Unknown Chisel Error ⚒️")),
                 }
+
+                // The line may have defined items, or switched sessions
+                update_completions(&mut rl, &dispatcher);
+                print_evicted(&mut dispatcher);
+
+                // If the line switched sessions, switch to the new session's history as well. A
+                // new session that was just written for the first time keeps its history.
+                if dispatcher.session.history_file() != history_file {
+                    let reserved = history_file.is_none() &&
+                        dispatcher.session.history_file().map_or(false, |path| !path.exists());
+                    history_file = dispatcher.session.history_file();
+                    if reserved {
+                        save_history(&session_history, history_file.as_deref(), &history_config);
+                    } else {
+                        session_history = load_history(
+                            &mut rl,
+                            global_history.as_ref(),
+                            history_file.as_deref(),
+                            &history_config,
+                        );
+                    }
+                }
             }
             Err(ReadlineError::Interrupted) => {
+                // Discard incomplete input
+                if !pending.is_empty() {
+                    pending.clear();
+                    continue
+                }
                 if interrupt {
                     break
                 } else {
@@ -179,9 +422,73 @@ async fn main() -> eyre::Result<()> {
         }
     }
 
+    // Persist the history and make sure the last autosave has hit the disk before exiting
+    save_history(&session_history, history_file.as_deref(), &history_config);
+    if let Err(e) = dispatcher.session.flush_autosave() {
+        eprintln!("{}", Paint::yellow(format!("Warning: Failed to autosave session: {e}")));
+    }
+
     Ok(())
 }
 
+/// Creates the rustyline editor of the REPL, with the [SolidityHelper] and a history that follows
+/// the [HistoryConfig]
+fn create_rustyline_editor(history_config: &HistoryConfig) -> eyre::Result<Editor<SolidityHelper>> {
+    let mut rl = Editor::<SolidityHelper>::with_config(history_config.editor_config())?;
+    rl.set_helper(Some(SolidityHelper::default()));
+    Ok(rl)
+}
+
+/// Derives the tab completions of the editor from the dispatcher's current session again
+fn update_completions(rl: &mut Editor<SolidityHelper>, dispatcher: &ChiselDispatcher) {
+    if let Some(helper) = rl.helper_mut() {
+        helper.completions.update(dispatcher.session.session_source.as_ref());
+    }
+}
+
+/// Reports the sessions that writes of the session evicted from the cache
+fn print_evicted(dispatcher: &mut ChiselDispatcher) {
+    for id in dispatcher.session.take_evicted() {
+        println!("{}", Paint::yellow(format!("Evicted session {id} from the cache")));
+    }
+}
+
+/// Loads the global history followed by the session's history into the editor, so that the
+/// entries of both can be recalled, and returns the entries of the session's history.
+fn load_history(
+    rl: &mut Editor<SolidityHelper>,
+    global_history: Option<&GlobalHistory>,
+    history_file: Option<&Path>,
+    history_config: &HistoryConfig,
+) -> Vec<String> {
+    rl.clear_history();
+    match global_history.map(GlobalHistory::load).transpose() {
+        Ok(entries) => {
+            for entry in entries.unwrap_or_default() {
+                rl.add_history_entry(entry);
+            }
+        }
+        Err(e) => {
+            eprintln!("{}", Paint::yellow(format!("Warning: Failed to load global history: {e}")))
+        }
+    }
+    let entries =
+        history_file.map(|path| read_editor_history(path, history_config)).unwrap_or_default();
+    for entry in &entries {
+        rl.add_history_entry(entry.as_str());
+    }
+    entries
+}
+
+/// Saves the session's history to its history file, printing a warning on failure.
+fn save_history(entries: &[String], history_file: Option<&Path>, history_config: &HistoryConfig) {
+    if let Some(path) = history_file {
+        if let Err(e) = write_editor_history(path, entries, history_config) {
+            eprintln!("{}", Paint::yellow(format!("Warning: Failed to save history: {e}")));
+        }
+    }
+}
+
 /// [Provider] impl
 impl Provider for ChiselParser {
     fn metadata(&self) -> Metadata {
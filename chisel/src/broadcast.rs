@@ -0,0 +1,378 @@
+//! Broadcast
+//!
+//! This module contains the [Broadcaster], which signs the statements of a session as
+//! transactions and sends them to a real network, see `!broadcast`. The transactions are planned
+//! by [crate::session_source::SessionSource::plan_broadcast] against the session first.
+
+use ethers::{
+    providers::Middleware,
+    types::{
+        transaction::eip2718::TypedTransaction, Address, Bytes, TransactionReceipt,
+        TransactionRequest, H256, U256,
+    },
+    utils::{hex, to_checksum},
+};
+use eyre::{eyre, Result};
+use foundry_cli::opts::{EthereumOpts, Wallet, WalletType};
+use foundry_common::{ProviderBuilder, RetryProvider};
+use serde::{Deserialize, Serialize};
+use std::{fmt, sync::Arc};
+
+/// The signer of broadcast transactions. There is no default signer, so that nothing is
+/// broadcast without one being chosen explicitly.
+///
+/// A private key is never accepted as an argument, so that it is not echoed to the terminal nor
+/// written to a history.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BroadcastSigner {
+    /// A private key entered at a hidden prompt, `--interactive`
+    Interactive,
+    /// A private key read from an environment variable, `--private-key-env <var>`
+    PrivateKeyEnv(String),
+    /// A Ledger hardware wallet, `--ledger`
+    Ledger,
+}
+
+/// The arguments of `!broadcast`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BroadcastArgs {
+    /// The RPC URL or `[rpc_endpoints]` alias of the network
+    pub endpoint: String,
+    /// The signer of the transactions
+    pub signer: BroadcastSigner,
+    /// The chain ID that the network must have, `--chain-id <id>`. If omitted, the chain ID of
+    /// the network is confirmed at a prompt instead.
+    pub chain_id: Option<u64>,
+    /// The indices of the snippets to broadcast, in order. If empty, the last statement of the
+    /// session is broadcast.
+    pub snippets: Vec<usize>,
+}
+
+impl BroadcastArgs {
+    /// Parses the arguments of `!broadcast`
+    ///
+    /// ### Takes
+    ///
+    /// The arguments, i.e. `["mainnet", "--ledger", "3"]`
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the [BroadcastArgs]. Fails if no signer is supplied, if more than one is, or
+    /// if a private key is supplied as an argument.
+    pub fn parse(args: &[&str]) -> Result<Self> {
+        let (mut endpoint, mut signer, mut chain_id, mut snippets) = (None, None, None, Vec::new());
+        let mut set_signer = |new: BroadcastSigner| match signer.replace(new) {
+            Some(_) => {
+                Err(eyre!("Supply only one of `--interactive`, `--private-key-env` and `--ledger`"))
+            }
+            None => Ok(()),
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match *arg {
+                "--private-key" => eyre::bail!(
+                    "Private keys are not accepted as arguments. Supply `--interactive` to enter it at a hidden prompt, or `--private-key-env <var>` to read it from an environment variable"
+                ),
+                "--interactive" => set_signer(BroadcastSigner::Interactive)?,
+                "--private-key-env" => {
+                    let var = args.next().ok_or_else(|| {
+                        eyre!("`--private-key-env` requires the name of an environment variable")
+                    })?;
+                    set_signer(BroadcastSigner::PrivateKeyEnv(var.to_string()))?;
+                }
+                "--ledger" => set_signer(BroadcastSigner::Ledger)?,
+                "--chain-id" => {
+                    let id =
+                        args.next().ok_or_else(|| eyre!("`--chain-id` requires a chain ID"))?;
+                    chain_id =
+                        Some(id.parse::<u64>().map_err(|_| eyre!("Invalid chain ID `{id}`"))?);
+                }
+                flag if flag.starts_with("--") => eyre::bail!("Unknown flag `{flag}`"),
+                value if endpoint.is_none() => endpoint = Some(value.to_string()),
+                index => snippets.push(
+                    index.parse::<usize>().map_err(|_| eyre!("Invalid snippet index `{index}`"))?,
+                ),
+            }
+        }
+
+        Ok(Self {
+            endpoint: endpoint.ok_or_else(|| {
+                eyre!("Must supply the RPC URL or `[rpc_endpoints]` alias of the network to broadcast to")
+            })?,
+            signer: signer.ok_or_else(|| {
+                eyre!("Refusing to broadcast without a signer. Supply `--interactive`, `--private-key-env <var>` or `--ledger` to sign the transactions with")
+            })?,
+            chain_id,
+            snippets,
+        })
+    }
+}
+
+/// What a broadcast transaction does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransactionKind {
+    /// The single external call of a statement, sent from the signer
+    Call,
+    /// The deployment of a REPL contract that holds the session up to a statement
+    Deployment,
+    /// A call to the `run()` function of the REPL contract deployed by the preceding
+    /// [TransactionKind::Deployment]
+    Run,
+}
+
+impl fmt::Display for TransactionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TransactionKind::Call => "call",
+            TransactionKind::Deployment => "deployment of the REPL contract",
+            TransactionKind::Run => "call to `run()` of the REPL contract",
+        })
+    }
+}
+
+/// A transaction that broadcasts a snippet, see
+/// [crate::session_source::SessionSource::plan_broadcast]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedTransaction {
+    /// The index of the snippet that the transaction broadcasts
+    pub snippet: usize,
+    /// What the transaction does
+    pub kind: TransactionKind,
+    /// The recipient. `None` for a [TransactionKind::Deployment], and for a
+    /// [TransactionKind::Run] until its deployment is mined.
+    pub to: Option<Address>,
+    /// The calldata, or the creation bytecode of a deployment
+    pub data: Bytes,
+    /// The value in wei sent along with the transaction
+    pub value: U256,
+}
+
+impl fmt::Display for PlannedTransaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Snippet {}: {}", self.snippet, self.kind)?;
+        if let Some(to) = self.to {
+            write!(f, " to {}", to_checksum(&to, None))?;
+        }
+        match self.kind {
+            TransactionKind::Call if self.data.len() >= 4 => write!(
+                f,
+                " with selector 0x{} and {} bytes of arguments",
+                hex::encode(&self.data[..4]),
+                self.data.len() - 4
+            )?,
+            TransactionKind::Deployment => write!(f, " ({} bytes)", self.data.len())?,
+            _ => {}
+        }
+        if !self.value.is_zero() {
+            write!(f, ", sending {} wei", self.value)?;
+        }
+        Ok(())
+    }
+}
+
+/// A transaction that was broadcast for a snippet, recorded alongside it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BroadcastReceipt {
+    /// The chain ID of the network that the transaction was sent to
+    pub chain_id: u64,
+    /// What the transaction did
+    pub kind: TransactionKind,
+    /// The hash of the transaction
+    pub hash: H256,
+    /// Whether the transaction succeeded, or `None` if it was sent but no receipt was received
+    pub success: Option<bool>,
+}
+
+impl BroadcastReceipt {
+    /// Creates a receipt from the receipt of the network, if one was received
+    pub fn new(
+        chain_id: u64,
+        kind: TransactionKind,
+        hash: H256,
+        receipt: Option<&TransactionReceipt>,
+    ) -> Self {
+        let success = receipt.map(|receipt| receipt.status == Some(1u64.into()));
+        Self { chain_id, kind, hash, success }
+    }
+}
+
+impl fmt::Display for BroadcastReceipt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self.success {
+            Some(true) => "succeeded",
+            Some(false) => "failed",
+            None => "pending",
+        };
+        write!(
+            f,
+            "{} 0x{} on chain {} ({status})",
+            self.kind,
+            hex::encode(self.hash),
+            self.chain_id
+        )
+    }
+}
+
+/// Signs transactions and sends them to a real network
+#[derive(Debug)]
+pub struct Broadcaster {
+    /// The chain ID of the network
+    pub chain_id: u64,
+    /// The signer, connected to the network
+    signer: WalletType,
+    /// The provider of the network
+    provider: Arc<RetryProvider>,
+}
+
+impl Broadcaster {
+    /// Connects to a network with a signer
+    ///
+    /// ### Takes
+    ///
+    /// - The RPC URL of the network
+    /// - The [BroadcastSigner]
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the [Broadcaster]. Fails if the network can not be reached, or if the signer
+    /// is invalid, its environment variable is not set or, for a Ledger, not connected.
+    pub async fn connect(rpc_url: &str, signer: &BroadcastSigner) -> Result<Self> {
+        let provider = Arc::new(ProviderBuilder::new(rpc_url).connect().await?);
+        let chain_id = provider.get_chainid().await?;
+        let wallet = match signer {
+            BroadcastSigner::Interactive => Wallet { interactive: true, ..Default::default() },
+            BroadcastSigner::PrivateKeyEnv(var) => {
+                let key = std::env::var(var)
+                    .map_err(|_| eyre!("The environment variable `{var}` is not set"))?;
+                Wallet {
+                    private_key: Some(key.trim().trim_start_matches("0x").to_string()),
+                    ..Default::default()
+                }
+            }
+            BroadcastSigner::Ledger => Wallet { ledger: true, ..Default::default() },
+        };
+        let opts =
+            EthereumOpts { rpc_url: Some(rpc_url.to_string()), wallet, ..Default::default() };
+        let signer = opts
+            .signer_with(chain_id, provider.clone())
+            .await?
+            .ok_or_else(|| eyre!("Failed to instantiate the signer"))?;
+        Ok(Self { chain_id: chain_id.as_u64(), signer, provider })
+    }
+
+    /// Checks that the recipient of a [TransactionKind::Call] is a contract on the network, as
+    /// the call was planned against the session, whose contracts may not exist on the network
+    ///
+    /// ### Takes
+    ///
+    /// The recipient of the call
+    ///
+    /// ### Returns
+    ///
+    /// Fails if the network has no code at the recipient, or if it can not be reached.
+    pub async fn check_recipient(&self, to: Address) -> Result<()> {
+        let code = self.provider.get_code(to, None).await?;
+        if code.is_empty() {
+            eyre::bail!(
+                "There is no contract at {} on chain ID {}, so the call was planned against another network",
+                to_checksum(&to, None),
+                self.chain_id
+            )
+        }
+        Ok(())
+    }
+
+    /// The address of the signer
+    pub fn address(&self) -> Address {
+        match &self.signer {
+            WalletType::Local(client) => client.address(),
+            WalletType::Ledger(client) => client.address(),
+            WalletType::Trezor(client) => client.address(),
+            WalletType::Aws(client) => client.address(),
+        }
+    }
+
+    /// Fills the nonce, gas limit, and gas price of a planned transaction from the network
+    ///
+    /// ### Takes
+    ///
+    /// - The [PlannedTransaction]
+    /// - The recipient, if the planned transaction does not have one yet, i.e. the REPL contract of
+    ///   a [TransactionKind::Run]
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the transaction as it would be signed. Fails if the network estimates that
+    /// it reverts.
+    pub async fn prepare(
+        &self,
+        planned: &PlannedTransaction,
+        to: Option<Address>,
+    ) -> Result<TypedTransaction> {
+        let mut tx: TypedTransaction = TransactionRequest::new()
+            .from(self.address())
+            .data(planned.data.clone())
+            .value(planned.value)
+            .chain_id(self.chain_id)
+            .into();
+        if let Some(to) = planned.to.or(to) {
+            tx.set_to(to);
+        }
+        match &self.signer {
+            WalletType::Local(client) => fill(client, &mut tx).await?,
+            WalletType::Ledger(client) => fill(client, &mut tx).await?,
+            WalletType::Trezor(client) => fill(client, &mut tx).await?,
+            WalletType::Aws(client) => fill(client, &mut tx).await?,
+        }
+        Ok(tx)
+    }
+
+    /// Signs a transaction and sends it, waiting for its receipt
+    ///
+    /// ### Takes
+    ///
+    /// The transaction, see [Broadcaster::prepare]
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the hash of the transaction and its receipt, if one was received
+    pub async fn send(&self, tx: TypedTransaction) -> Result<(H256, Option<TransactionReceipt>)> {
+        match &self.signer {
+            WalletType::Local(client) => send(client, tx).await,
+            WalletType::Ledger(client) => send(client, tx).await,
+            WalletType::Trezor(client) => send(client, tx).await,
+            WalletType::Aws(client) => send(client, tx).await,
+        }
+    }
+}
+
+/// Fills a transaction with a client of the network
+async fn fill<M: Middleware>(client: &M, tx: &mut TypedTransaction) -> Result<()>
+where
+    M::Error: 'static,
+{
+    client
+        .fill_transaction(tx, None)
+        .await
+        .map_err(|err| eyre!("Failed to prepare the transaction: {err}"))
+}
+
+/// Sends a transaction with a client of the network and waits for its receipt
+async fn send<M: Middleware>(
+    client: &M,
+    tx: TypedTransaction,
+) -> Result<(H256, Option<TransactionReceipt>)>
+where
+    M::Error: 'static,
+{
+    let pending = client
+        .send_transaction(tx, None)
+        .await
+        .map_err(|err| eyre!("Failed to send the transaction: {err}"))?;
+    let hash = *pending;
+    let receipt = pending.await.map_err(|err| {
+        eyre!("Sent transaction 0x{}, but failed to get its receipt: {err}", hex::encode(hash))
+    })?;
+    Ok((hash, receipt))
+}
@@ -15,9 +15,23 @@ pub enum ChiselCommand {
     /// Quit the REPL
     Quit,
     /// Clear the current session source
+    /// Takes: [keep]
     Clear,
     /// Print the generated source contract
     Source,
+    /// List the snippets of the current session along with when and how they were entered
+    Snippets,
+    /// Remove the most recently entered snippet from the current session
+    Undo,
+    /// Restore the snippet most recently removed by `Undo`
+    Redo,
+    /// Take a snapshot of the current state of the session
+    Snapshot,
+    /// Restore the session to the state of a snapshot
+    /// Takes: <snapshot-id>
+    Revert,
+    /// List the snapshots of the current session
+    Snapshots,
     /// Save the current session to the cache
     /// Takes: [session-id]
     Save,
@@ -27,19 +41,147 @@ pub enum ChiselCommand {
     /// WARNING: This will overwrite the current session (though the current session will be
     /// optimistically cached)
     Load,
+    /// Recover a session from its journal after a crash
+    /// Takes: <session-id>
+    ///
+    /// WARNING: This will overwrite the current session
+    Recover,
     /// List all cached sessions
     ListSessions,
+    /// Delete a session from the cache
+    /// Takes: <session-id>
+    Delete,
+    /// Duplicate the current session into a new session
+    Duplicate,
+    /// Merge a cached session into the current session
+    /// Takes: <session-id>
+    Merge,
     /// Clear the cache of all stored sessions
     ClearCache,
+    /// Add code to the global scope of the current session's source, i.e. free functions
+    /// Takes: <code>
+    TopLevel,
+    /// Print the ABI of the REPL contract, or generate an interface from a contract's ABI and
+    /// add it to the current session's source
+    /// Takes: [<name> <path-or-json>]
+    Abi,
+    /// Print the creation and deployed bytecode of the REPL contract, or of another contract of
+    /// the current session
+    /// Takes: [name] [creation|deployed]
+    Bytecode,
+    /// Disassemble the deployed bytecode of the REPL contract, or of another contract of the
+    /// current session
+    /// Takes: [name] [--pc <n>]
+    Disasm,
+    /// Print the selectors and event topics of the REPL contract
+    Hashes,
+    /// Print the gas estimates of solc for the functions of the compiled contracts, or the gas
+    /// used by each executed snippet
+    /// Takes [used]
+    Gas,
+    /// Set how integers within values are displayed
+    /// Takes: [hex|dec]
+    Format,
+    /// Estimate the gas of a statement without adding it to the current session
+    /// Takes: <statement>
+    Estimate,
+    /// Step through the instructions of the last statement in an interactive debugger
+    /// Takes: [statement]
+    Debug,
+    /// Print where the gas of the last statement went
+    /// Takes: [--json]
+    Profile,
+    /// Print the accounts and storage slots that the last statement touched
+    /// Takes: [--all] [--json]
+    StateDiff,
+    /// Print statistics on the most recent compilation of the current session's source
+    Stats,
+    /// Print the events emitted by the current session
+    Events,
+    /// Rename the REPL contract of the current session's source
+    /// Takes: <name>
+    ContractName,
+    /// Set the SPDX license identifier of the current session's source and its exported script
+    /// Takes: <id>
+    License,
+    /// Override the solidity pragma of the current session's source
+    /// Takes: [version-requirement]
+    Pragma,
+    /// Enable or disable the optimizer for the current session's source
+    /// Takes: [runs|off]
+    Optimizer,
+    /// Enable or disable compiling the current session's source via the IR pipeline
+    /// Takes: [on|off]
+    ViaIr,
+    /// Add a remapping for imports of the current session's source, or list the remappings
+    /// Takes: [<from>=<to>]
+    Remap,
+    /// Change the solc version of the current session, or list the available versions
+    /// Takes: [version|list]
+    Solc,
+    /// Set the EVM version of the current session
+    /// Takes: [version]
+    EvmVersion,
     /// Fork an RPC in the current session
-    /// Takes <fork-url|env-var|rpc_endpoints-alias>
+    /// Takes <fork-url|env-var|rpc-alias> [block]
     Fork,
+    /// List, add, or remove the RPC endpoint aliases of the user
+    /// Takes: [list|add <alias> <url>|remove <alias>]
+    Rpc,
     /// Enable / disable traces for the current session
     Traces,
+    /// Print the current value of a state variable of the current session
+    /// Takes: <name>[key]...
+    Inspect,
+    /// Print the storage layout of the REPL contract
+    Storage,
+    /// Execute the current session again from a clean state
+    Rerun,
+    /// Override the block number that the current session is executed at
+    /// Takes: [number]
+    Block,
+    /// Override the block timestamp that the current session is executed at
+    /// Takes: [timestamp]
+    Timestamp,
+    /// Override the base fee of the block that the current session is executed at
+    /// Takes: [wei]
+    BaseFee,
+    /// Override the chain ID that the current session is executed on
+    /// Takes: [id]
+    ChainId,
+    /// Override the sender of the call to the REPL contract
+    /// Takes: [address]
+    Sender,
+    /// Override the value sent along with the call to the REPL contract
+    /// Takes: [wei]
+    Value,
+    /// Print the environment that the current session is executed in
+    Env,
+    /// Set the gas limit of each execution of the current session
+    /// Takes: [gas]
+    GasLimit,
+    /// Set the wall-clock time limit of each execution of the current session
+    /// Takes: [seconds]
+    Timeout,
+    /// Credit an account with ether before each execution of the current session
+    /// Takes: <address> <amount>
+    Deal,
+    /// Print the balance of an account after executing the current session
+    /// Takes: <address>
+    Balance,
+    /// Broadcast statements of the current session to a real network as transactions
+    /// Takes: <rpc-url|rpc_endpoints-alias> <--interactive|--private-key-env <var>|--ledger>
+    /// [--chain-id <id>] [n]...
+    Broadcast,
     /// Dump the raw memory
+    /// Takes: [--full]
     MemDump,
-    /// Dump the raw stack
+    /// Dump the raw stack and return data
+    /// Takes: [--full]
     StackDump,
+    /// Enable or disable retaining the machine state of each execution
+    /// Takes: [on|off]
+    State,
     /// Export the current REPL session source to a Script file
     Export,
     /// Fetch an interface of a verified contract on Etherscan
@@ -50,6 +192,7 @@ pub enum ChiselCommand {
     /// Display the raw value of a variable's stack allocation.
     RawStack,
     /// Open the current session in an editor
+    /// Takes: [snippet-index]
     Edit,
 }
 
@@ -63,14 +206,62 @@ impl FromStr for ChiselCommand {
             "quit" | "q" => Ok(ChiselCommand::Quit),
             "clear" | "c" => Ok(ChiselCommand::Clear),
             "source" | "so" => Ok(ChiselCommand::Source),
+            "snippets" | "sn" => Ok(ChiselCommand::Snippets),
+            "undo" | "u" => Ok(ChiselCommand::Undo),
+            "redo" => Ok(ChiselCommand::Redo),
+            "snapshot" | "snap" => Ok(ChiselCommand::Snapshot),
+            "revert" => Ok(ChiselCommand::Revert),
+            "snapshots" => Ok(ChiselCommand::Snapshots),
             "save" | "s" => Ok(ChiselCommand::Save),
             "list" | "ls" => Ok(ChiselCommand::ListSessions),
             "load" | "l" => Ok(ChiselCommand::Load),
+            "recover" => Ok(ChiselCommand::Recover),
+            "delete" => Ok(ChiselCommand::Delete),
+            "duplicate" | "dup" => Ok(ChiselCommand::Duplicate),
+            "merge" | "m" => Ok(ChiselCommand::Merge),
             "clearcache" | "cc" => Ok(ChiselCommand::ClearCache),
+            "toplevel" | "tl" => Ok(ChiselCommand::TopLevel),
+            "abi" => Ok(ChiselCommand::Abi),
+            "bytecode" | "bc" => Ok(ChiselCommand::Bytecode),
+            "disasm" | "da" => Ok(ChiselCommand::Disasm),
+            "hashes" => Ok(ChiselCommand::Hashes),
+            "stats" => Ok(ChiselCommand::Stats),
+            "events" => Ok(ChiselCommand::Events),
+            "gas" => Ok(ChiselCommand::Gas),
+            "estimate" | "est" => Ok(ChiselCommand::Estimate),
+            "debug" | "dbg" => Ok(ChiselCommand::Debug),
+            "profile" => Ok(ChiselCommand::Profile),
+            "statediff" => Ok(ChiselCommand::StateDiff),
+            "format" => Ok(ChiselCommand::Format),
+            "contractname" | "cn" => Ok(ChiselCommand::ContractName),
+            "license" => Ok(ChiselCommand::License),
+            "pragma" => Ok(ChiselCommand::Pragma),
+            "optimizer" | "opt" => Ok(ChiselCommand::Optimizer),
+            "evm" => Ok(ChiselCommand::EvmVersion),
+            "solc" => Ok(ChiselCommand::Solc),
+            "viair" => Ok(ChiselCommand::ViaIr),
+            "remap" => Ok(ChiselCommand::Remap),
             "fork" | "f" => Ok(ChiselCommand::Fork),
+            "rpc" => Ok(ChiselCommand::Rpc),
             "traces" | "t" => Ok(ChiselCommand::Traces),
+            "rerun" => Ok(ChiselCommand::Rerun),
+            "block" => Ok(ChiselCommand::Block),
+            "timestamp" | "ts" => Ok(ChiselCommand::Timestamp),
+            "basefee" => Ok(ChiselCommand::BaseFee),
+            "chainid" => Ok(ChiselCommand::ChainId),
+            "sender" => Ok(ChiselCommand::Sender),
+            "value" => Ok(ChiselCommand::Value),
+            "env" => Ok(ChiselCommand::Env),
+            "gaslimit" => Ok(ChiselCommand::GasLimit),
+            "timeout" => Ok(ChiselCommand::Timeout),
+            "deal" => Ok(ChiselCommand::Deal),
+            "balance" | "bal" => Ok(ChiselCommand::Balance),
+            "broadcast" => Ok(ChiselCommand::Broadcast),
+            "inspect" | "in" => Ok(ChiselCommand::Inspect),
+            "storage" => Ok(ChiselCommand::Storage),
             "memdump" | "md" => Ok(ChiselCommand::MemDump),
             "stackdump" | "sd" => Ok(ChiselCommand::StackDump),
+            "state" => Ok(ChiselCommand::State),
             "export" | "ex" => Ok(ChiselCommand::Export),
             "fetch" | "fe" => Ok(ChiselCommand::Fetch),
             "exec" | "e" => Ok(ChiselCommand::Exec),
@@ -121,21 +312,69 @@ impl From<ChiselCommand> for CmdDescriptor {
             ChiselCommand::Quit => (&["quit", "q"], "Quit Chisel", CmdCategory::General),
             ChiselCommand::Exec => (&["exec <command> [args]", "e <command> [args]"], "Execute a shell command and print the output", CmdCategory::General),
             // Session
-            ChiselCommand::Clear => (&["clear", "c"], "Clear current session source", CmdCategory::Session),
-            ChiselCommand::Source => (&["source", "so"], "Display the source code of the current session", CmdCategory::Session),
+            ChiselCommand::Clear => (&["clear [keep]", "c [keep]"], "Clear current session source. Supply `keep` to keep import and pragma directives", CmdCategory::Session),
+            ChiselCommand::Source => (&["source", "so"], "Display the source code of the current session, with line numbers and the snippet index of each entered line", CmdCategory::Session),
+            ChiselCommand::Snippets => (&["snippets", "sn"], "List the snippets of the current session, along with when and how they were entered", CmdCategory::Session),
+            ChiselCommand::Undo => (&["undo", "u"], "Remove the most recently entered snippet from the current session", CmdCategory::Session),
+            ChiselCommand::Redo => (&["redo"], "Restore the snippet most recently removed with `!undo`", CmdCategory::Session),
+            ChiselCommand::Snapshot => (&["snapshot", "snap"], "Take a snapshot of the current state of the session and print its ID", CmdCategory::Session),
+            ChiselCommand::Revert => (&["revert <id>"], "Restore the session and its state to a snapshot, removing the snippets entered since", CmdCategory::Session),
+            ChiselCommand::Snapshots => (&["snapshots"], "List the snapshots of the current session, along with the index of the most recent snippet that each includes", CmdCategory::Session),
             ChiselCommand::Save => (&["save [id]", "s [id]"], "Save the current session to cache", CmdCategory::Session),
             ChiselCommand::Load => (&["load <id>", "l <id>"], "Load a previous session ID from cache", CmdCategory::Session),
+            ChiselCommand::Recover => (&["recover <id>"], "Recover unsaved inputs of a session ID from its journal", CmdCategory::Session),
             ChiselCommand::ListSessions => (&["list", "ls"], "List all cached sessions", CmdCategory::Session),
+            ChiselCommand::Delete => (&["delete <id>"], "Delete a session ID from cache", CmdCategory::Session),
+            ChiselCommand::Duplicate => (&["duplicate", "dup"], "Duplicate the current session into a new session ID", CmdCategory::Session),
+            ChiselCommand::Merge => (&["merge <id>", "m <id>"], "Merge a session ID from cache into the current session", CmdCategory::Session),
             ChiselCommand::ClearCache => (&["clearcache", "cc"], "Clear the chisel cache of all stored sessions", CmdCategory::Session),
+            ChiselCommand::TopLevel => (&["toplevel <code>", "tl <code>"], "Add code to the file level of the current session source rather than the REPL contract, i.e. free functions", CmdCategory::Session),
+            ChiselCommand::Abi => (&["abi [<name> <path-or-json>]"], "Generate an interface from an ABI file or JSON and add it to the current session source. Supply 0 arguments to print the ABI of the REPL contract", CmdCategory::Session),
+            ChiselCommand::ContractName => (&["contractname <name>", "cn <name>"], "Rename the REPL contract of the current session source", CmdCategory::Session),
+            ChiselCommand::License => (&["license <id>"], "Set the SPDX license identifier of the current session source and the script it is exported to, `UNLICENSED` by default. The identifier may not contain whitespace", CmdCategory::Session),
+            ChiselCommand::Pragma => (&["pragma [req]"], "Override the solidity version requirement of the current session source. Supply 0 arguments to derive it from the solc version", CmdCategory::Session),
+            ChiselCommand::Optimizer => (&["optimizer [runs]", "opt [runs]"], "Compile the current session source with the optimizer and the given number of runs. Supply `0` or `off` to disable it, or 0 arguments to show the current settings", CmdCategory::Session),
+            ChiselCommand::ViaIr => (&["viair [on|off]"], "Enable / disable compiling the current session source via the IR pipeline. Supply 0 arguments to show the current pipeline", CmdCategory::Session),
+            ChiselCommand::Remap => (&["remap [<from>=<to>]"], "Remap imports of the current session source that start with `from` to the directory `to`. Supply 0 arguments to list the active remappings", CmdCategory::Session),
             ChiselCommand::Export => (&["export", "ex"], "Export the current session source to a script file", CmdCategory::Session),
             ChiselCommand::Fetch => (&["fetch <addr> <name>", "fe <addr> <name>"], "Fetch the interface of a verified contract on Etherscan", CmdCategory::Session),
             // Environment
-            ChiselCommand::Fork => (&["fork <url>", "f <url>"], "Fork an RPC for the current session. Supply 0 arguments to return to a local network", CmdCategory::Env),
+            ChiselCommand::Fork => (&["fork <url> [block]", "f <url> [block]"], "Fork an RPC for the current session at a block, defaulting to the latest. Supply 0 arguments to return to a local network", CmdCategory::Env),
+            ChiselCommand::Rpc => (&["rpc [list]", "rpc add <alias> <url>", "rpc remove <alias>"], "List the RPC aliases that `!fork` and `!broadcast` accept, or add and remove the aliases of the user, which are shared by every session. URLs may reference environment variables, i.e. `${ALCHEMY_KEY}`", CmdCategory::Env),
+            ChiselCommand::Solc => (&["solc [version|list]"], "Change the solc version the current session is compiled with. Supply `list` to list the installed and available versions, or 0 arguments to show the current version", CmdCategory::Env),
+            ChiselCommand::EvmVersion => (&["evm [version]"], "Set the EVM version the current session is compiled for and executed on. Supply 0 arguments to show the current version", CmdCategory::Env),
             ChiselCommand::Traces => (&["traces", "t"], "Enable / disable traces for the current session", CmdCategory::Env),
+            ChiselCommand::Rerun => (&["rerun"], "Execute the current session again from a clean state, fetching the state of the forked network again", CmdCategory::Env),
+            ChiselCommand::Block => (&["block [number]"], "Set `block.number` for subsequent statements. Supply 0 arguments to reset it", CmdCategory::Env),
+            ChiselCommand::Timestamp => (&["timestamp [ts]", "ts [ts]"], "Set `block.timestamp` for subsequent statements. Supply 0 arguments to reset it", CmdCategory::Env),
+            ChiselCommand::BaseFee => (&["basefee [wei]"], "Set `block.basefee` for subsequent statements. Supply 0 arguments to reset it", CmdCategory::Env),
+            ChiselCommand::ChainId => (&["chainid [id]"], "Set `block.chainid` for subsequent statements. Supply 0 arguments to reset it", CmdCategory::Env),
+            ChiselCommand::Sender => (&["sender [address]"], "Set `msg.sender` of subsequent statements. Supply 0 arguments to reset it to the zero address", CmdCategory::Env),
+            ChiselCommand::Value => (&["value [wei]"], "Set `msg.value` of subsequent statements. Supply 0 arguments to reset it", CmdCategory::Env),
+            ChiselCommand::Env => (&["env"], "Print the block and transaction context that the current session is executed in", CmdCategory::Env),
+            ChiselCommand::GasLimit => (&["gaslimit [gas]"], "Set the gas limit of each execution of the current session. Statements that exceed it are rolled back. Supply 0 arguments to show the current limit", CmdCategory::Env),
+            ChiselCommand::Timeout => (&["timeout [seconds]"], "Set the wall-clock time limit of each execution of the current session. Statements that exceed it are rolled back. Supply 0 arguments to show the current limit", CmdCategory::Env),
+            ChiselCommand::Deal => (&["deal <address> <amount>"], "Set the balance of an account before each execution of the current session, overriding its forked balance. The amount is in wei unless suffixed with `gwei` or `ether`", CmdCategory::Env),
+            ChiselCommand::Balance => (&["balance <address>", "bal <address>"], "Print the balance of an account after executing the current session, in wei, gwei, and ether", CmdCategory::Env),
+            ChiselCommand::Broadcast => (&["broadcast <url> <--interactive|--private-key-env <var>|--ledger> [--chain-id <id>] [n]..."], "Send statements of the current session to a real network as transactions, the last statement unless snippet indices are supplied. Each transaction is confirmed at a prompt, as is the chain ID unless `--chain-id` is supplied", CmdCategory::Env),
             // Debug
-            ChiselCommand::MemDump => (&["memdump", "md"], "Dump the raw memory of the current state", CmdCategory::Debug),
-            ChiselCommand::StackDump => (&["stackdump", "sd"], "Dump the raw stack of the current state", CmdCategory::Debug),
-            ChiselCommand::Edit => (&["edit"], "Open the current session in an editor", CmdCategory::Session),
+            ChiselCommand::MemDump => (&["memdump [--full]", "md [--full]"], "Dump the raw memory of the current state by word. Large memory is truncated unless `--full` is supplied", CmdCategory::Debug),
+            ChiselCommand::StackDump => (&["stackdump [--full]", "sd [--full]"], "Dump the raw stack and return data buffer of the current state. Large stacks are truncated unless `--full` is supplied", CmdCategory::Debug),
+            ChiselCommand::State => (&["state [on|off]"], "Enable / disable retaining the machine state at the end of each execution, so that `!memdump` and `!stackdump` do not execute the session again. Supply 0 arguments to show whether it is enabled", CmdCategory::Debug),
+            ChiselCommand::Bytecode => (&["bytecode [name] [creation|deployed]", "bc [name] [creation|deployed]"], "Print the creation and deployed bytecode of the REPL contract, along with the addresses of the libraries it is linked against. Supply the name of a contract defined within the session to print its bytecode instead, and `creation` or `deployed` to print only its hex", CmdCategory::Debug),
+            ChiselCommand::Disasm => (&["disasm [name] [--pc <n>]", "da [name] [--pc <n>]"], "Disassemble the deployed bytecode of the REPL contract, or of a contract defined within the session, into an opcode listing split into basic blocks. With `--pc`, print only the basic block of a program counter and mark its instruction", CmdCategory::Debug),
+            ChiselCommand::Hashes => (&["hashes"], "Print the selectors of the functions and errors, and the topics of the events, of the REPL contract", CmdCategory::Debug),
+            ChiselCommand::Gas => (&["gas", "gas used"], "Print the gas estimates of solc for the functions of the REPL contract and the other contracts of the current session. Unbounded estimates are marked as `infinite`. With `used`, print the gas used by each executed snippet instead", CmdCategory::Debug),
+            ChiselCommand::Format => (&["format [hex|dec]"], "Display integers within arrays, structs, events, and `!inspect` in hex or decimal. Supply 0 arguments to show the current format", CmdCategory::Debug),
+            ChiselCommand::Estimate => (&["estimate <statement>", "est <statement>"], "Estimate the gas used by a statement and whether it would revert, without adding it to the current session or changing its state", CmdCategory::Debug),
+            ChiselCommand::Debug => (&["debug [statement]", "dbg [statement]"], "Step through the instructions of the last statement of the current session, or of the last statement that reverted, in an interactive debugger. Supply a statement to debug it against the current session instead", CmdCategory::Debug),
+            ChiselCommand::Profile => (&["profile [--json]"], "Execute the last statement of the current session again and print where its gas went, by category of opcode, by opcode, and by external call. With `--json`, print the raw profile as JSON instead", CmdCategory::Debug),
+            ChiselCommand::StateDiff => (&["statediff [--all] [--json]"], "Print the accounts and storage slots that the last statement of the current session touched, how their balance, nonce, code, and values changed, and an EIP-2930 access list for the statement. The bookkeeping of the REPL is hidden unless `--all` is supplied. With `--json`, print the raw diff as JSON instead", CmdCategory::Debug),
+            ChiselCommand::Stats => (&["stats"], "Print how long the most recent compilation of the current session source took, and whether it was cached", CmdCategory::Debug),
+            ChiselCommand::Inspect => (&["inspect <name>", "in <name>"], "Print the current value of a state variable of the current session, read from the storage of the REPL contract. Index mappings and arrays by appending keys, i.e. `balances[0xabc]`", CmdCategory::Debug),
+            ChiselCommand::Storage => (&["storage"], "Print the storage layout of the REPL contract, the slot, offset, type, and name of each state variable of the current session. Warns if variables moved since the previous compile", CmdCategory::Debug),
+            ChiselCommand::Events => (&["events"], "Print the events emitted by the statements of the current session, decoded by the session's events", CmdCategory::Debug),
+            ChiselCommand::Edit => (&["edit [n]"], "Open the current session in an editor. Supply a snippet index to edit only that snippet", CmdCategory::Session),
             ChiselCommand::RawStack => (&["rawstack <var>", "rs <var>"], "Display the raw value of a variable's stack allocation. For variables that are > 32 bytes in length, this will display their memory pointer.", CmdCategory::Debug),
         }
     }
@@ -0,0 +1,426 @@
+//! Completion
+//!
+//! This module contains the [Completions] of the REPL, the candidates that the
+//! [crate::solidity_helper::SolidityHelper] completes on tab: commands, Solidity keywords and
+//! builtins, and the items defined within the current session along with their members.
+
+use crate::{
+    cmd::{ChiselCommand, CmdDescriptor},
+    session_source::{SessionSource, VM_SOURCE},
+};
+use solang_parser::pt;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use strum::IntoEnumIterator;
+
+/// Solidity keywords and global functions, which complete anywhere an identifier does. The sized
+/// integer and bytes types are generated by [sized_types].
+static KEYWORDS: &[&str] = &[
+    "abi",
+    "abstract",
+    "addmod",
+    "address",
+    "anonymous",
+    "assert",
+    "block",
+    "blockhash",
+    "bool",
+    "break",
+    "bytes",
+    "calldata",
+    "catch",
+    "constant",
+    "constructor",
+    "continue",
+    "contract",
+    "days",
+    "delete",
+    "do",
+    "ecrecover",
+    "else",
+    "emit",
+    "enum",
+    "error",
+    "ether",
+    "event",
+    "external",
+    "false",
+    "for",
+    "function",
+    "gasleft",
+    "gwei",
+    "hours",
+    "if",
+    "immutable",
+    "import",
+    "indexed",
+    "interface",
+    "internal",
+    "is",
+    "keccak256",
+    "library",
+    "mapping",
+    "memory",
+    "minutes",
+    "modifier",
+    "msg",
+    "mulmod",
+    "new",
+    "override",
+    "payable",
+    "pragma",
+    "private",
+    "public",
+    "pure",
+    "require",
+    "return",
+    "returns",
+    "revert",
+    "ripemd160",
+    "seconds",
+    "selfdestruct",
+    "sha256",
+    "storage",
+    "string",
+    "struct",
+    "this",
+    "true",
+    "try",
+    "tx",
+    "type",
+    "unchecked",
+    "using",
+    "view",
+    "virtual",
+    "vm",
+    "weeks",
+    "wei",
+    "while",
+];
+
+/// The members of the builtin globals, as well as of `type(..)` and of addresses
+static BUILTIN_MEMBERS: &[(&str, &[&str])] = &[
+    ("msg", &["data", "sender", "sig", "value"]),
+    (
+        "block",
+        &[
+            "basefee",
+            "chainid",
+            "coinbase",
+            "difficulty",
+            "gaslimit",
+            "number",
+            "prevrandao",
+            "timestamp",
+        ],
+    ),
+    ("tx", &["gasprice", "origin"]),
+    (
+        "abi",
+        &[
+            "decode",
+            "encode",
+            "encodeCall",
+            "encodePacked",
+            "encodeWithSelector",
+            "encodeWithSignature",
+        ],
+    ),
+    ("type", &["creationCode", "interfaceId", "max", "min", "name", "runtimeCode"]),
+    (
+        "address",
+        &["balance", "call", "code", "codehash", "delegatecall", "send", "staticcall", "transfer"],
+    ),
+];
+
+/// The candidates that the REPL completes on tab
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completions {
+    /// The members of the builtin globals and types, by name, including the cheatcodes of `vm`
+    pub builtins: BTreeMap<String, BTreeSet<String>>,
+    /// The names of the items defined within the session, see
+    /// [SessionSource::defined_symbols]
+    pub identifiers: BTreeSet<String>,
+    /// The names of the types of the variables defined within the session, by variable
+    pub types: HashMap<String, String>,
+    /// The members of the contracts and structs defined within the session, by name
+    pub members: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl Default for Completions {
+    fn default() -> Self {
+        let mut builtins = BUILTIN_MEMBERS
+            .iter()
+            .map(|(name, members)| {
+                (name.to_string(), members.iter().map(|member| member.to_string()).collect())
+            })
+            .collect::<BTreeMap<_, BTreeSet<_>>>();
+        builtins.insert("vm".to_string(), cheatcodes());
+        Self {
+            builtins,
+            identifiers: BTreeSet::new(),
+            types: HashMap::new(),
+            members: BTreeMap::new(),
+        }
+    }
+}
+
+impl Completions {
+    /// Creates the completions of a session source
+    pub fn new(source: Option<&SessionSource>) -> Self {
+        let mut completions = Self::default();
+        completions.update(source);
+        completions
+    }
+
+    /// Derives the candidates of the session again, i.e. after the session changed
+    ///
+    /// ### Takes
+    ///
+    /// The current [SessionSource], if any
+    pub fn update(&mut self, source: Option<&SessionSource>) {
+        self.identifiers.clear();
+        self.types.clear();
+        self.members.clear();
+        let source = match source {
+            Some(source) => source,
+            None => return,
+        };
+
+        // Items nested within a contract are qualified with its name, i.e. `Counter.increment`
+        for symbol in source.defined_symbols() {
+            match symbol.name.split_once('.') {
+                Some((contract, member)) => {
+                    self.members
+                        .entry(contract.to_string())
+                        .or_default()
+                        .insert(member.to_string());
+                }
+                None => {
+                    self.identifiers.insert(symbol.name);
+                }
+            }
+        }
+
+        // The symbol table does not know the types of variables nor the fields of structs
+        let pt::SourceUnit(parts) = match source.parse() {
+            Ok(source_unit) => source_unit,
+            Err(_) => return,
+        };
+        for part in &parts {
+            match part {
+                pt::SourceUnitPart::ContractDefinition(cd) => {
+                    for part in &cd.parts {
+                        match part {
+                            pt::ContractPart::StructDefinition(def) => self.add_struct(def),
+                            pt::ContractPart::VariableDefinition(def)
+                                if cd.name.name == source.contract_name =>
+                            {
+                                self.add_variable(&def.name.name, &def.ty)
+                            }
+                            pt::ContractPart::FunctionDefinition(def)
+                                if cd.name.name == source.contract_name &&
+                                    def.name
+                                        .as_ref()
+                                        .map_or(false, |name| name.name == "run") =>
+                            {
+                                if let Some(pt::Statement::Block { statements, .. }) = &def.body {
+                                    for (name, ty) in statements
+                                        .iter()
+                                        .flat_map(SessionSource::get_statement_definitions)
+                                    {
+                                        self.add_variable(&name, &ty);
+                                    }
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                pt::SourceUnitPart::StructDefinition(def) => self.add_struct(def),
+                pt::SourceUnitPart::VariableDefinition(def) => {
+                    self.add_variable(&def.name.name, &def.ty)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Records the fields of a struct as its members
+    fn add_struct(&mut self, def: &pt::StructDefinition) {
+        let fields = def.fields.iter().map(|field| field.name.name.clone());
+        self.members.entry(def.name.name.clone()).or_default().extend(fields);
+    }
+
+    /// Records the type of a variable, if its members can be completed
+    fn add_variable(&mut self, name: &str, ty: &pt::Expression) {
+        if let Some(ty) = type_name(ty) {
+            self.types.insert(name.to_string(), ty);
+        }
+    }
+
+    /// Finds the candidates that complete the input at a cursor position
+    ///
+    /// ### Takes
+    ///
+    /// - The input
+    /// - The byte position of the cursor within the input
+    ///
+    /// ### Returns
+    ///
+    /// The byte position that the candidates replace the input from, along with the candidates,
+    /// sorted. After a dot, the candidates are the members of the expression before it.
+    /// Otherwise, they are the commands after a `!` and the identifiers elsewhere.
+    pub fn complete(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let before = match line.get(..pos) {
+            Some(before) => before,
+            None => return (pos, Vec::new()),
+        };
+        let start = word_start(before);
+        let prefix = &before[start..];
+
+        // Commands are only completed as the first word of the input
+        let trimmed = before.trim_start();
+        if let Some(command) = trimmed.strip_prefix('!') {
+            if !command.contains(char::is_whitespace) {
+                return (pos - command.len(), matching(command_names(), command))
+            }
+            return (start, matching(self.identifiers.iter().cloned(), prefix))
+        }
+
+        let candidates = match before[..start].strip_suffix('.') {
+            Some(receiver) => match receiver_name(receiver) {
+                Some(receiver) => matching(self.members_of(receiver), prefix),
+                None => Vec::new(),
+            },
+            None if prefix.is_empty() => Vec::new(),
+            None => matching(
+                KEYWORDS
+                    .iter()
+                    .map(|keyword| keyword.to_string())
+                    .chain(sized_types())
+                    .chain(self.identifiers.iter().cloned()),
+                prefix,
+            ),
+        };
+        (start, candidates)
+    }
+
+    /// Collects the members of a name, which is either a variable, a type, or a builtin global
+    fn members_of(&self, name: &str) -> Vec<String> {
+        let ty = self.types.get(name).map(String::as_str).unwrap_or(name);
+        self.builtins
+            .get(name)
+            .into_iter()
+            .chain(self.builtins.get(ty))
+            .chain(self.members.get(ty))
+            .flatten()
+            .cloned()
+            .collect()
+    }
+}
+
+/// Filters candidates by a prefix
+///
+/// ### Returns
+///
+/// The candidates that start with the prefix, sorted and without duplicates
+fn matching(candidates: impl IntoIterator<Item = String>, prefix: &str) -> Vec<String> {
+    candidates
+        .into_iter()
+        .filter(|candidate| candidate.starts_with(prefix))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// Whether a character may be part of a Solidity identifier
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '$'
+}
+
+/// Finds the byte position of the identifier that the input ends with
+fn word_start(input: &str) -> usize {
+    input.trim_end_matches(is_identifier_char).len()
+}
+
+/// Finds the name that members are completed for, given the input up to the dot
+///
+/// ### Returns
+///
+/// The identifier the input ends with, i.e. `c` in `c.`. If the input ends with a call, i.e.
+/// `Counter(addr).` or `type(Counter).`, the name of the callee.
+fn receiver_name(input: &str) -> Option<&str> {
+    let input = match input.strip_suffix(')') {
+        Some(args) => {
+            let mut depth = 1usize;
+            let open = args.char_indices().rev().find_map(|(i, c)| {
+                match c {
+                    ')' => depth += 1,
+                    '(' => depth -= 1,
+                    _ => {}
+                }
+                (depth == 0).then_some(i)
+            })?;
+            &args[..open]
+        }
+        None => input,
+    };
+    let name = &input[word_start(input)..];
+    (!name.is_empty()).then_some(name)
+}
+
+/// Finds the name of the type that a variable of a type expression has members of
+fn type_name(ty: &pt::Expression) -> Option<String> {
+    match ty {
+        pt::Expression::Variable(ident) | pt::Expression::MemberAccess(_, _, ident) => {
+            Some(ident.name.clone())
+        }
+        pt::Expression::Type(_, pt::Type::Address | pt::Type::AddressPayable) => {
+            Some("address".to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Generates the names of the integer and fixed-size bytes types
+///
+/// ### Returns
+///
+/// `uint` and `int`, `uint8` through `uint256` and `int8` through `int256` in steps of 8 bits,
+/// and `bytes1` through `bytes32`
+fn sized_types() -> impl Iterator<Item = String> {
+    ["uint", "int"]
+        .into_iter()
+        .map(str::to_string)
+        .chain((8..=256).step_by(8).flat_map(|bits| [format!("uint{bits}"), format!("int{bits}")]))
+        .chain((1..=32).map(|size| format!("bytes{size}")))
+}
+
+/// Collects the names and aliases of the commands, see [CmdDescriptor]
+fn command_names() -> Vec<String> {
+    ChiselCommand::iter()
+        .flat_map(|cmd| CmdDescriptor::from(cmd).0.iter())
+        .filter_map(|usage| usage.split_whitespace().next())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Collects the names of the cheatcodes of forge-std's `Vm` interface
+fn cheatcodes() -> BTreeSet<String> {
+    let pt::SourceUnit(parts) = match solang_parser::parse(VM_SOURCE, 0) {
+        Ok((source_unit, _)) => source_unit,
+        Err(_) => return BTreeSet::new(),
+    };
+    parts
+        .iter()
+        .filter_map(|part| match part {
+            pt::SourceUnitPart::ContractDefinition(cd) => Some(&cd.parts),
+            _ => None,
+        })
+        .flatten()
+        .filter_map(|part| match part {
+            pt::ContractPart::FunctionDefinition(def) => Some(def.name.as_ref()?.name.clone()),
+            _ => None,
+        })
+        .collect()
+}
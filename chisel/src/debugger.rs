@@ -0,0 +1,257 @@
+//! Debugger
+//!
+//! This module contains the [StepDebugger], a line-mode viewer that steps through the
+//! instructions executed by the `run()` function of the REPL contract, as recorded by the
+//! debugger inspector. It starts at the last statement of the session.
+
+use crate::dispatcher::{ChiselDispatcher, DUMP_LIMIT};
+use ethers::{types::Address, utils::hex};
+use forge::debug::{DebugStep, Instruction};
+use revm::opcode;
+use yansi::Paint;
+
+/// The number of stack items that are shown along with each step
+const STACK_PREVIEW: usize = 4;
+
+/// The help text of the [StepDebugger]
+pub const DEBUGGER_HELP: &str = "Commands:
+  s, step [n]            Execute the next instruction, or the next n instructions
+  b, back [n]            Go back an instruction, or n instructions
+  n, next                Step over calls, to the next instruction of the current call frame
+  c, continue            Run until the next CALL, CREATE, RETURN, or REVERT
+  stack                  Print the whole stack, from its top down
+  m, memory [off] [len]  Print the memory, or a slice of it, by word
+  h, help                Print this help
+  q, quit                Exit the debugger";
+
+/// An instruction executed by the `run()` function of the REPL contract, or by a contract that it
+/// called
+#[derive(Debug, Clone)]
+pub struct Step {
+    /// The address of the code that executed the instruction
+    pub address: Address,
+    /// The depth of the call frame, starting at 0 for the `run()` function
+    pub depth: usize,
+    /// The state before the instruction was executed
+    pub step: DebugStep,
+    /// The line of the formatted REPL contract that the instruction maps to, if the REPL
+    /// contract executed it
+    pub line: Option<usize>,
+    /// The index of the snippet that the instruction maps to, if any
+    pub snippet: Option<usize>,
+}
+
+/// A line-mode step debugger over the recorded [Step]s of an execution
+#[derive(Debug, Clone)]
+pub struct StepDebugger {
+    /// The steps of the execution, in order
+    pub steps: Vec<Step>,
+    /// The lines of the formatted REPL contract
+    pub source: Vec<String>,
+    /// The index of the current step
+    pub cursor: usize,
+}
+
+impl StepDebugger {
+    /// Creates a new [StepDebugger]
+    ///
+    /// ### Takes
+    ///
+    /// - The steps of the execution, in order
+    /// - The formatted source of the REPL contract that the lines of the steps refer to
+    /// - The index of the snippet to start at. The debugger starts at the first step of the
+    ///   snippet, or else at the first step.
+    pub fn new(steps: Vec<Step>, source: &str, start: Option<usize>) -> Self {
+        let cursor = start
+            .and_then(|start| steps.iter().position(|step| step.snippet == Some(start)))
+            .unwrap_or_default();
+        Self { steps, source: source.lines().map(String::from).collect(), cursor }
+    }
+
+    /// The current step
+    pub fn current(&self) -> Option<&Step> {
+        self.steps.get(self.cursor)
+    }
+
+    /// Executes a command of the debugger, see [DEBUGGER_HELP]. An empty command executes the
+    /// next instruction.
+    ///
+    /// ### Returns
+    ///
+    /// The output of the command, or `None` if the debugger was quit
+    pub fn command(&mut self, input: &str) -> Option<String> {
+        let args = input.split_whitespace().collect::<Vec<_>>();
+        Some(match args.as_slice() {
+            [] => self.step_by(true, 1),
+            ["s" | "step"] => self.step_by(true, 1),
+            ["s" | "step", n] => match n.parse::<usize>() {
+                Ok(n) => self.step_by(true, n),
+                Err(_) => format!("Invalid number of steps `{n}`"),
+            },
+            ["b" | "back"] => self.step_by(false, 1),
+            ["b" | "back", n] => match n.parse::<usize>() {
+                Ok(n) => self.step_by(false, n),
+                Err(_) => format!("Invalid number of steps `{n}`"),
+            },
+            ["n" | "next"] => match self.current() {
+                Some(step) => {
+                    let depth = step.depth;
+                    self.advance_to(|step| step.depth <= depth)
+                }
+                None => self.location(),
+            },
+            ["c" | "continue"] => self.advance_to(|step| {
+                matches!(
+                    step.step.instruction,
+                    Instruction::OpCode(
+                        opcode::CALL |
+                            opcode::CALLCODE |
+                            opcode::DELEGATECALL |
+                            opcode::STATICCALL |
+                            opcode::CREATE |
+                            opcode::CREATE2 |
+                            opcode::RETURN |
+                            opcode::REVERT
+                    )
+                )
+            }),
+            ["stack"] => self.stack(None),
+            ["m" | "memory", range @ ..] if range.len() <= 2 => {
+                match range.iter().map(|arg| parse_number(arg)).collect::<Option<Vec<_>>>() {
+                    Some(range) => self.memory(range.first().copied(), range.get(1).copied()),
+                    None => String::from(
+                        "The offset and length must be decimal or `0x` prefixed hex numbers",
+                    ),
+                }
+            }
+            ["h" | "help"] => String::from(DEBUGGER_HELP),
+            ["q" | "quit"] => return None,
+            _ => format!("Unknown command `{input}`, enter `help` to list the commands"),
+        })
+    }
+
+    /// Describes the current step: its position, instruction, call frame, source line, and the
+    /// top of the stack
+    pub fn location(&self) -> String {
+        let step = match self.current() {
+            Some(step) => step,
+            None => return String::from("No instructions were executed"),
+        };
+        let push = step
+            .step
+            .push_bytes
+            .as_ref()
+            .map(|bytes| format!(" 0x{}", hex::encode(bytes)))
+            .unwrap_or_default();
+        let source = match step.line {
+            Some(line) => format!(
+                "line {line}: {}",
+                line.checked_sub(1)
+                    .and_then(|index| self.source.get(index))
+                    .map(|code| code.trim())
+                    .unwrap_or_default()
+            ),
+            None => format!("{:?} (not the REPL contract)", step.address),
+        };
+        format!(
+            "{} pc 0x{:x} {}{push} (depth {}, gas used {})\n{} {}\n{}",
+            Paint::yellow(format!("[{}/{}]", self.cursor + 1, self.steps.len())),
+            step.step.pc,
+            Paint::cyan(&step.step.instruction),
+            step.depth,
+            step.step.total_gas_used,
+            Paint::yellow("Source:"),
+            source,
+            self.stack(Some(STACK_PREVIEW))
+        )
+    }
+
+    /// Moves the cursor forward or back by a number of steps, within the bounds of the execution
+    fn step_by(&mut self, forward: bool, steps: usize) -> String {
+        let last = self.steps.len().saturating_sub(1);
+        let target = if forward {
+            self.cursor.saturating_add(steps).min(last)
+        } else {
+            self.cursor.saturating_sub(steps)
+        };
+        let note = match steps {
+            0 => "",
+            _ if forward && self.cursor == last => "Reached the end of the execution\n",
+            _ if !forward && self.cursor == 0 => "Reached the start of the execution\n",
+            _ => "",
+        };
+        self.cursor = target;
+        format!("{note}{}", self.location())
+    }
+
+    /// Moves the cursor to the next step that satisfies a predicate, or to the last step
+    fn advance_to(&mut self, predicate: impl Fn(&Step) -> bool) -> String {
+        match self.steps.iter().skip(self.cursor + 1).position(predicate) {
+            Some(offset) => {
+                self.cursor += offset + 1;
+                self.location()
+            }
+            None => {
+                self.cursor = self.steps.len().saturating_sub(1);
+                format!("Reached the end of the execution\n{}", self.location())
+            }
+        }
+    }
+
+    /// Formats the stack of the current step from its top down, optionally only its top items
+    fn stack(&self, limit: Option<usize>) -> String {
+        let stack = match self.current() {
+            Some(step) if !step.step.stack.is_empty() => &step.step.stack,
+            _ => return format!("{} empty", Paint::yellow("Stack:")),
+        };
+        let shown = limit.unwrap_or(stack.len()).min(stack.len());
+        let mut lines = vec![Paint::yellow("Stack:").to_string()];
+        lines.extend(stack.iter().rev().take(shown).enumerate().map(|(i, item)| {
+            format!(
+                "  {}: {}",
+                Paint::yellow(format!("[{i}]")),
+                Paint::cyan(format!("0x{item:02x}"))
+            )
+        }));
+        if shown < stack.len() {
+            lines.push(format!(
+                "  ... {} more items, enter `stack` to print all of them",
+                stack.len() - shown
+            ));
+        }
+        lines.join("\n")
+    }
+
+    /// Formats the memory of the current step by word, optionally only a slice of it
+    fn memory(&self, offset: Option<usize>, length: Option<usize>) -> String {
+        let memory = match self.current() {
+            Some(step) => step.step.memory.data(),
+            None => return String::from("Memory is empty"),
+        };
+        let offset = offset.unwrap_or_default().min(memory.len());
+        let length = length.unwrap_or(DUMP_LIMIT * 32);
+        let end = offset.saturating_add(length).min(memory.len());
+        if offset == end {
+            return String::from("Memory is empty")
+        }
+        let mut words = ChiselDispatcher::format_words(&memory[offset..end], true);
+        if offset > 0 {
+            words = format!("Memory from offset 0x{offset:02x}:\n{words}");
+        }
+        if end < memory.len() {
+            words.push_str(&format!(
+                "\n... {} more bytes, supply an offset and a length to print them",
+                memory.len() - end
+            ));
+        }
+        words
+    }
+}
+
+/// Parses a decimal or `0x` prefixed hex number
+fn parse_number(number: &str) -> Option<usize> {
+    match number.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => number.parse().ok(),
+    }
+}
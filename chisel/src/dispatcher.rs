@@ -4,20 +4,27 @@
 //! of both builtin commands and Solidity snippets.
 
 use crate::prelude::{
-    ChiselCommand, ChiselResult, ChiselSession, CmdCategory, CmdDescriptor, SessionSourceConfig,
-    SolidityHelper,
+    check_cheatcodes, disassemble, layout_table, parse_amount, parse_timeout, redact_api_key,
+    AbiHashes, BroadcastArgs, BroadcastReceipt, Broadcaster, ChiselCommand, ChiselResult,
+    ChiselSession, CmdCategory, CmdDescriptor, ForkConfig, NumberFormat, ParseTreeFragment,
+    RedefinitionPolicy, RevertReason, RpcAliases, SessionSource, SessionSourceConfig,
+    SnippetOrigin, SolSnippet, TransactionKind,
+};
+use ethers::{
+    abi::ParamType,
+    types::{Address, U256},
+    utils::{hex, to_checksum},
 };
-use ethers::{abi::ParamType, utils::hex};
 use forge::{
     decode::decode_console_logs,
     trace::{
-        identifier::{EtherscanIdentifier, SignaturesIdentifier},
+        identifier::{EtherscanIdentifier, LocalTraceIdentifier, SignaturesIdentifier},
         CallTraceDecoder, CallTraceDecoderBuilder, TraceKind,
     },
 };
 use forge_fmt::FormatterConfig;
-use foundry_config::{Config, RpcEndpoint};
-use reqwest::Url;
+use foundry_common::ContractsByArtifact;
+use foundry_config::{Chain, Config};
 use serde::{Deserialize, Serialize};
 use solang_parser::diagnostics::Diagnostic;
 use std::{error::Error, io::Write, path::PathBuf, process::Command};
@@ -26,10 +33,14 @@ use yansi::Paint;
 
 /// Prompt arrow slice
 static PROMPT_ARROW: char = '➜';
+/// Prompt shown while reading the continuation lines of incomplete input
+pub static CONTINUATION_PROMPT: &str = "... ";
 /// Command leader character
 static COMMAND_LEADER: char = '!';
 /// Chisel character
 static CHISEL_CHAR: &str = "⚒️";
+/// The number of words or stack items that `!memdump` and `!stackdump` print without `--full`
+pub static DUMP_LIMIT: usize = 64;
 
 /// Chisel input dispatcher
 #[derive(Debug)]
@@ -38,6 +49,9 @@ pub struct ChiselDispatcher {
     pub errored: bool,
     /// A Chisel Session
     pub session: ChiselSession,
+    /// The most recent statement that reverted, which was not added to the session. `!debug`
+    /// steps through it.
+    pub reverted: Option<String>,
 }
 
 /// Chisel dispatch result variants
@@ -57,6 +71,19 @@ pub enum DispatchResult {
     CommandFailed(String),
     /// File IO Error
     FileIoError(Box<dyn Error>),
+    /// The REPL should exit, once it has persisted the session and its history
+    Quit,
+}
+
+/// A session command applied by [ChiselDispatcher::apply_command]
+#[derive(Debug)]
+pub struct AppliedCommand {
+    /// The journal entry that applies the command again, see [ChiselSession::journal]
+    pub entry: String,
+    /// The message describing the applied command
+    pub message: Option<String>,
+    /// Warnings about the applied command
+    pub warnings: Vec<String>,
 }
 
 /// A response from the Etherscan API's `getabi` action
@@ -110,7 +137,12 @@ pub fn format_source(source: &str, config: FormatterConfig) -> eyre::Result<Stri
 impl ChiselDispatcher {
     /// Associated public function to create a new Dispatcher instance
     pub fn new(config: &SessionSourceConfig) -> eyre::Result<Self> {
-        ChiselSession::new(config).map(|session| Self { errored: false, session })
+        ChiselSession::new(config).map(Self::with_session)
+    }
+
+    /// Associated public function to create a new Dispatcher instance for an existing session
+    pub fn with_session(session: ChiselSession) -> Self {
+        Self { errored: false, session, reverted: None }
     }
 
     /// Returns the prompt given the last input's error status
@@ -176,40 +208,68 @@ impl ChiselDispatcher {
                         .join("\n")
                 )))
             }
-            ChiselCommand::Quit => {
-                // Exit the process with status code `0` for success.
-                std::process::exit(0);
-            }
-            ChiselCommand::Clear => {
-                if let Some(session_source) = self.session.session_source.as_mut() {
-                    // Drain all source sections
-                    session_source.drain_run();
-                    session_source.drain_global_code();
-                    session_source.drain_top_level_code();
-
-                    DispatchResult::CommandSuccess(Some(String::from("Cleared session!")))
-                } else {
-                    DispatchResult::CommandFailed(
-                        Paint::red("Session source not present!").to_string(),
-                    )
+            // Exiting is left to the REPL, which shuts down the same way as on EOF
+            ChiselCommand::Quit => DispatchResult::Quit,
+            ChiselCommand::Clear |
+            ChiselCommand::Undo |
+            ChiselCommand::Redo |
+            ChiselCommand::Snapshot |
+            ChiselCommand::Revert => self.apply(cmd, args).await,
+            ChiselCommand::Snapshots => match &self.session.session_source {
+                Some(source) if source.snapshots.is_empty() => DispatchResult::CommandSuccess(
+                    Some(String::from("The session has no snapshots")),
+                ),
+                Some(source) => {
+                    let snapshots = source
+                        .snapshots
+                        .iter()
+                        .map(|snapshot| {
+                            let last = snapshot.snippets.iter().rfind(|snippet| !snippet.continued);
+                            let snippet = match (snapshot.snippet_index(), last) {
+                                (Some(index), Some(last)) => format!(
+                                    "snippet {index} `{}`",
+                                    last.code.trim().lines().next().unwrap_or_default()
+                                ),
+                                _ => String::from("empty session"),
+                            };
+                            let kind = if snapshot.automatic { " (automatic)" } else { "" };
+                            format!("  {}: {snippet}{kind}", snapshot.id)
+                        })
+                        .collect::<Vec<_>>();
+                    DispatchResult::CommandSuccess(Some(format!(
+                        "Snapshots:\n{}",
+                        snapshots.join("\n")
+                    )))
                 }
-            }
+                None => {
+                    DispatchResult::CommandFailed(Self::make_error("Session source not present!"))
+                }
+            },
             ChiselCommand::Save => {
                 if args.len() <= 1 {
                     // If a new name was supplied, overwrite the ID of the current session.
-                    if args.len() == 1 {
-                        // TODO: Should we delete the old cache file if the id of the session
-                        // changes?
-                        self.session.id = Some(args[0].to_owned());
-                    }
+                    // TODO: Should we delete the old cache file if the id of the session
+                    // changes?
+                    let res = if args.len() == 1 {
+                        if let Err(e) = ChiselSession::validate_id(args[0]) {
+                            return DispatchResult::CommandFailed(Self::make_error(e))
+                        }
+                        self.session.write_as(args[0])
+                    } else {
+                        self.session.write()
+                    };
 
-                    if let Err(e) = self.session.write() {
+                    if let Err(e) = res {
                         return DispatchResult::FileIoError(e.into())
                     }
-                    DispatchResult::CommandSuccess(Some(format!(
+                    let mut msg = format!(
                         "Saved session to cache with ID = {}",
                         self.session.id.as_ref().unwrap()
-                    )))
+                    );
+                    for id in self.session.take_evicted() {
+                        msg.push_str(&format!("\nEvicted session {id} from the cache"));
+                    }
+                    DispatchResult::CommandSuccess(Some(msg))
                 } else {
                     DispatchResult::CommandFailed(Self::make_error("Too many arguments supplied!"))
                 }
@@ -236,36 +296,180 @@ impl ChiselDispatcher {
                 }
                 // Parse the arguments
                 let new_session = match name {
-                    "latest" => ChiselSession::latest(),
-                    _ => ChiselSession::load(name),
+                    "latest" => self.session.latest(),
+                    _ => self.session.load(name),
                 };
 
                 // WARNING: Overwrites the current session
-                if let Ok(mut new_session) = new_session {
-                    // Regenerate [IntermediateOutput]; It cannot be serialized.
-                    //
-                    // SAFETY
-                    // Should never panic due to the checks performed when the session was created
-                    // in the first place.
-                    new_session.session_source.as_mut().unwrap().build().unwrap();
-
-                    self.session = new_session;
+                match new_session {
+                    Ok(mut new_session) => {
+                        new_session.autosave = self.session.autosave;
+                        if let Some(source) = &self.session.session_source {
+                            new_session
+                                .inherit_rpc_endpoints(&source.config.foundry_config.rpc_endpoints);
+                        }
+                        for skipped in &new_session.skipped_sessions {
+                            eprintln!("{}", Paint::yellow(format!("Skipping session: {skipped}")));
+                        }
+                        for broken in &new_session.broken_snippets {
+                            eprintln!("{}", Paint::yellow(format!("Skipped {broken}")));
+                        }
+
+                        self.session = new_session;
+                        DispatchResult::CommandSuccess(Some(format!(
+                            "Loaded Chisel session! (ID = {})",
+                            self.session.id.as_ref().unwrap()
+                        )))
+                    }
+                    Err(e) => DispatchResult::CommandFailed(Self::make_error(format!(
+                        "Failed to load session! {e}"
+                    ))),
+                }
+            }
+            ChiselCommand::Recover => {
+                if args.len() != 1 {
+                    // Must supply a session ID as the argument.
+                    return DispatchResult::CommandFailed(Self::make_error(
+                        "Must supply a session ID as the argument.",
+                    ))
+                }
+
+                // WARNING: Overwrites the current session
+                match self.session.recover(args[0]).await {
+                    Ok(mut recovered) => {
+                        recovered.autosave = self.session.autosave;
+                        if let Some(source) = &self.session.session_source {
+                            recovered
+                                .inherit_rpc_endpoints(&source.config.foundry_config.rpc_endpoints);
+                        }
+                        for broken in &recovered.broken_snippets {
+                            eprintln!("{}", Paint::yellow(format!("Skipped {broken}")));
+                        }
+                        self.session = recovered;
+                        DispatchResult::CommandSuccess(Some(format!(
+                            "Recovered Chisel session! (ID = {}) Use `!save` to keep it.",
+                            self.session.id.as_ref().unwrap()
+                        )))
+                    }
+                    Err(e) => DispatchResult::CommandFailed(Self::make_error(format!(
+                        "Failed to recover session! {e}"
+                    ))),
+                }
+            }
+            ChiselCommand::Delete => {
+                if args.len() != 1 {
+                    // Must supply a session ID as the argument.
+                    return DispatchResult::CommandFailed(Self::make_error(
+                        "Must supply a session ID as the argument.",
+                    ))
+                }
+
+                match self.session.delete_session(args[0]) {
+                    Ok(_) => DispatchResult::CommandSuccess(Some(format!(
+                        "Deleted session with ID = {}",
+                        args[0]
+                    ))),
+                    Err(e) => DispatchResult::CommandFailed(Self::make_error(e)),
+                }
+            }
+            ChiselCommand::Duplicate => match self.session.duplicate() {
+                Ok(duplicate) => {
+                    // Experiment on the duplicate from here on out; The original session is
+                    // left untouched in the cache.
+                    let original = std::mem::replace(&mut self.session, duplicate);
                     DispatchResult::CommandSuccess(Some(format!(
-                        "Loaded Chisel session! (ID = {})",
+                        "Duplicated session {}! (ID = {})",
+                        original.id.as_deref().unwrap_or("<unsaved>"),
                         self.session.id.as_ref().unwrap()
                     )))
-                } else {
-                    DispatchResult::CommandFailed(Self::make_error("Failed to load session!"))
                 }
+                Err(e) => DispatchResult::CommandFailed(Self::make_error(e)),
+            },
+            ChiselCommand::Merge => {
+                if args.len() != 1 {
+                    // Must supply a session ID as the argument.
+                    return DispatchResult::CommandFailed(Self::make_error(
+                        "Must supply a session ID as the argument.",
+                    ))
+                }
+
+                let other = match self.session.load(args[0]) {
+                    Ok(other) => other,
+                    Err(e) => return DispatchResult::CommandFailed(Self::make_error(e)),
+                };
+
+                // Keep a copy of the current source in case the merged source fails to build.
+                let backup = self.session.session_source.clone();
+                let report = match self.session.merge(&other) {
+                    Ok(report) => report,
+                    Err(e) => return DispatchResult::CommandFailed(Self::make_error(e)),
+                };
+                if !report.is_merged() {
+                    return DispatchResult::CommandFailed(Self::make_error(format!(
+                        "Failed to merge session {}!\n{}",
+                        args[0],
+                        report.conflicts.join("\n")
+                    )))
+                }
+
+                // The merged pragmas may call for another solc version than the current one
+                let source = self.session.session_source.as_mut().unwrap();
+                let switched = match source.select_solc_version() {
+                    Ok(switched) => switched,
+                    Err(e) => {
+                        self.session.session_source = backup;
+                        return DispatchResult::CommandFailed(Self::make_error(format!(
+                            "Failed to merge session {}! {e}",
+                            args[0]
+                        )))
+                    }
+                };
+                if let Err(e) = source.build() {
+                    self.session.session_source = backup;
+                    return DispatchResult::CommandFailed(Self::make_error(format!(
+                        "Failed to build merged session! {e}"
+                    )))
+                }
+                self.autosave();
+
+                let mut msg = format!("Merged session {}!", args[0]);
+                if let Some(version) = switched {
+                    msg.push_str(&format!(" (Switched to solc {version})"));
+                }
+                if !report.deduplicated.is_empty() {
+                    msg.push_str(&format!(
+                        " (Skipped duplicates: {})",
+                        report.deduplicated.join(" ")
+                    ));
+                }
+                DispatchResult::CommandSuccess(Some(msg))
             }
-            ChiselCommand::ListSessions => match ChiselSession::list_sessions() {
+            ChiselCommand::ListSessions => match self.session.list_sessions() {
                 Ok(sessions) => DispatchResult::CommandSuccess(Some(format!(
                     "{}\n{}",
                     Paint::cyan(format!("{CHISEL_CHAR} Chisel Sessions")),
                     sessions
                         .iter()
-                        .map(|(time, name)| {
-                            format!("{} - {}", Paint::blue(format!("{time:?}")), name)
+                        .map(|info| {
+                            let mut line = format!(
+                                "{} - {}",
+                                Paint::blue(format!("{:?}", info.formatted_modified())),
+                                info.file_name()
+                            );
+                            if let Some(error) = &info.error {
+                                line.push_str(&format!(" {}", Paint::red(format!("[{error}]"))));
+                                return line
+                            }
+                            if let Some(description) = &info.description {
+                                line.push_str(&format!(" - {description}"));
+                            }
+                            if !info.tags.is_empty() {
+                                line.push_str(&format!(
+                                    " {}",
+                                    Paint::yellow(format!("[{}]", info.tags.join(", ")))
+                                ));
+                            }
+                            line
                         })
                         .collect::<Vec<String>>()
                         .join("\n")
@@ -274,211 +478,961 @@ impl ChiselDispatcher {
                     "No sessions found. Use the `!save` command to save a session.",
                 )),
             },
-            ChiselCommand::Source => {
-                if let Some(session_source) = self.session.session_source.as_ref() {
-                    match format_source(
-                        &session_source.to_repl_source(),
-                        session_source.config.foundry_config.fmt.clone(),
-                    ) {
-                        Ok(formatted_source) => DispatchResult::CommandSuccess(Some(
-                            SolidityHelper::highlight(&formatted_source),
-                        )),
-                        Err(_) => DispatchResult::CommandFailed(String::from(
-                            "Failed to format session source",
-                        )),
-                    }
-                } else {
-                    DispatchResult::CommandFailed(Self::make_error("Session not present."))
-                }
-            }
-            ChiselCommand::ClearCache => match ChiselSession::clear_cache() {
-                Ok(_) => {
-                    self.session.id = None;
-                    DispatchResult::CommandSuccess(Some(String::from("Cleared chisel cache!")))
+            ChiselCommand::Source => match self.session.session_source {
+                Some(_) => DispatchResult::CommandSuccess(Some(self.session.numbered_source())),
+                None => DispatchResult::CommandFailed(Self::make_error("Session not present.")),
+            },
+            ChiselCommand::Snippets => match &self.session.session_source {
+                Some(source) if source.snippets.is_empty() => {
+                    DispatchResult::CommandSuccess(Some(String::from("No snippets entered yet.")))
                 }
-                Err(_) => DispatchResult::CommandFailed(Self::make_error("Failed to clear cache!")),
+                Some(_) => DispatchResult::CommandSuccess(Some(self.session.snippet_list())),
+                None => DispatchResult::CommandFailed(Self::make_error("Session not present.")),
             },
-            ChiselCommand::Fork => {
-                if let Some(session_source) = self.session.session_source.as_mut() {
-                    if args.is_empty() {
-                        session_source.config.evm_opts.fork_url = None;
-                        return DispatchResult::CommandSuccess(Some(String::from(
-                            "Now using local environment.",
-                        )))
-                    } else if args.len() != 1 || args[0].trim().is_empty() {
+            ChiselCommand::TopLevel => self.apply(cmd, args).await,
+            ChiselCommand::Abi if args.is_empty() => {
+                let abi = match self.session.compile() {
+                    Ok(result) => result.abi.unwrap_or_default(),
+                    Err(e) => return DispatchResult::CommandFailed(Self::make_error(e)),
+                };
+                match serde_json::to_string_pretty(&abi) {
+                    Ok(json) => DispatchResult::CommandSuccess(Some(json)),
+                    Err(e) => DispatchResult::CommandFailed(Self::make_error(e)),
+                }
+            }
+            ChiselCommand::Bytecode => {
+                let result = match self.session.compile() {
+                    Ok(result) => result,
+                    Err(e) => return DispatchResult::CommandFailed(Self::make_error(e)),
+                };
+                let (name, part) = match args {
+                    [] => (None, None),
+                    [part @ ("creation" | "deployed")] => (None, Some(*part)),
+                    [name] => (Some(*name), None),
+                    [name, part @ ("creation" | "deployed")] => (Some(*name), Some(*part)),
+                    _ => {
                         return DispatchResult::CommandFailed(Self::make_error(
-                            "Must supply a session ID as the argument.",
+                            "Must supply either `creation`, `deployed`, or 0 arguments, optionally preceded by the name of a contract of the session.",
                         ))
                     }
+                };
 
-                    // If the argument is an RPC alias designated in the
-                    // `[rpc_endpoints]` section of the `foundry.toml` within
-                    // the pwd, use the URL matched to the key.
-                    let endpoint = if let Some(endpoint) =
-                        session_source.config.foundry_config.rpc_endpoints.get(args[0])
-                    {
-                        endpoint.clone()
-                    } else {
-                        RpcEndpoint::Env(args[0].to_string())
-                    };
-                    let fork_url = match endpoint.resolve() {
-                        Ok(fork_url) => fork_url,
-                        Err(e) => {
-                            return DispatchResult::CommandFailed(Self::make_error(format!(
-                                "\"{}\" ENV Variable not set!",
-                                e.var
-                            )))
+                // The bytecode of the REPL contract, or of another contract of the session
+                let (bytecode, deployed_bytecode, libraries) = match name {
+                    None => (result.bytecode, result.deployed_bytecode, result.libraries),
+                    Some(name) => {
+                        match result.contracts.into_iter().find(|contract| contract.name == name) {
+                            Some(contract) => {
+                                (contract.bytecode, contract.deployed_bytecode, Vec::new())
+                            }
+                            None => {
+                                return DispatchResult::CommandFailed(Self::make_error(format!(
+                                    "No contract named `{name}` is defined within the session!"
+                                )))
+                            }
                         }
+                    }
+                };
+                let encode = |bytecode: Option<ethers::types::Bytes>| {
+                    bytecode.map(|bytecode| format!("0x{}", hex::encode(bytecode)))
+                };
+                let (creation, deployed) = match (encode(bytecode), encode(deployed_bytecode)) {
+                    (Some(creation), Some(deployed)) => (creation, deployed),
+                    _ => {
+                        return DispatchResult::CommandFailed(Self::make_error(match name {
+                            Some(name) => format!(
+                                "`{name}` has no bytecode, as it is abstract, an interface, or must be linked against a library!"
+                            ),
+                            None => String::from("The REPL contract could not be linked!"),
+                        }))
+                    }
+                };
+                match part {
+                    None if libraries.is_empty() => DispatchResult::CommandSuccess(Some(format!(
+                        "Creation bytecode:\n{creation}\nDeployed bytecode:\n{deployed}"
+                    ))),
+                    None => {
+                        let libraries = libraries
+                            .iter()
+                            .map(|library| format!("\n  {library}"))
+                            .collect::<String>();
+                        DispatchResult::CommandSuccess(Some(format!(
+                            "Creation bytecode:\n{creation}\nDeployed bytecode:\n{deployed}\nLinked libraries:{libraries}"
+                        )))
+                    }
+                    Some("creation") => DispatchResult::CommandSuccess(Some(creation)),
+                    Some(_) => DispatchResult::CommandSuccess(Some(deployed)),
+                }
+            }
+            ChiselCommand::Disasm => {
+                let (name, pc) = match args {
+                    [] => (None, None),
+                    ["--pc", pc] => (None, Some(*pc)),
+                    [name] => (Some(*name), None),
+                    [name, "--pc", pc] => (Some(*name), Some(*pc)),
+                    _ => {
+                        return DispatchResult::CommandFailed(Self::make_error(
+                            "Must supply the name of a contract of the session, `--pc <n>`, both, or 0 arguments.",
+                        ))
+                    }
+                };
+                let pc =
+                    match pc.map(|pc| match pc.strip_prefix("0x") {
+                        Some(hex) => usize::from_str_radix(hex, 16),
+                        None => pc.parse::<usize>(),
+                    }) {
+                        Some(Ok(pc)) => Some(pc),
+                        Some(Err(_)) => return DispatchResult::CommandFailed(Self::make_error(
+                            "The program counter must be a decimal or `0x` prefixed hex number.",
+                        )),
+                        None => None,
                     };
+                let result = match self.session.compile() {
+                    Ok(result) => result,
+                    Err(e) => return DispatchResult::CommandFailed(Self::make_error(e)),
+                };
 
-                    // Check validity of URL
-                    if Url::parse(&fork_url).is_err() {
-                        return DispatchResult::CommandFailed(Self::make_error("Invalid fork URL!"))
+                // The deployed bytecode of the REPL contract, or of another contract of the session
+                let deployed_bytecode = match name {
+                    None => result.deployed_bytecode,
+                    Some(name) => {
+                        match result.contracts.into_iter().find(|contract| contract.name == name) {
+                            Some(contract) => contract.deployed_bytecode,
+                            None => {
+                                return DispatchResult::CommandFailed(Self::make_error(format!(
+                                    "No contract named `{name}` is defined within the session!"
+                                )))
+                            }
+                        }
                     }
+                };
+                match deployed_bytecode {
+                    Some(bytecode) if bytecode.is_empty() => DispatchResult::CommandFailed(
+                        Self::make_error("The contract has no deployed bytecode!"),
+                    ),
+                    Some(bytecode) => match disassemble(&bytecode, pc) {
+                        Ok(listing) => DispatchResult::CommandSuccess(Some(listing)),
+                        Err(e) => DispatchResult::CommandFailed(Self::make_error(e)),
+                    },
+                    None => DispatchResult::CommandFailed(Self::make_error(match name {
+                        Some(name) => format!(
+                            "`{name}` has no bytecode, as it is abstract, an interface, or must be linked against a library!"
+                        ),
+                        None => String::from("The REPL contract could not be linked!"),
+                    })),
+                }
+            }
+            ChiselCommand::Gas if matches!(args, ["used"]) => {
+                let source = match self.session.session_source.as_mut() {
+                    Some(source) => source,
+                    None => {
+                        return DispatchResult::CommandFailed(Self::make_error(
+                            "Session source not present!",
+                        ))
+                    }
+                };
+                match source.current_state().await.map(|state| state.gas_per_snippet.clone()) {
+                    Ok(gas) if gas.is_empty() => DispatchResult::CommandSuccess(Some(
+                        String::from("No statements have been executed"),
+                    )),
+                    Ok(gas) => DispatchResult::CommandSuccess(Some(Self::format_gas_used(
+                        source,
+                        &gas.into_iter().collect::<Vec<_>>(),
+                    ))),
+                    Err(e) => DispatchResult::CommandFailed(Self::make_error(e)),
+                }
+            }
+            ChiselCommand::Gas => match self.session.compile() {
+                Ok(result) if result.gas.contracts.is_empty() => DispatchResult::CommandSuccess(
+                    Some(String::from("No gas estimates are available")),
+                ),
+                Ok(result) => DispatchResult::CommandSuccess(Some(result.gas.to_string())),
+                Err(e) => DispatchResult::CommandFailed(Self::make_error(e)),
+            },
+            ChiselCommand::Format if args.is_empty() => match &self.session.session_source {
+                Some(source) => DispatchResult::CommandSuccess(Some(format!(
+                    "The number format is {}",
+                    source.number_format
+                ))),
+                None => {
+                    DispatchResult::CommandFailed(Self::make_error("Session source not present!"))
+                }
+            },
+            ChiselCommand::Format => self.apply(cmd, args).await,
+            ChiselCommand::Debug => {
+                let source = match &self.session.session_source {
+                    Some(source) => source,
+                    None => {
+                        return DispatchResult::CommandFailed(Self::make_error(
+                            "Session source not present!",
+                        ))
+                    }
+                };
+                let statement =
+                    if args.is_empty() { self.reverted.clone() } else { Some(args.join(" ")) };
+                let mut debugger = match source.debug(statement.as_deref()).await {
+                    Ok(debugger) => debugger,
+                    Err(e) => return DispatchResult::CommandFailed(Self::make_error(e)),
+                };
 
-                    // Create success message before moving the fork_url
-                    let success_msg = format!("Set fork URL to {}", Paint::yellow(&fork_url));
-
-                    // Update the fork_url inside of the [SessionSourceConfig]'s [EvmOpts]
-                    // field
-                    session_source.config.evm_opts.fork_url = Some(fork_url);
-
-                    // Clear the backend so that it is re-instantiated with the new fork
-                    // upon the next execution of the session source.
-                    session_source.config.backend = None;
-
-                    DispatchResult::CommandSuccess(Some(success_msg))
-                } else {
-                    DispatchResult::CommandFailed(Self::make_error("Session not present."))
+                // A line-mode loop, reading the commands of the debugger from stdin until it is
+                // quit or stdin is closed
+                println!(
+                    "Enter `help` to list the commands of the debugger\n{}",
+                    debugger.location()
+                );
+                while let Some(line) = Self::prompt(&Paint::yellow("debug>").to_string()).await {
+                    match debugger.command(&line) {
+                        Some(output) => println!("{output}"),
+                        None => break,
+                    }
                 }
+                DispatchResult::CommandSuccess(Some(String::from("Exited the debugger")))
             }
-            ChiselCommand::Traces => {
-                if let Some(session_source) = self.session.session_source.as_mut() {
-                    session_source.config.traces = !session_source.config.traces;
-                    DispatchResult::CommandSuccess(Some(format!(
-                        "{} traces!",
-                        if session_source.config.traces { "Enabled" } else { "Disabled" }
-                    )))
+            ChiselCommand::Profile => {
+                let json = match args.as_slice() {
+                    [] => false,
+                    ["--json"] => true,
+                    _ => {
+                        return DispatchResult::CommandFailed(Self::make_error(
+                            "Invalid arguments. Usage: `!profile [--json]`",
+                        ))
+                    }
+                };
+                let source = match &self.session.session_source {
+                    Some(source) => source,
+                    None => {
+                        return DispatchResult::CommandFailed(Self::make_error(
+                            "Session source not present!",
+                        ))
+                    }
+                };
+                let profile = match source.profile().await {
+                    Ok(res) => res.profile.unwrap_or_default(),
+                    Err(e) => return DispatchResult::CommandFailed(Self::make_error(e)),
+                };
+                if json {
+                    match serde_json::to_string_pretty(&profile) {
+                        Ok(json) => DispatchResult::CommandSuccess(Some(json)),
+                        Err(e) => DispatchResult::CommandFailed(Self::make_error(e)),
+                    }
                 } else {
-                    DispatchResult::CommandFailed(Self::make_error("Session not present."))
+                    DispatchResult::CommandSuccess(Some(profile.table()))
                 }
             }
-            ChiselCommand::MemDump | ChiselCommand::StackDump => {
-                if let Some(session_source) = self.session.session_source.as_mut() {
-                    match session_source.execute().await {
-                        Ok((_, res)) => {
-                            if let Some((stack, mem, _)) = res.state.as_ref() {
-                                if matches!(cmd, ChiselCommand::MemDump) {
-                                    // Print memory by word
-                                    (0..mem.len()).step_by(32).for_each(|i| {
-                                        println!(
-                                            "{}: {}",
-                                            Paint::yellow(format!(
-                                                "[0x{:02x}:0x{:02x}]",
-                                                i,
-                                                i + 32
-                                            )),
-                                            Paint::cyan(format!(
-                                                "0x{}",
-                                                hex::encode(&mem.data()[i..i + 32])
-                                            ))
-                                        );
-                                    });
-                                } else {
-                                    // Print all stack items
-                                    (0..stack.len()).rev().for_each(|i| {
-                                        println!(
-                                            "{}: {}",
-                                            Paint::yellow(format!("[{}]", stack.len() - i - 1)),
-                                            Paint::cyan(format!("0x{:02x}", stack.data()[i]))
-                                        );
-                                    });
-                                }
-                                DispatchResult::CommandSuccess(None)
-                            } else {
-                                DispatchResult::CommandFailed(Self::make_error(
-                                    "Run function is empty.",
-                                ))
-                            }
+            ChiselCommand::StateDiff => {
+                let (mut all, mut json) = (false, false);
+                for arg in &args {
+                    match *arg {
+                        "--all" => all = true,
+                        "--json" => json = true,
+                        _ => {
+                            return DispatchResult::CommandFailed(Self::make_error(
+                                "Invalid arguments. Usage: `!statediff [--all] [--json]`",
+                            ))
                         }
-                        Err(e) => DispatchResult::CommandFailed(Self::make_error(e.to_string())),
+                    }
+                }
+                let source = match &self.session.session_source {
+                    Some(source) => source,
+                    None => {
+                        return DispatchResult::CommandFailed(Self::make_error(
+                            "Session source not present!",
+                        ))
+                    }
+                };
+                let diff = match source.state_diff().await {
+                    Ok(res) => res.state_diff.unwrap_or_default(),
+                    Err(e) => return DispatchResult::CommandFailed(Self::make_error(e)),
+                };
+                if json {
+                    match serde_json::to_string_pretty(&diff) {
+                        Ok(json) => DispatchResult::CommandSuccess(Some(json)),
+                        Err(e) => DispatchResult::CommandFailed(Self::make_error(e)),
                     }
                 } else {
-                    DispatchResult::CommandFailed(Self::make_error("Session not present."))
+                    DispatchResult::CommandSuccess(Some(diff.table(all)))
                 }
             }
-            ChiselCommand::Export => {
-                // Check if the current session inherits `Script.sol` before exporting
-                if let Some(session_source) = self.session.session_source.as_ref() {
-                    // Check if the pwd is a foundry project
-                    if PathBuf::from("foundry.toml").exists() {
-                        // Create "script" dir if it does not already exist.
-                        if !PathBuf::from("script").exists() {
-                            if let Err(e) = std::fs::create_dir_all("script") {
-                                return DispatchResult::CommandFailed(Self::make_error(
-                                    e.to_string(),
-                                ))
-                            }
-                        }
-
-                        match format_source(
-                            &session_source.to_script_source(),
-                            session_source.config.foundry_config.fmt.clone(),
-                        ) {
-                            Ok(formatted_source) => {
-                                // Write session source to `script/REPL.s.sol`
-                                if let Err(e) = std::fs::write(
-                                    PathBuf::from("script/REPL.s.sol"),
-                                    formatted_source,
-                                ) {
-                                    return DispatchResult::CommandFailed(Self::make_error(
-                                        e.to_string(),
-                                    ))
-                                }
-
-                                DispatchResult::CommandSuccess(Some(String::from(
-                                    "Exported session source to script/REPL.s.sol!",
-                                )))
-                            }
-                            Err(_) => DispatchResult::CommandFailed(String::from(
-                                "Failed to format session source",
-                            )),
-                        }
-                    } else {
-                        DispatchResult::CommandFailed(Self::make_error(
-                            "Must be in a foundry project to export source to script.",
+            ChiselCommand::Estimate => {
+                if args.is_empty() {
+                    return DispatchResult::CommandFailed(Self::make_error(
+                        "Must supply a statement to estimate as the argument.",
+                    ))
+                }
+                let source = match &self.session.session_source {
+                    Some(source) => source,
+                    None => {
+                        return DispatchResult::CommandFailed(Self::make_error(
+                            "Session source not present!",
                         ))
                     }
-                } else {
-                    DispatchResult::CommandFailed(Self::make_error("Session not present."))
+                };
+                match source.estimate(&args.join(" ")).await {
+                    Ok((estimated, res)) if !res.success => {
+                        let abi = estimated.artifact().and_then(|artifact| artifact.abi);
+                        DispatchResult::CommandSuccess(Some(format!(
+                            "The statement would revert: {}\nGas used by the call: {}",
+                            res.revert_reason(abi.as_ref()).unwrap_or_default(),
+                            res.gas_used
+                        )))
+                    }
+                    Ok((_, res)) => {
+                        // The gas of the snippets that the statement was appended as
+                        let gas = res
+                            .gas_per_snippet
+                            .iter()
+                            .filter(|(index, _)| *index >= source.snippet_count())
+                            .map(|(_, gas)| gas)
+                            .sum::<u64>();
+                        DispatchResult::CommandSuccess(Some(format!(
+                            "The statement would succeed\nGas: {gas}\nGas used by the call: {}",
+                            res.gas_used
+                        )))
+                    }
+                    Err(e) => DispatchResult::CommandFailed(Self::make_error(e)),
                 }
             }
-            ChiselCommand::Fetch => {
-                if args.len() != 2 {
+            ChiselCommand::Inspect => {
+                if args.is_empty() {
                     return DispatchResult::CommandFailed(Self::make_error(
-                        "Incorrect number of arguments supplied. Expected: <address> <name>",
+                        "Must supply the name of a state variable as the argument.",
                     ))
                 }
 
-                let request_url = format!(
-                    "https://api.etherscan.io/api?module=contract&action=getabi&address={}{}",
-                    args[0],
-                    if let Some(api_key) = self
+                // String keys may contain spaces
+                match self.session.inspect(&args.join(" ")).await {
+                    Ok(value) => {
+                        let formatter = self
+                            .session
+                            .session_source
+                            .as_ref()
+                            .map(SessionSource::value_formatter)
+                            .unwrap_or_default();
+                        DispatchResult::CommandSuccess(Some(value.format(&formatter)))
+                    }
+                    Err(e) => DispatchResult::CommandFailed(Self::make_error(e)),
+                }
+            }
+            ChiselCommand::Storage => match self.session.storage_layout() {
+                Ok(layout) if layout.storage.is_empty() => DispatchResult::CommandSuccess(Some(
+                    String::from("The current session does not define any state variables"),
+                )),
+                Ok(layout) => {
+                    let mut out = layout_table(&layout);
+                    let moved = self
                         .session
                         .session_source
                         .as_ref()
-                        .unwrap()
-                        .config
-                        .foundry_config
-                        .etherscan_api_key
-                        .as_ref()
-                    {
-                        format!("&apikey={api_key}")
-                    } else {
-                        String::default()
+                        .map(|source| source.moved_variables.clone())
+                        .unwrap_or_default();
+                    if !moved.is_empty() {
+                        let moved = moved
+                            .iter()
+                            .map(|variable| format!("  {variable}"))
+                            .collect::<Vec<_>>();
+                        out.push_str(&format!(
+                            "\n{}\n{}",
+                            Paint::yellow("Warning: The storage layout changed since the previous compile. The raw state of the session no longer lines up with these variables:"),
+                            moved.join("\n")
+                        ));
                     }
-                );
-
+                    DispatchResult::CommandSuccess(Some(out))
+                }
+                Err(e) => DispatchResult::CommandFailed(Self::make_error(e)),
+            },
+            ChiselCommand::Events => {
+                let source = match self.session.session_source.as_mut() {
+                    Some(source) => source,
+                    None => {
+                        return DispatchResult::CommandFailed(Self::make_error(
+                            "Session source not present!",
+                        ))
+                    }
+                };
+                let res = match source.current_state().await {
+                    Ok(_) => source.build().map(|_| source.decode_logs(&source.logs)),
+                    Err(e) => Err(e),
+                };
+                match res {
+                    Ok(decoded_logs) => {
+                        let formatter = source.value_formatter();
+                        let events = decoded_logs
+                            .iter()
+                            .filter(|log| !log.is_console_log())
+                            .map(|log| format!("  {}", log.format(&formatter)))
+                            .collect::<Vec<_>>();
+                        if events.is_empty() {
+                            DispatchResult::CommandSuccess(Some(String::from(
+                                "No events have been emitted",
+                            )))
+                        } else {
+                            DispatchResult::CommandSuccess(Some(format!(
+                                "Events:\n{}",
+                                events.join("\n")
+                            )))
+                        }
+                    }
+                    Err(e) => DispatchResult::CommandFailed(Self::make_error(e)),
+                }
+            }
+            ChiselCommand::Stats => {
+                match self.session.session_source.as_ref().map(|source| &source.compile_stats) {
+                    Some(Some(stats)) => DispatchResult::CommandSuccess(Some(stats.to_string())),
+                    Some(None) => DispatchResult::CommandSuccess(Some(String::from(
+                        "The session source has not been compiled yet",
+                    ))),
+                    None => DispatchResult::CommandFailed(Self::make_error(
+                        "Session source not present!",
+                    )),
+                }
+            }
+            ChiselCommand::Hashes => match self.session.compile() {
+                Ok(result) => {
+                    let hashes = result.hashes();
+                    if hashes == AbiHashes::default() {
+                        DispatchResult::CommandSuccess(Some(String::from(
+                            "The REPL contract has no functions, errors, or events",
+                        )))
+                    } else {
+                        DispatchResult::CommandSuccess(Some(hashes.to_string()))
+                    }
+                }
+                Err(e) => DispatchResult::CommandFailed(Self::make_error(e)),
+            },
+            ChiselCommand::Abi => {
+                if args.len() < 2 {
+                    return DispatchResult::CommandFailed(Self::make_error(
+                        "Incorrect number of arguments supplied. Expected: <name> <path-or-json>",
+                    ))
+                }
+
+                // Inline JSON may contain spaces; Anything else is treated as a path to a file
+                let input = args[1..].join(" ");
+                let abi_json = if input.trim_start().starts_with(|c| c == '[' || c == '{') {
+                    input
+                } else {
+                    match std::fs::read_to_string(&input) {
+                        Ok(contents) => contents,
+                        Err(e) => return DispatchResult::FileIoError(e.into()),
+                    }
+                };
+                self.apply(cmd, &[args[0], abi_json.as_str()]).await
+            }
+            ChiselCommand::ContractName | ChiselCommand::License | ChiselCommand::Pragma => {
+                self.apply(cmd, args).await
+            }
+            ChiselCommand::Optimizer if args.is_empty() => {
+                match self.session.session_source.as_ref().map(|source| &source.compiler_settings) {
+                    Some(settings) if settings.optimizer => DispatchResult::CommandSuccess(Some(
+                        format!("The optimizer is enabled with {} runs", settings.runs),
+                    )),
+                    Some(_) => DispatchResult::CommandSuccess(Some(String::from(
+                        "The optimizer is disabled",
+                    ))),
+                    None => DispatchResult::CommandFailed(Self::make_error(
+                        "Session source not present!",
+                    )),
+                }
+            }
+            ChiselCommand::ViaIr if args.is_empty() => match self.session.session_source.as_ref() {
+                Some(source) if source.compiler_settings.via_ir => DispatchResult::CommandSuccess(
+                    Some(String::from("The session source is compiled via IR")),
+                ),
+                Some(_) => DispatchResult::CommandSuccess(Some(String::from(
+                    "The session source is compiled with the legacy pipeline",
+                ))),
+                None => {
+                    DispatchResult::CommandFailed(Self::make_error("Session source not present!"))
+                }
+            },
+            ChiselCommand::Remap if args.is_empty() => {
+                let source = match self.session.session_source.as_ref() {
+                    Some(source) => source,
+                    None => {
+                        return DispatchResult::CommandFailed(Self::make_error(
+                            "Session source not present!",
+                        ))
+                    }
+                };
+                let remappings = source.all_remappings();
+                if remappings.is_empty() && source.source_directories.is_empty() {
+                    return DispatchResult::CommandSuccess(Some(String::from(
+                        "No remappings are active",
+                    )))
+                }
+                let mut listing = String::from("Remappings:");
+                for remapping in remappings {
+                    listing.push_str(&format!("\n  {remapping}"));
+                }
+                if !source.source_directories.is_empty() {
+                    listing.push_str("\nSource directories:");
+                    for dir in &source.source_directories {
+                        listing.push_str(&format!("\n  {}", dir.display()));
+                    }
+                }
+                DispatchResult::CommandSuccess(Some(listing))
+            }
+            ChiselCommand::Solc if args == ["list"] => match self.session.solc_versions() {
+                Ok(versions) => DispatchResult::CommandSuccess(Some(versions.to_string())),
+                Err(e) => DispatchResult::CommandFailed(Self::make_error(e)),
+            },
+            ChiselCommand::Solc if args.is_empty() => {
+                match self.session.session_source.as_ref().map(|s| s.solc.version()) {
+                    Some(Ok(version)) => DispatchResult::CommandSuccess(Some(format!(
+                        "The session is compiled with solc {version}"
+                    ))),
+                    Some(Err(e)) => DispatchResult::CommandFailed(Self::make_error(e)),
+                    None => DispatchResult::CommandFailed(Self::make_error(
+                        "Session source not present!",
+                    )),
+                }
+            }
+            ChiselCommand::EvmVersion if args.is_empty() => {
+                match self.session.session_source.as_ref() {
+                    Some(source) => DispatchResult::CommandSuccess(Some(format!(
+                        "The session uses the {} EVM version",
+                        source.config.foundry_config.evm_version
+                    ))),
+                    None => DispatchResult::CommandFailed(Self::make_error(
+                        "Session source not present!",
+                    )),
+                }
+            }
+            ChiselCommand::Optimizer |
+            ChiselCommand::ViaIr |
+            ChiselCommand::Remap |
+            ChiselCommand::Solc |
+            ChiselCommand::EvmVersion => self.apply(cmd, args).await,
+            ChiselCommand::ClearCache => match self.session.clear_cache() {
+                Ok(_) => {
+                    self.session.id = None;
+                    DispatchResult::CommandSuccess(Some(String::from("Cleared chisel cache!")))
+                }
+                Err(_) => DispatchResult::CommandFailed(Self::make_error("Failed to clear cache!")),
+            },
+            ChiselCommand::Rpc => {
+                let mut aliases = match RpcAliases::load() {
+                    Ok(aliases) => aliases,
+                    Err(e) => return DispatchResult::CommandFailed(Self::make_error(e)),
+                };
+                match args.as_slice() {
+                    [] | ["list"] => {
+                        let project = self
+                            .session
+                            .session_source
+                            .as_ref()
+                            .map(|source| source.config.foundry_config.rpc_endpoints.clone())
+                            .unwrap_or_default();
+                        if aliases.endpoints.is_empty() && project.is_empty() {
+                            return DispatchResult::CommandSuccess(Some(String::from(
+                                "No RPC aliases are defined. Add one with `!rpc add <alias> <url>`",
+                            )))
+                        }
+                        // Endpoints are printed with their API keys redacted
+                        let redact =
+                            |endpoint: String| redact_api_key(&endpoint).unwrap_or(endpoint);
+                        let mut lines = Vec::new();
+                        for (alias, endpoint) in aliases.endpoints.iter() {
+                            let shadowed = if project.contains_key(alias) {
+                                " (shadowed by `[rpc_endpoints]`)"
+                            } else {
+                                ""
+                            };
+                            let endpoint = redact(endpoint.to_string());
+                            lines.push(format!("{}: {endpoint}{shadowed}", Paint::cyan(alias)));
+                        }
+                        for (alias, endpoint) in project.iter() {
+                            lines.push(format!(
+                                "{}: {} (`[rpc_endpoints]`)",
+                                Paint::cyan(alias),
+                                redact(endpoint.to_string())
+                            ));
+                        }
+                        DispatchResult::CommandSuccess(Some(lines.join("\n")))
+                    }
+                    ["add", alias, url] => match aliases.add(alias, url) {
+                        Ok(previous) => match aliases.save() {
+                            Ok(_) => DispatchResult::CommandSuccess(Some(match previous {
+                                Some(previous) => {
+                                    let previous = previous.to_string();
+                                    let previous = redact_api_key(&previous).unwrap_or(previous);
+                                    format!("Replaced RPC alias `{alias}` (was {previous})")
+                                }
+                                None => format!("Added RPC alias `{alias}`"),
+                            })),
+                            Err(e) => DispatchResult::FileIoError(e.into()),
+                        },
+                        Err(e) => DispatchResult::CommandFailed(Self::make_error(e)),
+                    },
+                    ["remove" | "rm", alias] => match aliases.remove(alias) {
+                        Some(_) => match aliases.save() {
+                            Ok(_) => DispatchResult::CommandSuccess(Some(format!(
+                                "Removed RPC alias `{alias}`"
+                            ))),
+                            Err(e) => DispatchResult::FileIoError(e.into()),
+                        },
+                        None => DispatchResult::CommandFailed(Self::make_error(format!(
+                            "No RPC alias named `{alias}`"
+                        ))),
+                    },
+                    _ => DispatchResult::CommandFailed(Self::make_error(
+                        "Invalid arguments. Usage: `!rpc [list]`, `!rpc add <alias> <url>`, or `!rpc remove <alias>`",
+                    )),
+                }
+            }
+            ChiselCommand::Fork => self.apply(cmd, args).await,
+            ChiselCommand::Traces => {
+                if let Some(session_source) = self.session.session_source.as_mut() {
+                    session_source.config.traces = !session_source.config.traces;
+                    DispatchResult::CommandSuccess(Some(format!(
+                        "{} traces!",
+                        if session_source.config.traces { "Enabled" } else { "Disabled" }
+                    )))
+                } else {
+                    DispatchResult::CommandFailed(Self::make_error("Session not present."))
+                }
+            }
+            ChiselCommand::Block |
+            ChiselCommand::Timestamp |
+            ChiselCommand::BaseFee |
+            ChiselCommand::ChainId |
+            ChiselCommand::Sender |
+            ChiselCommand::Value => self.apply(cmd, args).await,
+            ChiselCommand::Env => {
+                let source = match self.session.session_source.as_ref() {
+                    Some(source) => source,
+                    None => {
+                        return DispatchResult::CommandFailed(Self::make_error(
+                            "Session source not present!",
+                        ))
+                    }
+                };
+                let env = match source.execution_env().await {
+                    Ok(env) => env,
+                    Err(e) => return DispatchResult::CommandFailed(Self::make_error(e)),
+                };
+
+                let overrides = &source.env_overrides;
+                let rows = [
+                    (
+                        "block.number",
+                        env.block.number.to_string(),
+                        overrides.block_number.is_some(),
+                    ),
+                    (
+                        "block.timestamp",
+                        env.block.timestamp.to_string(),
+                        overrides.timestamp.is_some(),
+                    ),
+                    ("block.basefee", env.block.basefee.to_string(), overrides.basefee.is_some()),
+                    ("block.chainid", env.cfg.chain_id.to_string(), overrides.chain_id.is_some()),
+                    ("block.coinbase", to_checksum(&env.block.coinbase, None), false),
+                    ("block.gaslimit", env.block.gas_limit.to_string(), false),
+                    ("msg.sender", to_checksum(&env.tx.caller, None), overrides.sender.is_some()),
+                    ("msg.value", env.tx.value.to_string(), overrides.value.is_some()),
+                    (
+                        "fork",
+                        source
+                            .config
+                            .fork
+                            .as_ref()
+                            .map(|fork| match fork.block {
+                                Some(block) => format!("{} at block {block}", fork.endpoint),
+                                None => fork.endpoint.clone(),
+                            })
+                            .unwrap_or_else(|| String::from("none")),
+                        false,
+                    ),
+                ];
+                let rows = rows
+                    .into_iter()
+                    .map(|(name, value, overridden)| {
+                        let note = if overridden { " (overridden)" } else { "" };
+                        format!("  {:<16} {}{note}", name, Paint::cyan(value))
+                    })
+                    .collect::<Vec<_>>();
+                DispatchResult::CommandSuccess(Some(format!(
+                    "Execution environment:\n{}",
+                    rows.join("\n")
+                )))
+            }
+            ChiselCommand::GasLimit if args.is_empty() => match &self.session.session_source {
+                Some(source) => DispatchResult::CommandSuccess(Some(format!(
+                    "The gas limit is {}",
+                    source.limits.gas_limit
+                ))),
+                None => {
+                    DispatchResult::CommandFailed(Self::make_error("Session source not present!"))
+                }
+            },
+            ChiselCommand::Timeout if args.is_empty() => match &self.session.session_source {
+                Some(source) => DispatchResult::CommandSuccess(Some(format!(
+                    "The time limit is {}s",
+                    source.limits.timeout.as_secs_f64()
+                ))),
+                None => {
+                    DispatchResult::CommandFailed(Self::make_error("Session source not present!"))
+                }
+            },
+            ChiselCommand::GasLimit | ChiselCommand::Timeout | ChiselCommand::Deal => {
+                self.apply(cmd, args).await
+            }
+            ChiselCommand::Balance => {
+                let account = match args {
+                    [account] => match account.parse::<Address>() {
+                        Ok(account) => account,
+                        Err(_) => {
+                            return DispatchResult::CommandFailed(Self::make_error(format!(
+                                "Invalid address {account}!"
+                            )))
+                        }
+                    },
+                    _ => {
+                        return DispatchResult::CommandFailed(Self::make_error(
+                            "Must supply an address as the argument.",
+                        ))
+                    }
+                };
+                let source = match self.session.session_source.as_mut() {
+                    Some(source) => source,
+                    None => {
+                        return DispatchResult::CommandFailed(Self::make_error(
+                            "Session source not present!",
+                        ))
+                    }
+                };
+                match source.balance_of(account).await {
+                    Ok(balance) => {
+                        DispatchResult::CommandSuccess(Some(Self::format_balance(balance)))
+                    }
+                    Err(e) => DispatchResult::CommandFailed(Self::make_error(e)),
+                }
+            }
+            ChiselCommand::Broadcast => self.broadcast(args).await,
+            ChiselCommand::Rerun => {
+                let source = match self.session.session_source.as_mut() {
+                    Some(source) => source,
+                    None => {
+                        return DispatchResult::CommandFailed(Self::make_error(
+                            "Session source not present!",
+                        ))
+                    }
+                };
+
+                // Drop the persisted state and replay every statement against a fresh backend,
+                // so that forked state is fetched again as well
+                source.persisted = None;
+                source.config.backend = None;
+                match source.execute().await {
+                    Ok((_, mut res)) => {
+                        if source.config.traces || !res.success {
+                            if let Ok(decoder) = Self::decode_traces(
+                                &source.config,
+                                &mut res,
+                                &source.known_contracts(),
+                            ) {
+                                if Self::show_traces(&decoder, &mut res).await.is_err() {
+                                    return DispatchResult::CommandFailed(
+                                        "Failed to display traces".to_owned(),
+                                    )
+                                }
+
+                                // Show console logs, if there are any
+                                let decoded_logs = decode_console_logs(&res.logs);
+                                if !decoded_logs.is_empty() {
+                                    println!("{}", Paint::green("Logs:"));
+                                    for log in decoded_logs {
+                                        println!("  {log}");
+                                    }
+                                }
+                            }
+                        }
+
+                        if res.success {
+                            DispatchResult::CommandSuccess(Some(String::from(
+                                "Re-executed the session from a clean state",
+                            )))
+                        } else {
+                            DispatchResult::CommandFailed(Self::make_error(Self::describe_revert(
+                                source,
+                                &res,
+                                "REPL contract",
+                            )))
+                        }
+                    }
+                    Err(e) => DispatchResult::CommandFailed(Self::make_error(e)),
+                }
+            }
+            ChiselCommand::MemDump | ChiselCommand::StackDump => {
+                let full = match args {
+                    [] => false,
+                    ["--full"] => true,
+                    _ => {
+                        return DispatchResult::CommandFailed(Self::make_error(
+                            "Must supply either `--full` or no arguments.",
+                        ))
+                    }
+                };
+                let source = match self.session.session_source.as_ref() {
+                    Some(source) => source,
+                    None => {
+                        return DispatchResult::CommandFailed(Self::make_error(
+                            "Session not present.",
+                        ))
+                    }
+                };
+
+                // Reuse the state retained by the most recent execution, or else execute the
+                // last statement again with the machine state captured
+                let state = match source.retained_state() {
+                    Some(state) => state.clone(),
+                    None => {
+                        let mut source = source.clone();
+                        if let Some(last) =
+                            source.snippets.iter().rposition(|snippet| snippet.kind.is_executable())
+                        {
+                            source.persisted = source.state_before(last);
+                        }
+                        source.config.retain_state = true;
+                        match source.execute().await {
+                            Ok((_, res)) => match res.machine_state() {
+                                Some(state) => state,
+                                None => {
+                                    return DispatchResult::CommandFailed(Self::make_error(
+                                        "Run function is empty.",
+                                    ))
+                                }
+                            },
+                            Err(e) => {
+                                return DispatchResult::CommandFailed(Self::make_error(
+                                    e.to_string(),
+                                ))
+                            }
+                        }
+                    }
+                };
+
+                if matches!(cmd, ChiselCommand::MemDump) {
+                    DispatchResult::CommandSuccess(Some(if state.memory.data().is_empty() {
+                        String::from("Memory is empty")
+                    } else {
+                        Self::format_words(state.memory.data(), full)
+                    }))
+                } else {
+                    let stack = if state.stack.data().is_empty() {
+                        String::from("Stack is empty")
+                    } else {
+                        Self::format_stack(&state.stack, full)
+                    };
+                    let return_data = if state.return_data.is_empty() {
+                        String::from(" empty")
+                    } else {
+                        format!("\n{}", Self::format_words(&state.return_data, full))
+                    };
+                    DispatchResult::CommandSuccess(Some(format!(
+                        "{stack}\n{}{return_data}",
+                        Paint::green("Return data:")
+                    )))
+                }
+            }
+            ChiselCommand::State => {
+                let source = match self.session.session_source.as_mut() {
+                    Some(source) => source,
+                    None => {
+                        return DispatchResult::CommandFailed(Self::make_error(
+                            "Session source not present!",
+                        ))
+                    }
+                };
+                let retain = match args {
+                    [] => {
+                        return DispatchResult::CommandSuccess(Some(String::from(
+                            if source.config.retain_state {
+                                "The machine state of each execution is retained"
+                            } else {
+                                "The machine state is only captured by `!memdump`, `!stackdump`, and inspections"
+                            },
+                        )))
+                    }
+                    ["on"] => true,
+                    ["off"] => false,
+                    _ => {
+                        return DispatchResult::CommandFailed(Self::make_error(
+                            "Must supply either `on` or `off` as the argument.",
+                        ))
+                    }
+                };
+
+                source.config.retain_state = retain;
+                if !retain {
+                    source.machine_state = None;
+                }
+                DispatchResult::CommandSuccess(Some(format!(
+                    "{} retaining the machine state of each execution!",
+                    if retain { "Enabled" } else { "Disabled" }
+                )))
+            }
+            ChiselCommand::Export => {
+                // Check if the current session inherits `Script.sol` before exporting
+                if let Some(session_source) = self.session.session_source.as_ref() {
+                    // Check if the pwd is a foundry project
+                    if PathBuf::from("foundry.toml").exists() {
+                        // Create "script" dir if it does not already exist.
+                        if !PathBuf::from("script").exists() {
+                            if let Err(e) = std::fs::create_dir_all("script") {
+                                return DispatchResult::CommandFailed(Self::make_error(
+                                    e.to_string(),
+                                ))
+                            }
+                        }
+
+                        // Write session source to `script/REPL.s.sol`
+                        if let Err(e) = std::fs::write(
+                            PathBuf::from("script/REPL.s.sol"),
+                            session_source.to_formatted_script_source(),
+                        ) {
+                            return DispatchResult::CommandFailed(Self::make_error(e.to_string()))
+                        }
+
+                        DispatchResult::CommandSuccess(Some(String::from(
+                            "Exported session source to script/REPL.s.sol!",
+                        )))
+                    } else {
+                        DispatchResult::CommandFailed(Self::make_error(
+                            "Must be in a foundry project to export source to script.",
+                        ))
+                    }
+                } else {
+                    DispatchResult::CommandFailed(Self::make_error("Session not present."))
+                }
+            }
+            ChiselCommand::Fetch => {
+                if args.len() != 2 {
+                    return DispatchResult::CommandFailed(Self::make_error(
+                        "Incorrect number of arguments supplied. Expected: <address> <name>",
+                    ))
+                }
+
+                let request_url = format!(
+                    "https://api.etherscan.io/api?module=contract&action=getabi&address={}{}",
+                    args[0],
+                    if let Some(api_key) = self
+                        .session
+                        .session_source
+                        .as_ref()
+                        .unwrap()
+                        .config
+                        .foundry_config
+                        .etherscan_api_key
+                        .as_ref()
+                    {
+                        format!("&apikey={api_key}")
+                    } else {
+                        String::default()
+                    }
+                );
+
                 // TODO: Not the cleanest method of building a solidity interface from
                 // the ABI, but does the trick. Might want to pull this logic elsewhere
                 // and/or refactor at some point.
@@ -560,11 +1514,13 @@ impl ChiselDispatcher {
                                 // Add the interface to the source outright - no need to verify
                                 // syntax via compilation and/or
                                 // parsing.
-                                self.session
-                                    .session_source
-                                    .as_mut()
-                                    .unwrap()
-                                    .with_global_code(&interface);
+                                self.session.session_source.as_mut().unwrap().append_snippet(
+                                    SolSnippet::new(
+                                        ParseTreeFragment::Source,
+                                        format!("{}\n", interface.trim()),
+                                    )
+                                    .with_origin(SnippetOrigin::Import),
+                                );
 
                                 DispatchResult::CommandSuccess(Some(format!(
                                     "Added {}'s interface to source as `{}`",
@@ -610,63 +1566,57 @@ impl ChiselDispatcher {
                     Err(e) => DispatchResult::CommandFailed(e.to_string()),
                 }
             }
-            ChiselCommand::Edit => {
-                if let Some(session_source) = self.session.session_source.as_mut() {
-                    // create a temp file with the content of the run code
-                    let mut temp_file_path = std::env::temp_dir();
-                    temp_file_path.push("chisel-tmp.sol");
-                    let result = std::fs::File::create(&temp_file_path)
-                        .map(|mut file| file.write_all(session_source.run_code.as_bytes()));
-                    if let Err(e) = result {
-                        return DispatchResult::CommandFailed(format!(
-                            "Could not write to a temporary file: {e}"
+            ChiselCommand::Edit if args.len() == 1 => {
+                let index = match args[0].parse::<usize>() {
+                    Ok(index) => index,
+                    Err(_) => {
+                        return DispatchResult::CommandFailed(Self::make_error(
+                            "Snippet index must be a non-negative integer.",
                         ))
                     }
+                };
+                let snippet = match self
+                    .session
+                    .session_source
+                    .as_ref()
+                    .and_then(|source| source.snippet_code(index))
+                {
+                    Some(code) => code,
+                    None => {
+                        return DispatchResult::CommandFailed(Self::make_error(format!(
+                            "Snippet index {index} is out of range!"
+                        )))
+                    }
+                };
 
-                    // open the temp file with the editor
-                    let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vim".to_string());
-                    let mut cmd = Command::new(editor);
-                    cmd.arg(&temp_file_path);
+                let edited_code = match Self::open_in_editor(&snippet) {
+                    Ok(edited_code) => edited_code,
+                    Err(e) => return DispatchResult::CommandFailed(e),
+                };
 
-                    match cmd.status() {
-                        Ok(status) => {
-                            if !status.success() {
-                                if let Some(status_code) = status.code() {
-                                    return DispatchResult::CommandFailed(format!(
-                                        "Editor exited with status {status_code}"
-                                    ))
-                                } else {
-                                    return DispatchResult::CommandFailed(
-                                        "Editor exited without a status code".to_string(),
-                                    )
-                                }
-                            }
-                        }
-                        Err(_) => {
-                            return DispatchResult::CommandFailed(
-                                "Editor exited without a status code".to_string(),
-                            )
-                        }
-                    }
+                self.apply(cmd, &[args[0], edited_code.as_str()]).await
+            }
+            ChiselCommand::Edit => {
+                if let Some(session_source) = self.session.session_source.as_mut() {
+                    let edited_code = match Self::open_in_editor(&session_source.run_code) {
+                        Ok(edited_code) => edited_code,
+                        Err(e) => return DispatchResult::CommandFailed(e),
+                    };
 
                     let mut new_session_source = session_source.clone();
-                    if let Ok(edited_code) = std::fs::read_to_string(temp_file_path) {
-                        new_session_source.drain_run();
-                        new_session_source.with_run_code(&edited_code);
-                    } else {
-                        return DispatchResult::CommandFailed(
-                            "Could not read the edited file".to_string(),
-                        )
-                    }
+                    new_session_source.drain_run();
+                    new_session_source.with_run_code(&edited_code);
 
                     // if the editor exited successfully, try to compile the new code
                     match new_session_source.execute().await {
                         Ok((_, mut res)) => {
                             let failed = !res.success;
                             if new_session_source.config.traces || failed {
-                                if let Ok(decoder) =
-                                    Self::decode_traces(&new_session_source.config, &mut res)
-                                {
+                                if let Ok(decoder) = Self::decode_traces(
+                                    &new_session_source.config,
+                                    &mut res,
+                                    &new_session_source.known_contracts(),
+                                ) {
                                     if Self::show_traces(&decoder, &mut res).await.is_err() {
                                         self.errored = true;
                                         return DispatchResult::CommandFailed(
@@ -683,12 +1633,18 @@ impl ChiselDispatcher {
                                         }
                                     }
                                 }
+                            }
 
+                            if failed {
                                 // If the contract execution failed, continue on without
                                 // updating the source.
                                 self.errored = true;
                                 DispatchResult::CommandFailed(Self::make_error(
-                                    "Failed to execute edited contract!",
+                                    Self::describe_revert(
+                                        &new_session_source,
+                                        &res,
+                                        "edited contract",
+                                    ),
                                 ))
                             } else {
                                 // the code could be compiled, save it
@@ -787,6 +1743,12 @@ impl ChiselDispatcher {
             }
         };
 
+        // Pure builtins over literals are evaluated without compiling the session
+        if let Some(res) = source.evaluate_natively(input) {
+            self.errored = false;
+            return DispatchResult::Success(Some(res))
+        }
+
         // TODO: Cloning / parsing the session source twice on non-inspected inputs kinda sucks.
         // Should change up how this works.
         match source.inspect(input).await {
@@ -805,17 +1767,18 @@ impl ChiselDispatcher {
         }
 
         // Create new source with exact input appended and parse
-        let (mut new_source, do_execute) = match source.clone_with_new_line(input.to_string()) {
-            Ok(new) => new,
-            Err(e) => {
-                self.errored = true;
-                return DispatchResult::CommandFailed(Self::make_error(format!(
-                    "Failed to parse input! {e}"
-                )))
-            }
-        };
+        let (mut new_source, do_execute, warnings) =
+            match Self::prepare_input(source, input, self.session.redefinition_policy) {
+                Ok(prepared) => prepared,
+                Err(e) => {
+                    self.errored = true;
+                    return DispatchResult::CommandFailed(Self::make_error(e))
+                }
+            };
 
         if do_execute {
+            let previous_logs = new_source.logs.clone();
+            let previous_snippets = source.snippets.clone();
             match new_source.execute().await {
                 Ok((_, mut res)) => {
                     let failed = !res.success;
@@ -823,7 +1786,11 @@ impl ChiselDispatcher {
                     // If traces are enabled or there was an error in execution, show the execution
                     // traces.
                     if new_source.config.traces || failed {
-                        if let Ok(decoder) = Self::decode_traces(&new_source.config, &mut res) {
+                        if let Ok(decoder) = Self::decode_traces(
+                            &new_source.config,
+                            &mut res,
+                            &new_source.known_contracts(),
+                        ) {
                             if Self::show_traces(&decoder, &mut res).await.is_err() {
                                 self.errored = true;
                                 return DispatchResult::CommandFailed(
@@ -839,20 +1806,74 @@ impl ChiselDispatcher {
                                     println!("  {log}");
                                 }
                             }
+                        }
+                    }
 
-                            // If the contract execution failed, continue on without adding the new
-                            // line to the source.
-                            if failed {
-                                self.errored = true;
-                                return DispatchResult::Failure(Some(Self::make_error(
-                                    "Failed to execute REPL contract!",
-                                )))
-                            }
+                    // If the contract execution failed, continue on without adding the new line
+                    // to the source.
+                    if failed {
+                        self.errored = true;
+                        let mut message = Self::describe_revert(&new_source, &res, "REPL contract");
+                        if matches!(
+                            res.revert,
+                            Some(
+                                RevertReason::GasLimitExceeded(_) |
+                                    RevertReason::TimeLimitExceeded(_)
+                            )
+                        ) {
+                            message.push_str(
+                                "\nThe session was rolled back to its state before the statement",
+                            );
                         }
+                        self.reverted = Some(input.to_string());
+                        return DispatchResult::Failure(Some(Self::make_error(message)))
+                    }
+
+                    // Show the events emitted by the entered statement. If the session was
+                    // replayed, the statements entered before it ran again and
+                    // emitted their events first.
+                    let unchanged = if res.replayed {
+                        previous_logs
+                            .iter()
+                            .zip(&res.logs)
+                            .take_while(|(previous, log)| previous == log)
+                            .count()
+                    } else {
+                        0
+                    };
+                    let events = res
+                        .decoded_logs
+                        .iter()
+                        .skip(unchanged)
+                        .filter(|log| !log.is_console_log())
+                        .collect::<Vec<_>>();
+                    if !events.is_empty() {
+                        let formatter = new_source.value_formatter();
+                        println!("{}", Paint::green("Events:"));
+                        for event in events {
+                            println!("  {}", event.format(&formatter));
+                        }
+                    }
+
+                    // Show the gas used by the entered statement
+                    let last = new_source.snippet_count().saturating_sub(1);
+                    if let Some((_, gas)) =
+                        res.gas_per_snippet.iter().find(|(index, _)| *index == last)
+                    {
+                        println!("{}", Paint::cyan(format!("Gas: {gas}")));
                     }
 
+                    // Snapshot the state before the statement, so that `!undo` also rewinds its
+                    // logs. Replaying the journal takes the same snapshots.
+                    new_source.push_snapshot(previous_snippets, previous_logs, true);
+
                     // Replace the old session source with the new version
                     self.session.session_source = Some(new_source);
+                    self.reverted = None;
+                    self.persist(input);
+                    for warning in &warnings {
+                        eprintln!("{}", Paint::yellow(warning));
+                    }
                     // Clear any outstanding errors
                     self.errored = false;
 
@@ -864,9 +1885,14 @@ impl ChiselDispatcher {
                 }
             }
         } else {
-            match new_source.build() {
+            match new_source.compile_repl_contract() {
                 Ok(_) => {
                     self.session.session_source = Some(new_source);
+                    self.reverted = None;
+                    self.persist(input);
+                    for warning in &warnings {
+                        eprintln!("{}", Paint::yellow(warning));
+                    }
                     self.errored = false;
                     DispatchResult::Success(None)
                 }
@@ -878,29 +1904,153 @@ impl ChiselDispatcher {
         }
     }
 
+    /// Opens code in the user's `$EDITOR` (or vim, if unset) via a temporary file
+    ///
+    /// ### Takes
+    ///
+    /// The code to pre-fill the temporary file with
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the edited code. Otherwise, a message describing the failure.
+    fn open_in_editor(code: &str) -> Result<String, String> {
+        // create a temp file with the code
+        let mut temp_file_path = std::env::temp_dir();
+        temp_file_path.push("chisel-tmp.sol");
+        std::fs::File::create(&temp_file_path)
+            .and_then(|mut file| file.write_all(code.as_bytes()))
+            .map_err(|e| format!("Could not write to a temporary file: {e}"))?;
+
+        // open the temp file with the editor
+        let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vim".to_string());
+        let mut cmd = Command::new(editor);
+        cmd.arg(&temp_file_path);
+
+        match cmd.status() {
+            Ok(status) if !status.success() => match status.code() {
+                Some(status_code) => return Err(format!("Editor exited with status {status_code}")),
+                None => return Err("Editor exited without a status code".to_string()),
+            },
+            Ok(_) => {}
+            Err(_) => return Err("Editor exited without a status code".to_string()),
+        }
+
+        std::fs::read_to_string(temp_file_path)
+            .map_err(|_| "Could not read the edited file".to_string())
+    }
+
+    /// Persists the session if autosave is enabled. Failures are printed as a warning rather
+    /// than failing the dispatch.
+    fn autosave(&mut self) {
+        if let Err(e) = self.session.autosave() {
+            eprintln!("{}", Paint::yellow(format!("Warning: Failed to autosave session: {e}")));
+        }
+    }
+
+    /// Persists an accepted input by autosaving the session or, if autosave is disabled, by
+    /// appending the input to the session's journal. Failures are printed as a warning rather
+    /// than failing the dispatch.
+    ///
+    /// A journal is replayed on top of the session's cache file, so a session that was never
+    /// written is written once instead, which reserves its ID.
+    fn persist(&mut self, input: &str) {
+        if self.session.autosave {
+            self.autosave();
+        } else if self.session.id.is_none() {
+            if let Err(e) = self.session.write() {
+                eprintln!("{}", Paint::yellow(format!("Warning: Failed to write session: {e}")));
+            }
+        } else if let Err(e) = self.session.journal(input) {
+            eprintln!("{}", Paint::yellow(format!("Warning: Failed to journal input: {e}")));
+        }
+    }
+
+    /// Formats the gas used by each snippet of a session
+    ///
+    /// ### Takes
+    ///
+    /// - The [SessionSource] that was executed
+    /// - The gas used by each snippet. See [ChiselResult::gas_per_snippet].
+    ///
+    /// ### Returns
+    ///
+    /// A table of the snippets' gas, headed by the first line of their code, and the total
+    fn format_gas_used(source: &SessionSource, gas_per_snippet: &[(usize, u64)]) -> String {
+        let rows = gas_per_snippet
+            .iter()
+            .map(|(index, gas)| {
+                let code = source.snippet_code(*index).unwrap_or_default();
+                (format!("{index}: {}", code.trim().lines().next().unwrap_or_default()), gas)
+            })
+            .collect::<Vec<_>>();
+        let width = rows.iter().map(|(label, _)| label.len()).max().unwrap_or_default();
+        let total = gas_per_snippet.iter().map(|(_, gas)| gas).sum::<u64>();
+        let gas_width = total.to_string().len();
+        let mut table = String::from(
+            "Gas used by each snippet, excluding the deployment and base cost of the REPL contract:",
+        );
+        for (label, gas) in rows {
+            table.push_str(&format!("\n  {label:width$}  {gas:>gas_width$}"));
+        }
+        table.push_str(&format!("\n  {:width$}  {total}", "Total"));
+        table
+    }
+
     /// Decodes traces in the [ChiselResult]
-    /// TODO: Add `known_contracts` back in.
     ///
     /// ### Takes
     ///
     /// - A reference to a [SessionSourceConfig]
     /// - A mutable reference to a [ChiselResult]
+    /// - The contracts known to the session. See [SessionSource::known_contracts].
     ///
     /// ### Returns
     ///
     /// Optionally, a [CallTraceDecoder]
+    ///
+    /// ### Notes
+    ///
+    /// Calls are decoded by the functions of all of the session's contracts and interfaces,
+    /// falling back to the signatures of Etherscan and the signature database.
     pub fn decode_traces(
         session_config: &SessionSourceConfig,
         result: &mut ChiselResult,
-        // known_contracts: &ContractsByArtifact,
+        known_contracts: &ContractsByArtifact,
     ) -> eyre::Result<CallTraceDecoder> {
+        let mut local_identifier = LocalTraceIdentifier::new(known_contracts);
         let mut etherscan_identifier = EtherscanIdentifier::new(
             &session_config.foundry_config,
-            session_config.evm_opts.get_remote_chain_id(),
+            session_config
+                .fork
+                .as_ref()
+                .and_then(|fork| fork.chain_id)
+                .map(Chain::from)
+                .or_else(|| session_config.evm_opts.get_remote_chain_id().map(Chain::from)),
         )?;
 
-        let mut decoder =
-            CallTraceDecoderBuilder::new().with_labels(result.labeled_addresses.clone()).build();
+        let mut decoder = CallTraceDecoderBuilder::new()
+            .with_labels(result.labeled_addresses.clone())
+            .with_events(local_identifier.events())
+            .build();
+
+        // Calls to addresses that are not identified, i.e. through an interface on a fork, are
+        // decoded by selector
+        for (_, (abi, _)) in known_contracts.iter() {
+            for function in abi.functions() {
+                let functions = decoder.functions.entry(function.short_signature()).or_default();
+                if !functions.contains(function) {
+                    functions.push(function.clone());
+                }
+            }
+            for (name, errors) in &abi.errors {
+                let known = decoder.errors.errors.entry(name.clone()).or_default();
+                for error in errors {
+                    if !known.contains(error) {
+                        known.push(error.clone());
+                    }
+                }
+            }
+        }
 
         decoder.add_signature_identifier(SignaturesIdentifier::new(
             Config::foundry_cache_dir(),
@@ -908,7 +2058,7 @@ impl ChiselDispatcher {
         )?);
 
         for (_, trace) in &mut result.traces {
-            // decoder.identify(trace, &mut local_identifier);
+            decoder.identify(trace, &mut local_identifier);
             decoder.identify(trace, &mut etherscan_identifier);
         }
         Ok(decoder)
@@ -944,6 +2094,12 @@ impl ChiselDispatcher {
         Ok(())
     }
 
+    /// Joins the code of the parts of a snippet into a single line, i.e. to name an undone
+    /// snippet
+    fn join_parts(parts: &[SolSnippet]) -> String {
+        parts.iter().map(|part| part.code.trim()).collect::<Vec<_>>().join(" ")
+    }
+
     /// Format a type that implements [fmt::Display] as a chisel error string.
     ///
     /// ### Takes
@@ -956,4 +2112,770 @@ impl ChiselDispatcher {
     pub fn make_error<T: std::fmt::Display>(msg: T) -> String {
         format!("{} {}", Paint::red(format!("{CHISEL_CHAR} Chisel Error:")), Paint::red(msg))
     }
+
+    /// Formats bytes as 32 byte words prefixed with their offsets, i.e. memory or return data
+    ///
+    /// ### Takes
+    ///
+    /// - The bytes to format
+    /// - Whether to format all words, rather than only the first [DUMP_LIMIT] words
+    ///
+    /// ### Returns
+    ///
+    /// A line per word, followed by the number of words that were left out, if any
+    pub fn format_words(data: &[u8], full: bool) -> String {
+        let words = data.chunks(32).collect::<Vec<_>>();
+        let shown = if full { words.len() } else { words.len().min(DUMP_LIMIT) };
+        let mut lines = words[..shown]
+            .iter()
+            .enumerate()
+            .map(|(i, word)| {
+                format!(
+                    "{}: {}",
+                    Paint::yellow(format!("[0x{:02x}:0x{:02x}]", i * 32, i * 32 + word.len())),
+                    Paint::cyan(format!("0x{}", hex::encode(word)))
+                )
+            })
+            .collect::<Vec<_>>();
+        if shown < words.len() {
+            lines.push(format!(
+                "... {} more words, supply `--full` to print all of them",
+                words.len() - shown
+            ));
+        }
+        lines.join("\n")
+    }
+
+    /// Formats the items of a stack, from the top of the stack down
+    ///
+    /// ### Takes
+    ///
+    /// - The stack to format
+    /// - Whether to format all items, rather than only the top [DUMP_LIMIT] items
+    ///
+    /// ### Returns
+    ///
+    /// A line per item, followed by the number of items that were left out, if any
+    pub fn format_stack(stack: &revm::Stack, full: bool) -> String {
+        let items = stack.data();
+        let shown = if full { items.len() } else { items.len().min(DUMP_LIMIT) };
+        let mut lines = items
+            .iter()
+            .rev()
+            .take(shown)
+            .enumerate()
+            .map(|(i, item)| {
+                format!(
+                    "{}: {}",
+                    Paint::yellow(format!("[{i}]")),
+                    Paint::cyan(format!("0x{item:02x}"))
+                )
+            })
+            .collect::<Vec<_>>();
+        if shown < items.len() {
+            lines.push(format!(
+                "... {} more items, supply `--full` to print all of them",
+                items.len() - shown
+            ));
+        }
+        lines.join("\n")
+    }
+
+    /// Formats a balance in wei, gwei, and ether
+    ///
+    /// ### Takes
+    ///
+    /// The balance in wei
+    ///
+    /// ### Returns
+    ///
+    /// The balance in each unit on its own line, without trailing zeros
+    pub fn format_balance(balance: U256) -> String {
+        format!(
+            "Type: {}\n├ Wei: {}\n├ Gwei: {}\n└ Ether: {}",
+            Paint::red("balance"),
+            Paint::cyan(Self::format_units(balance, 0)),
+            Paint::cyan(Self::format_units(balance, 9)),
+            Paint::cyan(Self::format_units(balance, 18))
+        )
+    }
+
+    /// Formats an amount of wei in a larger unit, without trailing zeros
+    ///
+    /// ### Takes
+    ///
+    /// - The amount in wei
+    /// - The number of decimals of the unit, i.e. 9 for gwei
+    fn format_units(amount: U256, decimals: usize) -> String {
+        let digits = format!("{:0>width$}", amount.to_string(), width = decimals + 1);
+        let (integer, fraction) = digits.split_at(digits.len() - decimals);
+        match fraction.trim_end_matches('0') {
+            "" => integer.to_string(),
+            fraction => format!("{integer}.{fraction}"),
+        }
+    }
+
+    /// Prints a prompt and reads a line of input from stdin. The blocking read happens on a
+    /// blocking thread, so that it does not stall the runtime.
+    ///
+    /// ### Returns
+    ///
+    /// The trimmed line, or `None` if stdin was closed
+    async fn prompt(message: &str) -> Option<String> {
+        print!("{message} ");
+        let _ = std::io::stdout().flush();
+        tokio::task::spawn_blocking(|| {
+            let mut line = String::new();
+            match std::io::stdin().read_line(&mut line) {
+                Ok(0) | Err(_) => None,
+                Ok(_) => Some(line.trim().to_string()),
+            }
+        })
+        .await
+        .ok()
+        .flatten()
+    }
+
+    /// Broadcasts statements of the session to a real network as transactions, see
+    /// `!broadcast`. The chain ID of the network and each transaction are confirmed at a
+    /// prompt, and the transactions that are sent are recorded alongside their snippets.
+    async fn broadcast(&mut self, args: &[&str]) -> DispatchResult {
+        let args = match BroadcastArgs::parse(args) {
+            Ok(args) => args,
+            Err(e) => return DispatchResult::CommandFailed(Self::make_error(e)),
+        };
+        let source = match &self.session.session_source {
+            Some(source) => source,
+            None => {
+                return DispatchResult::CommandFailed(Self::make_error(
+                    "Session source not present!",
+                ))
+            }
+        };
+        let snippets = if args.snippets.is_empty() {
+            match source.snippets.iter().rposition(|snippet| snippet.kind().is_executable()) {
+                Some(last) => vec![source.snippet_index_of(last)],
+                None => {
+                    return DispatchResult::CommandFailed(Self::make_error(
+                        "The session has no statements to broadcast",
+                    ))
+                }
+            }
+        } else {
+            args.snippets.clone()
+        };
+        let endpoint = ForkConfig {
+            endpoint: args.endpoint.clone(),
+            block: None,
+            chain_id: None,
+            secret_url: None,
+        };
+        let rpc_url =
+            match endpoint.resolve(&source.config.foundry_config, &source.config.rpc_aliases()) {
+                Ok(rpc_url) => rpc_url,
+                Err(e) => return DispatchResult::CommandFailed(Self::make_error(e)),
+            };
+        let fork_chain_id = source.config.fork.as_ref().and_then(|fork| fork.chain_id);
+
+        // Plan the transactions against the session first, so that nothing is signed for
+        // statements that revert
+        let planned = match source.plan_broadcast(&snippets).await {
+            Ok(planned) => planned,
+            Err(e) => return DispatchResult::CommandFailed(Self::make_error(e)),
+        };
+        let broadcaster = match Broadcaster::connect(&rpc_url, &args.signer).await {
+            Ok(broadcaster) => broadcaster,
+            Err(e) => return DispatchResult::CommandFailed(Self::make_error(e)),
+        };
+
+        // The chain ID of the network must be confirmed, either by `--chain-id` or at a prompt
+        let chain_id = broadcaster.chain_id;
+        match args.chain_id {
+            Some(expected) if expected != chain_id => {
+                return DispatchResult::CommandFailed(Self::make_error(format!(
+                    "The network has chain ID {chain_id}, not {expected}. Nothing was broadcast."
+                )))
+            }
+            Some(_) => {}
+            None => {
+                let answer = Self::prompt(&format!(
+                    "The network has chain ID {}. Enter it to confirm broadcasting to it:",
+                    Paint::yellow(chain_id)
+                ))
+                .await;
+                if answer != Some(chain_id.to_string()) {
+                    return DispatchResult::CommandFailed(Self::make_error(
+                        "The chain ID was not confirmed. Nothing was broadcast.",
+                    ))
+                }
+            }
+        }
+
+        // Calls are sent to the contracts that they reached within the session, which must be the
+        // same contracts on the network
+        let calls = planned.iter().filter(|transaction| transaction.kind == TransactionKind::Call);
+        if calls.clone().next().is_some() {
+            if let Some(fork_chain_id) = fork_chain_id.filter(|id| *id != chain_id) {
+                return DispatchResult::CommandFailed(Self::make_error(format!(
+                    "The session forks chain ID {fork_chain_id}, so its calls were planned against another network than chain ID {chain_id}. Nothing was broadcast."
+                )))
+            }
+            for to in calls.filter_map(|transaction| transaction.to) {
+                if let Err(e) = broadcaster.check_recipient(to).await {
+                    return DispatchResult::CommandFailed(Self::make_error(format!(
+                        "{e}. Nothing was broadcast."
+                    )))
+                }
+            }
+        }
+
+        println!(
+            "Broadcasting {} transaction(s) from {}",
+            planned.len(),
+            Paint::cyan(to_checksum(&broadcaster.address(), None))
+        );
+        let (mut sent, mut deployed, mut failure) = (0, None, None);
+        for (i, transaction) in planned.iter().enumerate() {
+            let tx = match broadcaster.prepare(transaction, deployed).await {
+                Ok(tx) => tx,
+                Err(e) => {
+                    failure = Some(e.to_string());
+                    break
+                }
+            };
+            let gas = tx.gas().copied().unwrap_or_default();
+            let gas_price = tx.gas_price().unwrap_or_default();
+            println!(
+                "{} {transaction}\n  Gas limit: {gas}, gas price: {} gwei, max cost: {} ether",
+                Paint::yellow(format!("[{}/{}]", i + 1, planned.len())),
+                Self::format_units(gas_price, 9),
+                Self::format_units(gas.saturating_mul(gas_price), 18)
+            );
+            let answer = Self::prompt("Send this transaction? [y/N]").await;
+            if !matches!(answer.as_deref(), Some("y" | "yes")) {
+                failure = Some(String::from("The transaction was declined"));
+                break
+            }
+
+            let (hash, receipt) = match broadcaster.send(tx).await {
+                Ok(sent) => sent,
+                Err(e) => {
+                    failure = Some(e.to_string());
+                    break
+                }
+            };
+            let record = BroadcastReceipt::new(chain_id, transaction.kind, hash, receipt.as_ref());
+            match &receipt {
+                Some(receipt) => println!(
+                    "  Sent 0x{}, {} in block {} using {} gas",
+                    hex::encode(hash),
+                    if record.success == Some(true) { "succeeded" } else { "failed" },
+                    receipt.block_number.unwrap_or_default(),
+                    receipt.gas_used.unwrap_or_default()
+                ),
+                None => println!("  Sent 0x{}, but no receipt was received", hex::encode(hash)),
+            }
+            if let Err(e) = self.session.record_broadcast(transaction.snippet, record.clone()) {
+                eprintln!(
+                    "{}",
+                    Paint::yellow(format!("Warning: Failed to record the broadcast: {e}"))
+                );
+            }
+            sent += 1;
+
+            if transaction.kind == TransactionKind::Deployment {
+                deployed = receipt.and_then(|receipt| receipt.contract_address);
+                if let Some(address) = deployed {
+                    println!("  Deployed the REPL contract at {}", to_checksum(&address, None));
+                }
+            }
+            if record.success != Some(true) {
+                failure = Some(format!(
+                    "Transaction 0x{} did not succeed, so the rest were not sent",
+                    hex::encode(hash)
+                ));
+                break
+            }
+        }
+
+        // The broadcast is not journaled, so that recovering the session does not send the
+        // transactions again
+        if sent > 0 && self.session.autosave {
+            self.autosave();
+        }
+        match failure {
+            None => DispatchResult::CommandSuccess(Some(format!(
+                "Broadcast {sent} transaction(s) to chain ID {chain_id}"
+            ))),
+            Some(reason) => DispatchResult::CommandFailed(Self::make_error(format!(
+                "{reason}. {sent} of {} transaction(s) were sent.",
+                planned.len()
+            ))),
+        }
+    }
+
+    /// Applies a [ChiselCommand] that changes the session, persisting it if it was applied
+    async fn apply(&mut self, cmd: ChiselCommand, args: &[&str]) -> DispatchResult {
+        match Self::apply_command(&mut self.session, &cmd, args, false).await {
+            Ok(applied) => {
+                self.persist(&applied.entry);
+                for warning in applied.warnings {
+                    eprintln!("{}", Paint::yellow(format!("Warning: {warning}")));
+                }
+                DispatchResult::CommandSuccess(applied.message)
+            }
+            Err(e) => DispatchResult::CommandFailed(Self::make_error(e)),
+        }
+    }
+
+    /// Applies a [ChiselCommand] that changes the session, i.e. `!pragma` or `!undo`. Commands
+    /// entered into the REPL and commands replayed from the session's journal are both applied
+    /// here, so that they are validated alike.
+    ///
+    /// ### Takes
+    ///
+    /// - The [ChiselSession] to apply the command to
+    /// - The [ChiselCommand] and its arguments
+    /// - Whether the command is replayed from the journal. A replayed `!fork` is pinned to its
+    ///   journaled block without reaching the network.
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the [AppliedCommand]. Fails if the arguments are invalid, if the command
+    /// could not be applied, or if the command does not change the session.
+    pub async fn apply_command(
+        session: &mut ChiselSession,
+        cmd: &ChiselCommand,
+        args: &[&str],
+        replay: bool,
+    ) -> eyre::Result<AppliedCommand> {
+        let (entry, message, warnings) = match cmd {
+            ChiselCommand::Clear => {
+                let keep_preamble = match args {
+                    [] => false,
+                    ["keep"] => true,
+                    _ => eyre::bail!(
+                        "Invalid arguments! Supply `keep` to keep import and pragma directives."
+                    ),
+                };
+                if session.session_source.is_none() {
+                    eyre::bail!("Session source not present!")
+                }
+                session.clear_session(keep_preamble);
+                let entry = if keep_preamble { "!clear keep" } else { "!clear" };
+                (entry.to_string(), Some(String::from("Cleared session!")), Vec::new())
+            }
+            ChiselCommand::Undo => {
+                let parts = session.pop_snippet().ok_or(eyre::eyre!("Nothing to undo!"))?;
+                let message = format!("Removed `{}` from the session", Self::join_parts(&parts));
+                (String::from("!undo"), Some(message), Vec::new())
+            }
+            ChiselCommand::Redo => {
+                let parts = session.redo_snippet().ok_or(eyre::eyre!("Nothing to redo!"))?;
+                let message = format!("Restored `{}` to the session", Self::join_parts(&parts));
+                (String::from("!redo"), Some(message), Vec::new())
+            }
+            ChiselCommand::Snapshot => {
+                let id = session.snapshot()?;
+                (String::from("!snapshot"), Some(format!("Took snapshot {id}")), Vec::new())
+            }
+            ChiselCommand::Revert => {
+                let id = match args {
+                    [id] => id
+                        .parse::<usize>()
+                        .map_err(|_| eyre::eyre!("Invalid snapshot ID \"{id}\"!"))?,
+                    _ => {
+                        eyre::bail!("Must supply a snapshot ID as the argument. See `!snapshots`.")
+                    }
+                };
+                session.revert_to_snapshot(id)?;
+                let message = format!("Reverted the session to snapshot {id}");
+                (format!("!revert {id}"), Some(message), Vec::new())
+            }
+            ChiselCommand::TopLevel => {
+                // The input was split on spaces; Join it back together verbatim
+                let code = args.join(" ");
+                if code.trim().is_empty() {
+                    eyre::bail!("No code supplied!")
+                }
+                let source =
+                    session.session_source.as_ref().ok_or(eyre::eyre!("Session not present."))?;
+                let mut new_source = source.clone_with_global_code(&code)?;
+                new_source.build()?;
+                session.session_source = Some(new_source);
+                session.dirty = true;
+                (format!("!toplevel {code}"), None, Vec::new())
+            }
+            ChiselCommand::Format => {
+                let number_format = match args {
+                    [number_format] => number_format.parse::<NumberFormat>()?,
+                    _ => eyre::bail!("Must supply a number format as the argument."),
+                };
+                session.set_number_format(number_format)?;
+                let message = format!("Set the number format to {number_format}");
+                (format!("!format {number_format}"), Some(message), Vec::new())
+            }
+            ChiselCommand::Abi => {
+                if args.len() < 2 {
+                    eyre::bail!("Incorrect number of arguments supplied. Expected: <name> <json>")
+                }
+                let abi_json = args[1..].join(" ");
+                session.add_interface_from_abi(args[0], &abi_json)?;
+                // Journal the ABI itself, as the file it was read from may change before recovery
+                let message = format!("Added interface `{}` to the session source", args[0]);
+                (format!("!abi {} {}", args[0], abi_json.trim()), Some(message), Vec::new())
+            }
+            ChiselCommand::ContractName => {
+                if args.len() != 1 {
+                    eyre::bail!("Must supply a contract name as the argument.")
+                }
+                let contract_name = session.set_contract_name(args[0])?;
+                let message = if contract_name == args[0] {
+                    format!("Renamed the REPL contract to `{contract_name}`")
+                } else {
+                    format!(
+                        "Renamed the REPL contract to `{contract_name}`, as `{}` is already defined within the session",
+                        args[0]
+                    )
+                };
+                (format!("!contractname {}", args[0]), Some(message), Vec::new())
+            }
+            ChiselCommand::License => {
+                if args.len() != 1 {
+                    eyre::bail!("Must supply an SPDX license identifier as the argument.")
+                }
+                session.set_license(args[0].to_string())?;
+                let message = format!("Set the license of the session source to {}", args[0]);
+                (format!("!license {}", args[0]), Some(message), Vec::new())
+            }
+            ChiselCommand::Pragma => {
+                // The version requirement may contain spaces, i.e. `>=0.8.0 <0.9.0`
+                let requirement = args.join(" ");
+                let requirement = requirement.trim().trim_end_matches(';');
+                if requirement.is_empty() {
+                    session.set_pragma(None)?;
+                } else {
+                    session.set_pragma(Some(requirement.to_string()))?;
+                }
+                let pragma = session
+                    .session_source
+                    .as_ref()
+                    .map(|source| source.pragma_requirement())
+                    .unwrap_or_default();
+                let message = format!("The session source now uses `pragma solidity {pragma};`");
+                (format!("!pragma {requirement}").trim_end().to_string(), Some(message), Vec::new())
+            }
+            ChiselCommand::Optimizer => {
+                let runs = match args {
+                    ["off"] => None,
+                    [runs] => match runs.parse::<u32>() {
+                        Ok(0) => None,
+                        Ok(runs) => Some(runs),
+                        Err(_) => eyre::bail!("Invalid number of optimizer runs \"{runs}\""),
+                    },
+                    _ => eyre::bail!(
+                        "Must supply the number of optimizer runs, or `off`, as the argument."
+                    ),
+                };
+                session.set_optimizer(runs)?;
+                let message = match runs {
+                    Some(runs) => format!("Enabled the optimizer with {runs} runs"),
+                    None => String::from("Disabled the optimizer"),
+                };
+                (format!("!optimizer {}", args[0]), Some(message), Vec::new())
+            }
+            ChiselCommand::ViaIr => {
+                let via_ir = match args {
+                    ["on"] => true,
+                    ["off"] => false,
+                    _ => eyre::bail!("Must supply either `on` or `off` as the argument."),
+                };
+                session.set_via_ir(via_ir)?;
+                let message = if via_ir {
+                    String::from("The session source is now compiled via IR")
+                } else {
+                    String::from("The session source is now compiled with the legacy pipeline")
+                };
+                (format!("!viair {}", args[0]), Some(message), Vec::new())
+            }
+            ChiselCommand::Remap => {
+                let (from, to) = match args {
+                    [remapping] => match remapping.split_once('=') {
+                        Some((from, to)) if !from.is_empty() && !to.is_empty() => (from, to),
+                        _ => eyre::bail!("Must supply a remapping in the form of `<from>=<to>`."),
+                    },
+                    _ => eyre::bail!("Must supply a remapping in the form of `<from>=<to>`."),
+                };
+                let remapping = session.add_remapping(from, PathBuf::from(to))?;
+                // Journal the absolute target, so that replaying the journal does not depend on
+                // the working directory
+                let message = format!("Added remapping {remapping}");
+                (format!("!remap {remapping}"), Some(message), Vec::new())
+            }
+            ChiselCommand::Solc => {
+                if args.len() != 1 {
+                    eyre::bail!("Must supply a solc version as the argument.")
+                }
+                session.set_solc_version(args[0])?;
+                let message = format!(
+                    "The session is now compiled with solc {}",
+                    args[0].trim_start_matches('v')
+                );
+                (format!("!solc {}", args[0]), Some(message), Vec::new())
+            }
+            ChiselCommand::EvmVersion => {
+                if args.len() != 1 {
+                    eyre::bail!("Must supply an EVM version as the argument.")
+                }
+                session.set_evm_version(args[0])?;
+                let version = session
+                    .session_source
+                    .as_ref()
+                    .map(|source| source.config.foundry_config.evm_version.to_string())
+                    .unwrap_or_default();
+                let message = format!("Set the EVM version of the session to {version}");
+                (format!("!evm {}", args[0]), Some(message), Vec::new())
+            }
+            ChiselCommand::Fork if args.is_empty() => {
+                session.clear_fork()?;
+                let message = String::from("Now using local environment.");
+                (String::from("!fork"), Some(message), Vec::new())
+            }
+            ChiselCommand::Fork => {
+                if args.len() > 2 || args[0].trim().is_empty() {
+                    eyre::bail!(
+                        "Must supply a fork URL or RPC alias, and optionally a block number, as the arguments."
+                    )
+                }
+                let block = match args.get(1) {
+                    Some(block) => Some(
+                        block
+                            .parse::<u64>()
+                            .map_err(|_| eyre::eyre!("Invalid block number: {block}"))?,
+                    ),
+                    None => None,
+                };
+
+                // If the argument is an RPC alias designated in the `[rpc_endpoints]` section of
+                // the `foundry.toml` within the pwd, or registered with `!rpc add`, the URL
+                // matched to the key is used.
+                let warnings = if replay {
+                    session.restore_fork(args[0], block)?;
+                    Vec::new()
+                } else {
+                    session.fork(args[0], block).await?
+                };
+                let fork = session
+                    .session_source
+                    .as_ref()
+                    .and_then(|source| source.config.fork.clone())
+                    .ok_or(eyre::eyre!("Session source not present!"))?;
+                let entry = format!("!fork {} {}", fork.endpoint, fork.block.unwrap_or_default());
+                (entry, Some(format!("Forked {}", Paint::yellow(&fork))), warnings)
+            }
+            ChiselCommand::Block |
+            ChiselCommand::Timestamp |
+            ChiselCommand::BaseFee |
+            ChiselCommand::ChainId |
+            ChiselCommand::Sender |
+            ChiselCommand::Value => {
+                let (field, name) = match cmd {
+                    ChiselCommand::Block => ("block", "block.number"),
+                    ChiselCommand::Timestamp => ("timestamp", "block.timestamp"),
+                    ChiselCommand::BaseFee => ("basefee", "block.basefee"),
+                    ChiselCommand::ChainId => ("chainid", "block.chainid"),
+                    ChiselCommand::Sender => ("sender", "msg.sender"),
+                    _ => ("value", "msg.value"),
+                };
+                let value = match args {
+                    [] => None,
+                    [value] => Some(*value),
+                    _ => eyre::bail!("Too many arguments supplied!"),
+                };
+                let warnings = session.set_env_override(field, value)?;
+                let message = match value {
+                    Some(value) => format!("Set `{name}` to {value} for subsequent statements"),
+                    None => format!("Reset `{name}`"),
+                };
+                let entry =
+                    format!("!{field} {}", value.unwrap_or_default()).trim_end().to_string();
+                (entry, Some(message), warnings)
+            }
+            ChiselCommand::GasLimit => {
+                let gas_limit = match args {
+                    [gas_limit] => gas_limit
+                        .replace('_', "")
+                        .parse::<u64>()
+                        .map_err(|_| eyre::eyre!("Invalid gas limit {gas_limit}!"))?,
+                    _ => eyre::bail!("Must supply a gas limit as the argument."),
+                };
+                session.set_gas_limit(gas_limit)?;
+                let message = format!("Set the gas limit to {gas_limit}");
+                (format!("!gaslimit {gas_limit}"), Some(message), Vec::new())
+            }
+            ChiselCommand::Timeout => {
+                let timeout = match args {
+                    [timeout] => parse_timeout(timeout)?,
+                    _ => eyre::bail!("Must supply a time limit as the argument."),
+                };
+                session.set_timeout(timeout)?;
+                let secs = timeout.as_secs_f64();
+                (
+                    format!("!timeout {secs}"),
+                    Some(format!("Set the time limit to {secs}s")),
+                    Vec::new(),
+                )
+            }
+            ChiselCommand::Deal => {
+                let (account, amount) = match args {
+                    [account, amount @ ..] if !amount.is_empty() => (account, amount.join("")),
+                    _ => eyre::bail!(
+                        "Must supply an address and an amount, i.e. `!deal 0x... 1ether`."
+                    ),
+                };
+                let account = account
+                    .parse::<Address>()
+                    .map_err(|_| eyre::eyre!("Invalid address {account}!"))?;
+                let balance = parse_amount(&amount)?;
+                session.deal(account, balance)?;
+                let message =
+                    format!("Set the balance of {} to {balance} wei", to_checksum(&account, None));
+                (format!("!deal {account:?} {balance}"), Some(message), Vec::new())
+            }
+            ChiselCommand::Edit => {
+                let (index, code) = match args {
+                    [index, code @ ..] if !code.is_empty() => (index, code.join(" ")),
+                    _ => eyre::bail!("Must supply a snippet index and its new code."),
+                };
+                let index = index
+                    .parse::<usize>()
+                    .map_err(|_| eyre::eyre!("Snippet index must be a non-negative integer."))?;
+
+                // Keep a copy of the source in case the edited source fails to compile
+                let backup = session.session_source.clone();
+                session.replace_snippet(index, &code)?;
+                if let Err(e) = session.session_source.as_mut().unwrap().build() {
+                    session.session_source = backup;
+                    eyre::bail!("The edited snippet could not be compiled: {e}")
+                }
+                let message = format!("Successfully edited snippet {index}!");
+                (format!("!edit {index} {code}"), Some(message), Vec::new())
+            }
+            _ => eyre::bail!("`{cmd:?}` does not change the session"),
+        };
+        Ok(AppliedCommand { entry, message, warnings })
+    }
+
+    /// Appends an input to a copy of a session's source, applying any redefinitions and
+    /// switching to a solc version that satisfies any newly entered `pragma solidity` directive.
+    /// Inputs entered into the REPL and inputs replayed from the session's journal are both
+    /// prepared here.
+    ///
+    /// ### Takes
+    ///
+    /// - The [SessionSource] to append the input to
+    /// - The raw input
+    /// - The [RedefinitionPolicy] of the session
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the new [SessionSource], whether the input must be executed, and warnings
+    /// about the input. Fails if the input can not be parsed, uses a cheatcode that can not take
+    /// effect within a REPL session, or redefines an item against the policy.
+    pub fn prepare_input(
+        source: &SessionSource,
+        input: &str,
+        redefinition_policy: RedefinitionPolicy,
+    ) -> eyre::Result<(SessionSource, bool, Vec<String>)> {
+        // Malformed input is never appended; Report the parser's diagnostics instead
+        let (mut new_source, do_execute) = source.clone_with_new_line(input.to_string())?;
+
+        // Cheatcodes that can not take effect within a REPL session are rejected up front
+        if do_execute {
+            check_cheatcodes(input)?;
+        }
+
+        // Items that the input defines again replace or conflict with their earlier definition
+        let redefinitions =
+            new_source.apply_redefinitions(source.snippets.len(), redefinition_policy)?;
+
+        // Let the user know if the input forced the REPL contract to be renamed, or replaced
+        // earlier definitions
+        let mut warnings = redefinitions
+            .iter()
+            .map(|r| format!("Redefined `{}`, replacing snippet {}", r.name, r.index))
+            .collect::<Vec<_>>();
+
+        // Switch to a solc version that satisfies any newly entered `pragma solidity` directive.
+        // If there is none, the source fails to compile with the conflicting pragmas.
+        match new_source.select_solc_version() {
+            Ok(Some(version)) => warnings.push(format!(
+                "Switched to solc {version} to satisfy the session's `pragma solidity` directives"
+            )),
+            Ok(None) => {}
+            Err(e) => warnings.push(format!("Warning: {e}")),
+        }
+        if new_source.contract_name != source.contract_name {
+            warnings.push(format!(
+                "Warning: `{}` is defined within the session; Renamed the REPL contract to `{}`",
+                source.contract_name, new_source.contract_name
+            ));
+        }
+
+        Ok((new_source, do_execute, warnings))
+    }
+
+    /// Replays an input from a session's journal, without executing it. See
+    /// [ChiselSession::recover].
+    ///
+    /// ### Takes
+    ///
+    /// - The [ChiselSession] to replay the input on
+    /// - The journaled input, which is either a command or code
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, nothing. Fails if the input is rejected, just as it would be if it was
+    /// entered into the REPL.
+    pub async fn replay_input(session: &mut ChiselSession, input: &str) -> eyre::Result<()> {
+        if input.starts_with(COMMAND_LEADER) {
+            let split: Vec<&str> = input.split(' ').collect();
+            let cmd = split[0][1..].parse::<ChiselCommand>().map_err(|e| eyre::eyre!("{e}"))?;
+            Self::apply_command(session, &cmd, &split[1..], true).await?;
+            return Ok(())
+        }
+
+        let source =
+            session.session_source.as_mut().ok_or(eyre::eyre!("Session source not present!"))?;
+        let (mut new_source, do_execute, _) =
+            Self::prepare_input(source, input, session.redefinition_policy)?;
+        // Statements are snapshotted automatically, which the IDs of the snapshots taken with
+        // `!snapshot` depend on
+        if do_execute {
+            new_source.push_snapshot(source.snippets.clone(), Vec::new(), true);
+        }
+        *source = new_source;
+        session.dirty = true;
+        Ok(())
+    }
+
+    /// Describes a failed execution of a session source
+    ///
+    /// ### Takes
+    ///
+    /// - The [SessionSource] that was executed
+    /// - The [ChiselResult] of the execution
+    /// - What was executed, i.e. `REPL contract`
+    ///
+    /// ### Returns
+    ///
+    /// A message containing the decoded revert reason. Custom errors are decoded with the ABI
+    /// of the REPL contract.
+    fn describe_revert(source: &SessionSource, res: &ChiselResult, what: &str) -> String {
+        let abi = source.artifact().and_then(|artifact| artifact.abi);
+        match res.revert_reason(abi.as_ref()) {
+            Some(reason) => format!("Failed to execute {what}: {reason}"),
+            None => format!("Failed to execute {what}!"),
+        }
+    }
 }
@@ -2,22 +2,42 @@
 //!
 //! This module contains the execution logic for the [SessionSource].
 
-use crate::prelude::{
-    ChiselDispatcher, ChiselResult, ChiselRunner, IntermediateOutput, SessionSource,
+use crate::{
+    format::type_name,
+    native::{evaluate, NATIVE_BUILTINS},
+    prelude::{
+        CallGas, ChiselDispatcher, ChiselResult, ChiselRunner, DecodedLog, DecodedValue,
+        GasProfile, GeneratedOutput, IntermediateOutput, MachineState, ParseTreeFragment,
+        PersistedState, PlannedTransaction, RevertReason, SessionSource, SolSnippet, StateAccesses,
+        StateDiff, Step, StepDebugger, StorageDecoder, SymbolKind, TransactionKind,
+    },
+    runner::RUN_SELECTOR,
+    session_source::link_repl_contract,
+    storage::parse_storage_path,
 };
 use core::fmt::Debug;
 use ethers::{
     abi::{ethabi, ParamType, Token},
-    types::{Address, I256, U256},
+    types::{Address, Bytes, Log, I256, U256},
     utils::hex,
 };
-use ethers_solc::Artifact;
+use ethers_solc::{artifacts::StorageLayout, Artifact};
 use eyre::{Result, WrapErr};
 use forge::{
     decode::decode_console_logs,
-    executor::{inspector::CheatsConfig, Backend, ExecutorBuilder},
+    executor::{
+        inspector::CheatsConfig, Backend, ExecutorBuilder, CHEATCODE_ADDRESS,
+        HARDHAT_CONSOLE_ADDRESS,
+    },
+    CallKind,
 };
+use revm::{opcode, DatabaseRef, OpCode, Return};
 use solang_parser::pt::{self, CodeLocation};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+    time::Instant,
+};
 use yansi::Paint;
 
 /// Executor implementation for [SessionSource]
@@ -28,88 +48,464 @@ impl SessionSource {
     ///
     /// Optionally, a tuple containing the [Address] of the deployed REPL contract as well as
     /// the [ChiselResult].
+    ///
+    /// ### Notes
+    ///
+    /// If the source extends the snippets of its [SessionSource::persisted] state, only its new
+    /// statements are executed against that state. Otherwise, the session is replayed from a
+    /// clean state. The persisted state is only replaced if the execution succeeds.
     pub async fn execute(&mut self) -> Result<(Address, ChiselResult)> {
+        let (_, address, result) = self.execute_source().await?;
+        Ok((address, result))
+    }
+
+    /// Runs the source with the [ChiselRunner], see [SessionSource::execute]
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the copy of the source that was compiled and executed in its place, see
+    /// [SessionSource::executable_source], the [Address] of the deployed REPL contract, and the
+    /// [ChiselResult].
+    pub(crate) async fn execute_source(
+        &mut self,
+    ) -> Result<(SessionSource, Address, ChiselResult)> {
         // Recompile the project and ensure no errors occurred.
         let compiled = self.build()?;
-        if let Some((_, contract)) =
-            compiled.compiler_output.contracts_into_iter().find(|(name, _)| name.eq(&"REPL"))
+        if !compiled
+            .compiler_output
+            .contracts_into_iter()
+            .any(|(name, _)| name == self.contract_name)
         {
-            // These *should* never panic after a successful compilation.
-            let bytecode = contract.get_bytecode_bytes().expect("No bytecode for contract.");
-            let deployed_bytecode =
-                contract.get_deployed_bytecode_bytes().expect("No deployed bytecode for contract.");
-
-            // Fetch the run function's body statement
-            let run_func_statements = compiled.intermediate.run_func_body()?;
-
-            // Find the last statement within the "run()" method and get the program
-            // counter via the source map.
-            if let Some(final_statement) = run_func_statements.last() {
-                // If the final statement is some type of block (assembly, unchecked, or regular),
-                // we need to find the final statement within that block. Otherwise, default to
-                // the source loc of the final statement of the `run()` function's block.
-                //
-                // There is some code duplication within the arms due to the difference between
-                // the [pt::Statement] type and the [pt::YulStatement] types.
-                let source_loc = match final_statement {
-                    pt::Statement::Assembly { loc: _, dialect: _, flags: _, block } => {
-                        if let Some(statement) = block.statements.last() {
-                            statement.loc()
-                        } else {
-                            // In the case where the block is empty, attempt to grab the statement
-                            // before the asm block. Because we use saturating sub to get the second
-                            // to last index, this can always be safely unwrapped.
-                            run_func_statements
-                                .get(run_func_statements.len().saturating_sub(2))
-                                .unwrap()
-                                .loc()
-                        }
-                    }
-                    pt::Statement::Block { loc: _, unchecked: _, statements } => {
-                        if let Some(statement) = statements.last() {
-                            statement.loc()
-                        } else {
-                            // In the case where the block is empty, attempt to grab the statement
-                            // before the block. Because we use saturating sub to get the second to
-                            // last index, this can always be safely unwrapped.
-                            run_func_statements
-                                .get(run_func_statements.len().saturating_sub(2))
-                                .unwrap()
-                                .loc()
-                        }
-                    }
-                    _ => final_statement.loc(),
-                };
+            eyre::bail!("Failed to find REPL contract!")
+        }
+
+        // Return a default result if no statements are present.
+        if compiled.intermediate.run_func_body()?.is_empty() {
+            return Ok((self.clone(), Address::zero(), ChiselResult::default()))
+        }
+
+        // The persisted state is put back unless the execution succeeds and replaces it
+        let persisted = self.persisted.take();
+        match self.execute_against(persisted.as_ref()).await {
+            Ok((source, address, result, Some(mut state))) => {
+                // Only the state before the last execution is kept to re-execute it against
+                state.previous = persisted.filter(|_| !result.replayed).map(|mut previous| {
+                    previous.previous = None;
+                    Box::new(previous)
+                });
+                self.persisted = Some(state);
+                Ok((source, address, result))
+            }
+            Ok((source, address, result, None)) => {
+                self.persisted = persisted;
+                Ok((source, address, result))
+            }
+            Err(err) => {
+                self.persisted = persisted;
+                Err(err)
+            }
+        }
+    }
+
+    /// Executes the source against a persisted state, or replays it from a clean state if it
+    /// can not resume the persisted state, see [SessionSource::can_resume]
+    ///
+    /// ### Takes
+    ///
+    /// The state that the most recent successful execution of the source left behind, if any
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the executed copy of the source, the [Address] of the deployed REPL contract,
+    /// the [ChiselResult], and the [PersistedState] that the execution left behind if it
+    /// succeeded.
+    async fn execute_against(
+        &mut self,
+        persisted: Option<&PersistedState>,
+    ) -> Result<(SessionSource, Address, ChiselResult, Option<PersistedState>)> {
+        // The libraries of a resumed state are already deployed, so the REPL contract must still
+        // be linked against the same ones. A profiled execution is not instrumented, so that only
+        // the code of the statement is counted.
+        let instrument = !self.config.profile;
+        let resumed = persisted.filter(|state| self.can_resume(state)).and_then(|state| {
+            let source = self.executable_source(Some(state), instrument).ok()?;
+            (source.libraries == state.libraries).then_some((state, source))
+        });
+        let (resumed, mut executable) = match resumed {
+            Some((state, source)) => (Some(state), source),
+            None => {
+                let source = self
+                    .executable_source(None, instrument)
+                    .or_else(|_| self.executable_source(None, false))?;
+                (None, source)
+            }
+        };
+        let profile = if self.config.profile {
+            let last = self
+                .snippets
+                .iter()
+                .rposition(|snippet| snippet.kind.is_executable())
+                .map(|position| self.snippet_index_of(position));
+            Some(executable.source.snippet_pcs(last)?)
+        } else {
+            None
+        };
+        let contract = executable
+            .compiled
+            .compiler_output
+            .contracts_iter()
+            .find(|(name, _)| **name == executable.source.contract_name)
+            .map(|(_, contract)| contract)
+            .ok_or_else(|| eyre::eyre!("Failed to find REPL contract!"))?;
 
-                // Map the source location of the final statement of the `run()` function to its
-                // corresponding runtime program counter
-                let final_pc = {
-                    let offset = source_loc.start();
-                    let length = source_loc.end() - source_loc.start();
-                    contract
-                        .get_source_map_deployed()
+        // Fetch the run function's body statement, without the statement that returns the
+        // measured gas and the captured values
+        let mut run_func_statements = executable.compiled.intermediate.run_func_body()?;
+        if executable.instrumented {
+            run_func_statements.pop();
+        }
+
+        // Find the last statement within the "run()" method and get the program
+        // counter via the source map.
+        //
+        // If the final statement is some type of block (assembly, unchecked, or regular),
+        // we need to find the final statement within that block. Otherwise, default to
+        // the source loc of the final statement of the `run()` function's block.
+        //
+        // There is some code duplication within the arms due to the difference between
+        // the [pt::Statement] type and the [pt::YulStatement] types.
+        let source_loc = run_func_statements.last().map(|final_statement| match final_statement {
+            pt::Statement::Assembly { loc: _, dialect: _, flags: _, block } => {
+                if let Some(statement) = block.statements.last() {
+                    statement.loc()
+                } else {
+                    // In the case where the block is empty, attempt to grab the statement
+                    // before the asm block. Because we use saturating sub to get the second
+                    // to last index, this can always be safely unwrapped.
+                    run_func_statements
+                        .get(run_func_statements.len().saturating_sub(2))
                         .unwrap()
+                        .loc()
+                }
+            }
+            pt::Statement::Block { loc: _, unchecked: _, statements } => {
+                if let Some(statement) = statements.last() {
+                    statement.loc()
+                } else {
+                    // In the case where the block is empty, attempt to grab the statement
+                    // before the block. Because we use saturating sub to get the second to
+                    // last index, this can always be safely unwrapped.
+                    run_func_statements
+                        .get(run_func_statements.len().saturating_sub(2))
                         .unwrap()
-                        .into_iter()
-                        .zip(InstructionIter::new(&deployed_bytecode))
-                        .filter(|(s, _)| s.offset == offset && s.length == length)
-                        .map(|(_, i)| i.pc)
-                        .max()
-                        .unwrap_or_default()
-                };
+                        .loc()
+                }
+            }
+            _ => final_statement.loc(),
+        });
 
-                // Create a new runner
-                let mut runner = self.prepare_runner(final_pc).await;
+        // Map the source location of the final statement of the `run()` function to its
+        // corresponding runtime program counter
+        let source_map = contract.get_source_map_deployed().unwrap().unwrap();
+        let final_pc = source_loc
+            .and_then(|source_loc| {
+                let offset = source_loc.start();
+                let length = source_loc.end() - source_loc.start();
+                source_map
+                    .iter()
+                    .zip(InstructionIter::new(&executable.deployed_bytecode))
+                    .filter(|(s, _)| s.offset == offset && s.length == length)
+                    .map(|(_, i)| i.pc)
+                    .max()
+            })
+            .unwrap_or_default();
 
-                // Return [ChiselResult] or bubble up error
-                runner.run(bytecode.into_owned())
-            } else {
-                // Return a default result if no statements are present.
-                Ok((Address::zero(), ChiselResult::default()))
+        // Run the new statements against the persisted state, or deploy the REPL contract to a
+        // clean state
+        let deadline = Instant::now() + self.limits.timeout;
+        let (address, mut result, executor) = match resumed {
+            Some(state) => {
+                let mut runner = self.resume_runner(state, final_pc, deadline);
+                runner.profile = profile;
+                runner.executor.set_code(state.address, executable.deployed_bytecode.0.clone())?;
+                let result = runner.run(state.address)?;
+                (state.address, result, runner.executor)
             }
+            None => {
+                let mut runner = self.prepare_runner(final_pc, deadline).await?;
+                runner.profile = profile;
+                let address =
+                    runner.deploy(executable.libraries.clone(), executable.bytecode.clone())?;
+                let result = runner.run(address)?;
+                (address, result, runner.executor)
+            }
+        };
+        result.replayed = resumed.is_none();
+
+        // The gas of the statements that were executed before is the gas they used back then,
+        // rather than that of the code which restored their variables
+        let mut gas_per_snippet =
+            resumed.map(|state| state.gas_per_snippet.clone()).unwrap_or_default();
+        gas_per_snippet.extend(executable.gas_per_snippet(&result.returned));
+        result.gas_per_snippet = gas_per_snippet.clone().into_iter().collect();
+
+        // Decode the logs and the revert reason by the events and errors of the session's
+        // contracts and interfaces
+        result.decoded_logs = self.decode_logs(&result.logs);
+        if !result.success {
+            let errors = self
+                .known_contracts()
+                .values()
+                .flat_map(|(abi, _)| abi.errors().cloned())
+                .collect::<Vec<_>>();
+            // Running out of gas at the top level means that the execution exceeded one of
+            // its limits, as the timeout halts execution by running out of gas as well
+            result.revert = Some(match result.exit_reason {
+                Some(Return::OutOfGas) if result.timed_out => {
+                    RevertReason::TimeLimitExceeded(self.limits.timeout)
+                }
+                Some(Return::OutOfGas) => RevertReason::GasLimitExceeded(self.limits.gas_limit),
+                _ => RevertReason::decode(&result.returned, &errors, result.exit_reason),
+            });
+        }
+
+        // A resumed execution only emits the logs of the new statements
+        if result.replayed {
+            self.logs = result.logs.clone();
         } else {
-            eyre::bail!("Failed to find REPL contract!")
+            self.logs.extend(result.logs.iter().cloned());
+        }
+        if self.config.retain_state {
+            self.machine_state = self.output_checksum.clone().zip(result.machine_state());
+        }
+
+        // The forked network failed to serve an account or storage slot that was accessed
+        if result.exit_reason == Some(Return::FatalExternalError) {
+            if let Some(fork) = &self.config.fork {
+                let account = result.last_access.map(|access| (access, true)).or_else(|| {
+                    result
+                        .traces
+                        .iter()
+                        .flat_map(|(_, arena)| arena.arena.iter())
+                        .last()
+                        .map(|node| ((node.trace.address, None), false))
+                });
+                let target = match account {
+                    Some(((account, Some(slot)), _)) => {
+                        format!("storage slot {slot:#x} of {account:?}")
+                    }
+                    Some(((account, None), true)) => format!("the account {account:?}"),
+                    Some(((account, None), false)) => {
+                        format!("the account or storage of {account:?}")
+                    }
+                    None => String::from("state"),
+                };
+                eyre::bail!(
+                    "Failed to fetch {target} from the forked network {fork}. Is the RPC endpoint reachable?"
+                )
+            }
+        }
+
+        let state = result.success.then(|| PersistedState {
+            executor,
+            address,
+            snippets: self.snippets.clone(),
+            libraries: executable.libraries.clone(),
+            locals: executable.locals(&result.returned),
+            gas_per_snippet,
+            fork: self.config.fork.clone(),
+            evm_version: self.config.foundry_config.evm_version,
+            env_overrides: self.env_overrides.clone(),
+            previous: None,
+        });
+        Ok((executable.source, address, result, state))
+    }
+
+    /// Decodes logs by the events of the session's contracts and interfaces, as of the most
+    /// recent compile of the source
+    ///
+    /// ### Takes
+    ///
+    /// The raw logs, i.e. the logs of the session, see [SessionSource::logs]
+    ///
+    /// ### Returns
+    ///
+    /// The [DecodedLog]s, in order
+    pub fn decode_logs(&self, logs: &[Log]) -> Vec<DecodedLog> {
+        let events = self
+            .known_contracts()
+            .values()
+            .flat_map(|(abi, _)| abi.events().cloned())
+            .collect::<Vec<_>>();
+        logs.iter().map(|log| DecodedLog::decode(log, &events)).collect()
+    }
+
+    /// Whether an execution of the source can resume from a persisted state, rather than
+    /// replaying the session from a clean state
+    ///
+    /// ### Takes
+    ///
+    /// The state that the most recent successful execution of the source left behind
+    ///
+    /// ### Returns
+    ///
+    /// `true` if the snippets that produced the state are a prefix of the source's, the new
+    /// snippets do not initialize state upon deployment, the variables of the state were
+    /// captured, and the network, EVM version, and environment overrides are unchanged. New state
+    /// variables are placed after those of the persisted state, so none of them move.
+    fn can_resume(&self, state: &PersistedState) -> bool {
+        state.locals.is_some() &&
+            self.extends(state) &&
+            !self.snippets[state.snippets.len()..].iter().any(SolSnippet::initializes_state)
+    }
+
+    /// Whether the snippets that produced a persisted state are a prefix of the source's, in an
+    /// unchanged network, EVM version, and environment
+    fn extends(&self, state: &PersistedState) -> bool {
+        let same_code = |(snippet, executed): (&SolSnippet, &SolSnippet)| {
+            snippet.fragment == executed.fragment &&
+                snippet.code == executed.code &&
+                snippet.continued == executed.continued
+        };
+        state.fork == self.config.fork &&
+            state.evm_version == self.config.foundry_config.evm_version &&
+            state.env_overrides == self.env_overrides &&
+            self.snippets.len() >= state.snippets.len() &&
+            self.snippets.iter().zip(&state.snippets).all(same_code)
+    }
+
+    /// Compiles the copy of the source that is executed in its place
+    ///
+    /// ### Takes
+    ///
+    /// - The persisted state to resume, whose snippets are a prefix of the source's, or `None` to
+    ///   replay the session
+    /// - Whether to instrument the "run()" function, to measure the gas used by each new statement
+    ///   and capture the values of the variables declared within it at its end, see
+    ///   [PersistedState::locals]
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the compiled and linked [ExecutableSource], in which the statements of the
+    /// persisted state only declare the variables that they declared, with their captured
+    /// values. Fails if the copy does not compile, or if a persisted state is resumed but the
+    /// source was modified outside of its snippets.
+    ///
+    /// ### Notes
+    ///
+    /// The gas of a statement is measured by storing `gasleft()` before it and after it. Two
+    /// probes in a row measure the cost of a probe itself, which is subtracted, see
+    /// [ExecutableSource::gas_per_snippet].
+    fn executable_source(
+        &self,
+        state: Option<&PersistedState>,
+        instrument: bool,
+    ) -> Result<ExecutableSource> {
+        let mut source = self.clone();
+        let (mut captured, mut probes, mut instrumented) = (None, Vec::new(), false);
+        if state.is_some() || instrument {
+            if !self.is_rendered_from_snippets() {
+                eyre::bail!("The session source has been modified outside of its snippets!")
+            }
+            let locals = state.and_then(|state| state.locals.as_ref());
+            let executed = state.map_or(0, |state| state.snippets.len());
+            let (mut names, mut encodable) = (Vec::new(), true);
+            let mut snippets = Vec::with_capacity(self.snippets.len() + 1);
+            for (position, snippet) in self.snippets.iter().enumerate() {
+                let variables = snippet.local_variables();
+                encodable &= variables.iter().all(|variable| variable.encodable);
+                names.extend(variables.iter().map(|variable| variable.name.clone()));
+                if snippet.fragment != ParseTreeFragment::Function {
+                    snippets.push(snippet.clone());
+                    continue
+                }
+
+                // Each new statement is preceded by a probe, the first one by the calibrating
+                // probe as well
+                if position >= executed {
+                    if !instrument {
+                        snippets.push(snippet.clone());
+                        continue
+                    }
+                    let mut code = String::new();
+                    if probes.is_empty() {
+                        code.push_str(&format!(
+                            "uint256[] memory {GAS} = new uint256[]({});\n{GAS}[0] = gasleft();\n",
+                            self.snippets[position..]
+                                .iter()
+                                .filter(|snippet| snippet.fragment == ParseTreeFragment::Function)
+                                .count() +
+                                2
+                        ));
+                    }
+                    probes.push(self.snippet_index_of(position));
+                    code.push_str(&format!("{GAS}[{}] = gasleft();\n", probes.len()));
+                    code.push_str(&snippet.code);
+                    snippets.push(SolSnippet { code, ..snippet.clone() });
+                    continue
+                }
+
+                let mut restored = String::new();
+                for variable in variables {
+                    let value = locals
+                        .and_then(|locals| locals.get(&variable.name))
+                        .ok_or_else(|| eyre::eyre!("`{}` was not captured", variable.name))?;
+                    restored.push_str(&format!(
+                        "{} {} = abi.decode(hex\"{}\", ({}));\n",
+                        variable.declaration,
+                        variable.name,
+                        hex::encode(value),
+                        variable.ty
+                    ));
+                }
+                snippets.push(SolSnippet { code: restored, ..snippet.clone() });
+            }
+
+            // The measured gas and the captured values are returned by the "run()" function,
+            // ABI-encoded as a `uint256[]` and a `bytes[]`. If a variable can not be ABI-encoded,
+            // i.e. a reference to storage, none are captured, so that the session is replayed
+            // from then on.
+            let capture = instrument && encodable;
+            instrumented = !probes.is_empty() || (capture && !names.is_empty());
+            if instrumented {
+                let captured = if capture { names.as_slice() } else { &[] };
+                let mut code = String::from("{\n");
+                if probes.is_empty() {
+                    code.push_str(&format!("uint256[] memory {GAS};\n"));
+                } else {
+                    code.push_str(&format!("{GAS}[{}] = gasleft();\n", probes.len() + 1));
+                }
+                code.push_str(&format!(
+                    "bytes[] memory {LOCALS} = new bytes[]({});\n",
+                    captured.len()
+                ));
+                for (index, name) in captured.iter().enumerate() {
+                    code.push_str(&format!("{LOCALS}[{index}] = abi.encode({name});\n"));
+                }
+                code.push_str(&format!(
+                    "bytes memory {OUTPUT} = abi.encode({GAS}, {LOCALS});\nassembly {{\nreturn(add({OUTPUT}, 0x20), mload({OUTPUT}))\n}}\n}}\n"
+                ));
+                snippets.push(SolSnippet::new(ParseTreeFragment::Function, code));
+            }
+            if snippets != self.snippets {
+                source.replace_snippets(snippets)?;
+            }
+            captured = Some(names).filter(|_| capture);
         }
+
+        let compiled = source.build()?;
+        let (libraries, bytecode, deployed_bytecode) =
+            link_repl_contract(&compiled.compiler_output, &source.contract_name)?;
+        Ok(ExecutableSource {
+            source,
+            captured,
+            probes,
+            instrumented,
+            compiled,
+            libraries: libraries.into_iter().map(|(_, library)| library).collect(),
+            bytecode,
+            deployed_bytecode,
+        })
     }
 
     /// Inspect a contract element inside of the current session
@@ -131,6 +527,7 @@ impl SessionSource {
         } else {
             return Ok(None)
         };
+        source.config.retain_state = true;
 
         let mut res = if let Ok((_, res)) = source.execute().await { res } else { return Ok(None) };
 
@@ -150,13 +547,25 @@ impl SessionSource {
                 self.infer_inner_expr_type(&source)
             };
 
-            let ty = if let Some(ty) =
-                ty_opt.and_then(|ty| ty.try_as_ethabi(&generated_output.intermediate))
-            {
-                ty
-            } else {
+            // The name of the variable's type, to display the structs and enums that the session
+            // defines by their names
+            let formatter = source.value_formatter();
+            let type_name = generated_output
+                .intermediate
+                .repl_contract_expressions
+                .get(item)
+                .and_then(type_name);
+            let ty = match ty_opt.and_then(|ty| ty.try_as_ethabi(&generated_output.intermediate)) {
+                Some(ty) => ty,
+                // Enums defined at file level are not inferred, but are encoded as a `uint8`
+                None if type_name
+                    .as_ref()
+                    .map_or(false, |name| formatter.enums.contains_key(name)) =>
+                {
+                    ParamType::Uint(8)
+                }
                 // Move on gracefully; This type was denied for inspection.
-                return Ok(None)
+                None => return Ok(None),
             };
             let memory_offset = if let Some(offset) = stack.data().last() {
                 offset.as_usize()
@@ -170,10 +579,13 @@ impl SessionSource {
             let mut tokens = ethabi::decode(&[ty], data).wrap_err("Could not decode ABI")?;
 
             tokens.pop().map_or(Err(eyre::eyre!("No tokens decoded")), |token| {
-                Ok(Some(format_token(token)))
+                let ty = formatter.resolve(&ty, type_name.as_deref());
+                Ok(Some(formatter.inspection(&ty, &token)))
             })
         } else {
-            if let Ok(decoder) = ChiselDispatcher::decode_traces(&source.config, &mut res) {
+            if let Ok(decoder) =
+                ChiselDispatcher::decode_traces(&source.config, &mut res, &source.known_contracts())
+            {
                 if ChiselDispatcher::show_traces(&decoder, &mut res).await.is_err() {
                     eyre::bail!("Failed to display traces");
                 };
@@ -187,8 +599,668 @@ impl SessionSource {
                     }
                 }
             }
-            eyre::bail!("Failed to inspect expression")
+            match res.revert_reason(None) {
+                Some(reason) => eyre::bail!("Failed to inspect expression: {reason}"),
+                None => eyre::bail!("Failed to inspect expression"),
+            }
+        }
+    }
+
+    /// The machine state retained by the most recent execution of the source
+    ///
+    /// ### Returns
+    ///
+    /// The [MachineState], or `None` if no state was retained or the source was modified since
+    /// it was last executed. See [crate::session_source::SessionSourceConfig::retain_state].
+    pub fn retained_state(&self) -> Option<&MachineState> {
+        let checksum = self.compiler_input_checksum().ok()?;
+        self.machine_state
+            .as_ref()
+            .filter(|(executed, _)| *executed == checksum)
+            .map(|(_, state)| state)
+    }
+
+    /// Evaluates an expression natively, without compiling or executing the REPL contract
+    ///
+    /// ### Takes
+    ///
+    /// The expression to evaluate
+    ///
+    /// ### Returns
+    ///
+    /// The inspection of the value, if the expression only calls pure builtins over literals and
+    /// none of the builtins are shadowed by the session. See [crate::native::evaluate].
+    pub fn evaluate_natively(&self, item: &str) -> Option<String> {
+        let shadowed = self.defined_symbols().iter().any(|symbol| {
+            let name = symbol.name.split('(').next().unwrap_or_default();
+            NATIVE_BUILTINS.contains(&name)
+        });
+        if shadowed {
+            return None
+        }
+        let (ty, token) = evaluate(item)?;
+        let formatter = self.value_formatter();
+        Some(formatter.inspection(&formatter.resolve(&ty, None), &token))
+    }
+
+    /// Builds the session source and extracts the storage layout of the REPL contract
+    ///
+    /// ### Returns
+    ///
+    /// The storage layout that solc output for the REPL contract, which places every state
+    /// variable of the session. Variables that moved since the previous compile are collected in
+    /// [SessionSource::moved_variables].
+    pub fn storage_layout(&mut self) -> Result<StorageLayout> {
+        self.build()?;
+        self.storage_layout.clone().ok_or_else(|| eyre::eyre!("Failed to find REPL contract!"))
+    }
+
+    /// Reads the current value of a state variable of the session from the storage of the REPL
+    /// contract
+    ///
+    /// ### Takes
+    ///
+    /// The name of a state variable, optionally indexed by mapping keys and array indices, i.e.
+    /// `balances[0xabc]`
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the [DecodedValue] of the variable after executing the session. Fails if the
+    /// session does not define a state variable of the name, suggesting similar names of the
+    /// session's symbol table.
+    pub async fn inspect_storage(&mut self, path: &str) -> Result<DecodedValue> {
+        let (name, keys) = parse_storage_path(path)?;
+
+        let layout = self.storage_layout()?;
+        if !layout.storage.iter().any(|variable| variable.label == name) {
+            let symbols = self.defined_symbols();
+            if symbols
+                .iter()
+                .any(|symbol| symbol.kind == SymbolKind::Variable && symbol.name == name)
+            {
+                eyre::bail!(
+                    "`{name}` is declared within the \"run()\" function and is not stored. Enter `{name}` to print its value instead."
+                )
+            }
+            let candidates = symbols
+                .iter()
+                .filter(|symbol| {
+                    matches!(symbol.kind, SymbolKind::StateVariable | SymbolKind::Variable)
+                })
+                .map(|symbol| &symbol.name);
+            match foundry_cli::suggestions::did_you_mean(name, candidates).pop() {
+                Some(suggestion) => eyre::bail!(
+                    "`{name}` is not a state variable of the session. Did you mean `{suggestion}`?"
+                ),
+                None => eyre::bail!("`{name}` is not a state variable of the session"),
+            }
+        }
+
+        let state = self.current_state().await?;
+        StorageDecoder::new(&layout, state.executor.backend(), state.address).decode(name, &keys)
+    }
+
+    /// Reads the balance of an account after executing the session
+    ///
+    /// ### Takes
+    ///
+    /// The address of the account
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the balance of the account in wei. Fails if the session reverted.
+    pub async fn balance_of(&mut self, account: Address) -> Result<U256> {
+        let state = self.current_state().await?;
+        let info = DatabaseRef::basic(state.executor.backend(), account)
+            .map_err(|e| eyre::eyre!("Failed to read the balance of {account:?}: {e}"))?;
+        Ok(info.map(|info| info.balance).unwrap_or_default())
+    }
+
+    /// Executes a statement against a scratch copy of the session, to estimate the gas it would
+    /// use without adding it to the session
+    ///
+    /// ### Takes
+    ///
+    /// The statement to estimate
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the scratch copy of the source with the statement appended, and the
+    /// [ChiselResult] of its execution. Fails if the input is not a statement.
+    ///
+    /// ### Notes
+    ///
+    /// Only the statement is executed, on a clone of the executor of the session's persisted
+    /// state, so neither the source of the session nor its state are modified. The session is
+    /// only executed first if its persisted state is not current, see
+    /// [SessionSource::current_state].
+    pub async fn estimate(&self, statement: &str) -> Result<(SessionSource, ChiselResult)> {
+        let mut session = self.clone();
+        session.current_state().await?;
+        let (mut source, do_execute) = session.clone_with_new_line(statement.to_string())?;
+        if !do_execute {
+            eyre::bail!("Only statements can be estimated, as definitions are not executed")
+        }
+        let (_, result) = source.execute().await?;
+        Ok((source, result))
+    }
+
+    /// Executes a copy of the source with the step debugger, to step through its last statement
+    ///
+    /// ### Takes
+    ///
+    /// Optionally, a statement to append to the copy, i.e. one that reverted and was therefore
+    /// not added to the session
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, a [StepDebugger] over the instructions of the execution, starting at the last
+    /// statement. Fails if there is no statement to debug.
+    ///
+    /// ### Notes
+    ///
+    /// Only the last statement is executed, against the state before it, if that state is still
+    /// persisted. Otherwise, the statements before it run again and leave the same state behind.
+    /// The debugger shows the source that was executed, in which the statements before the last
+    /// one only declare the variables that they declared.
+    pub async fn debug(&self, statement: Option<&str>) -> Result<StepDebugger> {
+        let mut source = match statement {
+            Some(statement) => {
+                let (source, do_execute) = self.clone_with_new_line(statement.to_string())?;
+                if !do_execute {
+                    eyre::bail!("Only statements can be debugged, as definitions are not executed")
+                }
+                source
+            }
+            None => self.clone(),
+        };
+        let last = source
+            .snippets
+            .iter()
+            .rposition(|snippet| snippet.kind.is_executable())
+            .ok_or_else(|| eyre::eyre!("The session has no statements to debug"))?;
+        if statement.is_none() {
+            source.persisted = self.state_before(last);
+        }
+        source.config.debug = true;
+        let (mut executed, address, result) = source.execute_source().await?;
+        let steps = executed.steps(address, &result)?;
+        let (formatted, _) = executed.to_formatted_repl_source_with_map();
+        Ok(StepDebugger::new(steps, &formatted, Some(source.snippet_index_of(last))))
+    }
+
+    /// Executes the last statement of a copy of the source again, counting its instructions, to
+    /// profile where its gas went
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the [ChiselResult] of the execution, with its [GasProfile] set. Fails if the
+    /// session has no statement.
+    ///
+    /// ### Notes
+    ///
+    /// Only the last statement is executed, against the state before it, if that state is still
+    /// persisted. The instructions from the start of the statement on are counted, including
+    /// those of the contracts that it called, without being recorded.
+    pub async fn profile(&self) -> Result<ChiselResult> {
+        let mut source = self.clone();
+        let last = source
+            .snippets
+            .iter()
+            .rposition(|snippet| snippet.kind.is_executable())
+            .ok_or_else(|| eyre::eyre!("The session has no statements to profile"))?;
+        source.persisted = self.state_before(last);
+        source.config.profile = true;
+        let (_, _, mut result) = source.execute_source().await?;
+        let counts = result
+            .opcode_counts
+            .take()
+            .filter(|counts| !counts.opcodes.is_empty())
+            .ok_or_else(|| eyre::eyre!("No instructions of the last statement were counted"))?;
+
+        // The calls of the statement are the calls that the REPL contract made from its start
+        let functions = source
+            .known_contracts()
+            .values()
+            .flat_map(|(abi, _)| abi.functions().cloned().collect::<Vec<_>>())
+            .map(|function| {
+                let types = function.inputs.iter().map(|param| param.kind.to_string());
+                (
+                    function.short_signature(),
+                    format!("{}({})", function.name, types.collect::<Vec<_>>().join(",")),
+                )
+            })
+            .collect::<BTreeMap<_, _>>();
+        let calls = result
+            .traces
+            .iter()
+            .flat_map(|(_, arena)| {
+                let run = arena.arena.first().map(|node| node.children.as_slice());
+                run.unwrap_or_default()
+                    .iter()
+                    .skip(counts.calls_before as usize)
+                    .map(|child| &arena.arena[*child].trace)
+            })
+            .map(|trace| {
+                let data = trace.data.to_raw();
+                let function = data.get(..4).map(|selector| {
+                    let selector: [u8; 4] = selector.try_into().expect("4 bytes");
+                    functions
+                        .get(&selector)
+                        .cloned()
+                        .unwrap_or_else(|| format!("0x{}", hex::encode(selector)))
+                });
+                CallGas {
+                    address: trace.address,
+                    label: result
+                        .labeled_addresses
+                        .get(&trace.address)
+                        .cloned()
+                        .or_else(|| trace.label.clone()),
+                    function: if matches!(trace.kind, CallKind::Create | CallKind::Create2) {
+                        Some(String::from("constructor"))
+                    } else {
+                        function
+                    },
+                    count: 1,
+                    gas: trace.gas_cost,
+                }
+            })
+            .collect();
+
+        result.profile = Some(GasProfile::new(&counts, calls));
+        Ok(result)
+    }
+
+    /// Executes a copy of the source with every instruction recorded, to diff the state that its
+    /// last statement touched
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the [ChiselResult] of the execution, with its [StateDiff] set. Fails if the
+    /// session has no statement, or if the statements before the last one revert.
+    ///
+    /// ### Notes
+    ///
+    /// The state before the statement is the state that the statements before it leave behind,
+    /// so that only the changes of the statement itself are listed.
+    pub async fn state_diff(&self) -> Result<ChiselResult> {
+        let (mut source, last, address, mut result, steps, start) =
+            self.record_last_statement("diff").await?;
+        let accesses = StateAccesses::new(&steps[start..]);
+        let variables = source
+            .storage_layout()?
+            .storage
+            .into_iter()
+            .filter_map(|variable| Some((U256::from_dec_str(&variable.slot).ok()?, variable.label)))
+            .collect();
+
+        let current = source.persisted.as_ref().filter(|_| result.success).ok_or_else(|| {
+            eyre::eyre!("The last statement reverted, so its state can not be diffed")
+        })?;
+        let mut before;
+        let previous = match &current.previous {
+            Some(previous) => previous.executor.backend(),
+            None => {
+                before = self.clone();
+                before.persisted = None;
+                before.replace_snippets(self.snippets[..last].to_vec())?;
+                before.current_state().await?.executor.backend()
+            }
+        };
+        let diff = StateDiff::new(
+            &accesses,
+            previous,
+            current.executor.backend(),
+            address,
+            &result.labeled_addresses,
+            &variables,
+        )?;
+
+        result.state_diff = Some(diff);
+        Ok(result)
+    }
+
+    /// Executes a copy of the source with every instruction recorded, for the instructions of
+    /// its last statement
+    ///
+    /// ### Takes
+    ///
+    /// What the statement is recorded for, to explain an empty session, i.e. `diff`
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the copy of the source, the position of its last statement within
+    /// [SessionSource::snippets], the address of the
+    /// REPL contract, the [ChiselResult] of the execution, its [Step]s, and the index of the
+    /// first step of the last statement. Fails if the session has no statement.
+    ///
+    /// ### Notes
+    ///
+    /// Only the last statement is executed, against the state before it, if that state is still
+    /// persisted. See [SessionSource::debug].
+    async fn record_last_statement(
+        &self,
+        action: &str,
+    ) -> Result<(SessionSource, usize, Address, ChiselResult, Vec<Step>, usize)> {
+        let mut source = self.clone();
+        let last = source
+            .snippets
+            .iter()
+            .rposition(|snippet| snippet.kind.is_executable())
+            .ok_or_else(|| eyre::eyre!("The session has no statements to {action}"))?;
+        source.persisted = self.state_before(last);
+        source.config.debug = true;
+        let (mut executed, address, result) = source.execute_source().await?;
+        let steps = executed.steps(address, &result)?;
+        let index = source.snippet_index_of(last);
+        let start = steps
+            .iter()
+            .position(|step| step.snippet == Some(index))
+            .ok_or_else(|| eyre::eyre!("No instructions of the last statement were recorded"))?;
+        Ok((source, last, address, result, steps, start))
+    }
+
+    /// The persisted state that the source left behind before a snippet was executed
+    ///
+    /// ### Takes
+    ///
+    /// The position of the snippet within [SessionSource::snippets]
+    ///
+    /// ### Returns
+    ///
+    /// A copy of the state before the most recent execution, if it is still persisted and was
+    /// produced without the snippet
+    pub(crate) fn state_before(&self, position: usize) -> Option<PersistedState> {
+        let state = self.persisted.as_ref()?.previous.as_deref()?;
+        (state.snippets.len() <= position).then(|| state.clone())
+    }
+
+    /// Maps the recorded instructions of an execution of the source to the lines and snippets
+    /// of the formatted REPL contract
+    ///
+    /// ### Takes
+    ///
+    /// - The address of the deployed REPL contract
+    /// - The [ChiselResult] of an execution with the debugger enabled, see
+    ///   [crate::session_source::SessionSourceConfig::debug]
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the [Step]s of the execution, in order. Fails if no instructions were
+    /// recorded.
+    fn steps(&mut self, address: Address, result: &ChiselResult) -> Result<Vec<Step>> {
+        let arena =
+            result.debug.as_ref().ok_or_else(|| eyre::eyre!("No instructions were recorded"))?;
+        let lines = self.source_lines()?;
+        let (_, map) = self.to_formatted_repl_source_with_map();
+        let depth = arena.arena.first().map(|node| node.depth).unwrap_or_default();
+        Ok(arena
+            .arena
+            .iter()
+            .flat_map(|node| node.steps.iter().map(move |step| (node, step)))
+            .map(|(node, step)| {
+                let line =
+                    if node.address == address { lines.get(&step.pc).copied() } else { None };
+                Step {
+                    address: node.address,
+                    depth: node.depth.saturating_sub(depth),
+                    step: step.clone(),
+                    line,
+                    snippet: line.and_then(|line| {
+                        map.iter().find(|(lines, _)| lines.contains(&line)).map(|(_, index)| *index)
+                    }),
+                }
+            })
+            .collect())
+    }
+
+    /// Plans the transactions that broadcast statements of the source to a real network
+    ///
+    /// ### Takes
+    ///
+    /// The indices of the snippets to broadcast, each a statement
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the [PlannedTransaction]s, in order. Fails if a snippet is not a statement,
+    /// if its execution reverts against the session, if it calls a cheatcode, or if it can not
+    /// be deployed without the statements before it.
+    ///
+    /// ### Notes
+    ///
+    /// A statement that consists of a single external call is broadcast as that call, sent from
+    /// the signer. Any other statement is broadcast as a deployment of a REPL contract holding
+    /// the definitions of the session and only that statement, followed by a call to its
+    /// `run()` function, so that the statements before it are never sent again.
+    pub async fn plan_broadcast(&self, snippets: &[usize]) -> Result<Vec<PlannedTransaction>> {
+        let mut planned = Vec::new();
+        for &index in snippets {
+            self.check_snippet_index(index)?;
+            let parts = self.snippet_range(index).unwrap_or_default();
+            let statements = self.snippets[parts.clone()]
+                .iter()
+                .filter(|snippet| snippet.kind().is_executable())
+                .cloned()
+                .collect::<Vec<_>>();
+            if statements.is_empty() {
+                eyre::bail!("Snippet {index} is not a statement, only statements can be broadcast")
+            }
+
+            // Execute the session up to and including the statement, to check that it succeeds.
+            // It is replayed from a clean state, so that the calls of every statement up to the
+            // statement are traced.
+            let mut through = self.clone();
+            through.persisted = None;
+            through.replace_snippets(self.snippets[..parts.end].to_vec())?;
+            let (_, result) = through.execute().await?;
+            if !result.success {
+                let abi = through.artifact().and_then(|artifact| artifact.abi);
+                eyre::bail!(
+                    "Snippet {index} reverts against the session: {}",
+                    result.revert_reason(abi.as_ref()).unwrap_or_default()
+                )
+            }
+
+            // The calls of the statement are those that the statements before it did not make
+            let calls = Self::external_calls(&result);
+            let statement_calls: &[_] = match statements.as_slice() {
+                [statement] if statement.is_external_call() => {
+                    let mut before = self.clone();
+                    before.persisted = None;
+                    before.replace_snippets(self.snippets[..parts.start].to_vec())?;
+                    let previous = Self::external_calls(&before.execute().await?.1);
+                    &calls[previous.len().min(calls.len())..]
+                }
+                _ => &[],
+            };
+
+            match statement_calls {
+                [(to, ..)] if *to == CHEATCODE_ADDRESS => {
+                    eyre::bail!(
+                        "Snippet {index} calls a cheatcode, which does not exist on a real network"
+                    )
+                }
+                [(to, data, value)] => {
+                    planned.push(PlannedTransaction {
+                        snippet: index,
+                        kind: TransactionKind::Call,
+                        to: Some(*to),
+                        data: data.clone(),
+                        value: *value,
+                    });
+                }
+                _ if calls.iter().any(|(to, ..)| *to == CHEATCODE_ADDRESS) => {
+                    eyre::bail!("Snippet {index} can not be broadcast as the REPL contract, as the session calls cheatcodes, which do not exist on a real network")
+                }
+                _ => {
+                    // Only the statement itself is deployed, along with the definitions that it
+                    // may use
+                    let mut single = self.clone();
+                    single.persisted = None;
+                    let definitions = self.snippets[..parts.end]
+                        .iter()
+                        .filter(|snippet| !snippet.kind().is_executable())
+                        .cloned();
+                    single.replace_snippets(definitions.chain(statements).collect())?;
+                    let compiled = single.build().map_err(|e| {
+                        eyre::eyre!("Snippet {index} can not be broadcast on its own, as it depends on the statements before it: {e}")
+                    })?;
+                    let (_, result) = single.execute().await?;
+                    if !result.success {
+                        eyre::bail!("Snippet {index} can not be broadcast on its own, as it reverts without the statements before it")
+                    }
+                    let (libraries, bytecode, _) =
+                        link_repl_contract(&compiled.compiler_output, &single.contract_name)?;
+                    if !libraries.is_empty() {
+                        eyre::bail!("Snippet {index} can not be broadcast, as the REPL contract is linked against libraries")
+                    }
+                    planned.push(PlannedTransaction {
+                        snippet: index,
+                        kind: TransactionKind::Deployment,
+                        to: None,
+                        data: bytecode,
+                        value: U256::zero(),
+                    });
+                    planned.push(PlannedTransaction {
+                        snippet: index,
+                        kind: TransactionKind::Run,
+                        to: None,
+                        data: Bytes::from(RUN_SELECTOR),
+                        value: self.env_overrides.value.unwrap_or_default(),
+                    });
+                }
+            }
+        }
+        Ok(planned)
+    }
+
+    /// Collects the calls that the `run()` function of the REPL contract made to other
+    /// contracts, excluding those to `console.log`
+    ///
+    /// ### Takes
+    ///
+    /// The [ChiselResult] of an execution
+    ///
+    /// ### Returns
+    ///
+    /// The recipient, calldata, and value of each call, in order
+    fn external_calls(result: &ChiselResult) -> Vec<(Address, Bytes, U256)> {
+        result
+            .traces
+            .iter()
+            .flat_map(|(_, arena)| {
+                let run = arena.arena.first().map(|node| node.children.as_slice());
+                run.unwrap_or_default().iter().map(|child| &arena.arena[*child].trace)
+            })
+            .filter(|trace| !matches!(trace.kind, CallKind::StaticCall))
+            .filter(|trace| trace.address != HARDHAT_CONSOLE_ADDRESS)
+            .map(|trace| (trace.address, Bytes::from(trace.data.to_raw()), trace.value))
+            .collect()
+    }
+
+    /// Maps the instructions of the REPL contract's deployed bytecode to the lines of the
+    /// formatted REPL contract that they were compiled from
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the line of each instruction that the source map attributes to the REPL
+    /// contract's source file, by program counter
+    fn source_lines(&mut self) -> Result<BTreeMap<usize, usize>> {
+        let compiled = self.build()?;
+        let (_, _, deployed_bytecode) =
+            link_repl_contract(&compiled.compiler_output, &self.contract_name)?;
+        let file_id = compiled
+            .compiler_output
+            .sources
+            .iter()
+            .find(|(path, _)| Path::new(path) == self.file_name)
+            .map(|(_, file)| file.id);
+        let source_map = compiled
+            .compiler_output
+            .contracts_into_iter()
+            .find(|(name, _)| name == &self.contract_name)
+            .and_then(|(_, contract)| contract.get_source_map_deployed())
+            .ok_or_else(|| eyre::eyre!("Failed to find the source map of the REPL contract!"))?
+            .map_err(|err| {
+                eyre::eyre!("Failed to parse the source map of the REPL contract: {err}")
+            })?;
+
+        // The compiled source is formatted, so its lines are the lines of the formatted source
+        let (source, _) = self.to_formatted_repl_source_with_map();
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(offset, _)| offset + 1))
+            .collect::<Vec<_>>();
+        Ok(source_map
+            .iter()
+            .zip(InstructionIter::new(&deployed_bytecode))
+            .filter(|(element, _)| file_id.is_some() && element.index == file_id)
+            .map(|(element, instruction)| {
+                (instruction.pc, line_starts.partition_point(|start| *start <= element.offset))
+            })
+            .collect())
+    }
+
+    /// The program counters of the REPL contract's instructions that were compiled from a snippet
+    ///
+    /// ### Takes
+    ///
+    /// The index of the snippet, see [SessionSource::snippet_index_of]
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the program counters. Empty if there is no snippet. Fails if the source does
+    /// not compile.
+    fn snippet_pcs(&mut self, index: Option<usize>) -> Result<BTreeSet<usize>> {
+        let lines = self.source_lines()?;
+        let (_, map) = self.to_formatted_repl_source_with_map();
+        Ok(map
+            .iter()
+            .filter(|(_, snippet)| Some(*snippet) == index)
+            .flat_map(|(snippet_lines, _)| {
+                lines.iter().filter(|(_, line)| snippet_lines.contains(line)).map(|(pc, _)| *pc)
+            })
+            .collect())
+    }
+
+    /// The state that the source leaves behind, executing the source unless its persisted state
+    /// is current
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the [PersistedState] of the source's snippets. Fails if the session reverted.
+    pub async fn current_state(&mut self) -> Result<&PersistedState> {
+        let current = self.persisted.as_ref().map_or(false, |state| {
+            state.snippets.len() == self.snippets.len() &&
+                self.extends(state) &&
+                self.is_rendered_from_snippets()
+        });
+        if !current {
+            // The REPL contract is only deployed if the session has a statement to execute, so
+            // execute an empty one to read the initial state
+            let mut source = if self.run_code.trim().is_empty() {
+                self.clone_with_new_line(String::from("assembly {}"))?.0
+            } else {
+                self.clone()
+            };
+            let (_, result) = source.execute().await?;
+            if !result.success {
+                let abi = source.artifact().and_then(|artifact| artifact.abi);
+                eyre::bail!(
+                    "The session reverted, so its state can not be inspected: {}",
+                    result.revert_reason(abi.as_ref()).unwrap_or_default()
+                )
+            }
+
+            // The empty statement leaves the same state behind as the source's snippets
+            let mut state =
+                source.persisted.ok_or_else(|| eyre::eyre!("The session was not executed"))?;
+            state.snippets = self.snippets.clone();
+            state.gas_per_snippet.retain(|index, _| *index < self.snippet_count());
+            self.logs = source.logs;
+            self.persisted = Some(state);
         }
+        self.persisted.as_ref().ok_or_else(|| eyre::eyre!("The session was not executed"))
     }
 
     /// Gracefully attempts to extract the type of the expression within the `abi.encode(...)`
@@ -222,134 +1294,220 @@ impl SessionSource {
     ///
     /// ### Takes
     ///
-    /// The final statement's program counter for the [ChiselInspector]
+    /// - The final statement's program counter for the [ChiselInspector]
+    /// - The wall-clock deadline that the execution is halted at
     ///
     /// ### Returns
     ///
-    /// A configured [ChiselRunner]
-    async fn prepare_runner(&mut self, final_pc: usize) -> ChiselRunner {
-        let env = self.config.evm_opts.evm_env().await;
+    /// Optionally, a configured [ChiselRunner]. Fails if the forked network can not be reached.
+    async fn prepare_runner(&mut self, final_pc: usize, deadline: Instant) -> Result<ChiselRunner> {
+        let evm_opts = self.config.fork_evm_opts()?;
+        let mut env = match &evm_opts.fork_url {
+            Some(fork_url) => evm_opts.fork_evm_env(fork_url).await?,
+            None => evm_opts.local_evm_env(),
+        };
 
-        // Create an in-memory backend
-        let backend = self.config.backend.take().unwrap_or_else(|| {
-            let backend = Backend::spawn(
-                self.config.evm_opts.get_fork(&self.config.foundry_config, env.clone()),
-            );
-            self.config.backend = Some(backend.clone());
-            backend
-        });
+        // Create an in-memory backend, which is kept untouched so that each replay of the session
+        // starts from a copy of the same state
+        let backend = match &self.config.backend {
+            Some(backend) => backend.clone(),
+            None => {
+                let backend =
+                    Backend::spawn(evm_opts.get_fork(&self.config.foundry_config, env.clone()));
+                self.config.backend = Some(backend.clone());
+                backend
+            }
+        };
 
-        // Build a new executor
-        let executor = ExecutorBuilder::default()
+        // The overrides only apply to the executor, the fork's state is read at its own block
+        self.env_overrides.apply(&mut env);
+        let (caller, value) = (env.tx.caller, env.tx.value);
+
+        // Build a new executor. The machine state at the final statement is only captured when it
+        // is retained or inspected, and the state that is accessed is tracked on a fork to name
+        // what the network failed to serve.
+        let mut builder = ExecutorBuilder::default()
             .with_config(env)
-            .with_chisel_state(final_pc)
+            .with_chisel_access_tracking(self.config.fork.is_some());
+        if self.config.retain_state || self.config.fork.is_some() {
+            builder = builder.with_chisel_state(final_pc);
+        }
+        let executor = builder
             .set_tracing(true)
             .with_spec(foundry_cli::utils::evm_spec(&self.config.foundry_config.evm_version))
-            .with_gas_limit(self.config.evm_opts.gas_limit())
-            .with_cheatcodes(CheatsConfig::new(&self.config.foundry_config, &self.config.evm_opts))
+            .with_gas_limit(self.limits.gas_limit.into())
+            .with_deadline(deadline)
+            .with_cheatcodes(CheatsConfig::new(&self.config.foundry_config, &evm_opts))
             .build(backend);
 
         // Create a [ChiselRunner] with a default balance of [U256::MAX] and
-        // the sender [Address::zero].
-        ChiselRunner::new(executor, U256::MAX, Address::zero())
+        // the sender [Address::zero]. The `run()` function is called with the overridden sender
+        // and value.
+        let mut runner = ChiselRunner::new(executor, U256::MAX, Address::zero());
+        runner.caller = caller;
+        runner.value = value;
+        runner.balances = self.balances.clone();
+        runner.debug = self.config.debug;
+        Ok(runner)
+    }
+
+    /// Prepare a runner that executes the new statements of the source against a persisted
+    /// state
+    ///
+    /// ### Takes
+    ///
+    /// - The persisted state, see [SessionSource::can_resume]
+    /// - The final statement's program counter for the [ChiselInspector]
+    /// - The wall-clock deadline that the execution is halted at
+    ///
+    /// ### Returns
+    ///
+    /// A [ChiselRunner] over a copy of the persisted executor, configured like
+    /// [SessionSource::prepare_runner]
+    fn resume_runner(
+        &self,
+        state: &PersistedState,
+        final_pc: usize,
+        deadline: Instant,
+    ) -> ChiselRunner {
+        let mut executor = state.executor.clone();
+        let (caller, value) = (executor.env().tx.caller, executor.env().tx.value);
+        let config = executor.inspector_config_mut();
+        config.chisel_state =
+            Some(final_pc).filter(|_| self.config.retain_state || self.config.fork.is_some());
+        config.chisel_access_tracking = self.config.fork.is_some();
+        config.deadline = Some(deadline);
+        executor.set_gas_limit(self.limits.gas_limit.into());
+
+        let mut runner = ChiselRunner::new(executor, U256::MAX, Address::zero());
+        runner.caller = caller;
+        runner.value = value;
+        runner.balances = self.balances.clone();
+        runner.debug = self.config.debug;
+        runner
+    }
+
+    /// The environment that the source is executed in
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the [revm::Env] of the session's EVM options or forked network, with the
+    /// session's [crate::session_source::EnvOverrides] applied. Fails if the forked network can not
+    /// be reached.
+    pub async fn execution_env(&self) -> Result<revm::Env> {
+        let evm_opts = self.config.fork_evm_opts()?;
+        let mut env = match &evm_opts.fork_url {
+            Some(fork_url) => evm_opts.fork_evm_env(fork_url).await?,
+            None => evm_opts.local_evm_env(),
+        };
+        self.env_overrides.apply(&mut env);
+        Ok(env)
     }
 }
 
-/// Formats a [Token] into an inspection message
-///
-/// ### Takes
-///
-/// An owned [Token]
-///
-/// ### Returns
-///
-/// A formatted [Token] for use in inspection output.
-///
-/// TODO: Verbosity option
-fn format_token(token: Token) -> String {
-    match token {
-        Token::Address(a) => {
-            format!("Type: {}\n└ Data: {}", Paint::red("address"), Paint::cyan(format!("0x{a:x}")))
-        }
-        Token::FixedBytes(b) => {
-            format!(
-                "Type: {}\n└ Data: {}",
-                Paint::red(format!("bytes{}", b.len())),
-                Paint::cyan(format!("0x{}", hex::encode(b)))
-            )
-        }
-        Token::Int(i) => {
-            format!(
-                "Type: {}\n├ Hex: {}\n└ Decimal: {}",
-                Paint::red("int"),
-                Paint::cyan(format!("0x{i:x}")),
-                Paint::cyan(I256::from_raw(i))
-            )
-        }
-        Token::Uint(i) => {
-            format!(
-                "Type: {}\n├ Hex: {}\n└ Decimal: {}",
-                Paint::red("uint"),
-                Paint::cyan(format!("0x{i:x}")),
-                Paint::cyan(i)
-            )
-        }
-        Token::Bool(b) => {
-            format!("Type: {}\n└ Value: {}", Paint::red("bool"), Paint::cyan(b))
-        }
-        Token::String(_) | Token::Bytes(_) => {
-            let hex = hex::encode(ethers::abi::encode(&[token.clone()]));
-            let s = token.into_string();
-            format!(
-                "Type: {}\n{}├ Hex (Memory):\n├─ Length ({}): {}\n├─ Contents ({}): {}\n├ Hex (Tuple Encoded):\n├─ Pointer ({}): {}\n├─ Length ({}): {}\n└─ Contents ({}): {}",
-                Paint::red(if s.is_some() { "string" } else { "dynamic bytes" }),
-                if s.is_some() {
-                    format!("├ UTF-8: {}\n", Paint::cyan(s.unwrap()))
-                } else {
-                    String::default()
-                },
-                Paint::yellow("[0x00:0x20]"),
-                Paint::cyan(format!("0x{}", &hex[64..128])),
-                Paint::yellow("[0x20:..]"),
-                Paint::cyan(format!("0x{}", &hex[128..])),
-                Paint::yellow("[0x00:0x20]"),
-                Paint::cyan(format!("0x{}", &hex[..64])),
-                Paint::yellow("[0x20:0x40]"),
-                Paint::cyan(format!("0x{}", &hex[64..128])),
-                Paint::yellow("[0x40:..]"),
-                Paint::cyan(format!("0x{}", &hex[128..])),
-            )
+/// The name of the array that the gas left before each new statement is stored in, see
+/// [SessionSource::executable_source]
+const GAS: &str = "__chisel_gas";
+
+/// The name of the array that the values of the variables declared within the "run()" function
+/// are captured in, see [PersistedState::locals]
+const LOCALS: &str = "__chisel_locals";
+
+/// The name of the ABI-encoded gas and captured values, which the "run()" function returns
+const OUTPUT: &str = "__chisel_output";
+
+/// A copy of the source that is compiled and executed in its place, see
+/// [SessionSource::executable_source]
+struct ExecutableSource {
+    /// The copy of the source
+    source: SessionSource,
+    /// The variables whose values are captured at the end of the "run()" function, in order, or
+    /// `None` if they are not captured
+    captured: Option<Vec<String>>,
+    /// The index of the snippet that each probe measures the gas of, in order
+    probes: Vec<usize>,
+    /// Whether a statement that returns the measured gas and the captured values was appended
+    /// to the "run()" function
+    instrumented: bool,
+    /// The compiled copy of the source
+    compiled: GeneratedOutput,
+    /// The creation bytecode of the libraries that the REPL contract is linked against, in order
+    /// of deployment
+    libraries: Vec<Bytes>,
+    /// The linked creation bytecode of the REPL contract
+    bytecode: Bytes,
+    /// The linked deployed bytecode of the REPL contract
+    deployed_bytecode: Bytes,
+}
+
+impl ExecutableSource {
+    /// Decodes the data that the instrumented "run()" function returned
+    ///
+    /// ### Returns
+    ///
+    /// The gas left at each probe, and the ABI-encoded values of the captured variables, or
+    /// `None` if the function returned something else, i.e. because a statement returned early
+    fn decode_output(&self, returned: &[u8]) -> Option<(Vec<u64>, Vec<Bytes>)> {
+        let kinds = [
+            ParamType::Array(Box::new(ParamType::Uint(256))),
+            ParamType::Array(Box::new(ParamType::Bytes)),
+        ];
+        match ethabi::decode(&kinds, returned).ok()?.as_slice() {
+            [Token::Array(gas), Token::Array(values)] => Some((
+                gas.iter()
+                    .map(|gas| gas.clone().into_uint().map(|gas| gas.low_u64()))
+                    .collect::<Option<_>>()?,
+                values
+                    .iter()
+                    .map(|value| value.clone().into_bytes().map(Bytes::from))
+                    .collect::<Option<_>>()?,
+            )),
+            _ => None,
         }
-        Token::FixedArray(tokens) | Token::Array(tokens) => {
-            let mut out = format!(
-                "{}({}) = {}",
-                Paint::red("array"),
-                Paint::yellow(format!("{}", tokens.len())),
-                Paint::red('[')
-            );
-            for token in tokens {
-                out.push_str("\n  ├ ");
-                out.push_str(&format_token(token).replace('\n', "\n  "));
-                out.push('\n');
-            }
-            out.push_str(&Paint::red(']').to_string());
-            out
+    }
+
+    /// The gas used by the code of each new snippet, measured by its probes
+    ///
+    /// ### Takes
+    ///
+    /// The data that the "run()" function returned
+    ///
+    /// ### Returns
+    ///
+    /// The gas used by each snippet, by snippet index. Empty if the "run()" function was not
+    /// instrumented or did not reach its end.
+    fn gas_per_snippet(&self, returned: &[u8]) -> BTreeMap<usize, u64> {
+        let mut gas_per_snippet = BTreeMap::new();
+        let gas = match self.decode_output(returned) {
+            Some((gas, _)) if self.instrumented && gas.len() == self.probes.len() + 2 => gas,
+            _ => return gas_per_snippet,
+        };
+        // The gas used between the two calibrating probes is that of a probe itself
+        let probe = gas[0].saturating_sub(gas[1]);
+        for (index, left) in self.probes.iter().zip(gas[1..].windows(2)) {
+            *gas_per_snippet.entry(*index).or_default() +=
+                left[0].saturating_sub(left[1]).saturating_sub(probe);
         }
-        Token::Tuple(tokens) => {
-            let mut out = format!(
-                "{}({}) = {}",
-                Paint::red("tuple"),
-                Paint::yellow(tokens.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(",")),
-                Paint::red('(')
-            );
-            for token in tokens {
-                out.push_str("\n  ├ ");
-                out.push_str(&format_token(token).replace('\n', "\n  "));
-                out.push('\n');
-            }
-            out.push_str(&Paint::red(')').to_string());
-            out
+        gas_per_snippet
+    }
+
+    /// Decodes the values of the variables that the "run()" function captured
+    ///
+    /// ### Takes
+    ///
+    /// The data that the "run()" function returned
+    ///
+    /// ### Returns
+    ///
+    /// The ABI-encoded value of each variable by name, or `None` if they were not captured or the
+    /// "run()" function did not reach its end
+    fn locals(&self, returned: &[u8]) -> Option<BTreeMap<String, Bytes>> {
+        let names = self.captured.as_ref()?;
+        if !self.instrumented {
+            return Some(BTreeMap::new())
         }
+        let (_, values) = self.decode_output(returned)?;
+        (values.len() == names.len()).then(|| names.iter().cloned().zip(values).collect())
     }
 }
 
@@ -595,18 +1753,16 @@ impl Type {
                 } else if let Some(struct_def) =
                     intermediate_contract.struct_definitions.get(cur_type)
                 {
+                    // A struct with a field of a type that can not be inferred can not be inspected
                     let inner_types = struct_def
                         .fields
                         .iter()
-                        .map(|var| {
-                            // TODO: Check safety of these unwraps
-                            Type::from_expression(&var.ty)
-                                .unwrap()
-                                .try_as_ethabi(intermediate)
-                                .unwrap()
-                        })
-                        .collect::<Vec<_>>();
-                    Ok(Some(ParamType::Tuple(inner_types)))
+                        .map(|var| Type::from_expression(&var.ty)?.try_as_ethabi(intermediate))
+                        .collect::<Option<Vec<_>>>();
+                    Ok(inner_types.map(ParamType::Tuple))
+                } else if intermediate_contract.enum_definitions.contains_key(cur_type) {
+                    // Enums are ABI encoded as a `uint8`
+                    Ok(Some(ParamType::Uint(8)))
                 } else {
                     eyre::bail!("Could not find function definitions for contract!")
                 }
@@ -616,9 +1772,11 @@ impl Type {
         } else {
             // Check if the custom type is a variable or function within the REPL contract before
             // anything. If it is, we can stop here.
-            if let Ok(res) =
-                Self::infer_custom_type(intermediate, custom_type, Some("REPL".to_owned()))
-            {
+            if let Ok(res) = Self::infer_custom_type(
+                intermediate,
+                custom_type,
+                Some(intermediate.repl_contract_name.clone()),
+            ) {
                 return Ok(res)
             }
 
@@ -783,7 +1941,8 @@ impl<'a> Iterator for InstructionIter<'a> {
         let opcode = *self.bytes.get(pc)?;
         let (data, data_len) = if matches!(opcode, 0x60..=0x7F) {
             let mut data = [0; 32];
-            let data_len = (opcode - 0x60 + 1) as usize;
+            // The push data of the last instruction may be cut off, i.e. by the metadata hash
+            let data_len = ((opcode - 0x60 + 1) as usize).min(self.bytes.len() - self.offset);
             data[..data_len].copy_from_slice(&self.bytes[self.offset..self.offset + data_len]);
             self.offset += data_len;
             (data, data_len as u8)
@@ -793,3 +1952,62 @@ impl<'a> Iterator for InstructionIter<'a> {
         Some(Instruction { pc, opcode, data, data_len })
     }
 }
+
+/// Disassembles bytecode into an opcode listing
+///
+/// ### Takes
+///
+/// - The bytecode to disassemble, i.e. the deployed bytecode of the REPL contract
+/// - Optionally, a program counter to highlight, i.e. one of a trace step
+///
+/// ### Returns
+///
+/// A line per instruction with its program counter in hex, its mnemonic, and the argument of a
+/// push. Basic blocks are separated by an empty line before each `JUMPDEST`. If a program counter
+/// is supplied, only the basic block that contains it is listed, with its instruction marked.
+/// Fails if the program counter is not the start of an instruction.
+pub fn disassemble(bytecode: &[u8], pc: Option<usize>) -> Result<String> {
+    // Split the instructions into basic blocks at each jump destination
+    let mut blocks: Vec<Vec<Instruction>> = vec![Vec::new()];
+    for instruction in InstructionIter::new(bytecode) {
+        if instruction.opcode == opcode::JUMPDEST && !blocks[blocks.len() - 1].is_empty() {
+            blocks.push(Vec::new());
+        }
+        blocks.last_mut().expect("at least one block").push(instruction);
+    }
+
+    let blocks = match pc {
+        Some(pc) => vec![blocks
+            .into_iter()
+            .find(|block| block.iter().any(|instruction| instruction.pc == pc))
+            .ok_or_else(|| {
+                eyre::eyre!("Program counter {pc} (0x{pc:x}) is not the start of an instruction")
+            })?],
+        None => blocks,
+    };
+
+    let width = format!("{:x}", bytecode.len()).len();
+    let line = |instruction: &Instruction| {
+        let mnemonic = OpCode::try_from_u8(instruction.opcode).map_or_else(
+            || format!("UNDEFINED(0x{:02x})", instruction.opcode),
+            |opcode| opcode.as_str().to_string(),
+        );
+        let argument = if instruction.data_len > 0 {
+            format!(" 0x{}", hex::encode(&instruction.data[..instruction.data_len as usize]))
+        } else {
+            String::new()
+        };
+        if Some(instruction.pc) == pc {
+            Paint::yellow(format!("{:0>width$x}|▶{mnemonic}{argument}", instruction.pc))
+                .bold()
+                .to_string()
+        } else {
+            format!("{:0>width$x}| {mnemonic}{argument}", instruction.pc)
+        }
+    };
+    Ok(blocks
+        .iter()
+        .map(|block| block.iter().map(line).collect::<Vec<_>>().join("\n"))
+        .collect::<Vec<_>>()
+        .join("\n\n"))
+}
@@ -0,0 +1,552 @@
+//! Format
+//!
+//! This module contains the [ValueFormatter], which displays ABI decoded values by their solidity
+//! type, naming the fields of the structs and the variants of the enums that the session defines.
+
+use ethers::{
+    abi::{ParamType, Token},
+    types::I256,
+    utils::{hex, to_checksum},
+};
+use serde::{Deserialize, Serialize};
+use solang_parser::pt;
+use std::{collections::BTreeMap, fmt, str::FromStr};
+use yansi::Paint;
+
+/// The width that an array, tuple, or struct is printed within on a single line. Wider values
+/// are printed with each of their elements on a line of its own.
+static INLINE_WIDTH: usize = 80;
+
+/// How integers are displayed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NumberFormat {
+    /// In decimal, i.e. `255`
+    #[default]
+    Decimal,
+    /// In hexadecimal, i.e. `0xff`
+    Hex,
+}
+
+impl FromStr for NumberFormat {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dec" | "decimal" => Ok(NumberFormat::Decimal),
+            "hex" => Ok(NumberFormat::Hex),
+            _ => eyre::bail!("Unknown number format `{s}`! Expected `hex` or `dec`"),
+        }
+    }
+}
+
+impl fmt::Display for NumberFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NumberFormat::Decimal => f.write_str("dec"),
+            NumberFormat::Hex => f.write_str("hex"),
+        }
+    }
+}
+
+/// The solidity type of an ABI decoded value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValueType {
+    /// An elementary type, i.e. `uint256`, `address`, or `bytes`
+    Elementary(ParamType),
+    /// A dynamic array
+    Array(Box<ValueType>),
+    /// A fixed size array
+    FixedArray(Box<ValueType>, usize),
+    /// A tuple of unnamed values
+    Tuple(Vec<ValueType>),
+    /// A struct, by name, along with the names and types of its fields
+    Struct(String, Vec<(String, ValueType)>),
+    /// An enum, by name, along with the names of its variants
+    Enum(String, Vec<String>),
+}
+
+impl From<&ParamType> for ValueType {
+    fn from(kind: &ParamType) -> Self {
+        match kind {
+            ParamType::Array(inner) => ValueType::Array(Box::new(inner.as_ref().into())),
+            ParamType::FixedArray(inner, size) => {
+                ValueType::FixedArray(Box::new(inner.as_ref().into()), *size)
+            }
+            ParamType::Tuple(components) => {
+                ValueType::Tuple(components.iter().map(ValueType::from).collect())
+            }
+            kind => ValueType::Elementary(kind.clone()),
+        }
+    }
+}
+
+impl ValueType {
+    /// The type of a [Token] whose solidity type is unknown
+    ///
+    /// ### Takes
+    ///
+    /// The [Token] to type
+    ///
+    /// ### Returns
+    ///
+    /// The [ValueType] of the token. Integers are typed as 256 bits wide, and the elements of
+    /// empty arrays as `uint256`.
+    pub fn of(token: &Token) -> Self {
+        let element = |tokens: &[Token]| {
+            Box::new(tokens.first().map_or(ValueType::Elementary(ParamType::Uint(256)), Self::of))
+        };
+        match token {
+            Token::Address(_) => ValueType::Elementary(ParamType::Address),
+            Token::FixedBytes(bytes) => ValueType::Elementary(ParamType::FixedBytes(bytes.len())),
+            Token::Bytes(_) => ValueType::Elementary(ParamType::Bytes),
+            Token::Int(_) => ValueType::Elementary(ParamType::Int(256)),
+            Token::Uint(_) => ValueType::Elementary(ParamType::Uint(256)),
+            Token::Bool(_) => ValueType::Elementary(ParamType::Bool),
+            Token::String(_) => ValueType::Elementary(ParamType::String),
+            Token::FixedArray(tokens) => ValueType::FixedArray(element(tokens), tokens.len()),
+            Token::Array(tokens) => ValueType::Array(element(tokens)),
+            Token::Tuple(tokens) => ValueType::Tuple(tokens.iter().map(Self::of).collect()),
+        }
+    }
+
+    /// The [ParamType] that values of the type are ABI decoded as
+    pub fn param_type(&self) -> ParamType {
+        match self {
+            ValueType::Elementary(kind) => kind.clone(),
+            ValueType::Array(inner) => ParamType::Array(Box::new(inner.param_type())),
+            ValueType::FixedArray(inner, size) => {
+                ParamType::FixedArray(Box::new(inner.param_type()), *size)
+            }
+            ValueType::Tuple(types) => {
+                ParamType::Tuple(types.iter().map(Self::param_type).collect())
+            }
+            ValueType::Struct(_, fields) => {
+                ParamType::Tuple(fields.iter().map(|(_, ty)| ty.param_type()).collect())
+            }
+            ValueType::Enum(_, _) => ParamType::Uint(8),
+        }
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueType::Elementary(kind) => write!(f, "{kind}"),
+            ValueType::Array(inner) => write!(f, "{inner}[]"),
+            ValueType::FixedArray(inner, size) => write!(f, "{inner}[{size}]"),
+            ValueType::Tuple(types) => {
+                let types = types.iter().map(ToString::to_string).collect::<Vec<_>>();
+                write!(f, "({})", types.join(","))
+            }
+            ValueType::Struct(name, _) => write!(f, "struct {name}"),
+            ValueType::Enum(name, _) => write!(f, "enum {name}"),
+        }
+    }
+}
+
+/// Displays ABI decoded values by their [ValueType]
+#[derive(Debug, Clone, Default)]
+pub struct ValueFormatter {
+    /// How integers are displayed
+    pub numbers: NumberFormat,
+    /// The fields of the structs that the session defines, by the name of the struct. Each field
+    /// is named along with the name of its type if it is user defined, i.e. `Point[]`.
+    pub structs: BTreeMap<String, Vec<(String, Option<String>)>>,
+    /// The variants of the enums that the session defines, by the name of the enum
+    pub enums: BTreeMap<String, Vec<String>>,
+    /// The names of the user defined types of the parameters of the events that the session
+    /// defines, by the name of the event. Each overload is listed separately.
+    pub events: BTreeMap<String, Vec<Vec<Option<String>>>>,
+}
+
+impl ValueFormatter {
+    /// Create a new [ValueFormatter] from the definitions of a source
+    ///
+    /// ### Takes
+    ///
+    /// - How integers are displayed
+    /// - The parsed source to collect the struct, enum, and event definitions of
+    ///
+    /// ### Returns
+    ///
+    /// A new [ValueFormatter]
+    pub fn new(numbers: NumberFormat, source: &pt::SourceUnit) -> Self {
+        let mut formatter = Self { numbers, ..Default::default() };
+        for part in &source.0 {
+            match part {
+                pt::SourceUnitPart::StructDefinition(def) => formatter.add_struct(def),
+                pt::SourceUnitPart::EnumDefinition(def) => formatter.add_enum(def),
+                pt::SourceUnitPart::EventDefinition(def) => formatter.add_event(def),
+                pt::SourceUnitPart::ContractDefinition(def) => {
+                    for part in &def.parts {
+                        match part {
+                            pt::ContractPart::StructDefinition(def) => formatter.add_struct(def),
+                            pt::ContractPart::EnumDefinition(def) => formatter.add_enum(def),
+                            pt::ContractPart::EventDefinition(def) => formatter.add_event(def),
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        formatter
+    }
+
+    fn add_struct(&mut self, def: &pt::StructDefinition) {
+        let fields = def
+            .fields
+            .iter()
+            .map(|field| (field.name.name.clone(), type_name(&field.ty)))
+            .collect();
+        self.structs.insert(def.name.name.clone(), fields);
+    }
+
+    fn add_enum(&mut self, def: &pt::EnumDefinition) {
+        let variants = def.values.iter().map(|value| value.name.clone()).collect();
+        self.enums.insert(def.name.name.clone(), variants);
+    }
+
+    fn add_event(&mut self, def: &pt::EventDefinition) {
+        let params = def.fields.iter().map(|field| type_name(&field.ty)).collect();
+        self.events.entry(def.name.name.clone()).or_default().push(params);
+    }
+
+    /// Resolves the [ValueType] of a value by the name of its type
+    ///
+    /// ### Takes
+    ///
+    /// - The [ParamType] that the value is ABI decoded as
+    /// - The name of the value's type if it is user defined, i.e. `Point[]`, `enum Color`, or
+    ///   `struct Shapes.Point`. Tuples are resolved as the struct, and integers as the enum, that
+    ///   it names.
+    ///
+    /// ### Returns
+    ///
+    /// The [ValueType] of the value, naming the fields of the structs and the variants of the
+    /// enums it contains where they are known.
+    pub fn resolve(&self, kind: &ParamType, type_name: Option<&str>) -> ValueType {
+        let type_name = type_name.map(str::trim);
+        match kind {
+            ParamType::Array(inner) => {
+                let inner_name = type_name.and_then(|name| name.strip_suffix("[]"));
+                ValueType::Array(Box::new(self.resolve(inner, inner_name)))
+            }
+            ParamType::FixedArray(inner, size) => {
+                let suffix = format!("[{size}]");
+                let inner_name = type_name.and_then(|name| name.strip_suffix(&suffix));
+                ValueType::FixedArray(Box::new(self.resolve(inner, inner_name)), *size)
+            }
+            ParamType::Tuple(components) => {
+                let definition = type_name
+                    .map(definition_name)
+                    .and_then(|name| Some((name, self.structs.get(name)?)))
+                    .filter(|(_, fields)| fields.len() == components.len());
+                match definition {
+                    Some((name, fields)) => ValueType::Struct(
+                        name.to_string(),
+                        fields
+                            .iter()
+                            .zip(components)
+                            .map(|((field, ty), kind)| {
+                                (field.clone(), self.resolve(kind, ty.as_deref()))
+                            })
+                            .collect(),
+                    ),
+                    None => ValueType::Tuple(
+                        components.iter().map(|kind| self.resolve(kind, None)).collect(),
+                    ),
+                }
+            }
+            ParamType::Uint(_) => {
+                match type_name
+                    .map(definition_name)
+                    .and_then(|name| Some((name, self.enums.get(name)?)))
+                {
+                    Some((name, variants)) => ValueType::Enum(name.to_string(), variants.clone()),
+                    None => ValueType::Elementary(kind.clone()),
+                }
+            }
+            kind => ValueType::Elementary(kind.clone()),
+        }
+    }
+
+    /// Resolves the [ValueType]s of the parameters of an event
+    ///
+    /// ### Takes
+    ///
+    /// - The name of the event
+    /// - The decoded values of its parameters
+    ///
+    /// ### Returns
+    ///
+    /// The [ValueType] of each parameter, by the overload of the event with as many parameters.
+    pub fn resolve_event(&self, name: &str, values: &[&Token]) -> Vec<ValueType> {
+        let type_names = self
+            .events
+            .get(name)
+            .and_then(|overloads| overloads.iter().find(|params| params.len() == values.len()));
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let type_name = type_names.and_then(|names| names[i].as_deref());
+                self.resolve(&ValueType::of(value).param_type(), type_name)
+            })
+            .collect()
+    }
+
+    /// Formats a value
+    ///
+    /// ### Takes
+    ///
+    /// - The [ValueType] of the value
+    /// - The decoded value
+    ///
+    /// ### Returns
+    ///
+    /// The formatted value. Arrays, tuples, and structs that do not fit within [INLINE_WIDTH] are
+    /// printed with each of their elements on an indented line of its own. Values that do not
+    /// match their type are formatted by their [Token] alone.
+    pub fn format(&self, ty: &ValueType, token: &Token) -> String {
+        self.pretty(ty, token, 0)
+    }
+
+    /// Formats a value as the result of an evaluated expression
+    ///
+    /// ### Takes
+    ///
+    /// - The [ValueType] of the value
+    /// - The decoded value
+    ///
+    /// ### Returns
+    ///
+    /// The type of the value, followed by its formatted value. Integers are printed in hex as well
+    /// as in decimal, and strings and dynamic bytes along with their ABI encoding.
+    pub fn inspection(&self, ty: &ValueType, token: &Token) -> String {
+        match token {
+            Token::Int(i) | Token::Uint(i) if !matches!(ty, ValueType::Enum(_, _)) => {
+                let decimal = match token {
+                    Token::Int(_) => I256::from_raw(*i).to_string(),
+                    _ => i.to_string(),
+                };
+                let (first, second) = match self.numbers {
+                    NumberFormat::Decimal => (("Hex", format!("0x{i:x}")), ("Decimal", decimal)),
+                    NumberFormat::Hex => (("Decimal", decimal), ("Hex", format!("0x{i:x}"))),
+                };
+                format!(
+                    "Type: {}\n├ {}: {}\n└ {}: {}",
+                    Paint::red(ty),
+                    first.0,
+                    Paint::cyan(first.1),
+                    second.0,
+                    Paint::cyan(second.1)
+                )
+            }
+            Token::Address(_) | Token::FixedBytes(_) => {
+                let (data, text) = match token {
+                    Token::FixedBytes(bytes) => (format!("0x{}", hex::encode(bytes)), text(bytes)),
+                    _ => (self.format(ty, token), None),
+                };
+                format!(
+                    "Type: {}\n{}└ Data: {}",
+                    Paint::red(ty),
+                    text.map(|text| format!("├ UTF-8: {}\n", Paint::cyan(text)))
+                        .unwrap_or_default(),
+                    Paint::cyan(data)
+                )
+            }
+            Token::String(_) | Token::Bytes(_) => {
+                let hex = hex::encode(ethers::abi::encode(&[token.clone()]));
+                let s = token.clone().into_string();
+                format!(
+                    "Type: {}\n{}├ Hex (Memory):\n├─ Length ({}): {}\n├─ Contents ({}): {}\n├ Hex (Tuple Encoded):\n├─ Pointer ({}): {}\n├─ Length ({}): {}\n└─ Contents ({}): {}",
+                    Paint::red(ty),
+                    s.map(|s| format!("├ UTF-8: {}\n", Paint::cyan(s))).unwrap_or_default(),
+                    Paint::yellow("[0x00:0x20]"),
+                    Paint::cyan(format!("0x{}", &hex[64..128])),
+                    Paint::yellow("[0x20:..]"),
+                    Paint::cyan(format!("0x{}", &hex[128..])),
+                    Paint::yellow("[0x00:0x20]"),
+                    Paint::cyan(format!("0x{}", &hex[..64])),
+                    Paint::yellow("[0x20:0x40]"),
+                    Paint::cyan(format!("0x{}", &hex[64..128])),
+                    Paint::yellow("[0x40:..]"),
+                    Paint::cyan(format!("0x{}", &hex[128..])),
+                )
+            }
+            _ => format!(
+                "Type: {}\n└ Value: {}",
+                Paint::red(ty),
+                Paint::cyan(self.format(ty, token).replace('\n', "\n  "))
+            ),
+        }
+    }
+
+    /// Formats a value within the given level of indentation
+    fn pretty(&self, ty: &ValueType, token: &Token, indent: usize) -> String {
+        let inline = self.inline(ty, token);
+        if indent * 2 + inline.len() <= INLINE_WIDTH {
+            return inline
+        }
+        let (open, close, elements) = match self.elements(ty, token) {
+            Some(parts) => parts,
+            None => return inline,
+        };
+        let padding = "  ".repeat(indent + 1);
+        let elements = elements
+            .iter()
+            .map(|(name, ty, token)| {
+                let value = self.pretty(ty, token, indent + 1);
+                match name {
+                    Some(name) => format!("{padding}{name}: {value}"),
+                    None => format!("{padding}{value}"),
+                }
+            })
+            .collect::<Vec<_>>();
+        format!(
+            "{}\n{}\n{}{}",
+            open.trim_end(),
+            elements.join(",\n"),
+            "  ".repeat(indent),
+            close.trim_start()
+        )
+    }
+
+    /// Formats a value on a single line
+    fn inline(&self, ty: &ValueType, token: &Token) -> String {
+        match self.elements(ty, token) {
+            Some((open, close, elements)) => {
+                let elements = elements
+                    .iter()
+                    .map(|(name, ty, token)| {
+                        let value = self.inline(ty, token);
+                        match name {
+                            Some(name) => format!("{name}: {value}"),
+                            None => value,
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                format!("{open}{}{close}", elements.join(", "))
+            }
+            None => self.scalar(ty, token),
+        }
+    }
+
+    /// Splits an array, tuple, or struct into its delimiters and its elements, along with their
+    /// names and types, if the value matches its type
+    #[allow(clippy::type_complexity)]
+    fn elements<'a>(
+        &self,
+        ty: &'a ValueType,
+        token: &'a Token,
+    ) -> Option<(String, &'static str, Vec<(Option<&'a str>, &'a ValueType, &'a Token)>)> {
+        match (ty, token) {
+            (ValueType::Array(inner), Token::Array(tokens)) |
+            (ValueType::FixedArray(inner, _), Token::FixedArray(tokens)) => Some((
+                String::from("["),
+                "]",
+                tokens.iter().map(|token| (None, inner.as_ref(), token)).collect(),
+            )),
+            (ValueType::Tuple(types), Token::Tuple(tokens)) if types.len() == tokens.len() => {
+                Some((
+                    String::from("("),
+                    ")",
+                    types.iter().zip(tokens).map(|(ty, token)| (None, ty, token)).collect(),
+                ))
+            }
+            (ValueType::Struct(name, fields), Token::Tuple(tokens))
+                if fields.len() == tokens.len() =>
+            {
+                Some((
+                    format!("{name} {{ "),
+                    " }",
+                    fields
+                        .iter()
+                        .zip(tokens)
+                        .map(|((field, ty), token)| (Some(field.as_str()), ty, token))
+                        .collect(),
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /// Formats a value that is neither an array, tuple, nor struct of its type
+    fn scalar(&self, ty: &ValueType, token: &Token) -> String {
+        match (ty, token) {
+            (ValueType::Enum(name, variants), Token::Uint(i)) => {
+                match variants.get(i.low_u64() as usize).filter(|_| i.bits() <= 64) {
+                    Some(variant) => format!("{name}.{variant}"),
+                    None => format!("{name}({i})"),
+                }
+            }
+            (_, Token::Uint(i)) => match self.numbers {
+                NumberFormat::Decimal => i.to_string(),
+                NumberFormat::Hex => format!("0x{i:x}"),
+            },
+            (_, Token::Int(i)) => {
+                let i = I256::from_raw(*i);
+                match self.numbers {
+                    NumberFormat::Decimal => i.to_string(),
+                    NumberFormat::Hex if i.is_negative() => format!("-0x{:x}", i.unsigned_abs()),
+                    NumberFormat::Hex => format!("0x{:x}", i.into_raw()),
+                }
+            }
+            (_, Token::Address(address)) => to_checksum(address, None),
+            (_, Token::FixedBytes(bytes)) => match text(bytes) {
+                Some(text) => format!("0x{} ({text:?})", hex::encode(bytes)),
+                None => format!("0x{}", hex::encode(bytes)),
+            },
+            (_, Token::Bytes(bytes)) => format!("0x{}", hex::encode(bytes)),
+            (_, Token::String(s)) => format!("{s:?}"),
+            (_, Token::Bool(b)) => b.to_string(),
+            // The value does not match its type, so it can only be formatted by itself
+            (_, token) => self.inline(&ValueType::of(token), token),
+        }
+    }
+}
+
+/// Renders a `bytes32` as text, if it holds printable UTF-8 padded with zeros
+fn text(bytes: &[u8]) -> Option<&str> {
+    if bytes.len() != 32 {
+        return None
+    }
+    let end = bytes.iter().rposition(|byte| *byte != 0)? + 1;
+    std::str::from_utf8(&bytes[..end]).ok().filter(|text| !text.chars().any(char::is_control))
+}
+
+/// The name of the user defined type of a declaration
+///
+/// ### Takes
+///
+/// The type expression of the declaration
+///
+/// ### Returns
+///
+/// Optionally, the name of the struct, enum, or contract type along with its array suffixes,
+/// i.e. `Point[2][]`. Elementary types have no name.
+pub fn type_name(ty: &pt::Expression) -> Option<String> {
+    match ty {
+        pt::Expression::Variable(ident) => Some(ident.name.clone()),
+        pt::Expression::MemberAccess(_, _, ident) => Some(ident.name.clone()),
+        pt::Expression::ArraySubscript(_, inner, None) => Some(format!("{}[]", type_name(inner)?)),
+        pt::Expression::ArraySubscript(_, inner, Some(size)) => match size.as_ref() {
+            pt::Expression::NumberLiteral(_, size, exp) if exp.is_empty() => {
+                Some(format!("{}[{size}]", type_name(inner)?))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Strips the kind and the scope off of the name of a user defined type, i.e. `struct
+/// Shapes.Point` becomes `Point`
+fn definition_name(name: &str) -> &str {
+    let name = ["struct ", "enum ", "contract "]
+        .iter()
+        .find_map(|kind| name.strip_prefix(kind))
+        .unwrap_or(name);
+    name.rsplit('.').next().unwrap_or(name)
+}
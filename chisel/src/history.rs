@@ -0,0 +1,248 @@
+//! History
+//!
+//! This module contains the [GlobalHistory], the readline history that is shared by every
+//! session, so that common inputs can be recalled across sessions. Each session keeps its own
+//! history file as well, see [crate::session::ChiselSession::history_file].
+
+use crate::rpc::redact_api_key;
+use eyre::Result;
+use fs2::FileExt;
+use rustyline::Config;
+use std::{
+    fs::OpenOptions,
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+/// The environment variable that overrides the path of the [GlobalHistory] file
+pub static HISTORY_FILE_ENV: &str = "CHISEL_HISTORY_FILE";
+
+/// The default number of entries that a history keeps
+pub const DEFAULT_HISTORY_SIZE: usize = 1000;
+
+/// How inputs are recorded in the histories
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryConfig {
+    /// The number of most recent entries that a history keeps. `0` disables the histories, and
+    /// leaves their files untouched.
+    pub max_entries: usize,
+    /// Whether an input that repeats the previous entry is left out
+    pub ignore_dups: bool,
+    /// Whether inputs that start with a space are left out, i.e. to keep secrets out of the
+    /// history
+    pub ignore_space: bool,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self { max_entries: DEFAULT_HISTORY_SIZE, ignore_dups: true, ignore_space: false }
+    }
+}
+
+impl HistoryConfig {
+    /// Whether an input is recorded after an entry
+    ///
+    /// ### Takes
+    ///
+    /// - The input
+    /// - The most recent entry of the history, if any
+    ///
+    /// ### Notes
+    ///
+    /// Inputs that may contain secrets are never recorded, see [contains_secret].
+    pub fn records(&self, input: &str, last: Option<&str>) -> bool {
+        self.max_entries > 0 &&
+            !input.trim().is_empty() &&
+            !contains_secret(input) &&
+            !(self.ignore_space && input.starts_with(' ')) &&
+            !(self.ignore_dups && last == Some(input))
+    }
+
+    /// The configuration of a rustyline editor whose history follows this configuration
+    pub fn editor_config(&self) -> Config {
+        Config::builder()
+            .max_history_size(self.max_entries)
+            .history_ignore_dups(self.ignore_dups)
+            .history_ignore_space(self.ignore_space)
+            .build()
+    }
+}
+
+/// The readline history that is shared by every session, i.e. `~/.foundry/chisel_history`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlobalHistory {
+    /// The path of the history file
+    pub path: PathBuf,
+    /// How inputs are recorded
+    pub config: HistoryConfig,
+}
+
+impl GlobalHistory {
+    /// Creates a global history at its default path, see [GlobalHistory::default_path]
+    pub fn new(config: HistoryConfig) -> Result<Self> {
+        Ok(Self { path: Self::default_path()?, config })
+    }
+
+    /// The default path of the history file
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the path set in the `CHISEL_HISTORY_FILE` environment variable. If the
+    /// variable is not set, defaults to `~/.foundry/chisel_history`.
+    pub fn default_path() -> Result<PathBuf> {
+        if let Some(path) = std::env::var_os(HISTORY_FILE_ENV).filter(|path| !path.is_empty()) {
+            return Ok(PathBuf::from(path))
+        }
+        let home_dir = dirs::home_dir().ok_or(eyre::eyre!("Failed to grab home directory"))?;
+        Ok(home_dir.join(".foundry").join("chisel_history"))
+    }
+
+    /// Reads the entries of the history
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the entries, oldest first, which are empty if the history is disabled or its
+    /// file does not exist yet. Fails if the file can not be read.
+    ///
+    /// ### Notes
+    ///
+    /// The file is locked for reading, so that a concurrent [GlobalHistory::record] can not be
+    /// read halfway through compacting it.
+    pub fn load(&self) -> Result<Vec<String>> {
+        if self.config.max_entries == 0 || !self.path.exists() {
+            return Ok(Vec::new())
+        }
+        let mut file = OpenOptions::new().read(true).open(&self.path)?;
+        file.lock_shared()?;
+        let mut contents = String::new();
+        let res = file.read_to_string(&mut contents);
+        let _ = file.unlock();
+        res?;
+        let mut entries = parse_entries(&contents);
+        entries.drain(..entries.len().saturating_sub(self.config.max_entries));
+        Ok(entries)
+    }
+
+    /// Records an input in the history, unless the [HistoryConfig] leaves it out
+    ///
+    /// ### Takes
+    ///
+    /// The input
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, whether the input was recorded. Fails if the file can not be written.
+    ///
+    /// ### Notes
+    ///
+    /// - The file is locked while it is read and appended to, so that concurrent chisel processes
+    ///   do not clobber each other's entries. Once it holds more than twice the maximum number of
+    ///   entries, it is compacted to the most recent ones.
+    /// - On unix, the file is created readable and writable by its owner only.
+    pub fn record(&self, input: &str) -> Result<bool> {
+        if self.config.max_entries == 0 {
+            return Ok(false)
+        }
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut options = OpenOptions::new();
+        options.create(true).read(true).append(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&self.path)?;
+        file.lock_exclusive()?;
+        let res = (|| -> Result<bool> {
+            let mut contents = String::new();
+            file.read_to_string(&mut contents)?;
+            let mut entries = parse_entries(&contents);
+            if !self.config.records(input, entries.last().map(String::as_str)) {
+                return Ok(false)
+            }
+
+            // Each entry is a JSON string, so that multi-line inputs occupy a single line.
+            entries.push(input.to_string());
+            if entries.len() > self.config.max_entries * 2 {
+                entries.drain(..entries.len() - self.config.max_entries);
+                file.set_len(0)?;
+                file.seek(SeekFrom::Start(0))?;
+                for entry in &entries {
+                    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+                }
+            } else {
+                writeln!(file, "{}", serde_json::to_string(input)?)?;
+            }
+            file.sync_data()?;
+            Ok(true)
+        })();
+        let _ = file.unlock();
+        res
+    }
+}
+
+/// Whether an input may contain a secret, which keeps it out of every history
+///
+/// ### Returns
+///
+/// `true` for `!broadcast` commands, which may carry a private key, for `!rpc add`, whose URL
+/// may carry an API key, and for `!fork` with a URL that carries one, see [redact_api_key]
+pub fn contains_secret(input: &str) -> bool {
+    let mut words = input.split_whitespace();
+    match words.next() {
+        Some("!broadcast") => true,
+        Some("!rpc") => words.next() == Some("add"),
+        Some("!fork") => words.next().map_or(false, |url| redact_api_key(url).is_some()),
+        _ => false,
+    }
+}
+
+/// Parses the entries of a history file, skipping lines that are not a JSON string
+fn parse_entries(contents: &str) -> Vec<String> {
+    contents.lines().filter_map(|line| serde_json::from_str(line).ok()).collect()
+}
+
+/// Reads the entries of a history file written by a rustyline editor, i.e. the history file of a
+/// session
+///
+/// ### Takes
+///
+/// - The path of the history file
+/// - The [HistoryConfig], whose maximum number of entries is kept
+///
+/// ### Returns
+///
+/// The entries, oldest first, or an empty history if the file can not be read or the history is
+/// disabled
+pub fn read_editor_history(path: &Path, config: &HistoryConfig) -> Vec<String> {
+    if config.max_entries == 0 {
+        return Vec::new()
+    }
+    let mut editor = match rustyline::Editor::<()>::with_config(config.editor_config()) {
+        Ok(editor) => editor,
+        Err(_) => return Vec::new(),
+    };
+    if editor.load_history(path).is_err() {
+        return Vec::new()
+    }
+    editor.history().iter().cloned().collect()
+}
+
+/// Writes entries to a history file in the format of a rustyline editor
+///
+/// ### Takes
+///
+/// - The path of the history file
+/// - The entries, oldest first
+/// - The [HistoryConfig], whose maximum number of entries is kept. If the history is disabled, the
+///   file is left untouched.
+pub fn write_editor_history(path: &Path, entries: &[String], config: &HistoryConfig) -> Result<()> {
+    if config.max_entries == 0 {
+        return Ok(())
+    }
+    let mut editor = rustyline::Editor::<()>::with_config(config.editor_config())?;
+    for entry in entries {
+        editor.add_history_entry(entry.as_str());
+    }
+    editor.save_history(path)?;
+    Ok(())
+}
@@ -22,13 +22,44 @@ pub mod runner;
 /// REPL contract executor
 pub mod executor;
 
+/// REPL contract storage decoder
+pub mod storage;
+
+/// REPL value formatter
+pub mod format;
+
+/// Native evaluation of builtin expressions
+pub mod native;
+
+/// Line-mode step debugger
+pub mod debugger;
+
+/// Broadcasting of statements to a real network
+pub mod broadcast;
+
+/// Gas profiling of statements
+pub mod profile;
+
+/// Readline history shared by every session
+pub mod history;
+
+/// RPC endpoint aliases of the user
+pub mod rpc;
+
+/// State diffs and access lists of statements
+pub mod state_diff;
+
+/// Tab completion of commands, builtins, and session symbols
+pub mod completion;
+
 /// A Solidity Helper module for rustyline
 pub mod solidity_helper;
 
 /// Prelude of all chisel modules
 pub mod prelude {
     pub use crate::{
-        cmd::*, dispatcher::*, executor::*, runner::*, session::*, session_source::*,
-        solidity_helper::*,
+        broadcast::*, cmd::*, completion::*, debugger::*, dispatcher::*, executor::*, format::*,
+        history::*, native::*, profile::*, rpc::*, runner::*, session::*, session_source::*,
+        solidity_helper::*, state_diff::*, storage::*,
     };
 }
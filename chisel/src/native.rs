@@ -0,0 +1,339 @@
+//! Native evaluation
+//!
+//! This module evaluates expressions that only call pure builtins, i.e. `keccak256` and the
+//! `abi.encode*` functions, and type conversions over literals without compiling or executing
+//! the REPL contract. Anything else is left to the EVM.
+
+use ethers::{
+    abi::{self, ParamType, Token},
+    types::U256,
+    utils::{hex, keccak256, to_checksum},
+};
+use solang_parser::pt;
+
+/// The names of the builtins that are evaluated natively. If the session defines an item with
+/// one of these names, the builtin is shadowed and inputs are not evaluated natively.
+pub const NATIVE_BUILTINS: [&str; 2] = ["keccak256", "abi"];
+
+/// A value produced while evaluating an expression
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    /// A number literal, which is converted to the type it is used as
+    Number {
+        /// Whether the literal is negated
+        negative: bool,
+        /// The absolute value of the literal
+        magnitude: U256,
+        /// The number of digits of a hex number literal, i.e. `4` for `0xbeef`
+        hex_digits: Option<usize>,
+        /// The literal as entered
+        raw: String,
+    },
+    /// A string or hex literal, which is converted to the type it is used as
+    Literal {
+        /// The bytes of the literal
+        bytes: Vec<u8>,
+        /// Whether the literal is a hex literal, i.e. `hex"beef"`
+        hex: bool,
+    },
+    /// A value of a concrete type
+    Typed(ParamType, Token),
+}
+
+/// Evaluates an expression natively
+///
+/// ### Takes
+///
+/// The input of the REPL
+///
+/// ### Returns
+///
+/// `Some((ParamType, Token))` if the input is a single expression that only calls `keccak256`,
+/// `abi.encode`, `abi.encodePacked`, `abi.encodeWithSignature`, `abi.encodeWithSelector`, and
+/// elementary type conversions over literals. Otherwise `None`, as the input has to be compiled
+/// and executed.
+pub fn evaluate(input: &str) -> Option<(ParamType, Token)> {
+    // Statements are executed, not inspected
+    if input.trim_end().ends_with(';') {
+        return None
+    }
+    let pt::SourceUnit(parts) = solang_parser::parse(
+        &format!("contract REPL {{\nfunction run() public {{\n{input};\n}}\n}}"),
+        0,
+    )
+    .ok()?
+    .0;
+    let statements = parts.iter().find_map(|part| match part {
+        pt::SourceUnitPart::ContractDefinition(contract) => {
+            contract.parts.iter().find_map(|part| match part {
+                pt::ContractPart::FunctionDefinition(def) => match &def.body {
+                    Some(pt::Statement::Block { statements, .. }) => Some(statements),
+                    _ => None,
+                },
+                _ => None,
+            })
+        }
+        _ => None,
+    })?;
+    match statements.as_slice() {
+        [pt::Statement::Expression(_, expr)] => match eval(expr)? {
+            Value::Typed(ty, token) => Some((ty, token)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Evaluates an expression, returning `None` if it can not be evaluated natively
+fn eval(expr: &pt::Expression) -> Option<Value> {
+    match expr {
+        pt::Expression::Parenthesis(_, inner) => eval(inner),
+        pt::Expression::BoolLiteral(_, value) => {
+            Some(Value::Typed(ParamType::Bool, Token::Bool(*value)))
+        }
+        pt::Expression::NumberLiteral(_, digits, exponent) => {
+            let digits = digits.replace('_', "");
+            let exponent = if exponent.is_empty() { 0 } else { exponent.parse::<u32>().ok()? };
+            let magnitude = U256::from_dec_str(&digits)
+                .ok()?
+                .checked_mul(U256::from(10).checked_pow(U256::from(exponent))?)?;
+            Some(Value::Number { negative: false, magnitude, hex_digits: None, raw: digits })
+        }
+        pt::Expression::HexNumberLiteral(_, literal) => {
+            let digits = literal.trim_start_matches("0x").replace('_', "");
+            let magnitude = U256::from_str_radix(&digits, 16).ok()?;
+            Some(Value::Number {
+                negative: false,
+                magnitude,
+                hex_digits: Some(digits.len()),
+                raw: literal.replace('_', ""),
+            })
+        }
+        pt::Expression::UnaryMinus(_, inner) => match eval(inner)? {
+            Value::Number { negative, magnitude, hex_digits: None, raw } => {
+                Some(Value::Number { negative: !negative, magnitude, hex_digits: None, raw })
+            }
+            _ => None,
+        },
+        pt::Expression::StringLiteral(literals) => {
+            let mut bytes = Vec::new();
+            for literal in literals {
+                // Escape sequences and non-ASCII characters outside of unicode literals are left
+                // to the compiler
+                if literal.string.contains('\\') || (!literal.unicode && !literal.string.is_ascii())
+                {
+                    return None
+                }
+                bytes.extend_from_slice(literal.string.as_bytes());
+            }
+            Some(Value::Literal { bytes, hex: false })
+        }
+        pt::Expression::HexLiteral(literals) => {
+            let mut bytes = Vec::new();
+            for literal in literals {
+                let digits = literal
+                    .hex
+                    .trim_start_matches("hex")
+                    .trim_matches(|c| c == '"' || c == '\'')
+                    .replace('_', "");
+                bytes.extend(hex::decode(digits).ok()?);
+            }
+            Some(Value::Literal { bytes, hex: true })
+        }
+        pt::Expression::FunctionCall(_, func, args) => {
+            let args = args.iter().map(eval).collect::<Option<Vec<_>>>()?;
+            match func.as_ref() {
+                pt::Expression::Type(_, ty) => match args.as_slice() {
+                    [value] => convert(ty, value),
+                    _ => None,
+                },
+                pt::Expression::Variable(ident) if ident.name == "keccak256" => {
+                    match args.as_slice() {
+                        [value] => Some(Value::Typed(
+                            ParamType::FixedBytes(32),
+                            Token::FixedBytes(keccak256(bytes_of(value)?).to_vec()),
+                        )),
+                        _ => None,
+                    }
+                }
+                pt::Expression::MemberAccess(_, base, member) => match base.as_ref() {
+                    pt::Expression::Variable(ident) if ident.name == "abi" => {
+                        let encoded = encode(&member.name, &args)?;
+                        Some(Value::Typed(ParamType::Bytes, Token::Bytes(encoded)))
+                    }
+                    _ => None,
+                },
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Converts a value to an elementary type, as an explicit type conversion, i.e. `bytes4(..)`
+fn convert(ty: &pt::Type, value: &Value) -> Option<Value> {
+    let typed = |ty: ParamType, token: Token| Some(Value::Typed(ty, token));
+    match (ty, value) {
+        (pt::Type::Bool, Value::Typed(ParamType::Bool, token)) => {
+            typed(ParamType::Bool, token.clone())
+        }
+        (pt::Type::Uint(size), Value::Number { negative: false, magnitude, .. }) => {
+            let size = *size as usize;
+            fits(*magnitude, size)
+                .then(|| Value::Typed(ParamType::Uint(size), Token::Uint(*magnitude)))
+        }
+        (pt::Type::Int(size), Value::Number { negative, magnitude, .. }) => {
+            let size = *size as usize;
+            let raw = if *negative {
+                // The most negative value has no positive counterpart
+                if *magnitude > U256::one() << (size - 1) {
+                    return None
+                }
+                (!*magnitude).overflowing_add(U256::one()).0
+            } else {
+                if !fits(*magnitude, size - 1) {
+                    return None
+                }
+                *magnitude
+            };
+            typed(ParamType::Int(size), Token::Int(raw))
+        }
+        (
+            pt::Type::Address,
+            Value::Number { negative: false, magnitude, hex_digits: Some(digits), raw },
+        ) => {
+            if !fits(*magnitude, 160) {
+                return None
+            }
+            let address = ethers::types::Address::from_slice(&u256_bytes(*magnitude)[12..]);
+            // Hex number literals of the size of an address have to be checksummed
+            if *digits == 40 && to_checksum(&address, None) != *raw {
+                return None
+            }
+            typed(ParamType::Address, Token::Address(address))
+        }
+        (pt::Type::Bytes(size), value) => {
+            let size = *size as usize;
+            let bytes = match value {
+                Value::Literal { bytes, .. } if bytes.len() <= size => bytes.clone(),
+                Value::Number { negative: false, magnitude, hex_digits, .. } => {
+                    if magnitude.is_zero() {
+                        vec![0; size]
+                    } else if *hex_digits == Some(size * 2) {
+                        u256_bytes(*magnitude)[32 - size..].to_vec()
+                    } else {
+                        return None
+                    }
+                }
+                Value::Typed(ParamType::FixedBytes(_), Token::FixedBytes(bytes)) => {
+                    bytes.iter().copied().take(size).collect()
+                }
+                _ => return None,
+            };
+            let mut padded = bytes;
+            padded.resize(size, 0);
+            typed(ParamType::FixedBytes(size), Token::FixedBytes(padded))
+        }
+        (pt::Type::DynamicBytes, value) => typed(ParamType::Bytes, Token::Bytes(bytes_of(value)?)),
+        (pt::Type::String, Value::Literal { bytes, hex: false }) => {
+            typed(ParamType::String, Token::String(String::from_utf8(bytes.clone()).ok()?))
+        }
+        (pt::Type::String, Value::Typed(ParamType::String, token)) => {
+            typed(ParamType::String, token.clone())
+        }
+        _ => None,
+    }
+}
+
+/// Evaluates a member of `abi`, i.e. `abi.encodePacked`
+fn encode(member: &str, args: &[Value]) -> Option<Vec<u8>> {
+    let tokens = |args: &[Value]| args.iter().map(token_of).collect::<Option<Vec<_>>>();
+    match member {
+        "encode" => Some(abi::encode(&tokens(args)?)),
+        "encodePacked" => {
+            args.iter().map(packed).collect::<Option<Vec<_>>>().map(|parts| parts.concat())
+        }
+        "encodeWithSignature" => {
+            let (signature, args) = args.split_first()?;
+            let signature = match signature {
+                Value::Literal { bytes, hex: false } => bytes.clone(),
+                Value::Typed(ParamType::String, Token::String(signature)) => {
+                    signature.as_bytes().to_vec()
+                }
+                _ => return None,
+            };
+            Some([&keccak256(signature)[..4], &abi::encode(&tokens(args)?)].concat())
+        }
+        "encodeWithSelector" => {
+            let (selector, args) = args.split_first()?;
+            let selector = match selector {
+                Value::Typed(ParamType::FixedBytes(4), Token::FixedBytes(selector)) => {
+                    selector.clone()
+                }
+                Value::Number { negative: false, magnitude, hex_digits: Some(8), .. } => {
+                    u256_bytes(*magnitude)[28..].to_vec()
+                }
+                _ => return None,
+            };
+            Some([selector, abi::encode(&tokens(args)?)].concat())
+        }
+        _ => None,
+    }
+}
+
+/// Converts a value to the token it is ABI-encoded as, by `abi.encode`
+fn token_of(value: &Value) -> Option<Token> {
+    match value {
+        Value::Typed(_, token) => Some(token.clone()),
+        // Number literals are encoded as the smallest integer type that fits them, which
+        // occupies a whole word either way
+        Value::Number { negative: false, magnitude, .. } => Some(Token::Uint(*magnitude)),
+        Value::Number { negative: true, .. } => match convert(&pt::Type::Int(256), value)? {
+            Value::Typed(_, token) => Some(token),
+            _ => None,
+        },
+        Value::Literal { bytes, hex: false } => {
+            Some(Token::String(String::from_utf8(bytes.clone()).ok()?))
+        }
+        Value::Literal { hex: true, .. } => None,
+    }
+}
+
+/// Packs a value, as `abi.encodePacked`. Number literals can not be packed, as their type is
+/// ambiguous.
+fn packed(value: &Value) -> Option<Vec<u8>> {
+    match value {
+        Value::Literal { bytes, .. } => Some(bytes.clone()),
+        Value::Typed(ParamType::Uint(size), Token::Uint(value)) |
+        Value::Typed(ParamType::Int(size), Token::Int(value)) => {
+            Some(u256_bytes(*value)[32 - size / 8..].to_vec())
+        }
+        Value::Typed(ParamType::Address, Token::Address(address)) => Some(address.0.to_vec()),
+        Value::Typed(ParamType::Bool, Token::Bool(value)) => Some(vec![*value as u8]),
+        Value::Typed(ParamType::FixedBytes(_), Token::FixedBytes(bytes)) |
+        Value::Typed(ParamType::Bytes, Token::Bytes(bytes)) => Some(bytes.clone()),
+        Value::Typed(ParamType::String, Token::String(string)) => Some(string.as_bytes().to_vec()),
+        _ => None,
+    }
+}
+
+/// Converts a value to `bytes`, as the argument of `keccak256` or `bytes(..)`
+fn bytes_of(value: &Value) -> Option<Vec<u8>> {
+    match value {
+        Value::Literal { bytes, .. } => Some(bytes.clone()),
+        Value::Typed(ParamType::Bytes, Token::Bytes(bytes)) => Some(bytes.clone()),
+        _ => None,
+    }
+}
+
+/// Returns whether a value fits into an unsigned integer of `bits` bits
+fn fits(value: U256, bits: usize) -> bool {
+    value.bits() <= bits
+}
+
+/// Returns the big-endian bytes of a word
+fn u256_bytes(value: U256) -> [u8; 32] {
+    let mut bytes = [0; 32];
+    value.to_big_endian(&mut bytes);
+    bytes
+}
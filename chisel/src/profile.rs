@@ -0,0 +1,272 @@
+//! Profile
+//!
+//! This module contains the [GasProfile], which aggregates where the gas of a statement went: by
+//! category of opcode, by opcode, and by the target of each external call. See `!profile`.
+
+use ethers::{types::Address, utils::to_checksum};
+use forge::executor::inspector::OpcodeCounts;
+use revm::{opcode, OpCode};
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt};
+use yansi::Paint;
+
+/// The number of opcodes that the table of a [GasProfile] lists
+pub const TOP_OPCODES: usize = 10;
+
+/// A category of opcodes that a [GasProfile] aggregates gas by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OpcodeCategory {
+    /// `SLOAD`
+    StorageRead,
+    /// `SSTORE`
+    StorageWrite,
+    /// `CALL`, `CALLCODE`, `DELEGATECALL`, and `STATICCALL`, excluding the gas used by the
+    /// called code
+    Call,
+    /// `CREATE` and `CREATE2`, excluding the gas used by the init code
+    Create,
+    /// `KECCAK256`
+    Hashing,
+    /// `LOG0` through `LOG4`
+    Log,
+    /// The expansion of memory by any instruction
+    MemoryExpansion,
+    /// `BALANCE`, `EXTCODESIZE`, `EXTCODECOPY`, and `EXTCODEHASH`
+    AccountAccess,
+    /// Calls to cheatcodes
+    Cheatcode,
+    /// Every other instruction
+    Other,
+}
+
+impl OpcodeCategory {
+    /// The category of an opcode. Memory expansion is split off of the opcode that caused it.
+    pub fn of(op: u8) -> Self {
+        match op {
+            opcode::SLOAD => OpcodeCategory::StorageRead,
+            opcode::SSTORE => OpcodeCategory::StorageWrite,
+            opcode::CALL | opcode::CALLCODE | opcode::DELEGATECALL | opcode::STATICCALL => {
+                OpcodeCategory::Call
+            }
+            opcode::CREATE | opcode::CREATE2 => OpcodeCategory::Create,
+            opcode::SHA3 => OpcodeCategory::Hashing,
+            opcode::LOG0..=opcode::LOG4 => OpcodeCategory::Log,
+            opcode::BALANCE | opcode::EXTCODESIZE | opcode::EXTCODECOPY | opcode::EXTCODEHASH => {
+                OpcodeCategory::AccountAccess
+            }
+            _ => OpcodeCategory::Other,
+        }
+    }
+}
+
+impl fmt::Display for OpcodeCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OpcodeCategory::StorageRead => "storage reads (SLOAD)",
+            OpcodeCategory::StorageWrite => "storage writes (SSTORE)",
+            OpcodeCategory::Call => "calls (CALL family)",
+            OpcodeCategory::Create => "contract creation (CREATE, CREATE2)",
+            OpcodeCategory::Hashing => "hashing (KECCAK256)",
+            OpcodeCategory::Log => "logs (LOG0-LOG4)",
+            OpcodeCategory::MemoryExpansion => "memory expansion",
+            OpcodeCategory::AccountAccess => "account access (BALANCE, EXTCODE*)",
+            OpcodeCategory::Cheatcode => "cheatcodes",
+            OpcodeCategory::Other => "other",
+        })
+    }
+}
+
+/// The number of times that the instructions of a category were executed, and the gas they used
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CategoryGas {
+    /// The category
+    pub category: OpcodeCategory,
+    /// The number of instructions executed. Not counted for [OpcodeCategory::MemoryExpansion].
+    pub count: u64,
+    /// The gas used
+    pub gas: u64,
+}
+
+/// The number of times that an opcode was executed, and the gas it used
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OpcodeGas {
+    /// The mnemonic of the opcode, i.e. `SLOAD`
+    pub opcode: String,
+    /// The number of times it was executed
+    pub count: u64,
+    /// The gas used, excluding memory expansion and the gas used by called code
+    pub gas: u64,
+}
+
+/// The gas used by the external calls to a function of a contract
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CallGas {
+    /// The address of the called contract
+    pub address: Address,
+    /// The label of the called contract, if any
+    pub label: Option<String>,
+    /// The signature of the called function, if it is known to the session, or else its hex
+    /// selector. `None` for calls without calldata.
+    pub function: Option<String>,
+    /// The number of calls
+    pub count: u64,
+    /// The gas used by the called code, including the calls it made in turn
+    pub gas: u64,
+}
+
+impl CallGas {
+    /// The called contract and function, i.e. `Counter (0x5615…b72f).increment()`
+    pub fn target(&self) -> String {
+        let address = to_checksum(&self.address, None);
+        let contract = match &self.label {
+            Some(label) => format!("{label} ({address})"),
+            None => address,
+        };
+        match &self.function {
+            Some(function) => format!("{contract}.{function}"),
+            None => contract,
+        }
+    }
+}
+
+/// Where the gas of a statement went, see `!profile`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct GasProfile {
+    /// The gas used by the instructions of the statement, including those of the code it called
+    pub total: u64,
+    /// The gas by category of opcode, sorted by gas in descending order
+    pub categories: Vec<CategoryGas>,
+    /// The gas by opcode, sorted by gas in descending order
+    pub opcodes: Vec<OpcodeGas>,
+    /// The gas by the target of each external call made by the statement itself, sorted by gas
+    /// in descending order
+    pub calls: Vec<CallGas>,
+}
+
+impl GasProfile {
+    /// Aggregates the gas of a statement
+    ///
+    /// ### Takes
+    ///
+    /// - The instructions executed by the statement, including those of the code it called, see
+    ///   [OpcodeCounts]
+    /// - The external calls made by the statement itself, each with a count of 1
+    ///
+    /// ### Returns
+    ///
+    /// The [GasProfile]
+    pub fn new(counts: &OpcodeCounts, calls: Vec<CallGas>) -> Self {
+        // The count and gas of each category
+        let mut categories = BTreeMap::<OpcodeCategory, (u64, u64)>::new();
+        for (op, (count, gas)) in &counts.opcodes {
+            let category = categories.entry(OpcodeCategory::of(*op)).or_default();
+            category.0 += count;
+            category.1 += gas;
+        }
+        if counts.memory_expansion > 0 {
+            categories.entry(OpcodeCategory::MemoryExpansion).or_default().1 +=
+                counts.memory_expansion;
+        }
+        if counts.cheatcodes > 0 {
+            categories.entry(OpcodeCategory::Cheatcode).or_default().0 += counts.cheatcodes;
+        }
+
+        // Calls to the same function of the same contract are merged
+        let mut merged = Vec::<CallGas>::new();
+        for call in calls {
+            match merged
+                .iter_mut()
+                .find(|other| other.address == call.address && other.function == call.function)
+            {
+                Some(other) => {
+                    other.count += call.count;
+                    other.gas += call.gas;
+                }
+                None => merged.push(call),
+            }
+        }
+
+        let mut profile = Self {
+            total: counts.total,
+            categories: categories
+                .into_iter()
+                .map(|(category, (count, gas))| CategoryGas { category, count, gas })
+                .collect(),
+            opcodes: counts
+                .opcodes
+                .iter()
+                .map(|(&op, &(count, gas))| OpcodeGas {
+                    opcode: OpCode::try_from_u8(op).map_or_else(
+                        || format!("UNDEFINED(0x{op:02x})"),
+                        |opcode| opcode.as_str().to_string(),
+                    ),
+                    count,
+                    gas,
+                })
+                .collect(),
+            calls: merged,
+        };
+        profile.categories.sort_by(|a, b| b.gas.cmp(&a.gas).then(a.category.cmp(&b.category)));
+        profile.opcodes.sort_by(|a, b| b.gas.cmp(&a.gas).then(b.count.cmp(&a.count)));
+        profile.calls.sort_by(|a, b| b.gas.cmp(&a.gas));
+        profile
+    }
+
+    /// Formats the profile as tables of the categories, the [TOP_OPCODES] opcodes that used the
+    /// most gas, and the external calls, each sorted by gas
+    pub fn table(&self) -> String {
+        let share = |gas: u64| match self.total {
+            0 => String::from("-"),
+            total => format!("{:.1}%", gas as f64 * 100.0 / total as f64),
+        };
+        let mut lines = vec![format!("{} {} gas", Paint::yellow("Total:"), self.total)];
+
+        lines.push(format!("\n{}", Paint::yellow("By category:")));
+        let width = self.categories.iter().map(|c| c.category.to_string().len()).max();
+        for category in
+            self.categories.iter().filter(|category| category.count > 0 || category.gas > 0)
+        {
+            lines.push(format!(
+                "  {:<width$}  {:>6}  {:>10}  {:>6}",
+                category.category.to_string(),
+                match category.category {
+                    OpcodeCategory::MemoryExpansion => String::from("-"),
+                    _ => format!("{}x", category.count),
+                },
+                category.gas,
+                share(category.gas),
+                width = width.unwrap_or_default()
+            ));
+        }
+
+        lines.push(format!("\n{}", Paint::yellow(format!("Top {TOP_OPCODES} opcodes:"))));
+        let top = &self.opcodes[..self.opcodes.len().min(TOP_OPCODES)];
+        let width = top.iter().map(|opcode| opcode.opcode.len()).max().unwrap_or_default();
+        for opcode in top {
+            lines.push(format!(
+                "  {:<width$}  {:>6}  {:>10}  {:>6}",
+                opcode.opcode,
+                format!("{}x", opcode.count),
+                opcode.gas,
+                share(opcode.gas)
+            ));
+        }
+
+        lines.push(format!("\n{}", Paint::yellow("By external call:")));
+        if self.calls.is_empty() {
+            lines.push(String::from("  No external calls"));
+        }
+        let targets = self.calls.iter().map(CallGas::target).collect::<Vec<_>>();
+        let width = targets.iter().map(String::len).max().unwrap_or_default();
+        for (call, target) in self.calls.iter().zip(&targets) {
+            lines.push(format!(
+                "  {target:<width$}  {:>6}  {:>10}  {:>6}",
+                format!("{}x", call.count),
+                call.gas,
+                share(call.gas)
+            ));
+        }
+        lines.join("\n")
+    }
+}
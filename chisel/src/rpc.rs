@@ -0,0 +1,216 @@
+//! RPC Aliases
+//!
+//! This module contains the [RpcAliases] registry, the RPC endpoint aliases of the user that
+//! `!fork` and `!broadcast` resolve, see `!rpc`. Unlike the `[rpc_endpoints]` of a foundry
+//! project, the registry is shared by every session, and it is never persisted with one.
+
+use eyre::Result;
+use foundry_config::{RpcEndpoint, RpcEndpoints};
+use std::path::{Path, PathBuf};
+
+/// The environment variable that overrides the path of the [RpcAliases] file
+pub static RPC_ALIASES_ENV: &str = "CHISEL_RPC_ALIASES";
+
+/// What [redact_api_key] replaces the API key of an RPC URL with
+pub static REDACTED: &str = "REDACTED";
+
+/// The RPC endpoint aliases of the user, stored in a file outside of the session cache
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RpcAliases {
+    /// The path of the file that the aliases are stored in
+    pub path: PathBuf,
+    /// The endpoints, by alias. An endpoint is stored as it was supplied, so that an environment
+    /// variable that it references (ex. `${ALCHEMY_KEY}`) is interpolated when it is resolved.
+    pub endpoints: RpcEndpoints,
+}
+
+impl RpcAliases {
+    /// The default path of the aliases file
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the path set in the `CHISEL_RPC_ALIASES` environment variable. If the
+    /// variable is not set, defaults to `~/.foundry/chisel/rpc_endpoints.json`.
+    pub fn default_path() -> Result<PathBuf> {
+        if let Some(path) = std::env::var_os(RPC_ALIASES_ENV).filter(|path| !path.is_empty()) {
+            return Ok(PathBuf::from(path))
+        }
+        let home_dir = dirs::home_dir().ok_or(eyre::eyre!("Failed to grab home directory"))?;
+        Ok(home_dir.join(".foundry").join("chisel").join("rpc_endpoints.json"))
+    }
+
+    /// Loads the aliases from their default path, see [RpcAliases::default_path]
+    pub fn load() -> Result<Self> {
+        Self::load_from(&Self::default_path()?)
+    }
+
+    /// Loads the aliases from a file, or from their default path, for resolving an endpoint
+    ///
+    /// ### Takes
+    ///
+    /// The path of the aliases file, if it is not the default path
+    ///
+    /// ### Returns
+    ///
+    /// The [RpcAliases]. If the file can not be read or parsed, it is treated as having no
+    /// aliases, so that an endpoint that is not an alias of the user still resolves.
+    pub fn load_or_default(path: Option<&Path>) -> Self {
+        let path = match path.map(Path::to_path_buf).map(Ok).unwrap_or_else(Self::default_path) {
+            Ok(path) => path,
+            Err(e) => {
+                tracing::warn!(%e, "failed to locate the RPC aliases");
+                return Self::default()
+            }
+        };
+        Self::load_from(&path).unwrap_or_else(|e| {
+            tracing::warn!(%e, "failed to load the RPC aliases, ignoring them");
+            Self { path, endpoints: RpcEndpoints::default() }
+        })
+    }
+
+    /// Loads the aliases from a file
+    ///
+    /// ### Takes
+    ///
+    /// The path of the aliases file
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the [RpcAliases], which are empty if the file does not exist yet. Fails if
+    /// the file can not be read or parsed.
+    pub fn load_from(path: &Path) -> Result<Self> {
+        let endpoints = if path.exists() {
+            let contents = std::fs::read_to_string(path)?;
+            serde_json::from_str(&contents).map_err(|e| {
+                eyre::eyre!("Failed to parse the RPC aliases in {}: {e}", path.display())
+            })?
+        } else {
+            RpcEndpoints::default()
+        };
+        Ok(Self { path: path.to_path_buf(), endpoints })
+    }
+
+    /// Writes the aliases to their file, creating its directory if necessary
+    pub fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string_pretty(&self.endpoints)?)?;
+        Ok(())
+    }
+
+    /// Adds an alias, replacing the endpoint of an existing alias of the same name
+    ///
+    /// ### Takes
+    ///
+    /// - The alias, i.e. `mainnet`
+    /// - The RPC URL, which may reference environment variables (ex. `${ALCHEMY_KEY}`)
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the endpoint that the alias replaced. Fails if the alias is not a plain name,
+    /// or if the URL is invalid once its environment variables are interpolated.
+    pub fn add(&mut self, alias: &str, url: &str) -> Result<Option<RpcEndpoint>> {
+        if alias.is_empty() ||
+            !alias.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            eyre::bail!(
+                "Invalid alias `{alias}`: aliases may only contain letters, digits, `-`, and `_`"
+            )
+        }
+        let endpoint: RpcEndpoint = serde_json::from_value(serde_json::Value::from(url))?;
+        if let RpcEndpoint::Url(url) = &endpoint {
+            if reqwest::Url::parse(url).is_err() {
+                eyre::bail!("Invalid RPC URL: {url}")
+            }
+        }
+        Ok(self.endpoints.insert(alias.to_string(), endpoint))
+    }
+
+    /// Removes an alias
+    ///
+    /// ### Returns
+    ///
+    /// The endpoint of the alias, if it existed
+    pub fn remove(&mut self, alias: &str) -> Option<RpcEndpoint> {
+        self.endpoints.remove(alias)
+    }
+
+    /// Finds the alias of an RPC URL, so that a session persists the alias rather than a URL
+    /// that may contain an API key
+    ///
+    /// ### Takes
+    ///
+    /// The RPC URL, as it was supplied
+    ///
+    /// ### Returns
+    ///
+    /// The first alias whose endpoint is the URL, if any
+    pub fn alias_of(&self, url: &str) -> Option<&str> {
+        self.endpoints
+            .iter()
+            .find(|(_, endpoint)| endpoint.to_string() == url)
+            .map(|(alias, _)| alias.as_str())
+    }
+}
+
+/// Redacts the API key that an RPC URL may contain, i.e. the key within
+/// `https://eth-mainnet.g.alchemy.com/v2/<key>`, so that the URL can be saved
+///
+/// ### Takes
+///
+/// The RPC URL, as it was supplied
+///
+/// ### Returns
+///
+/// The URL with its password, its query, and each path segment that looks like a key replaced
+/// with [REDACTED], or `None` if it contains none of them. URLs that reference environment
+/// variables are never redacted, as their keys are not part of them.
+pub fn redact_api_key(url: &str) -> Option<String> {
+    if url.contains("${") {
+        return None
+    }
+    let mut parsed = reqwest::Url::parse(url).ok()?;
+    let mut redacted = false;
+    if parsed.password().is_some() {
+        redacted |= parsed.set_password(Some(REDACTED)).is_ok();
+    }
+    if parsed.query().is_some() {
+        parsed.set_query(Some(REDACTED));
+        redacted = true;
+    }
+    if let Some(segments) = parsed.path_segments() {
+        let segments = segments
+            .map(|segment| {
+                // Keys are long tokens of letters and digits, unlike the version segments and
+                // network names around them
+                let is_key = segment.len() >= 20 &&
+                    segment.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') &&
+                    segment.chars().any(|c| c.is_ascii_digit()) &&
+                    segment.chars().any(|c| c.is_ascii_alphabetic());
+                redacted |= is_key;
+                if is_key {
+                    REDACTED
+                } else {
+                    segment
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        parsed.set_path(&segments);
+    }
+    redacted.then(|| parsed.to_string())
+}
+
+/// Whether an endpoint is an alias rather than an RPC URL, i.e. `mainnet`
+///
+/// ### Takes
+///
+/// The endpoint, as it was supplied
+///
+/// ### Returns
+///
+/// `true` if the endpoint has no scheme and does not reference an environment variable
+pub fn is_alias(endpoint: &str) -> bool {
+    !endpoint.contains("://") && !endpoint.contains("${")
+}
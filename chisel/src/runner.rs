@@ -3,20 +3,39 @@
 //! This module contains the `ChiselRunner` struct, which assists with deploying
 //! and calling the REPL contract on a in-memory REVM instance.
 
+use crate::{
+    format::{ValueFormatter, ValueType},
+    profile::GasProfile,
+    session_source::{EnvOverrides, ForkConfig, SolSnippet},
+    state_diff::StateDiff,
+};
 use ethers::{
+    abi::{ethabi, Abi, AbiError, Event, ParamType, RawLog, Token},
     prelude::{types::U256, Address},
     types::{Bytes, Log},
+    utils::{hex, to_checksum},
 };
+use ethers_solc::EvmVersion;
 use eyre::Result;
 use forge::{
-    executor::{DeployResult, Executor, RawCallResult},
+    debug::DebugArena,
+    decode::decode_console_log,
+    error::ERROR_PREFIX,
+    executor::{inspector::OpcodeCounts, DeployResult, Executor, RawCallResult},
     trace::{CallTraceArena, TraceKind},
 };
-use revm::{return_ok, Return};
-use std::collections::BTreeMap;
+use revm::{return_ok, BlockEnv, Return};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    time::Duration,
+};
 
 /// The function selector of the REPL contract's entrypoint, the `run()` function.
-static RUN_SELECTOR: [u8; 4] = [0xc0, 0x40, 0x62, 0x26];
+pub static RUN_SELECTOR: [u8; 4] = [0xc0, 0x40, 0x62, 0x26];
+
+/// The balance in wei that the REPL contract is deployed with, one billion ether
+pub static REPL_BALANCE: u128 = 1_000_000_000 * 10u128.pow(18);
 
 /// The Chisel Runner
 ///
@@ -30,6 +49,19 @@ pub struct ChiselRunner {
     pub initial_balance: U256,
     /// The sender
     pub sender: Address,
+    /// The sender of the call to the `run()` function. Defaults to the sender.
+    pub caller: Address,
+    /// The value sent along with the call to the `run()` function
+    pub value: U256,
+    /// The balances that accounts are credited with before the `run()` function is called,
+    /// overriding the forked balance and the balance of the REPL contract
+    pub balances: BTreeMap<Address, U256>,
+    /// Record the steps of the call to the `run()` function for the step debugger, including
+    /// those of a call that reverts
+    pub debug: bool,
+    /// Count the instructions of the call to the `run()` function from one of these program
+    /// counters of the REPL contract on, to profile its gas
+    pub profile: Option<BTreeSet<usize>>,
 }
 
 /// Represents the result of a Chisel REPL run
@@ -51,6 +83,388 @@ pub struct ChiselResult {
     pub address: Option<Address>,
     /// EVM State at the final instruction of the `run()` function
     pub state: Option<(revm::Stack, revm::Memory, revm::Return)>,
+    /// The return data buffer at the final instruction of the `run()` function, i.e. the output
+    /// of the last call that the REPL contract made
+    pub return_data: Option<bytes::Bytes>,
+    /// The reason that the call exited with
+    pub exit_reason: Option<Return>,
+    /// The account, and optionally the storage slot, that the call accessed last. If the call
+    /// halted with [Return::FatalExternalError], it is what the database failed to serve.
+    pub last_access: Option<(Address, Option<U256>)>,
+    /// Gas used by the code of each snippet, by snippet index. Excludes the deployment of the
+    /// REPL contract as well as the base and calldata cost of the call.
+    pub gas_per_snippet: Vec<(usize, u64)>,
+    /// Transaction logs, decoded by the events known to the session. See [DecodedLog].
+    pub decoded_logs: Vec<DecodedLog>,
+    /// Whether the session was replayed from a clean state, rather than only its new statements
+    /// being executed against the state of the previous execution. See [PersistedState].
+    pub replayed: bool,
+    /// The reason that the call reverted with, decoded by the errors known to the session. See
+    /// [RevertReason].
+    pub revert: Option<RevertReason>,
+    /// The steps of the call to the `run()` function, if they were recorded for the step
+    /// debugger
+    pub debug: Option<DebugArena>,
+    /// The instructions of the call to the `run()` function that were counted, if it was
+    /// profiled, see [ChiselRunner::profile]
+    pub opcode_counts: Option<OpcodeCounts>,
+    /// Whether the call was halted because it exceeded the time limit of the session
+    pub timed_out: bool,
+    /// Where the gas of the last statement went, if it was profiled, see
+    /// [crate::session_source::SessionSource::profile]
+    pub profile: Option<GasProfile>,
+    /// The accounts and storage slots that the last statement touched, if they were diffed, see
+    /// [crate::session_source::SessionSource::state_diff]
+    pub state_diff: Option<StateDiff>,
+}
+
+/// The state of the EVM that a successful execution of the REPL contract left behind
+///
+/// The next execution only deploys the new code of the REPL contract over the persisted one and
+/// runs its new statements, as long as the snippets it was produced by are a prefix of the
+/// session's. The statements that were executed before are replaced by code that declares the
+/// variables they declared with their captured values, see [PersistedState::locals].
+#[derive(Debug, Clone)]
+pub struct PersistedState {
+    /// The executor that holds the state, including the state of its cheatcodes
+    pub executor: Executor,
+    /// The address of the deployed REPL contract
+    pub address: Address,
+    /// The snippets of the source that were executed to produce the state
+    pub snippets: Vec<SolSnippet>,
+    /// The creation bytecode of the libraries that the REPL contract is linked against
+    pub libraries: Vec<Bytes>,
+    /// The ABI-encoded values of the variables declared within the "run()" function at its end,
+    /// by name. `None` if they could not be captured, i.e. because a statement returned early,
+    /// in which case the next execution replays the session.
+    pub locals: Option<BTreeMap<String, Bytes>>,
+    /// The gas used by the code of each executed snippet when it was executed, by snippet index
+    pub gas_per_snippet: BTreeMap<usize, u64>,
+    /// The network that the state was forked from
+    pub fork: Option<ForkConfig>,
+    /// The EVM version that the state was produced with
+    pub evm_version: EvmVersion,
+    /// The overrides of the environment that the state was produced with
+    pub env_overrides: EnvOverrides,
+    /// The state before the most recent execution, i.e. to re-execute the last statement
+    /// against. Only a single previous state is kept.
+    pub previous: Option<Box<PersistedState>>,
+}
+
+/// The reason that a call to the REPL contract reverted with
+#[derive(Debug, Clone, PartialEq)]
+pub enum RevertReason {
+    /// The call reverted without any data, or halted with the given reason, i.e. `OutOfGas`
+    Empty(Option<Return>),
+    /// An `Error(string)`, i.e. the message of a failed `require`, or a failed cheatcode
+    Message(String),
+    /// A `Panic(uint256)` raised by a check of the compiler, i.e. an arithmetic overflow
+    Panic(U256),
+    /// A custom error defined within the session or an interface added to it, along with its
+    /// named arguments
+    Custom(String, Vec<(String, Token)>),
+    /// Data that matches none of the errors known to the session
+    Unknown(Vec<u8>),
+    /// The execution ran out of the given gas limit
+    GasLimitExceeded(u64),
+    /// The execution took longer than the given time limit
+    TimeLimitExceeded(Duration),
+}
+
+impl RevertReason {
+    /// Decodes the data that a call reverted with
+    ///
+    /// ### Takes
+    ///
+    /// - The data that the call reverted with
+    /// - The custom errors to decode the data by, i.e. those of the session's contracts and
+    ///   interfaces
+    /// - The reason that the call exited with
+    ///
+    /// ### Returns
+    ///
+    /// The [RevertReason]. Data that does not match any error is left undecoded.
+    pub fn decode(data: &[u8], errors: &[AbiError], exit_reason: Option<Return>) -> Self {
+        if data.is_empty() {
+            return Self::Empty(exit_reason)
+        }
+        if data.len() < 4 {
+            return Self::Unknown(data.to_vec())
+        }
+
+        let (selector, args) = data.split_at(4);
+        let decode_one = |kind: ParamType| ethabi::decode(&[kind], args).ok()?.pop();
+        match selector {
+            // keccak256("Error(string)"), or the prefix of a failed cheatcode's error
+            _ if selector == [0x08, 0xc3, 0x79, 0xa0] || selector == ERROR_PREFIX => {
+                if let Some(Token::String(message)) = decode_one(ParamType::String) {
+                    return Self::Message(message)
+                }
+            }
+            // keccak256("Panic(uint256)")
+            [0x4e, 0x48, 0x7b, 0x71] => {
+                if let Some(Token::Uint(code)) = decode_one(ParamType::Uint(256)) {
+                    return Self::Panic(code)
+                }
+            }
+            _ => {}
+        }
+
+        errors
+            .iter()
+            .filter(|error| error.signature()[..4] == *selector)
+            .find_map(|error| {
+                let tokens = error.decode(args).ok()?;
+                let names = error.inputs.iter().map(|input| input.name.clone());
+                Some(Self::Custom(error.name.clone(), names.zip(tokens).collect()))
+            })
+            .unwrap_or_else(|| Self::Unknown(data.to_vec()))
+    }
+
+    /// The description of a `Panic(uint256)` code, see
+    /// <https://docs.soliditylang.org/en/latest/control-structures.html#panic-via-assert-and-error-via-require>
+    pub fn panic_description(code: U256) -> Option<&'static str> {
+        if code > U256::from(u8::MAX) {
+            return None
+        }
+        Some(match code.low_u32() {
+            0x00 => "generic compiler panic",
+            0x01 => "assertion failed",
+            0x11 => "arithmetic underflow or overflow",
+            0x12 => "division or modulo by zero",
+            0x21 => "conversion into non-existent enum value",
+            0x22 => "incorrectly encoded storage byte array",
+            0x31 => "`pop()` on an empty array",
+            0x32 => "array index out of bounds",
+            0x41 => "memory allocation overflow",
+            0x51 => "call to a zero-initialized variable of internal function type",
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for RevertReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RevertReason::Empty(Some(reason)) if !matches!(reason, return_ok!() | Return::Revert) => {
+                write!(f, "EvmError: {reason:?}")
+            }
+            RevertReason::Empty(_) => f.write_str("Reverted without a reason"),
+            RevertReason::Message(message) => f.write_str(message),
+            RevertReason::Panic(code) => match Self::panic_description(*code) {
+                Some(description) => write!(f, "Panic: {description} (0x{code:02x})"),
+                None => write!(f, "Panic: unknown code 0x{code:02x}"),
+            },
+            RevertReason::Custom(name, params) => {
+                let formatter = ValueFormatter::default();
+                let params = params
+                    .iter()
+                    .map(|(param, value)| {
+                        let value = formatter.format(&ValueType::of(value), value);
+                        if param.is_empty() {
+                            value
+                        } else {
+                            format!("{param}: {value}")
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                write!(f, "{name}({})", params.join(", "))
+            }
+            RevertReason::Unknown(data) if data.len() >= 4 => write!(
+                f,
+                "Unknown error 0x{} with data 0x{}. Add the ABI that defines it with `!abi` to decode it",
+                hex::encode(&data[..4]),
+                hex::encode(&data[4..])
+            ),
+            RevertReason::Unknown(data) => write!(f, "Reverted with 0x{}", hex::encode(data)),
+            RevertReason::GasLimitExceeded(gas_limit) => write!(
+                f,
+                "Statement exceeded the gas limit of {gas_limit}. Raise it with `!gaslimit`"
+            ),
+            RevertReason::TimeLimitExceeded(timeout) => write!(
+                f,
+                "Statement exceeded the time limit of {}s. Raise it with `!timeout`",
+                timeout.as_secs_f64()
+            ),
+        }
+    }
+}
+
+/// The machine state of the REPL contract's call frame at the final instruction of the `run()`
+/// function, see [ChiselResult::machine_state]
+#[derive(Debug, Clone)]
+pub struct MachineState {
+    /// The stack
+    pub stack: revm::Stack,
+    /// The memory
+    pub memory: revm::Memory,
+    /// The return data buffer, i.e. the output of the last call that the REPL contract made
+    pub return_data: bytes::Bytes,
+}
+
+/// A log emitted during a REPL execution, decoded by one of the session's events if possible
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedLog {
+    /// The raw log
+    pub log: Log,
+    /// The name of the event that the log was decoded by, and its parameters
+    pub event: Option<(String, Vec<DecodedLogParam>)>,
+}
+
+/// A parameter of a [DecodedLog]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedLogParam {
+    /// The name of the parameter
+    pub name: String,
+    /// The decoded value of the parameter
+    pub value: Token,
+    /// Whether the parameter is an indexed dynamic type, i.e. a `string`, whose topic only holds
+    /// the keccak256 hash of its value
+    pub hashed: bool,
+}
+
+impl DecodedLog {
+    /// Decodes a log by the first event that it matches
+    ///
+    /// ### Takes
+    ///
+    /// - The raw log
+    /// - The events to decode the log by, i.e. those of the session's contracts and interfaces
+    ///
+    /// ### Returns
+    ///
+    /// The [DecodedLog]. If no event matches the log's topics and data, it is left undecoded.
+    pub fn decode(log: &Log, events: &[Event]) -> Self {
+        let raw = RawLog { topics: log.topics.clone(), data: log.data.to_vec() };
+        let event = events
+            .iter()
+            .filter(|event| !event.anonymous && log.topics.first() == Some(&event.signature()))
+            .find_map(|event| {
+                let parsed = event.parse_log(raw.clone()).ok()?;
+                let params = parsed
+                    .params
+                    .into_iter()
+                    .zip(&event.inputs)
+                    .map(|(param, input)| DecodedLogParam {
+                        name: param.name,
+                        value: param.value,
+                        hashed: input.indexed &&
+                            matches!(
+                                input.kind,
+                                ParamType::String |
+                                    ParamType::Bytes |
+                                    ParamType::Array(_) |
+                                    ParamType::FixedArray(..) |
+                                    ParamType::Tuple(_)
+                            ),
+                    })
+                    .collect();
+                Some((event.name.clone(), params))
+            });
+        Self { log: log.clone(), event }
+    }
+
+    /// Whether the log was emitted by `console.log` or a DSTest logging event, which are shown
+    /// as console logs instead
+    pub fn is_console_log(&self) -> bool {
+        decode_console_log(&self.log).is_some()
+    }
+
+    /// Formats the log
+    ///
+    /// ### Takes
+    ///
+    /// The [ValueFormatter] to format the parameters of the event with
+    ///
+    /// ### Returns
+    ///
+    /// The name of the event along with its named parameters, or the raw topics and data of the
+    /// log if no event matched it.
+    pub fn format(&self, formatter: &ValueFormatter) -> String {
+        match &self.event {
+            Some((name, params)) => {
+                let values = params.iter().map(|param| &param.value).collect::<Vec<_>>();
+                let params = params
+                    .iter()
+                    .zip(formatter.resolve_event(name, &values))
+                    .map(|(param, ty)| {
+                        let value = formatter.format(&ty, &param.value);
+                        let value = if param.hashed {
+                            format!("{value} (hash of the indexed value)")
+                        } else {
+                            value
+                        };
+                        if param.name.is_empty() {
+                            value
+                        } else {
+                            format!("{}: {value}", param.name)
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                format!("{name}({})", params.join(", "))
+            }
+            None => {
+                let topics = self
+                    .log
+                    .topics
+                    .iter()
+                    .map(|topic| format!("0x{}", hex::encode(topic)))
+                    .collect::<Vec<_>>();
+                format!(
+                    "Unknown event(topics: [{}], data: 0x{}) emitted by {}",
+                    topics.join(", "),
+                    hex::encode(&self.log.data),
+                    to_checksum(&self.log.address, None)
+                )
+            }
+        }
+    }
+}
+
+impl fmt::Display for DecodedLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.format(&ValueFormatter::default()))
+    }
+}
+
+impl ChiselResult {
+    /// The machine state of the REPL contract at the final instruction of the `run()` function
+    ///
+    /// ### Returns
+    ///
+    /// The [MachineState], or `None` if the final instruction was not reached, i.e. because the
+    /// `run()` function is empty.
+    pub fn machine_state(&self) -> Option<MachineState> {
+        let (stack, memory, _) = self.state.as_ref()?;
+        Some(MachineState {
+            stack: stack.clone(),
+            memory: memory.clone(),
+            return_data: self.return_data.clone().unwrap_or_default(),
+        })
+    }
+
+    /// Decodes the reason that the call to the REPL contract reverted with
+    ///
+    /// ### Takes
+    ///
+    /// The ABI of the REPL contract, if available, to decode its custom errors with. Only used
+    /// if the reason was not already decoded by the errors of the whole session, see
+    /// [ChiselResult::revert].
+    ///
+    /// ### Returns
+    ///
+    /// The decoded revert reason, i.e. the message of a failed `require`, or `None` if the call
+    /// succeeded. See [RevertReason].
+    pub fn revert_reason(&self, abi: Option<&Abi>) -> Option<String> {
+        if self.success {
+            return None
+        }
+        let reason = self.revert.clone().unwrap_or_else(|| {
+            let errors = abi.map(|abi| abi.errors().cloned().collect::<Vec<_>>());
+            RevertReason::decode(&self.returned, &errors.unwrap_or_default(), self.exit_reason)
+        });
+        Some(reason.to_string())
+    }
 }
 
 /// ChiselRunner implementation
@@ -65,24 +479,45 @@ impl ChiselRunner {
     ///
     /// A new [ChiselRunner]
     pub fn new(executor: Executor, initial_balance: U256, sender: Address) -> Self {
-        Self { executor, initial_balance, sender }
+        Self {
+            executor,
+            initial_balance,
+            sender,
+            caller: sender,
+            value: U256::zero(),
+            balances: BTreeMap::new(),
+            debug: false,
+            profile: None,
+        }
     }
 
-    /// Run a contract as a REPL session
+    /// Deploys the REPL contract, along with the libraries that it is linked against, and
+    /// credits the accounts with their balances
     ///
     /// ### Takes
     ///
-    /// The creation bytecode of the REPL contract
+    /// - The creation bytecode of the libraries that the REPL contract is linked against, in order
+    ///   of deployment
+    /// - The creation bytecode of the REPL contract
     ///
     /// ### Returns
     ///
-    /// Optionally, a tuple containing the deployed address of the bytecode as well as a
-    /// [ChiselResult] containing information about the result of the call to the deployed REPL
-    /// contract.
-    pub fn run(&mut self, bytecode: Bytes) -> Result<(Address, ChiselResult)> {
+    /// Optionally, the deployed address of the REPL contract
+    pub fn deploy(&mut self, libraries: Vec<Bytes>, bytecode: Bytes) -> Result<Address> {
         // Set the sender's balance to [U256::MAX] for deployment of the REPL contract.
         self.executor.set_balance(self.sender, U256::MAX)?;
 
+        // Deploy the libraries that the REPL contract is linked against. Their addresses were
+        // derived from the sender's address starting at nonce 0.
+        if !libraries.is_empty() {
+            self.executor.set_nonce(self.sender, 0)?;
+        }
+        for library in libraries {
+            self.executor
+                .deploy(self.sender, library.0, 0.into(), None)
+                .map_err(|err| eyre::eyre!("Failed to deploy library:\n{}", err))?;
+        }
+
         // Deploy an instance of the REPL contract
         // We don't care about deployment traces / logs here
         let DeployResult { address, .. } = self
@@ -90,13 +525,50 @@ impl ChiselRunner {
             .deploy(self.sender, bytecode.0, 0.into(), None)
             .map_err(|err| eyre::eyre!("Failed to deploy REPL contract:\n{}", err))?;
 
-        // Reset the sender's balance to the initial balance for calls.
+        // Reset the sender's balance to the initial balance for calls, and fund the REPL
+        // contract so that it can send ether
         self.executor.set_balance(self.sender, self.initial_balance)?;
+        self.executor.set_balance(address, REPL_BALANCE.into())?;
+        for (account, balance) in &self.balances {
+            self.executor.set_balance(*account, *balance)?;
+        }
+        Ok(address)
+    }
 
-        // Call the "run()" function of the REPL contract
-        let call_res = self.call(self.sender, address, Bytes::from(RUN_SELECTOR), 0.into(), true);
+    /// Calls the `run()` function of a deployed REPL contract, committing its changes to the
+    /// state of the executor if it succeeds
+    ///
+    /// ### Takes
+    ///
+    /// The address of the deployed REPL contract, see [ChiselRunner::deploy]
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, a [ChiselResult] containing information about the result of the call. The
+    /// state of the executor is rolled back to the state before the call if it fails.
+    pub fn run(&mut self, address: Address) -> Result<ChiselResult> {
+        let snapshot = self.executor.clone();
+        let res = self.run_committing(address);
+        if !matches!(&res, Ok(res) if res.success) {
+            self.executor = snapshot;
+        }
+        res
+    }
+
+    /// Calls the `run()` function of a deployed REPL contract, see [ChiselRunner::run]
+    fn run_committing(&mut self, address: Address) -> Result<ChiselResult> {
+        // The caller must be able to afford the value it sends
+        if self.executor.get_balance(self.caller)? < self.value {
+            self.executor.set_balance(self.caller, self.value)?;
+        }
 
-        call_res.map(|res| (address, res))
+        // Only the step debugger and the profiler inspect the instructions of the call
+        self.executor.set_debugger(self.debug);
+        self.executor.inspector_config_mut().opcode_counter = self.profile.clone();
+        let res = self.call(self.caller, address, Bytes::from(RUN_SELECTOR), self.value, true);
+        self.executor.set_debugger(false);
+        self.executor.inspector_config_mut().opcode_counter = None;
+        res
     }
 
     /// Executes the call
@@ -126,7 +598,9 @@ impl ChiselRunner {
 
         let mut res = self.executor.call_raw(from, to, calldata.0.clone(), value)?;
         let mut gas_used = res.gas_used;
-        if matches!(res.exit_reason, return_ok!()) {
+        // The debugger and the profiler inspect the call above, rather than each call of the
+        // search
+        if matches!(res.exit_reason, return_ok!()) && !self.debug && self.profile.is_none() {
             // store the current gas limit and reset it later
             let init_gas_limit = self.executor.env_mut().tx.gas_limit;
 
@@ -176,15 +650,47 @@ impl ChiselRunner {
                 cheatcodes.fs_commit = !cheatcodes.fs_commit;
             }
 
-            res = self.executor.call_raw(from, to, calldata.0.clone(), value)?;
+            if !commit {
+                res = self.executor.call_raw(from, to, calldata.0.clone(), value)?;
+            }
         }
 
         if commit {
-            // if explicitly requested we can now commit the call
-            res = self.executor.call_raw_committing(from, to, calldata.0, value)?;
+            // if explicitly requested we can now commit the call, which has the same result as
+            // the call above unless `fs_commit` changed
+            if fs_commit_changed {
+                res = self.executor.call_raw_committing(from, to, calldata.0, value)?;
+            } else {
+                self.executor.commit(&mut res);
+            }
+
+            // Changes to the block environment, i.e. by `vm.warp`, carry over to later calls
+            if !res.reverted {
+                let env = self.executor.env_mut();
+                env.block = BlockEnv {
+                    basefee: env.block.basefee,
+                    gas_limit: env.block.gas_limit,
+                    ..res.env.block.clone()
+                };
+                env.cfg.chain_id = res.env.cfg.chain_id;
+            }
         }
 
-        let RawCallResult { result, reverted, logs, traces, labels, chisel_state, .. } = res;
+        let RawCallResult {
+            result,
+            reverted,
+            logs,
+            traces,
+            labels,
+            chisel_state,
+            chisel_return_data,
+            chisel_last_access,
+            exit_reason,
+            debug,
+            opcode_counts,
+            timed_out,
+            ..
+        } = res;
 
         Ok(ChiselResult {
             returned: result,
@@ -202,6 +708,13 @@ impl ChiselRunner {
             labeled_addresses: labels,
             address: None,
             state: chisel_state,
+            return_data: chisel_return_data,
+            last_access: chisel_last_access,
+            exit_reason: Some(exit_reason),
+            debug,
+            opcode_counts,
+            timed_out,
+            ..Default::default()
         })
     }
 }
@@ -3,15 +3,189 @@
 //! This module contains the `ChiselSession` struct, which is the top-level
 //! wrapper for a serializable REPL session.
 
-use crate::prelude::{SessionSource, SessionSourceConfig};
-use ethers_solc::Solc;
+use crate::{
+    prelude::{
+        redact_api_key, BroadcastReceipt, ChiselDispatcher, DecodedValue, NumberFormat,
+        SessionSource, SessionSourceConfig, SolidityHelper,
+    },
+    session_source::{
+        load_project_config, parse_evm_version, solidity_version_req, CompileResult, EnvOverrides,
+        ForkConfig, ParseDiagnostics, ParseTreeFragment, RedefinitionPolicy, SnippetKind,
+        SnippetMap, SnippetOrigin, SolSnippet, Symbol,
+    },
+};
+use ethers::{
+    abi::RawAbi,
+    types::{Address, U256},
+    utils::{hex, keccak256},
+};
+use ethers_solc::{artifacts::StorageLayout, remappings::Remapping, Solc};
 use eyre::Result;
-use foundry_config::SolcReq;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use forge::executor::opts::EvmOpts;
+use foundry_config::{Config, RpcEndpoints, SolcReq};
+use fs2::FileExt;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use similar::{ChangeTag, TextDiff};
+use std::{
+    fmt,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime},
+};
 use time::{format_description, OffsetDateTime};
 use yansi::Paint;
 
+/// The environment variable that overrides the default chisel cache directory
+pub static CACHE_DIR_ENV: &str = "CHISEL_CACHE_DIR";
+
+/// The name of the lock file that guards the cache directory
+static CACHE_LOCK_FILE: &str = ".lock";
+
+/// How long to wait for another chisel process to release the cache lock
+pub static CACHE_LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serialized sessions larger than this many bytes are gzip compressed in the cache
+pub static COMPRESSION_THRESHOLD: usize = 1024 * 1024;
+
+/// The magic bytes that every gzip stream begins with
+static GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// The current version of the session cache format
+///
+/// Bump this whenever the serialized layout of [ChiselSession] changes, and add a migration
+/// from the previous version to `migrate_cached_session`.
+pub static CACHE_FORMAT_VERSION: u32 = 2;
+
+/// The current version of the portable session export format
+pub static EXPORT_FORMAT_VERSION: u32 = 1;
+
+/// A portable, self-contained representation of a [ChiselSession]'s source.
+///
+/// Only the raw source of the session is exported; It is re-parsed upon import.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionExport {
+    /// The version of the export format
+    pub format_version: u32,
+    /// The solidity compiler version used by the session
+    pub solc_version: String,
+    /// Global level solidity code
+    pub global_code: String,
+    /// Top level solidity code
+    pub top_level_code: String,
+    /// Code existing within the "run()" function's scope
+    pub run_code: String,
+}
+
+/// Errors that can occur while loading a cached [ChiselSession]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionError {
+    /// The cache file was corrupted, i.e. it was only partially written or its contents do
+    /// not match the checksum it was written with.
+    SessionCorrupted {
+        /// The path of the corrupted cache file
+        path: PathBuf,
+        /// The reason the cache file is considered corrupted
+        reason: String,
+    },
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::SessionCorrupted { path, reason } => {
+                write!(f, "Cached session at {} is corrupted: {reason}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+/// A snippet of a cached session that failed to parse upon load
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrokenSnippet {
+    /// The index of the snippet within the cached session
+    pub index: usize,
+    /// The diagnostics of the parser
+    pub diagnostics: ParseDiagnostics,
+}
+
+impl fmt::Display for BrokenSnippet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Only show the start of the raw code, as snippets may span many lines
+        let code = self.diagnostics.code.trim();
+        let prefix = code.lines().next().unwrap_or_default();
+        let prefix: String = prefix.chars().take(40).collect();
+        let ellipsis = if prefix.len() < code.len() { "..." } else { "" };
+        write!(f, "snippet {} (`{prefix}{ellipsis}`) failed to parse", self.index)?;
+        for diagnostic in &self.diagnostics.diagnostics {
+            write!(f, "; {} at byte {}", diagnostic.message, diagnostic.span.start)?;
+        }
+        Ok(())
+    }
+}
+
+/// The solc versions that a session can be compiled with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SolcVersions {
+    /// The version the session is currently compiled with
+    pub current: semver::Version,
+    /// The version new sessions are compiled with, if one is set or installed
+    pub default: Option<semver::Version>,
+    /// The global version of SVM, if set
+    pub svm_global: Option<semver::Version>,
+    /// All installed versions, oldest first
+    pub installed: Vec<semver::Version>,
+    /// All released versions that are not installed, oldest first. Empty while offline.
+    pub available: Vec<semver::Version>,
+}
+
+impl fmt::Display for SolcVersions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Installed solc versions:")?;
+        for version in &self.installed {
+            let mut labels = Vec::new();
+            if version == &self.current {
+                labels.push("session");
+            }
+            if Some(version) == self.default.as_ref() {
+                labels.push("default for new sessions");
+            }
+            if Some(version) == self.svm_global.as_ref() {
+                labels.push("svm global");
+            }
+            let marker = if version == &self.current { '*' } else { ' ' };
+            write!(f, "\n{marker} {version}")?;
+            if !labels.is_empty() {
+                write!(f, " ({})", labels.join(", "))?;
+            }
+        }
+        if !self.available.is_empty() {
+            let available = self.available.iter().map(ToString::to_string).collect::<Vec<_>>();
+            write!(f, "\nAvailable for install:\n  {}", available.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Limits on the total size of the session cache, enforced by [ChiselSession::write] by
+/// evicting the least recently modified sessions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheLimits {
+    /// The maximum total size of all cached session files, in bytes
+    pub max_cache_size_bytes: u64,
+    /// The maximum number of cached sessions
+    pub max_sessions: usize,
+}
+
+impl Default for CacheLimits {
+    fn default() -> Self {
+        Self { max_cache_size_bytes: 512 * 1024 * 1024, max_sessions: 1_000 }
+    }
+}
+
 /// A Chisel REPL Session
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ChiselSession {
@@ -19,6 +193,159 @@ pub struct ChiselSession {
     pub session_source: Option<SessionSource>,
     /// The current session's identifier
     pub id: Option<String>,
+    /// The directory that sessions are cached in
+    #[serde(skip)]
+    pub cache_dir: PathBuf,
+    /// An optional description of the session
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Tags attached to the session
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Whether the session has been modified since it was last written to the cache
+    #[serde(skip)]
+    pub dirty: bool,
+    /// A hex-encoded hash of the session's raw source, used to detect corrupted cache files.
+    /// Sessions cached before checksums were introduced do not have one.
+    #[serde(default)]
+    pub checksum: Option<String>,
+    /// The version of the cache format that the session was serialized with. Sessions cached
+    /// before the format was versioned are treated as version `0`.
+    #[serde(default)]
+    pub format_version: u32,
+    /// Whether the session is automatically persisted after each accepted input
+    #[serde(skip, default = "default_autosave")]
+    pub autosave: bool,
+    /// The in-flight background write started by [ChiselSession::autosave], if any
+    #[serde(skip)]
+    autosave_handle: Option<JoinHandle<Result<()>>>,
+    /// The limits on the size of the session cache
+    #[serde(skip)]
+    pub cache_limits: CacheLimits,
+    /// How inputs that redefine an item of the session are handled
+    #[serde(skip)]
+    pub redefinition_policy: RedefinitionPolicy,
+    /// Whether cached sessions are loaded even if some of their snippets fail to parse, in
+    /// which case the broken snippets are skipped
+    #[serde(skip)]
+    pub lenient: bool,
+    /// The snippets that were skipped when the session was leniently loaded
+    #[serde(skip)]
+    pub broken_snippets: Vec<BrokenSnippet>,
+    /// The corrupted sessions that were skipped in favor of this one by [ChiselSession::latest]
+    #[serde(skip)]
+    pub skipped_sessions: Vec<SessionError>,
+    /// The IDs of the sessions evicted from the cache by writes, until they are taken with
+    /// [ChiselSession::take_evicted]
+    #[serde(skip)]
+    evicted: Vec<String>,
+}
+
+/// The outcome of merging one [ChiselSession] into another via [ChiselSession::merge]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    /// Import directives and pragmas that were already present in the session and skipped
+    pub deduplicated: Vec<String>,
+    /// Conflicts between the two sessions. If any are present, the session is left untouched.
+    pub conflicts: Vec<String>,
+}
+
+impl MergeReport {
+    /// Returns whether the sessions were merged, i.e. there were no conflicts
+    pub fn is_merged(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/// A section of a session's source code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceSection {
+    /// Global level solidity code
+    Global,
+    /// Top level solidity code
+    TopLevel,
+    /// Code existing within the "run()" function's scope
+    Run,
+}
+
+/// How a snippet changed between two sessions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnippetChange {
+    /// The snippet is only present in the new session
+    Added,
+    /// The snippet is only present in the old session
+    Removed,
+    /// The snippet is present in both sessions
+    Unchanged,
+}
+
+/// A single snippet within a [SessionDiff]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnippetDiff {
+    /// The section of the source that the snippet belongs to
+    pub section: SourceSection,
+    /// How the snippet changed
+    pub change: SnippetChange,
+    /// The raw source of the snippet
+    pub source: String,
+}
+
+/// The difference between two sessions, as produced by [ChiselSession::diff]
+///
+/// Its [Display](fmt::Display) implementation renders a unified diff of both sessions'
+/// contract sources.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionDiff {
+    /// The snippets of both sessions, in order, classified by how they changed
+    pub snippets: Vec<SnippetDiff>,
+    /// The contract source of the old session
+    old_source: String,
+    /// The contract source of the new session
+    new_source: String,
+}
+
+impl SessionDiff {
+    /// Returns whether the raw sources of both sessions are identical
+    pub fn is_empty(&self) -> bool {
+        self.snippets.iter().all(|snippet| snippet.change == SnippetChange::Unchanged)
+    }
+}
+
+impl fmt::Display for SessionDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let diff = TextDiff::from_lines(&self.old_source, &self.new_source);
+        write!(f, "{}", diff.unified_diff().context_radius(3).header("old", "new"))
+    }
+}
+
+/// An exclusive lock on the cache directory, released when dropped
+struct CacheLock(std::fs::File);
+
+impl Drop for CacheLock {
+    fn drop(&mut self) {
+        let _ = self.0.unlock();
+    }
+}
+
+/// Information about a cached [ChiselSession], as seen by [ChiselSession::list_sessions]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionInfo {
+    /// The session's identifier
+    pub id: String,
+    /// The path of the session's cache file
+    pub path: PathBuf,
+    /// The last time the cache file was modified
+    pub modified: SystemTime,
+    /// The solidity compiler version used by the session
+    pub solc_version: Option<String>,
+    /// The number of snippets entered within the session
+    pub snippet_count: usize,
+    /// The session's description
+    pub description: Option<String>,
+    /// The session's tags
+    pub tags: Vec<String>,
+    /// Set if the cache file could not be read or is corrupted
+    pub error: Option<String>,
 }
 
 // ChiselSession Common Associated Functions
@@ -31,250 +358,2636 @@ impl ChiselSession {
     ///
     /// ### Returns
     ///
-    /// An owned [ChiselSession]
+    /// An owned [ChiselSession], cached in the [default cache directory](Self::default_cache_dir)
     pub fn new(config: &SessionSourceConfig) -> Result<Self> {
+        Self::new_in(config, Self::default_cache_dir()?)
+    }
+
+    /// Create a new `ChiselSession` within a foundry project, inheriting its configuration
+    ///
+    /// ### Takes
+    ///
+    /// The root directory of the project, containing its `foundry.toml`
+    ///
+    /// ### Returns
+    ///
+    /// An owned [ChiselSession], cached in the [default cache directory](Self::default_cache_dir)
+    ///
+    /// ### Notes
+    ///
+    /// The session is compiled with the project's solc version, optimizer settings, EVM
+    /// version, and remappings, and imports are resolved relative to the project's root, i.e.
+    /// `import "src/Counter.sol";`. The root is recorded in the session, so that the project's
+    /// configuration is loaded again whenever the session is loaded from the cache.
+    pub fn from_foundry_root(root: &Path) -> Result<Self> {
+        let root = root.canonicalize()?;
+        let foundry_config = load_project_config(&root)?;
+        let evm_opts = Config::figment_with_root(&root).extract::<EvmOpts>()?;
+        Self::new(&SessionSourceConfig {
+            traces: foundry_config.verbosity > 0,
+            foundry_config,
+            evm_opts,
+            backend: None,
+            fork: None,
+            retain_state: false,
+            debug: false,
+            profile: false,
+            rpc_aliases_file: None,
+        })
+    }
+
+    /// Create a new `ChiselSession` that is cached in the specified directory.
+    ///
+    /// ### Takes
+    ///
+    /// - A reference to a [SessionSourceConfig]
+    /// - The directory that the session is cached in
+    ///
+    /// ### Returns
+    ///
+    /// An owned [ChiselSession]
+    ///
+    /// ### Notes
+    ///
+    /// Nothing is written to the cache upon creation. The session reserves its ID under the cache
+    /// lock the first time that it is written, see [ChiselSession::write].
+    pub fn new_in(config: &SessionSourceConfig, cache_dir: PathBuf) -> Result<Self> {
         // Solc version precidence
         // - Foundry configuration / `--use` flag
         // - Latest installed version via SVM
         // - Default: 0.8.17
-        let solc = Solc::find_or_install_svm_version(
-            if let Some(SolcReq::Version(version)) = config.foundry_config.solc.as_ref() {
-                let version = format!("{}.{}.{}", version.major, version.minor, version.patch);
-                if let Ok(None) = Solc::find_svm_installed_version(&version) {
-                    println!(
-                        "{}",
-                        Paint::green(format!("Installing solidity version {}...", &version))
-                    );
-                }
-                version
-            } else {
-                // If no version was explicitly set, use the latest SVM version.
-                if let Some(version) = Solc::installed_versions().into_iter().max() {
-                    version.to_string()
-                } else {
-                    println!(
-                        "{}",
-                        Paint::green(
-                            "No solidity versions installed! Installing solidity version 0.8.17..."
-                        )
-                    );
-                    String::from("0.8.17")
-                }
-            },
-        );
+        let version = default_solc_version(config).map(|version| version.to_string());
+        let version = version.unwrap_or_else(|| {
+            println!("{}", Paint::green("No solidity versions installed!"));
+            String::from("0.8.17")
+        });
 
         // Return initialized ChiselSession with set solc version
-        solc.map(|solc| Self { session_source: Some(SessionSource::new(&solc, config)), id: None })
-            .map_err(|e| eyre::eyre!(e))
+        let solc = find_or_install_solc(&version, config.foundry_config.offline)?;
+        let mut session = Self::open_in(cache_dir);
+        session.session_source = Some(SessionSource::new(&solc, config));
+        Ok(session)
     }
 
-    /// Render the full source code for the current session.
+    /// Opens the [default cache directory](Self::default_cache_dir) without creating a session
+    /// source. See [ChiselSession::open_in].
+    pub fn open() -> Result<Self> {
+        Ok(Self::open_in(Self::default_cache_dir()?))
+    }
+
+    /// Opens a cache directory without creating a session source
+    ///
+    /// ### Takes
+    ///
+    /// The directory that sessions are cached in
     ///
     /// ### Returns
     ///
-    /// Returns the full, flattened source code for the current session.
+    /// An owned [ChiselSession] without a [SessionSource].
     ///
     /// ### Notes
     ///
-    /// This function will not panic, but will return a blank string if the
-    /// session's [SessionSource] is None.
-    pub fn contract_source(&self) -> String {
-        if let Some(source) = &self.session_source {
-            source.to_repl_source()
-        } else {
-            String::default()
+    /// No solc binary is resolved, so the returned session is cheap to create for commands that
+    /// only work with the cache, i.e. listing, loading, or pruning sessions.
+    pub fn open_in(cache_dir: PathBuf) -> Self {
+        Self {
+            session_source: None,
+            id: None,
+            cache_dir,
+            description: None,
+            tags: Vec::new(),
+            dirty: false,
+            checksum: None,
+            format_version: CACHE_FORMAT_VERSION,
+            autosave: true,
+            autosave_handle: None,
+            cache_limits: CacheLimits::default(),
+            redefinition_policy: RedefinitionPolicy::default(),
+            lenient: false,
+            broken_snippets: Vec::new(),
+            skipped_sessions: Vec::new(),
+            evicted: Vec::new(),
         }
     }
 
-    /// Clears the cache directory
+    /// Overrides the directory that the session is cached in.
     ///
-    /// ### WARNING
+    /// ### Takes
     ///
-    /// This will delete all sessions from the cache.
-    /// There is no method of recovering these deleted sessions.
-    pub fn clear_cache() -> Result<()> {
-        let cache_dir = Self::cache_dir()?;
-        for entry in std::fs::read_dir(cache_dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_dir() {
-                std::fs::remove_dir_all(path)?;
-            } else {
-                std::fs::remove_file(path)?;
-            }
-        }
-        Ok(())
-    }
-
-    /// Writes the ChiselSession to a file by serializing it to a JSON string
+    /// The path of the new cache directory
     ///
     /// ### Returns
     ///
-    /// Returns the path of the new cache file
-    pub fn write(&mut self) -> Result<String> {
-        // Try to create the cache directory
-        let cache_dir = Self::cache_dir()?;
-        std::fs::create_dir_all(&cache_dir)?;
+    /// The [ChiselSession] with the updated cache directory
+    ///
+    /// ### Notes
+    ///
+    /// The ID that the session reserved in its previous cache directory is not carried over, so
+    /// the next [ChiselSession::write] reserves a new one.
+    pub fn with_cache_dir(mut self, cache_dir: PathBuf) -> Self {
+        self.cache_dir = cache_dir;
+        self.id = None;
+        self
+    }
 
-        let cache_file_name = match self.id.as_ref() {
-            Some(id) => {
-                // ID is already set- use the existing cache file.
-                format!("{cache_dir}chisel-{id}.json")
-            }
-            None => {
-                // Get the next session cache ID / file
-                let (id, file_name) = Self::next_cached_session()?;
-                // Set the session's ID
-                self.id = Some(id);
-                // Return the new session's cache file name
-                file_name
-            }
-        };
+    /// Sets the description of the session and marks it as dirty.
+    ///
+    /// ### Takes
+    ///
+    /// The new description, or `None` to remove it
+    pub fn set_description(&mut self, description: Option<String>) {
+        self.description = description;
+        self.dirty = true;
+    }
 
-        // Write the current ChiselSession to that file
-        let serialized_contents = serde_json::to_string_pretty(self)?;
-        std::fs::write(&cache_file_name, serialized_contents)?;
+    /// Sets the tags of the session and marks it as dirty.
+    ///
+    /// ### Takes
+    ///
+    /// The new tags of the session
+    pub fn set_tags(&mut self, tags: Vec<String>) {
+        self.tags = tags;
+        self.dirty = true;
+    }
 
-        // Return the full cache file path
-        // Ex: /home/user/.foundry/cache/chisel/chisel-0.json
-        Ok(cache_file_name)
+    /// Sets the SPDX license identifier of the session's rendered source
+    ///
+    /// ### Takes
+    ///
+    /// The SPDX license identifier, i.e. `MIT`
+    pub fn set_license(&mut self, license: String) -> Result<()> {
+        if license.is_empty() || license.contains(char::is_whitespace) {
+            eyre::bail!("Invalid SPDX license identifier \"{license}\"")
+        }
+        let source =
+            self.session_source.as_mut().ok_or(eyre::eyre!("Session source not present!"))?;
+        source.license = license;
+        source.generated_output = None;
+        self.dirty = true;
+        Ok(())
     }
 
-    /// Get the next default session cache file name
+    /// Renames the REPL contract of the session's source
+    ///
+    /// ### Takes
+    ///
+    /// The requested name of the REPL contract
     ///
     /// ### Returns
     ///
-    /// Optionally, returns a tuple containing the next cached session's id and file name.
-    pub fn next_cached_session() -> Result<(String, String)> {
-        let cache_dir = Self::cache_dir()?;
-        let mut entries = std::fs::read_dir(&cache_dir)?;
+    /// Optionally, the name the REPL contract was given, which is suffixed if the requested
+    /// name collides with a contract defined or imported within the session. Fails if the name
+    /// is not a valid identifier.
+    pub fn set_contract_name(&mut self, name: &str) -> Result<String> {
+        if !is_identifier(name) {
+            eyre::bail!("Invalid contract name \"{name}\"")
+        }
 
-        // If there are no existing cached sessions, just create the first one: "chisel-0.json"
-        let mut latest = if let Some(e) = entries.next() {
-            e?
-        } else {
-            return Ok((String::from("0"), format!("{cache_dir}chisel-0.json")))
-        };
+        let source =
+            self.session_source.as_mut().ok_or(eyre::eyre!("Session source not present!"))?;
+        let contract_name = source.set_contract_name(name).to_string();
+        self.dirty = true;
+        Ok(contract_name)
+    }
 
-        let mut session_num = 1;
-        // Get the latest cached session
-        for entry in entries {
-            let entry = entry?;
-            if entry.metadata()?.modified()? >= latest.metadata()?.modified()? {
-                latest = entry;
-            }
+    /// Generates a Solidity interface from a contract's ABI and appends it to the session's
+    /// global code, so that subsequent snippets can call contracts implementing it.
+    ///
+    /// ### Takes
+    ///
+    /// - The name of the interface
+    /// - The JSON ABI of the contract, or a JSON artifact containing it under the `abi` key
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, nothing. Fails if the name is not a valid identifier or if the ABI can not be
+    /// converted into an interface.
+    pub fn add_interface_from_abi(&mut self, name: &str, abi_json: &str) -> Result<()> {
+        let snippet = interface_from_abi(name, abi_json)?;
+        let source =
+            self.session_source.as_mut().ok_or(eyre::eyre!("Session source not present!"))?;
+        source.append_snippet(snippet.with_origin(SnippetOrigin::Import));
+        self.dirty = true;
+        Ok(())
+    }
 
-            // Increase session_num counter rather than cloning the iterator and using `.count`
-            session_num += 1;
+    /// Overrides the version requirement of the session's `pragma solidity` directive
+    ///
+    /// ### Takes
+    ///
+    /// The version requirement, i.e. `>=0.8.0`, or `None` to derive it from the solc version
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, nothing. Fails if the requirement is not satisfied by the session's solc
+    /// version.
+    pub fn set_pragma(&mut self, requirement: Option<String>) -> Result<()> {
+        let source =
+            self.session_source.as_mut().ok_or(eyre::eyre!("Session source not present!"))?;
+        if let Some(requirement) = &requirement {
+            source.check_pragma_override(requirement)?;
         }
+        source.pragma = requirement;
+        source.generated_output = None;
+        self.dirty = true;
+        Ok(())
+    }
 
-        Ok((format!("{session_num}"), format!("{cache_dir}chisel-{session_num}.json")))
+    /// Overrides a field of the environment that the session is executed in
+    ///
+    /// ### Takes
+    ///
+    /// - The name of the field, see [EnvOverrides::FIELDS]
+    /// - The new value of the field, or `None` to remove the override
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the warnings for overrides that contradict the network the session is forked
+    /// from. Fails if the value is invalid. See [EnvOverrides::set].
+    pub fn set_env_override(&mut self, field: &str, value: Option<&str>) -> Result<Vec<String>> {
+        let source =
+            self.session_source.as_mut().ok_or(eyre::eyre!("Session source not present!"))?;
+        source.env_overrides.set(field, value)?;
+        source.machine_state = None;
+        self.dirty = true;
+        Ok(source.env_overrides.fork_conflicts(source.config.fork.as_ref()))
     }
 
-    /// The Chisel Cache Directory
+    /// Sets the gas limit of each execution of the session
+    ///
+    /// ### Takes
+    ///
+    /// The gas limit
     ///
     /// ### Returns
     ///
-    /// Optionally, the directory of the chisel cache.
-    pub fn cache_dir() -> Result<String> {
-        let home_dir = dirs::home_dir().ok_or(eyre::eyre!("Failed to grab home directory"))?;
-        let home_dir_str =
-            home_dir.to_str().ok_or(eyre::eyre!("Failed to convert home directory to string"))?;
-        Ok(format!("{home_dir_str}/.foundry/cache/chisel/"))
+    /// Optionally, nothing. Fails if the gas limit is zero.
+    pub fn set_gas_limit(&mut self, gas_limit: u64) -> Result<()> {
+        if gas_limit == 0 {
+            eyre::bail!("The gas limit must be greater than zero!")
+        }
+        let source =
+            self.session_source.as_mut().ok_or(eyre::eyre!("Session source not present!"))?;
+        source.limits.gas_limit = gas_limit;
+        source.machine_state = None;
+        self.dirty = true;
+        Ok(())
     }
 
-    /// Create the cache directory if it does not exist
+    /// Sets the wall-clock time limit of each execution of the session
+    ///
+    /// ### Takes
+    ///
+    /// The time limit
     ///
     /// ### Returns
     ///
-    /// The unit type if the operation was successful.
-    pub fn create_cache_dir() -> Result<()> {
-        let cache_dir = Self::cache_dir()?;
-        if !Path::new(&cache_dir).exists() {
-            std::fs::create_dir_all(&cache_dir)?;
+    /// Optionally, nothing. Fails if the time limit is zero.
+    pub fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        if timeout.is_zero() {
+            eyre::bail!("The time limit must be greater than zero!")
         }
+        let source =
+            self.session_source.as_mut().ok_or(eyre::eyre!("Session source not present!"))?;
+        source.limits.timeout = timeout;
+        self.dirty = true;
         Ok(())
     }
 
-    /// Lists all available cached sessions
+    /// Sets how integers within the values of the session are displayed
+    ///
+    /// ### Takes
+    ///
+    /// The [NumberFormat]
     ///
     /// ### Returns
     ///
-    /// Optionally, a vector containing tuples of session IDs and cache-file names.
-    pub fn list_sessions() -> Result<Vec<(String, String)>> {
-        // Read the cache directory entries
-        let cache_dir = Self::cache_dir()?;
-        let entries = std::fs::read_dir(cache_dir)?;
+    /// Optionally, nothing. Fails if the session source is not present.
+    pub fn set_number_format(&mut self, number_format: NumberFormat) -> Result<()> {
+        let source =
+            self.session_source.as_mut().ok_or(eyre::eyre!("Session source not present!"))?;
+        source.number_format = number_format;
+        self.dirty = true;
+        Ok(())
+    }
 
-        // For each entry, get the file name and modified time
-        let mut sessions = Vec::new();
-        for entry in entries {
-            let entry = entry?;
-            let modified_time = entry.metadata()?.modified()?;
-            let file_name = entry.file_name();
-            let file_name = file_name
-                .into_string()
-                .map_err(|e| eyre::eyre!(format!("{}", e.to_string_lossy())))?;
-            sessions.push((
-                systemtime_strftime(modified_time, "[year]-[month]-[day] [hour]:[minute]:[second]")
-                    .unwrap(),
-                file_name,
-            ));
+    /// Records a transaction that a snippet of the session was broadcast with, see `!broadcast`
+    ///
+    /// ### Takes
+    ///
+    /// - The index of the snippet
+    /// - The [BroadcastReceipt] of the transaction
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, nothing. Fails if the session source is not present, or if the snippet does
+    /// not exist.
+    pub fn record_broadcast(&mut self, snippet: usize, receipt: BroadcastReceipt) -> Result<()> {
+        let source =
+            self.session_source.as_mut().ok_or(eyre::eyre!("Session source not present!"))?;
+        let position = source
+            .snippet_range(snippet)
+            .ok_or_else(|| eyre::eyre!("Snippet index {snippet} is out of range!"))?
+            .start;
+        source.snippets[position].broadcasts.push(receipt);
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Credits an account with a balance, overriding its balance on a forked network. The
+    /// balance is set in the persisted state right away, and before the statements run whenever
+    /// the session is replayed from a clean state.
+    ///
+    /// ### Takes
+    ///
+    /// - The address of the account
+    /// - The balance in wei
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, nothing. Fails if the session source is not present, or if the balance can
+    /// not be set in the persisted state.
+    pub fn deal(&mut self, account: Address, balance: U256) -> Result<()> {
+        let source =
+            self.session_source.as_mut().ok_or(eyre::eyre!("Session source not present!"))?;
+        if let Some(state) = source.persisted.as_mut() {
+            state.executor.set_balance(account, balance)?;
         }
+        source.balances.insert(account, balance);
+        source.machine_state = None;
+        self.dirty = true;
+        Ok(())
+    }
 
-        if sessions.is_empty() {
-            eyre::bail!("No sessions found!")
-        } else {
-            // Return the list of sessions and their modified times
-            Ok(sessions)
+    /// Enables or disables the optimizer the session's source is compiled with
+    ///
+    /// ### Takes
+    ///
+    /// The number of optimizer runs, or `None` to disable the optimizer
+    pub fn set_optimizer(&mut self, runs: Option<u32>) -> Result<()> {
+        let source =
+            self.session_source.as_mut().ok_or(eyre::eyre!("Session source not present!"))?;
+        source.compiler_settings.optimizer = runs.is_some();
+        if let Some(runs) = runs {
+            source.compiler_settings.runs = runs;
         }
+        source.generated_output = None;
+        self.dirty = true;
+        Ok(())
     }
 
-    /// Loads a specific ChiselSession from the specified cache file
+    /// Changes the solc version the session's source is compiled with
     ///
     /// ### Takes
     ///
-    /// The ID of the chisel session that you wish to load.
+    /// The solc version, i.e. `0.8.20`
     ///
     /// ### Returns
     ///
-    /// Optionally, an owned instance of the loaded chisel session.
-    pub fn load(id: &str) -> Result<Self> {
-        let cache_dir = ChiselSession::cache_dir()?;
-        let contents = std::fs::read_to_string(Path::new(&format!("{cache_dir}chisel-{id}.json")))?;
-        let chisel_env: ChiselSession = serde_json::from_str(&contents)?;
-        Ok(chisel_env)
+    /// Optionally, nothing. Fails if the version is invalid or could not be installed, or with
+    /// a [PragmaConflict](crate::session_source::PragmaConflict) if it does not satisfy the
+    /// `pragma solidity` directives of the session, in which case the version is not installed.
+    pub fn set_solc_version(&mut self, version: &str) -> Result<()> {
+        let version = semver::Version::parse(version.trim().trim_start_matches('v'))
+            .map_err(|e| eyre::eyre!("Invalid solc version \"{version}\": {e}"))?;
+        let source =
+            self.session_source.as_mut().ok_or(eyre::eyre!("Session source not present!"))?;
+
+        // Refuse versions that do not satisfy the session's pragmas before installing them
+        source.check_solc_version(&version)?;
+        source.solc =
+            find_or_install_solc(&version.to_string(), source.config.foundry_config.offline)?;
+        source.generated_output = None;
+        self.dirty = true;
+        Ok(())
     }
 
-    /// Gets the most recent chisel session from the cache dir
+    /// Lists the solc versions that the session can be compiled with
     ///
     /// ### Returns
     ///
-    /// Optionally, the file name of the most recently modified cached session.
-    pub fn latest_cached_session() -> Result<String> {
-        let cache_dir = Self::cache_dir()?;
-        let mut entries = std::fs::read_dir(cache_dir)?;
-        let mut latest = entries.next().ok_or(eyre::eyre!("No entries found!"))??;
-        for entry in entries {
-            let entry = entry?;
-            if entry.metadata()?.modified()? > latest.metadata()?.modified()? {
-                latest = entry;
-            }
+    /// Optionally, the installed and released [SolcVersions], along with the session's current
+    /// version and the version new sessions start with. Released versions are only listed while
+    /// online.
+    pub fn solc_versions(&self) -> Result<SolcVersions> {
+        let source =
+            self.session_source.as_ref().ok_or(eyre::eyre!("Session source not present!"))?;
+        let installed = installed_solc_versions();
+        let mut available = Vec::new();
+        if !source.config.foundry_config.offline {
+            available = Solc::all_versions()
+                .into_iter()
+                .filter_map(|version| semver::Version::parse(&version.to_string()).ok())
+                .filter(|version| !installed.contains(version))
+                .collect::<Vec<_>>();
+            available.sort();
+            available.dedup();
         }
-        Ok(latest.path().to_str().ok_or(eyre::eyre!("Failed to get session path!"))?.to_string())
+
+        Ok(SolcVersions {
+            current: source.solc.version()?,
+            default: default_solc_version(&source.config),
+            svm_global: Solc::svm_global_version(),
+            installed,
+            available,
+        })
     }
 
-    /// Loads the latest ChiselSession from the cache file
+    /// Enables or disables compiling the session's source via the IR pipeline
     ///
-    /// ### Returns
+    /// ### Takes
     ///
-    /// Optionally, an owned instance of the most recently modified cached session.
-    pub fn latest() -> Result<Self> {
-        let last_session = Self::latest_cached_session()?;
-        let last_session_contents = std::fs::read_to_string(Path::new(&last_session))?;
-        let chisel_env: ChiselSession = serde_json::from_str(&last_session_contents)?;
-        Ok(chisel_env)
+    /// Whether to compile via the IR pipeline
+    pub fn set_via_ir(&mut self, via_ir: bool) -> Result<()> {
+        let source =
+            self.session_source.as_mut().ok_or(eyre::eyre!("Session source not present!"))?;
+        source.compiler_settings.via_ir = via_ir;
+        source.generated_output = None;
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Adds a remapping that imports of the session's source are resolved with
+    ///
+    /// ### Takes
+    ///
+    /// - The import prefix to remap, i.e. `@openzeppelin/`
+    /// - The directory that the prefix is remapped to
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the added [Remapping]. Fails if the directory does not exist.
+    ///
+    /// ### Notes
+    ///
+    /// The directory is stored as an absolute path, so that the session can be loaded from
+    /// anywhere. A remapping with the same prefix is replaced.
+    pub fn add_remapping(&mut self, from: &str, to: PathBuf) -> Result<Remapping> {
+        let source =
+            self.session_source.as_mut().ok_or(eyre::eyre!("Session source not present!"))?;
+        let to = to
+            .canonicalize()
+            .map_err(|e| eyre::eyre!("Invalid remapping target {}: {e}", to.display()))?;
+        let mut to = to.to_string_lossy().to_string();
+        if from.ends_with('/') && !to.ends_with('/') {
+            to.push('/');
+        }
+        let remapping = format!("{from}={to}")
+            .parse::<Remapping>()
+            .map_err(|e| eyre::eyre!("Invalid remapping \"{from}={to}\": {e}"))?;
+
+        source.remappings.retain(|existing| existing.name != remapping.name);
+        source.remappings.push(remapping.clone());
+        source.generated_output = None;
+        self.dirty = true;
+        Ok(remapping)
+    }
+
+    /// Adds a directory that imports of the session's source are resolved within
+    ///
+    /// ### Takes
+    ///
+    /// The directory containing the sources, i.e. a dependency's `src` directory
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, nothing. Fails if the path is not a directory.
+    ///
+    /// ### Notes
+    ///
+    /// As with [ChiselSession::add_remapping], the directory is stored as an absolute path.
+    /// Resolving imports within source directories requires solc 0.8.8 or newer.
+    pub fn add_source_directory(&mut self, path: PathBuf) -> Result<()> {
+        let source =
+            self.session_source.as_mut().ok_or(eyre::eyre!("Session source not present!"))?;
+        let path = path
+            .canonicalize()
+            .map_err(|e| eyre::eyre!("Invalid source directory {}: {e}", path.display()))?;
+        if !path.is_dir() {
+            eyre::bail!("{} is not a directory", path.display())
+        }
+
+        if !source.source_directories.contains(&path) {
+            source.source_directories.push(path);
+        }
+        source.generated_output = None;
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Sets the EVM version the session's source is compiled for and executed on
+    ///
+    /// ### Takes
+    ///
+    /// The name of the EVM version, i.e. `london`
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, nothing. Fails with a list of the valid EVM versions if the version is
+    /// unknown.
+    pub fn set_evm_version(&mut self, version: &str) -> Result<()> {
+        let evm_version = parse_evm_version(version)?;
+        let source =
+            self.session_source.as_mut().ok_or(eyre::eyre!("Session source not present!"))?;
+        source.compiler_settings.evm_version = Some(evm_version.to_string());
+        source.config.foundry_config.evm_version = evm_version;
+        source.generated_output = None;
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Forks a network for the session, so that its statements run against the network's state
+    ///
+    /// ### Takes
+    ///
+    /// - The RPC URL of the network, an alias of the `[rpc_endpoints]` of the session's
+    ///   configuration or of the user's [crate::rpc::RpcAliases], or a URL that references
+    ///   environment variables (ex. `${ALCHEMY_KEY}`)
+    /// - Optionally, the block number to fork at. Defaults to the latest block.
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, warnings about the fork. Fails if the endpoint can not be resolved or the
+    /// network can not be reached.
+    ///
+    /// ### Notes
+    ///
+    /// - The fork is pinned to the block, so that every execution of the session sees the same
+    ///   state. Accounts and storage are fetched lazily from the network as they are accessed.
+    /// - Only the endpoint as it was supplied is persisted with the session. See [ForkConfig]. A
+    ///   URL that the user registered an alias for is persisted as the alias instead.
+    /// - A URL that contains an API key and that is not registered is persisted redacted, see
+    ///   [crate::rpc::redact_api_key], which is warned about.
+    pub async fn fork(&mut self, rpc_url: &str, block: Option<u64>) -> Result<Vec<String>> {
+        let source =
+            self.session_source.as_mut().ok_or(eyre::eyre!("Session source not present!"))?;
+        let aliases = source.config.rpc_aliases();
+        let mut warnings = Vec::new();
+        let mut fork = match aliases.alias_of(rpc_url) {
+            Some(alias) => {
+                ForkConfig { endpoint: alias.to_string(), block, chain_id: None, secret_url: None }
+            }
+            None => match redact_api_key(rpc_url) {
+                Some(redacted) => {
+                    warnings.push(format!(
+                        "The RPC URL contains an API key, so it is saved with the session as {redacted}. Register it with `!rpc add <alias> <url>` and fork the alias to keep the fork across sessions"
+                    ));
+                    ForkConfig {
+                        endpoint: redacted,
+                        block,
+                        chain_id: None,
+                        secret_url: Some(rpc_url.to_string()),
+                    }
+                }
+                None => ForkConfig {
+                    endpoint: rpc_url.to_string(),
+                    block,
+                    chain_id: None,
+                    secret_url: None,
+                },
+            },
+        };
+
+        // Fetch the fork's environment to pin its block and chain ID
+        let mut evm_opts = source.config.evm_opts.clone();
+        let fork_url = fork.resolve(&source.config.foundry_config, &aliases)?;
+        evm_opts.fork_block_number = block;
+        let env = evm_opts.fork_evm_env(&fork_url).await?;
+        fork.block = Some(env.block.number.as_u64());
+        fork.chain_id = Some(env.cfg.chain_id.as_u64());
+
+        source.config.evm_opts.fork_url = None;
+        source.config.fork = Some(fork);
+        // Re-instantiate the backend with the new fork upon the next execution, which replays
+        // the session against it
+        source.config.backend = None;
+        source.persisted = None;
+        self.dirty = true;
+        Ok(warnings)
+    }
+
+    /// Forks a network for the session at a pinned block, without reaching the network, i.e.
+    /// when replaying the session's journal. See [ChiselSession::fork].
+    ///
+    /// ### Takes
+    ///
+    /// - The endpoint of the fork, as it was persisted
+    /// - Optionally, the block number the fork is pinned to
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, nothing. Fails if the session's source is not present.
+    pub fn restore_fork(&mut self, endpoint: &str, block: Option<u64>) -> Result<()> {
+        let source =
+            self.session_source.as_mut().ok_or(eyre::eyre!("Session source not present!"))?;
+        source.config.evm_opts.fork_url = None;
+        source.config.fork = Some(ForkConfig {
+            endpoint: endpoint.to_string(),
+            block,
+            chain_id: None,
+            secret_url: None,
+        });
+        source.config.backend = None;
+        source.persisted = None;
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Stops forking a network, so that the session runs against a local network again
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, nothing. Fails if the session's source is not present.
+    pub fn clear_fork(&mut self) -> Result<()> {
+        let source =
+            self.session_source.as_mut().ok_or(eyre::eyre!("Session source not present!"))?;
+        source.config.evm_opts.fork_url = None;
+        source.config.fork = None;
+        source.config.backend = None;
+        source.persisted = None;
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Render the full source code for the current session.
+    ///
+    /// ### Returns
+    ///
+    /// Returns the full, flattened source code for the current session.
+    ///
+    /// ### Notes
+    ///
+    /// This function will not panic, but will return a blank string if the
+    /// session's [SessionSource] is None.
+    pub fn contract_source(&self) -> String {
+        if let Some(source) = &self.session_source {
+            source.to_repl_source()
+        } else {
+            String::default()
+        }
+    }
+
+    /// Clears the source of the session, keeping the session itself alive
+    ///
+    /// ### Takes
+    ///
+    /// Whether to keep the preamble of the session, i.e. its import and pragma directives
+    ///
+    /// ### Notes
+    ///
+    /// The cleared session is persisted upon the next [ChiselSession::write].
+    pub fn clear_session(&mut self, keep_preamble: bool) {
+        if let Some(source) = self.session_source.as_mut() {
+            source.clear(keep_preamble);
+            self.dirty = true;
+        }
+    }
+
+    /// Removes the most recently entered snippet from the session
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the parts of the removed snippet. See [SessionSource::pop_snippet].
+    pub fn pop_snippet(&mut self) -> Option<Vec<SolSnippet>> {
+        let snippet = self.session_source.as_mut()?.pop_snippet()?;
+        self.dirty = true;
+        Some(snippet)
+    }
+
+    /// Restores the snippet most recently removed by [ChiselSession::pop_snippet]
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the parts of the restored snippet. See [SessionSource::redo_snippet].
+    pub fn redo_snippet(&mut self) -> Option<Vec<SolSnippet>> {
+        let snippet = self.session_source.as_mut()?.redo_snippet()?;
+        self.dirty = true;
+        Some(snippet)
+    }
+
+    /// Takes a snapshot of the current state of the session
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the ID of the snapshot. See [SessionSource::snapshot].
+    ///
+    /// ### Notes
+    ///
+    /// Snapshots are not persisted to the cache.
+    pub fn snapshot(&mut self) -> Result<usize> {
+        Ok(self
+            .session_source
+            .as_mut()
+            .ok_or(eyre::eyre!("Session source not present!"))?
+            .snapshot())
+    }
+
+    /// Restores the session to the state of a snapshot
+    ///
+    /// ### Takes
+    ///
+    /// The ID of the snapshot
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, nothing. See [SessionSource::revert_to_snapshot].
+    pub fn revert_to_snapshot(&mut self, id: usize) -> Result<()> {
+        self.session_source
+            .as_mut()
+            .ok_or(eyre::eyre!("Session source not present!"))?
+            .revert_to_snapshot(id)?;
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Replaces a previously entered snippet of the session with new code
+    ///
+    /// ### Takes
+    ///
+    /// - The index of the snippet, in order of entry
+    /// - The raw code to replace the snippet with
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, nothing. See [SessionSource::replace_snippet].
+    pub fn replace_snippet(&mut self, index: usize, raw: &str) -> Result<()> {
+        self.session_source
+            .as_mut()
+            .ok_or(eyre::eyre!("Session source not present!"))?
+            .replace_snippet(index, raw)?;
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Removes a previously entered snippet from the session
+    ///
+    /// ### Takes
+    ///
+    /// The index of the snippet, in order of entry
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the parts of the removed snippet. See [SessionSource::remove_snippet].
+    pub fn remove_snippet(&mut self, index: usize) -> Result<Vec<SolSnippet>> {
+        let snippet = self
+            .session_source
+            .as_mut()
+            .ok_or(eyre::eyre!("Session source not present!"))?
+            .remove_snippet(index)?;
+        self.dirty = true;
+        Ok(snippet)
+    }
+
+    /// Render the full source code for the current session, along with a map of its lines to
+    /// the snippets they were entered in.
+    ///
+    /// ### Returns
+    ///
+    /// Returns the full, flattened source code for the current session and its [SnippetMap].
+    ///
+    /// ### Notes
+    ///
+    /// This function will not panic, but will return a blank string and an empty map if the
+    /// session's [SessionSource] is None.
+    pub fn contract_source_with_map(&self) -> (String, SnippetMap) {
+        if let Some(source) = &self.session_source {
+            source.to_repl_source_with_map()
+        } else {
+            Default::default()
+        }
+    }
+
+    /// Render the full source code for the current session, formatted with the session's
+    /// formatter configuration.
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the formatted source code for the current session. If formatting fails, the
+    /// unformatted source from [ChiselSession::contract_source] is returned instead.
+    ///
+    /// ### Notes
+    ///
+    /// This is the source that is compiled and exported. The unformatted source remains
+    /// available via [ChiselSession::contract_source] for debugging.
+    pub fn formatted_source(&self) -> Result<String> {
+        self.session_source
+            .as_ref()
+            .map(SessionSource::to_formatted_repl_source)
+            .ok_or(eyre::eyre!("Session source not present!"))
+    }
+
+    /// Compiles the current session
+    ///
+    /// ### Returns
+    ///
+    /// A [CompileResult] holding the ABI, bytecode, and warnings of the REPL contract. If
+    /// compilation fails, the error can be downcast to
+    /// [CompilerDiagnostics](crate::session_source::CompilerDiagnostics), or to
+    /// [GeneratedSourceDiagnostics](crate::session_source::GeneratedSourceDiagnostics) if the
+    /// generated REPL contract did not parse.
+    ///
+    /// ### Notes
+    ///
+    /// The session is compiled in memory with the session's solc, rather than within a
+    /// project on disk. Its snippets are left untouched, so a failed compile only has to be
+    /// undone by the caller if it added the offending snippet itself.
+    pub fn compile(&mut self) -> Result<CompileResult> {
+        let source =
+            self.session_source.as_mut().ok_or(eyre::eyre!("Session source not present!"))?;
+        if !source.solc.solc.exists() {
+            self.reset_solc()?;
+        }
+        self.session_source.as_mut().unwrap().compile_repl_contract()
+    }
+
+    /// Inspects the compiled REPL contract of the current session, without compiling it
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the [CompileResult] of the most recent compilation. See
+    /// [SessionSource::artifact].
+    ///
+    /// ### Notes
+    ///
+    /// This function will not panic, but will return `None` if the session's [SessionSource]
+    /// is None.
+    pub fn artifact(&self) -> Option<CompileResult> {
+        self.session_source.as_ref().and_then(SessionSource::artifact)
+    }
+
+    /// Builds the symbol table of the current session
+    ///
+    /// ### Returns
+    ///
+    /// Every item defined within the session, along with the index of the snippet defining it.
+    /// See [SessionSource::defined_symbols].
+    ///
+    /// ### Notes
+    ///
+    /// This function will not panic, but will return an empty vector if the
+    /// session's [SessionSource] is None.
+    pub fn defined_symbols(&self) -> Vec<Symbol> {
+        self.session_source.as_ref().map(SessionSource::defined_symbols).unwrap_or_default()
+    }
+
+    /// Reads the current value of a state variable of the session
+    ///
+    /// ### Takes
+    ///
+    /// The name of a state variable, optionally indexed by mapping keys and array indices, i.e.
+    /// `balances[0xabc]`
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the [DecodedValue] of the variable. See [SessionSource::inspect_storage].
+    pub async fn inspect(&mut self, name: &str) -> Result<DecodedValue> {
+        let source =
+            self.session_source.as_mut().ok_or(eyre::eyre!("Session source not present!"))?;
+        source.inspect_storage(name).await
+    }
+
+    /// Compiles the current session and extracts the storage layout of the REPL contract
+    ///
+    /// ### Returns
+    ///
+    /// The storage layout of the REPL contract. See [SessionSource::storage_layout].
+    pub fn storage_layout(&mut self) -> Result<StorageLayout> {
+        self.session_source
+            .as_mut()
+            .ok_or(eyre::eyre!("Session source not present!"))?
+            .storage_layout()
+    }
+
+    /// Lists the snippets of the current session along with their provenance
+    ///
+    /// ### Returns
+    ///
+    /// A line per snippet with its index, provenance, and the first line of its code, i.e.
+    /// `0: entered 2024-03-02 14:01 (interactive) - uint256 a = 1;`, followed by a line per
+    /// transaction that the snippet was broadcast with.
+    ///
+    /// ### Notes
+    ///
+    /// This function will not panic, but will return a blank string if the
+    /// session's [SessionSource] is None.
+    pub fn snippet_list(&self) -> String {
+        let source = match &self.session_source {
+            Some(source) => source,
+            None => return String::default(),
+        };
+        let index_width = source.snippet_count().saturating_sub(1).to_string().len();
+        (0..source.snippet_count())
+            .filter_map(|index| Some((index, &source.snippets[source.snippet_range(index)?])))
+            .map(|(index, parts)| {
+                let mut line = format!(
+                    "{index:>index_width$}: {} - {}",
+                    parts[0].provenance(),
+                    parts[0].code.lines().next().unwrap_or_default().trim()
+                );
+                for broadcast in parts.iter().flat_map(|part| &part.broadcasts) {
+                    line.push_str(&format!("\n{:index_width$}  broadcast: {broadcast}", ""));
+                }
+                line
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Render the full source code for the current session with line numbers, marking the lines
+    /// that were entered as snippets.
+    ///
+    /// ### Returns
+    ///
+    /// The formatted source code for the current session, as it is compiled. Lines that were
+    /// entered as snippets are highlighted and labeled with the index of their snippet, while
+    /// generated lines are dimmed.
+    ///
+    /// ### Notes
+    ///
+    /// This function will not panic, but will return a blank string if the
+    /// session's [SessionSource] is None.
+    pub fn numbered_source(&self) -> String {
+        let (source, map) = match &self.session_source {
+            Some(source) => source.to_formatted_repl_source_with_map(),
+            None => return String::default(),
+        };
+
+        let line_width = source.lines().count().to_string().len();
+        let index_width =
+            map.iter().map(|(_, index)| index.to_string().len()).max().unwrap_or_default();
+        source
+            .lines()
+            .enumerate()
+            .map(|(i, line)| {
+                let line_number = i + 1;
+                match map.iter().find(|(range, _)| range.contains(&line_number)) {
+                    Some((_, index)) => format!(
+                        "{line_number:>line_width$} {index:>index_width$} │ {}",
+                        SolidityHelper::highlight(line)
+                    ),
+                    None => Paint::new(format!(
+                        "{line_number:>line_width$} {:index_width$} │ {line}",
+                        ""
+                    ))
+                    .dimmed()
+                    .to_string(),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Clears the cache directory
+    ///
+    /// ### WARNING
+    ///
+    /// This will delete all sessions from the cache.
+    /// There is no method of recovering these deleted sessions.
+    pub fn clear_cache(&self) -> Result<()> {
+        let _lock = self.lock_cache()?;
+        for entry in std::fs::read_dir(&self.cache_dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            // The lock file is held for the duration of the clear; Leave it in place.
+            if entry.file_name() == CACHE_LOCK_FILE {
+                continue
+            }
+            if path.is_dir() {
+                std::fs::remove_dir_all(path)?;
+            } else {
+                std::fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Deletes a single cached session by its ID
+    ///
+    /// ### Takes
+    ///
+    /// The ID of the chisel session that you wish to delete.
+    ///
+    /// ### Notes
+    ///
+    /// If the deleted session is the currently loaded one, the session's ID will be reset so
+    /// that the next call to [ChiselSession::write] creates a fresh cache file.
+    pub fn delete_session(&mut self, id: &str) -> Result<()> {
+        let _lock = self.lock_cache()?;
+        let cache_file = self.existing_cache_file(id);
+        if !cache_file.exists() {
+            eyre::bail!("Session with ID \"{id}\" does not exist!")
+        }
+        self.remove_session_files(&cache_file, id)?;
+
+        // If the current session was deleted, reset its ID.
+        if self.id.as_deref() == Some(id) {
+            self.id = None;
+        }
+        Ok(())
+    }
+
+    /// Exports the session's source to a portable file that can be shared and later
+    /// imported via [ChiselSession::import].
+    ///
+    /// ### Takes
+    ///
+    /// The path of the file to export the session to
+    ///
+    /// ### Returns
+    ///
+    /// The unit type if the session was successfully exported.
+    pub fn export(&self, path: &Path) -> Result<()> {
+        let source =
+            self.session_source.as_ref().ok_or(eyre::eyre!("Session source not present!"))?;
+        let export = SessionExport {
+            format_version: EXPORT_FORMAT_VERSION,
+            solc_version: source.solc.version()?.to_string(),
+            global_code: source.global_code.clone(),
+            top_level_code: source.top_level_code.clone(),
+            run_code: source.run_code.clone(),
+        };
+        write_atomic(path, serde_json::to_string_pretty(&export)?.as_bytes())
+    }
+
+    /// Imports a session that was previously exported via [ChiselSession::export].
+    ///
+    /// ### Takes
+    ///
+    /// The path of the exported session file
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, a new [ChiselSession] containing the imported source. The imported session
+    /// shares this session's configuration and cache directory, and is assigned a fresh ID
+    /// upon its first write so that it does not clobber an existing cached session.
+    pub fn import(&self, path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        let export: SessionExport = serde_json::from_str(&contents)?;
+        if export.format_version > EXPORT_FORMAT_VERSION {
+            eyre::bail!(
+                "Unsupported session export format version {}; This session was exported by a newer version of chisel.",
+                export.format_version
+            )
+        }
+
+        let source =
+            self.session_source.as_ref().ok_or(eyre::eyre!("Session source not present!"))?;
+        let solc =
+            find_or_install_solc(&export.solc_version, source.config.foundry_config.offline)?;
+        let mut imported = SessionSource::new(&solc, &source.config);
+        for (fragment, code) in [
+            (ParseTreeFragment::Source, export.global_code),
+            (ParseTreeFragment::Contract, export.top_level_code),
+            (ParseTreeFragment::Function, export.run_code),
+        ] {
+            if !code.is_empty() {
+                imported.append_snippet(
+                    SolSnippet::new(fragment, code).with_origin(SnippetOrigin::File),
+                );
+            }
+        }
+
+        // Re-parse the imported source rather than trusting its contents.
+        if let Err(diagnostics) = imported.parse() {
+            let diagnostics = ParseDiagnostics::new(&imported.to_repl_source(), 0, diagnostics);
+            eyre::bail!("Failed to parse imported session:\n{diagnostics}")
+        }
+
+        Ok(Self {
+            session_source: Some(imported),
+            id: None,
+            cache_dir: self.cache_dir.clone(),
+            description: None,
+            tags: Vec::new(),
+            dirty: true,
+            checksum: None,
+            format_version: CACHE_FORMAT_VERSION,
+            autosave: true,
+            autosave_handle: None,
+            cache_limits: self.cache_limits,
+            redefinition_policy: self.redefinition_policy,
+            lenient: self.lenient,
+            broken_snippets: Vec::new(),
+            skipped_sessions: Vec::new(),
+            evicted: Vec::new(),
+        })
+    }
+
+    /// Duplicates the session, so that it can be experimented on without modifying the
+    /// original.
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, a new [ChiselSession] containing a copy of this session's source,
+    /// description, and tags. The duplicate reserves a fresh ID upon creation; The original
+    /// session's cache file is never touched by any operation on the duplicate.
+    pub fn duplicate(&self) -> Result<Self> {
+        let mut duplicate = Self {
+            session_source: self.session_source.clone(),
+            id: None,
+            cache_dir: self.cache_dir.clone(),
+            description: self.description.clone(),
+            tags: self.tags.clone(),
+            dirty: false,
+            checksum: None,
+            format_version: CACHE_FORMAT_VERSION,
+            autosave: self.autosave,
+            autosave_handle: None,
+            cache_limits: self.cache_limits,
+            redefinition_policy: self.redefinition_policy,
+            lenient: self.lenient,
+            broken_snippets: Vec::new(),
+            skipped_sessions: Vec::new(),
+            evicted: Vec::new(),
+        };
+
+        // Reserve the duplicate's ID
+        duplicate.write()?;
+
+        // Carry the history of the original session over to the duplicate
+        if let (Some(from), Some(to)) = (self.history_file(), duplicate.history_file()) {
+            if from.exists() {
+                std::fs::copy(from, to)?;
+            }
+        }
+        Ok(duplicate)
+    }
+
+    /// Merges another session into this one by appending its source after this session's.
+    ///
+    /// ### Takes
+    ///
+    /// A reference to the [ChiselSession] to merge into this one
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, a [MergeReport] listing the deduplicated directives and any conflicts.
+    ///
+    /// ### Notes
+    ///
+    /// - Import directives and pragmas that are already present in this session are skipped.
+    /// - `pragma solidity` directives of both sessions are kept as long as some available solc
+    ///   version satisfies all of them, regardless of the versions the sessions were compiled with.
+    ///   The session may have to switch to that version afterwards, see
+    ///   [SessionSource::select_solc_version], which considers the same versions.
+    /// - Pragmas that no available solc version satisfies together, and items that are defined in
+    ///   both sessions are reported as conflicts, in which case this session is left untouched
+    ///   rather than being merged into an uncompilable state.
+    pub fn merge(&mut self, other: &ChiselSession) -> Result<MergeReport> {
+        let source =
+            self.session_source.as_mut().ok_or(eyre::eyre!("Session source not present!"))?;
+        let other_source = other
+            .session_source
+            .as_ref()
+            .ok_or(eyre::eyre!("Other session source not present!"))?;
+        let mut report = MergeReport::default();
+
+        // Items may only be defined once
+        let names = source.defined_names();
+        let mut other_names = other_source.defined_names();
+        other_names.sort();
+        other_names.dedup();
+        for name in other_names.into_iter().filter(|name| names.contains(name)) {
+            report.conflicts.push(format!("`{name}` is defined in both sessions"));
+        }
+
+        // Deduplicate import directives and pragmas within the global code
+        let existing_lines = source.global_code.lines().map(str::trim).collect::<Vec<_>>();
+        let mut pragmas = existing_lines
+            .iter()
+            .filter(|line| line.starts_with("pragma solidity"))
+            .map(|line| line.to_string())
+            .collect::<Vec<_>>();
+
+        // The pragmas of both sessions only conflict if no solc version that the session could
+        // switch to satisfies all of them: The installed versions, or any released version
+        // unless chisel is offline
+        let mut candidates = installed_solc_versions();
+        candidates.push(source.solc.version()?);
+        if !source.config.foundry_config.offline {
+            candidates.extend(
+                Solc::all_versions()
+                    .into_iter()
+                    .filter_map(|version| semver::Version::parse(&version.to_string()).ok()),
+            );
+        }
+        let satisfiable = |pragmas: &[String]| {
+            let reqs = pragmas
+                .iter()
+                .filter_map(|pragma| {
+                    let req = pragma.trim_start_matches("pragma solidity").trim();
+                    solidity_version_req(req.trim_end_matches(';'))
+                })
+                .collect::<Vec<_>>();
+            candidates
+                .iter()
+                .any(|version| reqs.iter().all(|req| req.iter().any(|req| req.matches(version))))
+        };
+
+        let mut global_code = String::new();
+        for line in other_source.global_code.lines() {
+            let trimmed = line.trim();
+            let is_directive = trimmed.starts_with("import") || trimmed.starts_with("pragma");
+            if is_directive && existing_lines.contains(&trimmed) {
+                report.deduplicated.push(trimmed.to_string());
+                continue
+            }
+
+            if trimmed.starts_with("pragma solidity") {
+                let mut merged = pragmas.clone();
+                merged.push(trimmed.to_string());
+                if !satisfiable(&merged) {
+                    let existing =
+                        pragmas.iter().map(|pragma| format!("`{pragma}`")).collect::<Vec<_>>();
+                    let conflict = match existing.is_empty() {
+                        true => format!("No solc version satisfies `{trimmed}`"),
+                        false => format!(
+                            "Conflicting pragmas: no solc version satisfies {} and `{trimmed}`",
+                            existing.join(", ")
+                        ),
+                    };
+                    report.conflicts.push(conflict);
+                    continue
+                }
+                pragmas = merged;
+            }
+
+            global_code.push_str(line);
+            global_code.push('\n');
+        }
+
+        if !report.is_merged() {
+            return Ok(report)
+        }
+
+        // Each section of the other session is merged as a single snippet
+        for (fragment, code) in [
+            (ParseTreeFragment::Source, global_code),
+            (ParseTreeFragment::Contract, other_source.top_level_code.clone()),
+            (ParseTreeFragment::Function, other_source.run_code.clone()),
+        ] {
+            if !code.is_empty() {
+                source.append_snippet(
+                    SolSnippet::new(fragment, code).with_origin(SnippetOrigin::File),
+                );
+            }
+        }
+        self.dirty = true;
+        Ok(report)
+    }
+
+    /// Compares the raw source of two sessions
+    ///
+    /// ### Takes
+    ///
+    /// - The old [ChiselSession]
+    /// - The new [ChiselSession]
+    ///
+    /// ### Returns
+    ///
+    /// A [SessionDiff] classifying each snippet of both sessions as added, removed, or unchanged.
+    /// Snippets are compared by their raw source text, section by section.
+    pub fn diff(a: &ChiselSession, b: &ChiselSession) -> SessionDiff {
+        let sections = |session: &ChiselSession| {
+            session.session_source.as_ref().map_or_else(
+                || [String::new(), String::new(), String::new()],
+                |source| {
+                    [
+                        source.global_code.clone(),
+                        source.top_level_code.clone(),
+                        source.run_code.clone(),
+                    ]
+                },
+            )
+        };
+        let (old, new) = (sections(a), sections(b));
+
+        let mut snippets = Vec::new();
+        for (section, (old, new)) in
+            [SourceSection::Global, SourceSection::TopLevel, SourceSection::Run]
+                .into_iter()
+                .zip(old.iter().zip(new.iter()))
+        {
+            let diff = TextDiff::from_lines(old.as_str(), new.as_str());
+            snippets.extend(diff.iter_all_changes().map(|change| SnippetDiff {
+                section,
+                change: match change.tag() {
+                    ChangeTag::Insert => SnippetChange::Added,
+                    ChangeTag::Delete => SnippetChange::Removed,
+                    ChangeTag::Equal => SnippetChange::Unchanged,
+                },
+                source: change.value().trim_end_matches('\n').to_string(),
+            }));
+        }
+
+        SessionDiff { snippets, old_source: a.contract_source(), new_source: b.contract_source() }
+    }
+
+    /// Prunes cached sessions that have not been modified within the given duration
+    ///
+    /// ### Takes
+    ///
+    /// - The maximum age of a session before it is pruned
+    /// - The number of most recently modified sessions to always preserve, regardless of age
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the file names of the pruned sessions. Fails if the cache directory could not
+    /// be read.
+    ///
+    /// ### Notes
+    ///
+    /// Files within the cache directory that are not chisel sessions are never deleted.
+    pub fn prune_sessions(
+        &mut self,
+        older_than: Duration,
+        keep_latest: usize,
+    ) -> Result<Vec<String>> {
+        // Lock the cache for the whole prune, so that it cannot race a concurrent write. Sessions
+        // are listed most recent first.
+        let _lock = self.lock_cache()?;
+        let sessions = self.read_sessions()?;
+
+        let now = SystemTime::now();
+        let mut pruned = Vec::new();
+        for info in sessions.into_iter().skip(keep_latest) {
+            if now.duration_since(info.modified).unwrap_or_default() <= older_than {
+                continue
+            }
+            self.remove_session_files(&info.path, &info.id)?;
+
+            // If the current session was pruned, reset its ID.
+            if self.id.as_ref() == Some(&info.id) {
+                self.id = None;
+            }
+            pruned.push(info.file_name());
+        }
+        Ok(pruned)
+    }
+
+    /// Writes the ChiselSession to a file by serializing it to a JSON string
+    ///
+    /// ### Returns
+    ///
+    /// Returns the path of the new cache file
+    ///
+    /// ### Notes
+    ///
+    /// - Sessions that serialize to more than [COMPRESSION_THRESHOLD] bytes are gzip compressed and
+    ///   written to `chisel-<id>.json.gz` instead.
+    /// - The session's journal is truncated upon a successful write.
+    /// - If the cache exceeds its [CacheLimits] afterwards, the least recently modified sessions
+    ///   are evicted. See [ChiselSession::take_evicted].
+    pub fn write(&mut self) -> Result<PathBuf> {
+        // Wait for any in-flight autosave so that it cannot clobber this write. Its result is
+        // irrelevant, as this write supersedes it.
+        let _ = self.flush_autosave();
+
+        // Create the cache directory and lock it for the duration of the write
+        let _lock = self.lock_cache()?;
+        self.write_unlocked()
+    }
+
+    /// Writes the session to its cache file without acquiring the cache lock. The caller must
+    /// hold the lock. See [ChiselSession::write].
+    fn write_unlocked(&mut self) -> Result<PathBuf> {
+        let cache_file_name = match self.id.as_ref() {
+            Some(id) => {
+                // ID is already set- use the existing cache file.
+                self.cache_file(id)
+            }
+            None => {
+                // Get the next session cache ID / file
+                let (id, file_name) = self.next_cached_session_unlocked()?;
+                // Set the session's ID
+                self.id = Some(id);
+                // Return the new session's cache file name
+                file_name
+            }
+        };
+
+        // Write the current ChiselSession to that file
+        let serialized_contents = self.serialize_for_cache()?;
+        let cache_file_name = write_cache_file(&cache_file_name, serialized_contents)?;
+        self.dirty = false;
+
+        // The journal only holds inputs that have not been written yet; Truncate it.
+        if let Some(journal_file) = self.journal_file() {
+            if journal_file.exists() {
+                std::fs::remove_file(journal_file)?;
+            }
+        }
+
+        // Keep the cache within its limits
+        let evicted = self.evict_sessions()?;
+        self.evicted.extend(evicted);
+
+        // Return the full cache file path
+        // Ex: /home/user/.foundry/cache/chisel/chisel-0.json
+        Ok(cache_file_name)
+    }
+
+    /// Persists the session to the cache in the background, if autosave is enabled.
+    ///
+    /// The session is serialized on the calling thread, and the serialized contents are handed
+    /// off to a background thread that writes them to the session's cache file.
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, nothing. Fails if the session could not be serialized, or if the previous
+    /// background write failed.
+    pub fn autosave(&mut self) -> Result<()> {
+        if !self.autosave {
+            return Ok(())
+        }
+
+        // Writes are serialized so that an older autosave never overwrites a newer one.
+        let res = self.flush_autosave();
+
+        let id = match self.id.as_ref() {
+            Some(id) => id.clone(),
+            // The session does not have an ID yet; Reserve one with a synchronous write.
+            None => return res.and(self.write().map(|_| ())),
+        };
+
+        let serialized_contents = self.serialize_for_cache()?;
+        let cache_dir = self.cache_dir.clone();
+        let cache_file_name = self.cache_file(&id);
+
+        self.autosave_handle = Some(std::thread::spawn(move || {
+            let _lock = lock_cache_dir(&cache_dir)?;
+            write_cache_file(&cache_file_name, serialized_contents).map(|_| ())
+        }));
+        self.dirty = false;
+        res
+    }
+
+    /// Serializes the session for its cache file
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the serialized session, stamped with the current cache format version and the
+    /// checksum of its source.
+    ///
+    /// ### Notes
+    ///
+    /// The `[rpc_endpoints]` of the session's configuration are left out, as they may contain API
+    /// keys. They are restored from the project when the session is loaded, see
+    /// [ChiselSession::inherit_rpc_endpoints].
+    fn serialize_for_cache(&mut self) -> Result<String> {
+        self.format_version = CACHE_FORMAT_VERSION;
+        self.checksum = self.session_source.as_ref().map(source_checksum);
+        let endpoints = self
+            .session_source
+            .as_mut()
+            .map(|source| std::mem::take(&mut source.config.foundry_config.rpc_endpoints));
+        let serialized = serde_json::to_string_pretty(self);
+        if let (Some(source), Some(endpoints)) = (self.session_source.as_mut(), endpoints) {
+            source.config.foundry_config.rpc_endpoints = endpoints;
+        }
+        Ok(serialized?)
+    }
+
+    /// Honors the `[rpc_endpoints]` of the project that chisel was launched in, i.e. after the
+    /// session was loaded from the cache
+    ///
+    /// ### Takes
+    ///
+    /// The `[rpc_endpoints]` of the configuration that chisel was launched with
+    ///
+    /// ### Notes
+    ///
+    /// The endpoints take precedence over those of the session's own project of the same alias.
+    pub fn inherit_rpc_endpoints(&mut self, endpoints: &RpcEndpoints) {
+        if let Some(source) = self.session_source.as_mut() {
+            for (alias, endpoint) in endpoints.iter() {
+                source.config.foundry_config.rpc_endpoints.insert(alias.clone(), endpoint.clone());
+            }
+        }
+    }
+
+    /// Appends an accepted input to the session's journal, so that it can be recovered via
+    /// [ChiselSession::recover] if chisel exits before the session is written.
+    ///
+    /// ### Takes
+    ///
+    /// The raw input, exactly as it was entered
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, nothing. Fails if the session does not have an ID or the journal could not
+    /// be written to.
+    pub fn journal(&self, input: &str) -> Result<()> {
+        let journal_file =
+            self.journal_file().ok_or(eyre::eyre!("Cannot journal a session without an ID!"))?;
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(journal_file)?;
+
+        // Each entry is a JSON string, so that multi-line inputs occupy a single line.
+        writeln!(file, "{}", serde_json::to_string(input)?)?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    /// Lists the sessions that have journaled inputs which were never written to their cache
+    /// file, i.e. because chisel crashed.
+    ///
+    /// ### Returns
+    ///
+    /// The IDs of the sessions whose journal is newer than their cache file.
+    pub fn recoverable_sessions(&self) -> Vec<String> {
+        let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        self.list_sessions()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|info| {
+                let journal_file = self.journal_file_for(&info.id);
+                let non_empty = std::fs::metadata(&journal_file).map_or(false, |m| m.len() > 0);
+                non_empty && modified(&journal_file).map_or(false, |time| time >= info.modified)
+            })
+            .map(|info| info.id)
+            .collect()
+    }
+
+    /// Recovers a session by replaying its journal on top of its cache file
+    ///
+    /// ### Takes
+    ///
+    /// The ID of the session to recover
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the recovered, rehydrated session. The recovered session is not written
+    /// to the cache until [ChiselSession::write] is called, which also truncates the journal.
+    pub async fn recover(&self, id: &str) -> Result<Self> {
+        Self::validate_id(id)?;
+        let mut chisel_env = Self::from_cache_file(&self.existing_cache_file(id), self.lenient)?;
+        chisel_env.cache_dir = self.cache_dir.clone();
+        chisel_env.cache_limits = self.cache_limits;
+        chisel_env.redefinition_policy = self.redefinition_policy;
+        chisel_env.lenient = self.lenient;
+
+        // Session commands are journaled alongside the inputs they apply to, and are replayed
+        // just as the dispatcher applies them
+        let journal = std::fs::read_to_string(self.journal_file_for(id))?;
+        for (i, entry) in journal.lines().filter(|line| !line.trim().is_empty()).enumerate() {
+            let input: String = serde_json::from_str(entry)
+                .map_err(|e| eyre::eyre!("Invalid journal entry #{}: {e}", i + 1))?;
+            ChiselDispatcher::replay_input(&mut chisel_env, &input).await?;
+        }
+
+        chisel_env.rehydrate()?;
+        chisel_env.dirty = true;
+        Ok(chisel_env)
+    }
+
+    /// Waits for the in-flight background write started by [ChiselSession::autosave], if any.
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, nothing. Fails if the background write failed.
+    pub fn flush_autosave(&mut self) -> Result<()> {
+        match self.autosave_handle.take() {
+            Some(handle) => handle.join().map_err(|_| eyre::eyre!("Autosave thread panicked!"))?,
+            None => Ok(()),
+        }
+    }
+
+    /// Takes the IDs of the sessions that were evicted from the cache by the writes since they
+    /// were last taken, including the write that reserves a new session's ID
+    ///
+    /// ### Returns
+    ///
+    /// The IDs of the evicted sessions, oldest eviction first
+    pub fn take_evicted(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.evicted)
+    }
+
+    /// Evicts the least recently modified sessions from the cache until it is within its
+    /// [CacheLimits]. The current session is never evicted. The caller must hold the cache lock.
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the IDs of the evicted sessions.
+    fn evict_sessions(&self) -> Result<Vec<String>> {
+        // Sessions are listed most recent first
+        let mut sessions = self
+            .list_sessions()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|info| {
+                let files = [
+                    info.path.clone(),
+                    self.history_file_for(&info.id),
+                    self.journal_file_for(&info.id),
+                ];
+                let size = files
+                    .iter()
+                    .filter_map(|path| std::fs::metadata(path).ok())
+                    .map(|metadata| metadata.len())
+                    .sum::<u64>();
+                (info, size)
+            })
+            .collect::<Vec<_>>();
+
+        let mut total_size = sessions.iter().map(|(_, size)| size).sum::<u64>();
+        let mut evicted = Vec::new();
+        while total_size > self.cache_limits.max_cache_size_bytes ||
+            sessions.len() > self.cache_limits.max_sessions
+        {
+            // Find the least recently modified session that is not the current session
+            let index =
+                match sessions.iter().rposition(|(info, _)| self.id.as_ref() != Some(&info.id)) {
+                    Some(index) => index,
+                    None => break,
+                };
+            let (info, size) = sessions.remove(index);
+            self.remove_session_files(&info.path, &info.id)?;
+            tracing::info!("evicted {} from the chisel cache", info.file_name());
+            total_size -= size;
+            evicted.push(info.id);
+        }
+        Ok(evicted)
+    }
+
+    /// Removes a session's cache file along with its history and journal
+    ///
+    /// ### Takes
+    ///
+    /// - The path of the session's cache file
+    /// - The ID of the session
+    fn remove_session_files(&self, cache_file: &Path, id: &str) -> Result<()> {
+        std::fs::remove_file(cache_file)?;
+        for file in [self.history_file_for(id), self.journal_file_for(id)] {
+            if file.exists() {
+                std::fs::remove_file(file)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the ChiselSession to the cache under a user-supplied name
+    ///
+    /// ### Takes
+    ///
+    /// The name of the session, i.e. `my_session` is persisted to `chisel-my_session.json`.
+    ///
+    /// ### Returns
+    ///
+    /// Returns the path of the cache file. Fails if another session is already cached under the
+    /// name, or if the session could not be written, in which case it keeps its previous ID.
+    ///
+    /// ### Notes
+    ///
+    /// A numeric ID only reserves the session's place in the cache, so it is released once the
+    /// session is named: its cache file and journal are removed, and its history is kept under
+    /// the name.
+    pub fn write_as(&mut self, name: &str) -> Result<PathBuf> {
+        Self::validate_id(name)?;
+
+        // Hold the cache lock from the existence check through the write, so that no other
+        // process can claim the name in between
+        let _ = self.flush_autosave();
+        let _lock = self.lock_cache()?;
+        if self.id.as_deref() != Some(name) && self.existing_cache_file(name).exists() {
+            eyre::bail!(
+                "A session named \"{name}\" already exists! Delete it with `!delete {name}` or choose another name."
+            )
+        }
+
+        let previous = self.id.replace(name.to_owned());
+        let cache_file = match self.write_unlocked() {
+            Ok(cache_file) => cache_file,
+            Err(e) => {
+                self.id = previous;
+                return Err(e)
+            }
+        };
+
+        let previous = previous
+            .filter(|previous| previous != name && previous.chars().all(|c| c.is_ascii_digit()));
+        if let Some(previous) = previous {
+            let history_file = self.history_file_for(&previous);
+            if history_file.exists() && !self.history_file_for(name).exists() {
+                std::fs::rename(history_file, self.history_file_for(name))?;
+            }
+            let previous_file = self.existing_cache_file(&previous);
+            if previous_file.exists() {
+                self.remove_session_files(&previous_file, &previous)?;
+            }
+        }
+        Ok(cache_file)
+    }
+
+    /// Validates that a session ID can safely be used as part of a cache file name
+    ///
+    /// ### Takes
+    ///
+    /// The session ID to validate
+    ///
+    /// ### Returns
+    ///
+    /// The unit type if the ID is valid, or an error describing why it is not.
+    pub fn validate_id(id: &str) -> Result<()> {
+        if id.is_empty() {
+            eyre::bail!("Session name cannot be empty!")
+        }
+        if id == "." || id == ".." {
+            eyre::bail!("\"{id}\" is not a valid session name!")
+        }
+        if let Some(c) = id.chars().find(|c| {
+            c.is_control() || matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|')
+        }) {
+            eyre::bail!("Session name \"{}\" contains an invalid character: {:?}", id, c)
+        }
+        Ok(())
+    }
+
+    /// Get the next default session cache file name
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, returns a tuple containing the next cached session's id and file name.
+    pub fn next_cached_session(&self) -> Result<(String, PathBuf)> {
+        let _lock = self.lock_cache()?;
+        self.next_cached_session_unlocked()
+    }
+
+    /// Gets the next session cache ID / file without acquiring the cache lock. The caller must
+    /// hold the lock.
+    fn next_cached_session_unlocked(&self) -> Result<(String, PathBuf)> {
+        let entries = std::fs::read_dir(&self.cache_dir)?;
+
+        // Find the highest numeric ID present in the cache. Named sessions and files that
+        // are not chisel sessions are skipped.
+        let mut latest_id: Option<usize> = None;
+        for entry in entries {
+            let file_name = entry?.file_name();
+            if let Some(id) = file_name.to_str().and_then(numeric_session_id) {
+                latest_id = Some(latest_id.map_or(id, |latest| latest.max(id)));
+            }
+        }
+
+        // If there are no existing numbered sessions, start at "chisel-0.json"
+        let session_num = match latest_id {
+            Some(id) => id.checked_add(1).ok_or_else(|| {
+                eyre::eyre!(
+                    "No session IDs are left after {id}! Delete or rename the session with the highest ID."
+                )
+            })?,
+            None => 0,
+        };
+        let id = format!("{session_num}");
+        let file_name = self.cache_file(&id);
+        Ok((id, file_name))
+    }
+
+    /// The default Chisel Cache Directory
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the directory set in the `CHISEL_CACHE_DIR` environment variable. If the
+    /// variable is not set, defaults to `~/.foundry/cache/chisel`.
+    pub fn default_cache_dir() -> Result<PathBuf> {
+        if let Some(cache_dir) = std::env::var_os(CACHE_DIR_ENV).filter(|dir| !dir.is_empty()) {
+            return Ok(PathBuf::from(cache_dir))
+        }
+        let home_dir = dirs::home_dir().ok_or(eyre::eyre!("Failed to grab home directory"))?;
+        Ok(home_dir.join(".foundry").join("cache").join("chisel"))
+    }
+
+    /// The Chisel Cache Directory of this session
+    ///
+    /// ### Returns
+    ///
+    /// The directory of the chisel cache.
+    pub fn cache_dir(&self) -> PathBuf {
+        self.cache_dir.clone()
+    }
+
+    /// The path of a session's cache file within the cache directory
+    ///
+    /// ### Takes
+    ///
+    /// The ID of the session
+    ///
+    /// ### Returns
+    ///
+    /// The path of the cache file, i.e. `~/.foundry/cache/chisel/chisel-0.json`
+    fn cache_file(&self, id: &str) -> PathBuf {
+        self.cache_dir.join(format!("chisel-{id}.json"))
+    }
+
+    /// The path of the session's readline history file within the cache directory
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the path of the history file, i.e. `~/.foundry/cache/chisel/chisel-0.history`.
+    /// Returns `None` if the session does not have an ID.
+    pub fn history_file(&self) -> Option<PathBuf> {
+        self.id.as_ref().map(|id| self.history_file_for(id))
+    }
+
+    /// The path of a session's readline history file within the cache directory
+    ///
+    /// ### Takes
+    ///
+    /// The ID of the session
+    ///
+    /// ### Returns
+    ///
+    /// The path of the history file, i.e. `~/.foundry/cache/chisel/chisel-0.history`
+    fn history_file_for(&self, id: &str) -> PathBuf {
+        self.cache_dir.join(format!("chisel-{id}.history"))
+    }
+
+    /// The path of the session's journal file within the cache directory
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the path of the journal file, i.e. `~/.foundry/cache/chisel/chisel-0.journal`.
+    /// Returns `None` if the session does not have an ID.
+    pub fn journal_file(&self) -> Option<PathBuf> {
+        self.id.as_ref().map(|id| self.journal_file_for(id))
+    }
+
+    /// The path of a session's journal file within the cache directory
+    fn journal_file_for(&self, id: &str) -> PathBuf {
+        self.cache_dir.join(format!("chisel-{id}.journal"))
+    }
+
+    /// The path of a session's existing cache file within the cache directory, which may be
+    /// compressed.
+    ///
+    /// ### Takes
+    ///
+    /// The ID of the session
+    ///
+    /// ### Returns
+    ///
+    /// The path of the compressed cache file if it exists, otherwise the path of the
+    /// uncompressed cache file.
+    fn existing_cache_file(&self, id: &str) -> PathBuf {
+        let cache_file = self.cache_file(id);
+        let compressed = compressed_path(&cache_file);
+        if compressed.exists() {
+            compressed
+        } else {
+            cache_file
+        }
+    }
+
+    /// Create the cache directory if it does not exist
+    ///
+    /// ### Returns
+    ///
+    /// The unit type if the operation was successful.
+    pub fn create_cache_dir(&self) -> Result<()> {
+        if !self.cache_dir.exists() {
+            std::fs::create_dir_all(&self.cache_dir)?;
+        }
+        Ok(())
+    }
+
+    /// Lists all available cached sessions
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, a vector containing a [SessionInfo] for each cached session, sorted by the
+    /// time they were last modified, most recent first. Cache files that could not be read are
+    /// reported with their [SessionInfo::error] set rather than aborting the listing.
+    ///
+    /// ### Notes
+    ///
+    /// Only files matching `chisel-<id>.json` are listed; The lock file, temporary files, and
+    /// any other files within the cache directory are skipped.
+    pub fn list_sessions(&self) -> Result<Vec<SessionInfo>> {
+        let sessions = self.read_sessions()?;
+        if sessions.is_empty() {
+            eyre::bail!("No sessions found!")
+        }
+        Ok(sessions)
+    }
+
+    /// Lists the cached sessions like [ChiselSession::list_sessions], without failing if there
+    /// are none.
+    fn read_sessions(&self) -> Result<Vec<SessionInfo>> {
+        // Read the cache directory entries
+        let entries = std::fs::read_dir(&self.cache_dir)?;
+
+        // For each session file, peek into the cache file for its info
+        let mut sessions = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let id = match file_name.to_str().and_then(session_id_from_file_name) {
+                Some(id) => id,
+                None => continue,
+            };
+            let metadata = entry.metadata()?;
+            if !metadata.is_file() {
+                continue
+            }
+            sessions.push(SessionInfo::peek(id.to_string(), entry.path(), metadata.modified()?));
+        }
+
+        // Sort the sessions by their modified time, most recent first
+        sessions.sort_by(|a, b| b.modified.cmp(&a.modified));
+        Ok(sessions)
+    }
+
+    /// Loads a specific ChiselSession from the specified cache file
+    ///
+    /// ### Takes
+    ///
+    /// The ID of the chisel session that you wish to load. This may either be a numeric ID or
+    /// the name that the session was saved with.
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, an owned instance of the loaded, rehydrated chisel session. The loaded
+    /// session shares this session's cache directory. If the session does not exist, the error
+    /// lists the IDs of the sessions that do.
+    pub fn load(&self, id: &str) -> Result<Self> {
+        Self::validate_id(id)?;
+        let cache_file = self.existing_cache_file(id);
+        if !cache_file.exists() {
+            let mut ids = self
+                .list_sessions()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|info| info.id)
+                .collect::<Vec<_>>();
+            if ids.is_empty() {
+                eyre::bail!("Session {id} not found! No sessions are cached.")
+            }
+            // Numeric IDs first, in ascending order, followed by named sessions.
+            ids.sort_by_key(|id| (id.parse::<usize>().map_err(|_| id.clone()), id.clone()));
+            eyre::bail!("Session {id} not found! Available sessions: {}", ids.join(", "))
+        }
+
+        let mut chisel_env = Self::from_cache_file(&cache_file, self.lenient)?;
+        chisel_env.cache_dir = self.cache_dir.clone();
+        chisel_env.cache_limits = self.cache_limits;
+        chisel_env.redefinition_policy = self.redefinition_policy;
+        chisel_env.lenient = self.lenient;
+        chisel_env.rehydrate()?;
+        Ok(chisel_env)
+    }
+
+    /// Gets the most recent chisel session from the cache dir
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the path of the most recently modified cached session.
+    pub fn latest_cached_session(&self) -> Result<PathBuf> {
+        // Sessions are listed most recent first
+        self.list_sessions()?
+            .into_iter()
+            .find(|info| !info.is_empty())
+            .map(|info| info.path)
+            .ok_or(eyre::eyre!("No entries found!"))
+    }
+
+    /// Loads the latest ChiselSession from the cache file
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, an owned instance of the most recently modified, rehydrated cached session.
+    /// The loaded session shares this session's cache directory.
+    ///
+    /// ### Notes
+    ///
+    /// - Empty sessions, whose IDs were reserved by chisel processes that never entered anything,
+    ///   are skipped. See [SessionInfo::is_empty].
+    /// - Corrupted sessions are skipped in favor of the next most recently modified session, and
+    ///   recorded in the loaded session's [ChiselSession::skipped_sessions]. If every cached
+    ///   session is corrupted, the error of the most recent one is returned.
+    pub fn latest(&self) -> Result<Self> {
+        let mut skipped = Vec::new();
+        for info in self.list_sessions()?.into_iter().filter(|info| !info.is_empty()) {
+            match Self::from_cache_file(&info.path, self.lenient) {
+                Ok(mut chisel_env) => {
+                    chisel_env.cache_dir = self.cache_dir.clone();
+                    chisel_env.cache_limits = self.cache_limits;
+                    chisel_env.redefinition_policy = self.redefinition_policy;
+                    chisel_env.lenient = self.lenient;
+                    chisel_env.rehydrate()?;
+                    chisel_env.skipped_sessions = skipped;
+                    return Ok(chisel_env)
+                }
+                Err(e) => match e.downcast::<SessionError>() {
+                    Ok(e) => {
+                        tracing::warn!(%e, "skipping corrupted session");
+                        skipped.push(e);
+                    }
+                    Err(e) => return Err(e),
+                },
+            }
+        }
+        Err(skipped
+            .into_iter()
+            .next()
+            .map(eyre::Report::new)
+            .unwrap_or_else(|| eyre::eyre!("No sessions found!")))
+    }
+
+    /// Restores the state of a deserialized session that is not persisted in the cache, leaving
+    /// it as usable as a freshly created session.
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, nothing. Fails if the session's solc version could not be restored or its
+    /// source could not be compiled.
+    ///
+    /// ### Notes
+    ///
+    /// - If the solc binary that the session was cached with no longer exists, the same version is
+    ///   resolved through SVM, installing it if necessary.
+    /// - Sessions created within a foundry project load the project's configuration again. If it
+    ///   can not be loaded, i.e. because the project was moved, the cached configuration is kept.
+    /// - The session's [crate::session_source::GeneratedOutput] is regenerated from its source.
+    pub fn rehydrate(&mut self) -> Result<()> {
+        let source =
+            self.session_source.as_mut().ok_or(eyre::eyre!("Session source not present!"))?;
+        if let Err(e) = source.reload_project_config() {
+            tracing::warn!(%e, "failed to reload the configuration of the session's project");
+        }
+        if !source.solc.solc.exists() {
+            self.reset_solc()?;
+        }
+
+        self.session_source.as_mut().unwrap().build()?;
+        Ok(())
+    }
+
+    /// Resolves the solc binary of the session's source again, i.e. after it was removed
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, nothing. Fails if the session's solc version could not be determined or
+    /// installed.
+    ///
+    /// ### Notes
+    ///
+    /// The version is determined from the binary if it still runs, or else from its SVM path.
+    /// The solc binary of that version is then resolved through SVM, installing it if
+    /// necessary, and the session's compiled output is discarded.
+    pub fn reset_solc(&mut self) -> Result<()> {
+        let source =
+            self.session_source.as_mut().ok_or(eyre::eyre!("Session source not present!"))?;
+        let version = match source.solc.version() {
+            Ok(version) => version.to_string(),
+            Err(_) => solc_version_from_path(&source.solc.solc).ok_or(eyre::eyre!(
+                "Could not determine the solc version of the session from {}!",
+                source.solc.solc.display()
+            ))?,
+        };
+        source.solc = find_or_install_solc(&version, source.config.foundry_config.offline)?;
+        source.generated_output = None;
+        source.output_checksum = None;
+        Ok(())
+    }
+
+    /// Acquires an exclusive, advisory lock on the session's cache directory. See
+    /// `lock_cache_dir`.
+    fn lock_cache(&self) -> Result<CacheLock> {
+        lock_cache_dir(&self.cache_dir)
+    }
+
+    /// Reads a cache file and deserializes it, verifying its integrity.
+    ///
+    /// ### Takes
+    ///
+    /// - The path of the cache file
+    /// - Whether to skip snippets that fail to parse, rather than failing to load the session
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the deserialized [ChiselSession]. Fails with [SessionError::SessionCorrupted]
+    /// if the cache file could not be parsed, any of its snippets fail to parse, or its checksum
+    /// does not match its contents.
+    ///
+    /// ### Notes
+    ///
+    /// When loading leniently, the skipped snippets are collected into
+    /// [ChiselSession::broken_snippets]. As a session with broken snippets has been modified
+    /// since it was cached, its checksum is not verified in that case.
+    fn from_cache_file(path: &Path, lenient: bool) -> Result<Self> {
+        let corrupted =
+            |reason: String| SessionError::SessionCorrupted { path: path.to_path_buf(), reason };
+        let contents =
+            decode_cache_file(std::fs::read(path)?).map_err(|e| corrupted(e.to_string()))?;
+
+        let mut session = Self::from_cache_contents(&contents).map_err(|e| match e
+            .downcast::<serde_json::Error>()
+        {
+            Ok(e) => corrupted(e.to_string()).into(),
+            Err(e) => e,
+        })?;
+
+        let broken = session.session_source.as_ref().map_or_else(Vec::new, |source| {
+            source
+                .snippets
+                .iter()
+                .enumerate()
+                .filter_map(|(position, snippet)| {
+                    let index = source.snippet_index_of(position);
+                    snippet.validate().err().map(|diagnostics| BrokenSnippet { index, diagnostics })
+                })
+                .collect::<Vec<_>>()
+        });
+        if !broken.is_empty() {
+            if !lenient {
+                let reasons = broken.iter().map(ToString::to_string).collect::<Vec<_>>();
+                return Err(corrupted(reasons.join("\n")).into())
+            }
+            // Snippets are dropped with all of their parts
+            if let Some(source) = session.session_source.as_mut() {
+                let snippets = source
+                    .snippets
+                    .iter()
+                    .enumerate()
+                    .filter(|(position, _)| {
+                        let index = source.snippet_index_of(*position);
+                        !broken.iter().any(|b| b.index == index)
+                    })
+                    .map(|(_, snippet)| snippet.clone())
+                    .collect();
+                source.replace_snippets(snippets).map_err(|e| corrupted(e.to_string()))?;
+            }
+            session.broken_snippets = broken;
+            session.dirty = true;
+            return Ok(session)
+        }
+
+        if let (Some(checksum), Some(source)) = (&session.checksum, &session.session_source) {
+            if *checksum != source_checksum(source) {
+                return Err(corrupted(String::from("checksum mismatch")).into())
+            }
+        }
+        Ok(session)
+    }
+
+    /// Deserializes the contents of a cache file into a [ChiselSession], migrating sessions
+    /// that were cached with an older cache format.
+    ///
+    /// ### Takes
+    ///
+    /// The raw contents of a cache file
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the deserialized [ChiselSession]. Fails if the session was cached by a newer
+    /// version of chisel.
+    fn from_cache_contents(contents: &str) -> Result<Self> {
+        let mut session: serde_json::Value = serde_json::from_str(contents)?;
+
+        // Sessions saved before the cache format was versioned do not have a version
+        let version = match session.get("format_version") {
+            Some(version) => {
+                version.as_u64().ok_or(eyre::eyre!("Invalid cache format version: {version}"))?
+            }
+            None => 0,
+        };
+        let version = match u32::try_from(version) {
+            Ok(version) if version <= CACHE_FORMAT_VERSION => version,
+            _ => eyre::bail!(
+                "This session was saved by a newer chisel (cache format version {}, supported: {}). Please update chisel to load it.",
+                version,
+                CACHE_FORMAT_VERSION
+            ),
+        };
+
+        // Migrate the session up to the current format, one version at a time.
+        for from in version..CACHE_FORMAT_VERSION {
+            session = migrate_cached_session(session, from)?;
+        }
+
+        Ok(serde_json::from_value(session)?)
+    }
+}
+
+impl SessionInfo {
+    /// Peeks into a cache file to gather information about the session within it, without
+    /// fully deserializing the session.
+    ///
+    /// ### Takes
+    ///
+    /// - The ID of the session
+    /// - The path of the cache file
+    /// - The last time the cache file was modified
+    ///
+    /// ### Returns
+    ///
+    /// A [SessionInfo]. If the cache file could not be read, the `error` field is set.
+    pub fn peek(id: String, path: PathBuf, modified: SystemTime) -> Self {
+        let mut info = Self {
+            id,
+            path,
+            modified,
+            solc_version: None,
+            snippet_count: 0,
+            description: None,
+            tags: Vec::new(),
+            error: None,
+        };
+
+        let session = match std::fs::read(&info.path)
+            .and_then(decode_cache_file)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| {
+                serde_json::from_str::<serde_json::Value>(&contents).map_err(|e| e.to_string())
+            }) {
+            Ok(session) => session,
+            Err(e) => {
+                info.error = Some(e);
+                return info
+            }
+        };
+
+        info.description =
+            session.get("description").and_then(|d| d.as_str()).map(ToString::to_string);
+        info.tags = session
+            .get("tags")
+            .and_then(|tags| tags.as_array())
+            .map(|tags| tags.iter().filter_map(|t| t.as_str().map(ToString::to_string)).collect())
+            .unwrap_or_default();
+
+        match session.get("session_source") {
+            Some(source) if source.is_object() => {
+                // The later parts of split inputs are not counted
+                info.snippet_count = source
+                    .get("snippets")
+                    .and_then(|snippets| snippets.as_array())
+                    .map_or(0, |snippets| {
+                        snippets
+                            .iter()
+                            .filter(|snippet| {
+                                snippet.get("continued").and_then(|c| c.as_bool()) != Some(true)
+                            })
+                            .count()
+                    });
+                info.solc_version = source
+                    .get("solc")
+                    .and_then(|solc| solc.get("solc"))
+                    .and_then(|path| path.as_str())
+                    .and_then(|path| solc_version_from_path(Path::new(path)));
+            }
+            _ => info.error = Some(String::from("Missing session source")),
+        }
+        info
+    }
+
+    /// Whether the session holds nothing, i.e. a session whose ID was reserved upon creation but
+    /// that was never used
+    pub fn is_empty(&self) -> bool {
+        self.error.is_none() &&
+            self.snippet_count == 0 &&
+            self.description.is_none() &&
+            self.tags.is_empty()
+    }
+
+    /// Returns the file name of the session's cache file
+    pub fn file_name(&self) -> String {
+        self.path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default()
+    }
+
+    /// Returns the formatted last modified time of the session's cache file
+    pub fn formatted_modified(&self) -> String {
+        systemtime_strftime(self.modified, "[year]-[month]-[day] [hour]:[minute]:[second]")
+            .unwrap_or_default()
+    }
+}
+
+/// Checks whether a name is a valid Solidity identifier
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().map_or(false, |c| c.is_ascii_alphabetic() || c == '_' || c == '$') &&
+        chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+/// Generates a Solidity interface from a contract's ABI
+///
+/// ### Takes
+///
+/// - The name of the interface
+/// - The JSON ABI of the contract, or a JSON artifact containing it under the `abi` key
+///
+/// ### Returns
+///
+/// Optionally, the interface as a global-level [SolSnippet].
+fn interface_from_abi(name: &str, abi_json: &str) -> Result<SolSnippet> {
+    if !is_identifier(name) {
+        eyre::bail!("Invalid interface name \"{name}\"")
+    }
+
+    let mut abi: serde_json::Value =
+        serde_json::from_str(abi_json).map_err(|e| eyre::eyre!("Invalid JSON ABI: {e}"))?;
+    if let Some(artifact_abi) = abi.get_mut("abi") {
+        abi = artifact_abi.take();
+    }
+    let abi: RawAbi =
+        serde_json::from_value(abi).map_err(|e| eyre::eyre!("Invalid JSON ABI: {e}"))?;
+
+    let interface = foundry_utils::abi::abi_to_solidity(&abi, name)?;
+    let snippet = SolSnippet::try_parse(&interface)?;
+    if snippet.fragment != ParseTreeFragment::Source {
+        eyre::bail!("Generated interface `{name}` is not valid at file scope")
+    }
+    Ok(snippet)
+}
+
+/// Finds an SVM-installed solc binary of a version, if it exists on disk
+pub(crate) fn find_svm_solc(version: &str) -> Option<Solc> {
+    Solc::find_svm_installed_version(version).ok().flatten().filter(|solc| solc.solc.exists())
+}
+
+/// Lists the SVM-installed solc versions whose binaries exist on disk
+///
+/// ### Returns
+///
+/// The installed versions, oldest first.
+fn installed_solc_versions() -> Vec<semver::Version> {
+    let mut versions = Solc::installed_versions()
+        .into_iter()
+        .filter_map(|version| semver::Version::parse(&version.to_string()).ok())
+        .filter(|version| find_svm_solc(&version.to_string()).is_some())
+        .collect::<Vec<_>>();
+    versions.sort();
+    versions.dedup();
+    versions
+}
+
+/// Determines the solc version that new sessions are compiled with
+///
+/// ### Takes
+///
+/// The [SessionSourceConfig] of the session
+///
+/// ### Returns
+///
+/// The version set in the foundry configuration, i.e. with the `--use` flag, or else the newest
+/// installed version. Returns `None` if neither exists.
+fn default_solc_version(config: &SessionSourceConfig) -> Option<semver::Version> {
+    match config.foundry_config.solc.as_ref() {
+        Some(SolcReq::Version(version)) => {
+            Some(semver::Version::new(version.major, version.minor, version.patch))
+        }
+        _ => installed_solc_versions().pop(),
+    }
+}
+
+/// Resolves the SVM-installed solc binary of a version, installing it first if it is missing
+///
+/// ### Takes
+///
+/// - The solc version, i.e. `0.8.17`
+/// - Whether chisel is offline, in which case missing versions are not installed
+///
+/// ### Returns
+///
+/// Optionally, the [Solc] of the version. Fails with `Could not install solc <version>` if the
+/// version is missing and could not be installed.
+pub fn find_or_install_solc(version: &str, offline: bool) -> Result<Solc> {
+    if let Some(solc) = find_svm_solc(version) {
+        return Ok(solc)
+    }
+    if offline {
+        eyre::bail!(
+            "Could not install solc {version}; Installing solc versions is disabled while offline"
+        )
+    }
+
+    println!("{}", Paint::green(format!("Installing solidity version {version}...")));
+    Solc::find_or_install_svm_version(version)
+        .map_err(|e| eyre::eyre!("Could not install solc {version}: {e}"))
+}
+
+/// Attempts to infer the version of an SVM-installed solc binary from its path, i.e.
+/// `~/.svm/0.8.17/solc-0.8.17`.
+fn solc_version_from_path(path: &Path) -> Option<String> {
+    let file_name = path.file_name()?.to_str()?;
+    let version = file_name.strip_prefix("solc-").unwrap_or(file_name);
+    semver::Version::parse(version).ok().map(|version| version.to_string())
+}
+
+/// Migrates a serialized [ChiselSession] from cache format version `from` to `from + 1`.
+///
+/// ### Takes
+///
+/// - The serialized session
+/// - The cache format version that the session is currently in
+///
+/// ### Returns
+///
+/// Optionally, the serialized session in the next cache format version.
+fn migrate_cached_session(mut session: serde_json::Value, from: u32) -> Result<serde_json::Value> {
+    let obj = session.as_object_mut().ok_or(eyre::eyre!("Cached session is not a JSON object!"))?;
+    match from {
+        // v0 -> v1: Unversioned sessions may contain generated output from an older compiler
+        // pipeline. It is always regenerated from the raw source upon load, so drop it.
+        0 => {
+            if let Some(source) =
+                obj.get_mut("session_source").and_then(serde_json::Value::as_object_mut)
+            {
+                source.insert(String::from("generated_output"), serde_json::Value::Null);
+            }
+        }
+        // v1 -> v2: Snippets are classified by their kind upon entry. Classify the snippets of
+        // sessions cached before then.
+        1 => {
+            let snippets = obj
+                .get_mut("session_source")
+                .and_then(|source| source.get_mut("snippets"))
+                .and_then(serde_json::Value::as_array_mut);
+            for snippet in snippets.into_iter().flatten() {
+                let fragment: ParseTreeFragment =
+                    serde_json::from_value(snippet["fragment"].clone())?;
+                let code = snippet["code"].as_str().unwrap_or_default();
+                let kind = serde_json::to_value(SnippetKind::classify(fragment, code))?;
+                if let Some(snippet) = snippet.as_object_mut() {
+                    snippet.insert(String::from("kind"), kind);
+                }
+            }
+        }
+        _ => eyre::bail!("No migration exists for cache format version {from}!"),
+    }
+    obj.insert(String::from("format_version"), serde_json::Value::from(from + 1));
+    Ok(session)
+}
+
+/// Acquires an exclusive, advisory lock on the given cache directory, creating the directory
+/// if it does not exist.
+///
+/// ### Takes
+///
+/// The path of the cache directory
+///
+/// ### Returns
+///
+/// Optionally, a [CacheLock] that releases the lock when dropped. Fails if another process
+/// holds the lock for longer than [CACHE_LOCK_TIMEOUT].
+fn lock_cache_dir(cache_dir: &Path) -> Result<CacheLock> {
+    std::fs::create_dir_all(cache_dir)?;
+    let lock_path = cache_dir.join(CACHE_LOCK_FILE);
+    let file = std::fs::OpenOptions::new().create(true).write(true).open(&lock_path)?;
+
+    let start = Instant::now();
+    loop {
+        match file.try_lock_exclusive() {
+            Ok(()) => return Ok(CacheLock(file)),
+            Err(e) if e.kind() == fs2::lock_contended_error().kind() => {
+                if start.elapsed() >= CACHE_LOCK_TIMEOUT {
+                    eyre::bail!(
+                        "Timed out waiting for the chisel cache lock at {}; Is another chisel process running?",
+                        lock_path.display()
+                    )
+                }
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Sessions are automatically persisted by default
+fn default_autosave() -> bool {
+    true
+}
+
+/// Computes the checksum of a [SessionSource]'s raw source
+///
+/// ### Takes
+///
+/// The [SessionSource] to compute the checksum of
+///
+/// ### Returns
+///
+/// The hex-encoded keccak256 hash of the global, top level, and run code of the source.
+fn source_checksum(source: &SessionSource) -> String {
+    let raw = [&source.global_code, &source.top_level_code, &source.run_code]
+        .iter()
+        .map(|code| code.as_str())
+        .collect::<Vec<_>>()
+        .join("\0");
+    hex::encode(keccak256(raw.as_bytes()))
+}
+
+/// Writes a serialized session to its cache file, compressing it if it is larger than
+/// [COMPRESSION_THRESHOLD].
+///
+/// ### Takes
+///
+/// - The path of the session's uncompressed cache file, i.e. `chisel-0.json`
+/// - The serialized session
+///
+/// ### Returns
+///
+/// Optionally, the path of the written cache file. Any stale variant of the cache file, i.e.
+/// an uncompressed copy of a session that is now compressed, is removed so that each session
+/// only has one cache file.
+fn write_cache_file(cache_file: &Path, contents: String) -> Result<PathBuf> {
+    let compressed = compressed_path(cache_file);
+    let (path, stale) = if contents.len() > COMPRESSION_THRESHOLD {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(contents.as_bytes())?;
+        write_atomic(&compressed, &encoder.finish()?)?;
+        (compressed, cache_file.to_path_buf())
+    } else {
+        write_atomic(cache_file, contents.as_bytes())?;
+        (cache_file.to_path_buf(), compressed)
+    };
+
+    if stale.exists() {
+        std::fs::remove_file(stale)?;
+    }
+    Ok(path)
+}
+
+/// Decodes the raw contents of a cache file, transparently decompressing gzip compressed files.
+///
+/// ### Takes
+///
+/// The raw bytes of the cache file
+///
+/// ### Returns
+///
+/// Optionally, the serialized session.
+fn decode_cache_file(bytes: Vec<u8>) -> std::io::Result<String> {
+    if !bytes.starts_with(&GZIP_MAGIC) {
+        return String::from_utf8(bytes)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+    let mut contents = String::new();
+    GzDecoder::new(bytes.as_slice()).read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+/// Returns the path of the compressed variant of a cache file, i.e. `chisel-0.json.gz`
+fn compressed_path(cache_file: &Path) -> PathBuf {
+    let mut path = cache_file.as_os_str().to_owned();
+    path.push(".gz");
+    PathBuf::from(path)
+}
+
+/// Atomically writes `contents` to the file at `path`.
+///
+/// The contents are first written to a temporary file next to the target and synced to disk,
+/// after which the temporary file is renamed over the target. If the process is killed
+/// mid-write, the target file is left untouched rather than truncated.
+fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or(eyre::eyre!("Invalid cache file path!"))?;
+    let tmp_path = path.with_file_name(format!(".{file_name}.tmp"));
+
+    let res = (|| -> Result<()> {
+        let mut file = std::fs::File::create(&tmp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    })();
+
+    // Don't leave a dangling temporary file behind if anything failed.
+    if res.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    res
+}
+
+/// Parses the session ID out of a cached session's file name
+///
+/// ### Takes
+///
+/// The file name of a cache entry, i.e. `chisel-my_session.json` or `chisel-my_session.json.gz`
+///
+/// ### Returns
+///
+/// Optionally, the ID of the session. Returns `None` for files that are not chisel sessions.
+fn session_id_from_file_name(file_name: &str) -> Option<&str> {
+    let name = file_name.strip_prefix("chisel-")?;
+    let id = name.strip_suffix(".json.gz").or_else(|| name.strip_suffix(".json"))?;
+    ChiselSession::validate_id(id).ok().map(|_| id)
+}
+
+/// Parses the numeric ID out of a cached session's file name
+///
+/// ### Takes
+///
+/// The file name of a cache entry, i.e. `chisel-5.json`
+///
+/// ### Returns
+///
+/// Optionally, the numeric ID of the session. Returns `None` for named sessions and files
+/// that are not chisel sessions.
+fn numeric_session_id(file_name: &str) -> Option<usize> {
+    let id = session_id_from_file_name(file_name)?;
+    if !id.chars().all(|c| c.is_ascii_digit()) {
+        return None
     }
+    id.parse().ok()
 }
 
 /// Generic helper function that attempts to convert a type that has
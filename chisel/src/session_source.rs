@@ -4,24 +4,52 @@
 //! the REPL contract's source code. It provides simple compilation, parsing, and
 //! execution helpers.
 
+use crate::{
+    broadcast::BroadcastReceipt,
+    dispatcher::format_source,
+    format::{NumberFormat, ValueFormatter},
+    rpc::{is_alias, RpcAliases, REDACTED},
+    runner::{MachineState, PersistedState},
+    session::{find_or_install_solc, find_svm_solc},
+    storage::{moved_variables, MovedVariable},
+};
+use ethers::{
+    abi::Abi,
+    types::{Address, Bytes, Log, U256},
+    utils::{hex, keccak256},
+};
 use ethers_solc::{
-    artifacts::{Source, Sources},
-    CompilerInput, CompilerOutput, Solc,
+    artifacts::{
+        CompactBytecode, CompactContractBytecode, Libraries, Source, Sources, StorageLayout,
+    },
+    remappings::Remapping,
+    ArtifactId, CompilerInput, CompilerOutput, EvmVersion, Solc,
 };
 use eyre::Result;
 use forge::executor::{opts::EvmOpts, Backend};
-use foundry_config::Config;
-use semver::Version;
+use foundry_common::ContractsByArtifact;
+use foundry_config::{Config, RpcEndpoint};
+use semver::{Version, VersionReq};
 use serde::{Deserialize, Serialize};
-use solang_parser::pt;
-use std::{collections::HashMap, fs, path::PathBuf};
+use solang_parser::pt::{self, CodeLocation};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt, fs,
+    ops::Range,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+use time::{format_description, OffsetDateTime};
 
 /// Solidity source for the `Vm` interface in [forge-std](https://github.com/foundry-rs/forge-std)
-static VM_SOURCE: &str = include_str!("../../testdata/cheats/Cheats.sol");
+pub(crate) static VM_SOURCE: &str = include_str!("../../testdata/cheats/Cheats.sol");
 
 /// Intermediate output for the compiled [SessionSource]
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct IntermediateOutput {
+    /// The name of the REPL contract
+    #[serde(skip)]
+    pub repl_contract_name: String,
     /// All expressions within the REPL contract's run function and top level scope.
     #[serde(skip)]
     pub repl_contract_expressions: HashMap<String, pt::Expression>,
@@ -43,6 +71,9 @@ pub struct IntermediateContract {
     /// All struct definitions within the contract
     #[serde(skip)]
     pub struct_definitions: HashMap<String, Box<pt::StructDefinition>>,
+    /// All enum definitions within the contract
+    #[serde(skip)]
+    pub enum_definitions: HashMap<String, Box<pt::EnumDefinition>>,
     /// All variable definitions within the top level scope of the contract
     #[serde(skip)]
     pub variable_definitions: HashMap<String, Box<pt::VariableDefinition>>,
@@ -60,185 +91,2791 @@ pub struct GeneratedOutput {
     pub compiler_output: CompilerOutput,
 }
 
-/// Configuration for the [SessionSource]
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
-pub struct SessionSourceConfig {
-    /// Foundry configuration
-    pub foundry_config: Config,
-    /// EVM Options
-    pub evm_opts: EvmOpts,
-    #[serde(skip)]
-    /// In-memory REVM db for the session's runner.
-    pub backend: Option<Backend>,
-    /// Optionally enable traces for the REPL contract execution
-    pub traces: bool,
+/// Comment that marks the start of a snippet within the rendered source, followed by its index
+static SNIPPET_START_MARKER: &str = "// @chisel-snippet ";
+
+/// Comment that marks the end of a snippet within the rendered source
+static SNIPPET_END_MARKER: &str = "// @chisel-end";
+
+/// A map of line ranges within a rendered source to the indices of the snippets they were
+/// entered in. Line numbers start at 1.
+pub type SnippetMap = Vec<(Range<usize>, usize)>;
+
+/// A `pragma solidity` directive within the global code of a [SessionSource]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SolidityPragma {
+    /// The index of the snippet the pragma was entered in, if known
+    pub snippet_index: Option<usize>,
+    /// The raw version requirement of the pragma, i.e. `^0.8.17`
+    pub requirement: String,
+    /// Whether the requirement is satisfied by the session's solc version
+    pub compatible: bool,
 }
 
-/// REPL Session Source wrapper
+/// Error returned when the `pragma solidity` directives of a [SessionSource] can not all be
+/// satisfied by the session's solc version
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PragmaConflict {
+    /// The session's solc version
+    pub solc_version: Version,
+    /// All `pragma solidity` directives within the source, in order of entry
+    pub pragmas: Vec<SolidityPragma>,
+}
+
+impl fmt::Display for PragmaConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Conflicting pragma directives for solc {}:", self.solc_version)?;
+        for pragma in &self.pragmas {
+            let origin = match pragma.snippet_index {
+                Some(index) => format!("snippet {index}"),
+                None => String::from("unknown snippet"),
+            };
+            let status = if pragma.compatible { "" } else { " (incompatible)" };
+            write!(f, "\n  {origin}: pragma solidity {};{status}", pragma.requirement)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for PragmaConflict {}
+
+/// A single error produced while parsing a snippet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnippetDiagnostic {
+    /// The byte range of the offending code within the snippet
+    pub span: Range<usize>,
+    /// The message of the parser
+    pub message: String,
+}
+
+/// Error returned when a snippet can not be parsed within any scope of a [SessionSource]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDiagnostics {
+    /// The raw code of the snippet
+    pub code: String,
+    /// The errors of the parser, with spans relative to the snippet
+    pub diagnostics: Vec<SnippetDiagnostic>,
+}
+
+impl ParseDiagnostics {
+    /// Creates a new [ParseDiagnostics] from the diagnostics of parsing a snippet that was
+    /// wrapped in surrounding code
+    ///
+    /// ### Takes
+    ///
+    /// - The raw code of the snippet
+    /// - The byte offset of the snippet within the parsed code
+    /// - The diagnostics of the parser
+    pub(crate) fn new(
+        code: &str,
+        offset: usize,
+        diagnostics: Vec<solang_parser::diagnostics::Diagnostic>,
+    ) -> Self {
+        // Errors within the surrounding code are attributed to the end of the snippet
+        let clamp = |pos: usize| pos.saturating_sub(offset).min(code.len());
+        let diagnostics = diagnostics
+            .into_iter()
+            .map(|diagnostic| {
+                let span = match diagnostic.loc {
+                    pt::Loc::File(_, start, end) => clamp(start)..clamp(end),
+                    _ => code.len()..code.len(),
+                };
+                SnippetDiagnostic { span, message: diagnostic.message }
+            })
+            .collect();
+        Self { code: code.to_string(), diagnostics }
+    }
+
+    /// The byte offset of the first error within the snippet
+    fn first_error(&self) -> usize {
+        self.diagnostics.iter().map(|diagnostic| diagnostic.span.start).min().unwrap_or_default()
+    }
+
+    /// Converts a byte offset within the snippet into a line and column
+    ///
+    /// ### Takes
+    ///
+    /// The byte offset within the snippet
+    ///
+    /// ### Returns
+    ///
+    /// The 1-based line and column of the offset. Columns are counted in characters.
+    pub fn line_column(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.code.len());
+        let line_start = self.code[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line = self.code[..line_start].matches('\n').count() + 1;
+        (line, self.code[line_start..offset].chars().count() + 1)
+    }
+}
+
+impl fmt::Display for ParseDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let gutter = self
+            .diagnostics
+            .iter()
+            .map(|diagnostic| self.line_column(diagnostic.span.start).0.to_string().len())
+            .max()
+            .unwrap_or(1);
+        for (i, diagnostic) in self.diagnostics.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write_underlined_span(
+                f,
+                &self.code,
+                &diagnostic.span,
+                &format!("error: {}", diagnostic.message),
+                "",
+                gutter,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes a message followed by the line of code it refers to, with the offending span
+/// underlined rustc-style
 ///
-/// Heavily based on soli's [`ConstructedSource`](https://github.com/jpopesculian/soli/blob/master/src/main.rs#L166)
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SessionSource {
-    /// The file name
-    pub file_name: PathBuf,
-    /// The contract name
-    pub contract_name: String,
-    /// The solidity compiler version
-    pub solc: Solc,
-    /// Global level solidity code
+/// ### Takes
+///
+/// - The writer to write to
+/// - The code containing the span
+/// - The byte range of the span within the code
+/// - The message heading the span
+/// - A prefix for the `line:column` location of the span, i.e. `snippet 2:`
+/// - The width of the line number gutter
+fn write_underlined_span(
+    f: &mut impl fmt::Write,
+    code: &str,
+    span: &Range<usize>,
+    heading: &str,
+    location: &str,
+    gutter: usize,
+) -> fmt::Result {
+    let start = span.start.min(code.len());
+    let line_start = code[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = code[start..].find('\n').map_or(code.len(), |i| start + i);
+    let line = code[..line_start].matches('\n').count() + 1;
+    let column = code[line_start..start].chars().count() + 1;
+    let end = span.end.clamp(start, line_end);
+    let width = code[start..end].chars().count().max(1);
+    write!(
+        f,
+        "{heading}\n{:gutter$}--> {location}{line}:{column}\n{:gutter$} |\n{line:>gutter$} | {}\n{:gutter$} | {}{}",
+        "",
+        "",
+        &code[line_start..line_end],
+        "",
+        " ".repeat(column - 1),
+        "^".repeat(width),
+    )
+}
+
+impl std::error::Error for ParseDiagnostics {}
+
+/// The location of a compiler error within the code of a snippet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnippetSpan {
+    /// The index of the snippet
+    pub index: usize,
+    /// The byte range of the offending code within the snippet
+    pub span: Range<usize>,
+}
+
+/// Error returned when the REPL contract of a [SessionSource] fails to compile
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompilerDiagnostics {
+    /// The errors reported by solc
+    pub errors: Vec<ethers_solc::artifacts::Error>,
+    /// The location of each error within the snippets, if it is located within one
+    pub locations: Vec<Option<SnippetSpan>>,
+    /// The errors, rendered against the snippets they originate from
+    pub descriptions: Vec<String>,
+    /// Whether the source was compiled via the IR pipeline
+    pub via_ir: bool,
+    /// The remappings that imports were resolved with, if an import failed to resolve
+    pub remappings: Option<Vec<Remapping>>,
+}
+
+impl fmt::Display for CompilerDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pipeline = if self.via_ir { "via IR" } else { "legacy pipeline" };
+        write!(f, "Compiler errors ({pipeline}):\n{}", self.descriptions.concat())?;
+        match &self.remappings {
+            Some(remappings) if remappings.is_empty() => {
+                write!(f, "note: No remappings are active. Add one with `!remap <from>=<to>`")
+            }
+            Some(remappings) => {
+                write!(f, "note: Imports were resolved with the remappings:")?;
+                remappings.iter().try_for_each(|remapping| write!(f, "\n  {remapping}"))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for CompilerDiagnostics {}
+
+/// Error returned when the REPL contract rendered from a [SessionSource] does not parse, even
+/// though its snippets do, see [SessionSource::validate]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeneratedSourceDiagnostics {
+    /// The location of each error within the snippet it is attributed to, if any
+    pub locations: Vec<Option<SnippetSpan>>,
+    /// The errors, rendered against the snippets they are attributed to
+    pub descriptions: Vec<String>,
+}
+
+impl GeneratedSourceDiagnostics {
+    /// The indices of the snippets that the errors are attributed to, in ascending order
+    pub fn snippets(&self) -> Vec<usize> {
+        self.locations
+            .iter()
+            .flatten()
+            .map(|location| location.index)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+}
+
+impl fmt::Display for GeneratedSourceDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let snippets = self.snippets();
+        match snippets.as_slice() {
+            [] => writeln!(f, "The generated REPL contract does not parse:")?,
+            [index] => writeln!(f, "Snippet {index} breaks the generated REPL contract:")?,
+            _ => {
+                let indices = snippets.iter().map(ToString::to_string).collect::<Vec<_>>();
+                writeln!(f, "Snippets {} break the generated REPL contract:", indices.join(", "))?
+            }
+        }
+        write!(f, "{}", self.descriptions.concat())
+    }
+}
+
+impl std::error::Error for GeneratedSourceDiagnostics {}
+
+/// The compiled REPL contract of a [SessionSource]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileResult {
+    /// The ABI of the REPL contract
+    pub abi: Option<Abi>,
+    /// The creation bytecode of the REPL contract, linked against its libraries
+    pub bytecode: Option<Bytes>,
+    /// The runtime bytecode of the REPL contract, linked against its libraries
+    pub deployed_bytecode: Option<Bytes>,
+    /// The libraries that the REPL contract is linked against, in order of deployment
+    pub libraries: Vec<LinkedLibrary>,
+    /// The other contracts, interfaces, and libraries defined within the session's source, by
+    /// name. Their creation bytecode is embedded into the REPL contract, so that statements can
+    /// deploy them with `new`.
+    pub contracts: Vec<SessionContract>,
+    /// The warnings reported by solc
+    pub warnings: Vec<ethers_solc::artifacts::Error>,
+    /// The gas estimates of solc for the REPL contract and the other compiled contracts
+    pub gas: GasReport,
+    /// Statistics on the build that produced the REPL contract, if it was built by this process
+    pub stats: Option<CompileStats>,
+}
+
+/// Statistics on a build of a [SessionSource], see [SessionSource::build]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompileStats {
+    /// Whether the output of the previous build was reused, as the compiler input did not change
+    pub cache_hit: bool,
+    /// The number of sources within the compiler output, including imported files
+    pub sources: usize,
+    /// The version of the solc binary that was invoked, if known
+    pub solc_version: Option<Version>,
+    /// The time it took to compute the checksum of the compiler input
+    pub checksum: Duration,
+    /// The time it took solc to compile the source. Zero upon a cache hit.
+    pub solc: Duration,
+    /// The time it took to parse the intermediate output from the source. Zero upon a cache hit.
+    pub parsing: Duration,
+    /// The time the whole build took
+    pub total: Duration,
+}
+
+impl fmt::Display for CompileStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let solc_version =
+            self.solc_version.as_ref().map_or_else(|| String::from("unknown"), ToString::to_string);
+        writeln!(f, "Cache: {}", if self.cache_hit { "hit" } else { "miss" })?;
+        writeln!(f, "Sources: {}", self.sources)?;
+        writeln!(f, "Solc version: {solc_version}")?;
+        writeln!(f, "Checksum: {:?}", self.checksum)?;
+        writeln!(f, "Solc invocation: {:?}", self.solc)?;
+        writeln!(f, "Artifact parsing: {:?}", self.parsing)?;
+        write!(f, "Total: {:?}", self.total)
+    }
+}
+
+/// A contract defined within the source of a [SessionSource], other than the REPL contract
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionContract {
+    /// The name of the contract
+    pub name: String,
+    /// The ABI of the contract
+    pub abi: Option<Abi>,
+    /// The creation bytecode of the contract, unless it must be linked against a library or it
+    /// is abstract
+    pub bytecode: Option<Bytes>,
+    /// The runtime bytecode of the contract, unless it must be linked against a library or it
+    /// is abstract
+    pub deployed_bytecode: Option<Bytes>,
+}
+
+/// A library with external functions that the REPL contract is linked against
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkedLibrary {
+    /// The file that the library is defined in
+    pub file: String,
+    /// The name of the library
+    pub name: String,
+    /// The address that the library is deployed at ahead of the REPL contract
+    pub address: Address,
+}
+
+impl fmt::Display for LinkedLibrary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{} => {:?}", self.file, self.name, self.address)
+    }
+}
+
+impl CompileResult {
+    /// Extracts the compiled REPL contract from the output of the compiler
     ///
-    /// Typically, global-level code is present between the contract definition and the first
-    /// function (usually constructor)
-    pub global_code: String,
-    /// Top level solidity code
+    /// ### Takes
     ///
-    /// Typically, this is code seen above the contructor
-    pub top_level_code: String,
-    /// Code existing within the "run()" function's scope
-    pub run_code: String,
-    /// The generated output
-    pub generated_output: Option<GeneratedOutput>,
-    /// Session Source configuration
-    pub config: SessionSourceConfig,
+    /// - The [CompilerOutput] of the session's source
+    /// - The file name of the session's source
+    /// - The name of the REPL contract
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the [CompileResult] of the REPL contract, if it is part of the output. Its
+    /// bytecode is left unset if it could not be linked.
+    fn from_output(output: &CompilerOutput, file_name: &Path, contract_name: &str) -> Option<Self> {
+        let file_contracts = output.contracts.get(&file_name.to_string_lossy().to_string())?;
+        let contract = CompactContractBytecode::from(file_contracts.get(contract_name).cloned()?);
+        let contracts = file_contracts
+            .iter()
+            .filter(|(name, _)| name.as_str() != contract_name)
+            .map(|(name, contract)| {
+                let contract = CompactContractBytecode::from(contract.clone());
+                SessionContract {
+                    name: name.clone(),
+                    abi: contract.abi,
+                    bytecode: contract
+                        .bytecode
+                        .and_then(|bytecode| bytecode.object.as_bytes().cloned())
+                        .filter(|bytecode| !bytecode.is_empty()),
+                    deployed_bytecode: contract
+                        .deployed_bytecode
+                        .and_then(|deployed| deployed.bytecode)
+                        .and_then(|bytecode| bytecode.object.as_bytes().cloned())
+                        .filter(|bytecode| !bytecode.is_empty()),
+                }
+            })
+            .collect();
+        let (libraries, bytecode, deployed_bytecode) =
+            match link_repl_contract(output, contract_name) {
+                Ok((libraries, bytecode, deployed_bytecode)) => (
+                    libraries.into_iter().map(|(library, _)| library).collect(),
+                    Some(bytecode),
+                    Some(deployed_bytecode),
+                ),
+                Err(_) => (Vec::new(), None, None),
+            };
+
+        Some(Self {
+            abi: contract.abi,
+            bytecode,
+            deployed_bytecode,
+            libraries,
+            contracts,
+            warnings: output
+                .errors
+                .iter()
+                .filter(|error| error.severity.is_warning())
+                .cloned()
+                .collect(),
+            gas: GasReport::from_output(output, contract_name),
+            stats: None,
+        })
+    }
+
+    /// Computes the selectors of the functions and errors, as well as the topics of the events,
+    /// within the ABI of the REPL contract
+    ///
+    /// ### Returns
+    ///
+    /// The [AbiHashes] of the REPL contract, sorted by signature. Empty if there is no ABI.
+    pub fn hashes(&self) -> AbiHashes {
+        let abi = match &self.abi {
+            Some(abi) => abi,
+            None => return AbiHashes::default(),
+        };
+        let signature = |name: &str, inputs: Vec<&ethers::abi::ParamType>| {
+            let types = inputs.iter().map(ToString::to_string).collect::<Vec<_>>();
+            format!("{name}({})", types.join(","))
+        };
+
+        let mut hashes = AbiHashes {
+            functions: abi
+                .functions()
+                .map(|function| {
+                    let inputs = function.inputs.iter().map(|param| &param.kind).collect();
+                    (signature(&function.name, inputs), hex::encode(function.short_signature()))
+                })
+                .collect(),
+            errors: abi
+                .errors()
+                .map(|error| {
+                    let inputs = error.inputs.iter().map(|param| &param.kind).collect();
+                    (
+                        signature(&error.name, inputs),
+                        hex::encode(&error.signature().as_bytes()[..4]),
+                    )
+                })
+                .collect(),
+            events: abi
+                .events()
+                .map(|event| {
+                    let inputs = event.inputs.iter().map(|param| &param.kind).collect();
+                    (signature(&event.name, inputs), hex::encode(event.signature()))
+                })
+                .collect(),
+        };
+        hashes.functions.sort();
+        hashes.errors.sort();
+        hashes.events.sort();
+        hashes
+    }
 }
 
-impl SessionSource {
-    /// Creates a new source given a solidity compiler version
+/// The selectors and event topics of the REPL contract, as pairs of signatures and hex-encoded
+/// hashes
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AbiHashes {
+    /// The 4-byte selectors of the functions
+    pub functions: Vec<(String, String)>,
+    /// The 4-byte selectors of the custom errors
+    pub errors: Vec<(String, String)>,
+    /// The 32-byte topics of the events
+    pub events: Vec<(String, String)>,
+}
+
+impl fmt::Display for AbiHashes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sections =
+            [("Functions", &self.functions), ("Errors", &self.errors), ("Events", &self.events)]
+                .into_iter()
+                .filter(|(_, hashes)| !hashes.is_empty())
+                .map(|(title, hashes)| {
+                    let lines =
+                        hashes.iter().map(|(signature, hash)| format!("\n0x{hash}: {signature}"));
+                    format!("{title}:{}", lines.collect::<String>())
+                })
+                .collect::<Vec<_>>();
+        write!(f, "{}", sections.join("\n\n"))
+    }
+}
+
+/// The gas estimates of solc for the contracts compiled from a [SessionSource]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GasReport {
+    /// The estimates of each contract that solc estimated, the REPL contract first and the
+    /// others in order of their names
+    pub contracts: Vec<(String, ContractGas)>,
+}
+
+impl GasReport {
+    /// Collects the gas estimates from the output of the compiler
     ///
     /// ### Takes
     ///
-    /// - A reference to a [Solc] instance
-    /// - A reference to a [SessionSourceConfig]
+    /// - The [CompilerOutput] of the session's source
+    /// - The name of the REPL contract
     ///
     /// ### Returns
     ///
-    /// A blank [SessionSource]
-    pub fn new(solc: &Solc, config: &SessionSourceConfig) -> Self {
-        assert!(solc.version().is_ok());
-        Self {
-            file_name: PathBuf::from("ReplContract.sol".to_string()),
-            contract_name: "REPL".to_string(),
-            solc: solc.clone(),
-            global_code: Default::default(),
-            top_level_code: Default::default(),
-            run_code: Default::default(),
-            generated_output: None,
-            config: config.clone(),
+    /// The [GasReport] of all contracts with gas estimates, except for forge-std's `Vm`
+    /// interface.
+    fn from_output(output: &CompilerOutput, contract_name: &str) -> Self {
+        let mut contracts = output
+            .contracts
+            .iter()
+            .filter(|(file, _)| Path::new(file) != Path::new("forge-std/Vm.sol"))
+            .flat_map(|(_, contracts)| contracts.iter())
+            .filter_map(|(name, contract)| {
+                let estimates = contract.evm.as_ref()?.gas_estimates.as_ref()?;
+                let functions = estimates
+                    .external
+                    .iter()
+                    .chain(estimates.internal.iter())
+                    .filter_map(|(signature, gas)| Some((signature.clone(), gas.parse().ok()?)))
+                    .collect::<BTreeMap<_, _>>();
+                let gas = ContractGas {
+                    creation: estimates.creation.total_cost.parse().ok(),
+                    functions: functions.into_iter().collect(),
+                };
+                Some((name.clone(), gas))
+            })
+            .collect::<Vec<_>>();
+        contracts.sort_by(|(a, _), (b, _)| (a != contract_name, a).cmp(&(b != contract_name, b)));
+        Self { contracts }
+    }
+
+    /// Looks up the gas estimates of a contract
+    ///
+    /// ### Takes
+    ///
+    /// The name of the contract
+    pub fn contract(&self, name: &str) -> Option<&ContractGas> {
+        self.contracts.iter().find(|(contract, _)| contract == name).map(|(_, gas)| gas)
+    }
+}
+
+impl fmt::Display for GasReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sections = self
+            .contracts
+            .iter()
+            .map(|(name, gas)| {
+                let rows = gas
+                    .creation
+                    .iter()
+                    .map(|creation| (String::from("creation"), creation))
+                    .chain(gas.functions.iter().map(|(signature, gas)| (signature.clone(), gas)))
+                    .collect::<Vec<_>>();
+                let width = rows.iter().map(|(label, _)| label.len()).max().unwrap_or_default();
+                let lines = rows.iter().map(|(label, gas)| format!("\n  {label:width$}  {gas}"));
+                format!("{name}:{}", lines.collect::<String>())
+            })
+            .collect::<Vec<_>>();
+        write!(f, "{}", sections.join("\n\n"))
+    }
+}
+
+/// The gas estimates of solc for a single contract
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContractGas {
+    /// The total cost of deploying the contract, including its code deposit
+    pub creation: Option<GasEstimate>,
+    /// The execution cost of the contract's external and internal functions, by signature
+    pub functions: Vec<(String, GasEstimate)>,
+}
+
+/// A gas estimate of solc
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GasEstimate {
+    /// A fixed upper bound of the gas used
+    Gas(u64),
+    /// The gas used is unbounded, i.e. because it depends on the length of a loop or storage
+    /// array
+    Infinite,
+}
+
+impl std::str::FromStr for GasEstimate {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "infinite" => Ok(GasEstimate::Infinite),
+            gas => gas.parse().map(GasEstimate::Gas),
         }
     }
+}
 
-    // Clones a [SessionSource] without copying the [GeneratedOutput], as it will
-    // need to be regenerated as soon as new code is added.
-    //
-    // ### Returns
-    //
-    // A shallow-cloned [SessionSource]
-    fn shallow_clone(&self) -> Self {
+impl fmt::Display for GasEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GasEstimate::Gas(gas) => write!(f, "{gas}"),
+            GasEstimate::Infinite => write!(f, "infinite"),
+        }
+    }
+}
+
+/// Compiler settings that the [SessionSource] is compiled with
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompilerSettings {
+    /// Whether the optimizer is enabled
+    pub optimizer: bool,
+    /// The number of optimizer runs
+    pub runs: u32,
+    /// The target EVM version. If `None`, the compiler's default is used.
+    pub evm_version: Option<String>,
+    /// Whether to compile via the IR pipeline
+    pub via_ir: bool,
+}
+
+/// The default [CompilerSettings] match the settings of a default [CompilerInput], which
+/// sessions cached before compiler settings were persisted have been compiled with.
+impl Default for CompilerSettings {
+    fn default() -> Self {
+        Self { optimizer: false, runs: 200, evm_version: None, via_ir: false }
+    }
+}
+
+impl From<&Config> for CompilerSettings {
+    fn from(config: &Config) -> Self {
         Self {
-            file_name: self.file_name.clone(),
-            contract_name: self.contract_name.clone(),
-            solc: self.solc.clone(),
-            global_code: self.global_code.clone(),
-            top_level_code: self.top_level_code.clone(),
-            run_code: self.run_code.clone(),
-            generated_output: None,
-            config: self.config.clone(),
+            optimizer: config.optimizer,
+            runs: config.optimizer_runs as u32,
+            evm_version: Some(config.evm_version.to_string()),
+            via_ir: config.via_ir,
+        }
+    }
+}
+
+impl CompilerSettings {
+    /// Applies the compiler settings to a [CompilerInput]
+    ///
+    /// ### Takes
+    ///
+    /// A mutable reference to the [CompilerInput]
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, nothing. Fails if the EVM version is invalid.
+    pub fn apply(&self, input: &mut CompilerInput) -> Result<()> {
+        let settings = &mut input.settings;
+        if self.optimizer {
+            settings.optimizer.enable();
+        } else {
+            settings.optimizer.disable();
+        }
+        settings.optimizer.runs = Some(self.runs as usize);
+        if let Some(evm_version) = &self.evm_version {
+            settings.evm_version =
+                Some(evm_version.parse::<EvmVersion>().map_err(|e| eyre::eyre!(e))?);
+        }
+        settings.via_ir = self.via_ir.then_some(true);
+        Ok(())
+    }
+}
+
+/// Overrides of the environment that the [SessionSource] is executed in. Values that are not
+/// overridden are taken from the session's [EvmOpts], or from the forked network.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnvOverrides {
+    /// The number of the block, `block.number`
+    pub block_number: Option<u64>,
+    /// The timestamp of the block, `block.timestamp`
+    pub timestamp: Option<u64>,
+    /// The base fee of the block in wei, `block.basefee`
+    pub basefee: Option<U256>,
+    /// The chain ID, `block.chainid`
+    pub chain_id: Option<u64>,
+    /// The sender of the call to the REPL contract, `msg.sender`. Defaults to the zero address.
+    pub sender: Option<Address>,
+    /// The value in wei sent along with the call to the REPL contract, `msg.value`
+    pub value: Option<U256>,
+}
+
+impl EnvOverrides {
+    /// The names of the overridable fields, as accepted by [EnvOverrides::set]
+    pub const FIELDS: [&'static str; 6] =
+        ["block", "timestamp", "basefee", "chainid", "sender", "value"];
+
+    /// Overrides a field of the environment
+    ///
+    /// ### Takes
+    ///
+    /// - The name of the field, one of [EnvOverrides::FIELDS]
+    /// - The new value of the field. Numbers are decimal, or hex if prefixed with `0x`. If `None`,
+    ///   the override is removed.
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, nothing. Fails if the field is unknown or the value is invalid; The
+    /// overrides are left untouched.
+    pub fn set(&mut self, field: &str, value: Option<&str>) -> Result<()> {
+        let uint = |value: Option<&str>| value.map(parse_uint).transpose();
+        let uint64 = |value: Option<&str>| {
+            uint(value)?
+                .map(|value| {
+                    if value > U256::from(u64::MAX) {
+                        eyre::bail!("{value} does not fit into 64 bits!")
+                    }
+                    Ok(value.as_u64())
+                })
+                .transpose()
+        };
+        match field {
+            "block" => self.block_number = uint64(value)?,
+            "timestamp" => self.timestamp = uint64(value)?,
+            "basefee" => self.basefee = uint(value)?,
+            "chainid" => self.chain_id = uint64(value)?,
+            "sender" => {
+                self.sender = value
+                    .map(|value| {
+                        value
+                            .parse::<Address>()
+                            .map_err(|_| eyre::eyre!("Invalid address {value}!"))
+                    })
+                    .transpose()?
+            }
+            "value" => self.value = uint(value)?,
+            _ => eyre::bail!("Unknown environment field \"{field}\"!"),
+        }
+        Ok(())
+    }
+
+    /// Applies the overrides to an environment
+    ///
+    /// ### Takes
+    ///
+    /// A mutable reference to the [revm::Env]
+    pub fn apply(&self, env: &mut revm::Env) {
+        if let Some(number) = self.block_number {
+            env.block.number = number.into();
         }
+        if let Some(timestamp) = self.timestamp {
+            env.block.timestamp = timestamp.into();
+        }
+        if let Some(basefee) = self.basefee {
+            env.block.basefee = basefee;
+        }
+        if let Some(chain_id) = self.chain_id {
+            env.cfg.chain_id = chain_id.into();
+        }
+        env.tx.caller = self.sender.unwrap_or_default();
+        env.tx.value = self.value.unwrap_or_default();
+    }
+
+    /// Checks the overrides against the network that the session is forked from
+    ///
+    /// ### Takes
+    ///
+    /// The [ForkConfig] of the session, if any
+    ///
+    /// ### Returns
+    ///
+    /// A warning for each override that contradicts the fork. The state of the fork is read at
+    /// its own block regardless.
+    pub fn fork_conflicts(&self, fork: Option<&ForkConfig>) -> Vec<String> {
+        let fork = match fork {
+            Some(fork) => fork,
+            None => return Vec::new(),
+        };
+        let mut conflicts = Vec::new();
+        if let (Some(number), Some(block)) = (self.block_number, fork.block) {
+            if number < block {
+                conflicts.push(format!(
+                    "The block number {number} is below the fork block {block}; The state of the fork is still read at block {block}"
+                ));
+            }
+        }
+        if let (Some(chain_id), Some(fork_chain_id)) = (self.chain_id, fork.chain_id) {
+            if chain_id != fork_chain_id {
+                conflicts.push(format!(
+                    "The chain ID {chain_id} differs from the chain ID {fork_chain_id} of the forked network"
+                ));
+            }
+        }
+        conflicts
+    }
+}
+
+/// The default gas limit of each execution of a [SessionSource], roughly that of a block
+pub static DEFAULT_GAS_LIMIT: u64 = 30_000_000;
+
+/// The default wall-clock time limit of each execution of a [SessionSource]
+pub static DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Limits of each execution of a [SessionSource]. An execution that exceeds either limit is
+/// aborted, and the statement that was executed is not added to the session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionLimits {
+    /// The gas limit of the call to the REPL contract
+    pub gas_limit: u64,
+    /// The wall-clock time that the deployment of and call to the REPL contract may take,
+    /// including requests to the forked network
+    pub timeout: Duration,
+}
+
+impl Default for ExecutionLimits {
+    fn default() -> Self {
+        Self { gas_limit: DEFAULT_GAS_LIMIT, timeout: DEFAULT_TIMEOUT }
+    }
+}
+
+/// Parses a time limit, i.e. `10`, `2.5s`, or `500ms`
+///
+/// ### Takes
+///
+/// The time limit, in seconds unless suffixed with `ms`
+///
+/// ### Returns
+///
+/// Optionally, the time limit. Fails if it is not a positive number.
+pub fn parse_timeout(timeout: &str) -> Result<Duration> {
+    let timeout = timeout.trim();
+    let (number, scale) = match timeout.strip_suffix("ms") {
+        Some(millis) => (millis, 1e-3),
+        None => (timeout.strip_suffix('s').unwrap_or(timeout), 1.0),
+    };
+    number
+        .trim_end()
+        .parse::<f64>()
+        .ok()
+        .filter(|secs| *secs > 0.0)
+        .and_then(|secs| Duration::try_from_secs_f64(secs * scale).ok())
+        .ok_or_else(|| eyre::eyre!("Invalid time limit {timeout}!"))
+}
+
+/// Parses an amount of ether, i.e. `1.5ether`, `100 gwei`, or `1000`, which is in wei
+///
+/// ### Takes
+///
+/// The amount, optionally suffixed with a unit of `wei`, `gwei`, or `ether`
+///
+/// ### Returns
+///
+/// Optionally, the amount in wei. Fails if the amount is not a number, or has more decimals
+/// than its unit.
+pub fn parse_amount(amount: &str) -> Result<U256> {
+    let amount = amount.trim().to_lowercase();
+    let (number, decimals) = [("ether", 18), ("eth", 18), ("gwei", 9), ("wei", 0)]
+        .into_iter()
+        .find_map(|(unit, decimals)| Some((amount.strip_suffix(unit)?.trim_end(), decimals)))
+        .unwrap_or((amount.as_str(), 0));
+    if number.starts_with("0x") {
+        return parse_uint(number)?
+            .checked_mul(U256::exp10(decimals))
+            .ok_or_else(|| eyre::eyre!("The amount {amount} overflows!"))
+    }
+
+    let (integer, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if fraction.len() > decimals {
+        eyre::bail!("The amount {amount} has more than {decimals} decimals!")
+    }
+    let digits = format!("{integer}{fraction}");
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        eyre::bail!("Invalid amount {amount}!")
+    }
+    let digits = format!("{digits}{}", "0".repeat(decimals - fraction.len()));
+    U256::from_dec_str(&digits).map_err(|_| eyre::eyre!("The amount {amount} overflows!"))
+}
+
+/// Parses an unsigned integer, in decimal or, if prefixed with `0x`, in hex
+fn parse_uint(value: &str) -> Result<U256> {
+    match value.strip_prefix("0x") {
+        Some(hex) => U256::from_str_radix(hex, 16),
+        None => U256::from_dec_str(value),
+    }
+    .map_err(|_| eyre::eyre!("Invalid number {value}!"))
+}
+
+/// Configuration for the [SessionSource]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionSourceConfig {
+    /// Foundry configuration
+    pub foundry_config: Config,
+    /// EVM Options
+    pub evm_opts: EvmOpts,
+    #[serde(skip)]
+    /// In-memory REVM db for the session's runner.
+    pub backend: Option<Backend>,
+    /// Optionally enable traces for the REPL contract execution
+    pub traces: bool,
+    /// The network that the session is forked from, if any
+    #[serde(default)]
+    pub fork: Option<ForkConfig>,
+    /// Retain the stack, memory, and return data of the REPL contract at the end of each
+    /// execution, rather than only capturing them for inspections. This steps an additional
+    /// inspector through every instruction.
+    #[serde(default)]
+    pub retain_state: bool,
+    /// Record the steps of each execution for the step debugger. Only enabled on the copy of the
+    /// source that `!debug` executes.
+    #[serde(skip)]
+    pub debug: bool,
+    /// Count the instructions of the last statement of each execution, to profile its gas. Only
+    /// enabled on the copy of the source that `!profile` executes.
+    #[serde(skip)]
+    pub profile: bool,
+    /// The file that the user's [RpcAliases] are loaded from, if it is not their default file
+    #[serde(skip)]
+    pub rpc_aliases_file: Option<PathBuf>,
+}
+
+impl SessionSourceConfig {
+    /// Loads the user's [RpcAliases] that the session's endpoints are resolved with, see
+    /// [RpcAliases::load_or_default]
+    pub fn rpc_aliases(&self) -> RpcAliases {
+        RpcAliases::load_or_default(self.rpc_aliases_file.as_deref())
+    }
+
+    /// Returns the [EvmOpts] that the session is executed with
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the session's [EvmOpts] with the fork's URL and block number filled in. Fails
+    /// if the fork's endpoint can not be resolved.
+    pub fn fork_evm_opts(&self) -> Result<EvmOpts> {
+        let mut evm_opts = self.evm_opts.clone();
+        if let Some(fork) = &self.fork {
+            evm_opts.fork_url = Some(fork.resolve(&self.foundry_config, &self.rpc_aliases())?);
+            evm_opts.fork_block_number = fork.block;
+            evm_opts.env.chain_id = fork.chain_id.or(evm_opts.env.chain_id);
+        }
+        Ok(evm_opts)
+    }
+}
+
+/// The network that a session is forked from
+///
+/// Only the endpoint as it was supplied is stored, so that an alias or a URL that references an
+/// environment variable (ex. `${ALCHEMY_KEY}`) keeps API keys out of the session cache. It is
+/// resolved again on every execution. A URL that contains an API key is stored redacted, see
+/// [crate::rpc::redact_api_key].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForkConfig {
+    /// The RPC URL of the network, or an alias of the `[rpc_endpoints]` of the session's
+    /// configuration or of the user's [RpcAliases]
+    pub endpoint: String,
+    /// The block number that the fork is pinned to
+    pub block: Option<u64>,
+    /// The chain ID of the network
+    pub chain_id: Option<u64>,
+    /// The RPC URL that the endpoint was redacted from, as it contains an API key. It is never
+    /// persisted, so the network has to be forked again once the session is loaded.
+    #[serde(skip)]
+    pub secret_url: Option<String>,
+}
+
+impl ForkConfig {
+    /// Resolves the fork's endpoint to an RPC URL
+    ///
+    /// ### Takes
+    ///
+    /// - The [Config] whose `[rpc_endpoints]` aliases are looked up
+    /// - The user's [RpcAliases]
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the RPC URL. Fails if the endpoint is an alias that is not defined, if an
+    /// environment variable that the endpoint references is not set, if the resulting URL is
+    /// invalid, or if the endpoint is a redacted URL whose API key is no longer known.
+    ///
+    /// ### Notes
+    ///
+    /// The `[rpc_endpoints]` of the configuration take precedence over the user's [RpcAliases].
+    pub fn resolve(&self, config: &Config, aliases: &RpcAliases) -> Result<String> {
+        if let Some(url) = &self.secret_url {
+            return Ok(url.clone())
+        }
+        if self.endpoint.contains(REDACTED) {
+            eyre::bail!(
+                "The API key of {} was not saved with the session. Fork the network again with `!fork <url>`, or register the URL with `!rpc add <alias> <url>` and fork the alias to keep it across sessions",
+                self.endpoint
+            )
+        }
+        let endpoint = match config.rpc_endpoints.get(&self.endpoint) {
+            Some(endpoint) => endpoint.clone(),
+            None if is_alias(&self.endpoint) => {
+                aliases.endpoints.get(&self.endpoint).cloned().ok_or_else(|| {
+                    eyre::eyre!(
+                        "Unknown RPC alias `{}`: it is neither in the `[rpc_endpoints]` of the project nor in {}. Add it with `!rpc add {} <url>`",
+                        self.endpoint,
+                        aliases.path.display(),
+                        self.endpoint
+                    )
+                })?
+            }
+            None => RpcEndpoint::Env(self.endpoint.clone()),
+        };
+        let url =
+            endpoint.resolve().map_err(|e| eyre::eyre!("\"{}\" ENV Variable not set!", e.var))?;
+        if reqwest::Url::parse(&url).is_err() {
+            eyre::bail!("Invalid fork URL: {}", self.endpoint)
+        }
+        Ok(url)
+    }
+}
+
+impl fmt::Display for ForkConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.endpoint)?;
+        if let Some(block) = self.block {
+            write!(f, " at block {block}")?;
+        }
+        if let Some(chain_id) = self.chain_id {
+            write!(f, " (chain ID {chain_id})")?;
+        }
+        Ok(())
+    }
+}
+
+/// REPL Session Source wrapper
+///
+/// Heavily based on soli's [`ConstructedSource`](https://github.com/jpopesculian/soli/blob/master/src/main.rs#L166)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionSource {
+    /// The file name
+    pub file_name: PathBuf,
+    /// The contract name
+    pub contract_name: String,
+    /// The solidity compiler version
+    pub solc: Solc,
+    /// Global level solidity code
+    ///
+    /// Typically, global-level code is present between the contract definition and the first
+    /// function (usually constructor)
+    pub global_code: String,
+    /// Top level solidity code
+    ///
+    /// Typically, this is code seen above the contructor
+    pub top_level_code: String,
+    /// Code existing within the "run()" function's scope
+    pub run_code: String,
+    /// The generated output
+    pub generated_output: Option<GeneratedOutput>,
+    /// A hex-encoded hash of the compiler input that the generated output was built from
+    #[serde(skip)]
+    pub output_checksum: Option<String>,
+    /// Statistics on the most recent build of the source
+    #[serde(skip)]
+    pub compile_stats: Option<CompileStats>,
+    /// The storage layout of the REPL contract as of the most recent compile. Unlike the
+    /// generated output, it is kept by clones of the source to detect variables that move.
+    #[serde(skip)]
+    pub storage_layout: Option<StorageLayout>,
+    /// The state variables that were assigned other storage locations by the most recent
+    /// compile than by the compile before it
+    #[serde(skip)]
+    pub moved_variables: Vec<MovedVariable>,
+    /// Session Source configuration
+    pub config: SessionSourceConfig,
+    /// The compiler settings the source is compiled with. Sessions cached before compiler
+    /// settings were persisted are compiled with the default settings.
+    #[serde(default)]
+    pub compiler_settings: CompilerSettings,
+    /// Overrides of the environment that the source is executed in
+    #[serde(default)]
+    pub env_overrides: EnvOverrides,
+    /// The balances in wei that accounts are credited with before each execution, see
+    /// `!deal`
+    #[serde(default)]
+    pub balances: BTreeMap<Address, U256>,
+    /// The gas and time limits of each execution of the source
+    #[serde(default)]
+    pub limits: ExecutionLimits,
+    /// How integers within values are displayed, see `!format`
+    #[serde(default)]
+    pub number_format: NumberFormat,
+    /// The snippets appended to the source, in order of entry. An input that was split between
+    /// scopes is appended as several snippets, see [SolSnippet::continued]. Sessions cached
+    /// before snippets were tracked start with an empty list.
+    #[serde(default)]
+    pub snippets: Vec<SolSnippet>,
+    /// The snippets of the input most recently removed by [SessionSource::pop_snippet], if any
+    #[serde(skip)]
+    undone: Vec<SolSnippet>,
+    /// The SPDX license identifier of the rendered source
+    #[serde(default = "default_license")]
+    pub license: String,
+    /// The version requirement of the rendered source's `pragma solidity` directive, overriding
+    /// the requirement derived from the solc version. Solidity pragmas entered as snippets take
+    /// precedence over both.
+    #[serde(default)]
+    pub pragma: Option<String>,
+    /// Remappings that imports of the source are resolved with, in addition to the remappings
+    /// of the project. Sessions cached before remappings were persisted have none.
+    #[serde(default)]
+    pub remappings: Vec<Remapping>,
+    /// Directories that imports of the source are resolved within, in addition to the root of
+    /// the project
+    #[serde(default)]
+    pub source_directories: Vec<PathBuf>,
+    /// The root of the foundry project that the session was created in, if any. The project's
+    /// configuration is loaded again whenever the session is loaded from the cache.
+    #[serde(default)]
+    pub project_root: Option<PathBuf>,
+    /// The logs emitted by the most recent execution of the source
+    #[serde(skip)]
+    pub logs: Vec<Log>,
+    /// The machine state retained by the most recent execution of the source, along with the
+    /// checksum of the compiler input it was executed from. See
+    /// [SessionSourceConfig::retain_state].
+    #[serde(skip)]
+    pub machine_state: Option<(String, MachineState)>,
+    /// The snapshots of the source's state, in order of creation. See [StateSnapshot].
+    #[serde(skip)]
+    pub snapshots: Vec<StateSnapshot>,
+    /// The EVM state that the most recent successful execution of the source left behind, which
+    /// the next execution only runs its new statements against. See [PersistedState].
+    #[serde(skip)]
+    pub persisted: Option<PersistedState>,
+}
+
+/// The default SPDX license identifier of the rendered source
+fn default_license() -> String {
+    String::from("UNLICENSED")
+}
+
+/// A snippet of code entered into a [SessionSource]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SolSnippet {
+    /// The scope of the source the snippet was appended to
+    pub fragment: ParseTreeFragment,
+    /// The code of the snippet, exactly as it was appended to the source
+    pub code: String,
+    /// The kind of code the snippet consists of
+    pub kind: SnippetKind,
+    /// How the raw input of the snippet was wrapped to make it parse
+    #[serde(default)]
+    pub wrapping: SnippetWrapping,
+    /// When the snippet was entered. Snippets cached before this was recorded were entered at
+    /// the [SystemTime::UNIX_EPOCH].
+    #[serde(default = "unknown_entry_time")]
+    pub entered_at: SystemTime,
+    /// Where the snippet came from
+    #[serde(default)]
+    pub origin: SnippetOrigin,
+    /// The transactions that the snippet was broadcast to real networks with, see `!broadcast`
+    #[serde(default)]
+    pub broadcasts: Vec<BroadcastReceipt>,
+    /// Whether the snippet is a later part of the same input as the snippet before it, i.e. of
+    /// an input that was split between scopes. An input is undone, edited, and indexed as a
+    /// whole, see [SessionSource::snippet_range].
+    #[serde(default)]
+    pub continued: bool,
+}
+
+/// The entry time of snippets that were cached before entry times were recorded
+fn unknown_entry_time() -> SystemTime {
+    SystemTime::UNIX_EPOCH
+}
+
+/// Where a [SolSnippet] came from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SnippetOrigin {
+    /// The snippet was entered into the REPL
+    #[default]
+    Interactive,
+    /// The snippet was loaded from a file, i.e. an exported or merged session
+    File,
+    /// The snippet was generated by a command, i.e. an interface added via `!fetch` or `!abi`
+    Import,
+}
+
+impl fmt::Display for SnippetOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnippetOrigin::Interactive => write!(f, "interactive"),
+            SnippetOrigin::File => write!(f, "file"),
+            SnippetOrigin::Import => write!(f, "import"),
+        }
+    }
+}
+
+/// A snapshot of the state of a [SessionSource], see [SessionSource::snapshot]
+///
+/// The state of the EVM is determined by the snippets of the source: the persisted state of an
+/// execution is only reused while its snippets are a prefix of the source's, and is otherwise
+/// produced again by replaying the snippets from a clean state, see [SessionSource::persisted].
+/// A snapshot therefore records the snippets that produced the state, along with the logs that
+/// they emitted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateSnapshot {
+    /// The ID of the snapshot, unique within the session
+    pub id: usize,
+    /// The snippets of the source at the time of the snapshot
+    pub snippets: Vec<SolSnippet>,
+    /// The logs emitted by the snippets at the time of the snapshot
+    pub logs: Vec<Log>,
+    /// Whether the snapshot was taken automatically before a statement was executed, rather
+    /// than with `!snapshot`
+    pub automatic: bool,
+}
+
+impl StateSnapshot {
+    /// The index of the most recent snippet that the snapshot includes, or `None` if it was
+    /// taken of an empty source
+    pub fn snippet_index(&self) -> Option<usize> {
+        self.snippets.iter().filter(|snippet| !snippet.continued).count().checked_sub(1)
+    }
+}
+
+/// A variable declared at the top level of the "run()" function, see
+/// [SolSnippet::local_variables]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalVariable {
+    /// The name of the variable
+    pub name: String,
+    /// The type of the variable, i.e. `uint256[]`
+    pub ty: String,
+    /// The type of the variable along with its data location, i.e. `uint256[] memory`
+    pub declaration: String,
+    /// Whether the value of the variable can be ABI-encoded, which excludes references to
+    /// storage or calldata and function types
+    pub encodable: bool,
+}
+
+/// How a snippet that redefines an item of the session is handled, see
+/// [SessionSource::apply_redefinitions]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RedefinitionPolicy {
+    /// Replace the snippet containing the earlier definition with the new one
+    #[default]
+    Replace,
+    /// Reject the new snippet
+    Reject,
+}
+
+/// An item of the session that was redefined by a new snippet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redefinition {
+    /// The name of the item. Functions and events are named by their signature, as they can
+    /// be overloaded.
+    pub name: String,
+    /// The index of the snippet that contained the earlier definition
+    pub index: usize,
+}
+
+/// An item defined within a [SessionSource], see [SessionSource::defined_symbols]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    /// The name of the item. Items defined within a contract definition are qualified with the
+    /// name of the contract, i.e. `Counter.increment`.
+    pub name: String,
+    /// The kind of the item
+    pub kind: SymbolKind,
+    /// The index of the snippet that defines the item
+    pub snippet_index: usize,
+}
+
+/// The kind of a [Symbol]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SymbolKind {
+    /// A contract, interface, or library definition
+    Contract,
+    /// A function or modifier definition
+    Function,
+    /// A struct definition
+    Struct,
+    /// An enum definition
+    Enum,
+    /// A user defined value type definition
+    TypeDefinition,
+    /// An event definition
+    Event,
+    /// An error definition
+    Error,
+    /// A state variable, or a file level constant
+    StateVariable,
+    /// A variable declared within the "run()" function
+    Variable,
+}
+
+/// How the raw input of a [SolSnippet] was wrapped to make it parse
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SnippetWrapping {
+    /// The input parsed as-is
+    #[default]
+    None,
+    /// The input was terminated with a semicolon, i.e. `1 + 2` became `1 + 2;`
+    Terminated,
+    /// The trailing semicolons of the input were removed, i.e. `if (a) {};` became `if (a) {}`
+    Stripped,
+}
+
+/// The kind of code a [SolSnippet] consists of
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SnippetKind {
+    /// A pragma directive, i.e. `pragma solidity ^0.8.17;`
+    Pragma,
+    /// An import directive
+    Import,
+    /// A contract, interface, or library definition
+    ContractDef,
+    /// A struct, enum, user defined value type, event, or error definition
+    TypeDef,
+    /// A function, modifier, constructor, or fallback function definition
+    FunctionDef,
+    /// A state variable or file level constant definition
+    StateVariable,
+    /// A `using ... for ...` directive
+    Using,
+    /// A statement within the "run()" function, i.e. a variable declaration or assignment
+    Statement,
+    /// An expression within the "run()" function whose value can be inspected, i.e. `a + b`
+    Expression,
+    /// A combination of definitions of different kinds
+    Mixed,
+}
+
+impl SnippetKind {
+    /// Classifies code within the given scope of the source
+    ///
+    /// ### Takes
+    ///
+    /// - The scope of the source the code belongs to
+    /// - The code
+    ///
+    /// ### Returns
+    ///
+    /// The [SnippetKind] of the code. Code within the "run()" function is a
+    /// [SnippetKind::Statement] unless it consists solely of expressions. Code that does not
+    /// parse within its scope, or that consists solely of comments, is classified as a
+    /// [SnippetKind::Statement] as well.
+    pub fn classify(fragment: ParseTreeFragment, code: &str) -> Self {
+        let (prefix, suffix) = fragment.wrapper();
+        let pt::SourceUnit(parts) =
+            match solang_parser::parse(&format!("{prefix}{code}{suffix}"), 0) {
+                Ok((source_unit, _)) => source_unit,
+                Err(_) => return SnippetKind::Statement,
+            };
+
+        let contract_parts = || {
+            parts.iter().find_map(|part| match part {
+                pt::SourceUnitPart::ContractDefinition(cd) => Some(&cd.parts),
+                _ => None,
+            })
+        };
+        let kinds = match fragment {
+            ParseTreeFragment::Source => {
+                parts.iter().filter_map(Self::of_source_unit_part).collect::<HashSet<_>>()
+            }
+            ParseTreeFragment::Contract => {
+                contract_parts().into_iter().flatten().filter_map(Self::of_contract_part).collect()
+            }
+            ParseTreeFragment::Function => {
+                let statements =
+                    contract_parts().into_iter().flatten().find_map(|part| match part {
+                        pt::ContractPart::FunctionDefinition(def) => match &def.body {
+                            Some(pt::Statement::Block { statements, .. }) => Some(statements),
+                            _ => None,
+                        },
+                        _ => None,
+                    });
+                let is_expression = |statement: &pt::Statement| matches!(statement, pt::Statement::Expression(_, expr) if !mutates_state(expr));
+                return match statements {
+                    Some(statements)
+                        if !statements.is_empty() && statements.iter().all(is_expression) =>
+                    {
+                        SnippetKind::Expression
+                    }
+                    _ => SnippetKind::Statement,
+                }
+            }
+        };
+
+        match kinds.len() {
+            0 => SnippetKind::Statement,
+            1 => kinds.into_iter().next().unwrap(),
+            _ => SnippetKind::Mixed,
+        }
+    }
+
+    /// Whether snippets of this kind are executed, rather than only accumulated, when entered
+    pub fn is_executable(&self) -> bool {
+        matches!(self, SnippetKind::Statement | SnippetKind::Expression)
+    }
+
+    /// Classifies a part of the global scope
+    fn of_source_unit_part(part: &pt::SourceUnitPart) -> Option<Self> {
+        match part {
+            pt::SourceUnitPart::PragmaDirective(..) => Some(SnippetKind::Pragma),
+            pt::SourceUnitPart::ImportDirective(_) => Some(SnippetKind::Import),
+            pt::SourceUnitPart::ContractDefinition(_) => Some(SnippetKind::ContractDef),
+            pt::SourceUnitPart::EnumDefinition(_) |
+            pt::SourceUnitPart::StructDefinition(_) |
+            pt::SourceUnitPart::EventDefinition(_) |
+            pt::SourceUnitPart::ErrorDefinition(_) |
+            pt::SourceUnitPart::TypeDefinition(_) => Some(SnippetKind::TypeDef),
+            pt::SourceUnitPart::FunctionDefinition(_) => Some(SnippetKind::FunctionDef),
+            pt::SourceUnitPart::VariableDefinition(_) => Some(SnippetKind::StateVariable),
+            pt::SourceUnitPart::Using(_) => Some(SnippetKind::Using),
+            _ => None,
+        }
+    }
+
+    /// Classifies a part of the top level of the REPL contract
+    fn of_contract_part(part: &pt::ContractPart) -> Option<Self> {
+        match part {
+            pt::ContractPart::EnumDefinition(_) |
+            pt::ContractPart::StructDefinition(_) |
+            pt::ContractPart::EventDefinition(_) |
+            pt::ContractPart::ErrorDefinition(_) |
+            pt::ContractPart::TypeDefinition(_) => Some(SnippetKind::TypeDef),
+            pt::ContractPart::FunctionDefinition(_) => Some(SnippetKind::FunctionDef),
+            pt::ContractPart::VariableDefinition(_) => Some(SnippetKind::StateVariable),
+            pt::ContractPart::Using(_) => Some(SnippetKind::Using),
+            _ => None,
+        }
+    }
+}
+
+impl SolSnippet {
+    /// Creates a new snippet, classifying its code
+    ///
+    /// ### Takes
+    ///
+    /// - The scope of the source the snippet belongs to
+    /// - The code of the snippet
+    ///
+    /// ### Returns
+    ///
+    /// A [SolSnippet] of the [SnippetKind] of its code
+    pub fn new(fragment: ParseTreeFragment, code: String) -> Self {
+        let kind = SnippetKind::classify(fragment, &code);
+        Self {
+            fragment,
+            code,
+            kind,
+            wrapping: SnippetWrapping::None,
+            entered_at: SystemTime::now(),
+            origin: SnippetOrigin::default(),
+            broadcasts: Vec::new(),
+            continued: false,
+        }
+    }
+
+    /// Collects the items defined by the snippet, including those nested within the contracts
+    /// it defines
+    ///
+    /// ### Returns
+    ///
+    /// The qualified names and kinds of the items, in order of definition. See [Symbol].
+    /// Returns an empty vector if the snippet does not parse within its scope.
+    pub fn symbols(&self) -> Vec<(String, SymbolKind)> {
+        self.items().into_iter().map(|(name, kind, _)| (name, kind)).collect()
+    }
+
+    /// Collects the items defined by the snippet along with the keys they are redefined by
+    ///
+    /// ### Returns
+    ///
+    /// The qualified name, kind, and key of each item, in order of definition. Functions and
+    /// events are keyed by their signature, i.e. `transfer(address,uint256)`, as they can be
+    /// overloaded, and all other items by their name. Returns an empty vector if the snippet
+    /// does not parse within its scope.
+    fn items(&self) -> Vec<(String, SymbolKind, String)> {
+        let (prefix, suffix) = self.fragment.wrapper();
+        let wrapped = format!("{prefix}{}{suffix}", self.code);
+        let pt::SourceUnit(parts) = match solang_parser::parse(&wrapped, 0) {
+            Ok((source_unit, _)) => source_unit,
+            Err(_) => return Vec::new(),
+        };
+
+        let named = |name: &pt::Identifier, kind| (name.name.clone(), kind, name.name.clone());
+        let mut items = Vec::new();
+        for part in &parts {
+            match (self.fragment, part) {
+                // The wrapping REPL contract itself is not a symbol
+                (ParseTreeFragment::Contract, pt::SourceUnitPart::ContractDefinition(cd)) => {
+                    items.extend(contract_items(&wrapped, &cd.parts, ""))
+                }
+                (ParseTreeFragment::Function, pt::SourceUnitPart::ContractDefinition(cd)) => {
+                    let statements = cd.parts.iter().find_map(|part| match part {
+                        pt::ContractPart::FunctionDefinition(def) => match &def.body {
+                            Some(pt::Statement::Block { statements, .. }) => Some(statements),
+                            _ => None,
+                        },
+                        _ => None,
+                    });
+                    items.extend(
+                        statements
+                            .into_iter()
+                            .flatten()
+                            .flat_map(SessionSource::get_statement_definitions)
+                            .map(|(name, _)| (name.clone(), SymbolKind::Variable, name)),
+                    );
+                }
+                (_, pt::SourceUnitPart::ContractDefinition(cd)) => {
+                    items.push(named(&cd.name, SymbolKind::Contract));
+                    items.extend(contract_items(
+                        &wrapped,
+                        &cd.parts,
+                        &format!("{}.", cd.name.name),
+                    ));
+                }
+                (_, pt::SourceUnitPart::FunctionDefinition(def)) => {
+                    items.extend(def.name.as_ref().map(|name| {
+                        (name.name.clone(), SymbolKind::Function, function_signature(&wrapped, def))
+                    }))
+                }
+                (_, pt::SourceUnitPart::StructDefinition(def)) => {
+                    items.push(named(&def.name, SymbolKind::Struct))
+                }
+                (_, pt::SourceUnitPart::EnumDefinition(def)) => {
+                    items.push(named(&def.name, SymbolKind::Enum))
+                }
+                (_, pt::SourceUnitPart::TypeDefinition(def)) => {
+                    items.push(named(&def.name, SymbolKind::TypeDefinition))
+                }
+                (_, pt::SourceUnitPart::EventDefinition(def)) => items.push((
+                    def.name.name.clone(),
+                    SymbolKind::Event,
+                    event_signature(&wrapped, def),
+                )),
+                (_, pt::SourceUnitPart::ErrorDefinition(def)) => {
+                    items.push(named(&def.name, SymbolKind::Error))
+                }
+                (_, pt::SourceUnitPart::VariableDefinition(def)) => {
+                    items.push(named(&def.name, SymbolKind::StateVariable))
+                }
+                _ => {}
+            }
+        }
+        items
+    }
+
+    /// Checks that the code of the snippet parses within its scope, i.e. after being loaded
+    /// from a cache file that may have been edited by hand
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, nothing. Fails with the [ParseDiagnostics] of the code otherwise.
+    pub fn validate(&self) -> std::result::Result<(), ParseDiagnostics> {
+        let (prefix, suffix) = self.fragment.wrapper();
+        match solang_parser::parse(&format!("{prefix}{}{suffix}", self.code), 0) {
+            Ok(_) => Ok(()),
+            Err(diagnostics) => Err(ParseDiagnostics::new(&self.code, prefix.len(), diagnostics)),
+        }
+    }
+
+    /// Sets where the snippet came from
+    pub fn with_origin(mut self, origin: SnippetOrigin) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    /// Describes when and how the snippet was entered
+    ///
+    /// ### Returns
+    ///
+    /// The provenance of the snippet, i.e. `entered 2024-03-02 14:01 (interactive)`. Entry times
+    /// are in UTC.
+    pub fn provenance(&self) -> String {
+        let entered_at = if self.entered_at == unknown_entry_time() {
+            None
+        } else {
+            format_description::parse("[year]-[month]-[day] [hour]:[minute]")
+                .ok()
+                .and_then(|format| OffsetDateTime::from(self.entered_at).format(&format).ok())
+        };
+        match entered_at {
+            Some(entered_at) => format!("entered {entered_at} ({})", self.origin),
+            None => format!("entered at an unknown time ({})", self.origin),
+        }
+    }
+
+    /// Whether the snippet is a single external function call statement, i.e.
+    /// `token.transfer(to, 1);`, which can be broadcast as the call itself
+    pub fn is_external_call(&self) -> bool {
+        if self.fragment != ParseTreeFragment::Function {
+            return false
+        }
+        let (prefix, suffix) = self.fragment.wrapper();
+        let pt::SourceUnit(parts) =
+            match solang_parser::parse(&format!("{prefix}{}{suffix}", self.code), 0) {
+                Ok((source_unit, _)) => source_unit,
+                Err(_) => return false,
+            };
+        match run_statements(&parts) {
+            Some([pt::Statement::Expression(_, pt::Expression::FunctionCall(_, callee, _))]) => {
+                matches!(
+                    callee.as_ref(),
+                    pt::Expression::MemberAccess(..) | pt::Expression::FunctionCallBlock(..)
+                )
+            }
+            _ => false,
+        }
+    }
+
+    /// Collects the variables that the statements of the snippet declare at the top level of
+    /// the "run()" function
+    ///
+    /// ### Returns
+    ///
+    /// The [LocalVariable]s, in order of declaration. Returns an empty vector if the snippet is
+    /// not within the "run()" function or does not parse.
+    pub fn local_variables(&self) -> Vec<LocalVariable> {
+        if self.fragment != ParseTreeFragment::Function {
+            return Vec::new()
+        }
+        let (prefix, suffix) = self.fragment.wrapper();
+        let wrapped = format!("{prefix}{}{suffix}", self.code);
+        let pt::SourceUnit(parts) = match solang_parser::parse(&wrapped, 0) {
+            Ok((source_unit, _)) => source_unit,
+            Err(_) => return Vec::new(),
+        };
+
+        let text = |loc: pt::Loc| wrapped[loc.start()..loc.end()].trim().to_string();
+        let variable = |ty: &pt::Expression,
+                        storage: Option<&pt::StorageLocation>,
+                        name: &pt::Identifier| {
+            LocalVariable {
+                name: name.name.clone(),
+                ty: text(ty.loc()),
+                declaration: text(pt::Loc::File(0, ty.loc().start(), name.loc.start())),
+                encodable: !matches!(
+                    storage,
+                    Some(pt::StorageLocation::Storage(_) | pt::StorageLocation::Calldata(_))
+                ) && !matches!(ty, pt::Expression::Type(_, pt::Type::Function { .. })),
+            }
+        };
+        let mut variables = Vec::new();
+        for statement in run_statements(&parts).unwrap_or_default() {
+            match statement {
+                pt::Statement::VariableDefinition(_, def, _) => {
+                    variables.push(variable(&def.ty, def.storage.as_ref(), &def.name))
+                }
+                pt::Statement::Expression(_, pt::Expression::Assign(_, left, _)) => {
+                    if let pt::Expression::List(_, list) = left.as_ref() {
+                        variables.extend(
+                            list.iter().filter_map(|(_, param)| param.as_ref()).filter_map(
+                                |param| {
+                                    let name = param.name.as_ref()?;
+                                    Some(variable(&param.ty, param.storage.as_ref(), name))
+                                },
+                            ),
+                        )
+                    }
+                }
+                _ => {}
+            }
+        }
+        variables
+    }
+
+    /// Whether the snippet defines state that is initialized when the REPL contract is
+    /// deployed, i.e. a state variable with an initial value, an immutable, or a constructor. Such
+    /// state only takes effect by deploying the REPL contract again.
+    pub fn initializes_state(&self) -> bool {
+        if self.fragment != ParseTreeFragment::Contract {
+            return false
+        }
+        let (prefix, suffix) = self.fragment.wrapper();
+        let pt::SourceUnit(parts) =
+            match solang_parser::parse(&format!("{prefix}{}{suffix}", self.code), 0) {
+                Ok((source_unit, _)) => source_unit,
+                Err(_) => return true,
+            };
+        parts
+            .iter()
+            .filter_map(|part| match part {
+                pt::SourceUnitPart::ContractDefinition(cd) => Some(&cd.parts),
+                _ => None,
+            })
+            .flatten()
+            .any(|part| match part {
+                pt::ContractPart::VariableDefinition(def) => {
+                    let constant = def
+                        .attrs
+                        .iter()
+                        .any(|attr| matches!(attr, pt::VariableAttribute::Constant(_)));
+                    !constant &&
+                        (def.initializer.is_some() ||
+                            def.attrs
+                                .iter()
+                                .any(|attr| matches!(attr, pt::VariableAttribute::Immutable(_))))
+                }
+                pt::ContractPart::FunctionDefinition(def) => {
+                    matches!(def.ty, pt::FunctionTy::Constructor)
+                }
+                _ => false,
+            })
+    }
+
+    /// Returns the kind of code the snippet consists of
+    pub fn kind(&self) -> SnippetKind {
+        self.kind
+    }
+
+    /// Collects the names of the items defined by the snippet, i.e. functions, structs, events,
+    /// errors, and state variables
+    ///
+    /// ### Returns
+    ///
+    /// The keys of the items, in order of definition. See [SolSnippet::symbols]. Items nested
+    /// within the contracts that the snippet defines are redefined along with their contract, and
+    /// statements within the "run()" function do not define items.
+    pub fn definitions(&self) -> Vec<String> {
+        if self.fragment == ParseTreeFragment::Function {
+            return Vec::new()
+        }
+        self.items()
+            .into_iter()
+            .filter(|(name, _, _)| !name.contains('.'))
+            .map(|(_, _, key)| key)
+            .collect()
+    }
+
+    /// Whether executing the snippet produces a value that should be printed, i.e. if it is a
+    /// bare expression
+    pub fn expects_result(&self) -> bool {
+        self.kind == SnippetKind::Expression
+    }
+
+    /// Parses a snippet of code and assigns it a scope within the source. If the snippet does
+    /// not parse as-is, it is retried with a terminating semicolon appended, which also turns
+    /// bare expressions into expression statements, and then without its trailing semicolons.
+    ///
+    /// ### Takes
+    ///
+    /// The raw code of the snippet
+    ///
+    /// ### Returns
+    ///
+    /// The parsed snippet, or the [ParseDiagnostics] of the raw code if it does not parse
+    /// within any scope. The [SnippetWrapping] of the snippet records how it was wrapped.
+    pub fn try_parse(raw: &str) -> std::result::Result<SolSnippet, ParseDiagnostics> {
+        let snippet = |fragment, code: &str, wrapping| SolSnippet {
+            wrapping,
+            ..Self::new(fragment, format!("{}\n", code.trim()))
+        };
+        let diagnostics = match parse_fragment(raw) {
+            Ok(fragment) => return Ok(snippet(fragment, raw, SnippetWrapping::None)),
+            Err(diagnostics) => diagnostics,
+        };
+
+        let terminated = format!("{raw};");
+        if let Ok(fragment) = parse_fragment(&terminated) {
+            return Ok(snippet(fragment, &terminated, SnippetWrapping::Terminated))
+        }
+
+        // Blocks and definitions may be terminated nonetheless, i.e. `if (a) {};`
+        let stripped = raw.trim_end().trim_end_matches(';');
+        match parse_fragment(stripped) {
+            Ok(fragment) => Ok(snippet(fragment, stripped, SnippetWrapping::Stripped)),
+            Err(_) => Err(diagnostics),
+        }
+    }
+}
+
+impl SessionSource {
+    /// Creates a new source given a solidity compiler version
+    ///
+    /// ### Takes
+    ///
+    /// - A reference to a [Solc] instance
+    /// - A reference to a [SessionSourceConfig]
+    ///
+    /// ### Returns
+    ///
+    /// A blank [SessionSource]
+    pub fn new(solc: &Solc, config: &SessionSourceConfig) -> Self {
+        assert!(solc.version().is_ok());
+        Self {
+            file_name: PathBuf::from("ReplContract.sol".to_string()),
+            contract_name: "REPL".to_string(),
+            solc: solc.clone(),
+            global_code: Default::default(),
+            top_level_code: Default::default(),
+            run_code: Default::default(),
+            generated_output: None,
+            output_checksum: None,
+            compile_stats: None,
+            storage_layout: None,
+            moved_variables: Vec::new(),
+            config: config.clone(),
+            compiler_settings: CompilerSettings::from(&config.foundry_config),
+            env_overrides: EnvOverrides::default(),
+            balances: BTreeMap::new(),
+            limits: ExecutionLimits::default(),
+            number_format: NumberFormat::default(),
+            snippets: Vec::new(),
+            undone: Vec::new(),
+            license: default_license(),
+            pragma: None,
+            remappings: Vec::new(),
+            source_directories: Vec::new(),
+            project_root: Some(config.foundry_config.__root.0.clone())
+                .filter(|root| root.join(Config::FILE_NAME).is_file()),
+            logs: Vec::new(),
+            machine_state: None,
+            snapshots: Vec::new(),
+            persisted: None,
+        }
+    }
+
+    // Clones a [SessionSource] without copying the [GeneratedOutput], as it will
+    // need to be regenerated as soon as new code is added.
+    //
+    // ### Returns
+    //
+    // A shallow-cloned [SessionSource]
+    fn shallow_clone(&self) -> Self {
+        Self {
+            file_name: self.file_name.clone(),
+            contract_name: self.contract_name.clone(),
+            solc: self.solc.clone(),
+            global_code: self.global_code.clone(),
+            top_level_code: self.top_level_code.clone(),
+            run_code: self.run_code.clone(),
+            generated_output: None,
+            output_checksum: None,
+            compile_stats: None,
+            storage_layout: self.storage_layout.clone(),
+            moved_variables: Vec::new(),
+            config: self.config.clone(),
+            compiler_settings: self.compiler_settings.clone(),
+            env_overrides: self.env_overrides.clone(),
+            balances: self.balances.clone(),
+            limits: self.limits,
+            number_format: self.number_format,
+            snippets: self.snippets.clone(),
+            undone: Vec::new(),
+            license: self.license.clone(),
+            pragma: self.pragma.clone(),
+            remappings: self.remappings.clone(),
+            source_directories: self.source_directories.clone(),
+            project_root: self.project_root.clone(),
+            logs: self.logs.clone(),
+            machine_state: None,
+            snapshots: self.snapshots.clone(),
+            persisted: self.persisted.clone(),
+        }
+    }
+
+    /// Clones the [SessionSource] and appends a new line of code. Will return
+    /// an error result if the new line fails to be parsed.
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, a shallow-cloned [SessionSource] with the passed content appended to the
+    /// source code. Fails with the [ParseDiagnostics] of the content if it does not parse, in
+    /// which case nothing is appended.
+    ///
+    /// ### Notes
+    ///
+    /// If the line does not fit within a single scope as a whole, i.e. an event definition
+    /// followed by a statement, each of its parts is assigned a scope individually. The parts
+    /// remain a single snippet to undo, edit, and index.
+    pub fn clone_with_new_line(&self, content: String) -> Result<(SessionSource, bool)> {
+        let mut new_source = self.shallow_clone();
+
+        // Flag that tells the dispatcher whether to build or execute the session
+        // source based on the kind of the new code.
+        let mut do_execute = false;
+        for snippet in parse_input(&content)? {
+            do_execute |= snippet.kind.is_executable();
+            new_source.append_snippet(snippet);
+        }
+
+        Ok((new_source, do_execute))
+    }
+
+    /// Clones the [SessionSource] and appends new code to its global scope, regardless of the
+    /// scope it would be assigned otherwise, i.e. to define free functions at file level rather
+    /// than within the REPL contract.
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, a shallow-cloned [SessionSource] with the passed content appended to the
+    /// global code. Fails with the [ParseDiagnostics] of the content if it does not parse at
+    /// file scope, in which case nothing is appended.
+    pub fn clone_with_global_code(&self, content: &str) -> Result<SessionSource> {
+        if let Err(diagnostics) = solang_parser::parse(content, 0) {
+            return Err(ParseDiagnostics::new(content, 0, diagnostics).into())
+        }
+        let mut new_source = self.shallow_clone();
+        new_source.with_global_code(content);
+        Ok(new_source)
+    }
+
+    // Fillers
+
+    /// Appends global-level code to the source
+    pub fn with_global_code(&mut self, content: &str) -> &mut Self {
+        self.push_snippet(ParseTreeFragment::Source, format!("{}\n", content.trim()))
+    }
+
+    /// Appends top-level code to the source
+    pub fn with_top_level_code(&mut self, content: &str) -> &mut Self {
+        self.push_snippet(ParseTreeFragment::Contract, format!("{}\n", content.trim()))
+    }
+
+    /// Appends code to the "run()" function
+    pub fn with_run_code(&mut self, content: &str) -> &mut Self {
+        self.push_snippet(ParseTreeFragment::Function, format!("{}\n", content.trim()))
+    }
+
+    /// Appends a snippet of code to the given scope of the source, verbatim
+    ///
+    /// ### Takes
+    ///
+    /// - The scope to append the snippet to
+    /// - The code of the snippet
+    pub fn push_snippet(&mut self, fragment: ParseTreeFragment, code: String) -> &mut Self {
+        self.append_snippet(SolSnippet::new(fragment, code))
+    }
+
+    /// Handles items that are defined again by the most recently appended snippets
+    ///
+    /// ### Takes
+    ///
+    /// - The index of the first newly appended snippet
+    /// - The [RedefinitionPolicy] to apply
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the items that were redefined. With [RedefinitionPolicy::Replace], each
+    /// earlier snippet that defines a redefined item is replaced in place by the new snippet
+    /// redefining it. Fails, leaving the source untouched, if the policy is
+    /// [RedefinitionPolicy::Reject] or if an earlier snippet also defines items that are not
+    /// redefined, as it can not be replaced as a whole.
+    pub fn apply_redefinitions(
+        &mut self,
+        first_new: usize,
+        policy: RedefinitionPolicy,
+    ) -> Result<Vec<Redefinition>> {
+        let new_names = self.snippets[first_new..]
+            .iter()
+            .flat_map(SolSnippet::definitions)
+            .collect::<HashSet<_>>();
+        if new_names.is_empty() {
+            return Ok(Vec::new())
+        }
+
+        // Snippets are replaced as a whole, including all parts of a split input
+        let mut redefinitions = Vec::new();
+        let mut replaced = BTreeSet::new();
+        let inputs = self.snippets[..first_new].iter().filter(|snippet| !snippet.continued).count();
+        for index in 0..inputs {
+            let parts = self.snippet_range(index).unwrap_or_default();
+            let names = self.snippets[parts.clone()]
+                .iter()
+                .flat_map(SolSnippet::definitions)
+                .collect::<Vec<_>>();
+            let redefined =
+                names.iter().filter(|name| new_names.contains(*name)).cloned().collect::<Vec<_>>();
+            let name = match redefined.first() {
+                Some(name) => name,
+                None => continue,
+            };
+            if policy == RedefinitionPolicy::Reject {
+                eyre::bail!("`{name}` is already defined in snippet {index}")
+            }
+            if redefined.len() != names.len() {
+                eyre::bail!(
+                    "`{name}` is already defined in snippet {index}, along with other items; Use `!edit {index}` to change it"
+                )
+            }
+            redefinitions.extend(redefined.into_iter().map(|name| Redefinition { name, index }));
+            replaced.extend(parts);
+        }
+        if redefinitions.is_empty() {
+            return Ok(redefinitions)
+        }
+
+        // The new snippets that redefine items take the place of the first snippet they replace,
+        // while all other new snippets stay in order of entry. Each of the two groups becomes a
+        // snippet of its own.
+        let first_replaced = *replaced.iter().next().unwrap();
+        let (mut redefining, mut appended): (Vec<_>, Vec<_>) = self.snippets[first_new..]
+            .iter()
+            .cloned()
+            .partition(|snippet| snippet.definitions().iter().any(|name| new_names.contains(name)));
+        for group in [&mut redefining, &mut appended] {
+            for (i, snippet) in group.iter_mut().enumerate() {
+                snippet.continued = i > 0;
+            }
+        }
+        let mut snippets = Vec::with_capacity(self.snippets.len());
+        for (position, snippet) in self.snippets[..first_new].iter().enumerate() {
+            if position == first_replaced {
+                snippets.extend(redefining.iter().cloned());
+            }
+            if !replaced.contains(&position) {
+                snippets.push(snippet.clone());
+            }
+        }
+        snippets.extend(appended);
+        self.replace_snippets(snippets)?;
+        Ok(redefinitions)
+    }
+
+    /// Appends an already classified snippet to its scope of the source, verbatim
+    pub fn append_snippet(&mut self, snippet: SolSnippet) -> &mut Self {
+        let fragment = snippet.fragment;
+        self.code_mut(fragment).push_str(&snippet.code);
+        self.snippets.push(snippet);
+        self.generated_output = None;
+        // Global code may define or import a contract with the same name as the REPL contract
+        if fragment == ParseTreeFragment::Source {
+            let contract_name = self.contract_name.clone();
+            self.set_contract_name(&contract_name);
+        }
+        self
+    }
+
+    /// Removes the most recently entered snippet from the source
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the parts of the removed snippet, see [SolSnippet::continued]. Returns `None`
+    /// if there are no snippets left, or if the end of the source no longer matches the last
+    /// snippet (i.e. after it was edited).
+    ///
+    /// ### Notes
+    ///
+    /// The removed snippet can be restored once with [SessionSource::redo_snippet], until new
+    /// code is added to the source.
+    pub fn pop_snippet(&mut self) -> Option<Vec<SolSnippet>> {
+        let parts = self.snippet_range(self.snippet_count().checked_sub(1)?)?;
+        let removed = self.snippets[parts.clone()].to_vec();
+
+        // Each part of the snippet is at the end of its scope
+        let fragments =
+            [ParseTreeFragment::Source, ParseTreeFragment::Contract, ParseTreeFragment::Function];
+        let tails = fragments.map(|fragment| {
+            let tail = removed
+                .iter()
+                .filter(|snippet| snippet.fragment == fragment)
+                .map(|snippet| snippet.code.as_str())
+                .collect::<String>();
+            (fragment, tail)
+        });
+        if tails.iter().any(|(fragment, tail)| !self.code(*fragment).ends_with(tail.as_str())) {
+            return None
+        }
+        for (fragment, tail) in tails {
+            let code = self.code_mut(fragment);
+            code.truncate(code.len() - tail.len());
+        }
+
+        self.snippets.truncate(parts.start);
+        self.generated_output = None;
+        self.undone = removed.clone();
+
+        // Rewind the logs to those of the automatic snapshot taken before the snippet ran
+        self.logs = self
+            .snapshots
+            .iter()
+            .rev()
+            .find(|snapshot| snapshot.automatic && snapshot.snippets == self.snippets)
+            .map(|snapshot| snapshot.logs.clone())
+            .unwrap_or_default();
+        Some(removed)
+    }
+
+    /// Takes a snapshot of the current state of the source
+    ///
+    /// ### Returns
+    ///
+    /// The ID of the snapshot, to restore it with [SessionSource::revert_to_snapshot]
+    pub fn snapshot(&mut self) -> usize {
+        self.push_snapshot(self.snippets.clone(), self.logs.clone(), false)
+    }
+
+    /// Records a snapshot of a state of the source
+    ///
+    /// ### Takes
+    ///
+    /// - The snippets of the state
+    /// - The logs emitted by the snippets
+    /// - Whether the snapshot was taken automatically, i.e. before a statement was executed
+    ///
+    /// ### Returns
+    ///
+    /// The ID of the snapshot
+    pub fn push_snapshot(
+        &mut self,
+        snippets: Vec<SolSnippet>,
+        logs: Vec<Log>,
+        automatic: bool,
+    ) -> usize {
+        let id = self.snapshots.last().map_or(0, |snapshot| snapshot.id + 1);
+        self.snapshots.push(StateSnapshot { id, snippets, logs, automatic });
+        id
+    }
+
+    /// Restores the source to the state of a snapshot, discarding the snippets that were
+    /// entered since
+    ///
+    /// ### Takes
+    ///
+    /// The ID of the snapshot, see [SessionSource::snapshot]
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, nothing. Fails if there is no snapshot with the ID, or if the source can not
+    /// be re-rendered from its snippets; The source is left untouched.
+    ///
+    /// ### Notes
+    ///
+    /// The snapshots themselves are kept, so the source can also be restored to a snapshot that
+    /// was taken after the one reverted to.
+    pub fn revert_to_snapshot(&mut self, id: usize) -> Result<()> {
+        let snapshot = self
+            .snapshots
+            .iter()
+            .find(|snapshot| snapshot.id == id)
+            .cloned()
+            .ok_or_else(|| eyre::eyre!("Snapshot {id} does not exist! See `!snapshots`."))?;
+        if let Some(index) = self.snippet_count().checked_sub(1) {
+            self.check_snippet_index(index)?;
+        }
+        self.replace_snippets(snapshot.snippets)?;
+        self.logs = snapshot.logs;
+        self.machine_state = None;
+        Ok(())
+    }
+
+    /// Restores the snippet most recently removed by [SessionSource::pop_snippet]
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the parts of the restored snippet.
+    pub fn redo_snippet(&mut self) -> Option<Vec<SolSnippet>> {
+        if self.undone.is_empty() {
+            return None
+        }
+        let restored = std::mem::take(&mut self.undone);
+        for snippet in restored.iter().cloned() {
+            self.append_snippet(snippet);
+        }
+        Some(restored)
+    }
+
+    /// Replaces a previously entered snippet with new code
+    ///
+    /// ### Takes
+    ///
+    /// - The index of the snippet, in order of entry
+    /// - The raw code to replace the snippet with
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, nothing. Fails if the index is out of range, if the new code can not be
+    /// parsed, or if the resulting source does not parse; The source is left untouched.
+    ///
+    /// ### Notes
+    ///
+    /// All parts of the snippet are replaced, and the new code is split between scopes like a
+    /// newly entered line, see [SessionSource::clone_with_new_line].
+    pub fn replace_snippet(&mut self, index: usize, raw: &str) -> Result<()> {
+        self.check_snippet_index(index)?;
+        let parts = self.snippet_range(index).unwrap_or_default();
+        let mut snippets = self.snippets.clone();
+        snippets.splice(parts, parse_input(raw)?);
+        self.replace_snippets(snippets)
+    }
+
+    /// Removes a previously entered snippet from the source
+    ///
+    /// ### Takes
+    ///
+    /// The index of the snippet, in order of entry
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the parts of the removed snippet. Fails if the index is out of range, or if
+    /// the resulting source does not parse; The source is left untouched.
+    pub fn remove_snippet(&mut self, index: usize) -> Result<Vec<SolSnippet>> {
+        self.check_snippet_index(index)?;
+        let parts = self.snippet_range(index).unwrap_or_default();
+        let mut snippets = self.snippets.clone();
+        let removed = snippets.drain(parts).collect();
+        self.replace_snippets(snippets)?;
+        Ok(removed)
+    }
+
+    /// The number of snippets entered into the source, counting an input that was split between
+    /// scopes once, see [SolSnippet::continued]
+    pub fn snippet_count(&self) -> usize {
+        self.snippets.iter().filter(|snippet| !snippet.continued).count()
+    }
+
+    /// Finds the parts of an entered snippet within [SessionSource::snippets]
+    ///
+    /// ### Takes
+    ///
+    /// The index of the snippet, in order of entry
+    ///
+    /// ### Returns
+    ///
+    /// The range of the snippet's parts, or `None` if the index is out of range
+    pub fn snippet_range(&self, index: usize) -> Option<Range<usize>> {
+        let mut starts = self
+            .snippets
+            .iter()
+            .enumerate()
+            .filter(|(_, snippet)| !snippet.continued)
+            .map(|(position, _)| position)
+            .skip(index);
+        let start = starts.next()?;
+        Some(start..starts.next().unwrap_or(self.snippets.len()))
+    }
+
+    /// The index, in order of entry, of the snippet that a part within
+    /// [SessionSource::snippets] belongs to
+    pub fn snippet_index_of(&self, position: usize) -> usize {
+        self.snippets
+            .iter()
+            .take(position + 1)
+            .filter(|snippet| !snippet.continued)
+            .count()
+            .saturating_sub(1)
+    }
+
+    /// The code of an entered snippet, i.e. the code of all of its parts in order
+    pub fn snippet_code(&self, index: usize) -> Option<String> {
+        let parts = self.snippet_range(index)?;
+        Some(self.snippets[parts].iter().map(|snippet| snippet.code.as_str()).collect())
+    }
+
+    /// Finds the entered snippet that a part within [SessionSource::snippets] belongs to
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the index of the snippet, its code, and the byte range of the part within
+    /// the code
+    fn snippet_of_part(&self, position: usize) -> Option<(usize, String, Range<usize>)> {
+        let part = self.snippets.get(position)?;
+        let index = self.snippet_index_of(position);
+        let start = self.snippet_range(index)?.start;
+        let offset = self.snippets[start..position].iter().map(|snippet| snippet.code.len()).sum();
+        Some((index, self.snippet_code(index)?, offset..offset + part.code.len()))
+    }
+
+    /// Checks that a snippet index is in range and that the source can be re-rendered from its
+    /// snippets, which is not the case for sessions cached before snippets were tracked.
+    pub(crate) fn check_snippet_index(&self, index: usize) -> Result<()> {
+        if index >= self.snippet_count() {
+            eyre::bail!(
+                "Snippet index {index} is out of range! The session has {} snippet(s).",
+                self.snippet_count()
+            )
+        }
+        if !self.is_rendered_from_snippets() {
+            eyre::bail!("The session source has been modified outside of its snippets!")
+        }
+        Ok(())
+    }
+
+    /// Whether the code of each scope of the source is rendered from its snippets, which is not
+    /// the case after it was modified outside of them, i.e. by `!edit`
+    pub(crate) fn is_rendered_from_snippets(&self) -> bool {
+        [ParseTreeFragment::Source, ParseTreeFragment::Contract, ParseTreeFragment::Function]
+            .into_iter()
+            .all(|fragment| self.code(fragment) == Self::render_snippets(&self.snippets, fragment))
+    }
+
+    /// Re-renders the source from a new list of snippets, rolling back if the result does not
+    /// parse.
+    pub(crate) fn replace_snippets(&mut self, snippets: Vec<SolSnippet>) -> Result<()> {
+        let backup = self.clone();
+        for fragment in
+            [ParseTreeFragment::Source, ParseTreeFragment::Contract, ParseTreeFragment::Function]
+        {
+            *self.code_mut(fragment) = Self::render_snippets(&snippets, fragment);
+        }
+        self.snippets = snippets;
+        self.undone.clear();
+        self.generated_output = None;
+
+        let contract_name = self.contract_name.clone();
+        self.set_contract_name(&contract_name);
+
+        if let Err(diagnostics) = self.parse() {
+            let diagnostics = ParseDiagnostics::new(&self.to_repl_source(), 0, diagnostics);
+            *self = backup;
+            eyre::bail!("Failed to parse the edited source:\n{diagnostics}")
+        }
+        Ok(())
+    }
+
+    /// Renames the REPL contract. If the name collides with a contract that is defined or
+    /// imported by the global code of the source, it is suffixed with the lowest free index,
+    /// i.e. `REPL_1`.
+    ///
+    /// ### Takes
+    ///
+    /// The requested name of the REPL contract
+    ///
+    /// ### Returns
+    ///
+    /// The name the REPL contract was given
+    pub fn set_contract_name(&mut self, name: &str) -> &str {
+        let taken = self.global_contract_names();
+        let mut contract_name = name.to_string();
+        let mut suffix = 0;
+        while taken.contains(&contract_name) {
+            suffix += 1;
+            contract_name = format!("{name}_{suffix}");
+        }
+
+        if contract_name != self.contract_name {
+            self.contract_name = contract_name;
+            self.generated_output = None;
+        }
+        &self.contract_name
+    }
+
+    /// Collects the names of all contracts, interfaces, and libraries that are defined or
+    /// imported by the global code of the source
+    ///
+    /// ### Returns
+    ///
+    /// The names of all such definitions. Returns an empty set if the global code could not be
+    /// parsed.
+    ///
+    /// ### Notes
+    ///
+    /// Plain imports are resolved with the project's remappings, and only their top-level
+    /// definitions are collected. Imports that can not be read are ignored.
+    pub fn global_contract_names(&self) -> HashSet<String> {
+        let mut names = HashSet::new();
+        let pt::SourceUnit(parts) = match solang_parser::parse(&self.global_code, 0) {
+            Ok((source_unit, _)) => source_unit,
+            Err(_) => return names,
+        };
+
+        for part in parts {
+            match part {
+                pt::SourceUnitPart::ContractDefinition(cd) => {
+                    names.insert(cd.name.name);
+                }
+                pt::SourceUnitPart::ImportDirective(pt::Import::Plain(path, _)) => {
+                    names.extend(self.imported_contract_names(&path.string));
+                }
+                pt::SourceUnitPart::ImportDirective(pt::Import::GlobalSymbol(_, alias, _)) => {
+                    names.insert(alias.name);
+                }
+                pt::SourceUnitPart::ImportDirective(pt::Import::Rename(_, renames, _)) => {
+                    names.extend(
+                        renames.into_iter().map(|(symbol, alias)| alias.unwrap_or(symbol).name),
+                    );
+                }
+                _ => {}
+            }
+        }
+        names
+    }
+
+    /// Collects the names of the top-level contract definitions within an imported file
+    ///
+    /// ### Takes
+    ///
+    /// The import path, as written in the import directive
+    fn imported_contract_names(&self, path: &str) -> Vec<String> {
+        let config = &self.config.foundry_config;
+        let path = self
+            .all_remappings()
+            .into_iter()
+            .rev()
+            .find(|remapping| path.starts_with(&remapping.name))
+            .map_or_else(
+                || PathBuf::from(path),
+                |remapping| Path::new(&remapping.path).join(&path[remapping.name.len()..]),
+            );
+
+        let contents = match fs::read_to_string(config.__root.0.join(path)) {
+            Ok(contents) => contents,
+            Err(_) => return Vec::new(),
+        };
+        match solang_parser::parse(&contents, 0) {
+            Ok((pt::SourceUnit(parts), _)) => parts
+                .into_iter()
+                .filter_map(|part| match part {
+                    pt::SourceUnitPart::ContractDefinition(cd) => Some(cd.name.name),
+                    _ => None,
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Concatenates the code of all snippets within the given scope of the source
+    fn render_snippets(snippets: &[SolSnippet], fragment: ParseTreeFragment) -> String {
+        snippets
+            .iter()
+            .filter(|snippet| snippet.fragment == fragment)
+            .map(|snippet| snippet.code.as_str())
+            .collect()
+    }
+
+    /// Returns the code of the given scope of the source
+    fn code(&self, fragment: ParseTreeFragment) -> &str {
+        match fragment {
+            ParseTreeFragment::Source => &self.global_code,
+            ParseTreeFragment::Contract => &self.top_level_code,
+            ParseTreeFragment::Function => &self.run_code,
+        }
+    }
+
+    /// Returns a mutable reference to the code of the given scope of the source
+    fn code_mut(&mut self, fragment: ParseTreeFragment) -> &mut String {
+        match fragment {
+            ParseTreeFragment::Source => &mut self.global_code,
+            ParseTreeFragment::Contract => &mut self.top_level_code,
+            ParseTreeFragment::Function => &mut self.run_code,
+        }
+    }
+
+    // Drains
+
+    /// Clears global code from the source
+    pub fn drain_global_code(&mut self) -> &mut Self {
+        self.global_code = Default::default();
+        self.drain_snippets(ParseTreeFragment::Source);
+        self
+    }
+
+    /// Clears top-level code from the source
+    pub fn drain_top_level_code(&mut self) -> &mut Self {
+        self.top_level_code = Default::default();
+        self.drain_snippets(ParseTreeFragment::Contract);
+        self
+    }
+
+    /// Clears the "run()" function's code
+    pub fn drain_run(&mut self) -> &mut Self {
+        self.run_code = Default::default();
+        self.drain_snippets(ParseTreeFragment::Function);
+        self
+    }
+
+    /// Removes the snippets of a scope, keeping the remaining parts of split inputs together
+    fn drain_snippets(&mut self, fragment: ParseTreeFragment) {
+        let mut starts_input = false;
+        self.snippets.retain_mut(|snippet| {
+            starts_input |= !snippet.continued;
+            if snippet.fragment == fragment {
+                return false
+            }
+            snippet.continued = !starts_input;
+            starts_input = false;
+            true
+        });
+        self.undone.clear();
+        self.generated_output = None;
+    }
+
+    /// Collects the `pragma solidity` directives within the global code
+    ///
+    /// ### Returns
+    ///
+    /// All solidity pragmas, in order of entry. The snippet index of each pragma is only known
+    /// if the global code has not been modified outside of its snippets. Returns an empty vector
+    /// if the global code could not be parsed.
+    pub fn solidity_pragmas(&self) -> Vec<SolidityPragma> {
+        self.solidity_pragmas_for(self.solc.version().ok().as_ref())
+    }
+
+    /// Collects the `pragma solidity` directives within the global code, judging their
+    /// compatibility with a solc version. See [SessionSource::solidity_pragmas].
+    fn solidity_pragmas_for(&self, version: Option<&Version>) -> Vec<SolidityPragma> {
+        let pt::SourceUnit(source_unit_parts) = match solang_parser::parse(&self.global_code, 0) {
+            Ok((source_unit, _)) => source_unit,
+            Err(_) => return Vec::default(),
+        };
+
+        // Map offsets within the global code to the snippets they were entered in
+        let mut snippet_ends = Vec::new();
+        if Self::render_snippets(&self.snippets, ParseTreeFragment::Source) == self.global_code {
+            let mut offset = 0;
+            for (index, snippet) in self.snippets.iter().enumerate() {
+                if snippet.fragment == ParseTreeFragment::Source {
+                    offset += snippet.code.len();
+                    snippet_ends.push((offset, self.snippet_index_of(index)));
+                }
+            }
+        }
+
+        source_unit_parts
+            .into_iter()
+            .filter_map(|part| match part {
+                pt::SourceUnitPart::PragmaDirective(loc, name, value)
+                    if name.name == "solidity" =>
+                {
+                    Some((loc, value.string))
+                }
+                _ => None,
+            })
+            .map(|(loc, requirement)| {
+                let snippet_index = match loc {
+                    pt::Loc::File(_, start, _) => {
+                        snippet_ends.iter().find(|(end, _)| start < *end).map(|(_, index)| *index)
+                    }
+                    _ => None,
+                };
+                // Requirements that can not be interpreted are left for solc to judge
+                let compatible = match (solidity_version_req(&requirement), version) {
+                    (Some(reqs), Some(version)) => reqs.iter().any(|req| req.matches(version)),
+                    _ => true,
+                };
+                SolidityPragma {
+                    snippet_index,
+                    requirement: requirement.trim().to_string(),
+                    compatible,
+                }
+            })
+            .collect()
+    }
+
+    /// Checks that the session's pragma override, if any, is satisfied by the session's solc
+    /// version
+    ///
+    /// ### Takes
+    ///
+    /// The version requirement to check, i.e. `>=0.8.0 <0.9.0`
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, nothing. Fails if the requirement can not be interpreted or is not satisfied.
+    pub fn check_pragma_override(&self, requirement: &str) -> Result<()> {
+        check_pragma_requirement(requirement, &self.solc.version()?)
+    }
+
+    /// Checks that a solc version satisfies all `pragma solidity` directives within the source,
+    /// as well as the session's pragma override
+    ///
+    /// ### Takes
+    ///
+    /// The solc version to check
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, nothing. Fails with a [PragmaConflict] listing all solidity pragmas if any of
+    /// them is not satisfied by the version.
+    pub fn check_solc_version(&self, version: &Version) -> Result<()> {
+        let pragmas = self.solidity_pragmas_for(Some(version));
+        if !pragmas.iter().all(|pragma| pragma.compatible) {
+            return Err(PragmaConflict { solc_version: version.clone(), pragmas }.into())
+        }
+        match &self.pragma {
+            Some(requirement) => check_pragma_requirement(requirement, version),
+            None => Ok(()),
+        }
+    }
+
+    /// Switches the solc version of the source to one that satisfies its `pragma solidity`
+    /// directives, if the current version does not
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the version that was switched to, or `None` if the current version already
+    /// satisfies the directives. The newest satisfying installed version is preferred. If there
+    /// is none, the newest satisfying released version is installed, unless chisel is offline.
+    pub fn select_solc_version(&mut self) -> Result<Option<Version>> {
+        let conflict = match self.check_pragmas() {
+            Ok(_) => return Ok(None),
+            Err(e) => e,
+        };
+
+        let offline = self.config.foundry_config.offline;
+        let newest = |versions: Vec<ethers_solc::SolcVersion>| {
+            versions
+                .into_iter()
+                .filter_map(|version| Version::parse(&version.to_string()).ok())
+                .filter(|version| self.check_solc_version(version).is_ok())
+                .max()
+        };
+        let installed = newest(
+            Solc::installed_versions()
+                .into_iter()
+                .filter(|version| find_svm_solc(&version.to_string()).is_some())
+                .collect(),
+        );
+        let version = match installed {
+            Some(version) => version,
+            None if !offline => match newest(Solc::all_versions()) {
+                Some(version) => version,
+                None => eyre::bail!(
+                    "No released solc version satisfies the session's pragmas; {conflict}"
+                ),
+            },
+            None => {
+                eyre::bail!("No installed solc version satisfies the session's pragmas; {conflict}")
+            }
+        };
+
+        self.solc = find_or_install_solc(&version.to_string(), offline)?;
+        self.generated_output = None;
+        Ok(Some(version))
     }
 
-    /// Clones the [SessionSource] and appends a new line of code. Will return
-    /// an error result if the new line fails to be parsed.
+    /// Checks that all `pragma solidity` directives within the source are satisfied by the
+    /// session's solc version
     ///
     /// ### Returns
     ///
-    /// Optionally, a shallow-cloned [SessionSource] with the passed content appended to the
-    /// source code.
-    pub fn clone_with_new_line(&self, mut content: String) -> Result<(SessionSource, bool)> {
-        let mut new_source = self.shallow_clone();
-        if let Some(parsed) = parse_fragment(&new_source.solc, &new_source.config, &content)
-            .or_else(|| {
-                content = format!("{content};");
-                parse_fragment(&new_source.solc, &new_source.config, &content)
-            })
-            .or_else(|| {
-                parse_fragment(
-                    &new_source.solc,
-                    &new_source.config,
-                    content.trim_end().trim_end_matches(';'),
-                )
-            })
-        {
-            // Flag that tells the dispatcher whether to build or execute the session
-            // source based on the scope of the new code.
-            match parsed {
-                ParseTreeFragment::Function => new_source.with_run_code(&content),
-                ParseTreeFragment::Contract => new_source.with_top_level_code(&content),
-                ParseTreeFragment::Source => new_source.with_global_code(&content),
-            };
-
-            Ok((new_source, matches!(parsed, ParseTreeFragment::Function)))
-        } else {
-            eyre::bail!("\"{}\"", content.trim().to_owned());
+    /// Optionally, nothing. Fails with a [PragmaConflict] listing all solidity pragmas if any of
+    /// them is not satisfied.
+    pub fn check_pragmas(&self) -> Result<()> {
+        let pragmas = self.solidity_pragmas();
+        if pragmas.iter().all(|pragma| pragma.compatible) {
+            return Ok(())
         }
+        Err(PragmaConflict { solc_version: self.solc.version()?, pragmas }.into())
     }
 
-    // Fillers
-
-    /// Appends global-level code to the source
-    pub fn with_global_code(&mut self, content: &str) -> &mut Self {
-        self.global_code.push_str(&format!("{}\n", content.trim()));
-        self.generated_output = None;
+    /// Clears all code from the source
+    ///
+    /// ### Takes
+    ///
+    /// Whether to keep the preamble of the source, i.e. its import and pragma directives
+    pub fn clear(&mut self, keep_preamble: bool) -> &mut Self {
+        let preamble = if keep_preamble { self.preamble() } else { Vec::new() };
+        self.drain_global_code().drain_top_level_code().drain_run();
+        for directive in preamble {
+            self.with_global_code(&directive);
+        }
         self
     }
 
-    /// Appends top-level code to the source
-    pub fn with_top_level_code(&mut self, content: &str) -> &mut Self {
-        self.top_level_code.push_str(&format!("{}\n", content.trim()));
-        self.generated_output = None;
-        self
+    /// Collects the import and pragma directives within the global code
+    ///
+    /// ### Returns
+    ///
+    /// The raw text of all import and pragma directives, in order of entry. Returns an empty
+    /// vector if the global code could not be parsed.
+    fn preamble(&self) -> Vec<String> {
+        let pt::SourceUnit(source_unit_parts) = match solang_parser::parse(&self.global_code, 0) {
+            Ok((source_unit, _)) => source_unit,
+            Err(_) => return Vec::default(),
+        };
+
+        source_unit_parts
+            .into_iter()
+            .filter_map(|part| match part {
+                pt::SourceUnitPart::PragmaDirective(loc, _, _) => Some(loc),
+                pt::SourceUnitPart::ImportDirective(import) => Some(import_key(&import).1),
+                _ => None,
+            })
+            .filter_map(|loc| match loc {
+                pt::Loc::File(_, start, end) => {
+                    Some(self.global_code[start..directive_end(&self.global_code, end)].to_string())
+                }
+                _ => None,
+            })
+            .collect()
     }
 
-    /// Appends code to the "run()" function
-    pub fn with_run_code(&mut self, content: &str) -> &mut Self {
-        self.run_code.push_str(&format!("{}\n", content.trim()));
-        self.generated_output = None;
-        self
+    /// Generates and ethers_solc::CompilerInput from the source
+    ///
+    /// ### Returns
+    ///
+    /// A [CompilerInput] object containing forge-std's `Vm` interface as well as the formatted
+    /// REPL contract source.
+    pub fn compiler_input(&self) -> CompilerInput {
+        self.compiler_input_with_map().0
     }
 
-    // Drains
+    /// Generates and ethers_solc::CompilerInput from the source, along with the map of the REPL
+    /// contract source within it to positions within [SessionSource::snippets]
+    fn compiler_input_with_map(&self) -> (CompilerInput, SnippetMap) {
+        let (content, map) = self.to_repl_source_with_part_map(true);
+        let mut sources = Sources::new();
+        // forge-std's interface is named `Vm`, so that it can be imported like within a project
+        sources.insert(
+            PathBuf::from("forge-std/Vm.sol"),
+            Source { content: format!("{VM_SOURCE}\ninterface Vm is Cheats {{}}\n") },
+        );
+        sources.insert(self.file_name.clone(), Source { content });
+        let mut compiler_input = CompilerInput::with_sources(sources).pop().unwrap();
+        compiler_input.settings.remappings = self.all_remappings();
+        compiler_input.settings.push_output_selection("evm.gasEstimates");
+        compiler_input.settings.push_output_selection("storageLayout");
+        (compiler_input, map)
+    }
 
-    /// Clears global code from the source
-    pub fn drain_global_code(&mut self) -> &mut Self {
-        self.global_code = Default::default();
+    /// Loads the configuration of the source's foundry project again, i.e. after the session
+    /// was loaded from the cache
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, nothing. Fails if the project's configuration could not be loaded, in which
+    /// case the cached configuration is kept.
+    ///
+    /// ### Notes
+    ///
+    /// Sources that were not created within a foundry project are left untouched. The source's
+    /// compiler settings, i.e. its EVM version, take precedence over the project's configuration.
+    pub fn reload_project_config(&mut self) -> Result<()> {
+        let root = match &self.project_root {
+            Some(root) => root,
+            None => return Ok(()),
+        };
+        let mut foundry_config = load_project_config(root)?;
+        if let Some(evm_version) = &self.compiler_settings.evm_version {
+            foundry_config.evm_version = parse_evm_version(evm_version)?;
+        }
+        self.config.foundry_config = foundry_config;
         self.generated_output = None;
-        self
+        Ok(())
     }
 
-    /// Clears top-level code from the source
-    pub fn drain_top_level_code(&mut self) -> &mut Self {
-        self.top_level_code = Default::default();
-        self.generated_output = None;
-        self
+    /// Collects the remappings that imports of the source are resolved with
+    ///
+    /// ### Returns
+    ///
+    /// The remappings of the project, followed by the remappings of the session, which take
+    /// precedence over the former for equal prefixes.
+    pub fn all_remappings(&self) -> Vec<Remapping> {
+        let mut remappings = self.config.foundry_config.get_all_remappings();
+        remappings.extend(self.remappings.iter().cloned());
+        remappings
     }
 
-    /// Clears the "run()" function's code
-    pub fn drain_run(&mut self) -> &mut Self {
-        self.run_code = Default::default();
-        self.generated_output = None;
-        self
+    /// Configures the session's solc binary to read imported files
+    ///
+    /// ### Returns
+    ///
+    /// A [Solc] that is allowed to read the targets of the session's remappings, and that
+    /// resolves imports within the session's source directories. Within a foundry project,
+    /// imports are resolved relative to the project's root, and its libraries may be read.
+    fn solc_with_paths(&self) -> Solc {
+        let mut solc = self.solc.clone();
+        let root = &self.config.foundry_config.__root.0;
+        let project_paths = self.project_root.iter().flat_map(|project_root| {
+            std::iter::once(project_root.clone())
+                .chain(self.config.foundry_config.libs.iter().map(|lib| project_root.join(lib)))
+        });
+        let allowed = self
+            .remappings
+            .iter()
+            .map(|remapping| remapping.path.clone())
+            .chain(
+                self.source_directories
+                    .iter()
+                    .cloned()
+                    .chain(project_paths)
+                    .map(|dir| dir.to_string_lossy().to_string()),
+            )
+            .collect::<Vec<_>>();
+        if !allowed.is_empty() {
+            solc = solc.arg("--allow-paths").arg(allowed.join(","));
+        }
+        if self.project_root.is_some() || !self.source_directories.is_empty() {
+            solc = solc
+                .arg("--base-path")
+                .arg(self.project_root.as_ref().unwrap_or(root).to_string_lossy().to_string());
+            for dir in &self.source_directories {
+                solc = solc.arg("--include-path").arg(dir.to_string_lossy().to_string());
+            }
+        }
+        solc
     }
 
-    /// Generates and ethers_solc::CompilerInput from the source
+    /// Computes a checksum of everything the compiled output of the source depends on
     ///
     /// ### Returns
     ///
-    /// A [CompilerInput] object containing forge-std's `Vm` interface as well as the REPL contract
-    /// source.
-    pub fn compiler_input(&self) -> CompilerInput {
-        let mut sources = Sources::new();
-        sources.insert(PathBuf::from("forge-std/Vm.sol"), Source { content: VM_SOURCE.to_owned() });
-        sources.insert(self.file_name.clone(), Source { content: self.to_repl_source() });
-        CompilerInput::with_sources(sources).pop().unwrap()
+    /// The hex-encoded keccak256 hash of the compiler input, with the session's compiler
+    /// settings applied, and the path of the solc binary.
+    pub fn compiler_input_checksum(&self) -> Result<String> {
+        let mut compiler_input = self.compiler_input();
+        self.compiler_settings.apply(&mut compiler_input)?;
+        let mut raw = serde_json::to_vec(&compiler_input)?;
+        let solc = self.solc_with_paths();
+        for arg in std::iter::once(solc.solc.to_string_lossy().to_string()).chain(solc.args) {
+            raw.push(0);
+            raw.extend_from_slice(arg.as_bytes());
+        }
+        Ok(hex::encode(keccak256(raw)))
     }
 
     /// Compiles the source using [solang_parser]
@@ -271,38 +2908,236 @@ impl SessionSource {
     ///
     /// Optionally, a [CompilerOutput] object that contains compilation artifacts.
     pub fn compile(&self) -> Result<CompilerOutput> {
-        // Compile the contract
-        let compiled = self.solc.compile_exact(&self.compiler_input())?;
+        // Refuse to compile source with pragmas that can not be satisfied, or that was rendered
+        // into code that does not parse
+        self.check_pragmas()?;
+        self.validate()?;
 
-        // Extract compiler errors
+        // Compile the contract with the session's compiler settings
+        let (mut compiler_input, map) = self.compiler_input_with_map();
+        self.compiler_settings.apply(&mut compiler_input)?;
+        let compiled = self.solc_with_paths().compile_exact(&compiler_input)?;
+
+        // Extract compiler errors, pointing out the snippets they originate from
         let errors =
             compiled.errors.iter().filter(|error| error.severity.is_error()).collect::<Vec<_>>();
         if !errors.is_empty() {
-            eyre::bail!(
-                "Compiler errors:\n{}",
-                errors.into_iter().map(|err| err.to_string()).collect::<String>()
-            );
+            let source = &compiler_input.sources[&self.file_name].content;
+            let (locations, descriptions) =
+                errors.iter().map(|err| self.describe_compiler_error(err, source, &map)).unzip();
+            return Err(CompilerDiagnostics {
+                errors: errors.into_iter().cloned().collect(),
+                locations,
+                descriptions,
+                via_ir: self.compiler_settings.via_ir,
+                remappings: errors
+                    .iter()
+                    .any(|error| is_unresolved_import(error))
+                    .then_some(compiler_input.settings.remappings),
+            }
+            .into())
         }
 
         Ok(compiled)
     }
 
+    /// Describes a compiler error against the snippet it originates from, if known
+    ///
+    /// ### Takes
+    ///
+    /// - A reference to the compiler error
+    /// - The REPL contract source that was compiled
+    /// - The map of the compiled source to positions within [SessionSource::snippets]
+    ///
+    /// ### Returns
+    ///
+    /// The location of the error within its snippet, along with the error rendered against
+    /// the snippet's own code. Errors within the generated code surrounding the snippets say
+    /// so, and errors without a location in the REPL contract are formatted as solc does.
+    fn describe_compiler_error(
+        &self,
+        error: &ethers_solc::artifacts::Error,
+        source: &str,
+        map: &SnippetMap,
+    ) -> (Option<SnippetSpan>, String) {
+        let range = error
+            .source_location
+            .as_ref()
+            .filter(|location| Path::new(&location.file) == self.file_name)
+            .filter(|_| !map.is_empty())
+            .and_then(|location| {
+                let start = usize::try_from(location.start).ok()?.min(source.len());
+                let end = usize::try_from(location.end).unwrap_or(start).clamp(start, source.len());
+                Some(start..end)
+            })
+            .filter(|range| {
+                source.is_char_boundary(range.start) && source.is_char_boundary(range.end)
+            });
+        let range = match range {
+            Some(range) => range,
+            None => return (None, error.to_string()),
+        };
+
+        let heading = format!("error: {}: {}", error.r#type, error.message);
+        let line = source[..range.start].matches('\n').count() + 1;
+        let snippet =
+            map.iter().find(|(lines, _)| lines.contains(&line)).and_then(|(lines, position)| {
+                let (index, code, part) = self.snippet_of_part(*position)?;
+                Some((lines, index, code, part))
+            });
+        let (lines, index, code, part) = match snippet {
+            Some(snippet) => snippet,
+            None => {
+                return (
+                    None,
+                    format!(
+                        "{heading}\n --> line {line} of the generated REPL contract, outside of any snippet\n"
+                    ),
+                )
+            }
+        };
+
+        // The compiled source is formatted, so find the offending code within the snippet's
+        // own code, preferring an occurrence on the same line of the snippet.
+        let span = locate_in_snippet(&code[part.clone()], source, lines, range);
+        let span = span.start + part.start..span.end + part.start;
+
+        let mut description = String::new();
+        let gutter = (code[..span.start].matches('\n').count() + 1).to_string().len();
+        let _ = write_underlined_span(
+            &mut description,
+            &code,
+            &span,
+            &heading,
+            &format!("snippet {index}:"),
+            gutter,
+        );
+        description.push('\n');
+        (Some(SnippetSpan { index, span }), description)
+    }
+
+    /// Checks that the REPL contract rendered from the source parses, before it is handed to
+    /// solc
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, nothing. Fails with [GeneratedSourceDiagnostics] if the rendered source does
+    /// not parse, i.e. because snippets that were appended verbatim break the code surrounding
+    /// them.
+    ///
+    /// ### Notes
+    ///
+    /// The parser often only notices an error at the token following it, so errors within the
+    /// generated code are attributed to the snippet preceding them.
+    pub fn validate(&self) -> Result<()> {
+        let (source, map) = self.to_repl_source_with_part_map(false);
+        let diagnostics = match solang_parser::parse(&source, 0) {
+            Ok(_) => return Ok(()),
+            Err(diagnostics) => diagnostics,
+        };
+
+        let (locations, descriptions) = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let range = match diagnostic.loc {
+                    pt::Loc::File(_, start, end) => {
+                        let start = start.min(source.len());
+                        start..end.clamp(start, source.len())
+                    }
+                    _ => source.len()..source.len(),
+                };
+                let heading = format!("error: {}", diagnostic.message);
+                let line = source[..range.start].matches('\n').count() + 1;
+                let snippet = map
+                    .iter()
+                    .find(|(lines, _)| lines.contains(&line))
+                    .or_else(|| {
+                        map.iter().filter(|(lines, _)| lines.end <= line).max_by_key(|(lines, _)| lines.end)
+                    })
+                    .and_then(|(lines, position)| {
+                        let (index, code, part) = self.snippet_of_part(*position)?;
+                        Some((lines, index, code, part))
+                    });
+                match snippet {
+                    Some((lines, index, code, part)) => {
+                        let span = if lines.contains(&line) {
+                            let span = locate_in_snippet(&code[part.clone()], &source, lines, range);
+                            span.start + part.start..span.end + part.start
+                        } else {
+                            let end = part.start + code[part].trim_end().len();
+                            end..end
+                        };
+                        let gutter = (code[..span.start].matches('\n').count() + 1).to_string().len();
+                        let mut description = String::new();
+                        let _ = write_underlined_span(
+                            &mut description,
+                            &code,
+                            &span,
+                            &heading,
+                            &format!("snippet {index}:"),
+                            gutter,
+                        );
+                        description.push('\n');
+                        (Some(SnippetSpan { index, span }), description)
+                    }
+                    None => (
+                        None,
+                        format!(
+                            "{heading}\n --> line {line} of the generated REPL contract, outside of any snippet\n"
+                        ),
+                    ),
+                }
+            })
+            .unzip();
+        Err(GeneratedSourceDiagnostics { locations, descriptions }.into())
+    }
+
     /// Builds the SessionSource from input into the complete CompiledOutput
     ///
     /// ### Returns
     ///
     /// Optionally, a [GeneratedOutput] object containing both the [CompilerOutput] and the
     /// [IntermediateOutput].
+    ///
+    /// ### Notes
+    ///
+    /// If the compiler input did not change since the source was last built, the previous
+    /// output is returned without invoking the compiler again.
     pub fn build(&mut self) -> Result<GeneratedOutput> {
+        // Reuse the previous output if the source was not modified since it was built
+        let started = Instant::now();
+        let checksum = self.compiler_input_checksum()?;
+        let checksum_time = started.elapsed();
+        if let Some(generated_output) = self
+            .generated_output
+            .as_ref()
+            .filter(|_| self.output_checksum.as_deref() == Some(checksum.as_str()))
+        {
+            tracing::trace!("session source is unchanged, reusing its compiled output");
+            let previous = self.compile_stats.take().unwrap_or_default();
+            self.compile_stats = Some(CompileStats {
+                cache_hit: true,
+                sources: generated_output.compiler_output.sources.len(),
+                solc_version: previous.solc_version,
+                checksum: checksum_time,
+                total: started.elapsed(),
+                ..Default::default()
+            });
+            return Ok(generated_output.clone())
+        }
+
         // Compile
+        let solc_started = Instant::now();
         let compiler_output = self.compile()?;
+        let solc_time = solc_started.elapsed();
 
         // Parse generate intermediate contracts
+        let parsing_started = Instant::now();
         let intermediate_contracts = self.generate_intermediate_contracts()?;
 
         // Construct variable definitions
         let variable_definitions = intermediate_contracts
-            .get("REPL")
+            .get(&self.contract_name)
             .ok_or(eyre::eyre!("Could not find intermediate REPL contract!"))?
             .variable_definitions
             .clone()
@@ -311,6 +3146,7 @@ impl SessionSource {
             .collect::<HashMap<String, pt::Expression>>();
         // Construct intermediate output
         let mut intermediate_output = IntermediateOutput {
+            repl_contract_name: self.contract_name.clone(),
             repl_contract_expressions: variable_definitions,
             intermediate_contracts,
         };
@@ -323,23 +3159,142 @@ impl SessionSource {
         }
 
         // Construct generated output
+        let parsing_time = parsing_started.elapsed();
+        let stats = CompileStats {
+            cache_hit: false,
+            sources: compiler_output.sources.len(),
+            solc_version: self.solc.version().ok(),
+            checksum: checksum_time,
+            solc: solc_time,
+            parsing: parsing_time,
+            total: started.elapsed(),
+        };
+        // Compare the storage layout of the REPL contract to the one of the previous compile, as
+        // the raw state of the session no longer lines up with variables that moved
+        let layout = compiler_output
+            .contracts
+            .values()
+            .find_map(|contracts| contracts.get(&self.contract_name))
+            .map(|contract| contract.storage_layout.clone());
+        self.moved_variables = match (&self.storage_layout, &layout) {
+            (Some(previous), Some(current)) => moved_variables(previous, current),
+            _ => Vec::new(),
+        };
+        self.storage_layout = layout;
+
         let generated_output =
             GeneratedOutput { intermediate: intermediate_output, compiler_output };
         self.generated_output = Some(generated_output.clone()); // ehhh, need to not clone this.
+        self.output_checksum = Some(checksum);
+        tracing::debug!(elapsed = ?stats.total, solc = ?stats.solc, "built session source");
+        self.compile_stats = Some(stats);
         Ok(generated_output)
     }
 
+    /// Builds the SessionSource and extracts the compiled REPL contract from its output
+    ///
+    /// ### Returns
+    ///
+    /// A [CompileResult] for the REPL contract. If compilation fails, the error can be
+    /// downcast to [CompilerDiagnostics], or to [GeneratedSourceDiagnostics] if the REPL
+    /// contract did not parse.
+    pub fn compile_repl_contract(&mut self) -> Result<CompileResult> {
+        self.build()?;
+        self.artifact().ok_or_else(|| eyre::eyre!("Failed to find REPL contract!"))
+    }
+
+    /// Extracts the compiled REPL contract from the most recent build of the source, without
+    /// compiling it
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the [CompileResult] of the REPL contract. Returns `None` if the source has
+    /// not been built since it was last modified.
+    pub fn artifact(&self) -> Option<CompileResult> {
+        let output = self.generated_output.as_ref()?;
+        let mut result = CompileResult::from_output(
+            &output.compiler_output,
+            &self.file_name,
+            &self.contract_name,
+        )?;
+        result.stats = self.compile_stats.clone();
+        Some(result)
+    }
+
+    /// Collects the contracts that the source was compiled to, i.e. to identify them in traces
+    ///
+    /// ### Returns
+    ///
+    /// The ABI and deployed bytecode of each contract of the source's last compilation, by
+    /// [ArtifactId]. Empty if the source has not been compiled yet.
+    ///
+    /// ### Notes
+    ///
+    /// - Interfaces, i.e. those added with `!abi`, are included without bytecode, so that calls to
+    ///   them can still be decoded by their functions.
+    /// - The REPL contract's bytecode is linked against its libraries.
+    pub fn known_contracts(&self) -> ContractsByArtifact {
+        let output = match self.generated_output.as_ref() {
+            Some(output) => &output.compiler_output,
+            None => return ContractsByArtifact::default(),
+        };
+        let version = self
+            .compile_stats
+            .as_ref()
+            .and_then(|stats| stats.solc_version.clone())
+            .unwrap_or_else(|| Version::new(0, 0, 0));
+        let repl_deployed_bytecode = link_repl_contract(output, &self.contract_name)
+            .ok()
+            .map(|(_, _, deployed_bytecode)| deployed_bytecode);
+
+        ContractsByArtifact(
+            output
+                .contracts
+                .iter()
+                .filter(|(file, _)| Path::new(file) != Path::new("forge-std/Vm.sol"))
+                .flat_map(|(file, contracts)| {
+                    contracts.iter().map(move |(name, contract)| (file, name, contract))
+                })
+                .filter_map(|(file, name, contract)| {
+                    let contract = CompactContractBytecode::from(contract.clone());
+                    let deployed_bytecode =
+                        if Path::new(file) == self.file_name && name == &self.contract_name {
+                            repl_deployed_bytecode.clone()
+                        } else {
+                            contract
+                                .deployed_bytecode
+                                .and_then(|deployed| deployed.bytecode)
+                                .and_then(|bytecode| bytecode.object.as_bytes().cloned())
+                        };
+                    let id = ArtifactId {
+                        path: PathBuf::from(file),
+                        name: name.clone(),
+                        source: PathBuf::from(file),
+                        version: version.clone(),
+                    };
+                    Some((
+                        id,
+                        (
+                            contract.abi?,
+                            deployed_bytecode.map(|code| code.to_vec()).unwrap_or_default(),
+                        ),
+                    ))
+                })
+                .collect(),
+        )
+    }
+
     /// Convert the [SessionSource] to a valid Script contract
     ///
     /// ### Returns
     ///
     /// The [SessionSource] represented as a Forge Script contract.
     pub fn to_script_source(&self) -> String {
-        let Version { major, minor, patch, .. } = self.solc.version().unwrap();
         format!(
             r#"
-// SPDX-License-Identifier: UNLICENSED
-pragma solidity ^{major}.{minor}.{patch};
+// SPDX-License-Identifier: {}
+pragma solidity {};
+{}
 
 import {{Script}} from "forge-std/Script.sol";
 {}
@@ -353,37 +3308,346 @@ contract {} is Script {{
     }}
 }}
             "#,
-            self.global_code, self.contract_name, self.top_level_code, self.run_code,
+            self.license,
+            self.pragma_requirement(),
+            self.provenance_header(),
+            self.deduplicated_global_code(),
+            self.contract_name,
+            merge_constructors(&self.top_level_code),
+            self.run_code,
         )
     }
 
+    /// Renders a comment listing the provenance of each snippet of the source
+    ///
+    /// ### Returns
+    ///
+    /// The comment, i.e. `// Snippet 0: entered 2024-03-02 14:01 (interactive)` for each
+    /// snippet, preceded by a blank line. Empty if the source has no snippets.
+    fn provenance_header(&self) -> String {
+        self.snippets
+            .iter()
+            .filter(|snippet| !snippet.continued)
+            .enumerate()
+            .map(|(index, snippet)| format!("\n// Snippet {index}: {}", snippet.provenance()))
+            .collect()
+    }
+
     /// Convert the [SessionSource] to a valid REPL contract
     ///
     /// ### Returns
     ///
     /// The [SessionSource] represented as a REPL contract.
     pub fn to_repl_source(&self) -> String {
+        self.to_repl_source_with_map().0
+    }
+
+    /// Convert the [SessionSource] to a valid REPL contract, along with a map of its lines to
+    /// the snippets they were entered in
+    ///
+    /// ### Returns
+    ///
+    /// The [SessionSource] represented as a REPL contract, and its [SnippetMap]. The map is empty
+    /// if the source has been modified outside of its snippets.
+    pub fn to_repl_source_with_map(&self) -> (String, SnippetMap) {
+        let (source, map) = self.to_repl_source_with_part_map(false);
+        (source, self.map_to_snippets(map))
+    }
+
+    /// Renders the REPL contract along with a map of its lines to positions within
+    /// [SessionSource::snippets], rather than to the entered snippets that the parts of split
+    /// inputs belong to
+    ///
+    /// ### Takes
+    ///
+    /// Whether to format the REPL contract
+    fn to_repl_source_with_part_map(&self, formatted: bool) -> (String, SnippetMap) {
+        match self.to_marked_repl_source() {
+            // The markers are comments, which the formatter preserves
+            Some(marked) if formatted => strip_snippet_markers(&self.format(marked)),
+            Some(marked) => strip_snippet_markers(&marked),
+            None if formatted => {
+                (self.format(self.render_unmarked_repl_source()), SnippetMap::new())
+            }
+            None => (self.render_unmarked_repl_source(), SnippetMap::new()),
+        }
+    }
+
+    /// Converts a map of lines to positions within [SessionSource::snippets] into a
+    /// [SnippetMap], see [SessionSource::snippet_index_of]
+    fn map_to_snippets(&self, map: SnippetMap) -> SnippetMap {
+        map.into_iter().map(|(lines, position)| (lines, self.snippet_index_of(position))).collect()
+    }
+
+    /// Determines the version requirement of the rendered source's `pragma solidity` directive
+    ///
+    /// ### Returns
+    ///
+    /// In order of precedence:
+    /// - The requirements of all solidity pragmas entered as snippets, merged into one. If any of
+    ///   them has `||` separated alternatives, the requirement derived from the solc version is
+    ///   used instead, as they can not be merged.
+    /// - The session's pragma override.
+    /// - A caret requirement on the solc version, i.e. `^0.8.17`.
+    pub fn pragma_requirement(&self) -> String {
         let Version { major, minor, patch, .. } = self.solc.version().unwrap();
+        let derived = format!("^{major}.{minor}.{patch}");
+
+        let pragmas = self.solidity_pragmas();
+        if !pragmas.is_empty() {
+            if pragmas.iter().any(|pragma| pragma.requirement.contains("||")) {
+                return derived
+            }
+            return pragmas
+                .into_iter()
+                .map(|pragma| pragma.requirement)
+                .collect::<Vec<_>>()
+                .join(" ")
+        }
+        self.pragma.clone().unwrap_or(derived)
+    }
+
+    /// Renders the REPL contract from the raw code of each scope of the source
+    fn render_unmarked_repl_source(&self) -> String {
+        self.render_repl_source(
+            &self.deduplicated_global_code(),
+            &merge_constructors(&self.top_level_code),
+            &self.run_code,
+        )
+    }
+
+    /// Renders the REPL contract with each snippet enclosed in marker comments
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the marked REPL contract. Returns `None` if the source has been modified
+    /// outside of its snippets.
+    fn to_marked_repl_source(&self) -> Option<String> {
+        let mut sections = Vec::new();
+        for fragment in
+            [ParseTreeFragment::Source, ParseTreeFragment::Contract, ParseTreeFragment::Function]
+        {
+            if self.code(fragment) != Self::render_snippets(&self.snippets, fragment) {
+                return None
+            }
+            let mut section = String::new();
+            for (index, snippet) in self.snippets.iter().enumerate() {
+                if snippet.fragment == fragment {
+                    section.push_str(&format!("{SNIPPET_START_MARKER}{index}\n{}", snippet.code));
+                    if !snippet.code.ends_with('\n') {
+                        section.push('\n');
+                    }
+                    section.push_str(&format!("{SNIPPET_END_MARKER}\n"));
+                }
+            }
+            sections.push(section);
+        }
+        // Only imports and pragmas are deduplicated, and only function definitions merged, so
+        // skip re-parsing the sections if no snippet can contain them.
+        let has_kind = |fragment, kinds: &[SnippetKind]| {
+            self.snippets.iter().any(|snippet| {
+                snippet.fragment == fragment &&
+                    (snippet.kind == SnippetKind::Mixed || kinds.contains(&snippet.kind))
+            })
+        };
+        let global_code =
+            if has_kind(ParseTreeFragment::Source, &[SnippetKind::Pragma, SnippetKind::Import]) {
+                deduplicate_global_code(&sections[0])
+            } else {
+                sections[0].clone()
+            };
+        let top_level_code = if has_kind(ParseTreeFragment::Contract, &[SnippetKind::FunctionDef]) {
+            merge_constructors(&sections[1])
+        } else {
+            sections[1].clone()
+        };
+        Some(self.render_repl_source(&global_code, &top_level_code, &sections[2]))
+    }
+
+    /// Renders the REPL contract template
+    ///
+    /// ### Takes
+    ///
+    /// - The global code
+    /// - The top level code
+    /// - The code within the "run()" function
+    fn render_repl_source(
+        &self,
+        global_code: &str,
+        top_level_code: &str,
+        run_code: &str,
+    ) -> String {
         format!(
             r#"
-// SPDX-License-Identifier: UNLICENSED
-pragma solidity ^{major}.{minor}.{patch};
+// SPDX-License-Identifier: {}
+pragma solidity {};
 
 import {{Cheats}} from "forge-std/Vm.sol";
 {}
 
-contract {} {{
-    Cheats internal constant vm = Cheats(address(uint160(uint256(keccak256("hevm cheat code")))));
-    {}
-  
-    /// @notice REPL contract entry point
-    function run() public {{
-        {}
-    }}
-}}
-            "#,
-            self.global_code, self.contract_name, self.top_level_code, self.run_code,
-        )
+contract {} {{
+    Cheats internal constant vm = Cheats(address(uint160(uint256(keccak256("hevm cheat code")))));
+    {}
+  
+    /// @notice REPL contract entry point
+    function run() public payable {{
+        {}
+    }}
+}}
+            "#,
+            self.license,
+            self.pragma_requirement(),
+            global_code,
+            self.contract_name,
+            top_level_code,
+            run_code,
+        )
+    }
+
+    /// Formats the REPL contract with the session's formatter configuration
+    ///
+    /// ### Returns
+    ///
+    /// The [SessionSource] represented as a formatted REPL contract. If formatting fails, the
+    /// unformatted REPL contract from [SessionSource::to_repl_source] is returned instead.
+    pub fn to_formatted_repl_source(&self) -> String {
+        self.to_formatted_repl_source_with_map().0
+    }
+
+    /// Formats the REPL contract with the session's formatter configuration, along with a map of
+    /// its lines to the snippets they were entered in
+    ///
+    /// ### Returns
+    ///
+    /// The [SessionSource] represented as a formatted REPL contract, and its [SnippetMap]. See
+    /// [SessionSource::to_formatted_repl_source] and [SessionSource::to_repl_source_with_map].
+    pub fn to_formatted_repl_source_with_map(&self) -> (String, SnippetMap) {
+        let (source, map) = self.to_repl_source_with_part_map(true);
+        (source, self.map_to_snippets(map))
+    }
+
+    /// Formats the Script contract with the session's formatter configuration
+    ///
+    /// ### Returns
+    ///
+    /// The [SessionSource] represented as a formatted Script contract. If formatting fails, the
+    /// unformatted Script contract from [SessionSource::to_script_source] is returned instead.
+    pub fn to_formatted_script_source(&self) -> String {
+        self.format(self.to_script_source())
+    }
+
+    /// Formats solidity source with the session's formatter configuration, falling back to the
+    /// unformatted source if formatting fails.
+    fn format(&self, source: String) -> String {
+        format_source(&source, self.config.foundry_config.fmt.clone()).unwrap_or(source)
+    }
+
+    /// Removes import directives from the global code that import the same path and symbols as
+    /// an earlier import directive, as well as all `pragma solidity` directives, which are merged
+    /// into the pragma of the rendered source.
+    ///
+    /// ### Returns
+    ///
+    /// The global code with duplicate imports and solidity pragmas removed. The raw text of all
+    /// other code, including the first occurrence of each import, is preserved. If the global
+    /// code can not be parsed, it is returned as-is.
+    pub fn deduplicated_global_code(&self) -> String {
+        deduplicate_global_code(&self.global_code)
+    }
+
+    /// Builds the symbol table of the session source
+    ///
+    /// ### Returns
+    ///
+    /// Every item defined by the snippets of the source, in order of entry. See
+    /// [SolSnippet::symbols].
+    pub fn defined_symbols(&self) -> Vec<Symbol> {
+        self.snippets
+            .iter()
+            .enumerate()
+            .flat_map(|(position, snippet)| {
+                let snippet_index = self.snippet_index_of(position);
+                snippet.symbols().into_iter().map(move |(name, kind)| Symbol {
+                    name,
+                    kind,
+                    snippet_index,
+                })
+            })
+            .collect()
+    }
+
+    /// Builds the [ValueFormatter] that values of the session source are displayed with
+    ///
+    /// ### Returns
+    ///
+    /// A [ValueFormatter] with the session's number format, which knows the structs, enums, and
+    /// events defined by the source. It knows none if the source could not be parsed.
+    pub fn value_formatter(&self) -> ValueFormatter {
+        match self.parse() {
+            Ok(source_unit) => ValueFormatter::new(self.number_format, &source_unit),
+            Err(_) => ValueFormatter { numbers: self.number_format, ..Default::default() },
+        }
+    }
+
+    /// Collects the names of all items defined within the session source
+    ///
+    /// ### Returns
+    ///
+    /// The names of all global definitions, definitions within the top level of the REPL
+    /// contract (excluding the builtin `vm` and `run`), and variables declared within the
+    /// "run()" function. Returns an empty vector if the source could not be parsed.
+    pub fn defined_names(&self) -> Vec<String> {
+        let pt::SourceUnit(source_unit_parts) = match self.parse() {
+            Ok(source_unit) => source_unit,
+            Err(_) => return Vec::default(),
+        };
+
+        let mut names = Vec::new();
+        for part in source_unit_parts {
+            match part {
+                pt::SourceUnitPart::ContractDefinition(cd)
+                    if cd.name.name == self.contract_name =>
+                {
+                    for part in cd.parts {
+                        match part {
+                            pt::ContractPart::FunctionDefinition(def) => match def.name {
+                                Some(name) if name.name == "run" => {
+                                    if let Some(pt::Statement::Block { statements, .. }) = &def.body
+                                    {
+                                        names.extend(
+                                            statements
+                                                .iter()
+                                                .flat_map(Self::get_statement_definitions)
+                                                .map(|(name, _)| name),
+                                        );
+                                    }
+                                }
+                                Some(name) => names.push(name.name),
+                                None => {}
+                            },
+                            pt::ContractPart::VariableDefinition(def) if def.name.name != "vm" => {
+                                names.push(def.name.name)
+                            }
+                            pt::ContractPart::EventDefinition(def) => names.push(def.name.name),
+                            pt::ContractPart::StructDefinition(def) => names.push(def.name.name),
+                            pt::ContractPart::EnumDefinition(def) => names.push(def.name.name),
+                            pt::ContractPart::ErrorDefinition(def) => names.push(def.name.name),
+                            _ => {}
+                        }
+                    }
+                }
+                pt::SourceUnitPart::ContractDefinition(cd) => names.push(cd.name.name),
+                pt::SourceUnitPart::FunctionDefinition(def) => {
+                    names.extend(def.name.map(|name| name.name))
+                }
+                pt::SourceUnitPart::EventDefinition(def) => names.push(def.name.name),
+                pt::SourceUnitPart::StructDefinition(def) => names.push(def.name.name),
+                pt::SourceUnitPart::EnumDefinition(def) => names.push(def.name.name),
+                pt::SourceUnitPart::ErrorDefinition(def) => names.push(def.name.name),
+                _ => {}
+            }
+        }
+        names
     }
 
     /// Gets the [IntermediateContract] for a Solidity source string and inserts it into the
@@ -433,6 +3697,9 @@ contract {} {{
                             pt::ContractPart::StructDefinition(def) => {
                                 intermediate.struct_definitions.insert(def.name.name.clone(), def);
                             }
+                            pt::ContractPart::EnumDefinition(def) => {
+                                intermediate.enum_definitions.insert(def.name.name.clone(), def);
+                            }
                             pt::ContractPart::VariableDefinition(def) => {
                                 intermediate
                                     .variable_definitions
@@ -493,7 +3760,7 @@ impl IntermediateOutput {
     pub fn run_func_body(&self) -> Result<Vec<pt::Statement>> {
         match self
             .intermediate_contracts
-            .get("REPL")
+            .get(&self.repl_contract_name)
             .ok_or(eyre::eyre!("Could not find REPL intermediate contract!"))?
             .function_definitions
             .get("run")
@@ -508,11 +3775,740 @@ impl IntermediateOutput {
     }
 }
 
+/// The names of the EVM versions that the session's source can be compiled for, oldest first
+static EVM_VERSION_NAMES: &[&str] = &[
+    "homestead",
+    "tangerineWhistle",
+    "spuriousDragon",
+    "byzantium",
+    "constantinople",
+    "petersburg",
+    "istanbul",
+    "berlin",
+    "london",
+    "paris",
+    "shanghai",
+    "cancun",
+];
+
+/// Cheatcodes whose transactions are only recorded to be broadcast, which a REPL session never does
+static BROADCAST_CHEATCODES: &[&str] = &["broadcast", "startBroadcast", "stopBroadcast"];
+
+/// Cheatcodes that set up an expectation for the calls that follow them. The expectation fails
+/// the execution if no call satisfies it before the end of the `run()` function.
+static EXPECTATION_CHEATCODES: &[&str] = &["expectRevert", "expectEmit", "expectCall"];
+
+/// Checks the cheatcodes that the code of a snippet calls for ones that can't be used within a
+/// REPL session
+///
+/// ### Takes
+///
+/// The code of the snippet
+///
+/// ### Returns
+///
+/// Optionally, nothing. Fails if the code broadcasts transactions, or if it sets up an
+/// expectation, i.e. `vm.expectRevert()`, without making a call after it within the same snippet.
+/// Expectations can not carry over to later inputs, as every input is executed as a call of its
+/// own.
+pub fn check_cheatcodes(code: &str) -> Result<()> {
+    let is_identifier = |c: char| c.is_alphanumeric() || c == '_';
+    for (offset, _) in code.match_indices("vm.") {
+        // Skip members of other identifiers that end in `vm`, i.e. `evm.`
+        if code[..offset].chars().next_back().map_or(false, |c| is_identifier(c) || c == '.') {
+            continue
+        }
+        let rest = &code[offset + "vm.".len()..];
+        let name = &rest[..rest.find(|c: char| !is_identifier(c)).unwrap_or(rest.len())];
+
+        if BROADCAST_CHEATCODES.contains(&name) {
+            eyre::bail!(
+                "`vm.{name}` is not supported within chisel, use `!broadcast` to send statements to a network instead"
+            )
+        }
+        if EXPECTATION_CHEATCODES.contains(&name) {
+            let after = &rest[name.len()..];
+            let following = after.find(';').map_or("", |end| &after[end + 1..]);
+            if !following.contains('(') {
+                eyre::bail!(
+                    "`vm.{name}` must be followed by the call that it applies to within the same input, i.e. `vm.{name}(); target.call();`, as expectations can not carry over to later inputs"
+                )
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Lists the EVM versions that the session's source can be compiled for
+///
+/// ### Returns
+///
+/// The names of all EVM versions known to the compiler, oldest first.
+pub fn evm_versions() -> Vec<&'static str> {
+    EVM_VERSION_NAMES.iter().copied().filter(|name| name.parse::<EvmVersion>().is_ok()).collect()
+}
+
+/// Parses the name of an EVM version, ignoring its case
+///
+/// ### Takes
+///
+/// The name of the EVM version, i.e. `london` or `Shanghai`
+///
+/// ### Returns
+///
+/// Optionally, the [EvmVersion]. Fails with a list of the valid names if the version is unknown.
+pub fn parse_evm_version(name: &str) -> Result<EvmVersion> {
+    let versions = evm_versions();
+    versions
+        .iter()
+        .find(|version| version.eq_ignore_ascii_case(name.trim()))
+        .and_then(|version| version.parse::<EvmVersion>().ok())
+        .ok_or_else(|| {
+            eyre::eyre!(
+                "Unknown EVM version \"{name}\"; Valid versions are: {}",
+                versions.join(", ")
+            )
+        })
+}
+
+/// Checks that a solc version satisfies a solidity version requirement
+///
+/// ### Takes
+///
+/// - The version requirement to check, i.e. `>=0.8.0 <0.9.0`
+/// - The solc version
+///
+/// ### Returns
+///
+/// Optionally, nothing. Fails if the requirement can not be interpreted or is not satisfied.
+fn check_pragma_requirement(requirement: &str, version: &Version) -> Result<()> {
+    let reqs = solidity_version_req(requirement)
+        .ok_or(eyre::eyre!("Invalid version requirement \"{requirement}\""))?;
+    if !reqs.iter().any(|req| req.matches(version)) {
+        eyre::bail!("`pragma solidity {requirement};` is incompatible with solc {version}")
+    }
+    Ok(())
+}
+
+/// Links the REPL contract against the libraries that it calls into externally
+///
+/// ### Takes
+///
+/// - The [CompilerOutput] of the session
+/// - The name of the REPL contract
+///
+/// ### Returns
+///
+/// Optionally, each library the REPL contract depends on along with its creation bytecode, in
+/// order of deployment, followed by the linked creation and runtime bytecode of the REPL
+/// contract. The libraries are linked as if they were deployed by [Address::zero] starting at
+/// nonce 0.
+pub(crate) fn link_repl_contract(
+    output: &CompilerOutput,
+    contract_name: &str,
+) -> Result<(Vec<(LinkedLibrary, Bytes)>, Bytes, Bytes)> {
+    let contracts = output
+        .contracts
+        .iter()
+        .flat_map(|(file, contracts)| {
+            contracts.iter().map(move |(name, contract)| {
+                (format!("{file}:{name}"), CompactContractBytecode::from(contract.clone()))
+            })
+        })
+        .collect::<BTreeMap<_, _>>();
+    let dependency_tree = contracts
+        .iter()
+        .map(|(id, contract)| {
+            let dependencies = contract
+                .all_link_references()
+                .iter()
+                .flat_map(|(file, links)| {
+                    links
+                        .keys()
+                        .map(move |key| (format!("{file}:{key}"), file.clone(), key.clone()))
+                })
+                .collect::<Vec<_>>();
+            (id.clone(), dependencies)
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    let (id, contract) = contracts
+        .iter()
+        .find(|(id, _)| id.rsplit(':').next() == Some(contract_name))
+        .ok_or(eyre::eyre!("Failed to find REPL contract!"))?;
+    let mut bytecode = contract.bytecode.clone().ok_or(eyre::eyre!("No bytecode for contract."))?;
+    let mut deployed_bytecode = contract
+        .deployed_bytecode
+        .clone()
+        .and_then(|deployed_bytecode| deployed_bytecode.bytecode)
+        .ok_or(eyre::eyre!("No deployed bytecode for contract."))?;
+
+    let mut libraries = Vec::new();
+    foundry_utils::recurse_link(
+        id.clone(),
+        (&mut bytecode, &mut deployed_bytecode),
+        &contracts,
+        &dependency_tree,
+        &mut libraries,
+        &Libraries::default(),
+        U256::zero(),
+        Address::zero(),
+    );
+
+    let into_bytes = |bytecode: CompactBytecode| {
+        bytecode.object.into_bytes().ok_or(eyre::eyre!("REPL contract could not be linked!"))
+    };
+    let libraries = libraries
+        .into_iter()
+        .map(|(id, library)| {
+            // Deployed libraries are identified as `<file>:<name>:<address>`
+            let mut parts = id.rsplitn(3, ':');
+            let address = parts.next().and_then(|address| address.parse().ok());
+            let name = parts.next();
+            let file = parts.next();
+            match (file, name, address) {
+                (Some(file), Some(name), Some(address)) => Ok((
+                    LinkedLibrary { file: file.to_string(), name: name.to_string(), address },
+                    library,
+                )),
+                _ => Err(eyre::eyre!("Invalid library identifier \"{id}\"")),
+            }
+        })
+        .collect::<Result<_>>()?;
+    Ok((libraries, into_bytes(bytecode)?, into_bytes(deployed_bytecode)?))
+}
+
+/// Locates the code that an error refers to within the code of a snippet
+///
+/// ### Takes
+///
+/// - The code of the snippet
+/// - The REPL contract that the snippet was rendered into
+/// - The lines of the snippet within the REPL contract, see [SnippetMap]
+/// - The byte range of the error within the REPL contract
+///
+/// ### Returns
+///
+/// The byte range of the offending code within the snippet.
+///
+/// ### Notes
+///
+/// The REPL contract may be formatted, which mostly changes whitespace. The error's offsets
+/// relative to the start of the snippet are therefore mapped by the number of other characters
+/// before them. Only if the formatter changed more than whitespace, i.e. by expanding `uint`,
+/// is the offending code searched for within the snippet instead.
+fn locate_in_snippet(
+    code: &str,
+    source: &str,
+    lines: &Range<usize>,
+    range: Range<usize>,
+) -> Range<usize> {
+    let line_start =
+        |line: usize| source.split_inclusive('\n').take(line - 1).map(str::len).sum::<usize>();
+    let start = line_start(lines.start);
+    let end = line_start(lines.end).max(start);
+    let rendered = &source[start..end];
+    let relative = |offset: usize| offset.clamp(start, end) - start;
+
+    // The offsets of the characters within the snippet's code that are not whitespace
+    let significant = code.char_indices().filter(|(_, c)| !c.is_whitespace()).collect::<Vec<_>>();
+    let count = |text: &str| text.chars().filter(|c| !c.is_whitespace()).count();
+    let before_start = count(&rendered[..relative(range.start)]);
+    let before_end = count(&rendered[..relative(range.end)]);
+    let span_start = significant.get(before_start).map_or(code.trim_end().len(), |(i, _)| *i);
+    let span_end = match before_end.checked_sub(1).and_then(|n| significant.get(n)) {
+        Some((i, c)) => (i + c.len_utf8()).max(span_start),
+        None => span_start,
+    };
+
+    let strip = |text: &str| text.chars().filter(|c| !c.is_whitespace()).collect::<String>();
+    if strip(&code[span_start..span_end]) == strip(&source[range.clone()]) {
+        return span_start..span_end
+    }
+    let relative_line =
+        (source[..range.start].matches('\n').count() + 1).saturating_sub(lines.start);
+    search_in_snippet(code, &source[range], relative_line)
+}
+
+/// Searches for the code that an error refers to within the code of a snippet, see
+/// [locate_in_snippet]
+///
+/// ### Takes
+///
+/// - The code of the snippet
+/// - The code that the error refers to, as rendered within the REPL contract
+/// - The line of the error, relative to the first line of the snippet within the REPL contract
+///
+/// ### Returns
+///
+/// The byte range of the first line of the offending code within the snippet, preferring an
+/// occurrence on the same line of the snippet. Falls back to the start of the snippet if the
+/// code can not be found.
+fn search_in_snippet(code: &str, rendered: &str, relative_line: usize) -> Range<usize> {
+    let needle = rendered.lines().next().unwrap_or_default().trim_end();
+    let occurrences = if needle.is_empty() {
+        Vec::new()
+    } else {
+        code.match_indices(needle).map(|(offset, _)| offset).collect::<Vec<_>>()
+    };
+    occurrences
+        .iter()
+        .find(|offset| code[..**offset].matches('\n').count() == relative_line)
+        .or_else(|| occurrences.first())
+        .map(|offset| *offset..offset + needle.len())
+        .unwrap_or_else(|| {
+            let offset = code.len() - code.trim_start().len();
+            offset..offset
+        })
+}
+
+/// Loads the configuration of a foundry project
+///
+/// ### Takes
+///
+/// The root directory of the project
+///
+/// ### Returns
+///
+/// Optionally, the project's [Config]. Fails if the directory does not contain a
+/// `foundry.toml`, or if it could not be read.
+pub fn load_project_config(root: &Path) -> Result<Config> {
+    if !root.join(Config::FILE_NAME).is_file() {
+        eyre::bail!("No {} found in {}", Config::FILE_NAME, root.display())
+    }
+    Config::try_from(Config::figment_with_root(root)).map_err(|e| eyre::eyre!(e))
+}
+
+/// Checks whether a compiler error was caused by an import that could not be resolved
+///
+/// ### Takes
+///
+/// A reference to the compiler error
+fn is_unresolved_import(error: &ethers_solc::artifacts::Error) -> bool {
+    error.message.starts_with("Source \"") && error.message.contains("not found")
+}
+
+/// Converts a solidity version requirement into [VersionReq]s
+///
+/// ### Takes
+///
+/// The raw version requirement of a `pragma solidity` directive, i.e. `>=0.7.0 <0.9.0`
+///
+/// ### Returns
+///
+/// Optionally, one [VersionReq] per `||` separated alternative of the requirement.
+pub fn solidity_version_req(requirement: &str) -> Option<Vec<VersionReq>> {
+    requirement
+        .split("||")
+        .map(|alternative| {
+            let mut comparators = Vec::new();
+            let mut operator = String::new();
+            for token in alternative.split_whitespace() {
+                // Join operators with their versions, i.e. `>= 0.8.0` -> `>=0.8.0`
+                if token.chars().all(|c| "<>=^~".contains(c)) {
+                    operator.push_str(token);
+                    continue
+                }
+                let comparator = format!("{}{token}", std::mem::take(&mut operator));
+                // Bare versions are exact requirements in solidity
+                if comparator.starts_with(|c: char| c.is_ascii_digit()) {
+                    comparators.push(format!("={comparator}"));
+                } else {
+                    comparators.push(comparator);
+                }
+            }
+            VersionReq::parse(&comparators.join(", ")).ok()
+        })
+        .collect()
+}
+
+/// Parses an input into the snippets it is appended to the source as
+///
+/// ### Takes
+///
+/// The raw code of the input
+///
+/// ### Returns
+///
+/// Optionally, the snippets, in order. An input that does not fit within a single scope is
+/// split into parts, all but the first of which are [SolSnippet::continued]. Fails with the
+/// [ParseDiagnostics] of the input if it does not parse.
+fn parse_input(content: &str) -> Result<Vec<SolSnippet>> {
+    let mut parts = match SolSnippet::try_parse(content) {
+        Ok(snippet) => vec![snippet],
+        Err(diagnostics) => classify_parts(content).ok_or(diagnostics)?,
+    };
+    for part in parts.iter_mut().skip(1) {
+        part.continued = true;
+    }
+    Ok(parts)
+}
+
+/// Splits a snippet of code into its top-level parts and assigns each of them a scope within
+/// the source. Parts that do not parse on their own are joined with the parts following them,
+/// i.e. an `if` statement with its `else` branch.
+///
+/// ### Takes
+///
+/// The raw code of the snippet
+///
+/// ### Returns
+///
+/// Optionally, every part of the snippet as a [SolSnippet], in order. Returns `None` if any part
+/// can not be assigned a scope.
+fn classify_parts(content: &str) -> Option<Vec<SolSnippet>> {
+    let mut parts = Vec::new();
+    let mut pending = String::new();
+    for part in split_snippet(content) {
+        pending.push_str(part);
+        if let Ok(snippet) = SolSnippet::try_parse(&pending) {
+            parts.push(snippet);
+            pending.clear();
+        }
+    }
+    // Comments trailing the last part belong to it, without changing its kind
+    if let Some(SolSnippet { code, .. }) = parts.last_mut() {
+        if !pending.trim().is_empty() && is_comment_only(&pending) {
+            code.truncate(code.trim_end().len());
+            code.push_str(pending.trim_end());
+            pending.clear();
+        }
+    }
+    if pending.trim().is_empty() && parts.len() > 1 {
+        Some(parts)
+    } else {
+        None
+    }
+}
+
+/// Splits a snippet of code after every `;` and closing `}` that is not nested within
+/// brackets, string literals, or comments. Comments preceding a part stay attached to it.
+///
+/// ### Takes
+///
+/// The raw code of the snippet
+///
+/// ### Returns
+///
+/// The parts of the snippet, which concatenate back to the snippet.
+fn split_snippet(content: &str) -> Vec<&str> {
+    let bytes = content.as_bytes();
+    let mut parts = Vec::new();
+    let (mut start, mut depth, mut i) = (0, 0usize, 0);
+    while i < bytes.len() {
+        match bytes[i] {
+            // Skip string literals, respecting escaped quotes
+            quote @ (b'"' | b'\'') => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+            }
+            // Skip comments
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i += 2;
+                while i < bytes.len() && !(bytes[i] == b'*' && bytes.get(i + 1) == Some(&b'/')) {
+                    i += 1;
+                }
+                i += 1;
+            }
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' => depth = depth.saturating_sub(1),
+            b'}' => {
+                depth = depth.saturating_sub(1);
+                if depth == 0 {
+                    let end = part_end(bytes, i);
+                    parts.push(&content[start..end]);
+                    start = end;
+                    i = end - 1;
+                }
+            }
+            b';' if depth == 0 => {
+                let end = part_end(bytes, i);
+                parts.push(&content[start..end]);
+                start = end;
+                i = end - 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    if start < content.len() {
+        parts.push(&content[start..]);
+    }
+    parts
+}
+
+/// Checks whether a fragment of solidity code consists solely of comments
+fn is_comment_only(buffer: &str) -> bool {
+    matches!(solang_parser::parse(buffer, 0), Ok((pt::SourceUnit(parts), _)) if parts.is_empty())
+}
+
+/// Finds the end of a part of a snippet that ends at the given byte. A `//` comment that
+/// trails the part on the same line belongs to it, rather than to the part following it.
+fn part_end(bytes: &[u8], last: usize) -> usize {
+    let mut end = last + 1;
+    while matches!(bytes.get(end), Some(b' ' | b'\t')) {
+        end += 1;
+    }
+    if bytes.get(end) == Some(&b'/') && bytes.get(end + 1) == Some(&b'/') {
+        while end < bytes.len() && bytes[end] != b'\n' {
+            end += 1;
+        }
+        end
+    } else {
+        last + 1
+    }
+}
+
+/// Removes duplicate import directives and all `pragma solidity` directives from global code.
+/// See [SessionSource::deduplicated_global_code].
+fn deduplicate_global_code(global_code: &str) -> String {
+    let pt::SourceUnit(source_unit_parts) = match solang_parser::parse(global_code, 0) {
+        Ok((source_unit, _)) => source_unit,
+        Err(_) => return global_code.to_string(),
+    };
+
+    // Collect the spans of all imports that have been seen before and all solidity pragmas
+    let mut seen = HashSet::new();
+    let mut duplicates = Vec::new();
+    for part in source_unit_parts {
+        let loc = match part {
+            pt::SourceUnitPart::ImportDirective(import) => {
+                let (key, loc) = import_key(&import);
+                if seen.insert(key) {
+                    continue
+                }
+                loc
+            }
+            pt::SourceUnitPart::PragmaDirective(loc, name, _) if name.name == "solidity" => loc,
+            _ => continue,
+        };
+        if let pt::Loc::File(_, start, end) = loc {
+            duplicates.push((start, end));
+        }
+    }
+
+    let mut deduplicated = String::with_capacity(global_code.len());
+    let mut cursor = 0;
+    for (start, end) in duplicates {
+        deduplicated.push_str(&global_code[cursor..start]);
+
+        // Also remove the directive's line break
+        let end = directive_end(global_code, end);
+        let rest = &global_code[end..];
+        let rest = rest.trim_start_matches([' ', '\t']).strip_prefix('\n').unwrap_or(rest);
+        cursor = global_code.len() - rest.len();
+    }
+    deduplicated.push_str(&global_code[cursor..]);
+    deduplicated
+}
+
+/// Merges all constructors within top level code into the first one, concatenating their
+/// bodies in order of entry
+///
+/// ### Takes
+///
+/// The top level code, which may contain snippet markers
+///
+/// ### Returns
+///
+/// The top level code with a single constructor. The bodies of the merged constructors keep
+/// the markers of the snippets they were entered in. If the top level code can not be parsed,
+/// it is returned as-is.
+fn merge_constructors(top_level_code: &str) -> String {
+    // Top level code is only valid within a contract
+    let prefix = "contract C {\n";
+    let wrapped = format!("{prefix}{top_level_code}\n}}");
+    let source_unit_parts = match solang_parser::parse(&wrapped, 0) {
+        Ok((pt::SourceUnit(source_unit_parts), _)) => source_unit_parts,
+        Err(_) => return top_level_code.to_string(),
+    };
+
+    // Collect the spans of all constructors and their bodies
+    let constructors = source_unit_parts
+        .into_iter()
+        .filter_map(|part| match part {
+            pt::SourceUnitPart::ContractDefinition(cd) => Some(cd.parts),
+            _ => None,
+        })
+        .flatten()
+        .filter_map(|part| match part {
+            pt::ContractPart::FunctionDefinition(def)
+                if matches!(def.ty, pt::FunctionTy::Constructor) =>
+            {
+                match (def.loc, def.body) {
+                    (
+                        pt::Loc::File(_, start, _),
+                        Some(pt::Statement::Block {
+                            loc: pt::Loc::File(_, body_start, end), ..
+                        }),
+                    ) => {
+                        Some((start - prefix.len(), body_start - prefix.len(), end - prefix.len()))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if constructors.len() < 2 {
+        return top_level_code.to_string()
+    }
+
+    // Concatenate the bodies, wrapped in the markers of the snippets they were entered in
+    let mut body = String::new();
+    for &(start, body_start, end) in &constructors {
+        let marker = top_level_code[..start].rfind(SNIPPET_START_MARKER).and_then(|offset| {
+            let rest = &top_level_code[offset + SNIPPET_START_MARKER.len()..];
+            rest.split(|c: char| !c.is_ascii_digit()).next().map(str::to_string)
+        });
+        let inner = top_level_code[body_start + 1..end - 1].trim();
+        match marker {
+            Some(index) => body.push_str(&format!(
+                "{SNIPPET_START_MARKER}{index}\n{inner}\n{SNIPPET_END_MARKER}\n"
+            )),
+            None => body.push_str(&format!("{inner}\n")),
+        }
+    }
+
+    // Replace the first constructor with the merged one, and remove all others
+    let (first_start, first_body_start, _) = constructors[0];
+    let mut merged = String::from(&top_level_code[..first_start]);
+    merged.push_str(&top_level_code[first_start..first_body_start]);
+    merged.push_str(&format!("{{\n{body}}}"));
+    let mut cursor = constructors[0].2;
+    for &(start, _, end) in &constructors[1..] {
+        merged.push_str(&top_level_code[cursor..start]);
+        cursor = end;
+    }
+    merged.push_str(&top_level_code[cursor..]);
+    merged
+}
+
+/// Removes the snippet markers from a marked source, recording the lines between them
+///
+/// ### Takes
+///
+/// The marked source
+///
+/// ### Returns
+///
+/// The source without markers, and its [SnippetMap].
+fn strip_snippet_markers(marked: &str) -> (String, SnippetMap) {
+    let mut source = String::with_capacity(marked.len());
+    let mut map = SnippetMap::new();
+    let mut current = None;
+    let mut line = 1;
+    for raw_line in marked.split_inclusive('\n') {
+        let trimmed = raw_line.trim();
+        if let Some(index) = trimmed
+            .strip_prefix(SNIPPET_START_MARKER.trim_end())
+            .and_then(|index| index.trim().parse::<usize>().ok())
+        {
+            current = Some((index, line));
+        } else if trimmed == SNIPPET_END_MARKER {
+            if let Some((index, start)) = current.take() {
+                if start < line {
+                    map.push((start..line, index));
+                }
+            }
+        } else {
+            source.push_str(raw_line);
+            line += 1;
+        }
+    }
+    (source, map)
+}
+
+/// Finds the end of a directive within a source string, including its terminating semicolon
+///
+/// ### Takes
+///
+/// - The source string
+/// - The end offset of the directive, as reported by solang
+///
+/// ### Returns
+///
+/// The offset directly after the directive's terminating semicolon, if it has one.
+fn directive_end(source: &str, end: usize) -> usize {
+    let rest = &source[end..];
+    match rest.trim_start().strip_prefix(';') {
+        Some(rest) => source.len() - rest.len(),
+        None => end,
+    }
+}
+
+/// Normalizes an import directive for deduplication
+///
+/// ### Takes
+///
+/// A reference to a [pt::Import]
+///
+/// ### Returns
+///
+/// A tuple of the normalized path and sorted symbols of the import, as well as its location
+/// within the source. See [normalize_import_path].
+fn import_key(import: &pt::Import) -> ((String, Vec<String>), pt::Loc) {
+    let (path, mut symbols, loc) = match import {
+        pt::Import::Plain(path, loc) => (path, Vec::new(), loc),
+        pt::Import::GlobalSymbol(path, alias, loc) => {
+            (path, vec![format!("* as {}", alias.name)], loc)
+        }
+        pt::Import::Rename(path, renames, loc) => (
+            path,
+            renames
+                .iter()
+                .map(|(symbol, alias)| match alias {
+                    Some(alias) => format!("{} as {}", symbol.name, alias.name),
+                    None => symbol.name.clone(),
+                })
+                .collect(),
+            loc,
+        ),
+    };
+    symbols.sort();
+    ((normalize_import_path(path.string.trim()), symbols), *loc)
+}
+
+/// Normalizes the path of an import directive, the way solc resolves it to a source unit name
+///
+/// ### Returns
+///
+/// Relative paths, i.e. `./lib/../Foo.sol`, with their `.` and `..` segments resolved, i.e.
+/// `./Foo.sol`. They remain distinct from direct paths, i.e. `Foo.sol`, which are resolved
+/// through the remappings and base path rather than relative to the importing file, and which
+/// solc does not normalize either.
+fn normalize_import_path(path: &str) -> String {
+    if !path.starts_with("./") && !path.starts_with("../") {
+        return path.to_string()
+    }
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." if segments.last().map_or(false, |last| *last != "..") => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    match segments.first() {
+        Some(&"..") => segments.join("/"),
+        _ => format!("./{}", segments.join("/")),
+    }
+}
+
 /// A Parse Tree Fragment
 ///
 /// Used to determine whether an input will go to the "run()" function,
 /// the top level of the contract, or in global scope.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ParseTreeFragment {
     /// Code for the global scope
     Source,
@@ -522,24 +4518,184 @@ pub enum ParseTreeFragment {
     Function,
 }
 
-/// Parses a fragment of solidity code with solang_parser and assigns
-/// it a scope within the [SessionSource].
-pub fn parse_fragment(
-    solc: &Solc,
-    config: &SessionSourceConfig,
-    buffer: &str,
-) -> Option<ParseTreeFragment> {
-    let mut base = SessionSource::new(solc, config);
+/// Finds the statements of the "run()" function within a snippet of the "run()" function that was
+/// parsed within its wrapper, see [ParseTreeFragment::wrapper]
+fn run_statements(parts: &[pt::SourceUnitPart]) -> Option<&[pt::Statement]> {
+    parts
+        .iter()
+        .find_map(|part| match part {
+            pt::SourceUnitPart::ContractDefinition(cd) => Some(&cd.parts),
+            _ => None,
+        })
+        .into_iter()
+        .flatten()
+        .find_map(|part| match part {
+            pt::ContractPart::FunctionDefinition(def) => match &def.body {
+                Some(pt::Statement::Block { statements, .. }) => Some(statements.as_slice()),
+                _ => None,
+            },
+            _ => None,
+        })
+}
 
-    if base.clone().with_run_code(buffer).parse().is_ok() {
-        return Some(ParseTreeFragment::Function)
+impl ParseTreeFragment {
+    /// Returns the code that code of this scope is wrapped in to be parsed as a source unit
+    fn wrapper(&self) -> (&'static str, &'static str) {
+        match self {
+            ParseTreeFragment::Function => ("contract REPL {\nfunction run() public {\n", "\n}\n}"),
+            ParseTreeFragment::Contract => ("contract REPL {\n", "\n}"),
+            ParseTreeFragment::Source => ("", "\n"),
+        }
     }
-    if base.clone().with_top_level_code(buffer).parse().is_ok() {
-        return Some(ParseTreeFragment::Contract)
+}
+
+/// Collects the items defined within a contract, qualified with a prefix
+///
+/// ### Takes
+///
+/// - The source that the contract was parsed from
+/// - The parts of the contract
+/// - The prefix of the items' names, i.e. `Counter.`
+///
+/// ### Returns
+///
+/// The qualified name, kind, and key of each item. See [SolSnippet::symbols].
+fn contract_items(
+    source: &str,
+    parts: &[pt::ContractPart],
+    prefix: &str,
+) -> Vec<(String, SymbolKind, String)> {
+    parts
+        .iter()
+        .filter_map(|part| {
+            let (name, kind, key) = match part {
+                pt::ContractPart::FunctionDefinition(def) => {
+                    let name = &def.name.as_ref()?.name;
+                    (name, SymbolKind::Function, function_signature(source, def))
+                }
+                pt::ContractPart::StructDefinition(def) => {
+                    (&def.name.name, SymbolKind::Struct, def.name.name.clone())
+                }
+                pt::ContractPart::EnumDefinition(def) => {
+                    (&def.name.name, SymbolKind::Enum, def.name.name.clone())
+                }
+                pt::ContractPart::TypeDefinition(def) => {
+                    (&def.name.name, SymbolKind::TypeDefinition, def.name.name.clone())
+                }
+                pt::ContractPart::EventDefinition(def) => {
+                    (&def.name.name, SymbolKind::Event, event_signature(source, def))
+                }
+                pt::ContractPart::ErrorDefinition(def) => {
+                    (&def.name.name, SymbolKind::Error, def.name.name.clone())
+                }
+                pt::ContractPart::VariableDefinition(def) => {
+                    (&def.name.name, SymbolKind::StateVariable, def.name.name.clone())
+                }
+                _ => return None,
+            };
+            Some((format!("{prefix}{name}"), kind, key))
+        })
+        .collect()
+}
+
+/// Renders the signature of a function or event, i.e. `transfer(address,uint256)`. Only types
+/// are included, as parameter names and data locations can not overload.
+fn signature<'a>(
+    source: &str,
+    name: &str,
+    types: impl Iterator<Item = &'a pt::Expression>,
+) -> String {
+    let types = types
+        .map(|ty| source[ty.loc().start()..ty.loc().end()].split_whitespace().collect::<String>())
+        .collect::<Vec<_>>();
+    format!("{name}({})", types.join(","))
+}
+
+/// Renders the signature of a function, see [signature]
+fn function_signature(source: &str, def: &pt::FunctionDefinition) -> String {
+    let name = def.name.as_ref().map(|name| name.name.as_str()).unwrap_or_default();
+    signature(
+        source,
+        name,
+        def.params.iter().filter_map(|(_, param)| param.as_ref()).map(|p| &p.ty),
+    )
+}
+
+/// Renders the signature of an event, see [signature]
+fn event_signature(source: &str, def: &pt::EventDefinition) -> String {
+    signature(source, &def.name.name, def.fields.iter().map(|field| &field.ty))
+}
+
+/// Checks whether an expression statement modifies state, i.e. an assignment, rather than
+/// only producing a value
+fn mutates_state(expr: &pt::Expression) -> bool {
+    matches!(
+        expr,
+        pt::Expression::Assign(..) |
+            pt::Expression::AssignOr(..) |
+            pt::Expression::AssignAnd(..) |
+            pt::Expression::AssignXor(..) |
+            pt::Expression::AssignShiftLeft(..) |
+            pt::Expression::AssignShiftRight(..) |
+            pt::Expression::AssignAdd(..) |
+            pt::Expression::AssignSubtract(..) |
+            pt::Expression::AssignMultiply(..) |
+            pt::Expression::AssignDivide(..) |
+            pt::Expression::AssignModulo(..) |
+            pt::Expression::PreIncrement(..) |
+            pt::Expression::PostIncrement(..) |
+            pt::Expression::PreDecrement(..) |
+            pt::Expression::PostDecrement(..) |
+            pt::Expression::Delete(..)
+    )
+}
+
+/// Checks whether a fragment of solidity code consists solely of `using ... for ... global`
+/// directives
+fn is_global_using(buffer: &str) -> bool {
+    match solang_parser::parse(buffer, 0) {
+        Ok((pt::SourceUnit(parts), _)) => !parts.is_empty() &&
+            parts.iter().all(
+                |part| matches!(part, pt::SourceUnitPart::Using(using) if using.global.is_some()),
+            ),
+        Err(_) => false,
     }
-    if base.with_global_code(buffer).parse().is_ok() {
-        return Some(ParseTreeFragment::Source)
+}
+
+/// Parses a fragment of solidity code with solang_parser and assigns
+/// it a scope within the [SessionSource].
+///
+/// ### Takes
+///
+/// The fragment of solidity code
+///
+/// ### Returns
+///
+/// The scope of the fragment, or the [ParseDiagnostics] of the scope in which parsing
+/// progressed the furthest.
+pub fn parse_fragment(buffer: &str) -> std::result::Result<ParseTreeFragment, ParseDiagnostics> {
+    // Scopes are tried from the innermost outwards. State variables, i.e. `constant` and
+    // `immutable` ones, and function definitions do not parse as statements and fall through
+    // to the contract, where they can access the state of the REPL contract.
+    let scopes =
+        [ParseTreeFragment::Function, ParseTreeFragment::Contract, ParseTreeFragment::Source];
+
+    let mut furthest: Option<ParseDiagnostics> = None;
+    for fragment in scopes {
+        let (prefix, suffix) = fragment.wrapper();
+        match solang_parser::parse(&format!("{prefix}{buffer}{suffix}"), 0) {
+            // `using ... for ... global` directives are only valid at file scope
+            Ok(_) if fragment == ParseTreeFragment::Contract && is_global_using(buffer) => {}
+            Ok(_) => return Ok(fragment),
+            Err(diagnostics) => {
+                let diagnostics = ParseDiagnostics::new(buffer, prefix.len(), diagnostics);
+                if furthest.as_ref().map_or(true, |f| diagnostics.first_error() > f.first_error()) {
+                    furthest = Some(diagnostics);
+                }
+            }
+        }
     }
 
-    None
+    Err(furthest
+        .unwrap_or_else(|| ParseDiagnostics { code: buffer.to_string(), diagnostics: Vec::new() }))
 }
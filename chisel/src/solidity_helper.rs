@@ -3,12 +3,9 @@
 //! This module contains the `SolidityHelper`, a [rustyline::Helper] implementation for
 //! usage in Chisel. It is ported from [soli](https://github.com/jpopesculian/soli/blob/master/src/main.rs).
 
-use crate::prelude::ChiselCommand;
+use crate::prelude::{ChiselCommand, Completions};
 use rustyline::{
-    completion::Completer,
-    highlight::Highlighter,
-    hint::Hinter,
-    validate::{ValidationContext, ValidationResult, Validator},
+    completion::Completer, highlight::Highlighter, hint::Hinter, validate::Validator, Context,
     Helper,
 };
 use solang_parser::{
@@ -19,7 +16,12 @@ use std::{borrow::Cow, str::FromStr};
 use yansi::{Color, Paint, Style};
 
 /// A rustyline helper for Solidity code
-pub struct SolidityHelper;
+#[derive(Debug, Default)]
+pub struct SolidityHelper {
+    /// The candidates that are completed on tab, which are derived again as the session grows.
+    /// See [Completions::update].
+    pub completions: Completions,
+}
 
 /// Highlighter implementation for `SolHighlighter`
 impl Highlighter for SolidityHelper {
@@ -90,60 +92,76 @@ impl SolidityHelper {
         }
         out
     }
+}
 
-    /// Validate that a source snippet is closed (i.e., all braces and parenthesis are matched).
-    fn validate_closed(input: &str) -> ValidationResult {
-        let mut bracket_depth = 0usize;
-        let mut paren_depth = 0usize;
-        let mut brace_depth = 0usize;
-        let mut comments = Vec::new();
-        for res in Lexer::new(input, 0, &mut comments) {
-            match res {
-                Err(err) => match err {
-                    LexicalError::EndOfFileInComment(_) |
-                    LexicalError::EndofFileInHex(_) |
-                    LexicalError::EndOfFileInString(_) => return ValidationResult::Incomplete,
-                    _ => return ValidationResult::Valid(None),
-                },
-                Ok((_, token, _)) => match token {
-                    Token::OpenBracket => {
-                        bracket_depth = bracket_depth.saturating_add(1);
-                    }
-                    Token::OpenCurlyBrace => {
-                        brace_depth = brace_depth.saturating_add(1);
-                    }
-                    Token::OpenParenthesis => {
-                        paren_depth = paren_depth.saturating_add(1);
-                    }
-                    Token::CloseBracket => {
-                        bracket_depth = bracket_depth.saturating_sub(1);
-                    }
-                    Token::CloseCurlyBrace => {
-                        brace_depth = brace_depth.saturating_sub(1);
-                    }
-                    Token::CloseParenthesis => {
-                        paren_depth = paren_depth.saturating_sub(1);
-                    }
-                    _ => {}
-                },
-            }
-        }
-        if (bracket_depth | brace_depth | paren_depth) == 0 {
-            ValidationResult::Valid(None)
-        } else {
-            ValidationResult::Incomplete
+/// Checks whether REPL input is complete, i.e. whether all of its brackets, braces, and
+/// parentheses are matched and it does not end within a string literal or block comment.
+/// Brackets within string literals and comments are ignored.
+///
+/// ### Takes
+///
+/// The input read so far
+///
+/// ### Returns
+///
+/// `true` if the input is complete, or `false` if more lines should be read before it is
+/// dispatched. Input that can not be lexed for any other reason is considered complete, so
+/// that its error is reported right away.
+pub fn input_is_complete(src: &str) -> bool {
+    let mut bracket_depth = 0usize;
+    let mut paren_depth = 0usize;
+    let mut brace_depth = 0usize;
+    let mut comments = Vec::new();
+    for res in Lexer::new(src, 0, &mut comments) {
+        match res {
+            Err(err) => match err {
+                LexicalError::EndOfFileInComment(_) |
+                LexicalError::EndofFileInHex(_) |
+                LexicalError::EndOfFileInString(_) => return false,
+                _ => return true,
+            },
+            Ok((_, token, _)) => match token {
+                Token::OpenBracket => {
+                    bracket_depth = bracket_depth.saturating_add(1);
+                }
+                Token::OpenCurlyBrace => {
+                    brace_depth = brace_depth.saturating_add(1);
+                }
+                Token::OpenParenthesis => {
+                    paren_depth = paren_depth.saturating_add(1);
+                }
+                Token::CloseBracket => {
+                    bracket_depth = bracket_depth.saturating_sub(1);
+                }
+                Token::CloseCurlyBrace => {
+                    brace_depth = brace_depth.saturating_sub(1);
+                }
+                Token::CloseParenthesis => {
+                    paren_depth = paren_depth.saturating_sub(1);
+                }
+                _ => {}
+            },
         }
     }
+    (bracket_depth | brace_depth | paren_depth) == 0
 }
 
-impl Validator for SolidityHelper {
-    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
-        Ok(Self::validate_closed(ctx.input()))
-    }
-}
+/// Incomplete input is accumulated by the read loop across lines, see [input_is_complete]
+impl Validator for SolidityHelper {}
 
+/// Completes commands, keywords and builtins, and the items defined within the session, see
+/// [Completions::complete]
 impl Completer for SolidityHelper {
     type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(self.completions.complete(line, pos))
+    }
 }
 
 impl Hinter for SolidityHelper {
@@ -0,0 +1,377 @@
+//! State Diff
+//!
+//! This module contains the [StateDiff] of a statement, the accounts and storage slots that it
+//! touched and how they changed, along with an EIP-2930 access list for it. See `!statediff`.
+
+use crate::debugger::Step;
+use ethers::{
+    types::{
+        transaction::eip2930::{AccessList, AccessListItem},
+        Address, H256, U256,
+    },
+    utils::{hex, keccak256, to_checksum},
+};
+use eyre::Result;
+use forge::{
+    debug::Instruction,
+    executor::{Backend, CHEATCODE_ADDRESS, HARDHAT_CONSOLE_ADDRESS},
+};
+use revm::{opcode, DatabaseRef};
+use serde::Serialize;
+use std::{collections::BTreeMap, fmt::Write};
+use yansi::Paint;
+
+/// The highest address of a precompile, which are left out of access lists
+const LAST_PRECOMPILE: u64 = 9;
+
+/// The accounts and storage slots touched by the instructions of a statement, in the order they
+/// were first touched
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateAccesses {
+    /// The accounts whose code ran, that were called, or whose balance or code was read
+    pub accounts: Vec<Address>,
+    /// The storage slots read or written, by account. A slot is `true` if it was written.
+    pub slots: BTreeMap<Address, BTreeMap<U256, bool>>,
+}
+
+impl StateAccesses {
+    /// Records the accesses of a statement
+    ///
+    /// ### Takes
+    ///
+    /// The steps executed by the statement, including those of the code it called, in order
+    ///
+    /// ### Returns
+    ///
+    /// The [StateAccesses]
+    ///
+    /// ### Notes
+    ///
+    /// The code of a `DELEGATECALL` or `CALLCODE` reads and writes the storage of its caller, so
+    /// its slots are recorded for the caller.
+    pub fn new(steps: &[Step]) -> Self {
+        let mut accesses = Self::default();
+
+        // The account whose storage each call frame uses, by depth, and the opcode of the most
+        // recent call, which decides the storage of the frame that it enters
+        let mut contexts: Vec<Address> = Vec::new();
+        let mut entering = None;
+        for step in steps {
+            contexts.truncate(step.depth + 1);
+            if contexts.len() < step.depth + 1 {
+                let context = match (entering, contexts.last()) {
+                    (Some(opcode::DELEGATECALL | opcode::CALLCODE), Some(caller)) => *caller,
+                    _ => step.address,
+                };
+                contexts.push(context);
+            }
+            accesses.touch(step.address);
+            let context = *contexts.last().expect("a context was pushed");
+
+            let op = match step.step.instruction {
+                Instruction::OpCode(op) => op,
+                Instruction::Cheatcode(_) => continue,
+            };
+            let stack = &step.step.stack;
+            let operand = |n: usize| stack.len().checked_sub(n + 1).map(|index| stack[index]);
+            let account = |word: U256| Address::from_slice(&word_bytes(word)[12..]);
+            match op {
+                opcode::SLOAD | opcode::SSTORE => {
+                    if let Some(slot) = operand(0) {
+                        let written =
+                            accesses.slots.entry(context).or_default().entry(slot).or_default();
+                        *written |= op == opcode::SSTORE;
+                    }
+                }
+                opcode::CALL | opcode::CALLCODE | opcode::DELEGATECALL | opcode::STATICCALL => {
+                    entering = Some(op);
+                    if let Some(to) = operand(1) {
+                        accesses.touch(account(to));
+                    }
+                }
+                opcode::BALANCE |
+                opcode::EXTCODESIZE |
+                opcode::EXTCODECOPY |
+                opcode::EXTCODEHASH |
+                opcode::SELFDESTRUCT => {
+                    if let Some(address) = operand(0) {
+                        accesses.touch(account(address));
+                    }
+                }
+                opcode::CREATE | opcode::CREATE2 => entering = Some(op),
+                _ => {}
+            }
+        }
+        accesses
+    }
+
+    /// Records an account as touched, if it was not already
+    fn touch(&mut self, address: Address) {
+        if !self.accounts.contains(&address) {
+            self.accounts.push(address);
+        }
+    }
+}
+
+/// The value of a storage slot before and after a statement
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SlotDiff {
+    /// The slot
+    pub slot: U256,
+    /// The name of the session's state variable that the slot holds, for the REPL contract
+    pub variable: Option<String>,
+    /// The value before the statement
+    pub before: U256,
+    /// The value after the statement
+    pub after: U256,
+    /// Whether the statement wrote to the slot, even if it wrote the value it held already
+    pub written: bool,
+}
+
+impl SlotDiff {
+    /// Whether the value of the slot changed
+    pub fn is_changed(&self) -> bool {
+        self.before != self.after
+    }
+}
+
+/// The state of an account before and after a statement
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AccountDiff {
+    /// The address of the account
+    pub address: Address,
+    /// The label of the account, if any
+    pub label: Option<String>,
+    /// The balance in wei before and after the statement
+    pub balance: (U256, U256),
+    /// The nonce before and after the statement
+    pub nonce: (u64, u64),
+    /// The hash of the code before and after the statement
+    pub code_hash: (H256, H256),
+    /// The storage slots that the statement read or wrote, in order of slot
+    pub storage: Vec<SlotDiff>,
+}
+
+impl AccountDiff {
+    /// Whether the balance, nonce, code, or any storage slot of the account changed
+    pub fn is_changed(&self) -> bool {
+        self.balance.0 != self.balance.1 ||
+            self.nonce.0 != self.nonce.1 ||
+            self.code_hash.0 != self.code_hash.1 ||
+            self.storage.iter().any(SlotDiff::is_changed)
+    }
+}
+
+/// The accounts and storage slots that a statement touched, and how they changed
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct StateDiff {
+    /// The address of the REPL contract
+    pub repl: Address,
+    /// The touched accounts, in the order they were first touched
+    pub accounts: Vec<AccountDiff>,
+    /// An EIP-2930 access list of the touched accounts and slots, leaving out the REPL contract,
+    /// the cheatcode and console addresses, and precompiles
+    pub access_list: AccessList,
+}
+
+impl StateDiff {
+    /// Compares the touched accounts and slots of a statement between the state before and after
+    /// it
+    ///
+    /// ### Takes
+    ///
+    /// - The [StateAccesses] of the statement
+    /// - The backend after the statements before it
+    /// - The backend after the statement
+    /// - The address of the REPL contract
+    /// - The labels of accounts
+    /// - The names of the session's state variables, by the slot of the REPL contract that they
+    ///   start at
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the [StateDiff]. Fails if the state of an account can not be read, i.e. from
+    /// a fork.
+    pub fn new(
+        accesses: &StateAccesses,
+        before: &Backend,
+        after: &Backend,
+        repl: Address,
+        labels: &BTreeMap<Address, String>,
+        variables: &BTreeMap<U256, String>,
+    ) -> Result<Self> {
+        let empty_code = H256::from(keccak256([]));
+        let info = |backend: &Backend, address: Address| {
+            DatabaseRef::basic(backend, address)
+                .map(|info| info.unwrap_or_default())
+                .map_err(|err| eyre::eyre!("Failed to read the account {address:?}: {err}"))
+        };
+        let slot = |backend: &Backend, address: Address, slot: U256| {
+            DatabaseRef::storage(backend, address, slot).map_err(|err| {
+                eyre::eyre!("Failed to read storage slot {slot} of {address:?}: {err}")
+            })
+        };
+        let code_hash = |hash: H256| if hash.is_zero() { empty_code } else { hash };
+
+        let mut diff = Self { repl, ..Default::default() };
+        for &address in &accesses.accounts {
+            let (old, new) = (info(before, address)?, info(after, address)?);
+            let mut storage = Vec::new();
+            for (&index, &written) in accesses.slots.get(&address).into_iter().flatten() {
+                storage.push(SlotDiff {
+                    slot: index,
+                    variable: variables.get(&index).filter(|_| address == repl).cloned(),
+                    before: slot(before, address, index)?,
+                    after: slot(after, address, index)?,
+                    written,
+                });
+            }
+            diff.accounts.push(AccountDiff {
+                address,
+                label: labels.get(&address).cloned(),
+                balance: (old.balance, new.balance),
+                nonce: (old.nonce, new.nonce),
+                code_hash: (code_hash(old.code_hash), code_hash(new.code_hash)),
+                storage,
+            });
+        }
+
+        diff.access_list = AccessList(
+            diff.accounts
+                .iter()
+                .filter(|account| {
+                    ![repl, CHEATCODE_ADDRESS, HARDHAT_CONSOLE_ADDRESS].contains(&account.address)
+                })
+                .filter(|account| !is_precompile(account.address))
+                .map(|account| AccessListItem {
+                    address: account.address,
+                    storage_keys: account
+                        .storage
+                        .iter()
+                        .map(|slot| H256::from(word_bytes(slot.slot)))
+                        .collect(),
+                })
+                .collect(),
+        );
+        Ok(diff)
+    }
+
+    /// Whether an account or slot is bookkeeping of the REPL rather than state of the session:
+    /// the cheatcode and console addresses, the nonce of the REPL contract, and the slots of the
+    /// REPL contract that the statement only read
+    fn is_bookkeeping(&self, account: &AccountDiff, slot: Option<&SlotDiff>) -> bool {
+        match slot {
+            _ if [CHEATCODE_ADDRESS, HARDHAT_CONSOLE_ADDRESS].contains(&account.address) => true,
+            Some(slot) => account.address == self.repl && !slot.is_changed() && !slot.written,
+            None => false,
+        }
+    }
+
+    /// Formats the diff, listing the changes of each account, then the access list
+    ///
+    /// ### Takes
+    ///
+    /// Whether to include the bookkeeping of the REPL, see `!statediff --all`. Otherwise, the
+    /// number of hidden entries is printed instead.
+    pub fn table(&self, all: bool) -> String {
+        let mut out = String::new();
+        let mut hidden = 0;
+        for account in &self.accounts {
+            if !all && self.is_bookkeeping(account, None) {
+                hidden += 1;
+                continue
+            }
+            let mut lines = Vec::new();
+            if account.balance.0 != account.balance.1 {
+                lines.push(format!("balance: {} -> {}", account.balance.0, account.balance.1));
+            }
+            if account.nonce.0 != account.nonce.1 {
+                if all || account.address != self.repl {
+                    lines.push(format!("nonce: {} -> {}", account.nonce.0, account.nonce.1));
+                } else {
+                    hidden += 1;
+                }
+            }
+            if account.code_hash.0 != account.code_hash.1 {
+                let empty_code = H256::from(keccak256([]));
+                lines.push(String::from(match (account.code_hash.0, account.code_hash.1) {
+                    (before, _) if before == empty_code => "code: deployed",
+                    (_, after) if after == empty_code => "code: removed",
+                    _ => "code: changed",
+                }));
+            }
+            for slot in &account.storage {
+                if !all && self.is_bookkeeping(account, Some(slot)) {
+                    hidden += 1;
+                    continue
+                }
+                let name = match &slot.variable {
+                    Some(variable) => format!("slot {} ({variable})", hex_word(slot.slot)),
+                    None => format!("slot {}", hex_word(slot.slot)),
+                };
+                lines.push(match (slot.is_changed(), slot.written) {
+                    (true, _) => {
+                        format!("{name}: {} -> {}", hex_word(slot.before), hex_word(slot.after))
+                    }
+                    (false, true) => format!("{name}: {} (rewritten)", hex_word(slot.after)),
+                    (false, false) => format!("{name}: {} (read)", hex_word(slot.after)),
+                });
+            }
+
+            let address = to_checksum(&account.address, None);
+            let name = match &account.label {
+                Some(label) => format!("{label} ({address})"),
+                None if account.address == self.repl => format!("REPL contract ({address})"),
+                None => address,
+            };
+            let _ = writeln!(out, "{}", Paint::cyan(name));
+            if lines.is_empty() {
+                let _ = writeln!(out, "  touched, unchanged");
+            }
+            for line in lines {
+                let _ = writeln!(out, "  {line}");
+            }
+        }
+        if self.accounts.is_empty() {
+            let _ = writeln!(out, "The statement touched no accounts");
+        }
+        if hidden > 0 {
+            let _ = writeln!(
+                out,
+                "{}",
+                Paint::yellow(format!(
+                    "Hid {hidden} bookkeeping entries of the REPL, pass `--all` to show them"
+                ))
+            );
+        }
+
+        let _ = write!(out, "\n{}", Paint::yellow("Access list:"));
+        if self.access_list.0.is_empty() {
+            let _ = write!(out, "\n  empty");
+        }
+        for item in &self.access_list.0 {
+            let _ = write!(out, "\n  {}", to_checksum(&item.address, None));
+            for key in &item.storage_keys {
+                let _ = write!(out, "\n    {key:?}");
+            }
+        }
+        out
+    }
+}
+
+/// Whether an address is one of the precompiles
+fn is_precompile(address: Address) -> bool {
+    address[..12] == [0u8; 12] && (1..=LAST_PRECOMPILE).contains(&address.to_low_u64_be())
+}
+
+/// The big-endian bytes of a word
+fn word_bytes(word: U256) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    word.to_big_endian(&mut bytes);
+    bytes
+}
+
+/// Formats a word as 32 bytes of hex
+fn hex_word(word: U256) -> String {
+    format!("0x{}", hex::encode(word_bytes(word)))
+}
@@ -0,0 +1,570 @@
+//! Storage
+//!
+//! This module contains the [StorageDecoder], which reads the current value of the session's
+//! state variables from the storage of the REPL contract by the storage layout that solc
+//! outputs for it.
+
+use crate::format::{ValueFormatter, ValueType};
+use ethers::{
+    abi::{
+        ethabi,
+        param_type::Reader,
+        token::{LenientTokenizer, Tokenizer},
+        ParamType, Token,
+    },
+    types::{Address, U256},
+    utils::{hex, keccak256},
+};
+use ethers_solc::artifacts::{Storage, StorageLayout, StorageType};
+use eyre::Result;
+use forge::executor::Backend;
+use revm::DatabaseRef;
+use std::fmt;
+use yansi::Paint;
+
+/// The number of elements of an array that are decoded when it is inspected without an index
+pub static ARRAY_LIMIT: usize = 32;
+/// The maximum length of a `string` or `bytes` that is decoded, in bytes
+pub static BYTES_LIMIT: usize = 1 << 16;
+
+/// The value of a state variable, decoded from the storage of the REPL contract
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedValue {
+    /// The solidity type of the value, as labeled by solc, i.e. `mapping(address => uint256)`
+    pub ty: String,
+    /// The decoded value
+    pub value: StorageValue,
+}
+
+/// The contents of a [DecodedValue]
+#[derive(Debug, Clone, PartialEq)]
+pub enum StorageValue {
+    /// A value type, or the contents of a `string` or `bytes`
+    Token(Token),
+    /// The first [ARRAY_LIMIT] elements of a fixed size or dynamic array, and the number of
+    /// elements after them that were left out
+    Array(Vec<DecodedValue>, U256),
+    /// The members of a struct, by name
+    Struct(Vec<(String, DecodedValue)>),
+    /// A mapping. Its values can only be read by key.
+    Mapping,
+}
+
+impl StorageValue {
+    /// Formats the value
+    ///
+    /// ### Takes
+    ///
+    /// - The solidity type of the value, as labeled by solc
+    /// - The [ValueFormatter] to format the value with
+    ///
+    /// ### Returns
+    ///
+    /// The formatted value. Enums are displayed by the name of their variant if the formatter
+    /// knows them.
+    pub fn format(&self, ty: &str, formatter: &ValueFormatter) -> String {
+        match self {
+            StorageValue::Token(token) => formatter
+                .format(&formatter.resolve(&ValueType::of(token).param_type(), Some(ty)), token),
+            StorageValue::Array(elements, omitted) => {
+                let mut elements = elements
+                    .iter()
+                    .map(|element| element.value.format(&element.ty, formatter))
+                    .collect::<Vec<_>>();
+                if !omitted.is_zero() {
+                    elements.push(format!("… {omitted} more"));
+                }
+                format!("[{}]", elements.join(", "))
+            }
+            StorageValue::Struct(members) => {
+                let members = members
+                    .iter()
+                    .map(|(name, member)| {
+                        format!("{name}: {}", member.value.format(&member.ty, formatter))
+                    })
+                    .collect::<Vec<_>>();
+                format!("{{ {} }}", members.join(", "))
+            }
+            StorageValue::Mapping => {
+                String::from("(supply a key to read a value, i.e. `name[key]`)")
+            }
+        }
+    }
+}
+
+impl fmt::Display for StorageValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.format("", &ValueFormatter::default()))
+    }
+}
+
+impl DecodedValue {
+    /// Formats the value along with its type
+    ///
+    /// ### Takes
+    ///
+    /// The [ValueFormatter] to format the value with
+    ///
+    /// ### Returns
+    ///
+    /// The type of the value, followed by its formatted value
+    pub fn format(&self, formatter: &ValueFormatter) -> String {
+        format!(
+            "Type: {}\n└ Value: {}",
+            Paint::red(&self.ty),
+            Paint::cyan(self.value.format(&self.ty, formatter))
+        )
+    }
+}
+
+impl fmt::Display for DecodedValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.format(&ValueFormatter::default()))
+    }
+}
+
+/// Decodes the state variables of a deployed REPL contract from its storage
+#[derive(Debug)]
+pub struct StorageDecoder<'a> {
+    /// The storage layout of the REPL contract
+    layout: &'a StorageLayout,
+    /// The backend holding the state after the execution of the REPL contract
+    backend: &'a Backend,
+    /// The address of the deployed REPL contract
+    address: Address,
+}
+
+impl<'a> StorageDecoder<'a> {
+    /// Create a new [StorageDecoder]
+    ///
+    /// ### Takes
+    ///
+    /// - The storage layout of the REPL contract
+    /// - The backend holding the state after the execution of the REPL contract
+    /// - The address of the deployed REPL contract
+    ///
+    /// ### Returns
+    ///
+    /// A new [StorageDecoder]
+    pub fn new(layout: &'a StorageLayout, backend: &'a Backend, address: Address) -> Self {
+        Self { layout, backend, address }
+    }
+
+    /// Decodes the current value of a state variable
+    ///
+    /// ### Takes
+    ///
+    /// - The name of the state variable
+    /// - The keys to index the variable by, outermost first. Mappings are indexed by a key of their
+    ///   key type, and arrays by the index of an element.
+    ///
+    /// ### Returns
+    ///
+    /// Optionally, the [DecodedValue] of the variable. Fails if the layout contains no variable
+    /// of the name, or if a key does not apply to the type it indexes.
+    pub fn decode(&self, name: &str, keys: &[String]) -> Result<DecodedValue> {
+        let variable =
+            self.layout.storage.iter().find(|variable| variable.label == name).ok_or_else(
+                || eyre::eyre!("`{name}` is not a state variable of the REPL contract"),
+            )?;
+        self.decode_type(
+            &variable.storage_type,
+            parse_slot(&variable.slot)?,
+            variable.offset as usize,
+            keys,
+        )
+    }
+
+    /// Decodes a value of a type of the storage layout at a storage location
+    fn decode_type(
+        &self,
+        type_id: &str,
+        slot: U256,
+        offset: usize,
+        keys: &[String],
+    ) -> Result<DecodedValue> {
+        let ty = self.storage_type(type_id)?;
+        let size = ty.number_of_bytes.parse::<usize>()?;
+        let value = match ty.encoding.as_str() {
+            "mapping" => match keys.split_first() {
+                Some((key, keys)) => {
+                    let key_type = self.storage_type(mapping_field(&ty.key, &ty.label, "key")?)?;
+                    let value_type = mapping_field(&ty.value, &ty.label, "value")?;
+                    return self.decode_type(
+                        value_type,
+                        mapping_slot(&key_type.label, key, slot)?,
+                        0,
+                        keys,
+                    )
+                }
+                None => StorageValue::Mapping,
+            },
+            "dynamic_array" => {
+                let length = self.read(slot)?;
+                let base = base_type(ty)?;
+                let start = U256::from(keccak256(slot_bytes(slot)));
+                return self.decode_array(ty, &base, start, length, keys)
+            }
+            "bytes" => {
+                no_keys(ty, keys)?;
+                let data = self.read_bytes(slot)?;
+                StorageValue::Token(if ty.label == "string" {
+                    Token::String(String::from_utf8_lossy(&data).into_owned())
+                } else {
+                    Token::Bytes(data)
+                })
+            }
+            "inplace" => {
+                if let Some(members) = ty.other.get("members") {
+                    no_keys(ty, keys)?;
+                    let members = serde_json::from_value::<Vec<Storage>>(members.clone())?;
+                    StorageValue::Struct(
+                        members
+                            .iter()
+                            .map(|member| {
+                                let value = self.decode_type(
+                                    &member.storage_type,
+                                    wrapping_add(slot, parse_slot(&member.slot)?),
+                                    member.offset as usize,
+                                    &[],
+                                )?;
+                                Ok((member.label.clone(), value))
+                            })
+                            .collect::<Result<_>>()?,
+                    )
+                } else if ty.other.contains_key("base") {
+                    // The length of a fixed size array is only part of its label, i.e. `uint8[3]`
+                    let length = ty
+                        .label
+                        .rsplit_once('[')
+                        .and_then(|(_, length)| length.strip_suffix(']'))
+                        .and_then(|length| U256::from_dec_str(length).ok())
+                        .ok_or_else(|| eyre::eyre!("Invalid array type `{}`", ty.label))?;
+                    return self.decode_array(ty, &base_type(ty)?, slot, length, keys)
+                } else {
+                    no_keys(ty, keys)?;
+                    let word = slot_bytes(self.read(slot)?);
+                    let end = 32usize.saturating_sub(offset);
+                    let data = &word[end.saturating_sub(size)..end];
+                    StorageValue::Token(decode_word(&value_type(&ty.label, size), data))
+                }
+            }
+            encoding => eyre::bail!("Unsupported storage encoding `{encoding}` of `{}`", ty.label),
+        };
+        Ok(DecodedValue { ty: ty.label.clone(), value })
+    }
+
+    /// Decodes the first [ARRAY_LIMIT] elements of a fixed size or dynamic array, or one of them
+    /// if indexed
+    fn decode_array(
+        &self,
+        ty: &StorageType,
+        base: &str,
+        start: U256,
+        length: U256,
+        keys: &[String],
+    ) -> Result<DecodedValue> {
+        let element_size = self.storage_type(base)?.number_of_bytes.parse::<usize>()?;
+
+        // Elements smaller than a slot are packed, larger elements start a new slot each
+        // Storage slots wrap around, like they do within the EVM
+        let location = |index: U256| {
+            if element_size < 32 {
+                let per_slot = U256::from(32 / element_size.max(1));
+                let offset = (index % per_slot).as_usize() * element_size;
+                (wrapping_add(start, index / per_slot), offset)
+            } else {
+                let slots = U256::from((element_size + 31) / 32);
+                (wrapping_add(start, index.overflowing_mul(slots).0), 0)
+            }
+        };
+
+        if let Some((index, keys)) = keys.split_first() {
+            let index = LenientTokenizer::tokenize_uint(index)
+                .map(U256::from)
+                .map_err(|_| eyre::eyre!("Invalid index `{index}` of `{}`", ty.label))?;
+            if index >= length {
+                eyre::bail!("Index {index} is out of bounds of `{}` of length {length}", ty.label)
+            }
+            let (slot, offset) = location(index);
+            return self.decode_type(base, slot, offset, keys)
+        }
+
+        let shown = length.min(U256::from(ARRAY_LIMIT));
+        let elements = (0..shown.as_usize())
+            .map(|index| {
+                let (slot, offset) = location(U256::from(index));
+                self.decode_type(base, slot, offset, &[])
+            })
+            .collect::<Result<_>>()?;
+        Ok(DecodedValue {
+            ty: ty.label.clone(),
+            value: StorageValue::Array(elements, length - shown),
+        })
+    }
+
+    /// Reads the contents of a `string` or `bytes` stored at a slot
+    ///
+    /// Contents shorter than 32 bytes are stored in the slot along with their length. Longer
+    /// contents are stored from the keccak256 hash of the slot, which only holds their length.
+    /// Contents longer than [BYTES_LIMIT] are not decoded.
+    fn read_bytes(&self, slot: U256) -> Result<Vec<u8>> {
+        let word = self.read(slot)?;
+        if word.low_u64() & 1 == 0 {
+            let length = ((word.byte(0) / 2) as usize).min(32);
+            return Ok(slot_bytes(word)[..length].to_vec())
+        }
+
+        let length = (word - 1) / 2;
+        let length = match usize::try_from(length) {
+            Ok(length) if length <= BYTES_LIMIT => length,
+            _ => eyre::bail!(
+                "The length {length} stored at slot {slot} exceeds the limit of {BYTES_LIMIT} bytes"
+            ),
+        };
+        let start = U256::from(keccak256(slot_bytes(slot)));
+        let mut data = Vec::with_capacity(length);
+        for i in 0..(length + 31) / 32 {
+            data.extend(slot_bytes(self.read(wrapping_add(start, U256::from(i)))?));
+        }
+        data.truncate(length);
+        Ok(data)
+    }
+
+    /// Reads a slot of the REPL contract's storage
+    fn read(&self, slot: U256) -> Result<U256> {
+        DatabaseRef::storage(self.backend, self.address, slot)
+            .map_err(|err| eyre::eyre!("Failed to read storage slot {slot}: {err}"))
+    }
+
+    /// Looks up a type of the storage layout by its identifier, i.e. `t_uint256`
+    fn storage_type(&self, type_id: &str) -> Result<&StorageType> {
+        self.layout
+            .types
+            .get(type_id)
+            .ok_or_else(|| eyre::eyre!("Unknown type `{type_id}` in storage layout"))
+    }
+}
+
+/// A state variable that was assigned another storage location than by a previous compile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MovedVariable {
+    /// The name of the variable
+    pub label: String,
+    /// The slot and offset of the variable as of the previous compile
+    pub previous: (String, i64),
+    /// The slot and offset of the variable as of the current compile
+    pub current: (String, i64),
+}
+
+impl fmt::Display for MovedVariable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}` moved from slot {} (offset {}) to slot {} (offset {})",
+            self.label, self.previous.0, self.previous.1, self.current.0, self.current.1
+        )
+    }
+}
+
+/// Finds the state variables that are stored at another location by a storage layout than by a
+/// previous one
+///
+/// ### Takes
+///
+/// - The storage layout of the previous compile
+/// - The storage layout of the current compile
+///
+/// ### Returns
+///
+/// The variables of both layouts whose slot or offset differs, in order of the current layout.
+/// Variables that were added or removed did not move.
+pub fn moved_variables(previous: &StorageLayout, current: &StorageLayout) -> Vec<MovedVariable> {
+    current
+        .storage
+        .iter()
+        .filter_map(|variable| {
+            let before = previous.storage.iter().find(|before| before.label == variable.label)?;
+            (before.slot != variable.slot || before.offset != variable.offset).then(|| {
+                MovedVariable {
+                    label: variable.label.clone(),
+                    previous: (before.slot.clone(), before.offset),
+                    current: (variable.slot.clone(), variable.offset),
+                }
+            })
+        })
+        .collect()
+}
+
+/// Renders a storage layout as a table
+///
+/// ### Takes
+///
+/// The storage layout of the REPL contract
+///
+/// ### Returns
+///
+/// A row per state variable with its slot, offset, type, and name, in order of declaration.
+/// Variables that share a slot are packed at different offsets, and the slots of mappings and
+/// dynamic arrays are the base slots that their elements are stored relative to.
+pub fn layout_table(layout: &StorageLayout) -> String {
+    let rows = layout
+        .storage
+        .iter()
+        .map(|variable| {
+            let ty = layout.types.get(&variable.storage_type);
+            let slot = match ty.map(|ty| ty.encoding.as_str()) {
+                Some("mapping" | "dynamic_array") => format!("{} (base)", variable.slot),
+                _ => variable.slot.clone(),
+            };
+            let label = ty.map_or(variable.storage_type.as_str(), |ty| ty.label.as_str());
+            [slot, variable.offset.to_string(), label.to_string(), variable.label.clone()]
+        })
+        .collect::<Vec<_>>();
+    let header = ["Slot", "Offset", "Type", "Name"].map(String::from);
+    let widths = (0..4)
+        .map(|column| {
+            rows.iter().chain([&header]).map(|row| row[column].len()).max().unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+    let render = |row: &[String; 4]| {
+        row.iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+    std::iter::once(Paint::yellow(render(&header)).to_string())
+        .chain(rows.iter().map(render))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Splits the argument of an inspection, i.e. `balances[0xabc]`, into the name of a state
+/// variable and the keys that it is indexed by
+///
+/// ### Takes
+///
+/// The name of a state variable, optionally followed by keys in square brackets
+///
+/// ### Returns
+///
+/// Optionally, the name and the keys, outermost first. Fails if a bracket is not closed or a key
+/// is empty.
+pub fn parse_storage_path(path: &str) -> Result<(&str, Vec<String>)> {
+    let path = path.trim();
+    let (name, mut rest) = path.split_at(path.find('[').unwrap_or(path.len()));
+    let name = name.trim();
+    if name.is_empty() {
+        eyre::bail!("Expected the name of a state variable, i.e. `balances[0xabc]`")
+    }
+
+    let mut keys = Vec::new();
+    while let Some(inner) = rest.trim_start().strip_prefix('[') {
+        let end = inner.find(']').ok_or_else(|| eyre::eyre!("Unclosed `[` in `{path}`"))?;
+        let key = inner[..end].trim();
+        if key.is_empty() {
+            eyre::bail!("Empty key in `{path}`")
+        }
+        keys.push(key.to_string());
+        rest = &inner[end + 1..];
+    }
+    if !rest.trim().is_empty() {
+        eyre::bail!("Unexpected `{}` in `{path}`", rest.trim())
+    }
+    Ok((name, keys))
+}
+
+/// Computes the slot of the value of a mapping key
+///
+/// The slot is the keccak256 hash of the key followed by the slot of the mapping. Value types
+/// are padded to 32 bytes, while the contents of `string` and `bytes` keys are hashed as-is.
+fn mapping_slot(key_type: &str, key: &str, slot: U256) -> Result<U256> {
+    let mut preimage = match key_type {
+        "string" => {
+            key.strip_prefix('"').and_then(|key| key.strip_suffix('"')).unwrap_or(key).into()
+        }
+        "bytes" => hex::decode(key.trim_start_matches("0x"))
+            .map_err(|_| eyre::eyre!("Invalid key `{key}` of type `bytes`"))?,
+        label => {
+            let token = LenientTokenizer::tokenize(&value_type(label, 32), key)
+                .map_err(|err| eyre::eyre!("Invalid key `{key}` of type `{label}`: {err}"))?;
+            ethabi::encode(&[token])
+        }
+    };
+    preimage.extend(slot_bytes(slot));
+    Ok(U256::from(keccak256(preimage)))
+}
+
+/// Maps the label of a value type of the storage layout to the [ParamType] it is decoded as.
+/// Contracts are decoded as addresses and enums as `uint8`, while user defined value types are
+/// shown as raw bytes.
+fn value_type(label: &str, size: usize) -> ParamType {
+    if label.starts_with("contract ") || label.starts_with("address") {
+        ParamType::Address
+    } else if label.starts_with("enum ") {
+        ParamType::Uint(8)
+    } else {
+        Reader::read(label).unwrap_or(ParamType::FixedBytes(size))
+    }
+}
+
+/// Decodes the bytes of a value type, as they are packed within a storage slot
+fn decode_word(kind: &ParamType, data: &[u8]) -> Token {
+    match kind {
+        ParamType::Address if data.len() == 20 => Token::Address(Address::from_slice(data)),
+        ParamType::Bool => Token::Bool(data.iter().any(|byte| *byte != 0)),
+        ParamType::Uint(_) => Token::Uint(U256::from_big_endian(data)),
+        ParamType::Int(_) => {
+            // Sign extend the value to a full word
+            let fill = if data.first().map_or(false, |byte| byte & 0x80 != 0) { 0xff } else { 0 };
+            let mut word = [fill; 32];
+            word[32 - data.len()..].copy_from_slice(data);
+            Token::Int(U256::from_big_endian(&word))
+        }
+        _ => Token::FixedBytes(data.to_vec()),
+    }
+}
+
+/// Parses a slot of the storage layout, which solc outputs as a decimal string
+fn parse_slot(slot: &str) -> Result<U256> {
+    U256::from_dec_str(slot).map_err(|_| eyre::eyre!("Invalid storage slot `{slot}`"))
+}
+
+/// Encodes a storage slot or word as big endian bytes
+fn slot_bytes(slot: U256) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    slot.to_big_endian(&mut bytes);
+    bytes
+}
+
+/// Adds an offset to a storage slot, wrapping around like the EVM does
+fn wrapping_add(slot: U256, offset: U256) -> U256 {
+    slot.overflowing_add(offset).0
+}
+
+/// The base type of an array type of the storage layout
+fn base_type(ty: &StorageType) -> Result<String> {
+    ty.other
+        .get("base")
+        .and_then(|base| base.as_str())
+        .map(String::from)
+        .ok_or_else(|| eyre::eyre!("Missing base type of `{}` in storage layout", ty.label))
+}
+
+/// A field of a mapping type of the storage layout
+fn mapping_field<'a>(field: &'a Option<String>, label: &str, name: &str) -> Result<&'a str> {
+    field
+        .as_deref()
+        .ok_or_else(|| eyre::eyre!("Missing {name} type of `{label}` in storage layout"))
+}
+
+/// Ensures that a type that can not be indexed is not given any keys
+fn no_keys(ty: &StorageType, keys: &[String]) -> Result<()> {
+    match keys.first() {
+        Some(key) => eyre::bail!("`{}` can not be indexed by `[{key}]`", ty.label),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use chisel::{
+    broadcast::{BroadcastArgs, BroadcastSigner, TransactionKind},
+    runner::RUN_SELECTOR,
+};
+use common::new_session;
+use serial_test::serial;
+use tempfile::TempDir;
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+async fn test_plan_broadcast() {
+    // Nothing is broadcast without an explicit signer
+    let err = BroadcastArgs::parse(&["mainnet", "1"]).unwrap_err();
+    assert!(err.to_string().starts_with("Refusing to broadcast without a signer"), "{err}");
+    assert!(BroadcastArgs::parse(&["mainnet", "--ledger", "--interactive"]).is_err());
+    assert!(BroadcastArgs::parse(&["--ledger"]).is_err());
+    assert_eq!(
+        BroadcastArgs::parse(&["mainnet", "--private-key-env", "KEY", "--chain-id", "1", "3", "4"])
+            .unwrap(),
+        BroadcastArgs {
+            endpoint: String::from("mainnet"),
+            signer: BroadcastSigner::PrivateKeyEnv(String::from("KEY")),
+            chain_id: Some(1),
+            snippets: vec![3, 4],
+        }
+    );
+
+    // Private keys are never accepted as arguments
+    let err = BroadcastArgs::parse(&["mainnet", "--private-key", "0x01"]).unwrap_err();
+    assert!(err.to_string().starts_with("Private keys are not accepted"), "{err}");
+
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let env = new_session(tmp.path());
+    let mut source = env.session_source.unwrap();
+    for line in [
+        "contract Counter { uint256 public count; function increment() external payable { count++; } }",
+        "Counter c = new Counter();",
+        "c.increment{value: 1}();",
+        "uint256 next = c.count() + 1;",
+        "vm.roll(1);",
+    ] {
+        source = source.clone_with_new_line(line.to_string()).unwrap().0;
+    }
+    assert!(source.snippets[2].is_external_call());
+    assert!(!source.snippets[1].is_external_call() && !source.snippets[3].is_external_call());
+
+    // A single external call is broadcast as the call itself
+    let planned = source.plan_broadcast(&[2]).await.unwrap();
+    assert_eq!(planned.len(), 1);
+    assert_eq!(planned[0].kind, TransactionKind::Call);
+    assert!(planned[0].to.is_some());
+    assert_eq!(planned[0].data.to_vec(), ethers::utils::id("increment()").to_vec());
+    assert_eq!(planned[0].value, 1.into());
+
+    // Any other statement is broadcast as a deployment of a REPL contract holding only that
+    // statement, and a call to it
+    let planned = source.plan_broadcast(&[1]).await.unwrap();
+    let kinds = planned.iter().map(|planned| planned.kind).collect::<Vec<_>>();
+    assert_eq!(kinds, vec![TransactionKind::Deployment, TransactionKind::Run]);
+    assert!(planned[0].to.is_none() && !planned[0].data.is_empty());
+    assert_eq!(planned[1].data.to_vec(), RUN_SELECTOR.to_vec());
+    assert_eq!(planned[1].snippet, 1);
+
+    // The deployment does not send the earlier statements again, so a statement that uses their
+    // variables is refused
+    let err = source.plan_broadcast(&[3]).await.unwrap_err();
+    assert!(err.to_string().contains("depends on the statements before it"), "{err}");
+
+    // Definitions, cheatcodes, and unknown snippets are refused
+    assert!(source.plan_broadcast(&[0]).await.is_err());
+    let err = source.plan_broadcast(&[4]).await.unwrap_err();
+    assert!(err.to_string().contains("cheatcode"), "{err}");
+    assert!(source.plan_broadcast(&[5]).await.is_err());
+}
@@ -1,139 +1,246 @@
-use std::path::Path;
+mod common;
 
-use chisel::{session::ChiselSession, session_source::SessionSourceConfig};
+use chisel::{
+    session::{ChiselSession, SessionError, CACHE_DIR_ENV, CACHE_FORMAT_VERSION},
+    session_source::SnippetKind,
+};
+use common::{enter_inputs, new_session};
 use forge::executor::opts::EvmOpts;
 use foundry_config::Config;
 use serial_test::serial;
+use std::path::Path;
+use tempfile::TempDir;
+
+/// Helper function for ordering cache files without sleeping, by setting their modification time
+/// to a number of seconds in the past
+fn set_modified(path: &Path, secs_ago: u64) {
+    let modified = std::time::SystemTime::now() - std::time::Duration::from_secs(secs_ago);
+    std::fs::File::options().write(true).open(path).unwrap().set_modified(modified).unwrap();
+}
 
 #[test]
 #[serial]
 fn test_cache_directory() {
-    // Get the cache dir
+    // Get the default cache dir
     // Should be ~/.foundry/cache/chisel
-    let cache_dir = ChiselSession::cache_dir().unwrap();
+    std::env::remove_var(CACHE_DIR_ENV);
+    let cache_dir = ChiselSession::default_cache_dir().unwrap();
 
     // Validate the cache directory
     let home_dir = dirs::home_dir().unwrap();
-    assert_eq!(cache_dir, format!("{}/.foundry/cache/chisel/", home_dir.to_str().unwrap()));
+    assert_eq!(cache_dir, home_dir.join(".foundry").join("cache").join("chisel"));
+}
+
+#[test]
+#[serial]
+fn test_cache_directory_env() {
+    // The cache directory should be overridable via the environment
+    let tmp = TempDir::new().unwrap();
+    std::env::set_var(CACHE_DIR_ENV, tmp.path());
+    let cache_dir = ChiselSession::default_cache_dir();
+    std::env::remove_var(CACHE_DIR_ENV);
+
+    assert_eq!(cache_dir.unwrap(), tmp.path());
 }
 
 #[test]
 #[serial]
 fn test_create_cache_directory() {
     // Get the cache dir
-    let cache_dir = ChiselSession::cache_dir().unwrap();
+    let tmp = TempDir::new().unwrap();
+    let env = new_session(&tmp.path().join("chisel"));
+    let cache_dir = env.cache_dir();
 
     // Create the cache directory
-    ChiselSession::create_cache_dir().unwrap();
+    env.create_cache_dir().unwrap();
 
     // Validate the cache directory
-    assert!(Path::new(&cache_dir).exists());
+    assert!(cache_dir.exists());
 }
 
 #[test]
 #[serial]
 fn test_write_session() {
-    // Create the cache directory if it doesn't exist
-    let cache_dir = ChiselSession::cache_dir().unwrap();
-    ChiselSession::create_cache_dir().unwrap();
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
 
     // Create a new session
-    let mut env = ChiselSession::new(&chisel::session_source::SessionSourceConfig {
-        foundry_config: Config::default(),
-        evm_opts: EvmOpts::default(),
-        backend: None,
-        traces: false,
-    })
+    let mut env = ChiselSession::new_in(
+        &chisel::session_source::SessionSourceConfig {
+            foundry_config: Config::default(),
+            evm_opts: EvmOpts::default(),
+            backend: None,
+            fork: None,
+            retain_state: false,
+            debug: false,
+            profile: false,
+            rpc_aliases_file: Some(tmp.path().join("rpc_endpoints.json")),
+            traces: false,
+        },
+        tmp.path().to_path_buf(),
+    )
     .unwrap_or_else(|_| panic!("Failed to create ChiselSession!"));
+    let cache_dir = env.cache_dir();
 
     // Write the session
     let cached_session_name = env.write().unwrap();
 
-    // Count the number of items in the cache_dir directory
-    let mut num_items = std::fs::read_dir(&cache_dir).unwrap().count();
+    // Count the number of sessions in the cache_dir directory
+    let mut num_items = env.list_sessions().unwrap().len();
     num_items = if num_items > 0 { num_items - 1 } else { 0 };
 
     // Validate the session
-    assert_eq!(cached_session_name, format!("{cache_dir}chisel-{num_items}.json"));
+    assert_eq!(cached_session_name, cache_dir.join(format!("chisel-{num_items}.json")));
 }
 
 #[test]
 #[serial]
 fn test_write_session_with_name() {
-    // Create the cache directory if it doesn't exist
-    let cache_dir = ChiselSession::cache_dir().unwrap();
-    ChiselSession::create_cache_dir().unwrap();
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
 
     // Create a new session
-    let mut env = ChiselSession::new(&chisel::session_source::SessionSourceConfig::default())
-        .unwrap_or_else(|_| panic!("Failed to create ChiselSession!"));
+    let mut env = new_session(tmp.path());
+    let cache_dir = env.cache_dir();
     env.id = Some(String::from("test"));
 
     // Write the session
     let cached_session_name = env.write().unwrap();
 
     // Validate the session
-    assert_eq!(cached_session_name, format!("{cache_dir}chisel-test.json"));
+    assert_eq!(cached_session_name, cache_dir.join("chisel-test.json"));
 }
 
 #[test]
 #[serial]
 fn test_clear_cache() {
     // Create a session to validate clearing a non-empty cache directory
-    let cache_dir = ChiselSession::cache_dir().unwrap();
-    ChiselSession::create_cache_dir().unwrap();
-    let mut env = ChiselSession::new(&SessionSourceConfig::default())
-        .unwrap_or_else(|_| panic!("Failed to create ChiselSession!"));
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    let cache_dir = env.cache_dir();
     env.write().unwrap();
 
     // Clear the cache
-    ChiselSession::clear_cache().unwrap();
+    env.clear_cache().unwrap();
 
-    // Validate there are no items in the cache dir
-    let num_items = std::fs::read_dir(cache_dir).unwrap().count();
-    assert_eq!(num_items, 0);
+    // Validate only the lock file remains in the cache dir
+    let entries = std::fs::read_dir(cache_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(entries, vec![String::from(".lock")]);
 }
 
 #[test]
 #[serial]
 fn test_list_sessions() {
-    // Create and clear the cache directory
-    ChiselSession::create_cache_dir().unwrap();
-    ChiselSession::clear_cache().unwrap();
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
 
     // Create a new session
-    let mut env = ChiselSession::new(&SessionSourceConfig::default())
-        .unwrap_or_else(|_| panic!("Failed to create ChiselSession!"));
+    let mut env = new_session(tmp.path());
 
     env.write().unwrap();
 
     // List the sessions
-    let sessions = ChiselSession::list_sessions().unwrap();
+    let sessions = env.list_sessions().unwrap();
 
     // Validate the sessions
     assert_eq!(sessions.len(), 1);
-    assert_eq!(sessions[0].1, "chisel-0.json");
+    assert_eq!(sessions[0].id, "0");
+    assert_eq!(sessions[0].path, tmp.path().join("chisel-0.json"));
+    assert!(sessions[0].error.is_none());
+}
+
+#[test]
+#[serial]
+fn test_list_sessions_sorted() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+
+    // Create several sessions, the last of which is the most recently modified
+    let env = (0..3)
+        .map(|id| {
+            let mut env = new_session(tmp.path());
+            set_modified(&env.write().unwrap(), 30 - id * 10);
+            env
+        })
+        .last()
+        .unwrap();
+
+    // Drop some foreign files into the cache directory
+    std::fs::write(tmp.path().join("notes.txt"), "").unwrap();
+    std::fs::write(tmp.path().join("chisel-1.json~"), "").unwrap();
+    std::fs::write(tmp.path().join(".chisel-2.json.tmp"), "").unwrap();
+    std::fs::create_dir(tmp.path().join("chisel-dir.json")).unwrap();
+
+    // Only the sessions should be listed, most recent first
+    let ids = env.list_sessions().unwrap().into_iter().map(|info| info.id).collect::<Vec<_>>();
+    assert_eq!(ids, vec!["2", "1", "0"]);
+
+    // Foreign files should not affect the next ID
+    assert_eq!(env.next_cached_session().unwrap().0, "3");
+}
+
+#[test]
+#[serial]
+fn test_list_sessions_info() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+
+    // Create a described and tagged session
+    let mut env = new_session(tmp.path());
+    env.set_description(Some(String::from("scratchpad")));
+    env.set_tags(vec![String::from("erc20"), String::from("wip")]);
+    for input in ["uint256 a = 1;", "a += 1;"] {
+        let (new_source, _) =
+            env.session_source.as_ref().unwrap().clone_with_new_line(input.to_string()).unwrap();
+        env.session_source = Some(new_source);
+    }
+    assert!(env.dirty);
+    env.write().unwrap();
+    assert!(!env.dirty);
+
+    // Write a corrupted session next to it
+    std::fs::write(tmp.path().join("chisel-1.json"), "{ not json").unwrap();
+
+    // Both sessions should be listed
+    let mut sessions = env.list_sessions().unwrap();
+    sessions.sort_by(|a, b| a.id.cmp(&b.id));
+    assert_eq!(sessions.len(), 2);
+
+    assert_eq!(sessions[0].description.as_deref(), Some("scratchpad"));
+    assert_eq!(sessions[0].tags, vec!["erc20", "wip"]);
+    assert_eq!(sessions[0].snippet_count, 2);
+    assert!(sessions[0].error.is_none());
+
+    assert_eq!(sessions[1].id, "1");
+    assert!(sessions[1].error.is_some());
+
+    // The description and tags should survive a round trip through the cache
+    let loaded = env.load("0").unwrap();
+    assert_eq!(loaded.description.as_deref(), Some("scratchpad"));
+    assert_eq!(loaded.tags, vec!["erc20", "wip"]);
 }
 
 #[test]
 #[serial]
 fn test_load_cache() {
-    // Create and clear the cache directory
-    ChiselSession::create_cache_dir().unwrap();
-    ChiselSession::clear_cache().unwrap();
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
 
     // Create a new session
-    let mut env = ChiselSession::new(&SessionSourceConfig::default())
-        .unwrap_or_else(|_| panic!("Failed to create ChiselSession!"));
+    let mut env = new_session(tmp.path());
     env.write().unwrap();
 
     // Load the session
-    let new_env = ChiselSession::load("0");
+    let new_env = env.load("0");
 
     // Validate the session
     assert!(new_env.is_ok());
     let new_env = new_env.unwrap();
     assert_eq!(new_env.id.unwrap(), String::from("0"));
+    assert_eq!(new_env.cache_dir, tmp.path());
     assert_eq!(
         new_env.session_source.unwrap().to_repl_source(),
         env.session_source.unwrap().to_repl_source()
@@ -143,46 +250,859 @@ fn test_load_cache() {
 #[test]
 #[serial]
 fn test_write_same_session_multiple_times() {
-    // Create and clear the cache directory
-    ChiselSession::create_cache_dir().unwrap();
-    ChiselSession::clear_cache().unwrap();
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
 
     // Create a new session
-    let mut env = ChiselSession::new(&SessionSourceConfig::default())
-        .unwrap_or_else(|_| panic!("Failed to create ChiselSession!"));
+    let mut env = new_session(tmp.path());
     env.write().unwrap();
     env.write().unwrap();
     env.write().unwrap();
     env.write().unwrap();
-    assert_eq!(ChiselSession::list_sessions().unwrap().len(), 1);
+    assert_eq!(env.list_sessions().unwrap().len(), 1);
 }
 
 #[test]
 #[serial]
 fn test_load_latest_cache() {
-    // Create and clear the cache directory
-    ChiselSession::create_cache_dir().unwrap();
-    ChiselSession::clear_cache().unwrap();
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
 
     // Create sessions
-    let mut env = ChiselSession::new(&SessionSourceConfig::default())
-        .unwrap_or_else(|_| panic!("Failed to create ChiselSession!"));
-    env.write().unwrap();
-
-    let wait_time = std::time::Duration::from_millis(100);
-    std::thread::sleep(wait_time);
+    let mut env = new_session(tmp.path());
+    enter_inputs(&mut env, &["uint256 a = 1;"]);
+    set_modified(&env.write().unwrap(), 20);
 
-    let mut env2 = ChiselSession::new(&SessionSourceConfig::default())
-        .unwrap_or_else(|_| panic!("Failed to create ChiselSession!"));
-    env2.write().unwrap();
+    let mut env2 = new_session(tmp.path());
+    enter_inputs(&mut env2, &["uint256 a = 1;"]);
+    set_modified(&env2.write().unwrap(), 10);
 
     // Load the latest session
-    let new_env = ChiselSession::latest().unwrap();
+    let new_env = env.latest().unwrap();
 
     // Validate the session
     assert_eq!(new_env.id.unwrap(), "1");
     assert_eq!(
         new_env.session_source.unwrap().to_repl_source(),
-        env.session_source.unwrap().to_repl_source()
+        env.session_source.as_ref().unwrap().to_repl_source()
     );
+
+    // New sessions are not cached until they are written, and empty sessions are skipped even
+    // though they are more recent
+    let mut empty = new_session(tmp.path());
+    assert!(empty.id.is_none());
+    assert_eq!(env.list_sessions().unwrap().len(), 2);
+    empty.write().unwrap();
+    assert_eq!(empty.id.as_deref(), Some("2"));
+    assert_eq!(env.latest().unwrap().id.as_deref(), Some("1"));
+    assert_eq!(env.latest_cached_session().unwrap(), tmp.path().join("chisel-1.json"));
+
+    // A session moved to another cache directory reserves a new ID there
+    let other = TempDir::new().unwrap();
+    let mut moved = env.with_cache_dir(other.path().to_path_buf());
+    assert!(moved.id.is_none());
+    moved.write().unwrap();
+    assert!(other.path().join("chisel-0.json").exists());
+}
+
+#[test]
+#[serial]
+fn test_delete_session() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+
+    // Create several sessions
+    let env = (0..3)
+        .map(|_| {
+            let mut env = new_session(tmp.path());
+            env.write().unwrap();
+            env
+        })
+        .last()
+        .unwrap();
+    assert_eq!(env.list_sessions().unwrap().len(), 3);
+
+    // Load and delete the currently loaded session
+    let mut env = env.load("1").unwrap();
+    env.delete_session("1").unwrap();
+    assert!(env.id.is_none());
+
+    // Validate the deleted session is no longer listed
+    let sessions = env.list_sessions().unwrap();
+    assert_eq!(sessions.len(), 2);
+    assert!(!sessions.iter().any(|info| info.id == "1"));
+
+    // Deleting a session that does not exist should fail
+    assert!(env.delete_session("1").is_err());
+}
+
+#[test]
+#[serial]
+fn test_named_sessions() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+
+    // Write a named session
+    let mut env = new_session(tmp.path());
+    let cache_dir = env.cache_dir();
+    env.write().unwrap();
+    let cached_session_name = env.write_as("scratchpad").unwrap();
+    assert_eq!(cached_session_name, cache_dir.join("chisel-scratchpad.json"));
+
+    // Naming a session releases the numeric ID that it reserved when it was first written
+    assert!(!cache_dir.join("chisel-0.json").exists());
+    let (id, _) = env.next_cached_session().unwrap();
+    assert_eq!(id, "0");
+
+    // Load the named session
+    let loaded = env.load("scratchpad").unwrap();
+    assert_eq!(loaded.id.unwrap(), "scratchpad");
+
+    // Another session can not be saved over the named one, and keeps its ID
+    let mut other = new_session(tmp.path());
+    let id = other.id.clone();
+    assert!(other.write_as("scratchpad").is_err());
+    assert_eq!(other.id, id);
+    assert_eq!(env.write_as("scratchpad").unwrap(), cache_dir.join("chisel-scratchpad.json"));
+
+    // Invalid names should be rejected
+    assert!(env.write_as("bad/name").is_err());
+    assert!(env.write_as("").is_err());
+    assert!(env.load("../chisel-scratchpad").is_err());
+}
+
+#[test]
+#[serial]
+fn test_next_cached_session() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    let cache_dir = env.cache_dir();
+    env.create_cache_dir().unwrap();
+
+    // The new session should reserve ID 0 when it is first written
+    assert!(env.id.is_none());
+    assert_eq!(env.next_cached_session().unwrap().0, "0");
+    env.write().unwrap();
+    assert_eq!(env.id.as_deref(), Some("0"));
+    assert_eq!(
+        env.next_cached_session().unwrap(),
+        (String::from("1"), cache_dir.join("chisel-1.json"))
+    );
+
+    // Files that are not numbered chisel sessions should be skipped
+    std::fs::write(cache_dir.join("notes.txt"), "").unwrap();
+    std::fs::write(cache_dir.join("chisel-named.json"), "").unwrap();
+    std::fs::write(cache_dir.join("chisel-3.json.bak"), "").unwrap();
+    assert_eq!(env.next_cached_session().unwrap().0, "1");
+
+    // Gaps in the IDs should not cause collisions
+    std::fs::write(cache_dir.join("chisel-5.json"), "").unwrap();
+    assert_eq!(env.next_cached_session().unwrap().0, "6");
+
+    // Touching an older session should not change the next ID
+    std::fs::write(cache_dir.join("chisel-0.json"), "").unwrap();
+    assert_eq!(env.next_cached_session().unwrap().0, "6");
+
+    // Running out of IDs should fail rather than overflow
+    std::fs::write(cache_dir.join(format!("chisel-{}.json", usize::MAX)), "").unwrap();
+    assert!(env.next_cached_session().is_err());
+}
+
+#[test]
+#[serial]
+fn test_write_replaces_corrupted_session() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    let cache_dir = env.cache_dir();
+    env.create_cache_dir().unwrap();
+
+    // Simulate a truncated session file left behind by a crash
+    std::fs::write(cache_dir.join("chisel-0.json"), "{\"session_source\": {").unwrap();
+    assert!(env.load("0").is_err());
+
+    // Writing the session again should replace the corrupted file cleanly
+    env.id = Some(String::from("0"));
+    env.write().unwrap();
+    assert!(env.load("0").is_ok());
+
+    // No temporary files should be left in the cache directory
+    let mut entries = std::fs::read_dir(&cache_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    entries.sort();
+    assert_eq!(entries, vec![String::from(".lock"), String::from("chisel-0.json")]);
+}
+
+#[test]
+#[serial]
+fn test_export_import_session() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+
+    // Create a session with multiple snippets
+    let mut env = new_session(tmp.path());
+    env.id = Some(String::from("0"));
+    env.session_source
+        .as_mut()
+        .unwrap()
+        .with_global_code("pragma solidity ^0.8.0;")
+        .with_global_code("import {Cheats} from \"forge-std/Vm.sol\";")
+        .with_top_level_code("event Greeting(string greeting);")
+        .with_run_code("uint256 a = 1;")
+        .with_run_code("emit Greeting(\"Hello, \\\"world\\\"!\");");
+
+    // Export and re-import the session
+    let export_path = tmp.path().join("exported.json");
+    env.export(&export_path).unwrap();
+    let imported = env.import(&export_path).unwrap();
+
+    // The imported session should get a fresh ID
+    assert!(imported.id.is_none());
+
+    // The raw source should round-trip byte-for-byte
+    let (original, imported) =
+        (env.session_source.as_ref().unwrap(), imported.session_source.as_ref().unwrap());
+    assert_eq!(original.global_code, imported.global_code);
+    assert_eq!(original.top_level_code, imported.top_level_code);
+    assert_eq!(original.run_code, imported.run_code);
+    assert_eq!(original.solc.version().unwrap(), imported.solc.version().unwrap());
+
+    // Sessions that fail to parse should be rejected upon import
+    let mut export: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&export_path).unwrap()).unwrap();
+    export["run_code"] = serde_json::Value::String(String::from("uint256 a = ;"));
+    std::fs::write(&export_path, export.to_string()).unwrap();
+    assert!(env.import(&export_path).is_err());
+}
+
+#[test]
+#[serial]
+fn test_migrate_legacy_session() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    env.session_source.as_mut().unwrap().with_run_code("uint256 a = 1;");
+    let cache_file = env.write().unwrap();
+
+    // Rewrite the cached session in the legacy, unversioned format
+    let mut legacy: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&cache_file).unwrap()).unwrap();
+    legacy.as_object_mut().unwrap().remove("format_version");
+    legacy["session_source"]["snippets"][0].as_object_mut().unwrap().remove("kind");
+    std::fs::write(&cache_file, serde_json::to_string_pretty(&legacy).unwrap()).unwrap();
+
+    // The legacy session should be migrated upon load
+    let loaded = env.load("0").unwrap();
+    assert_eq!(loaded.format_version, CACHE_FORMAT_VERSION);
+    assert_eq!(loaded.session_source.as_ref().unwrap().run_code, "uint256 a = 1;\n");
+    assert_eq!(loaded.session_source.as_ref().unwrap().snippets[0].kind(), SnippetKind::Statement);
+    let latest = env.latest().unwrap();
+    assert_eq!(latest.format_version, CACHE_FORMAT_VERSION);
+
+    // Sessions saved by a newer chisel should be rejected with a clear error
+    legacy["format_version"] = serde_json::Value::from(CACHE_FORMAT_VERSION + 1);
+    std::fs::write(&cache_file, serde_json::to_string_pretty(&legacy).unwrap()).unwrap();
+    let err = env.load("0").unwrap_err();
+    assert!(err.to_string().contains("newer chisel"));
+
+    // Versions that overflow are newer as well, rather than wrapping around
+    legacy["format_version"] = serde_json::Value::from(u64::from(u32::MAX) + 2);
+    std::fs::write(&cache_file, serde_json::to_string_pretty(&legacy).unwrap()).unwrap();
+    let err = env.load("0").unwrap_err();
+    assert!(err.to_string().contains("newer chisel"));
+
+    // Versions that are not integers should be rejected rather than treated as legacy
+    for version in [serde_json::Value::from("1"), serde_json::Value::from(1.5)] {
+        legacy["format_version"] = version;
+        std::fs::write(&cache_file, serde_json::to_string_pretty(&legacy).unwrap()).unwrap();
+        let err = env.load("0").unwrap_err();
+        assert!(err.to_string().contains("Invalid cache format version"), "{err}");
+    }
+}
+
+#[test]
+#[serial]
+fn test_prune_sessions() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+
+    // Create several sessions, the last of which is the most recently modified
+    let mut env = (0..4)
+        .map(|id| {
+            let mut env = new_session(tmp.path());
+            set_modified(&env.write().unwrap(), 40 - id * 10);
+            env
+        })
+        .last()
+        .unwrap();
+    std::fs::write(tmp.path().join("notes.txt"), "").unwrap();
+
+    // An empty cache has nothing to prune
+    let empty = TempDir::new().unwrap();
+    let mut opened = ChiselSession::open_in(empty.path().to_path_buf());
+    assert!(opened.prune_sessions(std::time::Duration::ZERO, 0).unwrap().is_empty());
+
+    // No sessions are older than a day
+    let pruned = env.prune_sessions(std::time::Duration::from_secs(24 * 60 * 60), 0).unwrap();
+    assert!(pruned.is_empty());
+
+    // Prune all sessions except for the most recent one
+    let mut pruned = env.prune_sessions(std::time::Duration::ZERO, 1).unwrap();
+    pruned.sort();
+    assert_eq!(pruned, vec!["chisel-0.json", "chisel-1.json", "chisel-2.json"]);
+
+    // Non-chisel files should never be deleted
+    assert!(tmp.path().join("notes.txt").exists());
+    assert!(tmp.path().join("chisel-3.json").exists());
+}
+
+#[test]
+#[serial]
+fn test_load_bit_flipped_session() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+
+    // Write a session with some source
+    let mut env = new_session(tmp.path());
+    env.session_source.as_mut().unwrap().with_run_code("uint256 a = 1;");
+    let path = env.write().unwrap();
+    assert!(env.checksum.is_some());
+
+    // Flip a bit within the session's source
+    let contents = std::fs::read_to_string(&path).unwrap();
+    std::fs::write(&path, contents.replace("uint256 a = 1;", "uint256 a = 3;")).unwrap();
+
+    // Loading the session should fail with a corruption error naming the file
+    let err = env.load("0").unwrap_err();
+    match err.downcast_ref::<SessionError>() {
+        Some(SessionError::SessionCorrupted { path: corrupted, .. }) => {
+            assert_eq!(corrupted, &path)
+        }
+        _ => panic!("Expected a corrupted session error, got: {err}"),
+    }
+}
+
+#[test]
+#[serial]
+fn test_load_truncated_session() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+
+    // Write an older, valid session
+    let mut env = new_session(tmp.path());
+    env.session_source.as_mut().unwrap().with_run_code("uint256 a = 1;");
+    set_modified(&env.write().unwrap(), 10);
+
+    // Write a newer session and truncate it
+    let mut env2 = new_session(tmp.path());
+    let path = env2.write().unwrap();
+    let contents = std::fs::read_to_string(&path).unwrap();
+    std::fs::write(&path, &contents[..contents.len() / 2]).unwrap();
+
+    // Loading the truncated session should fail with a corruption error
+    let err = env.load("1").unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<SessionError>(),
+        Some(SessionError::SessionCorrupted { .. })
+    ));
+
+    // Loading the latest session should fall back to the older, valid session
+    let latest = env.latest().unwrap();
+    assert_eq!(latest.id.as_deref(), Some("0"));
+    assert_eq!(latest.skipped_sessions.len(), 1);
+    assert!(matches!(
+        &latest.skipped_sessions[0],
+        SessionError::SessionCorrupted { path: skipped, .. } if skipped == &path
+    ));
+    assert_eq!(latest.session_source.unwrap().run_code, "uint256 a = 1;\n");
+}
+
+#[test]
+#[serial]
+fn test_concurrent_writes() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let cache_dir = tmp.path().to_path_buf();
+
+    // Create and write two sessions concurrently
+    let handles = (0..2)
+        .map(|_| {
+            let cache_dir = cache_dir.clone();
+            std::thread::spawn(move || {
+                let mut env = new_session(&cache_dir);
+                env.write().unwrap()
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut paths = handles.into_iter().map(|handle| handle.join().unwrap()).collect::<Vec<_>>();
+    paths.sort();
+
+    // Each session should have been written to its own file
+    assert_eq!(paths, vec![cache_dir.join("chisel-0.json"), cache_dir.join("chisel-1.json")]);
+    assert_eq!(new_session(&cache_dir).list_sessions().unwrap().len(), 2);
+}
+
+#[test]
+#[serial]
+fn test_duplicate_session() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+
+    // Write a session with some source
+    let mut env = new_session(tmp.path());
+    env.session_source.as_mut().unwrap().with_run_code("uint256 a = 1;");
+    let original_path = env.write().unwrap();
+    let original_contents = std::fs::read_to_string(&original_path).unwrap();
+
+    // Duplicate the session; It should reserve a new ID
+    let mut duplicate = env.duplicate().unwrap();
+    assert_eq!(duplicate.id.as_deref(), Some("1"));
+    assert_eq!(
+        duplicate.session_source.as_ref().unwrap().run_code,
+        env.session_source.as_ref().unwrap().run_code
+    );
+
+    // Modifying and writing the duplicate must not touch the original
+    duplicate.session_source.as_mut().unwrap().with_run_code("uint256 b = 2;");
+    let duplicate_path = duplicate.write().unwrap();
+    assert_ne!(duplicate_path, original_path);
+    assert_eq!(std::fs::read_to_string(&original_path).unwrap(), original_contents);
+
+    // Both sessions should load independently
+    assert_eq!(env.load("0").unwrap().session_source.unwrap().run_code, "uint256 a = 1;\n");
+    assert_eq!(
+        env.load("1").unwrap().session_source.unwrap().run_code,
+        "uint256 a = 1;\nuint256 b = 2;\n"
+    );
+}
+
+#[test]
+#[serial]
+fn test_autosave_session() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    assert!(env.autosave);
+
+    // Append several snippets, autosaving after each
+    for i in 0..3 {
+        env.session_source.as_mut().unwrap().with_run_code(&format!("uint256 a{i} = {i};"));
+        env.autosave().unwrap();
+    }
+    env.flush_autosave().unwrap();
+
+    // The cached session should reflect the last snippet without an explicit save
+    let loaded = env.load("0").unwrap();
+    assert_eq!(
+        loaded.session_source.unwrap().run_code,
+        "uint256 a0 = 0;\nuint256 a1 = 1;\nuint256 a2 = 2;\n"
+    );
+
+    // Disabling autosave should leave the cache untouched
+    env.autosave = false;
+    env.session_source.as_mut().unwrap().with_run_code("uint256 a3 = 3;");
+    env.autosave().unwrap();
+    env.flush_autosave().unwrap();
+    assert!(!env.load("0").unwrap().session_source.unwrap().run_code.contains("a3"));
+}
+
+#[test]
+#[serial]
+fn test_compressed_session() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+
+    // Sessions larger than the threshold should be compressed
+    let large_code =
+        "// Padding to push the session over the threshold\n".repeat(COMPRESSION_THRESHOLD / 32);
+    env.session_source.as_mut().unwrap().with_run_code(&large_code);
+    let path = env.write().unwrap();
+    assert_eq!(path, tmp.path().join("chisel-0.json.gz"));
+    assert!(!tmp.path().join("chisel-0.json").exists());
+    assert!(std::fs::metadata(&path).unwrap().len() < COMPRESSION_THRESHOLD as u64);
+
+    // Compressed sessions should be listed, loaded, and counted like any other session
+    let sessions = env.list_sessions().unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].id, "0");
+    assert!(sessions[0].error.is_none());
+    assert_eq!(env.next_cached_session().unwrap().0, "1");
+    assert_eq!(env.load("0").unwrap().session_source.unwrap().run_code.len(), large_code.len());
+    assert_eq!(env.latest().unwrap().id.as_deref(), Some("0"));
+
+    // Shrinking the session should replace the compressed cache file
+    env.session_source.as_mut().unwrap().drain_run();
+    let path = env.write().unwrap();
+    assert_eq!(path, tmp.path().join("chisel-0.json"));
+    assert!(!tmp.path().join("chisel-0.json.gz").exists());
+}
+
+#[test]
+#[serial]
+fn test_load_missing_session() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    for _ in 0..3 {
+        new_session(tmp.path()).write().unwrap();
+    }
+    new_session(tmp.path()).write_as("scratchpad").unwrap();
+
+    // The error should list the sessions that do exist
+    let err = env.load("7").unwrap_err();
+    assert_eq!(err.to_string(), "Session 7 not found! Available sessions: 0, 1, 2, scratchpad");
+}
+
+#[test]
+#[serial]
+fn test_rehydrate_session() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+
+    // Write a session with some source
+    let mut env = new_session(tmp.path());
+    env.session_source.as_mut().unwrap().with_run_code("uint256 a = 1;");
+    env.write().unwrap();
+
+    // Loaded sessions should be ready to use without rebuilding them
+    let loaded = env.load("0").unwrap();
+    let source = loaded.session_source.as_ref().unwrap();
+    assert!(source.generated_output.is_some());
+    assert!(source.solc.solc.exists());
+    assert_eq!(source.to_repl_source(), env.session_source.as_ref().unwrap().to_repl_source());
+
+    let latest = env.latest().unwrap();
+    assert!(latest.session_source.as_ref().unwrap().generated_output.is_some());
+}
+
+#[test]
+#[serial]
+fn test_persist_compiler_settings() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+
+    // Write a session with custom compiler settings
+    let mut env = new_session(tmp.path());
+    let settings = CompilerSettings {
+        optimizer: true,
+        runs: 1_000,
+        evm_version: Some(String::from("berlin")),
+        via_ir: false,
+    };
+    env.session_source.as_mut().unwrap().compiler_settings = settings.clone();
+    let cache_file = env.write().unwrap();
+
+    // The settings should round-trip through the cache
+    let loaded = env.load("0").unwrap();
+    assert_eq!(loaded.session_source.unwrap().compiler_settings, settings);
+
+    // Sessions cached without compiler settings should load with the default settings
+    let mut legacy: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&cache_file).unwrap()).unwrap();
+    legacy["session_source"].as_object_mut().unwrap().remove("compiler_settings");
+    std::fs::write(&cache_file, serde_json::to_string_pretty(&legacy).unwrap()).unwrap();
+    let loaded = env.load("0").unwrap();
+    assert_eq!(loaded.session_source.unwrap().compiler_settings, CompilerSettings::default());
+}
+
+#[test]
+#[serial]
+fn test_history_files() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    assert!(env.history_file().is_none());
+    env.write().unwrap();
+    let history_file = env.history_file().unwrap();
+    assert_eq!(history_file, tmp.path().join("chisel-0.history"));
+
+    // History files should not be listed as sessions
+    std::fs::write(&history_file, "uint256 a = 1\n").unwrap();
+    assert_eq!(env.list_sessions().unwrap().len(), 1);
+
+    // Duplicates should inherit the history of the original session
+    let duplicate = env.duplicate().unwrap();
+    let duplicate_history = duplicate.history_file().unwrap();
+    assert_eq!(std::fs::read_to_string(&duplicate_history).unwrap(), "uint256 a = 1\n");
+
+    // Deleting a session should delete its history
+    env.delete_session("0").unwrap();
+    assert!(!history_file.exists());
+    assert!(env.history_file().is_none());
+
+    // Clearing the cache should delete all history files
+    env.clear_cache().unwrap();
+    assert!(!duplicate_history.exists());
+}
+
+/// Creates a session whose cache file is written, so that a journal can be replayed on top of it
+fn journaled_session(cache_dir: &Path) -> ChiselSession {
+    let mut env = new_session(cache_dir);
+    env.autosave = false;
+    env.write().unwrap();
+    env
+}
+
+#[tokio::test]
+#[serial]
+async fn test_recover_from_journal() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = journaled_session(tmp.path());
+
+    // Simulate a crash: Journal inputs without writing the session again
+    let inputs = [
+        "uint256 a = 1",
+        "function double(uint256 x) public pure returns (uint256) {\n    return x * 2;\n}",
+        "uint256 b = double(a);",
+    ];
+    for input in inputs {
+        let (new_source, _) =
+            env.session_source.as_ref().unwrap().clone_with_new_line(input.to_string()).unwrap();
+        env.session_source = Some(new_source);
+        env.journal(input).unwrap();
+    }
+    assert_eq!(env.recoverable_sessions(), vec!["0"]);
+
+    // Recovering the session should reproduce the snippets
+    let mut recovered = env.recover("0").await.unwrap();
+    let (original, recovered_source) =
+        (env.session_source.as_ref().unwrap(), recovered.session_source.as_ref().unwrap());
+    assert_eq!(recovered_source.global_code, original.global_code);
+    assert_eq!(recovered_source.top_level_code, original.top_level_code);
+    assert_eq!(recovered_source.run_code, original.run_code);
+    assert!(recovered.dirty);
+
+    // Writing the recovered session should truncate the journal
+    recovered.write().unwrap();
+    assert!(!recovered.journal_file().unwrap().exists());
+    assert!(env.recoverable_sessions().is_empty());
+}
+
+#[tokio::test]
+#[serial]
+async fn test_recover_edited_snippet() {
+    // Editing a snippet with `!edit` is journaled with the edited code, so that recovering the
+    // session replaces the snippet again
+    let tmp = TempDir::new().unwrap();
+    let mut env = journaled_session(tmp.path());
+    for input in ["uint256 a = 1;", "uint256 b = a + 1;"] {
+        enter_inputs(&mut env, &[input]);
+        env.journal(input).unwrap();
+    }
+    env.journal("!edit 0 uint256 a = 3;").unwrap();
+
+    let recovered = env.recover("0").await.unwrap();
+    let snippets = &recovered.session_source.as_ref().unwrap().snippets;
+    assert_eq!(snippets.len(), 2);
+    assert_eq!(snippets[0].code.trim(), "uint256 a = 3;");
+    assert_eq!(snippets[1].code.trim(), "uint256 b = a + 1;");
+}
+
+#[tokio::test]
+#[serial]
+async fn test_recover_optimizer() {
+    // The optimizer settings are journaled, and both enabling and disabling them replay
+    let tmp = TempDir::new().unwrap();
+    let env = journaled_session(tmp.path());
+    env.journal("!optimizer 1000").unwrap();
+    let settings = env.recover("0").await.unwrap().session_source.unwrap().compiler_settings;
+    assert!(settings.optimizer);
+    assert_eq!(settings.runs, 1000);
+    env.journal("!optimizer off").unwrap();
+    let settings = env.recover("0").await.unwrap().session_source.unwrap().compiler_settings;
+    assert!(!settings.optimizer);
+    assert_eq!(settings.runs, 1000);
+
+    env.journal("!optimizer many").unwrap();
+    assert!(env.recover("0").await.is_err());
+}
+
+#[tokio::test]
+#[serial]
+async fn test_recover_evm_version() {
+    // The EVM version is journaled for both the compiler and the executor
+    let tmp = TempDir::new().unwrap();
+    let env = journaled_session(tmp.path());
+    env.journal("!evm london").unwrap();
+    let source = env.recover("0").await.unwrap().session_source.unwrap();
+    assert_eq!(source.compiler_settings.evm_version.as_deref(), Some("london"));
+    assert_eq!(source.config.foundry_config.evm_version.to_string(), "london");
+
+    env.journal("!evm frontier").unwrap();
+    assert!(env.recover("0").await.is_err());
+}
+
+#[tokio::test]
+#[serial]
+async fn test_recover_via_ir() {
+    // Switching the compilation pipeline is journaled
+    let tmp = TempDir::new().unwrap();
+    let env = journaled_session(tmp.path());
+    env.journal("!viair on").unwrap();
+    assert!(env.recover("0").await.unwrap().session_source.unwrap().compiler_settings.via_ir);
+    env.journal("!viair off").unwrap();
+    assert!(!env.recover("0").await.unwrap().session_source.unwrap().compiler_settings.via_ir);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_recover_solc_version() {
+    // The solc version is journaled, and replaying it still checks the session's pragmas
+    let tmp = TempDir::new().unwrap();
+    let env = journaled_session(tmp.path());
+    let version = env.session_source.as_ref().unwrap().solc.version().unwrap();
+    let version = format!("{}.{}.{}", version.major, version.minor, version.patch);
+    env.journal(&format!("!solc v{version}")).unwrap();
+    let source = env.recover("0").await.unwrap().session_source.unwrap();
+    assert_eq!(source.solc.version().unwrap().to_string(), version);
+
+    env.journal(&format!("pragma solidity ={version};")).unwrap();
+    env.journal("!solc 0.7.6").unwrap();
+    let err = env.recover("0").await.unwrap_err();
+    assert!(err.downcast_ref::<PragmaConflict>().is_some(), "{err}");
+}
+
+#[tokio::test]
+#[serial]
+async fn test_recover_remapping() {
+    // Remappings are journaled with their absolute target, and replace a remapping of the same
+    // prefix
+    let tmp = TempDir::new().unwrap();
+    let env = journaled_session(tmp.path());
+    let lib = tmp.path().canonicalize().unwrap();
+    std::fs::create_dir(lib.join("old")).unwrap();
+    env.journal(&format!("!remap math/={}/old/", lib.display())).unwrap();
+    env.journal(&format!("!remap math/={}/", lib.display())).unwrap();
+    let remappings = env.recover("0").await.unwrap().session_source.unwrap().remappings;
+    assert_eq!(remappings.len(), 1);
+    assert_eq!(remappings[0].name, "math/");
+    assert_eq!(remappings[0].path, format!("{}/", lib.display()));
+}
+
+#[tokio::test]
+#[serial]
+async fn test_recover_validates_commands() {
+    // Journaled commands are replayed through the dispatcher, so they are validated just as
+    // they are when entered
+    let tmp = TempDir::new().unwrap();
+    let env = journaled_session(tmp.path());
+    env.journal("!contractname Counter").unwrap();
+    env.journal("!license MIT").unwrap();
+    let source = env.recover("0").await.unwrap().session_source.unwrap();
+    assert_eq!(source.contract_name, "Counter");
+    assert_eq!(source.license, "MIT");
+
+    env.journal("!contractname 1Counter").unwrap();
+    assert!(env.recover("0").await.is_err());
+}
+
+#[test]
+#[serial]
+fn test_cache_eviction() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    env.cache_limits = CacheLimits { max_cache_size_bytes: u64::MAX, max_sessions: 3 };
+    env.write().unwrap();
+
+    // Fill the cache up to its session limit, with the duplicates modified in order
+    for _ in 0..2 {
+        env.duplicate().unwrap();
+    }
+    set_modified(&tmp.path().join("chisel-1.json"), 20);
+    set_modified(&tmp.path().join("chisel-2.json"), 10);
+    std::fs::write(tmp.path().join("chisel-0.history"), "uint256 a = 1\n").unwrap();
+
+    // Writing the oldest session over the limit should evict the next oldest session instead
+    env.cache_limits.max_sessions = 2;
+    set_modified(&tmp.path().join("chisel-0.json"), 30);
+    env.write().unwrap();
+    assert_eq!(env.take_evicted(), vec!["1"]);
+    assert!(env.take_evicted().is_empty());
+    assert!(tmp.path().join("chisel-0.json").exists());
+    assert!(tmp.path().join("chisel-0.history").exists());
+    assert!(!tmp.path().join("chisel-1.json").exists());
+
+    // A tiny size cap should evict everything but the current session
+    env.cache_limits = CacheLimits { max_cache_size_bytes: 1, max_sessions: 2 };
+    env.write().unwrap();
+    assert_eq!(env.take_evicted(), vec!["2"]);
+    assert_eq!(env.list_sessions().unwrap().len(), 1);
+    assert!(tmp.path().join("chisel-0.json").exists());
+}
+
+#[test]
+#[serial]
+fn test_load_broken_snippets() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    for input in ["uint256 a = 1;", "uint256 b = 2;", "event Log(uint256 value);"] {
+        let (new_source, _) =
+            env.session_source.as_ref().unwrap().clone_with_new_line(input.to_string()).unwrap();
+        env.session_source = Some(new_source);
+    }
+    let cache_file = env.write().unwrap();
+
+    // Break the second snippet of the cached session by hand
+    let mut fixture: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&cache_file).unwrap()).unwrap();
+    fixture["session_source"]["snippets"][1]["code"] =
+        serde_json::Value::String(String::from("uint256 b = ;\n"));
+    std::fs::write(&cache_file, serde_json::to_string_pretty(&fixture).unwrap()).unwrap();
+
+    // The broken snippet is reported along with the parser's diagnostics
+    let err = env.load("0").unwrap_err();
+    let session_err = err.downcast_ref::<SessionError>().unwrap();
+    assert!(matches!(session_err, SessionError::SessionCorrupted { .. }));
+    let message = err.to_string();
+    assert!(message.contains("snippet 1 (`uint256 b = ;`) failed to parse"), "{message}");
+    assert!(message.contains("at byte 12"), "{message}");
+
+    // In lenient mode, the other snippets survive
+    env.lenient = true;
+    let loaded = env.load("0").unwrap();
+    assert_eq!(loaded.broken_snippets.len(), 1);
+    assert_eq!(loaded.broken_snippets[0].index, 1);
+    assert_eq!(loaded.broken_snippets[0].diagnostics.code, "uint256 b = ;\n");
+    let source = loaded.session_source.as_ref().unwrap();
+    let codes = source.snippets.iter().map(|snippet| snippet.code.as_str()).collect::<Vec<_>>();
+    assert_eq!(codes, ["uint256 a = 1;\n", "event Log(uint256 value);\n"]);
+    assert_eq!(source.run_code, "uint256 a = 1;\n");
+    assert!(loaded.dirty);
+}
+
+#[test]
+#[serial]
+fn test_open_cache_without_source() {
+    // Opening the cache neither creates a session source nor writes a session
+    let tmp = TempDir::new().unwrap();
+    let opened = ChiselSession::open_in(tmp.path().to_path_buf());
+    assert!(opened.session_source.is_none());
+    assert_eq!(opened.list_sessions().unwrap_err().to_string(), "No sessions found!");
+    assert_eq!(std::fs::read_dir(tmp.path()).unwrap().count(), 0);
+
+    // Sessions written by others can be listed and loaded from the opened cache
+    let mut env = new_session(tmp.path());
+    env.session_source.as_mut().unwrap().with_run_code("uint256 a = 1;");
+    env.write().unwrap();
+    let id = env.id.clone().unwrap();
+    assert_eq!(opened.list_sessions().unwrap().len(), 1);
+    let mut loaded = opened.load(&id).unwrap();
+    assert_eq!(loaded.session_source.as_ref().unwrap().run_code, "uint256 a = 1;\n");
+
+    // A vanished solc binary is resolved again on demand
+    let source = loaded.session_source.as_mut().unwrap();
+    let version = source.solc.version().unwrap();
+    let solc_path = tmp.path().join(format!("solc-{version}"));
+    std::fs::copy(&source.solc.solc, &solc_path).unwrap();
+    source.solc.solc = solc_path.clone();
+    std::fs::remove_file(&solc_path).unwrap();
+    assert!(loaded.compile().is_ok());
+    assert_ne!(loaded.session_source.as_ref().unwrap().solc.solc, solc_path);
 }
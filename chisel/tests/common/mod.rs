@@ -0,0 +1,27 @@
+//! Helpers shared by the integration tests of chisel
+//!
+//! Each test file is compiled as its own crate, which only uses some of the helpers.
+#![allow(dead_code)]
+
+use chisel::{session::ChiselSession, session_source::SessionSourceConfig};
+use std::path::Path;
+
+/// Helper function for creating a new [ChiselSession] that is cached in `cache_dir`
+pub fn new_session(cache_dir: &Path) -> ChiselSession {
+    ChiselSession::new_in(&SessionSourceConfig::default(), cache_dir.to_path_buf())
+        .unwrap_or_else(|_| panic!("Failed to create ChiselSession!"))
+}
+
+/// Helper function for entering inputs into the source of a [ChiselSession], as the REPL does
+/// before executing them
+pub fn enter_inputs(env: &mut ChiselSession, inputs: &[&str]) {
+    for input in inputs {
+        let (new_source, _) = env
+            .session_source
+            .as_ref()
+            .unwrap()
+            .clone_with_new_line(input.to_string())
+            .unwrap_or_else(|e| panic!("Failed to enter {input:?}: {e}"));
+        env.session_source = Some(new_source);
+    }
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use chisel::completion::Completions;
+use common::new_session;
+use serial_test::serial;
+use tempfile::TempDir;
+
+#[test]
+#[serial]
+fn test_completions() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+
+    // Commands and their aliases complete after a `!`
+    let completions = Completions::new(env.session_source.as_ref());
+    let (start, candidates) = completions.complete("!sn", 3);
+    assert_eq!(start, 1);
+    assert_eq!(candidates, ["sn", "snap", "snapshot", "snapshots", "snippets"]);
+    assert_eq!(completions.complete("!statediff", 10).1, ["statediff"]);
+
+    // Keywords and builtins, along with their members
+    assert_eq!(completions.complete("uint256 x = keccak", 18).1, ["keccak256"]);
+    assert_eq!(completions.complete("uint24", 6).1, ["uint24", "uint240", "uint248"]);
+    assert_eq!(
+        completions.complete("bytes2", 6).1,
+        [
+            "bytes2", "bytes20", "bytes21", "bytes22", "bytes23", "bytes24", "bytes25", "bytes26",
+            "bytes27", "bytes28", "bytes29"
+        ]
+    );
+    let candidates = completions.complete("int", 3).1;
+    assert_eq!(candidates.iter().filter(|candidate| candidate.starts_with("int")).count(), 35);
+    assert!(candidates.contains(&"int8".to_string()) && candidates.contains(&"int256".to_string()));
+    let (start, candidates) = completions.complete("msg.s", 5);
+    assert_eq!(start, 4);
+    assert_eq!(candidates, ["sender", "sig"]);
+    assert_eq!(completions.complete("block.", 6).1.len(), 8);
+    assert_eq!(completions.complete("type(uint256).m", 15).1, ["max", "min"]);
+    assert_eq!(completions.complete("vm.dea", 6).1, ["deal"]);
+    assert!(completions.complete("", 0).1.is_empty());
+
+    // Items defined within the session complete once they are entered
+    assert!(completions.complete("Coun", 4).1.is_empty());
+    let inputs = [
+        "contract Counter {\n    uint256 public count;\n    function increment() public { count += 1; }\n}",
+        "struct Point { uint256 x; uint256 y; }",
+        "Point point;",
+        "Counter counter = new Counter();",
+        "address owner = msg.sender;",
+    ];
+    for input in inputs {
+        let (new_source, _) =
+            env.session_source.as_ref().unwrap().clone_with_new_line(input.to_string()).unwrap();
+        env.session_source = Some(new_source);
+    }
+    let mut completions = completions;
+    completions.update(env.session_source.as_ref());
+    assert_eq!(completions.complete("Coun", 4).1, ["Counter"]);
+    assert_eq!(completions.complete("uint256 c = coun", 16).1, ["counter"]);
+    assert_eq!(completions.complete("counter.", 8).1, ["count", "increment"]);
+    assert_eq!(completions.complete("Counter(address(0)).c", 21).1, ["count"]);
+    assert_eq!(completions.complete("point.", 6).1, ["x", "y"]);
+    assert!(completions.complete("owner.bal", 9).1.contains(&"balance".to_string()));
+    assert!(completions.complete("vm.", 3).1.contains(&"warp".to_string()));
+    let (start, candidates) = completions.complete("!inspect po", 11);
+    assert_eq!(start, 9);
+    assert_eq!(candidates, ["point"]);
+
+    // Candidates are derived from the cursor position rather than the end of the input
+    let (start, candidates) = completions.complete("counter.inc + point.y", 11);
+    assert_eq!(start, 8);
+    assert_eq!(candidates, ["increment"]);
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use common::new_session;
+use serial_test::serial;
+use tempfile::TempDir;
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+async fn test_step_debugger() {
+    use forge::debug::Instruction;
+    yansi::Paint::disable();
+
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let env = new_session(tmp.path());
+    let source = env.session_source.unwrap();
+    assert!(source.debug(None).await.is_err());
+    let source = source.clone_with_new_line("uint256 a = 1;".to_string()).unwrap().0;
+
+    // The debugger starts at the first instruction of the last statement
+    let mut debugger = source.debug(None).await.unwrap();
+    let step = debugger.current().unwrap();
+    assert_eq!(step.snippet, Some(0));
+    assert_eq!(step.depth, 0);
+    let location = debugger.location();
+    assert!(location.contains("Source: line") && location.contains("uint256 a = 1;"), "{location}");
+
+    // Instructions are stepped through forwards and backwards, within the bounds of the execution
+    let start = debugger.cursor;
+    debugger.command("step").unwrap();
+    assert_eq!(debugger.cursor, start + 1);
+    debugger.command("").unwrap();
+    assert_eq!(debugger.cursor, start + 2);
+    debugger.command("back 2").unwrap();
+    assert_eq!(debugger.cursor, start);
+    debugger.command("b 1000000").unwrap();
+    assert_eq!(debugger.cursor, 0);
+    assert!(debugger.command("back").unwrap().starts_with("Reached the start of the execution"));
+    debugger.command(&format!("s {}", debugger.steps.len() * 2)).unwrap();
+    assert_eq!(debugger.cursor, debugger.steps.len() - 1);
+
+    // Step counts beyond the bounds of the execution are clamped rather than overflowing
+    debugger.command(&format!("b {}", usize::MAX)).unwrap();
+    assert_eq!(debugger.cursor, 0);
+    debugger.command(&format!("s {}", usize::MAX)).unwrap();
+    assert_eq!(debugger.cursor, debugger.steps.len() - 1);
+
+    // The machine state is printed on demand
+    debugger.command("b 1000000").unwrap();
+    debugger.command("s 3").unwrap();
+    assert!(debugger.command("memory").unwrap().contains("[0x00:0x20]"));
+    assert!(debugger.command("m 0x20 0x20").unwrap().starts_with("Memory from offset 0x20"));
+    assert!(debugger.command("stack").unwrap().contains("[0]: 0x"));
+    assert!(debugger.command("help").unwrap().contains("continue"));
+    assert!(debugger.command("frobnicate").unwrap().starts_with("Unknown command"));
+    assert_eq!(debugger.command("quit"), None);
+
+    // A statement that reverts is debugged against the session, including its revert
+    let mut debugger = source.debug(Some("require(a > 1, \"small\");")).await.unwrap();
+    assert_eq!(debugger.current().unwrap().snippet, Some(1));
+    while !matches!(debugger.current().unwrap().step.instruction, Instruction::OpCode(0xfd)) {
+        assert_ne!(debugger.cursor, debugger.steps.len() - 1, "no REVERT was reached");
+        debugger.command("continue").unwrap();
+    }
+
+    // Definitions are not executed, so they can not be debugged
+    assert!(source.debug(Some("function f() public {}")).await.is_err());
+}
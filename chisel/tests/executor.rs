@@ -0,0 +1,738 @@
+mod common;
+
+use chisel::{
+    dispatcher::{ChiselDispatcher, DUMP_LIMIT},
+    executor::disassemble,
+    runner::RevertReason,
+    session_source::{
+        check_cheatcodes, parse_amount, parse_timeout, EnvOverrides, ExecutionLimits, ForkConfig,
+    },
+    storage::{parse_storage_path, StorageValue},
+};
+use common::new_session;
+use ethers::{
+    types::U256,
+    utils::{hex, keccak256},
+};
+use serial_test::serial;
+use tempfile::TempDir;
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+async fn test_library_snippets() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    for input in [
+        "library MathLib { function square(uint x) internal pure returns (uint) { return x*x; } }",
+        "library PublicLib { function cube(uint256 x) public pure returns (uint256) { return x * x * x; } }",
+        "using MathLib for uint;",
+        "uint y = uint(3).square();",
+        "uint256 z = PublicLib.cube(y);",
+    ] {
+        let (new_source, _) =
+            env.session_source.as_ref().unwrap().clone_with_new_line(input.to_string()).unwrap();
+        env.session_source = Some(new_source);
+    }
+
+    // Libraries are rendered at file scope, above the REPL contract
+    let source = env.session_source.as_mut().unwrap();
+    let rendered = source.to_repl_source();
+    let repl = rendered.find("contract REPL").unwrap();
+    assert!(rendered.find("library MathLib").unwrap() < repl);
+    assert!(rendered.find("library PublicLib").unwrap() < repl);
+
+    // Libraries with public functions are linked and deployed ahead of the REPL contract
+    let (_, result) = source.execute().await.unwrap();
+    assert!(result.success);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+async fn test_revert_reasons() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let env = new_session(tmp.path());
+
+    // Successful runs have no revert reason
+    let (mut source, _) = env
+        .session_source
+        .as_ref()
+        .unwrap()
+        .clone_with_new_line("uint256 a = 1;".to_string())
+        .unwrap();
+    let (_, result) = source.execute().await.unwrap();
+    assert_eq!(result.revert_reason(None), None);
+
+    // Failed requirements revert with their message
+    let (mut reverting, _) =
+        source.clone_with_new_line("require(a == 2, \"a is not 2\");".to_string()).unwrap();
+    let (_, result) = reverting.execute().await.unwrap();
+    assert!(!result.success);
+    assert_eq!(result.revert_reason(None), Some(String::from("a is not 2")));
+
+    // Custom errors are decoded with the ABI of the REPL contract
+    let (source, _) =
+        source.clone_with_new_line("error Unexpected(uint256 a);".to_string()).unwrap();
+    let (mut reverting, _) =
+        source.clone_with_new_line("if (a == 1) revert Unexpected(a);".to_string()).unwrap();
+    let (_, result) = reverting.execute().await.unwrap();
+    let abi = reverting.artifact().unwrap().abi;
+    assert_eq!(result.revert_reason(abi.as_ref()), Some(String::from("Unexpected(1)")));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+async fn test_persisted_state() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let env = new_session(tmp.path());
+
+    // The first statement increments a counter
+    let (source, _) = env
+        .session_source
+        .as_ref()
+        .unwrap()
+        .clone_with_new_line(
+            "contract Counter { uint256 public count; event Incremented(uint256 count); function increment() public { count += 1; emit Incremented(count); } }"
+                .to_string(),
+        )
+        .unwrap();
+    let (mut source, _) = source
+        .clone_with_new_line("Counter counter = new Counter(); counter.increment();".to_string())
+        .unwrap();
+    let (_, first) = source.execute().await.unwrap();
+    assert!(first.success && first.replayed);
+    assert_eq!(first.logs.len(), 1);
+
+    // The second statement sees the count that the first one left behind. The first statement
+    // does not run again, so the counter is only incremented once and emits no event.
+    let (mut source, _) =
+        source.clone_with_new_line("uint256 count = counter.count();".to_string()).unwrap();
+    let (_, second) = source.execute().await.unwrap();
+    assert!(second.success && !second.replayed);
+    assert!(second.logs.is_empty());
+    assert_eq!(source.logs.len(), 1);
+    let one =
+        ethers::types::Bytes::from(ethers::abi::encode(&[ethers::abi::Token::Uint(U256::one())]));
+    let state = source.persisted.as_ref().unwrap();
+    assert_eq!(state.locals.as_ref().unwrap()["count"], one);
+
+    // `!rerun` replays the session from a clean state instead, with the same result
+    source.persisted = None;
+    source.config.backend = None;
+    let (_, replayed) = source.execute().await.unwrap();
+    assert!(replayed.success && replayed.replayed);
+    assert_eq!(replayed.logs.len(), 1);
+    let state = source.persisted.as_ref().unwrap();
+    assert_eq!(state.locals.as_ref().unwrap()["count"], one);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+async fn test_gas_per_snippet() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let env = new_session(tmp.path());
+
+    let (source, _) = env
+        .session_source
+        .as_ref()
+        .unwrap()
+        .clone_with_new_line("uint256 sum = 1;".to_string())
+        .unwrap();
+    let (mut source, _) = source
+        .clone_with_new_line("for (uint256 i; i < 10; i++) { sum += i; }".to_string())
+        .unwrap();
+    let (_, result) = source.execute().await.unwrap();
+    assert!(result.success);
+
+    // Both statements are attributed, and the loop costs more than the assignment
+    let indices = result.gas_per_snippet.iter().map(|(index, _)| *index).collect::<Vec<_>>();
+    assert_eq!(indices, vec![0, 1]);
+    assert!(result.gas_per_snippet[1].1 > result.gas_per_snippet[0].1);
+
+    // The deployment and the base cost of the call are excluded
+    let total = result.gas_per_snippet.iter().map(|(_, gas)| gas).sum::<u64>();
+    assert!(total > 0);
+    assert!(total < result.gas_used.saturating_sub(21_000));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+async fn test_traces_decoded_by_session_contracts() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let env = new_session(tmp.path());
+
+    let mut source = env.session_source.clone().unwrap();
+    for line in [
+        "contract Helper { function get(uint256 a) external pure returns (uint256) { return a * 2; } }",
+        "Helper helper = new Helper();",
+        "uint256 doubled = helper.get(21);",
+    ] {
+        source = source.clone_with_new_line(line.to_string()).unwrap().0;
+    }
+    let (_, mut result) = source.execute().await.unwrap();
+    assert!(result.success);
+
+    // The helper is identified by its bytecode, and its call is decoded by its ABI
+    let known_contracts = source.known_contracts();
+    assert!(known_contracts.keys().any(|id| id.name == "Helper"));
+    let decoder =
+        ChiselDispatcher::decode_traces(&source.config, &mut result, &known_contracts).unwrap();
+    yansi::Paint::disable();
+    let mut rendered = String::new();
+    for (_, trace) in &mut result.traces {
+        decoder.decode(trace).await;
+        rendered.push_str(&trace.to_string());
+    }
+    yansi::Paint::enable();
+    assert!(rendered.contains("new Helper@"), "{rendered}");
+    assert!(rendered.contains("Helper::get(21)"), "{rendered}");
+    assert!(rendered.contains("← 42"), "{rendered}");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+async fn test_decoded_logs() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let env = new_session(tmp.path());
+
+    let mut source = env.session_source.clone().unwrap();
+    for line in [
+        "event Transfer(address indexed from, address indexed to, uint256 value);",
+        "event Named(string indexed name, uint256 value);",
+        "emit Transfer(address(1), address(2), 100);",
+        "emit Named(\"chisel\", 1);",
+        "assembly { log1(0, 0, 0x1234) }",
+    ] {
+        source = source.clone_with_new_line(line.to_string()).unwrap().0;
+    }
+    let (_, result) = source.execute().await.unwrap();
+    assert!(result.success);
+    assert_eq!(result.decoded_logs.len(), result.logs.len());
+    assert_eq!(source.logs, result.logs);
+
+    let rendered = result.decoded_logs.iter().map(ToString::to_string).collect::<Vec<_>>();
+    assert_eq!(
+        rendered[0],
+        "Transfer(from: 0x0000000000000000000000000000000000000001, to: 0x0000000000000000000000000000000000000002, value: 100)"
+    );
+
+    // Indexed strings are only available as their hash
+    let (_, params) = result.decoded_logs[1].event.as_ref().unwrap();
+    assert!(params[0].hashed && !params[1].hashed);
+    assert_eq!(params[0].value, ethers::abi::Token::FixedBytes(keccak256("chisel").to_vec()));
+    assert!(rendered[1].contains("(hash of the indexed value)"));
+
+    // Logs that match none of the session's events are shown raw
+    assert!(result.decoded_logs[2].event.is_none());
+    assert!(rendered[2].starts_with(&format!("Unknown event(topics: [0x{:064x}]", 0x1234)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+async fn test_cheatcodes() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let env = new_session(tmp.path());
+
+    let mut source = env.session_source.clone().unwrap();
+    for line in [
+        "import {Vm} from \"forge-std/Vm.sol\";",
+        "contract Who { function sender() external view returns (address) { return msg.sender; } }",
+        "Who who = new Who();",
+        "vm.warp(1700000000);",
+        "vm.prank(address(0xbeef));",
+        "address sender = who.sender();",
+        "Vm(address(vm)).deal(address(this), 100 ether);",
+    ] {
+        source = source.clone_with_new_line(line.to_string()).unwrap().0;
+    }
+
+    // Cheatcodes take effect on the state that later statements observe
+    let timestamp = source.clone().inspect("block.timestamp").await.unwrap().unwrap();
+    assert!(timestamp.contains("1700000000"), "{timestamp}");
+    let sender = source.clone().inspect("sender").await.unwrap().unwrap();
+    assert!(sender.contains("0x000000000000000000000000000000000000beef"), "{sender}");
+    let balance = source.clone().inspect("address(this).balance").await.unwrap().unwrap();
+    assert!(balance.contains("100000000000000000000"), "{balance}");
+
+    // Expectations must be satisfied within the same input, and nothing is ever broadcast
+    assert!(check_cheatcodes("vm.expectRevert(); who.sender();").is_ok());
+    assert!(check_cheatcodes("vm.expectRevert();").is_err());
+    assert!(check_cheatcodes("vm.startBroadcast();").is_err());
+    assert!(check_cheatcodes("uint256 evm = 1; evm.expectRevert;").is_ok());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+async fn test_inspect_state_variables() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+
+    let mut source = env.session_source.clone().unwrap();
+    for line in [
+        "struct Point { uint128 x; uint128 y; }",
+        "uint256 public count = 5;",
+        "mapping(address => uint256) public balances;",
+        "string public name = \"chisel\";",
+        "string public motto = \"a string that does not fit into a single slot\";",
+        "uint8[3] public small;",
+        "Point public point;",
+        "uint256 local = 1;",
+        "balances[address(0xabc)] = 100;",
+        "small[1] = 7;",
+        "point = Point(1, 2);",
+        "count += 1;",
+        "uint256[40] public many;",
+        "uint256[] public huge;",
+        "bytes public blob;",
+        "assembly { sstore(huge.slot, not(0)) sstore(blob.slot, not(0)) }",
+    ] {
+        source = source.clone_with_new_line(line.to_string()).unwrap().0;
+    }
+    env.session_source = Some(source);
+
+    let uint = |value: u64| StorageValue::Token(ethers::abi::Token::Uint(value.into()));
+    let count = env.inspect("count").await.unwrap();
+    assert_eq!((count.ty.as_str(), count.value), ("uint256", uint(6)));
+    let balance =
+        env.inspect("balances[0x0000000000000000000000000000000000000abc]").await.unwrap();
+    assert_eq!(balance.value, uint(100));
+    assert_eq!(env.inspect("balances").await.unwrap().value, StorageValue::Mapping);
+
+    // Strings are stored within their slot, or from its hash if they are too long
+    assert_eq!(env.inspect("name").await.unwrap().value.to_string(), "\"chisel\"");
+    assert_eq!(
+        env.inspect("motto").await.unwrap().value.to_string(),
+        "\"a string that does not fit into a single slot\""
+    );
+
+    // Packed arrays and structs are decoded element by element
+    assert_eq!(env.inspect("small").await.unwrap().value.to_string(), "[0, 7, 0]");
+    assert_eq!(env.inspect("small[1]").await.unwrap().value, uint(7));
+    assert!(env.inspect("small[3]").await.is_err());
+    assert_eq!(env.inspect("point").await.unwrap().value.to_string(), "{ x: 1, y: 2 }");
+
+    // Large arrays are cut short, and lengths that do not fit into memory are errors, not panics
+    assert!(env.inspect("many").await.unwrap().value.to_string().ends_with(", 0, … 8 more]"));
+    let huge = env.inspect("huge").await.unwrap().value.to_string();
+    assert!(huge.ends_with(&format!("… {} more]", U256::MAX - 32)), "{huge}");
+    assert_eq!(env.inspect(&format!("huge[{}]", U256::MAX - 1)).await.unwrap().value, uint(0));
+    let err = env.inspect("blob").await.unwrap_err().to_string();
+    assert!(err.contains("exceeds the limit"), "{err}");
+
+    // Unknown names suggest similar names, and local variables are not stored
+    let err = env.inspect("cont").await.unwrap_err().to_string();
+    assert!(err.contains("Did you mean `count`?"), "{err}");
+    let err = env.inspect("local").await.unwrap_err().to_string();
+    assert!(err.contains("\"run()\" function"), "{err}");
+
+    assert_eq!(
+        parse_storage_path("owners[1][ 2 ]").unwrap(),
+        ("owners", vec![String::from("1"), String::from("2")])
+    );
+    assert!(parse_storage_path("owners[1").is_err());
+    assert!(parse_storage_path("[1]").is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+async fn test_retained_machine_state() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let env = new_session(tmp.path());
+
+    let mut source = env.session_source.clone().unwrap();
+    for line in [
+        "contract Answer { function get() external pure returns (uint256) { return 42; } }",
+        "Answer answer = new Answer();",
+        "uint256 value = answer.get();",
+    ] {
+        source = source.clone_with_new_line(line.to_string()).unwrap().0;
+    }
+
+    // The machine state is only retained once enabled
+    source.clone().execute().await.unwrap();
+    assert!(source.retained_state().is_none());
+    source.config.retain_state = true;
+    let (_, result) = source.execute().await.unwrap();
+    assert!(result.success);
+    let state = source.retained_state().unwrap();
+    assert!(!state.stack.data().is_empty());
+    assert_eq!(state.return_data.len(), 32);
+    assert_eq!(state.return_data[31], 42);
+
+    // The retained state is discarded once the source is modified
+    let source = source.clone_with_new_line("value += 1;".to_string()).unwrap().0;
+    assert!(source.retained_state().is_none());
+
+    // Dumps are truncated unless all words are requested
+    yansi::Paint::disable();
+    let memory = [0u8; 32 * (DUMP_LIMIT + 6)];
+    let truncated = ChiselDispatcher::format_words(&memory, false);
+    assert_eq!(truncated.lines().count(), DUMP_LIMIT + 1);
+    assert!(truncated.ends_with("... 6 more words, supply `--full` to print all of them"));
+    assert_eq!(ChiselDispatcher::format_words(&memory, true).lines().count(), DUMP_LIMIT + 6);
+    assert!(ChiselDispatcher::format_words(&[0xab; 40], false)
+        .ends_with("[0x20:0x28]: 0xabababababababab"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+async fn test_deploy_session_contracts() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let env = new_session(tmp.path());
+
+    let mut source = env.session_source.clone().unwrap();
+    for line in [
+        "contract Counter { uint256 count; function increment() external { count++; } function get() external view returns (uint256) { return count; } }",
+        "interface ICounter { function get() external view returns (uint256); }",
+        "Counter c = new Counter();",
+        "c.increment();",
+        "c.increment();",
+    ] {
+        source = source.clone_with_new_line(line.to_string()).unwrap().0;
+    }
+
+    // Every contract of the session is part of the compile result
+    let mut built = source.clone();
+    built.build().unwrap();
+    let artifact = built.artifact().unwrap();
+    let names =
+        artifact.contracts.iter().map(|contract| contract.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["Counter", "ICounter"]);
+    let counter = &artifact.contracts[0];
+    assert!(counter.abi.as_ref().unwrap().function("increment").is_ok());
+    assert!(counter.bytecode.is_some() && counter.deployed_bytecode.is_some());
+    assert!(artifact.contracts[1].bytecode.is_none());
+
+    // The deployed contract is called, and the value it returns is printed
+    let (_, result) = source.clone().execute().await.unwrap();
+    assert!(result.success);
+    let value = source.inspect("c.get()").await.unwrap().unwrap();
+    assert!(value.contains("Decimal: 2"), "{value}");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+async fn test_decode_revert_reasons() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let env = new_session(tmp.path());
+
+    let source = env.session_source.clone().unwrap();
+    let source = source
+        .clone_with_new_line("error Insufficient(uint256 available, uint256 needed);".to_string())
+        .unwrap()
+        .0;
+    let revert = |line: &str| {
+        let mut source = source.clone_with_new_line(line.to_string()).unwrap().0;
+        async move {
+            let (_, result) = source.execute().await.unwrap();
+            assert!(!result.success);
+            result.revert.unwrap()
+        }
+    };
+
+    // Error(string)
+    let reason = revert("require(false, \"not enough\");").await;
+    assert_eq!(reason, RevertReason::Message("not enough".to_string()));
+
+    // Panic(uint256)
+    let reason = revert("uint8 x = 255; x++;").await;
+    assert_eq!(reason, RevertReason::Panic(0x11.into()));
+    assert_eq!(reason.to_string(), "Panic: arithmetic underflow or overflow (0x11)");
+
+    // A custom error of the session
+    let reason = revert("revert Insufficient(1, 2);").await;
+    assert_eq!(reason.to_string(), "Insufficient(available: 1, needed: 2)");
+
+    // An error that is not known to the session
+    let reason = revert("assembly { mstore(0, shl(224, 0xdeadbeef)) revert(0, 4) }").await;
+    assert_eq!(reason, RevertReason::Unknown(hex::decode("deadbeef").unwrap()));
+    assert!(reason.to_string().contains("0xdeadbeef"), "{reason}");
+    assert!(reason.to_string().contains("!abi"), "{reason}");
+
+    // Reverting without data
+    assert_eq!(RevertReason::decode(&[], &[], None).to_string(), "Reverted without a reason");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+async fn test_snapshots() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut dispatcher = ChiselDispatcher::with_session(new_session(tmp.path()));
+    let source = |dispatcher: &ChiselDispatcher| dispatcher.session.session_source.clone().unwrap();
+
+    for line in ["event Set(uint256 value);", "uint256 a = 1;", "emit Set(a);"] {
+        dispatcher.dispatch(line).await;
+    }
+    assert_eq!(source(&dispatcher).logs.len(), 1);
+
+    // Statements are snapshotted automatically, before they are executed
+    let snapshots = source(&dispatcher).snapshots;
+    assert_eq!(snapshots.len(), 2);
+    assert!(snapshots.iter().all(|snapshot| snapshot.automatic));
+    assert_eq!(snapshots[1].snippet_index(), Some(1));
+
+    // Undoing a statement also rewinds the logs it emitted
+    dispatcher.dispatch("!undo").await;
+    assert!(source(&dispatcher).logs.is_empty());
+
+    // Reverting to a snapshot removes the snippets entered since
+    dispatcher.dispatch("!snapshot").await;
+    let id = source(&dispatcher).snapshots.last().unwrap().id;
+    assert_eq!(id, 2);
+    dispatcher.dispatch("a = 2;").await;
+    dispatcher.dispatch("emit Set(a);").await;
+    assert_eq!(source(&dispatcher).logs.len(), 1);
+    dispatcher.dispatch(&format!("!revert {id}")).await;
+    let reverted = source(&dispatcher);
+    assert_eq!(reverted.snippets.len(), 2);
+    assert!(!reverted.run_code.contains("a = 2;"));
+    assert!(reverted.logs.is_empty());
+    assert!(dispatcher.session.revert_to_snapshot(42).is_err());
+
+    // Snapshots taken after the one reverted to are kept, i.e. the one taken automatically
+    // before the second event was emitted
+    dispatcher.dispatch(&format!("!revert {}", id + 2)).await;
+    assert!(source(&dispatcher).run_code.contains("a = 2;"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+async fn test_env_overrides() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+
+    let sender = "0x00000000000000000000000000000000000000aa";
+    for (field, value) in [
+        ("block", "100"),
+        ("timestamp", "0x6553f100"),
+        ("chainid", "10"),
+        ("sender", sender),
+        ("value", "1000"),
+    ] {
+        assert!(env.set_env_override(field, Some(value)).unwrap().is_empty());
+    }
+    assert!(env.set_env_override("block", Some("abc")).is_err());
+    assert!(env.set_env_override("sender", Some("0x1234")).is_err());
+    assert!(env.set_env_override("coinbase", Some("1")).is_err());
+
+    // Snippets read the overridden context
+    let source = env.session_source.as_mut().unwrap();
+    let decimal = |value: &str| format!("Decimal: {value}");
+    for (expr, expected) in [
+        ("block.number", decimal("100")),
+        ("block.timestamp", decimal("1700000000")),
+        ("block.chainid", decimal("10")),
+        ("msg.value", decimal("1000")),
+        ("msg.sender", sender.to_string()),
+    ] {
+        let inspected = source.inspect(expr).await.unwrap().unwrap();
+        assert!(inspected.contains(&expected), "{expr}: {inspected}");
+    }
+    let context = source.execution_env().await.unwrap();
+    assert_eq!(context.block.number, 100.into());
+    assert_eq!(context.tx.value, 1000.into());
+
+    // The overrides are persisted with the session
+    env.write().unwrap();
+    let id = env.id.clone().unwrap();
+    let loaded = env.load(&id).unwrap();
+    let overrides = &loaded.session_source.as_ref().unwrap().env_overrides;
+    assert_eq!(overrides.block_number, Some(100));
+    assert_eq!(overrides.value, Some(1000.into()));
+
+    // Removing an override restores the default
+    env.set_env_override("value", None).unwrap();
+    assert_eq!(env.session_source.as_ref().unwrap().env_overrides.value, None);
+
+    // Overrides that contradict the fork are warned about
+    let fork = ForkConfig {
+        endpoint: "mainnet".to_string(),
+        block: Some(1000),
+        chain_id: Some(1),
+        secret_url: None,
+    };
+    let overrides = EnvOverrides { block_number: Some(100), ..Default::default() };
+    assert_eq!(overrides.fork_conflicts(Some(&fork)).len(), 1);
+    let overrides =
+        EnvOverrides { block_number: Some(2000), chain_id: Some(10), ..Default::default() };
+    assert_eq!(overrides.fork_conflicts(Some(&fork)).len(), 1);
+    assert!(overrides.fork_conflicts(None).is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+async fn test_balances() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+
+    // Amounts are in wei unless suffixed with a unit
+    let ether = ethers::types::U256::exp10(18);
+    assert_eq!(parse_amount("1000").unwrap(), 1000.into());
+    assert_eq!(parse_amount("100 gwei").unwrap(), (100u64 * 1_000_000_000).into());
+    assert_eq!(parse_amount("1.5ether").unwrap(), ether * 3 / 2);
+    assert!(parse_amount("1.0000000001gwei").is_err());
+    assert!(parse_amount("ether").is_err());
+    assert!(parse_amount("abc").is_err());
+
+    // Accounts are credited before each execution
+    let beef = "0x000000000000000000000000000000000000beef".parse().unwrap();
+    env.deal(beef, ether * 3 / 2).unwrap();
+    let source = env.session_source.as_mut().unwrap();
+    assert_eq!(source.balance_of(beef).await.unwrap(), ether * 3 / 2);
+
+    // The REPL contract can send ether right away
+    let mut source = source
+        .clone_with_new_line("payable(address(0xbeef)).transfer(1 ether);".to_string())
+        .unwrap()
+        .0;
+    let (_, result) = source.execute().await.unwrap();
+    assert!(result.success);
+    assert_eq!(source.balance_of(beef).await.unwrap(), ether * 5 / 2);
+    let inspected = source.inspect("address(0xbeef).balance").await.unwrap().unwrap();
+    assert!(inspected.contains("Decimal: 2500000000000000000"), "{inspected}");
+
+    // Balances are formatted in each unit
+    yansi::Paint::disable();
+    let formatted = ChiselDispatcher::format_balance(ether * 5 / 2);
+    assert!(formatted.contains("Wei: 2500000000000000000"), "{formatted}");
+    assert!(formatted.contains("Gwei: 2500000000\n"), "{formatted}");
+    assert!(formatted.ends_with("Ether: 2.5"), "{formatted}");
+    assert!(ChiselDispatcher::format_balance(1.into()).ends_with("Ether: 0.000000000000000001"));
+
+    // The balances are persisted with the session
+    env.write().unwrap();
+    let loaded = env.load(&env.id.clone().unwrap()).unwrap();
+    assert_eq!(loaded.session_source.unwrap().balances.get(&beef), Some(&(ether * 3 / 2)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+async fn test_execution_limits() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    assert_eq!(env.session_source.as_ref().unwrap().limits, ExecutionLimits::default());
+
+    // Timeouts are in seconds unless suffixed with a unit
+    assert_eq!(parse_timeout("2").unwrap(), std::time::Duration::from_secs(2));
+    assert_eq!(parse_timeout("2.5s").unwrap(), std::time::Duration::from_millis(2500));
+    assert_eq!(parse_timeout("500ms").unwrap(), std::time::Duration::from_millis(500));
+    assert!(parse_timeout("abc").is_err());
+
+    // The limits can't be zero
+    assert!(env.set_gas_limit(0).is_err());
+    assert!(env.set_timeout(std::time::Duration::ZERO).is_err());
+
+    // A statement that runs out of gas fails with the gas limit
+    env.set_gas_limit(1_000_000).unwrap();
+    let mut source = env
+        .session_source
+        .as_ref()
+        .unwrap()
+        .clone_with_new_line("while (true) {}".to_string())
+        .unwrap()
+        .0;
+    let (_, result) = source.execute().await.unwrap();
+    assert!(!result.success);
+    assert_eq!(result.revert, Some(RevertReason::GasLimitExceeded(1_000_000)));
+
+    // A statement that runs out of time fails with the time limit
+    env.set_gas_limit(1_000_000_000).unwrap();
+    env.set_timeout(std::time::Duration::from_millis(50)).unwrap();
+    let mut source = env
+        .session_source
+        .as_ref()
+        .unwrap()
+        .clone_with_new_line("while (true) {}".to_string())
+        .unwrap()
+        .0;
+    let (_, result) = source.execute().await.unwrap();
+    assert!(!result.success);
+    assert_eq!(
+        result.revert,
+        Some(RevertReason::TimeLimitExceeded(std::time::Duration::from_millis(50)))
+    );
+
+    // The limits are persisted with the session
+    env.write().unwrap();
+    let loaded = env.load(&env.id.clone().unwrap()).unwrap();
+    let limits = loaded.session_source.unwrap().limits;
+    assert_eq!(limits.gas_limit, 1_000_000_000);
+    assert_eq!(limits.timeout, std::time::Duration::from_millis(50));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+async fn test_estimate() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let env = new_session(tmp.path());
+    let mut source = env
+        .session_source
+        .as_ref()
+        .unwrap()
+        .clone_with_new_line("uint256 a = 1;".to_string())
+        .unwrap()
+        .0;
+    assert!(source.execute().await.unwrap().1.success);
+
+    // The statement is estimated without being added to the session
+    let (estimated, result) = source.estimate("a += 1;").await.unwrap();
+    assert!(result.success);
+    assert!(!result.replayed);
+    assert_eq!(estimated.snippets.len(), 2);
+    assert!(result.gas_per_snippet.iter().any(|(index, gas)| *index == 1 && *gas > 0));
+    assert_eq!(source.snippets.len(), 1);
+
+    // A statement that would revert is estimated with its reason
+    let (_, result) = source.estimate("require(a > 1, \"small\");").await.unwrap();
+    assert!(!result.success);
+    assert_eq!(result.revert, Some(RevertReason::Message(String::from("small"))));
+
+    // The state of the session is left untouched
+    let inspected = source.inspect("a").await.unwrap().unwrap();
+    assert!(inspected.contains("Decimal: 1"), "{inspected}");
+
+    // Only statements can be estimated
+    assert!(source.estimate("function f() public {}").await.is_err());
+}
+
+#[test]
+#[serial]
+fn test_disassemble() {
+    yansi::Paint::disable();
+
+    // Instructions are listed by program counter, split into basic blocks at jump destinations.
+    // The push data of the last instruction is cut off.
+    let bytecode = hex::decode("60806040525b0060").unwrap();
+    assert_eq!(
+        disassemble(&bytecode, None).unwrap(),
+        "0| PUSH1 0x80\n2| PUSH1 0x40\n4| MSTORE\n\n5| JUMPDEST\n6| STOP\n7| PUSH1"
+    );
+
+    // A program counter selects its basic block, and marks its instruction
+    assert_eq!(disassemble(&bytecode, Some(6)).unwrap(), "5| JUMPDEST\n6|▶STOP\n7| PUSH1");
+    assert!(disassemble(&bytecode, Some(1)).is_err());
+    assert!(disassemble(&bytecode, Some(8)).is_err());
+
+    // The deployed bytecode of the REPL contract is disassembled offline
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    let deployed = env.compile().unwrap().deployed_bytecode.unwrap();
+    let listing = disassemble(&deployed, None).unwrap();
+    assert!(listing.lines().next().unwrap().ends_with("| PUSH1 0x80"), "{listing}");
+    assert!(listing.contains("JUMPDEST"));
+}
@@ -0,0 +1,125 @@
+mod common;
+
+use chisel::format::{NumberFormat, ValueFormatter, ValueType};
+use common::new_session;
+use serial_test::serial;
+use tempfile::TempDir;
+
+#[test]
+#[serial]
+fn test_value_formatter() {
+    use ethers::abi::{ParamType, Token};
+    yansi::Paint::disable();
+
+    // Collect the definitions of the session
+    let tmp = TempDir::new().unwrap();
+    let env = new_session(tmp.path());
+    let mut source = env.session_source.unwrap();
+    for line in [
+        "enum Color { Red, Green }",
+        "struct Paint { Color color; bytes32 name; }",
+        "event Painted(Paint[] paints, Color color);",
+    ] {
+        source = source.clone_with_new_line(line.to_string()).unwrap().0;
+    }
+    let mut formatter = source.value_formatter();
+    assert_eq!(formatter.enums["Color"], vec![String::from("Red"), String::from("Green")]);
+    let format =
+        |formatter: &ValueFormatter, token: &Token| formatter.format(&ValueType::of(token), token);
+
+    // Integers are displayed in decimal or hex, including negative ones
+    let negative = Token::Int(ethers::types::I256::from(-255).into_raw());
+    assert_eq!(format(&formatter, &Token::Uint(255.into())), "255");
+    assert_eq!(format(&formatter, &negative), "-255");
+    formatter.numbers = NumberFormat::Hex;
+    assert_eq!(format(&formatter, &Token::Uint(255.into())), "0xff");
+    assert_eq!(format(&formatter, &negative), "-0xff");
+    let inspection = formatter.inspection(&ValueType::of(&negative), &negative);
+    let raw = format!("0x{}01", "f".repeat(62));
+    assert_eq!(inspection, format!("Type: int256\n├ Decimal: -255\n└ Hex: {raw}"));
+    formatter.numbers = NumberFormat::Decimal;
+    assert_eq!("hex".parse::<NumberFormat>().unwrap(), NumberFormat::Hex);
+    assert_eq!("dec".parse::<NumberFormat>().unwrap().to_string(), "dec");
+    assert!("oct".parse::<NumberFormat>().is_err());
+
+    // Addresses are checksummed, and a bytes32 holding text is rendered along with it
+    let address = Token::Address("0x000000000000000000000000000000000000beef".parse().unwrap());
+    assert_eq!(format(&formatter, &address), "0x000000000000000000000000000000000000bEEF");
+    let mut lime = b"lime".to_vec();
+    lime.resize(32, 0);
+    let lime = Token::FixedBytes(lime);
+    assert_eq!(format(&formatter, &lime), format!("0x6c696d65{} (\"lime\")", "0".repeat(56)));
+    assert_eq!(
+        format(&formatter, &Token::FixedBytes(vec![0xff; 32])),
+        format!("0x{}", "ff".repeat(32))
+    );
+    assert_eq!(format(&formatter, &Token::FixedBytes(b"abcd".to_vec())), "0x61626364");
+
+    // Nested tuples and strings
+    let tuple = Token::Tuple(vec![
+        Token::Uint(1.into()),
+        Token::Tuple(vec![Token::Bool(true), Token::String(String::from("a"))]),
+        Token::Bytes(vec![0xab]),
+    ]);
+    assert_eq!(ValueType::of(&tuple).to_string(), "(uint256,(bool,string),bytes)");
+    assert_eq!(format(&formatter, &tuple), "(1, (true, \"a\"), 0xab)");
+
+    // Dynamic arrays of structs are printed with the names of their fields and variants, with an
+    // element per line if they are too wide
+    let kind = ParamType::Array(Box::new(ParamType::Tuple(vec![
+        ParamType::Uint(8),
+        ParamType::FixedBytes(32),
+    ])));
+    let ty = formatter.resolve(&kind, Some("struct Paint[]"));
+    assert_eq!(ty.to_string(), "struct Paint[]");
+    assert_eq!(ty.param_type(), kind);
+    let paint = |color: u64, name: Token| Token::Tuple(vec![Token::Uint(color.into()), name]);
+    let short = Token::Array(vec![paint(1, Token::FixedBytes(vec![0xff; 32]))]);
+    let paints = Token::Array(vec![paint(1, lime.clone()), paint(7, lime)]);
+    let name = format!("0x6c696d65{} (\"lime\")", "0".repeat(56));
+    assert_eq!(
+        formatter.format(&ty, &paints),
+        format!(
+            "[\n  Paint {{\n    color: Color.Green,\n    name: {name}\n  }},\n  Paint {{\n    color: Color(7),\n    name: {name}\n  }}\n]"
+        )
+    );
+    assert!(formatter.format(&ty, &short).starts_with("[\n  Paint {\n    color: Color.Green,"));
+
+    // Values that do not match their type are formatted by themselves
+    assert_eq!(formatter.format(&ty, &Token::Bool(false)), "false");
+
+    // The parameters of events are resolved by the event's definition
+    let types = formatter.resolve_event("Painted", &[&short, &Token::Uint(0.into())]);
+    assert_eq!(types[0], ty);
+    assert_eq!(formatter.format(&types[1], &Token::Uint(0.into())), "Color.Red");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+async fn test_format_values() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    yansi::Paint::disable();
+
+    // Enums and structs defined by the session are displayed by name
+    let mut source = env.session_source.clone().unwrap();
+    for line in [
+        "enum Color { Red, Green }",
+        "struct Paint { Color color; uint256 shade; }",
+        "Color color = Color.Green;",
+        "Paint memory paint = Paint(Color.Red, 3);",
+    ] {
+        source = source.clone_with_new_line(line.to_string()).unwrap().0;
+    }
+    let inspected = source.inspect("color").await.unwrap().unwrap();
+    assert_eq!(inspected, "Type: enum Color\n└ Value: Color.Green");
+    let inspected = source.inspect("paint").await.unwrap().unwrap();
+    assert_eq!(inspected, "Type: struct Paint\n└ Value: Paint { color: Color.Red, shade: 3 }");
+
+    // The number format is persisted with the session
+    env.set_number_format(NumberFormat::Hex).unwrap();
+    env.write().unwrap();
+    let loaded = env.load(&env.id.clone().unwrap()).unwrap();
+    assert_eq!(loaded.session_source.unwrap().number_format, NumberFormat::Hex);
+}
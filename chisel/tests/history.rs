@@ -0,0 +1,67 @@
+use chisel::history::{read_editor_history, write_editor_history, GlobalHistory, HistoryConfig};
+use serial_test::serial;
+use tempfile::TempDir;
+
+#[test]
+#[serial]
+fn test_global_history() {
+    let tmp = TempDir::new().unwrap();
+    let config = HistoryConfig { max_entries: 3, ..Default::default() };
+    let history = GlobalHistory { path: tmp.path().join("history"), config };
+    assert!(history.load().unwrap().is_empty());
+
+    // Consecutive duplicates and blank inputs are left out, multi-line inputs are kept whole
+    assert!(history.record("uint a = 1;").unwrap());
+    assert!(!history.record("uint a = 1;").unwrap());
+    assert!(!history.record("   ").unwrap());
+    assert!(history.record("function f() public {\n    a++;\n}").unwrap());
+    assert!(history.record(" secret").unwrap());
+    assert_eq!(
+        history.load().unwrap(),
+        vec!["uint a = 1;", "function f() public {\n    a++;\n}", " secret"]
+    );
+
+    // Only the most recent entries are loaded, and the file is compacted past twice the limit
+    for i in 0..4 {
+        history.record(&format!("a = {i};")).unwrap();
+    }
+    assert_eq!(history.load().unwrap(), vec!["a = 1;", "a = 2;", "a = 3;"]);
+    let contents = std::fs::read_to_string(&history.path).unwrap();
+    assert_eq!(contents.lines().count(), 3);
+
+    // The file is private to its owner, as inputs may reference sensitive values
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&history.path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    // Inputs that may contain secrets are never recorded
+    assert!(!history.record("!broadcast --private-key 0x01 --rpc-url mainnet").unwrap());
+    assert!(!history.record("!rpc add mainnet https://eth.example/v2/secret").unwrap());
+    assert!(history.record("!rpc list").unwrap());
+    assert!(!config.records("!broadcast 0", None));
+
+    let config = HistoryConfig { ignore_space: true, ignore_dups: false, ..config };
+    let history = GlobalHistory { config, ..history };
+    assert!(!history.record(" secret").unwrap());
+    assert!(history.record("a = 3;").unwrap());
+
+    let disabled_config = HistoryConfig { max_entries: 0, ..config };
+    let disabled = GlobalHistory { config: disabled_config, ..history };
+    assert!(!disabled.record("a = 4;").unwrap());
+    assert!(disabled.load().unwrap().is_empty());
+
+    // Session histories round trip through the rustyline format
+    let path = tmp.path().join("chisel-0.history");
+    let entries = vec!["uint a = 1;".to_string(), "a++;".to_string(), "a;".to_string()];
+    write_editor_history(&path, &entries, &HistoryConfig::default()).unwrap();
+    assert_eq!(read_editor_history(&path, &HistoryConfig::default()), entries);
+    assert!(read_editor_history(&tmp.path().join("missing"), &config).is_empty());
+
+    // Disabling the histories leaves the session's history file untouched
+    assert!(read_editor_history(&path, &disabled_config).is_empty());
+    write_editor_history(&path, &[], &disabled_config).unwrap();
+    assert_eq!(read_editor_history(&path, &HistoryConfig::default()), entries);
+}
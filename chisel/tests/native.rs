@@ -0,0 +1,59 @@
+mod common;
+
+use common::new_session;
+use serial_test::serial;
+use tempfile::TempDir;
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+async fn test_native_evaluation() {
+    yansi::Paint::disable();
+
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let env = new_session(tmp.path());
+    let mut source = env.session_source.unwrap();
+
+    // Pure builtins over literals are evaluated natively, with the same result as the EVM
+    for expr in [
+        "keccak256(\"transfer(address,uint256)\")",
+        "bytes4(keccak256(\"transfer(address,uint256)\"))",
+        "keccak256(hex\"beef\")",
+        "abi.encode(1, -1, true, \"chisel\")",
+        "abi.encodePacked(uint8(1), int16(-2), \"ab\", hex\"cd\", bytes2(0xbeef))",
+        "abi.encodePacked(address(0xbeef), bytes32(\"lime\"))",
+        "abi.encodeWithSignature(\"transfer(address,uint256)\", address(0xbeef), 1e18)",
+        "abi.encodeWithSelector(bytes4(0xa9059cbb), uint256(7))",
+        "keccak256(abi.encodePacked(\"a\", uint256(1)))",
+        "uint8(255)",
+    ] {
+        let native = source.evaluate_natively(expr).unwrap_or_else(|| panic!("{expr}"));
+        let evm = source.inspect(expr).await.unwrap().unwrap();
+        assert_eq!(native, evm, "{expr}");
+    }
+    let selector = source.evaluate_natively("bytes4(keccak256(\"transfer(address,uint256)\"))");
+    assert!(selector.unwrap().contains("0xa9059cbb"));
+
+    // Statements, non-literals, and conversions that do not compile are left to the EVM
+    for expr in [
+        "keccak256(\"a\");",
+        "keccak256(abi.encodePacked(1))",
+        "uint8(256)",
+        "bytes4(0xbeef)",
+        "keccak256(abi.encode(block.number))",
+        "keccak256(\"\\n\")",
+    ] {
+        assert_eq!(source.evaluate_natively(expr), None, "{expr}");
+    }
+    source = source.clone_with_new_line("uint256 a = 1;".to_string()).unwrap().0;
+    assert_eq!(source.evaluate_natively("abi.encode(a)"), None);
+
+    // Builtins that the session shadows are never evaluated natively
+    source = source
+        .clone_with_new_line(
+            "function keccak256(bytes memory) pure returns (bytes32) {}".to_string(),
+        )
+        .unwrap()
+        .0;
+    assert_eq!(source.evaluate_natively("keccak256(\"a\")"), None);
+}
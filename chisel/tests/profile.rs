@@ -0,0 +1,51 @@
+mod common;
+
+use common::new_session;
+use serial_test::serial;
+use tempfile::TempDir;
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+async fn test_gas_profile() {
+    use chisel::profile::OpcodeCategory;
+
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let env = new_session(tmp.path());
+    let mut source = env.session_source.unwrap();
+    let err = source.profile().await.unwrap_err();
+    assert!(err.to_string().contains("no statements to profile"), "{err}");
+    for line in [
+        "contract Counter { uint256 public count; function increment() external { count++; } }",
+        "Counter c = new Counter();",
+        "c.increment();",
+    ] {
+        source = source.clone_with_new_line(line.to_string()).unwrap().0;
+    }
+
+    // The storage write of the call is attributed to the call, not to the earlier statements
+    let profile = source.profile().await.unwrap().profile.unwrap();
+    assert!(profile.total > 0);
+    let write = profile
+        .categories
+        .iter()
+        .find(|category| category.category == OpcodeCategory::StorageWrite)
+        .unwrap();
+    assert_eq!(write.count, 1);
+    assert!(write.gas >= 2900, "{}", write.gas);
+    assert!(profile.categories.iter().map(|category| category.gas).sum::<u64>() <= profile.total);
+    assert!(profile.categories.windows(2).all(|pair| pair[0].gas >= pair[1].gas));
+    assert!(profile.opcodes.iter().any(|opcode| opcode.opcode == "SSTORE"));
+    assert_eq!(profile.calls.len(), 1);
+    assert_eq!(profile.calls[0].function.as_deref(), Some("increment()"));
+    assert_eq!(profile.calls[0].count, 1);
+
+    // The table and the JSON hold the same profile
+    yansi::Paint::disable();
+    let table = profile.table();
+    assert!(table.starts_with(&format!("Total: {} gas", profile.total)), "{table}");
+    assert!(table.contains("By external call:") && table.contains("increment()"), "{table}");
+    let json = serde_json::to_value(&profile).unwrap();
+    assert_eq!(json["total"], profile.total);
+    assert_eq!(json["calls"][0]["function"], "increment()");
+}
@@ -0,0 +1,257 @@
+mod common;
+
+use chisel::session_source::{PragmaConflict, SolidityPragma};
+use common::new_session;
+use serial_test::serial;
+use tempfile::TempDir;
+
+#[test]
+#[serial]
+fn test_merge_duplicate_imports() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+
+    // Create two sessions that share an import
+    let mut env = new_session(tmp.path());
+    env.session_source
+        .as_mut()
+        .unwrap()
+        .with_global_code("import \"forge-std/Vm.sol\";")
+        .with_top_level_code("function helper() public pure returns (uint256) { return 1; }");
+    let mut other = new_session(tmp.path());
+    other
+        .session_source
+        .as_mut()
+        .unwrap()
+        .with_global_code("import \"forge-std/Vm.sol\";")
+        .with_global_code("struct Point { uint256 x; uint256 y; }")
+        .with_run_code("uint256 a = helper();");
+
+    // The duplicate import should be skipped
+    let report = env.merge(&other).unwrap();
+    assert!(report.is_merged());
+    assert_eq!(report.deduplicated, vec!["import \"forge-std/Vm.sol\";"]);
+
+    let source = env.session_source.as_ref().unwrap();
+    assert_eq!(
+        source.global_code,
+        "import \"forge-std/Vm.sol\";\nstruct Point { uint256 x; uint256 y; }\n"
+    );
+    assert_eq!(source.run_code, "uint256 a = helper();\n");
+    assert!(env.dirty);
+}
+
+#[test]
+#[serial]
+fn test_merge_conflicts() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+
+    // Create two sessions with conflicting pragmas and definitions
+    let mut env = new_session(tmp.path());
+    env.session_source
+        .as_mut()
+        .unwrap()
+        .with_global_code("pragma solidity ^0.8.0;")
+        .with_run_code("uint256 a = 1;");
+    let mut other = new_session(tmp.path());
+    other
+        .session_source
+        .as_mut()
+        .unwrap()
+        .with_global_code("pragma solidity ^0.7.0;")
+        .with_run_code("uint256 a = 2;");
+
+    // The conflicts should be reported and the session left untouched
+    let before = env.session_source.as_ref().unwrap().to_repl_source();
+    let report = env.merge(&other).unwrap();
+    assert!(!report.is_merged());
+    assert_eq!(
+        report.conflicts,
+        vec![
+            String::from("`a` is defined in both sessions"),
+            String::from(
+                "Conflicting pragmas: no solc version satisfies `pragma solidity ^0.8.0;` and \
+                 `pragma solidity ^0.7.0;`"
+            ),
+        ]
+    );
+    assert_eq!(env.session_source.as_ref().unwrap().to_repl_source(), before);
+}
+
+#[test]
+#[serial]
+fn test_merge_overlapping_pragmas() {
+    let tmp = TempDir::new().unwrap();
+
+    // Pragmas that differ but are satisfied by a common solc version do not conflict
+    let mut env = new_session(tmp.path());
+    env.session_source.as_mut().unwrap().with_global_code("pragma solidity ^0.8.0;");
+    let mut other = new_session(tmp.path());
+    other
+        .session_source
+        .as_mut()
+        .unwrap()
+        .with_global_code("pragma solidity >=0.8.4;")
+        .with_run_code("uint256 a = 1;");
+
+    // Both pragmas are kept, as the merged source must satisfy both of them
+    let report = env.merge(&other).unwrap();
+    assert!(report.is_merged());
+    assert!(report.deduplicated.is_empty());
+    assert_eq!(
+        env.session_source.as_ref().unwrap().global_code,
+        "pragma solidity ^0.8.0;\npragma solidity >=0.8.4;\n"
+    );
+}
+
+#[test]
+#[serial]
+fn test_merge_different_solc_versions() {
+    let tmp = TempDir::new().unwrap();
+
+    // Sessions compiled with different solc versions merge as long as an available version
+    // satisfies the pragmas of both
+    let mut env = new_session(tmp.path());
+    env.session_source.as_mut().unwrap().with_global_code("pragma solidity >=0.8.0;");
+    let mut other = new_session(tmp.path());
+    let other_source = other.session_source.as_mut().unwrap();
+    other_source.solc = Solc::new(tmp.path().join("solc-0.8.4"));
+    other_source.with_global_code("pragma solidity >=0.8.4;").with_run_code("uint256 a = 1;");
+
+    let report = env.merge(&other).unwrap();
+    assert!(report.is_merged(), "{:?}", report.conflicts);
+    assert_eq!(env.session_source.as_ref().unwrap().run_code, "uint256 a = 1;\n");
+}
+
+#[test]
+#[serial]
+fn test_diff_sessions() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+
+    // Create a session and a modified duplicate of it
+    let mut env = new_session(tmp.path());
+    env.session_source
+        .as_mut()
+        .unwrap()
+        .with_top_level_code("uint256 public x;")
+        .with_run_code("uint256 a = 1;")
+        .with_run_code("uint256 b = 2;");
+    let mut duplicate = env.duplicate().unwrap();
+    let source = duplicate.session_source.as_mut().unwrap();
+    source.run_code = String::from("uint256 a = 1;\nuint256 c = 3;\n");
+
+    // The snippets should be classified by their raw source
+    let diff = ChiselSession::diff(&env, &duplicate);
+    assert!(!diff.is_empty());
+    let changes = diff
+        .snippets
+        .iter()
+        .map(|snippet| (snippet.section, snippet.change, snippet.source.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        changes,
+        vec![
+            (SourceSection::TopLevel, SnippetChange::Unchanged, "uint256 public x;"),
+            (SourceSection::Run, SnippetChange::Unchanged, "uint256 a = 1;"),
+            (SourceSection::Run, SnippetChange::Removed, "uint256 b = 2;"),
+            (SourceSection::Run, SnippetChange::Added, "uint256 c = 3;"),
+        ]
+    );
+
+    // The rendered diff should be a unified diff of the contract sources
+    let rendered = diff.to_string();
+    assert!(rendered.contains("-uint256 b = 2;"));
+    assert!(rendered.contains("+uint256 c = 3;"));
+
+    // Identical sessions should have an empty diff
+    assert!(ChiselSession::diff(&env, &env).is_empty());
+}
+
+#[test]
+#[serial]
+fn test_find_or_install_solc() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let env = new_session(tmp.path());
+
+    // Installed versions are resolved without installing them again, even while offline
+    let version = env.session_source.as_ref().unwrap().solc.version().unwrap();
+    let version = format!("{}.{}.{}", version.major, version.minor, version.patch);
+    let solc = find_or_install_solc(&version, true).unwrap();
+    assert!(solc.solc.exists());
+
+    // Missing versions are not installed while offline
+    let err = find_or_install_solc("0.0.0", true).unwrap_err();
+    assert!(err.to_string().starts_with("Could not install solc 0.0.0"));
+}
+
+#[test]
+#[serial]
+fn test_set_solc_version() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    let version = env.session_source.as_ref().unwrap().solc.version().unwrap();
+    let version = format!("{}.{}.{}", version.major, version.minor, version.patch);
+    let (new_source, _) = env
+        .session_source
+        .as_ref()
+        .unwrap()
+        .clone_with_new_line(format!("pragma solidity ={version};"))
+        .unwrap();
+    env.session_source = Some(new_source);
+
+    // Invalid versions are rejected
+    let err = env.set_solc_version("0.8").unwrap_err();
+    assert!(err.to_string().starts_with("Invalid solc version \"0.8\""));
+
+    // Versions that do not satisfy the session's pragmas are rejected along with the pragma
+    let err = env.set_solc_version("0.7.6").unwrap_err();
+    let conflict = err.downcast_ref::<PragmaConflict>().unwrap();
+    assert_eq!(conflict.solc_version.to_string(), "0.7.6");
+    assert_eq!(
+        conflict.pragmas,
+        vec![SolidityPragma {
+            snippet_index: Some(0),
+            requirement: format!("={version}"),
+            compatible: false
+        }]
+    );
+    let source = env.session_source.as_ref().unwrap();
+    assert_eq!(source.solc.version().unwrap().to_string(), version);
+
+    // Satisfying versions are applied and persist through the cache
+    env.session_source.as_mut().unwrap().config.foundry_config.offline = true;
+    env.set_solc_version(&format!("v{version}")).unwrap();
+    assert!(env.compile().is_ok());
+    env.write().unwrap();
+    let loaded = env.load(env.id.as_deref().unwrap()).unwrap();
+    let source = loaded.session_source.as_ref().unwrap();
+    assert_eq!(source.solc.version().unwrap().to_string(), version);
+}
+
+#[test]
+#[serial]
+fn test_list_solc_versions() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    env.session_source.as_mut().unwrap().config.foundry_config.offline = true;
+
+    // The session's version is among the installed versions, and highlighted
+    let versions = env.solc_versions().unwrap();
+    let current = env.session_source.as_ref().unwrap().solc.version().unwrap();
+    assert_eq!(versions.current, current);
+    assert!(versions.installed.contains(&current));
+    assert!(versions.installed.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(versions.default.as_ref(), versions.installed.last());
+
+    // Released versions are not listed while offline
+    assert!(versions.available.is_empty());
+    let listing = versions.to_string();
+    assert!(listing.starts_with("Installed solc versions:"));
+    assert!(listing.contains(&format!("* {current} (session")));
+    assert!(!listing.contains("Available for install:"));
+}
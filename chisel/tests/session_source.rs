@@ -0,0 +1,1742 @@
+mod common;
+
+use chisel::{
+    session::{find_or_install_solc, ChiselSession, CACHE_DIR_ENV},
+    session_source::{
+        evm_versions, CompilerDiagnostics, GasEstimate, GeneratedSourceDiagnostics,
+        ParseDiagnostics, ParseTreeFragment, PragmaConflict, SnippetKind, SnippetSpan, SolSnippet,
+        SolidityPragma,
+    },
+    solidity_helper::input_is_complete,
+};
+use common::{enter_inputs, new_session};
+use ethers::utils::{hex, keccak256};
+use ethers_solc::Solc;
+use serial_test::serial;
+use tempfile::TempDir;
+
+#[test]
+#[serial]
+fn test_contract_definitions_in_source() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+
+    // Define an interface, a function that uses it, and a library after it
+    let inputs = [
+        "interface ICounter {\n    function count() external view returns (uint256);\n}",
+        "function countOf(ICounter c) public view returns (uint256) {\n    return c.count();\n}",
+        "library Math {\n    function double(uint256 x) internal pure returns (uint256) {\n        return x * 2;\n    }\n}",
+    ];
+    for input in inputs {
+        let (new_source, _) =
+            env.session_source.as_ref().unwrap().clone_with_new_line(input.to_string()).unwrap();
+        env.session_source = Some(new_source);
+    }
+
+    // Definitions are rendered once each, in order of entry, above the REPL contract
+    let source = env.contract_source();
+    let position = |needle: &str| {
+        assert_eq!(source.matches(needle).count(), 1, "{needle} should be rendered exactly once");
+        source.find(needle).unwrap()
+    };
+    assert!(position("interface ICounter") < position("library Math"));
+    assert!(position("library Math") < position("contract REPL"));
+    assert!(position("contract REPL") < position("function countOf"));
+
+    // The rendered source should compile
+    assert!(env.session_source.as_mut().unwrap().build().is_ok());
+}
+
+#[test]
+#[serial]
+fn test_deduplicate_imports() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+
+    // Enter plain, aliased, and symbol imports multiple times
+    let source = env.session_source.as_mut().unwrap();
+    for import in [
+        "import \"forge-std/Test.sol\";",
+        "import \"forge-std/Test.sol\" as TestLib;",
+        "import {Test, console} from \"forge-std/Test.sol\";",
+        "import \"./lib/Counter.sol\";",
+        "import \"forge-std/Test.sol\" as TestLib;",
+        "import {console, Test} from \"forge-std/Test.sol\";",
+        "import {Test} from \"forge-std/Test.sol\";",
+        "import \"./lib/./Counter.sol\";",
+        "import \"./src/../lib/Counter.sol\";",
+        "import \"lib/Counter.sol\";",
+    ] {
+        source.with_global_code(import);
+    }
+
+    // Only the first occurrence of each import should be kept, with its original style. Relative
+    // paths are resolved, but are not the same as direct paths, which are resolved differently.
+    assert_eq!(
+        source.deduplicated_global_code(),
+        "import \"forge-std/Test.sol\";\n\
+         import \"forge-std/Test.sol\" as TestLib;\n\
+         import {Test, console} from \"forge-std/Test.sol\";\n\
+         import \"./lib/Counter.sol\";\n\
+         import {Test} from \"forge-std/Test.sol\";\n\
+         import \"lib/Counter.sol\";\n"
+    );
+    assert_eq!(env.contract_source().matches("import \"forge-std/Test.sol\";").count(), 1);
+}
+
+#[test]
+#[serial]
+fn test_undo_snippets() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+
+    // Enter a snippet into each section of the source
+    let inputs = [
+        "uint256 a = 1",
+        "function double(uint256 x) public pure returns (uint256) {\n    return x * 2;\n}",
+        "interface ICounter {\n    function count() external view returns (uint256);\n}",
+    ];
+    for input in inputs {
+        let (new_source, _) =
+            env.session_source.as_ref().unwrap().clone_with_new_line(input.to_string()).unwrap();
+        env.session_source = Some(new_source);
+    }
+    env.write().unwrap();
+
+    // Undoing should remove the most recent snippet, and a redo should restore it
+    let removed = env.pop_snippet().unwrap();
+    assert_eq!(removed[0].code.trim(), inputs[2]);
+    assert!(env.session_source.as_ref().unwrap().global_code.is_empty());
+    assert!(env.dirty);
+    assert_eq!(env.redo_snippet().unwrap(), removed);
+    assert_eq!(env.session_source.as_ref().unwrap().global_code.trim(), inputs[2]);
+    assert!(env.redo_snippet().is_none());
+
+    // Undoing repeatedly should empty the session
+    assert_eq!(env.pop_snippet().unwrap()[0].code.trim(), inputs[2]);
+    assert_eq!(env.pop_snippet().unwrap()[0].code.trim(), inputs[1]);
+    assert_eq!(env.pop_snippet().unwrap()[0].code.trim(), "uint256 a = 1;");
+    assert!(env.pop_snippet().is_none());
+    let source = env.session_source.as_ref().unwrap();
+    assert!(source.global_code.is_empty());
+    assert!(source.top_level_code.is_empty());
+    assert!(source.run_code.is_empty());
+
+    // Autosaving should persist the removal
+    env.autosave().unwrap();
+    env.flush_autosave().unwrap();
+    let loaded = env.load("0").unwrap();
+    assert!(loaded.session_source.as_ref().unwrap().run_code.is_empty());
+    assert!(loaded.session_source.as_ref().unwrap().snippets.is_empty());
+}
+
+#[test]
+#[serial]
+fn test_replace_and_remove_snippets() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    for input in ["uint256 a = 1", "uint256 b = 2", "event Log(uint256 value);"] {
+        let (new_source, _) =
+            env.session_source.as_ref().unwrap().clone_with_new_line(input.to_string()).unwrap();
+        env.session_source = Some(new_source);
+    }
+
+    // Replacing a snippet should swap it in place, even across sections
+    env.replace_snippet(0, "uint256 a = 3").unwrap();
+    env.replace_snippet(2, "function one() public pure returns (uint256) { return 1; }").unwrap();
+    let source = env.session_source.as_ref().unwrap();
+    assert_eq!(source.run_code, "uint256 a = 3;\nuint256 b = 2;\n");
+    assert_eq!(
+        source.top_level_code,
+        "function one() public pure returns (uint256) { return 1; }\n"
+    );
+    assert!(env.dirty);
+
+    // Invalid code and out-of-range indices should be rejected without modifying the source
+    let before = env.session_source.as_ref().unwrap().to_repl_source();
+    assert!(env.replace_snippet(1, "uint256 b = ").is_err());
+    assert!(env.replace_snippet(3, "uint256 c = 3").is_err());
+    assert!(env.remove_snippet(3).is_err());
+    assert_eq!(env.session_source.as_ref().unwrap().to_repl_source(), before);
+
+    // Removing a snippet should only remove that snippet
+    assert_eq!(env.remove_snippet(0).unwrap()[0].code, "uint256 a = 3;\n");
+    let source = env.session_source.as_ref().unwrap();
+    assert_eq!(source.run_code, "uint256 b = 2;\n");
+    assert_eq!(source.snippets.len(), 2);
+}
+
+#[test]
+#[serial]
+fn test_clear_session() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    for input in [
+        "pragma solidity >=0.8.0;",
+        "import {Test} from \"forge-std/Test.sol\";",
+        "uint256 a = 1",
+        "event Log(uint256 value);",
+        "struct Point { uint256 x; uint256 y; }",
+    ] {
+        let (new_source, _) =
+            env.session_source.as_ref().unwrap().clone_with_new_line(input.to_string()).unwrap();
+        env.session_source = Some(new_source);
+    }
+    env.write().unwrap();
+
+    // Clearing with the preamble kept should only keep the directives
+    env.clear_session(true);
+    let source = env.session_source.as_ref().unwrap();
+    assert_eq!(
+        source.global_code,
+        "pragma solidity >=0.8.0;\nimport {Test} from \"forge-std/Test.sol\";\n"
+    );
+    assert!(source.top_level_code.is_empty());
+    assert!(source.run_code.is_empty());
+    assert_eq!(source.snippets.len(), 2);
+
+    // Clearing everything should empty the session, and writing should persist it
+    env.clear_session(false);
+    assert!(env.dirty);
+    env.write().unwrap();
+    let loaded = env.load("0").unwrap();
+    let source = loaded.session_source.as_ref().unwrap();
+    assert!(source.global_code.is_empty());
+    assert!(source.top_level_code.is_empty());
+    assert!(source.run_code.is_empty());
+    assert!(source.snippets.is_empty());
+}
+
+#[test]
+#[serial]
+fn test_conflicting_pragmas() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    for input in ["pragma solidity >= 0.8.0;", "uint256 a = 1", "pragma abicoder v2;"] {
+        let (new_source, _) =
+            env.session_source.as_ref().unwrap().clone_with_new_line(input.to_string()).unwrap();
+        env.session_source = Some(new_source);
+    }
+
+    // Compatible solidity pragmas are merged into the pragma of the rendered source
+    let source = env.session_source.as_mut().unwrap();
+    assert!(source.check_pragmas().is_ok());
+    assert_eq!(source.deduplicated_global_code(), "pragma abicoder v2;\n");
+    assert_eq!(source.to_repl_source().matches("pragma solidity").count(), 1);
+    assert!(source.build().is_ok());
+
+    // Incompatible pragmas should be reported along with the snippets they came from
+    source.with_global_code("pragma solidity ^0.7.0;");
+    let err = source.build().unwrap_err();
+    let conflict = err.downcast_ref::<PragmaConflict>().unwrap();
+    assert_eq!(
+        conflict.pragmas,
+        vec![
+            SolidityPragma {
+                snippet_index: Some(0),
+                requirement: String::from(">= 0.8.0"),
+                compatible: true
+            },
+            SolidityPragma {
+                snippet_index: Some(3),
+                requirement: String::from("^0.7.0"),
+                compatible: false
+            },
+        ]
+    );
+    assert!(err.to_string().contains("snippet 3: pragma solidity ^0.7.0; (incompatible)"));
+}
+
+#[test]
+#[serial]
+fn test_mixed_snippets() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+
+    // Each part of a mixed snippet should land in its own scope
+    for input in [
+        "event Log(uint256 value); uint256 a = 1; emit Log(a);",
+        "struct Point { uint256 x; uint256 y; }\nPoint memory p = Point(1, 2)",
+    ] {
+        let (new_source, do_execute) =
+            env.session_source.as_ref().unwrap().clone_with_new_line(input.to_string()).unwrap();
+        assert!(do_execute);
+        env.session_source = Some(new_source);
+    }
+
+    let source = env.session_source.as_mut().unwrap();
+    assert_eq!(
+        source.top_level_code,
+        "event Log(uint256 value);\nstruct Point { uint256 x; uint256 y; }\n"
+    );
+    assert_eq!(source.run_code, "uint256 a = 1;\nemit Log(a);\nPoint memory p = Point(1, 2);\n");
+    assert!(source.build().is_ok());
+
+    // Snippets with a part that does not fit in any scope should still be rejected
+    assert!(source.clone_with_new_line("event Foo(); uint256 b = ".to_string()).is_err());
+}
+
+#[test]
+#[serial]
+fn test_split_snippets_are_one_unit() {
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    enter_inputs(&mut env, &["uint256 a = 1;", "event Log(uint256 value); emit Log(a);"]);
+
+    // The parts of a split input count as a single snippet
+    let source = env.session_source.as_ref().unwrap();
+    assert_eq!(source.snippets.len(), 3);
+    assert_eq!(source.snippet_count(), 2);
+    assert_eq!(source.snippet_range(1), Some(1..3));
+    assert_eq!(source.snippet_index_of(2), 1);
+    assert_eq!(source.snippet_code(1).unwrap(), "event Log(uint256 value);\nemit Log(a);\n");
+    assert_eq!(source.defined_symbols().last().unwrap().snippet_index, 1);
+
+    // Both parts map to the index of the input within the rendered source
+    let (rendered, map) = source.to_repl_source_with_map();
+    let lines = rendered.lines().collect::<Vec<_>>();
+    let mapped = |index| {
+        map.iter()
+            .filter(|(_, i)| *i == index)
+            .map(|(range, _)| lines[range.start - 1].trim())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(mapped(1), vec!["event Log(uint256 value);", "emit Log(a);"]);
+    assert!(map.iter().all(|(_, index)| *index < 2));
+
+    // Undoing removes every part of the input, and redoing restores all of them
+    let removed = env.pop_snippet().unwrap();
+    assert_eq!(removed.len(), 2);
+    let source = env.session_source.as_ref().unwrap();
+    assert!(source.top_level_code.is_empty());
+    assert_eq!(source.run_code, "uint256 a = 1;\n");
+    assert_eq!(env.redo_snippet().unwrap(), removed);
+    assert_eq!(env.session_source.as_ref().unwrap().snippet_count(), 2);
+
+    // Editing the input replaces all of its parts, splitting the new code again
+    env.replace_snippet(1, "event Other(); emit Other(); emit Other();").unwrap();
+    let source = env.session_source.as_ref().unwrap();
+    assert_eq!(source.snippet_count(), 2);
+    assert_eq!(source.top_level_code, "event Other();\n");
+    assert_eq!(source.run_code, "uint256 a = 1;\nemit Other();\nemit Other();\n");
+
+    // Clearing a scope keeps the rest of the input together
+    let source = env.session_source.as_mut().unwrap();
+    source.drain_top_level_code();
+    assert_eq!(source.snippet_count(), 2);
+    assert_eq!(source.snippet_code(1).unwrap(), "emit Other();\nemit Other();\n");
+}
+
+#[test]
+#[serial]
+fn test_formatted_source() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    for input in
+        ["uint256   a=1", "function  double(uint256 x) public pure returns(uint256){return x*2;}"]
+    {
+        let (new_source, _) =
+            env.session_source.as_ref().unwrap().clone_with_new_line(input.to_string()).unwrap();
+        env.session_source = Some(new_source);
+    }
+
+    // The formatted source should be consistently formatted, and is what gets compiled
+    let formatted = env.formatted_source().unwrap();
+    assert_ne!(formatted, env.contract_source());
+    assert!(formatted.contains("uint256 a = 1;"));
+    assert!(formatted.contains("return x * 2;"));
+    let source = env.session_source.as_mut().unwrap();
+    let input = source.compiler_input();
+    assert!(input.sources.values().any(|source| source.content == formatted));
+    assert!(source.build().is_ok());
+
+    // The raw source should remain available
+    assert!(env.contract_source().contains("uint256   a=1;"));
+}
+
+#[test]
+#[serial]
+fn test_snippet_source_map() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    for input in [
+        "uint256 a = 1",
+        "event Log(uint256 value); emit Log(a);",
+        "function double(uint256 x) public pure returns (uint256) {\n    return x * 2;\n}",
+    ] {
+        let (new_source, _) =
+            env.session_source.as_ref().unwrap().clone_with_new_line(input.to_string()).unwrap();
+        env.session_source = Some(new_source);
+    }
+
+    // Every mapped line range should contain exactly the code of its snippet
+    let (source, map) = env.contract_source_with_map();
+    assert_eq!(source, env.contract_source());
+    let snippets = &env.session_source.as_ref().unwrap().snippets;
+    assert_eq!(map.iter().map(|(_, index)| *index).collect::<Vec<_>>(), vec![1, 3, 0, 2]);
+    let lines = source.lines().collect::<Vec<_>>();
+    for (range, index) in map {
+        let code = lines[range.start - 1..range.end - 1].join("\n");
+        assert_eq!(code.trim(), snippets[index].code.trim());
+    }
+
+    // Compiler errors should point out the snippet they originate from
+    let (mut new_source, _) = env
+        .session_source
+        .as_ref()
+        .unwrap()
+        .clone_with_new_line("uint256 x = y;".to_string())
+        .unwrap();
+    let err = new_source.build().unwrap_err();
+    assert!(err.to_string().contains("DeclarationError: Undeclared identifier."));
+    assert!(err.to_string().contains("--> snippet 4:1:13"));
+}
+
+#[test]
+#[serial]
+fn test_using_directives() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    for input in [
+        "library Math {\n    function double(uint256 x) internal pure returns (uint256) {\n        return x * 2;\n    }\n}",
+        "using Math for uint256;",
+        "uint256 a = uint256(2).double();",
+        "type Price is uint256;\nlibrary PriceLib {\n    function raw(Price p) internal pure returns (uint256) {\n        return Price.unwrap(p);\n    }\n}",
+        "using PriceLib for Price global;",
+    ] {
+        let (new_source, _) =
+            env.session_source.as_ref().unwrap().clone_with_new_line(input.to_string()).unwrap();
+        env.session_source = Some(new_source);
+    }
+
+    // Plain `using` directives apply within the REPL contract, global ones at file scope
+    let source = env.session_source.as_mut().unwrap();
+    assert_eq!(source.top_level_code, "using Math for uint256;\n");
+    assert!(source.global_code.ends_with("using PriceLib for Price global;\n"));
+    assert!(source.build().is_ok());
+}
+
+#[test]
+#[serial]
+fn test_constructor_snippets() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    for input in [
+        "uint256 immutable x;",
+        "constructor() { x = 42; }",
+        "uint256 public y;",
+        "constructor() { y = 7; }",
+        "uint256 z = x + y;",
+    ] {
+        let (new_source, _) =
+            env.session_source.as_ref().unwrap().clone_with_new_line(input.to_string()).unwrap();
+        env.session_source = Some(new_source);
+    }
+
+    // Immutables and constructors belong to the REPL contract
+    let source = env.session_source.as_mut().unwrap();
+    assert!(source.global_code.is_empty());
+    assert!(source.top_level_code.starts_with("uint256 immutable x;\n"));
+
+    // Constructors are merged into one, in order of entry
+    let rendered = source.to_repl_source();
+    assert_eq!(rendered.matches("constructor(").count(), 1);
+    assert!(rendered.find("x = 42;").unwrap() < rendered.find("y = 7;").unwrap());
+    assert!(source.build().is_ok());
+
+    // The merged constructor bodies should still map to their snippets
+    let (rendered, map) = source.to_repl_source_with_map();
+    let lines = rendered.lines().collect::<Vec<_>>();
+    let (range, _) = map.iter().find(|(_, index)| *index == 3).unwrap();
+    assert_eq!(lines[range.start - 1].trim(), "y = 7;");
+}
+
+#[test]
+#[serial]
+fn test_license_and_pragma() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+
+    // The header defaults to an unlicensed source with a pragma derived from the solc version
+    let rendered = env.session_source.as_ref().unwrap().to_repl_source();
+    assert!(rendered.starts_with("// SPDX-License-Identifier: UNLICENSED\npragma solidity ^"));
+
+    // Both the license and the pragma can be overridden
+    assert!(env.set_license(String::from("not a license")).is_err());
+    env.set_license(String::from("MIT")).unwrap();
+    env.set_pragma(Some(String::from(">=0.8.0"))).unwrap();
+    assert!(env.dirty);
+    let rendered = env.session_source.as_ref().unwrap().to_repl_source();
+    assert!(rendered.starts_with("// SPDX-License-Identifier: MIT\npragma solidity >=0.8.0;"));
+
+    // Overrides that the session's solc version does not satisfy are rejected
+    assert!(env.set_pragma(Some(String::from("^0.7.0"))).is_err());
+    assert_eq!(env.session_source.as_ref().unwrap().pragma.as_deref(), Some(">=0.8.0"));
+
+    // The license and pragma survive a round trip through the cache
+    env.write().unwrap();
+    let loaded = env.load("0").unwrap();
+    let loaded_source = loaded.session_source.as_ref().unwrap();
+    assert_eq!(loaded_source.license, "MIT");
+    assert_eq!(loaded_source.pragma.as_deref(), Some(">=0.8.0"));
+
+    // An explicit pragma within a snippet still takes precedence over the override
+    let source = env.session_source.as_mut().unwrap();
+    source.with_global_code("pragma solidity >=0.8.0 <0.9.0;");
+    assert_eq!(source.pragma_requirement(), ">=0.8.0 <0.9.0");
+    assert_eq!(source.to_repl_source().matches("pragma solidity").count(), 1);
+    assert!(source.build().is_ok());
+}
+
+#[test]
+#[serial]
+fn test_reject_malformed_snippets() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let env = new_session(tmp.path());
+
+    // Well-formed snippets are assigned a scope
+    let snippet = SolSnippet::try_parse("uint256 x = 1").unwrap();
+    assert_eq!(snippet.fragment, ParseTreeFragment::Function);
+    assert_eq!(snippet.code, "uint256 x = 1;\n");
+    let snippet = SolSnippet::try_parse("event Transfer(uint256 amount);").unwrap();
+    assert_eq!(snippet.fragment, ParseTreeFragment::Contract);
+
+    // Malformed snippets are reported along with the offending span
+    let err = SolSnippet::try_parse("uint256 x = ;").unwrap_err();
+    assert!(err.diagnostics.iter().any(|diagnostic| diagnostic.span == (12..13)));
+    assert!(err
+        .to_string()
+        .contains(&format!(" --> 1:13\n  |\n1 | uint256 x = ;\n  | {}^", " ".repeat(12))));
+
+    // ...and never make it into the session
+    let source = env.session_source.as_ref().unwrap();
+    for input in ["uint256 x = ;", "function foo() {", "contract {}"] {
+        let err = source.clone_with_new_line(input.to_string()).unwrap_err();
+        assert!(err.downcast_ref::<ParseDiagnostics>().is_some());
+    }
+    assert!(source.snippets.is_empty());
+    assert!(source.parse().is_ok());
+}
+
+#[test]
+#[serial]
+fn test_assembly_snippets() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    let inputs = [
+        "uint256 a = 1;",
+        "assembly {\n    mstore(0x80, a)\n}",
+        "unchecked { a += 1; }",
+        "uint256 b = a;",
+    ];
+    for input in inputs {
+        let (new_source, do_execute) =
+            env.session_source.as_ref().unwrap().clone_with_new_line(input.to_string()).unwrap();
+        assert!(do_execute);
+        env.session_source = Some(new_source);
+    }
+
+    // Assembly and unchecked blocks belong to the "run()" function and keep their raw text
+    let source = env.session_source.as_mut().unwrap();
+    assert!(source.snippets.iter().all(|snippet| snippet.fragment == ParseTreeFragment::Function));
+    assert!(source.global_code.is_empty());
+    assert!(source.top_level_code.is_empty());
+
+    // The rendered source should contain the snippets in order of entry
+    let rendered = source.to_repl_source();
+    let positions = inputs.iter().map(|input| rendered.find(input).unwrap()).collect::<Vec<_>>();
+    assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(source.build().is_ok());
+}
+
+#[test]
+#[serial]
+fn test_variable_definition_scopes() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    for (input, fragment) in [
+        ("uint256 constant FEE = 100;", ParseTreeFragment::Contract),
+        ("address immutable DEPLOYER = msg.sender;", ParseTreeFragment::Contract),
+        ("uint x = 1;", ParseTreeFragment::Function),
+        ("uint256 total = x + FEE;", ParseTreeFragment::Function),
+    ] {
+        assert_eq!(SolSnippet::try_parse(input).unwrap().fragment, fragment);
+        let (new_source, _) =
+            env.session_source.as_ref().unwrap().clone_with_new_line(input.to_string()).unwrap();
+        env.session_source = Some(new_source);
+    }
+
+    // Constant and immutable variables are state variables of the REPL contract, while plain
+    // variable declarations are statements of the "run()" function
+    let source = env.session_source.as_mut().unwrap();
+    assert_eq!(
+        source.top_level_code,
+        "uint256 constant FEE = 100;\naddress immutable DEPLOYER = msg.sender;\n"
+    );
+    assert_eq!(source.run_code, "uint x = 1;\nuint256 total = x + FEE;\n");
+    assert!(source.build().is_ok());
+}
+
+#[test]
+#[serial]
+fn test_contract_name_collisions() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+
+    // Defining a contract named after the REPL contract renames the REPL contract
+    for input in ["contract REPL { uint256 public value = 1; }", "uint256 x = 1;"] {
+        let (new_source, _) =
+            env.session_source.as_ref().unwrap().clone_with_new_line(input.to_string()).unwrap();
+        env.session_source = Some(new_source);
+    }
+    let source = env.session_source.as_mut().unwrap();
+    assert_eq!(source.contract_name, "REPL_1");
+    assert!(source.to_repl_source().contains("contract REPL_1 "));
+    let output = source.build().unwrap();
+    assert_eq!(output.intermediate.repl_contract_name, "REPL_1");
+    assert_eq!(output.intermediate.run_func_body().unwrap().len(), 1);
+
+    // The REPL contract can be renamed, as long as the name does not collide
+    assert!(env.set_contract_name("1nvalid").is_err());
+    assert_eq!(env.set_contract_name("Scratch").unwrap(), "Scratch");
+    assert_eq!(env.set_contract_name("REPL").unwrap(), "REPL_1");
+    assert_eq!(env.set_contract_name("Scratch").unwrap(), "Scratch");
+
+    // Imported symbols are taken into account as well
+    let source = env.session_source.as_mut().unwrap();
+    source.with_global_code("import {Test as Scratch} from \"forge-std/Test.sol\";");
+    assert_eq!(source.contract_name, "Scratch_1");
+    assert!(source.global_contract_names().contains("Scratch"));
+
+    // The name is persisted with the session
+    env.write().unwrap();
+    let loaded = env.load("0").unwrap();
+    assert_eq!(loaded.session_source.unwrap().contract_name, "Scratch_1");
+}
+
+#[test]
+#[serial]
+fn test_numbered_source() {
+    yansi::Paint::disable();
+
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    for input in ["event Ping(uint256 value);", "uint256 a = 1;", "emit Ping(a);"] {
+        let (new_source, _) =
+            env.session_source.as_ref().unwrap().clone_with_new_line(input.to_string()).unwrap();
+        env.session_source = Some(new_source);
+    }
+
+    // Every line of the compiled source is numbered
+    let numbered = env.numbered_source();
+    let formatted = env.formatted_source().unwrap();
+    assert_eq!(numbered.lines().count(), formatted.lines().count());
+
+    // Entered lines are labeled with the index of their snippet, generated lines are not
+    for (line, formatted_line) in numbered.lines().zip(formatted.lines()) {
+        let (prefix, code) = line.split_once(" │ ").unwrap();
+        assert_eq!(code.trim_end(), formatted_line.trim_end());
+        let label = prefix.split_whitespace().nth(1);
+        let expected = ["event Ping", "uint256 a", "emit Ping"]
+            .iter()
+            .position(|snippet| code.trim_start().starts_with(snippet))
+            .map(|index| index.to_string());
+        assert_eq!(label.map(str::to_string), expected, "{line}");
+    }
+    yansi::Paint::enable();
+}
+
+#[test]
+#[serial]
+fn test_interface_from_abi() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    let abi = r#"[
+        {"type":"function","name":"transfer","stateMutability":"nonpayable","inputs":[{"name":"to","type":"address"},{"name":"amount","type":"uint256"}],"outputs":[{"name":"","type":"bool"}]},
+        {"type":"function","name":"transfer","stateMutability":"nonpayable","inputs":[{"name":"to","type":"address"}],"outputs":[]},
+        {"type":"function","name":"balanceOf","stateMutability":"view","inputs":[{"name":"owner","type":"address"}],"outputs":[{"name":"","type":"uint256"}]},
+        {"type":"function","name":"deposit","stateMutability":"payable","inputs":[],"outputs":[]},
+        {"type":"function","name":"submit","stateMutability":"nonpayable","inputs":[{"name":"order","type":"tuple","internalType":"struct Token.Order","components":[{"name":"maker","type":"address","internalType":"address"},{"name":"amount","type":"uint256","internalType":"uint256"}]}],"outputs":[]},
+        {"type":"event","name":"Transfer","anonymous":false,"inputs":[{"name":"from","type":"address","indexed":true},{"name":"amount","type":"uint256","indexed":false}]}
+    ]"#;
+
+    // Invalid names and ABIs are rejected
+    assert!(env.add_interface_from_abi("1Token", abi).is_err());
+    assert!(env.add_interface_from_abi("IToken", "[{").is_err());
+
+    // Both raw ABIs and artifacts are accepted
+    env.add_interface_from_abi("IToken", abi).unwrap();
+    env.add_interface_from_abi("IArtifact", &format!("{{\"abi\": {abi}}}")).unwrap();
+    assert!(env.dirty);
+
+    let source = env.session_source.as_mut().unwrap();
+    assert_eq!(source.snippets.len(), 2);
+    assert!(source.snippets.iter().all(|snippet| snippet.fragment == ParseTreeFragment::Source));
+    let interface = &source.snippets[0].code;
+    assert!(interface.starts_with("interface IToken {"));
+    assert_eq!(interface.matches("function transfer(").count(), 2);
+    let line_of = |needle: &str| interface.lines().find(|line| line.contains(needle)).unwrap();
+    assert!(line_of("function balanceOf(address owner)").contains(" view "));
+    assert!(line_of("function deposit()").contains(" payable"));
+    assert!(interface.contains("struct Order {"));
+
+    // Subsequent snippets can use the interface
+    let (mut new_source, _) =
+        source.clone_with_new_line("IToken token = IToken(address(0x1234));".to_string()).unwrap();
+    assert!(new_source.build().is_ok());
+}
+
+#[test]
+#[serial]
+fn test_preserve_comments() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    for input in [
+        "/// @notice Doubles a value\n/// @param x The value to double\nfunction double(uint256 x) public pure returns (uint256) {\n    return x * 2;\n}",
+        "event Doubled(uint256 value); // emitted by run\nuint256 a = double(2); // four\nemit Doubled(a);\n// done",
+    ] {
+        let (new_source, _) =
+            env.session_source.as_ref().unwrap().clone_with_new_line(input.to_string()).unwrap();
+        env.session_source = Some(new_source);
+    }
+
+    // Comments stay attached to the parts they were entered with
+    let source = env.session_source.as_mut().unwrap();
+    assert_eq!(source.snippets[1].code, "event Doubled(uint256 value); // emitted by run\n");
+    assert_eq!(source.snippets[2].code, "uint256 a = double(2); // four\n");
+    assert_eq!(source.snippets[3].code, "emit Doubled(a);\n// done\n");
+
+    // Natspec comments should be rendered right above their function
+    for rendered in [source.to_repl_source(), source.to_formatted_repl_source()] {
+        let lines = rendered.lines().map(str::trim).collect::<Vec<_>>();
+        let function = lines.iter().position(|line| line.starts_with("function double(")).unwrap();
+        assert_eq!(lines[function - 2], "/// @notice Doubles a value");
+        assert_eq!(lines[function - 1], "/// @param x The value to double");
+        assert!(rendered.contains("uint256 a = double(2); // four"));
+    }
+    assert!(source.build().is_ok());
+}
+
+#[test]
+#[serial]
+fn test_function_definition_scopes() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    for (input, fragment) in [
+        ("uint256 public counter = 5;", ParseTreeFragment::Contract),
+        (
+            "function bump() public returns (uint256) {\n    counter += 1;\n    return counter;\n}",
+            ParseTreeFragment::Contract,
+        ),
+        (
+            "function sender() view returns (address) { return msg.sender; }",
+            ParseTreeFragment::Contract,
+        ),
+        ("uint256 b = bump();", ParseTreeFragment::Function),
+    ] {
+        assert_eq!(SolSnippet::try_parse(input).unwrap().fragment, fragment);
+        let (new_source, _) =
+            env.session_source.as_ref().unwrap().clone_with_new_line(input.to_string()).unwrap();
+        env.session_source = Some(new_source);
+    }
+
+    // Function definitions are members of the REPL contract and can access its state
+    let source = env.session_source.as_mut().unwrap();
+    assert!(source.global_code.is_empty());
+    assert!(source.top_level_code.contains("function bump()"));
+    assert!(source.build().is_ok());
+
+    // Free functions can still be defined at file level explicitly
+    let free_function = "function triple(uint256 x) pure returns (uint256) { return x * 3; }";
+    assert!(source.clone_with_global_code("uint256 x = ;").is_err());
+    let new_source = source.clone_with_global_code(free_function).unwrap();
+    assert_eq!(new_source.global_code, format!("{free_function}\n"));
+    assert_eq!(new_source.snippets.last().unwrap().fragment, ParseTreeFragment::Source);
+    let (mut new_source, _) =
+        new_source.clone_with_new_line("uint256 c = triple(b);".to_string()).unwrap();
+    assert!(new_source.build().is_ok());
+}
+
+#[test]
+#[serial]
+fn test_snippet_serialization_round_trip() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    let inputs = [
+        r#"string quoted = "say \"hi\"\\n";"#,
+        "string unicode = unicode\"héllo wörld ⚒️\";",
+        "function greet(string memory name) public pure returns (string memory) {\n    // Escapes: \\ \" \t\n    return string.concat(\"gm \", name);\n}",
+    ];
+    for input in inputs {
+        let (new_source, _) =
+            env.session_source.as_ref().unwrap().clone_with_new_line(input.to_string()).unwrap();
+        env.session_source = Some(new_source);
+    }
+
+    // Each snippet survives serialization as-is
+    let snippets = env.session_source.as_ref().unwrap().snippets.clone();
+    for snippet in &snippets {
+        let json = serde_json::to_string(snippet).unwrap();
+        assert_eq!(&serde_json::from_str::<SolSnippet>(&json).unwrap(), snippet);
+    }
+
+    // ...and so does the session caching them
+    env.write().unwrap();
+    let loaded = env.load("0").unwrap();
+    let loaded_source = loaded.session_source.as_ref().unwrap();
+    assert_eq!(loaded_source.snippets, snippets);
+    assert_eq!(
+        loaded_source.to_repl_source(),
+        env.session_source.as_ref().unwrap().to_repl_source()
+    );
+    for input in inputs {
+        assert!(loaded_source.to_repl_source().contains(input));
+    }
+}
+
+#[test]
+fn test_snippet_kinds() {
+    let cases = [
+        ("pragma solidity ^0.8.0;", SnippetKind::Pragma),
+        ("pragma abicoder v2;", SnippetKind::Pragma),
+        ("import \"forge-std/Test.sol\";", SnippetKind::Import),
+        ("import {Vm} from \"forge-std/Vm.sol\";", SnippetKind::Import),
+        ("contract Foo { uint256 a; }", SnippetKind::ContractDef),
+        ("interface IFoo { function foo() external; }", SnippetKind::ContractDef),
+        (
+            "library Lib { function one() internal pure returns (uint256) { return 1; } }",
+            SnippetKind::ContractDef,
+        ),
+        ("struct Point { uint256 x; uint256 y; }", SnippetKind::TypeDef),
+        ("enum Color { Red, Green }", SnippetKind::TypeDef),
+        ("event Transfer(uint256 amount);", SnippetKind::TypeDef),
+        ("error Unauthorized();", SnippetKind::TypeDef),
+        ("type Price is uint128;", SnippetKind::TypeDef),
+        (
+            "function add(uint256 a, uint256 b) public pure returns (uint256) { return a + b; }",
+            SnippetKind::FunctionDef,
+        ),
+        ("modifier onlyOwner() { _; }", SnippetKind::FunctionDef),
+        ("constructor() {}", SnippetKind::FunctionDef),
+        ("uint256 constant MAX = 10;", SnippetKind::StateVariable),
+        ("address immutable owner = msg.sender;", SnippetKind::StateVariable),
+        ("using Lib for uint256;", SnippetKind::Using),
+        ("uint256 a = 1;", SnippetKind::Statement),
+        ("a = 2;", SnippetKind::Statement),
+        ("a += 2;", SnippetKind::Statement),
+        ("a++;", SnippetKind::Statement),
+        ("delete a;", SnippetKind::Statement),
+        ("emit Transfer(1);", SnippetKind::Statement),
+        ("if (true) { a = 1; }", SnippetKind::Statement),
+        ("assembly { let x := 1 }", SnippetKind::Statement),
+        ("a", SnippetKind::Expression),
+        ("a + b", SnippetKind::Expression),
+        ("keccak256(abi.encode(1))", SnippetKind::Expression),
+        ("address(this).balance;", SnippetKind::Expression),
+        ("a; b;", SnippetKind::Expression),
+        ("a; b = 1;", SnippetKind::Statement),
+        ("struct A { uint256 x; } event B();", SnippetKind::TypeDef),
+        ("event A(); function b() public {}", SnippetKind::Mixed),
+        ("import \"a.sol\"; contract C {}", SnippetKind::Mixed),
+    ];
+    for (input, kind) in cases {
+        assert_eq!(SolSnippet::try_parse(input).unwrap().kind(), kind, "{input}");
+    }
+
+    // Comment-only code has no kind of its own, and comments do not affect the kind of a snippet
+    assert_eq!(
+        SnippetKind::classify(ParseTreeFragment::Function, "// a\n"),
+        SnippetKind::Statement
+    );
+    assert_eq!(
+        SolSnippet::try_parse("a; // the value of a").unwrap().kind(),
+        SnippetKind::Expression
+    );
+
+    // Only statements and expressions are executed
+    for kind in [SnippetKind::Statement, SnippetKind::Expression] {
+        assert!(kind.is_executable());
+    }
+    for kind in [
+        SnippetKind::Pragma,
+        SnippetKind::Import,
+        SnippetKind::ContractDef,
+        SnippetKind::TypeDef,
+        SnippetKind::FunctionDef,
+        SnippetKind::StateVariable,
+        SnippetKind::Using,
+        SnippetKind::Mixed,
+    ] {
+        assert!(!kind.is_executable());
+    }
+}
+
+#[test]
+#[serial]
+fn test_snippet_kinds_of_split_lines() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let env = new_session(tmp.path());
+
+    // Each part of a line that spans several scopes is classified individually
+    let source = env.session_source.as_ref().unwrap();
+    let (new_source, do_execute) =
+        source.clone_with_new_line("event Log(uint256 x); emit Log(1);".to_string()).unwrap();
+    let kinds = new_source.snippets.iter().map(SolSnippet::kind).collect::<Vec<_>>();
+    assert_eq!(kinds, [SnippetKind::TypeDef, SnippetKind::Statement]);
+    assert!(do_execute);
+
+    // Definitions are only accumulated
+    let (_, do_execute) =
+        new_source.clone_with_new_line("function f() public {}".to_string()).unwrap();
+    assert!(!do_execute);
+}
+
+#[test]
+fn test_parse_diagnostic_positions() {
+    // Errors are reported with the line and column of the offending token
+    let err = SolSnippet::try_parse("if (true) {\n    uint256 x = ;\n}").unwrap_err();
+    let diagnostic = err.diagnostics.first().unwrap();
+    assert_eq!(err.line_column(diagnostic.span.start), (2, 17));
+    let rendered = err.to_string();
+    assert!(rendered.starts_with("error: "));
+    assert!(rendered.contains(" --> 2:17\n  |\n2 |     uint256 x = ;\n  |                 ^"));
+
+    // Columns are counted in characters rather than bytes
+    let err = SolSnippet::try_parse("string s = unicode\"⚒️\" +;").unwrap_err();
+    let diagnostic = err.diagnostics.first().unwrap();
+    let (line, column) = err.line_column(diagnostic.span.start);
+    assert_eq!(line, 1);
+    assert_eq!(column, err.code[..diagnostic.span.start].chars().count() + 1);
+
+    // Every diagnostic of an input is rendered
+    let err = SolSnippet::try_parse(
+        "contract C { function f() { uint x = ; } function g() { uint y = ; } }",
+    )
+    .unwrap_err();
+    assert_eq!(err.to_string().matches("error: ").count(), err.diagnostics.len());
+}
+
+#[test]
+#[serial]
+fn test_multi_line_input() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let env = new_session(tmp.path());
+
+    // Brackets within string literals and comments do not affect completeness
+    assert!(input_is_complete("uint256 a = 1;"));
+    assert!(input_is_complete("string s = \"{(\";"));
+    assert!(input_is_complete("uint256 a = 1; // {"));
+    assert!(!input_is_complete("contract C {"));
+    assert!(!input_is_complete("uint256[] memory a = new uint256[]("));
+    assert!(!input_is_complete("/* {} "));
+    assert!(!input_is_complete("string s = \"abc"));
+    assert!(!input_is_complete("function f() public {\n    string memory s = \"}\";"));
+
+    // Lines are accumulated until the input is complete, resulting in a single snippet
+    let lines = [
+        "contract Counter {",
+        "    uint256 public count; // {",
+        "    /* } */",
+        "    function increment() public {",
+        "        count += 1;",
+        "    }",
+        "}",
+    ];
+    let mut pending = String::new();
+    for (i, line) in lines.iter().enumerate() {
+        if !pending.is_empty() {
+            pending.push('\n');
+        }
+        pending.push_str(line);
+        assert_eq!(input_is_complete(&pending), i == lines.len() - 1, "{pending}");
+    }
+    let (source, _) = env.session_source.as_ref().unwrap().clone_with_new_line(pending).unwrap();
+    assert_eq!(source.snippets.len(), 1);
+    assert_eq!(source.snippets[0].kind(), SnippetKind::ContractDef);
+    assert!(source.parse().is_ok());
+}
+
+#[test]
+#[serial]
+fn test_stripped_snippet_diagnostics() {
+    // A block that was terminated regardless is entered without its trailing semicolon, so
+    // that errors within it are located at the offending code
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    enter_inputs(&mut env, &["uint256 a = 1;", "if (a > 0) { a = b; };"]);
+    let snippet = &env.session_source.as_ref().unwrap().snippets[1];
+    assert_eq!(snippet.wrapping, SnippetWrapping::Stripped);
+    assert_eq!(snippet.code, "if (a > 0) { a = b; }\n");
+
+    let err = env.compile().unwrap_err();
+    let diagnostics = err.downcast_ref::<CompilerDiagnostics>().unwrap();
+    assert!(diagnostics.errors[0].message.contains("Undeclared identifier"));
+    assert_eq!(diagnostics.locations, vec![Some(SnippetSpan { index: 1, span: 17..18 })]);
+    assert!(err.to_string().contains("--> snippet 1:1:18"));
+}
+
+#[test]
+fn test_snippet_wrapping() {
+    // Input that parses as-is is not wrapped
+    let snippet = SolSnippet::try_parse("uint256 a = 1;").unwrap();
+    assert_eq!(snippet.wrapping, SnippetWrapping::None);
+    assert!(!snippet.expects_result());
+
+    // Statements without a trailing semicolon are terminated
+    let snippet = SolSnippet::try_parse("uint256 a = 1").unwrap();
+    assert_eq!(snippet.wrapping, SnippetWrapping::Terminated);
+    assert_eq!(snippet.code, "uint256 a = 1;\n");
+    assert!(!snippet.expects_result());
+
+    // ...and so are bare expressions, whose value is expected
+    for input in ["1 + 2", "token.balanceOf(addr)", "a"] {
+        let snippet = SolSnippet::try_parse(input).unwrap();
+        assert_eq!(snippet.wrapping, SnippetWrapping::Terminated, "{input}");
+        assert_eq!(snippet.fragment, ParseTreeFragment::Function);
+        assert!(snippet.expects_result(), "{input}");
+    }
+
+    // Definitions never expect a result
+    let snippet = SolSnippet::try_parse("event Log(uint256 x)").unwrap();
+    assert_eq!(snippet.wrapping, SnippetWrapping::Terminated);
+    assert!(!snippet.expects_result());
+
+    // Blocks that were terminated regardless are stored without their trailing semicolons
+    let snippet = SolSnippet::try_parse("if (a > 0) { a = b; };").unwrap();
+    assert_eq!(snippet.wrapping, SnippetWrapping::Stripped);
+    assert_eq!(snippet.fragment, ParseTreeFragment::Function);
+    assert_eq!(snippet.code, "if (a > 0) { a = b; }\n");
+
+    // Wrapping is recorded along with the snippet
+    let snippet = SolSnippet::try_parse("1 + 2").unwrap();
+    let json = serde_json::to_string(&snippet).unwrap();
+    assert_eq!(serde_json::from_str::<SolSnippet>(&json).unwrap(), snippet);
+
+    // Input that does not parse in any wrapping reports the diagnostics of the raw input
+    let err = SolSnippet::try_parse("1 +").unwrap_err();
+    assert_eq!(err.code, "1 +");
+    assert!(err.diagnostics.iter().all(|diagnostic| diagnostic.span.end <= err.code.len()));
+}
+
+#[test]
+#[serial]
+fn test_redefinitions() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let env = new_session(tmp.path());
+
+    // Items are named by their signature if they can be overloaded
+    let snippet = SolSnippet::try_parse(
+        "function f(uint256 a, string memory b) public {}\nevent E(address indexed who);\nuint256 x;",
+    )
+    .unwrap();
+    assert_eq!(snippet.definitions(), ["f(uint256,string)", "E(address)", "x"]);
+    assert!(SolSnippet::try_parse("uint256 y = 1;").unwrap().definitions().is_empty());
+
+    let append = |source: &SessionSource, input: &str, policy| {
+        let (mut new_source, _) = source.clone_with_new_line(input.to_string())?;
+        let redefinitions = new_source.apply_redefinitions(source.snippets.len(), policy)?;
+        Ok::<_, eyre::Report>((new_source, redefinitions))
+    };
+    let mut source = env.session_source.clone().unwrap();
+    for input in [
+        "function double(uint256 x) public pure returns (uint256) { return x + x; }",
+        "struct Point { uint256 x; }",
+        "function double(int256 x) public pure returns (int256) { return x + x; }",
+    ] {
+        let (new_source, redefinitions) =
+            append(&source, input, RedefinitionPolicy::Replace).unwrap();
+        assert!(redefinitions.is_empty());
+        source = new_source;
+    }
+
+    // Redefinitions replace the earlier definition in place
+    let redefined = "function double(uint256 x) public pure returns (uint256) { return x * 2; }";
+    let (replaced, redefinitions) =
+        append(&source, redefined, RedefinitionPolicy::Replace).unwrap();
+    assert_eq!(redefinitions, [Redefinition { name: String::from("double(uint256)"), index: 0 }]);
+    assert_eq!(replaced.snippets.len(), 3);
+    assert_eq!(replaced.snippets[0].code, format!("{redefined}\n"));
+    assert_eq!(replaced.top_level_code.matches("function double(uint256").count(), 1);
+    assert!(replaced.parse().is_ok());
+
+    // ...while statements entered along with them stay in order of entry
+    let (replaced, _) =
+        append(&source, "struct Point { uint256 y; } uint256 a = 1;", RedefinitionPolicy::Replace)
+            .unwrap();
+    assert_eq!(replaced.snippets[1].code, "struct Point { uint256 y; }\n");
+    assert_eq!(replaced.snippets.last().unwrap().code, "uint256 a = 1;\n");
+
+    // Rejected redefinitions name the earlier snippet
+    let err = append(&source, redefined, RedefinitionPolicy::Reject).unwrap_err();
+    assert_eq!(err.to_string(), "`double(uint256)` is already defined in snippet 0");
+
+    // Snippets defining more than the redefined items can not be replaced as a whole
+    let (source, _) =
+        append(&source, "event A(); event B();", RedefinitionPolicy::Replace).unwrap();
+    let err = append(&source, "event A();", RedefinitionPolicy::Replace).unwrap_err();
+    assert!(err.to_string().contains("`A()` is already defined in snippet 3"));
+    assert!(err.to_string().contains("!edit 3"));
+}
+
+#[test]
+#[serial]
+fn test_snippet_provenance() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+
+    // Snippets record when and how they were entered
+    let before = std::time::SystemTime::now();
+    let (new_source, _) = env
+        .session_source
+        .as_ref()
+        .unwrap()
+        .clone_with_new_line("uint256 a = 1".to_string())
+        .unwrap();
+    env.session_source = Some(new_source);
+    let snippet = env.session_source.as_ref().unwrap().snippets[0].clone();
+    assert_eq!(snippet.origin, SnippetOrigin::Interactive);
+    assert!(snippet.entered_at >= before);
+
+    let mut dated = snippet.clone();
+    dated.entered_at = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1709388060);
+    assert_eq!(dated.provenance(), "entered 2024-03-02 14:01 (interactive)");
+    assert_eq!(
+        dated.clone().with_origin(SnippetOrigin::Import).provenance(),
+        "entered 2024-03-02 14:01 (import)"
+    );
+
+    // Snippets cached before provenance was recorded still load
+    let mut legacy = serde_json::to_value(&snippet).unwrap();
+    legacy.as_object_mut().unwrap().remove("entered_at");
+    legacy.as_object_mut().unwrap().remove("origin");
+    let legacy: SolSnippet = serde_json::from_value(legacy).unwrap();
+    assert_eq!(legacy.origin, SnippetOrigin::Interactive);
+    assert_eq!(legacy.provenance(), "entered at an unknown time (interactive)");
+
+    // Provenance survives caching, and is listed along with each snippet
+    let abi = r#"[{"type":"function","name":"deposit","stateMutability":"payable","inputs":[],"outputs":[]}]"#;
+    env.add_interface_from_abi("IVault", abi).unwrap();
+    env.write().unwrap();
+    let loaded = env.load("0").unwrap();
+    let snippets = &loaded.session_source.as_ref().unwrap().snippets;
+    assert_eq!(snippets[0].entered_at, snippet.entered_at);
+    assert_eq!(snippets[1].origin, SnippetOrigin::Import);
+    let list = loaded.snippet_list();
+    let lines = list.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2);
+    assert!(
+        lines[0].starts_with("0: entered ") && lines[0].ends_with("(interactive) - uint256 a = 1;")
+    );
+    assert!(lines[1].starts_with("1: entered ") && lines[1].contains("(import)"));
+
+    // ...as well as in the header of exported scripts
+    let script = loaded.session_source.as_ref().unwrap().to_script_source();
+    assert!(script.contains(&format!("// Snippet 0: {}", snippets[0].provenance())));
+    assert!(script.contains(&format!("// Snippet 1: {}", snippets[1].provenance())));
+}
+
+#[test]
+#[serial]
+fn test_defined_symbols() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    let inputs = [
+        "contract Counter {\n    struct Snapshot { uint256 count; }\n    enum Mode { Up, Down }\n    uint256 public count;\n    event Incremented(uint256 count);\n    error Overflow();\n    function increment() public { count += 1; }\n}",
+        "type Price is uint128;",
+        "struct Point { uint256 x; uint256 y; }",
+        "event Log(uint256 value); error Unauthorized();",
+        "uint256 constant MAX = 10;",
+        "function double(uint256 x) public pure returns (uint256) { return x * 2; }",
+        "uint256 a = double(2); (uint256 b, bool c) = (1, true);",
+        "a + b",
+    ];
+    for input in inputs {
+        let (new_source, _) =
+            env.session_source.as_ref().unwrap().clone_with_new_line(input.to_string()).unwrap();
+        env.session_source = Some(new_source);
+    }
+
+    let symbols = env
+        .defined_symbols()
+        .into_iter()
+        .map(|symbol| (symbol.name, symbol.kind, symbol.snippet_index))
+        .collect::<Vec<_>>();
+    let expected = [
+        ("Counter", SymbolKind::Contract, 0),
+        ("Counter.Snapshot", SymbolKind::Struct, 0),
+        ("Counter.Mode", SymbolKind::Enum, 0),
+        ("Counter.count", SymbolKind::StateVariable, 0),
+        ("Counter.Incremented", SymbolKind::Event, 0),
+        ("Counter.Overflow", SymbolKind::Error, 0),
+        ("Counter.increment", SymbolKind::Function, 0),
+        ("Price", SymbolKind::TypeDefinition, 1),
+        ("Point", SymbolKind::Struct, 2),
+        ("Log", SymbolKind::Event, 3),
+        ("Unauthorized", SymbolKind::Error, 3),
+        ("MAX", SymbolKind::StateVariable, 4),
+        ("double", SymbolKind::Function, 5),
+        ("a", SymbolKind::Variable, 6),
+        ("b", SymbolKind::Variable, 6),
+        ("c", SymbolKind::Variable, 6),
+    ]
+    .map(|(name, kind, index)| (name.to_string(), kind, index));
+    assert_eq!(symbols, expected);
+
+    // Sessions without a source have no symbols
+    env.session_source = None;
+    assert!(env.defined_symbols().is_empty());
+}
+
+#[test]
+#[serial]
+fn test_compile_session() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    for input in [
+        "function double(uint256 x) public pure returns (uint256) { return x * 2; }",
+        "uint256 unused = 1;",
+    ] {
+        let (new_source, _) =
+            env.session_source.as_ref().unwrap().clone_with_new_line(input.to_string()).unwrap();
+        env.session_source = Some(new_source);
+    }
+
+    // The compiled REPL contract exposes its ABI, bytecode, and warnings
+    let result = env.compile().unwrap();
+    let abi = result.abi.unwrap();
+    assert!(abi.function("double").is_ok());
+    assert!(abi.function("run").is_ok());
+    assert!(!result.bytecode.unwrap().is_empty());
+    assert!(!result.deployed_bytecode.unwrap().is_empty());
+    assert!(result
+        .warnings
+        .iter()
+        .any(|warning| warning.message.contains("Unused local variable")));
+
+    // Compiler errors are returned as solc's diagnostics
+    let (new_source, _) = env
+        .session_source
+        .as_ref()
+        .unwrap()
+        .clone_with_new_line("uint256 y = z;".to_string())
+        .unwrap();
+    let snippets = env.session_source.replace(new_source).unwrap().snippets;
+    let err = env.compile().unwrap_err();
+    let diagnostics = err.downcast_ref::<CompilerDiagnostics>().unwrap();
+    assert_eq!(diagnostics.errors.len(), 1);
+    assert!(diagnostics.errors[0].message.contains("Undeclared identifier"));
+    assert_eq!(diagnostics.locations, vec![Some(SnippetSpan { index: 2, span: 12..13 })]);
+    assert!(err.to_string().contains("--> snippet 2:1:13"));
+
+    // Compiling does not drop the offending snippet; That is left to the caller
+    assert_eq!(env.session_source.as_ref().unwrap().snippets.len(), snippets.len() + 1);
+}
+
+#[test]
+#[serial]
+fn test_build_reuses_unchanged_output() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+
+    // Compile with a copy of solc, so that the compiler can be removed between builds
+    let source = env.session_source.as_mut().unwrap();
+    let solc_path = tmp.path().join("solc");
+    std::fs::copy(&source.solc.solc, &solc_path).unwrap();
+    source.solc.solc = solc_path.clone();
+    let (mut source, _) = source.clone_with_new_line("uint256 a = 1;".to_string()).unwrap();
+    let output = source.build().unwrap();
+
+    // Building an unchanged source again does not touch the compiler
+    std::fs::remove_file(&solc_path).unwrap();
+    assert_eq!(source.build().unwrap(), output);
+    assert_eq!(source.output_checksum, Some(source.compiler_input_checksum().unwrap()));
+
+    // Any change to the compiler input invalidates the previous output
+    source.compiler_settings.via_ir = !source.compiler_settings.via_ir;
+    assert!(source.build().is_err());
+    source.compiler_settings.via_ir = !source.compiler_settings.via_ir;
+    assert_eq!(source.build().unwrap(), output);
+    let (mut new_source, _) = source.clone_with_new_line("uint256 b = a;".to_string()).unwrap();
+    assert!(new_source.build().is_err());
+}
+
+#[test]
+#[serial]
+fn test_compiler_errors_point_at_snippets() {
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    enter_inputs(
+        &mut env,
+        &[
+            "uint256 a = 1;",
+            "string memory s = \"hello\";",
+            "uint256 c = a;",
+            "string memory t = s; uint256 b = s;",
+        ],
+    );
+
+    // The type error within snippet 3 is rendered against that snippet's own code, at the
+    // second `s` of the line rather than the first one
+    let err = env.compile().unwrap_err();
+    let diagnostics = err.downcast_ref::<CompilerDiagnostics>().unwrap();
+    assert_eq!(diagnostics.locations, vec![Some(SnippetSpan { index: 3, span: 33..34 })]);
+    let description = &diagnostics.descriptions[0];
+    assert!(description.starts_with("error: TypeError: "));
+    assert!(description.contains(&format!(
+        " --> snippet 3:1:34\n  |\n1 | string memory t = s; uint256 b = s;\n  |{}^\n",
+        " ".repeat(34)
+    )));
+    assert!(!description.contains("ReplContract.sol"));
+}
+
+#[test]
+#[serial]
+fn test_optimizer_settings() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    let (new_source, _) = env
+        .session_source
+        .as_ref()
+        .unwrap()
+        .clone_with_new_line("uint256 a = 1 + 2;".to_string())
+        .unwrap();
+    env.session_source = Some(new_source);
+    env.set_optimizer(None).unwrap();
+    let unoptimized = env.compile().unwrap().bytecode.unwrap();
+
+    // Enabling the optimizer recompiles the unchanged source into different bytecode
+    env.set_optimizer(Some(1000)).unwrap();
+    let settings = &env.session_source.as_ref().unwrap().compiler_settings;
+    assert!(settings.optimizer);
+    assert_eq!(settings.runs, 1000);
+    let optimized = env.compile().unwrap().bytecode.unwrap();
+    assert_ne!(optimized, unoptimized);
+
+    // The optimizer settings persist through the cache
+    env.write().unwrap();
+    let mut loaded = env.load(env.id.as_deref().unwrap()).unwrap();
+    let settings = &loaded.session_source.as_ref().unwrap().compiler_settings;
+    assert!(settings.optimizer);
+    assert_eq!(settings.runs, 1000);
+    assert_eq!(loaded.compile().unwrap().bytecode.unwrap(), optimized);
+
+    // Disabling the optimizer again restores the unoptimized bytecode
+    loaded.set_optimizer(None).unwrap();
+    assert_eq!(loaded.compile().unwrap().bytecode.unwrap(), unoptimized);
+}
+
+#[test]
+#[serial]
+fn test_evm_version_settings() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+
+    // Unknown EVM versions are rejected with a list of the valid versions
+    let err = env.set_evm_version("frontier").unwrap_err().to_string();
+    assert!(err.starts_with("Unknown EVM version \"frontier\"; Valid versions are: homestead"));
+    assert!(err.contains("london"));
+    assert_eq!(evm_versions().first(), Some(&"homestead"));
+
+    // EVM versions are matched regardless of their case, and apply to both compilation and
+    // execution
+    env.set_evm_version("London").unwrap();
+    let source = env.session_source.as_ref().unwrap();
+    assert_eq!(source.compiler_settings.evm_version.as_deref(), Some("london"));
+    assert_eq!(source.config.foundry_config.evm_version.to_string(), "london");
+    assert!(env.compile().is_ok());
+
+    // The EVM version persists through the cache
+    env.write().unwrap();
+    let loaded = env.load(env.id.as_deref().unwrap()).unwrap();
+    let source = loaded.session_source.as_ref().unwrap();
+    assert_eq!(source.compiler_settings.evm_version.as_deref(), Some("london"));
+    assert_eq!(source.config.foundry_config.evm_version.to_string(), "london");
+}
+
+#[test]
+#[serial]
+fn test_via_ir_settings() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    let (new_source, _) = env
+        .session_source
+        .as_ref()
+        .unwrap()
+        .clone_with_new_line("uint256 a = 1;".to_string())
+        .unwrap();
+    env.session_source = Some(new_source);
+    env.set_via_ir(false).unwrap();
+    let legacy = env.compile().unwrap().bytecode.unwrap();
+
+    // Compiling via IR produces different bytecode for the unchanged source
+    env.set_via_ir(true).unwrap();
+    assert!(env.session_source.as_ref().unwrap().compiler_settings.via_ir);
+    assert_ne!(env.compile().unwrap().bytecode.unwrap(), legacy);
+
+    // Compiler errors mention the pipeline the source was compiled with
+    let source = env.session_source.as_ref().unwrap();
+    let (mut new_source, _) = source.clone_with_new_line("uint256 b = c;".to_string()).unwrap();
+    let err = new_source.build().unwrap_err();
+    assert!(err.to_string().starts_with("Compiler errors (via IR):\n"));
+    new_source.compiler_settings.via_ir = false;
+    let err = new_source.build().unwrap_err();
+    assert!(err.to_string().starts_with("Compiler errors (legacy pipeline):\n"));
+
+    // The setting persists through the cache
+    env.write().unwrap();
+    let loaded = env.load(env.id.as_deref().unwrap()).unwrap();
+    assert!(loaded.session_source.as_ref().unwrap().compiler_settings.via_ir);
+}
+
+#[test]
+#[serial]
+fn test_select_solc_version_from_pragmas() {
+    // Find the oldest installed solc version
+    let oldest = Solc::installed_versions()
+        .into_iter()
+        .filter_map(|version| semver::Version::parse(&version.to_string()).ok())
+        .filter(|version| find_or_install_solc(&version.to_string(), true).is_ok())
+        .min()
+        .unwrap();
+
+    for requirement in
+        [format!("={oldest}"), format!("^{oldest}"), format!(">={oldest} <={oldest}")]
+    {
+        let tmp = TempDir::new().unwrap();
+        let env = new_session(tmp.path());
+        let mut source = env.session_source.unwrap();
+        source.config.foundry_config.offline = true;
+        let (mut source, _) =
+            source.clone_with_new_line(format!("pragma solidity {requirement};")).unwrap();
+
+        // The source is switched to an installed version that satisfies the pragma, if its
+        // current version does not
+        let current = source.solc.version().unwrap();
+        let selected = source.select_solc_version().unwrap();
+        let version = source.solc.version().unwrap();
+        assert_eq!(selected.is_some(), current != version);
+        assert!(source.check_pragmas().is_ok());
+        if requirement.starts_with('=') {
+            assert_eq!(version, oldest);
+        }
+        assert!(source.build().is_ok());
+    }
+
+    // Without a satisfying installed version, the conflicting pragmas are reported
+    let tmp = TempDir::new().unwrap();
+    let mut source = new_session(tmp.path()).session_source.unwrap();
+    source.config.foundry_config.offline = true;
+    let (mut source, _) =
+        source.clone_with_new_line("pragma solidity ^0.1.0;".to_string()).unwrap();
+    let err = source.select_solc_version().unwrap_err().to_string();
+    assert!(err.starts_with("No installed solc version satisfies the session's pragmas;"));
+    assert!(err.contains("^0.1.0"));
+}
+
+#[test]
+#[serial]
+fn test_artifact_hashes() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    for input in [
+        "function double(uint256 x) public pure returns (uint256) { return x * 2; }",
+        "error Unauthorized(address caller);",
+        "event Doubled(uint256 indexed x, uint256 result);",
+        "function check() public { if (msg.sender != address(0)) revert Unauthorized(msg.sender); emit Doubled(1, 2); }",
+    ] {
+        let (new_source, _) =
+            env.session_source.as_ref().unwrap().clone_with_new_line(input.to_string()).unwrap();
+        env.session_source = Some(new_source);
+    }
+
+    // The artifact is only available once the session has been compiled
+    assert!(env.artifact().is_none());
+    let result = env.compile().unwrap();
+    assert_eq!(env.artifact(), Some(result.clone()));
+
+    // Selectors and topics are derived from the ABI
+    let hash = |signature: &str, len: usize| hex::encode(&keccak256(signature)[..len]);
+    let hashes = result.hashes();
+    assert_eq!(
+        hashes.functions,
+        vec![
+            ("check()".to_string(), hash("check()", 4)),
+            ("double(uint256)".to_string(), hash("double(uint256)", 4)),
+            ("run()".to_string(), hash("run()", 4)),
+        ]
+    );
+    assert_eq!(
+        hashes.errors,
+        vec![("Unauthorized(address)".to_string(), hash("Unauthorized(address)", 4))]
+    );
+    assert_eq!(
+        hashes.events,
+        vec![("Doubled(uint256,uint256)".to_string(), hash("Doubled(uint256,uint256)", 32))]
+    );
+
+    // Hashes are printed without decoration, so that they can be copied
+    let listing = hashes.to_string();
+    assert!(listing.starts_with("Functions:\n0x"));
+    assert!(listing
+        .contains(&format!("\n0x{}: Unauthorized(address)", hash("Unauthorized(address)", 4))));
+    assert!(listing.contains("\n\nEvents:\n0x"));
+}
+
+#[test]
+#[serial]
+fn test_remapped_imports() {
+    // Create the cache directory along with a library to import
+    let tmp = TempDir::new().unwrap();
+    let lib = tmp.path().join("lib").join("math");
+    std::fs::create_dir_all(&lib).unwrap();
+    std::fs::write(
+        lib.join("Math.sol"),
+        "library Math { function double(uint256 x) internal pure returns (uint256) { return x * 2; } }",
+    )
+    .unwrap();
+    let mut env = new_session(tmp.path());
+
+    // Imports that can not be resolved list the active remappings
+    let (source, _) = env
+        .session_source
+        .as_ref()
+        .unwrap()
+        .clone_with_new_line("import \"math/Math.sol\";".to_string())
+        .unwrap();
+    env.session_source = Some(source);
+    let err = env.compile().unwrap_err();
+    let diagnostics = err.downcast_ref::<CompilerDiagnostics>().unwrap();
+    assert!(diagnostics.remappings.is_some());
+    assert!(err.to_string().contains("No remappings are active"));
+
+    // Imports are resolved with the session's remappings
+    let remapping = env.add_remapping("math/", lib.clone()).unwrap();
+    assert_eq!(remapping.path, format!("{}/", lib.canonicalize().unwrap().display()));
+    let (source, _) = env
+        .session_source
+        .as_ref()
+        .unwrap()
+        .clone_with_new_line("uint256 a = Math.double(2);".to_string())
+        .unwrap();
+    env.session_source = Some(source);
+    assert!(env.compile().is_ok());
+
+    // Remappings are persisted with the session, and targets must exist
+    env.write().unwrap();
+    let loaded = env.load(env.id.as_ref().unwrap()).unwrap();
+    assert_eq!(loaded.session_source.unwrap().remappings, vec![remapping]);
+    assert!(env.add_remapping("missing/", tmp.path().join("missing")).is_err());
+}
+
+#[test]
+#[serial]
+fn test_session_from_foundry_root() {
+    // Create a foundry project with a contract to import
+    let tmp = TempDir::new().unwrap();
+    let root = tmp.path().join("project");
+    std::fs::create_dir_all(root.join("src")).unwrap();
+    std::fs::write(
+        root.join("foundry.toml"),
+        "[profile.default]\noptimizer = true\noptimizer_runs = 1234\n",
+    )
+    .unwrap();
+    std::fs::write(
+        root.join("src").join("Counter.sol"),
+        "contract Counter { uint256 public count; function increment() public { count++; } }",
+    )
+    .unwrap();
+
+    // The session inherits the project's configuration
+    std::env::set_var(CACHE_DIR_ENV, tmp.path().join("cache"));
+    let env = ChiselSession::from_foundry_root(&root);
+    std::env::remove_var(CACHE_DIR_ENV);
+    let mut env = env.unwrap();
+    let source = env.session_source.as_ref().unwrap();
+    assert_eq!(source.project_root, Some(root.canonicalize().unwrap()));
+    assert!(source.compiler_settings.optimizer);
+    assert_eq!(source.compiler_settings.runs, 1234);
+
+    // Imports are resolved relative to the project's root
+    let (source, _) =
+        source.clone_with_new_line("import \"src/Counter.sol\";".to_string()).unwrap();
+    let (source, _) = source.clone_with_new_line("Counter c = new Counter();".to_string()).unwrap();
+    env.session_source = Some(source);
+    assert!(env.compile().is_ok());
+
+    // The project's configuration is loaded again along with the session
+    env.write().unwrap();
+    std::fs::write(root.join("foundry.toml"), "[profile.default]\noptimizer_runs = 42\n").unwrap();
+    let loaded = env.load(env.id.as_ref().unwrap()).unwrap();
+    assert_eq!(loaded.session_source.unwrap().config.foundry_config.optimizer_runs, 42);
+
+    // Directories without a foundry.toml are not projects
+    assert!(ChiselSession::from_foundry_root(tmp.path()).is_err());
+}
+
+#[test]
+#[serial]
+fn test_artifact_linked_libraries() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    for input in [
+        "library PublicLib { function cube(uint256 x) public pure returns (uint256) { return x * x * x; } }",
+        "uint256 z = PublicLib.cube(3);",
+    ] {
+        let (new_source, _) =
+            env.session_source.as_ref().unwrap().clone_with_new_line(input.to_string()).unwrap();
+        env.session_source = Some(new_source);
+    }
+
+    // The REPL contract is linked against the library's deployment address
+    let result = env.compile().unwrap();
+    assert_eq!(result.libraries.len(), 1);
+    let library = &result.libraries[0];
+    assert_eq!(library.name, "PublicLib");
+    assert_eq!(
+        library.address,
+        ethers::utils::get_contract_address(ethers::types::Address::zero(), 0)
+    );
+    let bytecode = hex::encode(result.bytecode.unwrap());
+    assert!(bytecode.contains(&hex::encode(library.address)));
+    assert!(!bytecode.contains("__$"));
+    assert!(library.to_string().starts_with(&format!("{}:PublicLib => 0x", library.file)));
+}
+
+#[test]
+#[serial]
+fn test_compile_stats() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    let (source, _) = env
+        .session_source
+        .as_ref()
+        .unwrap()
+        .clone_with_new_line("uint256 a = 1;".to_string())
+        .unwrap();
+    env.session_source = Some(source);
+
+    // A fresh build invokes solc on the REPL contract and the `Vm` interface
+    let stats = env.compile().unwrap().stats.unwrap();
+    let version = env.session_source.as_ref().unwrap().solc.version().unwrap();
+    assert!(!stats.cache_hit);
+    assert_eq!(stats.sources, 2);
+    assert_eq!(stats.solc_version, Some(version));
+    assert!(stats.solc > std::time::Duration::ZERO);
+    assert!(stats.total >= stats.checksum + stats.solc + stats.parsing);
+
+    // Building an unchanged source again hits the cache
+    let cached = env.compile().unwrap().stats.unwrap();
+    assert!(cached.cache_hit);
+    assert_eq!(cached.sources, stats.sources);
+    assert_eq!(cached.solc_version, stats.solc_version);
+    assert_eq!(cached.solc, std::time::Duration::ZERO);
+    assert!(cached.to_string().starts_with("Cache: hit\nSources: 2\n"));
+    assert_eq!(env.session_source.unwrap().compile_stats, Some(cached));
+}
+
+#[test]
+#[serial]
+fn test_validate_generated_source() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let env = new_session(tmp.path());
+    let mut source = env.session_source.unwrap();
+    source.with_run_code("uint256 a = 1;");
+    assert!(source.validate().is_ok());
+
+    // Snippets are appended verbatim, so a statement without its semicolon breaks the
+    // generated contract. The error is noticed at the generated code following the snippet.
+    let mut broken = source.clone();
+    broken.with_top_level_code("uint256 b = 2");
+    let err = broken.build().unwrap_err();
+    let diagnostics = err.downcast_ref::<GeneratedSourceDiagnostics>().unwrap();
+    assert_eq!(diagnostics.snippets(), vec![1]);
+    assert!(err.to_string().starts_with("Snippet 1 breaks the generated REPL contract:\nerror: "));
+    assert!(err.to_string().contains("--> snippet 1:1:"));
+
+    // The generated contract is never handed to solc
+    assert!(broken.compile_stats.is_none());
+
+    // A snippet closing the `run()` function early breaks the code surrounding it as well
+    let mut broken = source.clone();
+    broken.with_run_code("uint256 c = 3;");
+    broken.with_run_code("}");
+    let err = broken.validate().unwrap_err();
+    let diagnostics = err.downcast_ref::<GeneratedSourceDiagnostics>().unwrap();
+    assert!(diagnostics.snippets().contains(&2));
+}
+
+#[test]
+#[serial]
+fn test_gas_estimates() {
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    for input in [
+        "function sum(uint256 n) public pure returns (uint256 s) { for (uint256 i; i < n; i++) s += i; }",
+        "contract Counter { uint256 public count; function increment() public { count += 1; } }",
+        "uint256 a = 1;",
+    ] {
+        let (new_source, _) =
+            env.session_source.as_ref().unwrap().clone_with_new_line(input.to_string()).unwrap();
+        env.session_source = Some(new_source);
+    }
+
+    // The REPL contract is listed first, and unbounded functions are estimated as infinite
+    let gas = env.compile().unwrap().gas;
+    assert_eq!(gas.contracts[0].0, "REPL");
+    let repl = gas.contract("REPL").unwrap();
+    assert!(matches!(repl.creation, Some(GasEstimate::Gas(_))));
+    assert!(repl.functions.contains(&(String::from("sum(uint256)"), GasEstimate::Infinite)));
+    assert!(matches!(
+        gas.contract("Counter").unwrap().functions.iter().find(|(sig, _)| sig == "increment()"),
+        Some((_, GasEstimate::Gas(_)))
+    ));
+    assert!(gas.contract("Vm").is_none());
+    let report = gas.to_string();
+    assert!(report.starts_with("REPL:\n  creation"));
+    assert!(report
+        .lines()
+        .any(|line| line.starts_with("  sum(uint256) ") && line.ends_with("  infinite")));
+    assert!(report.contains("\n\nCounter:\n"));
+
+    // Changing the optimizer settings recompiles the estimates
+    env.set_optimizer(Some(200)).unwrap();
+    let result = env.compile().unwrap();
+    assert!(!result.stats.unwrap().cache_hit);
+    assert_eq!(result.gas.contracts.len(), gas.contracts.len());
+}
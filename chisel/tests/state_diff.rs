@@ -0,0 +1,68 @@
+mod common;
+
+use common::new_session;
+use ethers::types::U256;
+use serial_test::serial;
+use tempfile::TempDir;
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+async fn test_state_diff() {
+    use ethers::types::H256;
+
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let env = new_session(tmp.path());
+    let mut source = env.session_source.unwrap();
+    let err = source.state_diff().await.unwrap_err();
+    assert!(err.to_string().contains("no statements to diff"), "{err}");
+    for line in [
+        "contract Counter { uint256 public count; function increment() external { count++; } }",
+        "uint256 public total;",
+        "Counter c = new Counter();",
+        "c.increment();",
+    ] {
+        source = source.clone_with_new_line(line.to_string()).unwrap().0;
+    }
+
+    // Only the write of the last statement is listed, not the deployment before it
+    let diff = source.state_diff().await.unwrap().state_diff.unwrap();
+    let counter = diff
+        .accounts
+        .iter()
+        .find(|account| account.address != diff.repl && !account.storage.is_empty())
+        .unwrap();
+    assert_eq!(counter.nonce.0, counter.nonce.1);
+    assert_eq!(counter.storage.len(), 1);
+    assert_eq!(counter.storage[0].slot, U256::zero());
+    assert_eq!((counter.storage[0].before, counter.storage[0].after), (0.into(), 1.into()));
+    assert!(counter.storage[0].written && counter.is_changed());
+    assert!(counter.storage[0].variable.is_none());
+    assert_eq!(diff.access_list.0.len(), 1);
+    assert_eq!(diff.access_list.0[0].address, counter.address);
+    assert_eq!(diff.access_list.0[0].storage_keys, vec![H256::zero()]);
+
+    // Slots of the REPL contract are named after their state variable, and creations are listed
+    let source = source.clone_with_new_line("total = c.count() + 1;".to_string()).unwrap().0;
+    let diff = source.state_diff().await.unwrap().state_diff.unwrap();
+    let repl = diff.accounts.iter().find(|account| account.address == diff.repl).unwrap();
+    assert_eq!(repl.storage[0].variable.as_deref(), Some("total"));
+    assert_eq!(repl.storage[0].after, 2.into());
+    let counter = diff.accounts.iter().find(|account| account.address != diff.repl).unwrap();
+    assert!(!counter.is_changed());
+    assert_eq!(counter.storage[0].after, 1.into());
+    assert!(!counter.storage[0].written);
+
+    yansi::Paint::disable();
+    let table = diff.table(false);
+    assert!(table.contains("(total): 0x"), "{table}");
+    assert!(table.contains("(read)") && table.contains("Access list:"), "{table}");
+    let json = serde_json::to_value(&diff).unwrap();
+    assert_eq!(json["accounts"].as_array().unwrap().len(), diff.accounts.len());
+
+    let source = source.clone_with_new_line("Counter d = new Counter();".to_string()).unwrap().0;
+    let diff = source.state_diff().await.unwrap().state_diff.unwrap();
+    let created = diff.accounts.iter().find(|account| account.address != diff.repl).unwrap();
+    assert_ne!(created.code_hash.0, created.code_hash.1);
+    assert!(diff.table(false).contains("code: deployed"));
+}
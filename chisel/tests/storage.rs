@@ -0,0 +1,58 @@
+mod common;
+
+use chisel::storage::{layout_table, MovedVariable};
+use common::new_session;
+use serial_test::serial;
+use tempfile::TempDir;
+
+#[test]
+#[serial]
+fn test_storage_layout() {
+    yansi::Paint::disable();
+
+    // Create the cache directory
+    let tmp = TempDir::new().unwrap();
+    let mut env = new_session(tmp.path());
+    let mut source = env.session_source.take().unwrap();
+    for line in ["uint128 a;", "uint128 b;", "mapping(address => uint256) balances;"] {
+        source = source.clone_with_new_line(line.to_string()).unwrap().0;
+    }
+    env.session_source = Some(source);
+
+    // Packed variables share a slot, and mappings are listed at their base slot
+    let layout = env.storage_layout().unwrap();
+    let locations = layout
+        .storage
+        .iter()
+        .map(|variable| (variable.label.as_str(), variable.slot.as_str(), variable.offset))
+        .collect::<Vec<_>>();
+    assert_eq!(locations, vec![("a", "0", 0), ("b", "0", 16), ("balances", "1", 0)]);
+    let table = layout_table(&layout);
+    assert!(table.starts_with("Slot"), "{table}");
+    assert!(table.contains("1 (base)  0       mapping(address => uint256)  balances"), "{table}");
+    assert!(env.session_source.as_ref().unwrap().moved_variables.is_empty());
+
+    // Removing a variable refreshes the layout, and collects the variables that moved
+    env.session_source.as_mut().unwrap().remove_snippet(0).unwrap();
+    let layout = env.storage_layout().unwrap();
+    assert_eq!(layout.storage.len(), 2);
+    assert_eq!(
+        env.session_source.as_ref().unwrap().moved_variables,
+        vec![MovedVariable {
+            label: String::from("b"),
+            previous: (String::from("0"), 16),
+            current: (String::from("0"), 0),
+        }]
+    );
+
+    // Clones of the source compare their layout to the one of the source they were cloned from
+    let mut source = env
+        .session_source
+        .as_ref()
+        .unwrap()
+        .clone_with_new_line("uint256 c;".to_string())
+        .unwrap()
+        .0;
+    source.storage_layout().unwrap();
+    assert!(source.moved_variables.is_empty());
+}
@@ -104,6 +104,21 @@ impl ExecutorBuilder {
         self
     }
 
+    /// Track the state that is accessed with the chisel state inspector, see
+    /// [ExecutorBuilder::with_chisel_state]
+    #[must_use]
+    pub fn with_chisel_access_tracking(mut self, track_access: bool) -> Self {
+        self.inspector_config.chisel_access_tracking = track_access;
+        self
+    }
+
+    /// Halt execution once a wall-clock deadline has passed
+    #[must_use]
+    pub fn with_deadline(mut self, deadline: std::time::Instant) -> Self {
+        self.inspector_config.deadline = Some(deadline);
+        self
+    }
+
     /// Builds the executor as configured.
     pub fn build(self, db: Backend) -> Executor {
         let gas_limit = self.gas_limit.unwrap_or(self.env.block.gas_limit);
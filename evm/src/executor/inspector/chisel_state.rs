@@ -1,4 +1,9 @@
-use revm::{Database, Inspector};
+use bytes::Bytes;
+use ethers::{
+    abi::ethereum_types::BigEndianHash,
+    types::{Address, H256, U256},
+};
+use revm::{opcode, Database, Inspector};
 
 /// An inspector for Chisel
 #[derive(Default)]
@@ -7,11 +12,19 @@ pub struct ChiselState {
     pub final_pc: usize,
     /// The final state of the REPL contract call
     pub state: Option<(revm::Stack, revm::Memory, revm::Return)>,
+    /// The return data buffer of the REPL contract call at the final instruction
+    pub return_data: Option<Bytes>,
+    /// Whether to track the state that instructions access, see [ChiselState::last_access]
+    pub track_access: bool,
+    /// The account, and optionally the storage slot, that the last instruction which reads
+    /// state accessed. If the database fails to serve it, the call halts at that instruction.
+    /// Only tracked if [ChiselState::track_access] is set, i.e. on a fork.
+    pub last_access: Option<(Address, Option<U256>)>,
 }
 
 impl ChiselState {
-    pub fn new(final_pc: usize) -> Self {
-        Self { final_pc, state: None }
+    pub fn new(final_pc: usize, track_access: bool) -> Self {
+        Self { final_pc, state: None, return_data: None, track_access, last_access: None }
     }
 }
 
@@ -19,6 +32,37 @@ impl<DB> Inspector<DB> for ChiselState
 where
     DB: Database,
 {
+    fn step(
+        &mut self,
+        interp: &mut revm::Interpreter,
+        _: &mut revm::EVMData<'_, DB>,
+        _: bool,
+    ) -> revm::Return {
+        if !self.track_access {
+            return revm::Return::Continue
+        }
+        let address = |word: U256| -> Address { H256::from_uint(&word).into() };
+        let op = interp.contract.bytecode.bytecode()[interp.program_counter()];
+        let access = match op {
+            opcode::SLOAD | opcode::SSTORE => {
+                interp.stack().peek(0).ok().map(|slot| (interp.contract.address, Some(slot)))
+            }
+            opcode::EXTCODECOPY |
+            opcode::EXTCODEHASH |
+            opcode::EXTCODESIZE |
+            opcode::BALANCE |
+            opcode::SELFDESTRUCT => interp.stack().peek(0).ok().map(|word| (address(word), None)),
+            opcode::DELEGATECALL | opcode::CALL | opcode::STATICCALL | opcode::CALLCODE => {
+                interp.stack().peek(1).ok().map(|word| (address(word), None))
+            }
+            _ => None,
+        };
+        if access.is_some() {
+            self.last_access = access;
+        }
+        revm::Return::Continue
+    }
+
     fn step_end(
         &mut self,
         interp: &mut revm::Interpreter,
@@ -28,7 +72,8 @@ where
     ) -> revm::Return {
         // If we are at the final pc of the REPL contract execution, set the state.
         if self.final_pc == interp.program_counter() - 1 {
-            self.state = Some((interp.stack().clone(), interp.memory.clone(), eval))
+            self.state = Some((interp.stack().clone(), interp.memory.clone(), eval));
+            self.return_data = Some(interp.return_data_buffer.clone());
         }
         // Pass on [revm::Return] from arguments
         eval
@@ -4,7 +4,7 @@ mod utils;
 mod logs;
 
 pub use logs::LogCollector;
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, collections::BTreeSet, rc::Rc, time::Instant};
 
 mod access_list;
 pub use access_list::AccessListTracer;
@@ -27,6 +27,12 @@ pub use cheatcodes::{Cheatcodes, CheatsConfig, DEFAULT_CREATE2_DEPLOYER};
 mod chisel_state;
 pub use chisel_state::ChiselState;
 
+mod opcode_counter;
+pub use opcode_counter::{OpcodeCounter, OpcodeCounts};
+
+mod timeout;
+pub use timeout::Timeout;
+
 use ethers::types::U256;
 
 use revm::{BlockEnv, GasInspector};
@@ -67,6 +73,16 @@ pub struct InspectorStackConfig {
     /// If the inspector is enabled, Some(final_pc)
     /// If not, None
     pub chisel_state: Option<usize>,
+    /// Whether the chisel state inspector tracks the state that instructions access, i.e. to
+    /// name what a forked network failed to serve
+    pub chisel_access_tracking: bool,
+    /// The opcode counter inspector.
+    ///
+    /// If the inspector is enabled, Some(the program counters that counting starts at)
+    /// If not, None
+    pub opcode_counter: Option<BTreeSet<usize>>,
+    /// The wall-clock deadline that execution is halted at, if any
+    pub deadline: Option<Instant>,
 }
 
 impl InspectorStackConfig {
@@ -86,10 +102,15 @@ impl InspectorStackConfig {
         if self.tracing {
             stack.tracer = Some(Tracer::default());
         }
-        if self.debugger {
+        if self.debugger || self.opcode_counter.is_some() {
             let gas_inspector = Rc::new(RefCell::new(GasInspector::default()));
             stack.gas = Some(gas_inspector.clone());
-            stack.debugger = Some(Debugger::new(gas_inspector));
+            if self.debugger {
+                stack.debugger = Some(Debugger::new(gas_inspector.clone()));
+            }
+            if let Some(start_pcs) = &self.opcode_counter {
+                stack.opcode_counter = Some(OpcodeCounter::new(start_pcs.clone(), gas_inspector));
+            }
         }
         stack.fuzzer = self.fuzzer.clone();
 
@@ -102,7 +123,11 @@ impl InspectorStackConfig {
         }
 
         if let Some(final_pc) = self.chisel_state {
-            stack.chisel_state = Some(ChiselState::new(final_pc));
+            stack.chisel_state = Some(ChiselState::new(final_pc, self.chisel_access_tracking));
+        }
+
+        if let Some(deadline) = self.deadline {
+            stack.timeout = Some(Timeout::new(deadline));
         }
         stack
     }
@@ -0,0 +1,186 @@
+use super::utils::gas_used;
+use crate::executor::CHEATCODE_ADDRESS;
+use bytes::Bytes;
+use ethers::types::Address;
+use revm::{
+    CallInputs, CreateInputs, Database, EVMData, Gas, GasInspector, Inspector, Interpreter, Return,
+};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+};
+
+/// The instructions counted by an [OpcodeCounter]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OpcodeCounts {
+    /// The number of times that each opcode was executed, and the gas it used excluding memory
+    /// expansion and the gas used by the code it called, by opcode
+    pub opcodes: BTreeMap<u8, (u64, u64)>,
+    /// The gas used to expand memory
+    pub memory_expansion: u64,
+    /// The number of calls to cheatcodes
+    pub cheatcodes: u64,
+    /// The gas used by the counted instructions, including the code they called
+    pub total: u64,
+    /// The number of calls and contract creations that the outermost call frame made before
+    /// counting started
+    pub calls_before: u64,
+}
+
+/// The instruction most recently executed within a call frame, whose gas is known once the frame
+/// executes its next instruction
+#[derive(Debug, Default)]
+struct Frame {
+    /// The opcode of the most recent instruction, along with the gas used by the frame and the
+    /// size of its memory before it was executed
+    last: Option<(u8, u64, usize)>,
+    /// The gas attributed to the frame and the frames it called
+    attributed: u64,
+    /// The gas attributed to the frames called by the most recent instruction
+    called: u64,
+}
+
+/// An inspector that counts the instructions of a call and the gas they use, from one of the
+/// given program counters of its outermost call frame on, without recording them.
+///
+/// The gas of an instruction is the difference between the gas used by its call frame before
+/// and after it, less the cost of expanding memory and the gas used by the frames it called.
+/// The final instruction of a call frame, i.e. `RETURN`, is counted without any gas.
+#[derive(Debug)]
+pub struct OpcodeCounter {
+    /// The program counters of the outermost call frame that counting starts at
+    pub start_pcs: BTreeSet<usize>,
+    /// The instructions counted so far
+    pub counts: OpcodeCounts,
+    /// The depth of the outermost call frame
+    depth: Option<u64>,
+    /// Whether counting started
+    counting: bool,
+    /// The call frames entered since counting started, outermost first
+    frames: Vec<Frame>,
+    gas_inspector: Rc<RefCell<GasInspector>>,
+}
+
+impl OpcodeCounter {
+    pub fn new(start_pcs: BTreeSet<usize>, gas_inspector: Rc<RefCell<GasInspector>>) -> Self {
+        Self {
+            start_pcs,
+            counts: Default::default(),
+            depth: None,
+            counting: false,
+            frames: Vec::new(),
+            gas_inspector,
+        }
+    }
+
+    /// Passes the gas used by the innermost frame up to its caller
+    fn exit_frame(&mut self) {
+        let frame = self.frames.pop().unwrap_or_default();
+        if let Some(caller) = self.frames.last_mut() {
+            caller.called += frame.attributed;
+        }
+    }
+
+    /// Finishes counting, returning the [OpcodeCounts]
+    pub fn finish(mut self) -> OpcodeCounts {
+        while self.frames.len() > 1 {
+            self.exit_frame();
+        }
+        self.counts.total =
+            self.frames.first().map(|frame| frame.attributed + frame.called).unwrap_or_default();
+        self.counts
+    }
+}
+
+impl<DB> Inspector<DB> for OpcodeCounter
+where
+    DB: Database,
+{
+    fn step(
+        &mut self,
+        interpreter: &mut Interpreter,
+        data: &mut EVMData<'_, DB>,
+        _is_static: bool,
+    ) -> Return {
+        let depth = data.journaled_state.depth();
+        let outermost = *self.depth.get_or_insert(depth);
+        let pc = interpreter.program_counter();
+        if !self.counting {
+            if depth != outermost || !self.start_pcs.contains(&pc) {
+                return Return::Continue
+            }
+            self.counting = true;
+        }
+
+        // Returning from called frames passes the gas that they used up to their caller
+        let depth = depth.saturating_sub(outermost) as usize;
+        while self.frames.len() > depth + 1 {
+            self.exit_frame();
+        }
+        while self.frames.len() < depth + 1 {
+            self.frames.push(Frame::default());
+        }
+
+        // The gas of the frame's previous instruction is known now
+        let total_gas_used = gas_used(
+            data.env.cfg.spec_id,
+            interpreter.gas.limit() - self.gas_inspector.borrow().gas_remaining(),
+            interpreter.gas.refunded() as u64,
+        );
+        let memory = interpreter.memory.data().len();
+        let frame = self.frames.last_mut().expect("a frame was pushed");
+        if let Some((op, previous_gas, previous_memory)) = frame.last.take() {
+            let spent = total_gas_used.saturating_sub(previous_gas);
+            let expansion = memory_cost(memory).saturating_sub(memory_cost(previous_memory));
+            let expansion = expansion.min(spent);
+            let own = spent - expansion - frame.called.min(spent - expansion);
+
+            self.counts.opcodes.entry(op).or_default().1 += own;
+            self.counts.memory_expansion += expansion;
+            frame.attributed += own + expansion + frame.called;
+            frame.called = 0;
+        }
+
+        let op = interpreter.contract.bytecode.bytecode()[pc];
+        self.counts.opcodes.entry(op).or_default().0 += 1;
+        frame.last = Some((op, total_gas_used, memory));
+
+        Return::Continue
+    }
+
+    fn call(
+        &mut self,
+        data: &mut EVMData<'_, DB>,
+        call: &mut CallInputs,
+        _is_static: bool,
+    ) -> (Return, Gas, Bytes) {
+        if self.counting {
+            if call.contract == CHEATCODE_ADDRESS {
+                self.counts.cheatcodes += 1;
+            }
+        } else if self.depth == Some(data.journaled_state.depth()) {
+            self.counts.calls_before += 1;
+        }
+
+        (Return::Continue, Gas::new(call.gas_limit), Bytes::new())
+    }
+
+    fn create(
+        &mut self,
+        data: &mut EVMData<'_, DB>,
+        call: &mut CreateInputs,
+    ) -> (Return, Option<Address>, Gas, Bytes) {
+        if !self.counting && self.depth == Some(data.journaled_state.depth()) {
+            self.counts.calls_before += 1;
+        }
+
+        (Return::Continue, None, Gas::new(call.gas_limit), Bytes::new())
+    }
+}
+
+/// The gas cost of memory of a size in bytes, see the yellow paper's `C_mem`
+fn memory_cost(size: usize) -> u64 {
+    let words = (size as u64 + 31) / 32;
+    3 * words + words * words / 512
+}
@@ -1,4 +1,7 @@
-use super::{Cheatcodes, ChiselState, Debugger, Fuzzer, LogCollector, TracePrinter, Tracer};
+use super::{
+    Cheatcodes, ChiselState, Debugger, Fuzzer, LogCollector, OpcodeCounter, OpcodeCounts, Timeout,
+    TracePrinter, Tracer,
+};
 use crate::{
     coverage::HitMaps,
     debug::DebugArena,
@@ -8,7 +11,7 @@ use crate::{
 use bytes::Bytes;
 use ethers::{
     signers::LocalWallet,
-    types::{Address, Log, H256},
+    types::{Address, Log, H256, U256},
 };
 use revm::{
     return_revert, CallInputs, CreateInputs, EVMData, Gas, GasInspector, Inspector, Interpreter,
@@ -38,6 +41,10 @@ pub struct InspectorData {
     pub cheatcodes: Option<Cheatcodes>,
     pub script_wallets: Vec<LocalWallet>,
     pub chisel_state: Option<(revm::Stack, revm::Memory, revm::Return)>,
+    pub chisel_return_data: Option<Bytes>,
+    pub chisel_last_access: Option<(Address, Option<U256>)>,
+    pub opcode_counts: Option<OpcodeCounts>,
+    pub timed_out: bool,
 }
 
 /// An inspector that calls multiple inspectors in sequence.
@@ -55,10 +62,13 @@ pub struct InspectorStack {
     pub coverage: Option<CoverageCollector>,
     pub printer: Option<TracePrinter>,
     pub chisel_state: Option<ChiselState>,
+    pub opcode_counter: Option<OpcodeCounter>,
+    pub timeout: Option<Timeout>,
 }
 
 impl InspectorStack {
     pub fn collect_inspector_states(self) -> InspectorData {
+        let chisel_state = self.chisel_state.unwrap_or_default();
         InspectorData {
             logs: self.logs.map(|logs| logs.logs).unwrap_or_default(),
             labels: self
@@ -75,7 +85,11 @@ impl InspectorStack {
                 .map(|cheatcodes| cheatcodes.script_wallets.clone())
                 .unwrap_or_default(),
             cheatcodes: self.cheatcodes,
-            chisel_state: self.chisel_state.unwrap_or_default().state,
+            chisel_state: chisel_state.state,
+            chisel_return_data: chisel_state.return_data,
+            chisel_last_access: chisel_state.last_access,
+            opcode_counts: self.opcode_counter.map(OpcodeCounter::finish),
+            timed_out: self.timeout.map_or(false, |timeout| timeout.exceeded),
         }
     }
 
@@ -173,7 +187,10 @@ where
                 &mut self.coverage,
                 &mut self.logs,
                 &mut self.cheatcodes,
-                &mut self.printer
+                &mut self.printer,
+                &mut self.chisel_state,
+                &mut self.opcode_counter,
+                &mut self.timeout
             ],
             {
                 let status = inspector.step(interpreter, data, is_static);
@@ -247,6 +264,7 @@ where
                 &mut self.gas.as_deref().map(|gas| gas.borrow_mut()),
                 &mut self.fuzzer,
                 &mut self.debugger,
+                &mut self.opcode_counter,
                 &mut self.tracer,
                 &mut self.coverage,
                 &mut self.logs,
@@ -299,6 +317,7 @@ where
             [
                 &mut self.gas.as_deref().map(|gas| gas.borrow_mut()),
                 &mut self.debugger,
+                &mut self.opcode_counter,
                 &mut self.tracer,
                 &mut self.coverage,
                 &mut self.logs,
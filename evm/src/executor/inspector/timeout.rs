@@ -0,0 +1,45 @@
+use revm::{Database, EVMData, Inspector, Interpreter, Return};
+use std::time::Instant;
+
+/// The number of steps between checks of the clock
+const CHECK_INTERVAL: usize = 1024;
+
+/// An inspector that halts execution once a wall-clock deadline has passed
+///
+/// Every frame that is executed after the deadline halts with [Return::OutOfGas].
+#[derive(Debug, Clone)]
+pub struct Timeout {
+    /// The instant that execution is halted at
+    pub deadline: Instant,
+    /// Whether the deadline has passed
+    pub exceeded: bool,
+    /// The number of steps since the clock was last checked
+    steps: usize,
+}
+
+impl Timeout {
+    pub fn new(deadline: Instant) -> Self {
+        Self { deadline, exceeded: false, steps: 0 }
+    }
+}
+
+impl<DB> Inspector<DB> for Timeout
+where
+    DB: Database,
+{
+    fn step(&mut self, _: &mut Interpreter, _: &mut EVMData<'_, DB>, _: bool) -> Return {
+        if !self.exceeded {
+            self.steps += 1;
+            if self.steps < CHECK_INTERVAL {
+                return Return::Continue
+            }
+            self.steps = 0;
+            self.exceeded = Instant::now() >= self.deadline;
+        }
+        if self.exceeded {
+            Return::OutOfGas
+        } else {
+            Return::Continue
+        }
+    }
+}
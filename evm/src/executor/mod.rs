@@ -1,5 +1,6 @@
 use self::inspector::{
     cheatcodes::util::BroadcastableTransactions, Cheatcodes, InspectorData, InspectorStackConfig,
+    OpcodeCounts,
 };
 use crate::{debug::DebugArena, decode, trace::CallTraceArena, CALLER};
 pub use abi::{
@@ -184,6 +185,18 @@ impl Executor {
         Ok(self)
     }
 
+    /// Set the runtime code of an account, keeping its balance, nonce and storage.
+    pub fn set_code(&mut self, address: Address, code: Bytes) -> DatabaseResult<&mut Self> {
+        trace!(?address, "setting account code");
+        let mut account = self.backend_mut().basic(address)?.unwrap_or_default();
+        let code = Bytecode::new_raw(code).to_checked();
+        account.code_hash = code.hash();
+        account.code = Some(code);
+
+        self.backend_mut().insert_account_info(address, account);
+        Ok(self)
+    }
+
     pub fn set_tracing(&mut self, tracing: bool) -> &mut Self {
         self.inspector_config.tracing = tracing;
         self
@@ -362,8 +375,8 @@ impl Executor {
     }
 
     /// Commit the changeset to the database and adjust `self.inspector_config`
-    /// values according to the executed call result
-    fn commit(&mut self, result: &mut RawCallResult) {
+    /// values according to the executed call result, i.e. of [Executor::call_raw]
+    pub fn commit(&mut self, result: &mut RawCallResult) {
         // persist changes to db
         if let Some(changes) = result.state_changeset.as_ref() {
             self.backend_mut().commit(changes.clone());
@@ -715,6 +728,14 @@ pub struct RawCallResult {
     pub out: TransactOut,
     /// The chisel state
     pub chisel_state: Option<(revm::Stack, revm::Memory, revm::Return)>,
+    /// The return data buffer at the time the chisel state was captured
+    pub chisel_return_data: Option<Bytes>,
+    /// The account, and optionally the storage slot, that chisel saw accessed last
+    pub chisel_last_access: Option<(Address, Option<U256>)>,
+    /// The instructions that chisel counted, if it profiled the call
+    pub opcode_counts: Option<OpcodeCounts>,
+    /// Whether the call was halted because its deadline passed
+    pub timed_out: bool,
 }
 
 impl Default for RawCallResult {
@@ -738,6 +759,10 @@ impl Default for RawCallResult {
             cheatcodes: Default::default(),
             out: TransactOut::None,
             chisel_state: None,
+            chisel_return_data: None,
+            chisel_last_access: None,
+            opcode_counts: None,
+            timed_out: false,
         }
     }
 }
@@ -773,6 +798,10 @@ fn convert_executed_result(
         cheatcodes,
         script_wallets,
         chisel_state,
+        chisel_return_data,
+        chisel_last_access,
+        opcode_counts,
+        timed_out,
     } = inspector.collect_inspector_states();
 
     let transactions = match cheatcodes.as_ref() {
@@ -801,6 +830,10 @@ fn convert_executed_result(
         cheatcodes,
         out,
         chisel_state,
+        chisel_return_data,
+        chisel_last_access,
+        opcode_counts,
+        timed_out,
     })
 }
 